@@ -0,0 +1,54 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use elvis::{
+    core::{message::Message, Control},
+    simulation::flood_simulation,
+};
+
+fn message(c: &mut Criterion) {
+    let body = Message::new(vec![0u8; 1024]);
+    let headers = (0..4).fold(body.clone(), |message, _| {
+        message.with_header([0u8; 20].as_slice())
+    });
+
+    c.bench_function("Message push header", |b| {
+        b.iter(|| black_box(&body).with_header(b"Header"))
+    });
+    c.bench_function("Message slice", |b| {
+        b.iter(|| black_box(&headers).slice(20..))
+    });
+    c.bench_function("Message iterate", |b| {
+        b.iter(|| black_box(&headers).iter().fold(0u8, u8::wrapping_add))
+    });
+}
+
+fn control(c: &mut Criterion) {
+    c.bench_function("Control insert", |b| {
+        b.iter_batched(
+            Control::new,
+            |mut control| {
+                for key in 0..8u64 {
+                    control.insert(key, key as u32);
+                }
+                control
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    let control = (0..8u64).fold(Control::new(), |control, key| control.with(key, key as u32));
+    c.bench_function("Control get", |b| {
+        b.iter(|| {
+            (0..8u64)
+                .filter_map(|key| black_box(&control).get(key))
+                .count()
+        })
+    });
+}
+
+fn machine(c: &mut Criterion) {
+    c.bench_function("Machine awake with 5000 messages", |b| {
+        b.iter(|| flood_simulation(5000))
+    });
+}
+
+criterion_group!(benches, message, control, machine);
+criterion_main!(benches);
@@ -0,0 +1,225 @@
+//! A protocol written outside of Elvis, using only its public API.
+//!
+//! Rot13 sits between applications and UDP and rotates the letters of every
+//! payload by thirteen places on the way out and back on the way in, which is
+//! about as much as a protocol can do and still fit in an example. A machine
+//! sends a message through it to another, and the example prints what the
+//! network carried and what the other machine's application got.
+//!
+//! Run it with `cargo run --example rot13`.
+
+use elvis::{
+    applications::{Capture, SendMessage},
+    core::{Observer, Sent},
+    prelude::*,
+    protocols::{
+        ipv4::Ipv4,
+        udp::{LocalPort, RemotePort, Udp},
+    },
+};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// Rotates the letters of payloads sent through it, on top of [`Udp`].
+/// Applications open and listen on it as they would on UDP.
+#[derive(Default)]
+struct Rot13 {
+    listen_bindings: HashMap<LocalPort, ProtocolId>,
+    sessions: HashMap<(LocalPort, RemotePort), SharedSession>,
+}
+
+impl Rot13 {
+    const ID: ProtocolId = ProtocolId::from_string("Rot13");
+
+    fn new_shared() -> Arc<Mutex<Self>> {
+        Default::default()
+    }
+}
+
+impl Protocol for Rot13 {
+    fn id(&self) -> ProtocolId {
+        Self::ID
+    }
+
+    fn open(
+        &mut self,
+        upstream: ProtocolId,
+        participants: Control,
+        context: &mut ProtocolContext,
+    ) -> Result<SharedSession, SimError> {
+        let identifier = session_id(&participants);
+        if self.sessions.contains_key(&identifier) {
+            Err(Rot13Error("Tried to create an existing session"))?
+        }
+        let downstream = context
+            .protocol(Udp::ID)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .open(Self::ID, participants, context)?;
+        let session = SharedSession::new(Rot13Session {
+            upstream,
+            downstream,
+        });
+        self.sessions.insert(identifier, session.clone());
+        Ok(session)
+    }
+
+    fn listen(
+        &mut self,
+        upstream: ProtocolId,
+        participants: Control,
+        context: &mut ProtocolContext,
+    ) -> Result<(), SimError> {
+        let local_port = LocalPort::try_from(&participants).unwrap();
+        self.listen_bindings.insert(local_port, upstream);
+        context
+            .protocol(Udp::ID)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .listen(Self::ID, participants, context)
+    }
+
+    fn demux(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        // UDP leaves the connection's ports on the context
+        let identifier = session_id(&context.info);
+        let mut session = match self.sessions.get(&identifier) {
+            Some(session) => session.clone(),
+            None => {
+                let upstream = *self
+                    .listen_bindings
+                    .get(&identifier.0)
+                    .ok_or(Rot13Error("Nothing is listening on the port"))?;
+                let session = SharedSession::new(Rot13Session {
+                    upstream,
+                    downstream: context.current_session().expect("No current session"),
+                });
+                self.sessions.insert(identifier, session.clone());
+                session
+            }
+        };
+        session.receive(message, context)
+    }
+
+    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        Ok(ControlFlow::Continue)
+    }
+}
+
+struct Rot13Session {
+    upstream: ProtocolId,
+    downstream: SharedSession,
+}
+
+impl Session for Rot13Session {
+    fn send(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        self.downstream.send(rotate(message), context)
+    }
+
+    fn receive(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        context
+            .protocol(self.upstream)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .demux(rotate(message), context)
+    }
+
+    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        Ok(ControlFlow::Continue)
+    }
+}
+
+/// Identifies a session by its local and remote ports.
+fn session_id(control: &Control) -> (LocalPort, RemotePort) {
+    (
+        LocalPort::try_from(control).unwrap(),
+        RemotePort::try_from(control).unwrap(),
+    )
+}
+
+/// Rotates each ASCII letter thirteen places, which rotating again undoes.
+fn rotate(message: Message) -> Message {
+    let rotated: Vec<u8> = message
+        .iter()
+        .map(|byte| match byte {
+            b'a'..=b'z' => (byte - b'a' + 13) % 26 + b'a',
+            b'A'..=b'Z' => (byte - b'A' + 13) % 26 + b'A',
+            other => other,
+        })
+        .collect();
+    Message::new(rotated)
+}
+
+/// Errors from other crates reach Elvis as a [`SimError::Other`].
+#[derive(Debug)]
+struct Rot13Error(&'static str);
+
+impl fmt::Display for Rot13Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl Error for Rot13Error {}
+
+impl From<Rot13Error> for SimError {
+    fn from(error: Rot13Error) -> Self {
+        SimError::Other(Box::new(error))
+    }
+}
+
+/// Keeps the first message sent, headers and all.
+struct Wire(Arc<Mutex<Option<Vec<u8>>>>);
+
+impl Observer for Wire {
+    fn message_sent(&mut self, event: &Sent) {
+        let mut wire = self.0.lock().unwrap();
+        if wire.is_none() {
+            *wire = Some(event.message.iter().collect());
+        }
+    }
+}
+
+fn main() {
+    let mut internet = Internet::new();
+    internet.limit_rounds(10);
+    let wire = Arc::new(Mutex::new(None));
+    internet.observe(Arc::new(Mutex::new(Wire(wire.clone()))));
+    let network = internet.network(1500);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            Rot13::new_shared(),
+            UserProcess::new_shared(SendMessage::new("Hello, Elvis!").with_transport(Rot13::ID)),
+        ],
+        [network],
+    );
+    let capture = UserProcess::new_shared(Capture::new().with_transport(Rot13::ID));
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            Rot13::new_shared(),
+            capture.clone(),
+        ],
+        [network],
+    );
+    internet.run();
+
+    let wire = wire.lock().unwrap().clone().unwrap_or_default();
+    let received = capture.lock().unwrap().application().message();
+    let received: Vec<u8> = received.map_or_else(Vec::new, |message| message.iter().collect());
+    // The payload comes after the headers
+    let wire = &wire[wire.len().saturating_sub(received.len())..];
+    println!("On the wire: {}", String::from_utf8_lossy(wire));
+    println!("Received:    {}", String::from_utf8_lossy(&received));
+    assert_eq!(wire, b"Uryyb, Ryivf!");
+    assert_eq!(received, b"Hello, Elvis!");
+}
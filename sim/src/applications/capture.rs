@@ -6,24 +6,46 @@ use crate::{
         user_process::{Application, UserProcess},
     },
 };
-use std::{cell::RefCell, error::Error, rc::Rc};
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
+};
 
 /// An application that stores the first message it receives and then exits the
 /// simulation.
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Capture {
     message: Option<Message>,
+    transport: ProtocolId,
     did_set_up: bool,
 }
 
+impl Default for Capture {
+    fn default() -> Self {
+        Self {
+            message: None,
+            transport: Udp::ID,
+            did_set_up: false,
+        }
+    }
+}
+
 impl Capture {
     /// Creates a new capture.
     pub fn new() -> Self {
         Default::default()
     }
 
+    /// Listens through `transport` instead of UDP, such as a
+    /// [`Compression`](crate::protocols::compression::Compression) layer
+    /// that takes the same participants.
+    pub fn with_transport(mut self, transport: ProtocolId) -> Self {
+        self.transport = transport;
+        self
+    }
+
     /// Creates a new capture behind a shared handle.
-    pub fn new_shared() -> Rc<RefCell<UserProcess<Self>>> {
+    pub fn new_shared() -> Arc<Mutex<UserProcess<Self>>> {
         UserProcess::new_shared(Self::new())
     }
 
@@ -38,16 +60,19 @@ impl Application for Capture {
 
     fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
         if !self.did_set_up {
+            // Messages for us should come back to this instance of the application
+            let upstream = context.current_protocol().unwrap_or(Self::ID);
             let mut participants = Control::new();
             LocalAddress::set(&mut participants, Ipv4Address::LOCALHOST);
             RemoteAddress::set(&mut participants, Ipv4Address::LOCALHOST);
             LocalPort::set(&mut participants, 0xbeefu16);
             RemotePort::set(&mut participants, 0xdeadu16);
             context
-                .protocol(Udp::ID)
+                .protocol(self.transport)
                 .expect("No such protocol")
-                .borrow_mut()
-                .listen(Self::ID, participants, context)?;
+                .lock()
+                .unwrap()
+                .listen(upstream, participants, context)?;
         }
         self.did_set_up = true;
 
@@ -0,0 +1,198 @@
+use crate::{
+    core::{
+        message::Message, Control, ControlFlow, ProtocolContext, ProtocolId, SharedSession,
+        SimError,
+    },
+    protocols::{
+        ipv4::{Ipv4Address, LocalAddress, RemoteAddress},
+        udp::{LocalPort, RemotePort, Udp},
+        user_process::{Application, UserProcess},
+    },
+};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+/// Starts a message carrying a line of the conversation.
+const LINE: u8 = 0;
+/// Starts the message a chat sends when it leaves the conversation.
+const BYE: u8 = 1;
+
+/// One step of a [`Chat`] script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatStep {
+    /// Type out a line and send it
+    Say(&'static str),
+    /// Wait until the other side has said something
+    Await,
+}
+
+/// Who said a line of a [`Chat`] transcript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speaker {
+    Me,
+    Peer,
+}
+
+/// One half of an interactive conversation between two machines.
+///
+/// Each side follows a script of lines to say and points at which to wait for
+/// the other side. Typing a line takes one round of the simulation for every
+/// few characters, set by the typing speed. Once a side finishes its script it
+/// says goodbye and drops its session. The simulation ends when the second
+/// side has finished and heard the goodbye of the first.
+///
+/// The two sides must use each other's ports, with the local port of one being
+/// the remote port of the other.
+pub struct Chat {
+    script: VecDeque<ChatStep>,
+    local_port: u16,
+    remote_port: u16,
+    characters_per_round: usize,
+    session: Option<SharedSession>,
+    /// Rounds left typing the line at the front of the script
+    typing: Option<usize>,
+    /// Lines received that no [`ChatStep::Await`] has consumed yet
+    unread: usize,
+    transcript: Vec<(Speaker, String)>,
+    did_leave: bool,
+    peer_left: bool,
+}
+
+impl Chat {
+    /// Creates one side of a chat talking from `local_port` to `remote_port`.
+    pub fn new(
+        local_port: u16,
+        remote_port: u16,
+        script: impl IntoIterator<Item = ChatStep>,
+    ) -> Self {
+        Self {
+            script: script.into_iter().collect(),
+            local_port,
+            remote_port,
+            characters_per_round: 8,
+            session: None,
+            typing: None,
+            unread: 0,
+            transcript: vec![],
+            did_leave: false,
+            peer_left: false,
+        }
+    }
+
+    /// Types `characters` characters per round instead of 8.
+    pub fn with_typing_speed(mut self, characters: usize) -> Self {
+        self.characters_per_round = characters.max(1);
+        self
+    }
+
+    /// Creates a new chat behind a shared handle.
+    pub fn new_shared(
+        local_port: u16,
+        remote_port: u16,
+        script: impl IntoIterator<Item = ChatStep>,
+    ) -> Arc<Mutex<UserProcess<Self>>> {
+        UserProcess::new_shared(Self::new(local_port, remote_port, script))
+    }
+
+    /// Every line said so far, by either side, in the order this side saw
+    /// them.
+    pub fn transcript(&self) -> &[(Speaker, String)] {
+        &self.transcript
+    }
+
+    /// Whether both sides have finished their scripts.
+    pub fn is_over(&self) -> bool {
+        self.did_leave && self.peer_left
+    }
+
+    fn send(
+        &mut self,
+        kind: u8,
+        text: &str,
+        context: &mut ProtocolContext,
+    ) -> Result<(), SimError> {
+        let mut body = vec![kind];
+        body.extend_from_slice(text.as_bytes());
+        self.session
+            .as_mut()
+            .expect("The chat session is closed")
+            .send(Message::new(body), context)
+    }
+}
+
+impl Application for Chat {
+    const ID: ProtocolId = ProtocolId::from_string("Chat");
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        if self.session.is_none() && !self.did_leave {
+            // Messages for us should come back to this instance of the application
+            let upstream = context.current_protocol().unwrap_or(Self::ID);
+            let mut participants = Control::new();
+            LocalAddress::set(&mut participants, Ipv4Address::LOCALHOST);
+            RemoteAddress::set(&mut participants, Ipv4Address::LOCALHOST);
+            LocalPort::set(&mut participants, self.local_port);
+            RemotePort::set(&mut participants, self.remote_port);
+            self.session = Some(
+                context
+                    .protocol(Udp::ID)
+                    .expect("No such protocol")
+                    .lock()
+                    .unwrap()
+                    .open(upstream, participants, context)?,
+            );
+        }
+
+        while let Some(&step) = self.script.front() {
+            match step {
+                ChatStep::Await if self.unread > 0 => self.unread -= 1,
+                ChatStep::Await => break,
+                ChatStep::Say(text) => {
+                    let rounds = self
+                        .typing
+                        .get_or_insert(text.len().div_ceil(self.characters_per_round));
+                    if *rounds > 0 {
+                        *rounds -= 1;
+                        break;
+                    }
+                    self.typing = None;
+                    self.send(LINE, text, context)?;
+                    self.transcript.push((Speaker::Me, text.to_string()));
+                }
+            }
+            self.script.pop_front();
+        }
+
+        if self.script.is_empty() && !self.did_leave {
+            self.send(BYE, "", context)?;
+            self.did_leave = true;
+            self.session = None;
+        }
+
+        Ok(if self.is_over() {
+            ControlFlow::EndSimulation
+        } else {
+            ControlFlow::Continue
+        })
+    }
+
+    fn recv(
+        &mut self,
+        message: Message,
+        _context: &mut ProtocolContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut bytes = message.iter();
+        match bytes.next() {
+            Some(LINE) => {
+                let text = String::from_utf8_lossy(&bytes.collect::<Vec<_>>()).into_owned();
+                self.transcript.push((Speaker::Peer, text));
+                self.unread += 1;
+            }
+            Some(BYE) => self.peer_left = true,
+            _ => {}
+        }
+        Ok(())
+    }
+}
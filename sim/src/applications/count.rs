@@ -0,0 +1,79 @@
+use crate::{
+    core::{message::Message, Control, ControlFlow, ProtocolContext, ProtocolId},
+    protocols::{
+        ipv4::{Ipv4Address, LocalAddress, RemoteAddress},
+        udp::{LocalPort, RemotePort, Udp},
+        user_process::{Application, UserProcess},
+    },
+};
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+/// An application that counts the messages it receives and exits the
+/// simulation once it has seen the expected number.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Count {
+    expected: u32,
+    received: u32,
+    did_set_up: bool,
+}
+
+impl Count {
+    /// Creates a new count that waits for `expected` messages.
+    pub fn new(expected: u32) -> Self {
+        Self {
+            expected,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new count behind a shared handle.
+    pub fn new_shared(expected: u32) -> Arc<Mutex<UserProcess<Self>>> {
+        UserProcess::new_shared(Self::new(expected))
+    }
+
+    /// Gets the number of messages received so far.
+    pub fn received(&self) -> u32 {
+        self.received
+    }
+}
+
+impl Application for Count {
+    const ID: ProtocolId = ProtocolId::from_string("Count");
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        if !self.did_set_up {
+            // Messages for us should come back to this instance of the application
+            let upstream = context.current_protocol().unwrap_or(Self::ID);
+            let mut participants = Control::new();
+            LocalAddress::set(&mut participants, Ipv4Address::LOCALHOST);
+            RemoteAddress::set(&mut participants, Ipv4Address::LOCALHOST);
+            LocalPort::set(&mut participants, 0xbeefu16);
+            RemotePort::set(&mut participants, 0xdeadu16);
+            context
+                .protocol(Udp::ID)
+                .expect("No such protocol")
+                .lock()
+                .unwrap()
+                .listen(upstream, participants, context)?;
+        }
+        self.did_set_up = true;
+
+        Ok(if self.received >= self.expected {
+            ControlFlow::EndSimulation
+        } else {
+            ControlFlow::Continue
+        })
+    }
+
+    fn recv(
+        &mut self,
+        _message: Message,
+        _context: &mut ProtocolContext,
+    ) -> Result<(), Box<dyn Error>> {
+        self.received += 1;
+        Ok(())
+    }
+}
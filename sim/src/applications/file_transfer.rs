@@ -0,0 +1,294 @@
+use crate::{
+    core::{message::Message, Control, ControlFlow, ProtocolContext, ProtocolId, SharedSession},
+    protocols::{
+        ipv4::{Ipv4Address, LocalAddress, RemoteAddress},
+        udp::{LocalPort, RemotePort, Udp},
+        user_process::{Application, UserProcess},
+    },
+};
+use const_fnv1a_hash::fnv1a_hash_64;
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+/// The port the sender sends from.
+pub const SENDER_PORT: u16 = 0xf11e;
+/// The port the receiver listens on.
+pub const RECEIVER_PORT: u16 = 0xf11f;
+
+/// Starts a message carrying one chunk of the file.
+const CHUNK: u8 = 0;
+/// Starts the message describing the whole file, sent after the last chunk.
+const TRAILER: u8 = 1;
+
+/// Bytes of IPv4 and UDP headers in each packet
+const TRANSPORT_OVERHEAD: usize = 20 + 8;
+/// Bytes of the kind, chunk index, and chunk count in each chunk message
+const CHUNK_OVERHEAD: usize = 1 + 4 + 4;
+
+/// The checksum a receiver verifies the reassembled file against.
+fn checksum(data: &[u8]) -> u64 {
+    fnv1a_hash_64(data, None)
+}
+
+/// An application that sends a file to a [`FileReceiver`] over UDP.
+///
+/// The file is split into chunks small enough that each fits in one packet on
+/// a network with the given MTU. The chunks are sent a few per round, followed
+/// by a trailer with the file's length and checksum.
+pub struct FileSender {
+    chunks: Vec<Vec<u8>>,
+    length: u64,
+    checksum: u64,
+    chunks_per_round: usize,
+    sent: usize,
+    sent_trailer: bool,
+    session: Option<SharedSession>,
+}
+
+impl FileSender {
+    /// Creates a sender for `data` over a network with the given `mtu`.
+    pub fn new(data: impl Into<Vec<u8>>, mtu: u32) -> Self {
+        let data = data.into();
+        let chunk_size = (mtu as usize)
+            .checked_sub(TRANSPORT_OVERHEAD + CHUNK_OVERHEAD)
+            .filter(|&size| size > 0)
+            .expect("The MTU is too small to carry any file data");
+        Self {
+            chunks: data.chunks(chunk_size).map(<[u8]>::to_vec).collect(),
+            length: data.len() as u64,
+            checksum: checksum(&data),
+            chunks_per_round: 8,
+            sent: 0,
+            sent_trailer: false,
+            session: None,
+        }
+    }
+
+    /// Sends `chunks` chunks per round instead of 8.
+    pub fn with_chunks_per_round(mut self, chunks: usize) -> Self {
+        self.chunks_per_round = chunks.max(1);
+        self
+    }
+
+    /// Creates a new sender behind a shared handle.
+    pub fn new_shared(data: impl Into<Vec<u8>>, mtu: u32) -> Arc<Mutex<UserProcess<Self>>> {
+        UserProcess::new_shared(Self::new(data, mtu))
+    }
+
+    /// The number of chunks the file is split into.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+}
+
+impl Application for FileSender {
+    const ID: ProtocolId = ProtocolId::from_string("File Sender");
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                // Messages for us should come back to this instance of the application
+                let upstream = context.current_protocol().unwrap_or(Self::ID);
+                let mut participants = Control::new();
+                LocalAddress::set(&mut participants, Ipv4Address::LOCALHOST);
+                RemoteAddress::set(&mut participants, Ipv4Address::LOCALHOST);
+                LocalPort::set(&mut participants, SENDER_PORT);
+                RemotePort::set(&mut participants, RECEIVER_PORT);
+                let session = context
+                    .protocol(Udp::ID)
+                    .expect("No such protocol")
+                    .lock()
+                    .unwrap()
+                    .open(upstream, participants, context)?;
+                self.session.insert(session)
+            }
+        };
+
+        let count = self.chunks.len() as u32;
+        let end = (self.sent + self.chunks_per_round).min(self.chunks.len());
+        for (index, chunk) in self.chunks.iter().enumerate().take(end).skip(self.sent) {
+            let mut body = vec![CHUNK];
+            body.extend_from_slice(&(index as u32).to_be_bytes());
+            body.extend_from_slice(&count.to_be_bytes());
+            body.extend_from_slice(chunk);
+            session.send(Message::new(body), context)?;
+        }
+        if !self.sent_trailer && end == self.chunks.len() {
+            self.sent_trailer = true;
+            let mut body = vec![TRAILER];
+            body.extend_from_slice(&count.to_be_bytes());
+            body.extend_from_slice(&self.length.to_be_bytes());
+            body.extend_from_slice(&self.checksum.to_be_bytes());
+            session.send(Message::new(body), context)?;
+        }
+        self.sent = end;
+        Ok(ControlFlow::Continue)
+    }
+
+    fn recv(
+        &mut self,
+        _message: Message,
+        _context: &mut ProtocolContext,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// What a [`FileReceiver`] reports once a transfer completes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferReport {
+    /// The reassembled file
+    pub data: Vec<u8>,
+    /// Whether the file has the length and checksum the sender announced
+    pub intact: bool,
+    /// Rounds from the first chunk arriving to the last piece arriving,
+    /// counting both
+    pub rounds: u32,
+}
+
+impl TransferReport {
+    /// The bytes of the file received per round.
+    pub fn throughput(&self) -> f64 {
+        self.data.len() as f64 / self.rounds.max(1) as f64
+    }
+}
+
+/// An application that receives a file from a [`FileSender`], reassembling
+/// its chunks in order whatever order they arrive in, and ends the simulation
+/// once it has all of them.
+#[derive(Debug, Default)]
+pub struct FileReceiver {
+    chunks: Vec<Option<Vec<u8>>>,
+    /// The length and checksum from the trailer
+    expected: Option<(u64, u64)>,
+    /// Rounds since the first message arrived
+    rounds: Option<u32>,
+    report: Option<TransferReport>,
+    did_set_up: bool,
+}
+
+impl FileReceiver {
+    /// Creates a new file receiver.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a new file receiver behind a shared handle.
+    pub fn new_shared() -> Arc<Mutex<UserProcess<Self>>> {
+        UserProcess::new_shared(Self::new())
+    }
+
+    /// The outcome of the transfer, once every chunk has arrived.
+    pub fn report(&self) -> Option<&TransferReport> {
+        self.report.as_ref()
+    }
+
+    /// Makes room for `count` chunks.
+    fn expect_chunks(&mut self, count: usize) {
+        if self.chunks.len() < count {
+            self.chunks.resize(count, None);
+        }
+    }
+
+    /// Reassembles the file if every piece has arrived.
+    fn try_finish(&mut self) {
+        let Some((length, expected_checksum)) = self.expected else {
+            return;
+        };
+        if self.report.is_some() || self.chunks.iter().any(Option::is_none) {
+            return;
+        }
+        let data: Vec<u8> = self.chunks.iter().flatten().flatten().copied().collect();
+        let intact = data.len() as u64 == length && checksum(&data) == expected_checksum;
+        let rounds = self.rounds.unwrap_or(0) + 1;
+        self.report = Some(TransferReport {
+            data,
+            intact,
+            rounds,
+        });
+    }
+}
+
+impl Application for FileReceiver {
+    const ID: ProtocolId = ProtocolId::from_string("File Receiver");
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        if !self.did_set_up {
+            // Messages for us should come back to this instance of the application
+            let upstream = context.current_protocol().unwrap_or(Self::ID);
+            let mut participants = Control::new();
+            LocalAddress::set(&mut participants, Ipv4Address::LOCALHOST);
+            RemoteAddress::set(&mut participants, Ipv4Address::LOCALHOST);
+            LocalPort::set(&mut participants, RECEIVER_PORT);
+            RemotePort::set(&mut participants, SENDER_PORT);
+            context
+                .protocol(Udp::ID)
+                .expect("No such protocol")
+                .lock()
+                .unwrap()
+                .listen(upstream, participants, context)?;
+        }
+        self.did_set_up = true;
+
+        if self.report.is_some() {
+            return Ok(ControlFlow::EndSimulation);
+        }
+        if let Some(rounds) = &mut self.rounds {
+            *rounds += 1;
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    fn recv(
+        &mut self,
+        message: Message,
+        _context: &mut ProtocolContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let bytes: Vec<u8> = message.iter().collect();
+        let word = |at: usize| -> Option<u32> {
+            Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+        };
+        let long = |at: usize| -> Option<u64> {
+            Some(u64::from_be_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+        };
+        self.rounds.get_or_insert(0);
+        match bytes.first() {
+            Some(&CHUNK) => {
+                if let (Some(index), Some(count)) = (word(1), word(5)) {
+                    self.expect_chunks(count as usize);
+                    if let Some(slot) = self.chunks.get_mut(index as usize) {
+                        *slot = Some(bytes[CHUNK_OVERHEAD..].to_vec());
+                    }
+                }
+            }
+            Some(&TRAILER) => {
+                if let (Some(count), Some(length), Some(checksum)) = (word(1), long(5), long(13)) {
+                    self.expect_chunks(count as usize);
+                    self.expected = Some((length, checksum));
+                }
+            }
+            _ => {}
+        }
+        self.try_finish();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_fit_the_mtu() {
+        let sender = FileSender::new(vec![7u8; 1000], 100);
+        assert_eq!(sender.chunk_count(), 16);
+        assert!(sender
+            .chunks
+            .iter()
+            .all(|chunk| chunk.len() + CHUNK_OVERHEAD + TRANSPORT_OVERHEAD <= 100));
+        assert_eq!(FileSender::new(vec![], 100).chunk_count(), 0);
+    }
+}
@@ -2,7 +2,13 @@
 //! general purposes.
 
 mod capture;
+mod chat;
+mod count;
+mod file_transfer;
 mod send_message;
 
 pub use capture::Capture;
+pub use chat::{Chat, ChatStep, Speaker};
+pub use count::Count;
+pub use file_transfer::{FileReceiver, FileSender, TransferReport};
 pub use send_message::SendMessage;
@@ -1,54 +1,113 @@
 use crate::{
-    core::{message::Message, Control, ControlFlow, ProtocolContext, ProtocolId},
+    core::{message::Message, Control, ControlFlow, ProtocolContext, ProtocolId, SharedSession},
     protocols::{
         ipv4::{Ipv4Address, LocalAddress, RemoteAddress},
+        tap::SendQueueCapacity,
         udp::{LocalPort, RemotePort, Udp},
         user_process::{Application, UserProcess},
     },
 };
-use std::{cell::RefCell, error::Error, rc::Rc};
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
+};
 
-/// An application that sends a single message over the network.
+/// An application that sends a message over the network, once by default.
+///
+/// The messages all go out the first time the application is awoken, unless
+/// the session's send queue fills up, in which case the rest go out on later
+/// awakes as the queue empties.
 pub struct SendMessage {
-    text: &'static str,
-    did_set_up: bool,
+    message: Message,
+    count: u32,
+    transport: ProtocolId,
+    queue_capacity: Option<u32>,
+    session: Option<SharedSession>,
+    /// How many of the messages have gone out so far
+    sent: u32,
 }
 
 impl SendMessage {
     /// Creates a new send message application.
-    pub fn new(text: &'static str) -> Self {
+    pub fn new(text: &str) -> Self {
         Self {
-            text,
-            did_set_up: false,
+            message: Message::new(text),
+            count: 1,
+            transport: Udp::ID,
+            queue_capacity: None,
+            session: None,
+            sent: 0,
         }
     }
 
-    /// Creates a new send message application behind a shared handle.
-    pub fn new_shared(text: &'static str) -> Rc<RefCell<UserProcess<Self>>> {
-        UserProcess::new_shared(Self::new(text))
+    /// Sends the message `count` times instead of once.
+    pub fn with_count(mut self, count: u32) -> Self {
+        self.count = count;
+        self
     }
-}
 
-impl Application for SendMessage {
-    const ID: ProtocolId = ProtocolId::from_string("Send Message");
+    /// Sends through `transport` instead of UDP, such as a
+    /// [`Compression`](crate::protocols::compression::Compression) layer
+    /// that takes the same participants.
+    pub fn with_transport(mut self, transport: ProtocolId) -> Self {
+        self.transport = transport;
+        self
+    }
 
-    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
-        if self.did_set_up {
-            return Ok(ControlFlow::Continue);
-        }
-        self.did_set_up = true;
+    /// Opens the session with a [`SendQueueCapacity`] of `capacity`, so at
+    /// most that many messages wait to go out at once.
+    pub fn with_queue_capacity(mut self, capacity: u32) -> Self {
+        self.queue_capacity = Some(capacity);
+        self
+    }
 
+    /// Creates a new send message application behind a shared handle.
+    pub fn new_shared(text: &str) -> Arc<Mutex<UserProcess<Self>>> {
+        UserProcess::new_shared(Self::new(text))
+    }
+
+    /// Opens the session the messages go out on.
+    fn open(&self, context: &mut ProtocolContext) -> Result<SharedSession, Box<dyn Error>> {
+        // Messages for us should come back to this instance of the application
+        let upstream = context.current_protocol().unwrap_or(Self::ID);
         let mut participants = Control::new();
         // TODO(hardint): This should be some other IP addressODO
         LocalAddress::set(&mut participants, Ipv4Address::LOCALHOST);
         RemoteAddress::set(&mut participants, Ipv4Address::LOCALHOST);
         LocalPort::set(&mut participants, 0xdeadu16);
         RemotePort::set(&mut participants, 0xbeefu16);
-        let protocol = context.protocol(Udp::ID).expect("No such protocol");
-        let mut session = protocol
-            .borrow_mut()
-            .open(Self::ID, participants, context)?;
-        session.send(Message::new(self.text), context)?;
+        if let Some(capacity) = self.queue_capacity {
+            SendQueueCapacity::set(&mut participants, capacity);
+        }
+        let protocol = context.protocol(self.transport).expect("No such protocol");
+        let session = protocol
+            .lock()
+            .unwrap()
+            .open(upstream, participants, context)?;
+        Ok(session)
+    }
+}
+
+impl Application for SendMessage {
+    const ID: ProtocolId = ProtocolId::from_string("Send Message");
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => self.session.insert(self.open(context)?),
+        };
+        while self.sent < self.count {
+            match session.send(self.message.clone(), context) {
+                Ok(()) => self.sent += 1,
+                // Try again once the queue has room
+                Err(e) if e.is_would_block() => break,
+                Err(e) => {
+                    // Give up on the rest, as when they all went out at once
+                    self.sent = self.count;
+                    Err(e)?
+                }
+            }
+        }
         Ok(ControlFlow::Continue)
     }
 
@@ -0,0 +1,361 @@
+use super::{
+    message::Message, session::ControlFlow, Control, MachineId, Protocol, ProtocolContext,
+    ProtocolId, Round, SharedProtocol, SharedSession, SimError,
+};
+use crate::protocols::{
+    compression::Compression, firewall::Firewall, ipv4::Ipv4, ipv6::Ipv6, tap::Tap, tls::Tls,
+    udp::Udp,
+};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{Arc, Mutex, TryLockError},
+};
+
+/// A shared handle to a [`CallTrace`].
+pub type SharedCallTrace = Arc<Mutex<CallTrace>>;
+
+/// A call one protocol makes on another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Call {
+    Open,
+    Listen,
+    Demux,
+    Refused,
+}
+
+impl Call {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Listen => "listen",
+            Self::Demux => "demux",
+            Self::Refused => "refused",
+        }
+    }
+}
+
+/// One call recorded in a [`CallTrace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallRecord {
+    pub round: Round,
+    pub machine: MachineId,
+    /// The protocol making the call, or `None` if the machine made it
+    pub caller: Option<ProtocolId>,
+    pub callee: ProtocolId,
+    pub call: Call,
+    /// The protocols locked when the call was made, in the order they were
+    /// locked
+    pub held: Vec<ProtocolId>,
+    /// Whether the callee was already locked further up the chain, so that
+    /// making the call would have deadlocked
+    pub blocked: bool,
+}
+
+/// A record of the calls protocols make on one another, for debugging
+/// protocols that lock each other in the wrong order.
+///
+/// Protocols reach each other through
+/// [`ProtocolContext::protocol`](super::ProtocolContext::protocol) and hold
+/// each other's locks for as long as a call lasts, so UDP opening a session
+/// holds the UDP lock while it opens one on IPv4, which holds the IPv4 lock
+/// while it opens one on the tap. A protocol that calls back into one of the
+/// protocols already locked up the chain would deadlock the machine. With a
+/// trace registered through
+/// [`Internet::trace_calls`](super::Internet::trace_calls), every `open`,
+/// `listen`, `demux`, and `refused` call made through the context is recorded
+/// along with the locks held when it was made, and a call that would deadlock
+/// fails with [`SimError::Reentrant`] instead.
+///
+/// Calls the machine makes itself, such as `awake` or handing a message to
+/// the tap, are not recorded, and neither are calls between sessions.
+#[derive(Debug, Clone)]
+pub struct CallTrace {
+    calls: Vec<CallRecord>,
+    /// The protocols each machine has locked through traced calls, outermost
+    /// first
+    held: HashMap<MachineId, Vec<ProtocolId>>,
+    names: HashMap<ProtocolId, String>,
+}
+
+impl Default for CallTrace {
+    fn default() -> Self {
+        let names = [
+            (Tap::ID, "Tap"),
+            (Ipv4::ID, "IPv4"),
+            (Ipv6::ID, "IPv6"),
+            (Udp::ID, "UDP"),
+            (Tls::ID, "TLS"),
+            (Compression::ID, "Compression"),
+            (Firewall::ID, "Firewall"),
+        ]
+        .into_iter()
+        .map(|(id, name)| (id, name.to_string()))
+        .collect();
+        Self {
+            calls: vec![],
+            held: Default::default(),
+            names,
+        }
+    }
+}
+
+impl CallTrace {
+    /// Creates an empty trace that knows the names of the protocols that come
+    /// with Elvis.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates an empty trace behind a shared handle.
+    pub fn new_shared() -> SharedCallTrace {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// Names the protocol with the given ID, and its other instances, in
+    /// reports. Protocols without a name are shown by their ID number.
+    pub fn name_protocol(&mut self, id: ProtocolId, name: impl Into<String>) {
+        self.names.insert(id.base(), name.into());
+    }
+
+    /// Every call recorded, in the order they were made.
+    pub fn calls(&self) -> &[CallRecord] {
+        &self.calls
+    }
+
+    /// The calls that would have deadlocked.
+    pub fn deadlocks(&self) -> impl Iterator<Item = &CallRecord> {
+        self.calls.iter().filter(|record| record.blocked)
+    }
+
+    /// The name of a protocol in reports.
+    pub fn name(&self, id: ProtocolId) -> String {
+        let name = self
+            .names
+            .get(&id.base())
+            .cloned()
+            .unwrap_or_else(|| format!("{:x}", id.into_inner()));
+        match id.instance() {
+            0 => name,
+            instance => format!("{name}#{instance}"),
+        }
+    }
+
+    /// Writes the calls out as a [Mermaid] sequence diagram, with a note
+    /// wherever the round or machine changes. Calls that would have
+    /// deadlocked are drawn as lost messages.
+    ///
+    /// [Mermaid]: https://mermaid.js.org/syntax/sequenceDiagram.html
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("sequenceDiagram\n");
+        let mut participants: Vec<String> = vec![];
+        for record in &self.calls {
+            for participant in [self.caller_name(record), self.name(record.callee)] {
+                if !participants.contains(&participant) {
+                    writeln!(out, "    participant {}", mermaid_name(&participant)).unwrap();
+                    participants.push(participant);
+                }
+            }
+        }
+        let span = match (participants.first(), participants.last()) {
+            (Some(first), Some(last)) => format!("{},{}", mermaid_name(first), mermaid_name(last)),
+            _ => return out,
+        };
+        let mut at = None;
+        for record in &self.calls {
+            if at != Some((record.round, record.machine)) {
+                at = Some((record.round, record.machine));
+                writeln!(
+                    out,
+                    "    Note over {span}: Round {}, machine {}",
+                    record.round, record.machine
+                )
+                .unwrap();
+            }
+            let (arrow, label) = if record.blocked {
+                ("-x", format!("{} (deadlock)", record.call.name()))
+            } else {
+                ("->>", record.call.name().to_string())
+            };
+            writeln!(
+                out,
+                "    {}{arrow}{}: {label}",
+                mermaid_name(&self.caller_name(record)),
+                mermaid_name(&self.name(record.callee))
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// Describes the calls that would have deadlocked, one per line, along
+    /// with the order the protocols up the chain were locked in. Empty if
+    /// none would have.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        for record in self.deadlocks() {
+            let held: Vec<_> = record.held.iter().map(|&id| self.name(id)).collect();
+            writeln!(
+                out,
+                "Round {}, machine {}: {} called {} on {}, which is already locked (locked in order: {})",
+                record.round,
+                record.machine,
+                self.caller_name(record),
+                record.call.name(),
+                self.name(record.callee),
+                held.join(" -> ")
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    fn caller_name(&self, record: &CallRecord) -> String {
+        record
+            .caller
+            .map_or_else(|| "Machine".to_string(), |id| self.name(id))
+    }
+
+    /// Records a call and, unless it is blocked, marks the callee as locked
+    /// until [`leave`](Self::leave).
+    fn enter(&mut self, context: &ProtocolContext, callee: ProtocolId, call: Call, blocked: bool) {
+        let held = self.held.entry(context.machine()).or_default();
+        // The machine locks the protocol it is awaking without going through
+        // the context
+        let awake = context.current_protocol().filter(|id| !held.contains(id));
+        let held: Vec<_> = awake.into_iter().chain(held.iter().copied()).collect();
+        self.calls.push(CallRecord {
+            round: context.round(),
+            machine: context.machine(),
+            caller: held.last().copied(),
+            callee,
+            call,
+            held,
+            blocked,
+        });
+        if !blocked {
+            self.held.entry(context.machine()).or_default().push(callee);
+        }
+    }
+
+    fn leave(&mut self, machine: MachineId) {
+        if let Some(held) = self.held.get_mut(&machine) {
+            held.pop();
+        }
+    }
+}
+
+/// A protocol handle that records the calls made through it in a
+/// [`CallTrace`].
+pub(super) struct Traced {
+    id: ProtocolId,
+    protocol: SharedProtocol,
+    trace: SharedCallTrace,
+}
+
+impl Traced {
+    pub fn new_shared(
+        id: ProtocolId,
+        protocol: SharedProtocol,
+        trace: SharedCallTrace,
+    ) -> SharedProtocol {
+        Arc::new(Mutex::new(Self {
+            id,
+            protocol,
+            trace,
+        }))
+    }
+
+    /// Makes the call on the protocol, recording it along with the locks
+    /// held while it runs.
+    fn call<T>(
+        &self,
+        call: Call,
+        context: &mut ProtocolContext,
+        make: impl FnOnce(&mut dyn Protocol, &mut ProtocolContext) -> Result<T, SimError>,
+    ) -> Result<T, SimError> {
+        let mut protocol = match self.protocol.try_lock() {
+            Ok(protocol) => protocol,
+            Err(TryLockError::WouldBlock) => {
+                self.trace
+                    .lock()
+                    .unwrap()
+                    .enter(context, self.id, call, true);
+                Err(SimError::Reentrant(self.id))?
+            }
+            Err(TryLockError::Poisoned(e)) => panic!("{e}"),
+        };
+        self.trace
+            .lock()
+            .unwrap()
+            .enter(context, self.id, call, false);
+        let result = make(&mut *protocol, context);
+        self.trace.lock().unwrap().leave(context.machine());
+        result
+    }
+}
+
+impl Protocol for Traced {
+    fn id(&self) -> ProtocolId {
+        self.id.base()
+    }
+
+    fn open(
+        &mut self,
+        upstream: ProtocolId,
+        participants: Control,
+        context: &mut ProtocolContext,
+    ) -> Result<SharedSession, SimError> {
+        self.call(Call::Open, context, |protocol, context| {
+            protocol.open(upstream, participants, context)
+        })
+    }
+
+    fn listen(
+        &mut self,
+        upstream: ProtocolId,
+        participants: Control,
+        context: &mut ProtocolContext,
+    ) -> Result<(), SimError> {
+        self.call(Call::Listen, context, |protocol, context| {
+            protocol.listen(upstream, participants, context)
+        })
+    }
+
+    fn demux(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        self.call(Call::Demux, context, |protocol, context| {
+            protocol.demux(message, context)
+        })
+    }
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        self.protocol.lock().unwrap().awake(context)
+    }
+
+    fn refused(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        self.call(Call::Refused, context, |protocol, context| {
+            protocol.refused(message, context)
+        })
+    }
+}
+
+/// Mermaid takes participant names up to the first space or punctuation.
+fn mermaid_name(name: &str) -> String {
+    name.replace(|c: char| !c.is_alphanumeric(), "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_protocols_and_instances() {
+        let mut trace = CallTrace::new();
+        let app = ProtocolId::from_string("Echo");
+        assert_eq!(trace.name(Udp::ID), "UDP");
+        assert_eq!(trace.name(Ipv4::ID.with_instance(2)), "IPv4#2");
+        assert_eq!(trace.name(ProtocolId::new(0xabc).with_instance(1)), "abc#1");
+        trace.name_protocol(app.with_instance(1), "Echo");
+        assert_eq!(trace.name(app), "Echo");
+    }
+}
@@ -0,0 +1,159 @@
+/// The Internet checksum, the one's complement of the one's complement sum of
+/// 16-bit words described in RFC 1071, as used by IPv4, UDP, and TCP.
+///
+/// Bytes added with [`add_bytes`](Checksum::add_bytes) and
+/// [`add_byte`](Checksum::add_byte) are a stream paired up into big-endian
+/// words, so a payload can be added a piece at a time however it is split. A
+/// trailing odd byte is padded with zero. Words added with the `add_u*`
+/// methods are summed on their own and don't disturb the stream's pairing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Checksum {
+    sum: u16,
+    /// The first byte of a word from the stream still waiting on its second
+    odd: Option<u8>,
+}
+
+impl Checksum {
+    /// Creates an empty checksum.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the checksum of `bytes`.
+    pub fn of(bytes: &[u8]) -> u16 {
+        let mut checksum = Self::new();
+        checksum.add_bytes(bytes);
+        checksum.as_u16()
+    }
+
+    /// Adds a word with an end-around carry.
+    pub fn add_u16(&mut self, value: u16) {
+        self.sum = add(self.sum, value);
+    }
+
+    /// Adds the word made of `a` and then `b`.
+    pub fn add_u8(&mut self, a: u8, b: u8) {
+        self.add_u16(u16::from_be_bytes([a, b]));
+    }
+
+    /// Adds four bytes as two words.
+    pub fn add_u32(&mut self, value: [u8; 4]) {
+        self.add_u8(value[0], value[1]);
+        self.add_u8(value[2], value[3]);
+    }
+
+    /// Adds the next byte of the stream.
+    pub fn add_byte(&mut self, byte: u8) {
+        match self.odd.take() {
+            Some(first) => self.add_u8(first, byte),
+            None => self.odd = Some(byte),
+        }
+    }
+
+    /// Adds the next bytes of the stream.
+    pub fn add_bytes(&mut self, mut bytes: &[u8]) {
+        if let (Some(first), Some((&second, rest))) = (self.odd, bytes.split_first()) {
+            self.odd = None;
+            self.add_u8(first, second);
+            bytes = rest;
+        }
+        let mut words = bytes.chunks_exact(2);
+        for word in &mut words {
+            self.add_u8(word[0], word[1]);
+        }
+        if let [last] = words.remainder() {
+            self.odd = Some(*last);
+        }
+    }
+
+    /// Adds the pseudo header that UDP and TCP include in their checksums
+    /// over IPv4: the source and destination addresses, a zero byte, the
+    /// protocol number, and the length of the segment.
+    pub fn add_ipv4_pseudo_header(
+        &mut self,
+        source: [u8; 4],
+        destination: [u8; 4],
+        protocol: u8,
+        length: u16,
+    ) {
+        self.add_u32(source);
+        self.add_u32(destination);
+        self.add_u8(0, protocol);
+        self.add_u16(length);
+    }
+
+    /// Returns the checksum of everything added so far.
+    pub fn as_u16(&self) -> u16 {
+        let sum = match self.odd {
+            Some(last) => add(self.sum, u16::from_be_bytes([last, 0])),
+            None => self.sum,
+        };
+        match sum {
+            // Use that there are two one's complement representations of zero
+            // and pick the nonzero one to differentiate from an unused
+            // checksum.
+            0xffff => 0xffff,
+            sum => !sum,
+        }
+    }
+}
+
+/// One's complement addition.
+fn add(a: u16, b: u16) -> u16 {
+    let (sum, carry) = a.overflowing_add(b);
+    sum + carry as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::{collection::vec, prelude::*};
+
+    #[test]
+    fn matches_rfc_1071_example() {
+        // RFC 1071 section 3 sums these bytes to 0xddf2
+        assert_eq!(
+            Checksum::of(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]),
+            !0xddf2
+        );
+    }
+
+    #[test]
+    fn pads_an_odd_byte() {
+        assert_eq!(
+            Checksum::of(&[0x12, 0x34, 0x56]),
+            Checksum::of(&[0x12, 0x34, 0x56, 0])
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn streams_however_the_bytes_are_split(bytes in vec(any::<u8>(), 0..64), split in any::<prop::sample::Index>(), word: u16) {
+            let split = split.index(bytes.len() + 1);
+            let mut streamed = Checksum::new();
+            streamed.add_bytes(&bytes[..split]);
+            streamed.add_u16(word);
+            for &byte in &bytes[split..] {
+                streamed.add_byte(byte);
+            }
+            let mut whole = Checksum::new();
+            whole.add_bytes(&bytes);
+            whole.add_u16(word);
+            prop_assert_eq!(streamed.as_u16(), whole.as_u16());
+        }
+
+        #[test]
+        fn verifies_to_zero(bytes in vec(any::<u8>(), 0..64).prop_map(|mut bytes| {
+            if bytes.len() % 2 == 1 {
+                bytes.push(0);
+            }
+            bytes
+        })) {
+            // Summing data along with its checksum gives all ones
+            let mut checksum = Checksum::new();
+            checksum.add_bytes(&bytes);
+            checksum.add_u16(Checksum::of(&bytes));
+            prop_assert!(matches!(checksum.as_u16(), 0 | 0xffff));
+        }
+    }
+}
@@ -50,6 +50,9 @@ where
 }
 
 impl<const K: u64, V> ControlValue<K, V> {
+    /// The key the value is stored under on a [`Control`].
+    pub const KEY: u64 = K;
+
     /// Create a new control value to wrap the `value`.
     pub fn new(value: V) -> Self {
         Self(value)
@@ -59,6 +62,11 @@ impl<const K: u64, V> ControlValue<K, V> {
     pub fn into_inner(self) -> V {
         self.0
     }
+
+    /// Remove this kind of value from the `control`.
+    pub fn remove(control: &mut Control) {
+        control.remove(K);
+    }
 }
 
 impl<const K: u64, V> ControlValue<K, V>
@@ -100,6 +108,10 @@ where
     }
 }
 
+/// Hashes a name into a control key, such as `"UDP Local Port"` for
+/// [`LocalPort`](crate::protocols::udp::LocalPort). Keys made from different
+/// names should not collide, which [`key_collisions`](super::key_collisions)
+/// can check.
 pub const fn make_key(string_identifier: &'static str) -> u64 {
     fnv1a_hash_64(string_identifier.as_bytes(), None)
 }
@@ -111,9 +123,10 @@ pub const fn make_key(string_identifier: &'static str) -> u64 {
 /// such that a control value can be easily converted to and from any type the
 /// wrapped type provides `From` implementations for. Unfortunately, this does
 /// not seem to be possible at the moment. This macro simplifies the legwork of
-/// writing these implementations for specific types. Unfortunately, given
-/// Rust's coherence rules, this macro can only be used from inside the Elvis
-/// crate. A redesign will probably be necessary at some point.
+/// writing these implementations for specific types. Given Rust's coherence
+/// rules, crates outside Elvis can only use it with types of their own. A
+/// redesign will probably be necessary at some point.
+#[macro_export]
 macro_rules! from_impls {
     ($control_value:ty, $t:ty) => {
         impl From<$t> for $control_value {
@@ -130,7 +143,7 @@ macro_rules! from_impls {
     };
 }
 
-pub(crate) use from_impls;
+pub(crate) use crate::from_impls;
 
 /// An error occuring as a result of some operation on a [`ControlValue`].
 #[derive(Debug, ThisError)]
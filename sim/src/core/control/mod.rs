@@ -4,8 +4,11 @@ mod primitive;
 pub use primitive::{Primitive, PrimitiveError, PrimitiveKind};
 
 mod control_value;
-pub(crate) use control_value::{from_impls, make_key};
-pub use control_value::{ControlValue, ControlValueError};
+pub(crate) use control_value::from_impls;
+pub use control_value::{make_key, ControlValue, ControlValueError};
+
+mod registry;
+pub use registry::{key_collisions, KEYS};
 
 pub type ControlKey = u64;
 
@@ -49,4 +52,9 @@ impl Control {
     pub fn get(&self, key: ControlKey) -> Option<Primitive> {
         self.0.get(&key).cloned()
     }
+
+    /// Removes the value for the given key, returning it if it was present.
+    pub fn remove(&mut self, key: ControlKey) -> Option<Primitive> {
+        self.0.remove(&key)
+    }
 }
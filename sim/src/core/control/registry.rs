@@ -0,0 +1,101 @@
+use super::ControlKey;
+use crate::protocols::{congestion, ipv4, ipv6, tap, udp};
+
+/// Every control key Elvis defines, along with the name it is hashed from.
+///
+/// Keys are hashes of their names, so two [`ControlValue`](super::ControlValue)s
+/// only share a key if their names collide. Control values added to Elvis
+/// belong here so that the build checks their keys too.
+pub const KEYS: &[(&str, ControlKey)] = &[
+    ("Congestion Control Algorithm", congestion::Algorithm::KEY),
+    ("IPv4 Local Address", ipv4::LocalAddress::KEY),
+    ("IPv4 Remote Address", ipv4::RemoteAddress::KEY),
+    ("IPv4 Type of Service", ipv4::ServiceType::KEY),
+    ("IPv6 Local Address", ipv6::LocalAddress::KEY),
+    ("IPv6 Remote Address", ipv6::RemoteAddress::KEY),
+    ("IPv6 Traffic Class", ipv6::TrafficClass::KEY),
+    ("Tap Intercept", tap::Intercept::KEY),
+    ("Tap Network Index", tap::NetworkIndex::KEY),
+    ("Tap Physical Destination", tap::PhysicalDestination::KEY),
+    ("Tap Precedence", tap::Precedence::KEY),
+    ("Tap Send Queue Capacity", tap::SendQueueCapacity::KEY),
+    ("UDP Local Port", udp::LocalPort::KEY),
+    ("UDP Remote Port", udp::RemotePort::KEY),
+];
+
+// Fails the build if two of Elvis's own keys collide
+const _: () = assert!(distinct(KEYS), "Two control keys collide");
+
+/// Whether no two of the `keys` are the same.
+const fn distinct(keys: &[(&str, ControlKey)]) -> bool {
+    let mut i = 0;
+    while i < keys.len() {
+        let mut j = i + 1;
+        while j < keys.len() {
+            if keys[i].1 == keys[j].1 {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// The pairs of names whose keys collide, among the `keys` and with the
+/// [`KEYS`] Elvis defines.
+///
+/// Protocols and applications outside Elvis that define their own control
+/// keys with [`make_key`](super::make_key) can check them in a test:
+///
+/// ```
+/// use elvis::core::control::{key_collisions, make_key};
+///
+/// let keys = [("Echo Reply Count", make_key("Echo Reply Count"))];
+/// assert!(key_collisions(&keys).is_empty());
+/// ```
+///
+/// A name listed twice is the same key on purpose, not a collision.
+pub fn key_collisions<'a>(keys: &[(&'a str, ControlKey)]) -> Vec<(&'a str, &'a str)> {
+    let mut collisions = vec![];
+    for (i, &(name, key)) in keys.iter().enumerate() {
+        let earlier = KEYS.iter().chain(&keys[..i]);
+        for &(other, other_key) in earlier {
+            if other_key == key && other != name && !collisions.contains(&(other, name)) {
+                collisions.push((other, name));
+            }
+        }
+    }
+    collisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::control::make_key;
+
+    #[test]
+    fn keys_are_hashes_of_their_names() {
+        for &(name, key) in KEYS {
+            assert_eq!(make_key(name), key, "{name}");
+        }
+    }
+
+    #[test]
+    fn finds_colliding_keys() {
+        let keys = [
+            ("Echo Sequence", make_key("Echo Sequence")),
+            ("UDP Local Port", udp::LocalPort::KEY),
+            ("Echo Port", udp::LocalPort::KEY),
+            ("Echo Id", make_key("Echo Sequence")),
+        ];
+        assert_eq!(
+            key_collisions(&keys),
+            [
+                ("UDP Local Port", "Echo Port"),
+                ("Echo Sequence", "Echo Id")
+            ]
+        );
+        assert!(key_collisions(&keys[..2]).is_empty());
+    }
+}
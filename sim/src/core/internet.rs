@@ -1,22 +1,42 @@
-use super::{message::Message, ControlFlow, Machine, MachineId, Mtu, Network, RcProtocol};
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use super::{
+    call_trace::SharedCallTrace,
+    message::Message,
+    network::{Delivery, PhysicalAddress},
+    observer::Observers,
+    ControlFlow, DropStats, Dropped, Impairments, InvalidSpeedup, Machine, MachineId, Mtu, Network,
+    Pacer, Pacing, QueueDiscipline, QueueLimit, Round, Sent, SharedObserver, SharedProtocol,
+    StopCondition, StopWatch,
+};
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
 
 type NetworkIndex = usize;
 
-/// A shared, mutable handle to a network. We will be handing these out to
-/// multiple machines at a time.
-type SharedNetwork = Rc<RefCell<Network>>;
-/// A shared but immutable list of networks in the simulation.
-type SharedNetworks = Rc<RefCell<Vec<SharedNetwork>>>;
 /// A shared handle to a list of network indices. These are used to track which
 /// networks are available to a given machine.
-type NetworkIndices = Rc<Vec<NetworkIndex>>;
+type NetworkIndices = Arc<Vec<NetworkIndex>>;
+
+/// Messages refused by their destination, waiting to be handed back to the
+/// machine that sent each one.
+type Refusals = HashMap<MachineId, Vec<Message>>;
 
 /// The top-level container that controls the simulation.
 #[derive(Default)]
 pub struct Internet {
     machines: Vec<Machine>,
-    networks: SharedNetworks,
+    networks: Vec<Network>,
+    observers: Vec<SharedObserver>,
+    round: Round,
+    stop: Arc<Mutex<StopWatch>>,
+    seed: Option<u64>,
+    refused: Refusals,
+    call_trace: Option<SharedCallTrace>,
+    round_interval: Option<Duration>,
 }
 
 impl Internet {
@@ -27,28 +47,115 @@ impl Internet {
 
     /// Adds a network to the simulation and returns a handle to it.
     pub fn network(&mut self, mtu: Mtu) -> NetworkIndex {
-        let mut networks = self.networks.borrow_mut();
-        networks.push(Rc::new(RefCell::new(Network::new(mtu))));
-        networks.len() - 1
+        self.network_with_discipline(mtu, Default::default())
+    }
+
+    /// Adds a network on which machines send their queued messages in the
+    /// order given by `discipline` and returns a handle to it.
+    pub fn network_with_discipline(
+        &mut self,
+        mtu: Mtu,
+        discipline: QueueDiscipline,
+    ) -> NetworkIndex {
+        let mut network = Network::new(mtu).with_discipline(discipline);
+        if let Some(seed) = self.seed {
+            network.set_seed(seed);
+        }
+        self.networks.push(network);
+        self.networks.len() - 1
+    }
+
+    /// Seeds the random choices networks make, such as which messages RED
+    /// drops or impairments hit, for this and any later networks. Runs with
+    /// the same seed make the same choices.
+    pub fn seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        for network in self.networks.iter_mut() {
+            network.set_seed(seed);
+        }
+    }
+
+    /// Ends the simulation after `rounds` rounds, even if no machine asks for
+    /// it to end. Shorthand for [`stop_when`](Self::stop_when) with
+    /// [`StopCondition::Rounds`].
+    pub fn limit_rounds(&mut self, rounds: Round) {
+        self.stop_when(StopCondition::Rounds(rounds));
+    }
+
+    /// Ends the simulation once `condition` is met, even if no machine asks
+    /// for it to end. With several conditions, the first one met ends it.
+    pub fn stop_when(&mut self, condition: StopCondition) {
+        self.stop.lock().unwrap().add(condition);
+    }
+
+    /// Paces the rounds against the wall clock, to watch the simulation live.
+    /// Simulations run unpaced by default. Fails, leaving the pacing as it
+    /// was, if the speedup is not positive.
+    pub fn set_pacing(&mut self, pacing: Pacing) -> Result<(), InvalidSpeedup> {
+        self.round_interval = pacing.interval()?;
+        Ok(())
+    }
+
+    /// The number of rounds run so far.
+    pub fn rounds(&self) -> Round {
+        self.round
+    }
+
+    fn should_stop(&self) -> bool {
+        self.stop.lock().unwrap().should_stop(self.round)
+    }
+
+    /// The observers to call back during a run, ending with the stop watch.
+    fn observers(&self) -> Observers {
+        let mut observers = self.observers.clone();
+        observers.push(self.stop.clone());
+        Observers::new(observers)
+    }
+
+    /// Limits how many messages the `network` holds for each machine.
+    pub fn set_queue_limit(&mut self, network: NetworkIndex, limit: QueueLimit) {
+        self.networks[network].set_limit(limit);
+    }
+
+    /// Sets the faults the `network` injects, such as reordering or
+    /// duplicating messages.
+    pub fn set_impairments(&mut self, network: NetworkIndex, impairments: Impairments) {
+        self.networks[network].set_impairments(impairments);
+    }
+
+    /// The messages the `network` delivered and dropped so far.
+    pub fn network_stats(&self, network: NetworkIndex) -> DropStats {
+        self.networks[network].stats()
     }
 
     /// Adds a machine to the simulation with the given protocols and attached
     /// to the given networks.
-    pub fn machine<const P: usize, const N: usize>(
+    pub fn machine(
         &mut self,
-        protocols: [RcProtocol; P],
-        networks: [NetworkIndex; N],
+        protocols: impl IntoIterator<Item = SharedProtocol>,
+        networks: impl IntoIterator<Item = NetworkIndex>,
     ) {
         let mut machine = Machine::new(protocols, self.machines.len());
-        let self_networks = self.networks.borrow();
         for network in networks.into_iter() {
-            let network = self_networks.get(network).unwrap();
-            network.borrow_mut().attach(&machine);
-            machine.attach(network.borrow());
+            let network = self.networks.get_mut(network).unwrap();
+            network.attach(&machine);
+            machine.attach(network);
         }
         self.machines.push(machine);
     }
 
+    /// Registers an observer to be called back as the simulation runs.
+    /// Observers are called in the order they were registered.
+    pub fn observe(&mut self, observer: SharedObserver) {
+        self.observers.push(observer);
+    }
+
+    /// Records the calls protocols make on one another in `trace`, to debug
+    /// protocols that would deadlock. Tracing slows the simulation down.
+    pub fn trace_calls(&mut self, trace: SharedCallTrace) {
+        self.call_trace = Some(trace);
+    }
+
     /// Creates a new internet simulation with the given `machines` and
     /// `networks`
     fn networks_for_machine(&self) -> HashMap<MachineId, NetworkIndices> {
@@ -61,7 +168,6 @@ impl Internet {
                 // machine.
                 let networks_indices: Vec<_> = self
                     .networks
-                    .borrow()
                     .iter()
                     .enumerate()
                     .filter_map(|(network_index, network)| {
@@ -69,15 +175,13 @@ impl Internet {
                         // network's connected machines. If so, include the
                         // network in our list.
                         network
-                            .borrow()
                             .connected_machines()
-                            .iter()
-                            .any(|&connected| connected == machine_index)
+                            .contains(&machine_index)
                             .then_some(network_index)
                     })
                     .collect();
                 // The key-value pair to store in the map
-                (machine_index, Rc::new(networks_indices))
+                (machine_index, Arc::new(networks_indices))
             })
             .collect();
 
@@ -85,20 +189,103 @@ impl Internet {
     }
 
     /// Runs the simulation.
+    ///
+    /// Machines are awoken one after another, and messages a machine sends are
+    /// visible to the machines awoken after it in the same round.
     pub fn run(&mut self) {
         let networks_for_machine = self.networks_for_machine();
+        let observers = self.observers();
+        let pacer = Pacer::new(self.round_interval, self.round);
         'outer: loop {
+            if self.should_stop() {
+                return;
+            }
+            pacer.wait_for(self.round);
             for (mac, machine) in self.machines.iter_mut().enumerate() {
-                let mut context = MachineContext {
+                let mut context = MachineContext::new(
                     mac,
-                    networks_for_machine: networks_for_machine[&mac].clone(),
-                    networks: self.networks.clone(),
-                };
-                match machine.awake(&mut context) {
-                    ControlFlow::Continue => {}
+                    networks_for_machine[&mac].clone(),
+                    &mut self.networks,
+                    &mut self.refused,
+                    self.round,
+                    observers.clone(),
+                    self.call_trace.clone(),
+                );
+                let flow = machine.awake(&mut context);
+                context.deliver(&mut self.networks, &mut self.refused);
+                match flow {
+                    ControlFlow::Continue | ControlFlow::Exit => {}
                     ControlFlow::EndSimulation => break 'outer,
                 }
             }
+            self.round += 1;
+        }
+        self.round += 1;
+    }
+
+    /// Runs the simulation, awaking machines in parallel on up to `threads`
+    /// threads.
+    ///
+    /// The simulation proceeds in rounds. At the start of a round, each machine
+    /// receives the messages sent to it before the round began. Machines then
+    /// run in isolation from one another, and the messages they send are
+    /// delivered at the end of the round in machine order. This makes delivery
+    /// order independent of thread scheduling, so a parallel run always
+    /// delivers the same messages in the same order. The simulation ends after
+    /// any round in which a machine asks for it to end.
+    pub fn run_parallel(&mut self, threads: NonZeroUsize) {
+        let networks_for_machine = self.networks_for_machine();
+        let chunk_size = self.machines.len().div_ceil(threads.get()).max(1);
+        let observers = self.observers();
+        let pacer = Pacer::new(self.round_interval, self.round);
+        loop {
+            if self.should_stop() {
+                return;
+            }
+            pacer.wait_for(self.round);
+            let mut contexts: Vec<_> = (0..self.machines.len())
+                .map(|mac| {
+                    MachineContext::new(
+                        mac,
+                        networks_for_machine[&mac].clone(),
+                        &mut self.networks,
+                        &mut self.refused,
+                        self.round,
+                        observers.clone(),
+                        self.call_trace.clone(),
+                    )
+                })
+                .collect();
+
+            let flows: Vec<ControlFlow> = thread::scope(|scope| {
+                let workers: Vec<_> = self
+                    .machines
+                    .chunks_mut(chunk_size)
+                    .zip(contexts.chunks_mut(chunk_size))
+                    .map(|(machines, contexts)| {
+                        scope.spawn(move || {
+                            machines
+                                .iter_mut()
+                                .zip(contexts.iter_mut())
+                                .map(|(machine, context)| machine.awake(context))
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect();
+                // Join every worker, even after one asks to end the simulation
+                workers
+                    .into_iter()
+                    .flat_map(|worker| worker.join().expect("Machine thread panicked"))
+                    .collect()
+            });
+
+            for context in contexts {
+                context.deliver(&mut self.networks, &mut self.refused);
+            }
+            self.round += 1;
+            if flows.contains(&ControlFlow::EndSimulation) {
+                break;
+            }
         }
     }
 }
@@ -107,56 +294,139 @@ impl Internet {
 ///
 /// Provides the currently executing machine access to information about its
 /// execution environment, such as which networks it is connected to or its
-/// pending messages.
+/// pending messages. Messages the machine sends are held by the context until
+/// the [`Internet`] delivers them.
 pub struct MachineContext {
     mac: MachineId,
-    /// Contains a mapping from a machine index to network indices
-    networks_for_machine: Rc<Vec<NetworkIndex>>,
-    networks: Rc<RefCell<Vec<Rc<RefCell<Network>>>>>,
+    /// The indices of the networks the machine is connected to
+    networks_for_machine: NetworkIndices,
+    pending: Vec<(NetworkIndex, Delivery)>,
+    /// Messages the machine sent that were refused
+    refused: Vec<Message>,
+    outgoing: Vec<(NetworkIndex, PhysicalAddress, Message)>,
+    /// Messages the machine refused, along with the machine that sent each
+    refusing: Vec<(MachineId, Message)>,
+    round: Round,
+    observers: Observers,
+    call_trace: Option<SharedCallTrace>,
 }
 
 impl MachineContext {
-    /// Returns an iterator over the networks reachable by the currently
-    /// executing machine.
-    pub fn networks(&self) -> impl Iterator<Item = Rc<RefCell<Network>>> {
-        NetworksIterator {
-            current: 0,
-            networks_for_machine: self.networks_for_machine.clone(),
-            networks: self.networks.clone(),
+    /// Creates a context for the machine `mac`, taking the messages queued for
+    /// it on its networks and the refusals of messages it sent.
+    fn new(
+        mac: MachineId,
+        networks_for_machine: NetworkIndices,
+        networks: &mut [Network],
+        refused: &mut Refusals,
+        round: Round,
+        observers: Observers,
+        call_trace: Option<SharedCallTrace>,
+    ) -> Self {
+        let pending = networks_for_machine
+            .iter()
+            .flat_map(|&network| {
+                networks[network]
+                    .take_queue(mac)
+                    .into_iter()
+                    .map(move |delivery| (network, delivery))
+            })
+            .collect();
+        Self {
+            mac,
+            networks_for_machine,
+            pending,
+            refused: refused.remove(&mac).unwrap_or_default(),
+            outgoing: vec![],
+            refusing: vec![],
+            round,
+            observers,
+            call_trace,
         }
     }
 
-    /// Returns a list of the messages queued for delivery to the currently
-    /// executing machine from all of its connected networks.
-    pub fn pending(&self) -> Vec<Message> {
-        let mut networks = self.networks();
-        let mut messages = if let Some(network) = networks.next() {
-            network.borrow_mut().take_queue(self.mac)
-        } else {
-            vec![]
-        };
+    /// The round of the simulation the machine is running in.
+    pub fn round(&self) -> Round {
+        self.round
+    }
 
-        for network in networks {
-            messages.append(&mut network.borrow_mut().take_queue(self.mac));
-        }
+    /// The observers of the simulation.
+    pub(super) fn observers(&self) -> &Observers {
+        &self.observers
+    }
 
-        messages
+    /// Where to record the calls protocols make on one another, if anywhere.
+    pub(super) fn call_trace(&self) -> Option<&SharedCallTrace> {
+        self.call_trace.as_ref()
     }
-}
 
-/// An iterator over networks neighboring the currently executing machine.
-struct NetworksIterator {
-    current: NetworkIndex,
-    networks_for_machine: NetworkIndices,
-    networks: SharedNetworks,
-}
+    /// The number of networks reachable by the currently executing machine.
+    pub fn network_count(&self) -> usize {
+        self.networks_for_machine.len()
+    }
+
+    /// Removes and returns the messages queued for delivery to the currently
+    /// executing machine from all of its connected networks, along with the
+    /// network each came from.
+    pub fn take_pending(&mut self) -> Vec<(NetworkIndex, Delivery)> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Removes and returns the messages the currently executing machine sent
+    /// that their destinations refused since it was last awoken.
+    pub fn take_refused(&mut self) -> Vec<Message> {
+        std::mem::take(&mut self.refused)
+    }
 
-impl Iterator for NetworksIterator {
-    type Item = Rc<RefCell<Network>>;
+    /// Hands a message the currently executing machine refused back to the
+    /// `source` machine that sent it, which gets it the next time it awakes.
+    pub fn refuse(&mut self, source: MachineId, message: Message) {
+        self.refusing.push((source, message));
+    }
+
+    /// Sends a `message` on the machine's `network`th network. Messages for a
+    /// network the machine is not connected to are dropped.
+    pub fn send(&mut self, network: usize, address: PhysicalAddress, message: Message) {
+        if let Some(&network) = self.networks_for_machine.get(network) {
+            self.outgoing.push((network, address, message));
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let index = self.networks_for_machine.get(self.current).cloned()?;
-        self.current += 1;
-        self.networks.borrow().get(index).cloned()
+    /// Hands the messages sent by the machine to their networks, and the
+    /// messages it refused back to their senders.
+    fn deliver(self, networks: &mut [Network], refused: &mut Refusals) {
+        for (source, message) in self.refusing {
+            refused.entry(source).or_default().push(message);
+        }
+        for (network, destination, message) in self.outgoing {
+            let sent = Sent {
+                round: self.round,
+                machine: self.mac,
+                network,
+                destination,
+                message: &message,
+            };
+            self.observers
+                .notify(|observer| observer.message_sent(&sent));
+            let delivery = Delivery {
+                source: self.mac,
+                sent: self.round,
+                broadcast: destination.is_broadcast(),
+                message: message.clone(),
+            };
+            let drops = networks[network].send(destination, delivery);
+            for (machine, reason) in drops {
+                let dropped = Dropped {
+                    round: self.round,
+                    source: self.mac,
+                    machine,
+                    network: Some(network),
+                    reason,
+                    message: &message,
+                };
+                self.observers
+                    .notify(|observer| observer.message_dropped(&dropped));
+            }
+        }
     }
 }
@@ -1,19 +1,30 @@
 use super::{
-    internet::MachineContext, network::PhysicalAddress, protocol::RcProtocol, ControlFlow, Network,
-    ProtocolContext, ProtocolId,
+    internet::MachineContext, network::PhysicalAddress, protocol::SharedProtocol, ControlFlow,
+    DropReason, Dropped, Network, ProcessState, ProtocolContext, ProtocolId, Received,
 };
 use crate::protocols::tap::Tap;
 use std::{
-    cell::{Ref, RefCell},
-    collections::{hash_map::Entry, HashMap},
+    collections::HashMap,
     iter,
-    rc::Rc,
+    sync::{Arc, Mutex},
 };
 
 /// An identifier for a particular [`Machine`] in the simulation.
 pub type MachineId = usize;
 
-pub(super) type ProtocolMap = Rc<HashMap<ProtocolId, RcProtocol>>;
+pub(super) type ProtocolMap = Arc<HashMap<ProtocolId, SharedProtocol>>;
+
+/// The lifecycle state of each protocol on a machine, shared with the
+/// [`ProtocolContext`] so protocols can look it up.
+pub(super) type ProcessStates = Arc<Mutex<HashMap<ProtocolId, ProcessState>>>;
+
+/// The first instance of the protocol `base` for which `taken` is false.
+pub(super) fn free_instance(base: ProtocolId, taken: impl Fn(ProtocolId) -> bool) -> ProtocolId {
+    (0..)
+        .map(|instance| base.with_instance(instance))
+        .find(|&id| !taken(id))
+        .expect("Ran out of protocol instances")
+}
 
 /// A networked computer in the simultation.
 ///
@@ -21,92 +32,186 @@ pub(super) type ProtocolMap = Rc<HashMap<ProtocolId, RcProtocol>>;
 /// managed by the [`Internet`](super::Internet) and communicate through
 /// [`Network`](super::Network)s. Each machine contains a set of
 /// [`Protocol`](super::Protocol)s that it manages. The protocols may be
-/// networking protocols or user programs.
+/// networking protocols or user programs. When several protocols share an ID,
+/// each is registered as its own instance of that protocol, numbered in the
+/// order given. See [`ProtocolId::with_instance`].
+///
+/// Protocols may [`spawn`](ProtocolContext::spawn) more protocols while the
+/// simulation runs. The machine tracks the [`ProcessState`] of each and stops
+/// awaking those that have exited.
 pub struct Machine {
     id: MachineId,
     protocols: ProtocolMap,
-    tap: Rc<RefCell<Tap>>,
+    states: ProcessStates,
+    tap: Arc<Mutex<Tap>>,
 }
 
 impl Machine {
     /// Creates a new machine containing the `tap` and other `protocols`.
-    pub fn new<const S: usize>(protocols: [RcProtocol; S], id: MachineId) -> Self {
-        let tap = Rc::new(RefCell::new(Tap::new()));
+    pub fn new(protocols: impl IntoIterator<Item = SharedProtocol>, id: MachineId) -> Self {
+        let tap = Arc::new(Mutex::new(Tap::new()));
+        #[cfg(feature = "hop-trace")]
+        tap.lock().unwrap().set_machine(id);
         let mut map = HashMap::new();
-        for protocol in protocols
-            .into_iter()
-            .chain(iter::once(tap.clone() as RcProtocol))
-        {
-            let id = protocol.borrow().id();
-            match map.entry(id) {
-                Entry::Occupied(_) => panic!("Only one of each protocol should be provided"),
-                Entry::Vacant(entry) => {
-                    entry.insert(protocol);
-                }
-            }
+        // The machine's own tap goes first so it is always the first instance
+        for protocol in iter::once(tap.clone() as SharedProtocol).chain(protocols) {
+            let base = protocol.lock().unwrap().id();
+            let instance = free_instance(base, |id| map.contains_key(&id));
+            map.insert(instance, protocol);
         }
+        let states = map.keys().map(|&id| (id, ProcessState::Starting)).collect();
         Self {
             id,
             tap,
-            protocols: Rc::new(map),
+            protocols: Arc::new(map),
+            states: Arc::new(Mutex::new(states)),
         }
     }
 
-    pub fn attach(&mut self, network: Ref<Network>) {
-        self.tap.borrow_mut().attach(network);
+    pub fn attach(&mut self, network: &Network) {
+        self.tap.lock().unwrap().attach(network);
     }
 
     pub fn id(&self) -> MachineId {
         self.id
     }
 
+    /// The address other machines reach this one at on its networks.
+    pub fn physical_address(&self) -> PhysicalAddress {
+        PhysicalAddress::for_machine(self.id)
+    }
+
     /// Gives the machine time to process incoming messages and
     /// [`awake`](super::Protocol::awake) its protocols.
     pub fn awake(&mut self, context: &mut MachineContext) -> ControlFlow {
-        let mut protocol_context = ProtocolContext::new(self.protocols.clone());
+        let round = context.round();
+        let observers = context.observers().clone();
+        observers.notify(|observer| observer.machine_awoken(round, self.id));
+        let mut protocol_context = ProtocolContext::new(
+            self.protocols.clone(),
+            self.states.clone(),
+            self.id,
+            round,
+            observers.clone(),
+            context.call_trace().cloned(),
+        );
 
         let mut control_flow = ControlFlow::Continue;
-        for protocol in self.protocols.values() {
-            let flow = match protocol.borrow_mut().awake(&mut protocol_context) {
+        for (&id, protocol) in self.protocols.iter() {
+            if self.state(id) == ProcessState::Exited {
+                continue;
+            }
+            self.set_state(id, ProcessState::Running);
+            protocol_context.set_current_protocol(Some(id));
+            let flow = match protocol.lock().unwrap().awake(&mut protocol_context) {
                 Ok(flow) => flow,
                 Err(e) => {
                     eprintln!("{:?} -> {}", e, e);
+                    if e.is_fatal() {
+                        control_flow = ControlFlow::EndSimulation;
+                    }
                     continue;
                 }
             };
             match flow {
                 ControlFlow::Continue => {}
                 ControlFlow::EndSimulation => control_flow = ControlFlow::EndSimulation,
+                ControlFlow::Exit => self.set_state(id, ProcessState::Exited),
             }
         }
+        protocol_context.set_current_protocol(None);
 
-        for message in context.pending() {
+        // Hand refused messages back up the stack that sent them
+        for message in context.take_refused() {
+            let refused = self
+                .tap
+                .lock()
+                .unwrap()
+                // TODO(hardint): We want to know which network the message was sent on
+                .accept_refused(message, 0, &mut protocol_context);
+            if let Err(e) = refused {
+                eprintln!("{:?} -> {}", e, e);
+            }
+        }
+
+        for (network, delivery) in context.take_pending() {
+            let broadcast = delivery.broadcast;
+            let message = delivery.message;
+            let received = Received {
+                round,
+                machine: self.id,
+                source: delivery.source,
+                network,
+                sent: delivery.sent,
+                message: &message,
+            };
+            observers.notify(|observer| observer.message_received(&received));
             match self
                 .tap
-                .borrow_mut()
+                .lock()
+                .unwrap()
                 // TODO(hardint): We want to get the network number from pending()
-                .accept_incoming(message, 0, &mut protocol_context)
+                .accept_incoming(message.clone(), 0, &mut protocol_context)
             {
-                Ok(flow) => flow,
+                Ok(()) => {}
+                // A message that could not be delivered is dropped
                 Err(e) => {
                     eprintln!("{:?} -> {}", e, e);
-                    continue;
+                    // Tell the sender nobody was listening, but not for
+                    // broadcasts, which every machine would otherwise answer
+                    if e.is_refusal() && !broadcast {
+                        context.refuse(delivery.source, message.clone());
+                    }
+                    let dropped = Dropped {
+                        round,
+                        source: delivery.source,
+                        machine: Some(self.id),
+                        network: None,
+                        reason: DropReason::Rejected(&e),
+                        message: &message,
+                    };
+                    observers.notify(|observer| observer.message_dropped(&dropped));
+                    if e.is_fatal() {
+                        control_flow = ControlFlow::EndSimulation;
+                    }
                 }
             }
         }
 
-        let outgoing: HashMap<_, _> = self.tap.borrow_mut().outgoing().into_iter().collect();
-        for (i, network) in context.networks().enumerate() {
+        let outgoing: HashMap<_, _> = self.tap.lock().unwrap().outgoing().into_iter().collect();
+        for i in 0..context.network_count() {
             if let Some(messages) = outgoing.get(&(i as u8).into()) {
-                for message in messages {
-                    network
-                        .borrow_mut()
-                        // TODO(hardint): Use the correct physical address
-                        .send(PhysicalAddress::Broadcast, message.clone());
+                for &(address, ref message) in messages {
+                    context.send(i, address, message.clone());
                 }
             }
         }
 
+        // Protocols spawned during this pass first run on the next one. The
+        // context shares the protocol map, so it has to go before we can
+        // change the map.
+        let spawned = protocol_context.take_spawned();
+        drop(protocol_context);
+        if !spawned.is_empty() {
+            let protocols = Arc::make_mut(&mut self.protocols);
+            for (id, protocol) in spawned {
+                protocols.insert(id, protocol);
+                self.states
+                    .lock()
+                    .unwrap()
+                    .insert(id, ProcessState::Starting);
+            }
+        }
+
         control_flow
     }
+
+    /// The lifecycle state of the protocol with the given ID.
+    pub fn state(&self, id: ProtocolId) -> ProcessState {
+        self.states.lock().unwrap()[&id]
+    }
+
+    fn set_state(&self, id: ProtocolId, state: ProcessState) {
+        self.states.lock().unwrap().insert(id, state);
+    }
 }
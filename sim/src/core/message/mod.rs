@@ -1,4 +1,8 @@
-use std::{fmt::Display, rc::Rc};
+use std::{fmt::Display, sync::Arc};
+
+#[cfg(feature = "hop-trace")]
+use super::MachineId;
+use super::Round;
 
 mod chunk;
 pub use chunk::Chunk;
@@ -22,7 +26,12 @@ pub use message_bytes::MessageBytes;
 /// for composing, sending, and splitting byte sequences.
 #[derive(Debug, Clone)]
 pub struct Message {
-    stack: Rc<WrappedMessage>,
+    stack: Arc<WrappedMessage>,
+    /// The machines that have sent this message, oldest first
+    #[cfg(feature = "hop-trace")]
+    trace: Arc<Vec<MachineId>>,
+    /// The round the message was last sent by a transport protocol
+    timestamp: Option<Round>,
 }
 
 impl Message {
@@ -40,7 +49,10 @@ impl Message {
 
     fn new_inner(body: Chunk) -> Self {
         Self {
-            stack: Rc::new(WrappedMessage::Body(body)),
+            stack: Arc::new(WrappedMessage::Body(body)),
+            #[cfg(feature = "hop-trace")]
+            trace: Default::default(),
+            timestamp: None,
         }
     }
 
@@ -60,7 +72,10 @@ impl Message {
 
     fn with_header_inner(&self, header: Chunk) -> Self {
         Self {
-            stack: Rc::new(WrappedMessage::Header(header, self.stack.clone())),
+            stack: Arc::new(WrappedMessage::Header(header, self.stack.clone())),
+            #[cfg(feature = "hop-trace")]
+            trace: self.trace.clone(),
+            timestamp: self.timestamp,
         }
     }
 
@@ -87,11 +102,14 @@ impl Message {
         let start = range.start();
         let end = range.end();
         Self {
-            stack: Rc::new(WrappedMessage::Slice {
+            stack: Arc::new(WrappedMessage::Slice {
                 start,
                 length: end - start,
                 message: self.stack.clone(),
             }),
+            #[cfg(feature = "hop-trace")]
+            trace: self.trace.clone(),
+            timestamp: self.timestamp,
         }
     }
 
@@ -108,6 +126,45 @@ impl Message {
     pub fn iter(&self) -> MessageBytes {
         MessageBytes::new(self.stack.clone())
     }
+
+    /// The machines that have sent this message onto a network, oldest first.
+    /// Headers and slices of a message keep its trace, so a message forwarded
+    /// up and back down a protocol stack remembers where it has been.
+    #[cfg(feature = "hop-trace")]
+    pub fn trace(&self) -> &[MachineId] {
+        &self.trace
+    }
+
+    /// Creates a copy of the message with `machine` added to the end of its
+    /// trace.
+    #[cfg(feature = "hop-trace")]
+    pub fn with_hop(&self, machine: MachineId) -> Self {
+        let mut trace = (*self.trace).clone();
+        trace.push(machine);
+        Self {
+            stack: self.stack.clone(),
+            trace: Arc::new(trace),
+            timestamp: self.timestamp,
+        }
+    }
+
+    /// The round a transport protocol last sent the message in, if one did.
+    /// Headers and slices of a message keep its timestamp, so the receiving
+    /// side can tell how long the message took to arrive and a sender can
+    /// measure round trips from it.
+    pub fn timestamp(&self) -> Option<Round> {
+        self.timestamp
+    }
+
+    /// Creates a copy of the message stamped as sent in `round`.
+    pub fn with_timestamp(&self, round: Round) -> Self {
+        Self {
+            stack: self.stack.clone(),
+            #[cfg(feature = "hop-trace")]
+            trace: self.trace.clone(),
+            timestamp: Some(round),
+        }
+    }
 }
 
 impl Display for Message {
@@ -133,8 +190,8 @@ enum WrappedMessage {
     Slice {
         start: usize,
         length: usize,
-        message: Rc<WrappedMessage>,
+        message: Arc<WrappedMessage>,
     },
-    Header(Chunk, Rc<WrappedMessage>),
+    Header(Chunk, Arc<WrappedMessage>),
     Body(Chunk),
 }
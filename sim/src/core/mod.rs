@@ -7,8 +7,13 @@
 //! # Organization
 //! - [`Message`](message::Message) and [`Control`] provide basic utilities
 //!   common to most protocols
+//! - [`Checksum`] computes the Internet checksum for protocol headers
+//! - [`RttEstimator`] measures round trips for timer-based protocols
 //! - [`Protocol`] and [`Session`] implement individual protocols
 //! - [`Internet`] provides the actual simulation
+//! - [`Observer`] watches a simulation as it runs
+//! - [`StopCondition`] decides when a simulation ends
+//! - [`CallTrace`] records the calls protocols make on one another
 //!
 //! # Protocol structure
 //!
@@ -35,14 +40,23 @@ pub use control::Control;
 pub mod message;
 pub use message::Message;
 
+mod checksum;
+pub use checksum::Checksum;
+
+mod rtt;
+pub use rtt::{RttEstimator, RttStats};
+
 mod protocol;
-pub use protocol::{Protocol, ProtocolId, RcProtocol};
+pub use protocol::{Protocol, ProtocolId, SharedProtocol};
 
 mod shared_session;
 pub use shared_session::SharedSession;
 
 mod session;
-pub use session::{ControlFlow, Session};
+pub use session::{ControlFlow, ProcessState, Session};
+
+mod sim_error;
+pub use sim_error::SimError;
 
 mod protocol_context;
 pub use protocol_context::ProtocolContext;
@@ -50,8 +64,28 @@ pub use protocol_context::ProtocolContext;
 mod internet;
 pub use internet::Internet;
 
+mod observer;
+pub use observer::{
+    DropReason, Dropped, Observer, Received, Round, Sent, SessionEvent, SharedObserver,
+};
+
+mod call_trace;
+pub use call_trace::{Call, CallRecord, CallTrace, SharedCallTrace};
+
+mod pacing;
+use pacing::Pacer;
+pub use pacing::{InvalidSpeedup, Pacing};
+
+mod stop;
+use stop::StopWatch;
+pub use stop::{Event, StopCondition};
+
 mod machine;
+pub use machine::MachineId;
 pub(crate) use machine::*;
 
 mod network;
 pub(crate) use network::*;
+pub use network::{
+    DropStats, Impairments, PhysicalAddress, QueueDiscipline, QueueLimit, Red, Reordering,
+};
@@ -1,21 +1,50 @@
-use super::{message::Message, Machine, MachineId};
-use std::collections::{hash_map::Entry, HashMap};
+use super::{
+    control::{Primitive, PrimitiveError},
+    message::Message,
+    DropReason, Machine, MachineId, Round,
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt::{self, Display},
+};
 
 /// A maximum transmission unit
 pub type Mtu = u32;
 
-type Pending = HashMap<usize, Vec<Message>>;
+type Pending = HashMap<usize, Vec<Delivery>>;
+
+/// Seeds the generator deciding RED drops and which messages are impaired, so
+/// runs are reproducible
+const RED_SEED: u64 = 0x2545_f491_4f6c_dd1d;
 
 /// A link-level connection between [`Machine`](super::Machine)s.
 ///
 /// A network facilitates connecting multiple machines together and allowing
 /// them to exchange [`Message`]s. Roughly, it models an simplified Ethernet
-/// network with broadcast and MAC-based message delivery.
+/// network with broadcast and MAC-based message delivery. Each attached
+/// machine is reachable at its [`PhysicalAddress`], and messages sent to
+/// [`PhysicalAddress::BROADCAST`] reach every attached machine.
+///
+/// The messages waiting for each machine form a queue whose length the
+/// network's [`QueueLimit`] bounds. Messages that don't fit are dropped and
+/// counted in the network's [`DropStats`]. With [`Impairments`], some of the
+/// messages that fit are held back for a few rounds before joining the queue,
+/// or delivered twice.
 #[derive(Debug, Clone)]
 pub struct Network {
     mtu: Mtu,
+    discipline: QueueDiscipline,
+    limit: QueueLimit,
     connected: Vec<MachineId>,
+    addresses: HashMap<PhysicalAddress, MachineId>,
     pending: Pending,
+    /// The average queue length for each machine, used by RED
+    averages: HashMap<MachineId, f64>,
+    impairments: Impairments,
+    /// Messages the reordering holds back
+    held: Vec<Held>,
+    stats: DropStats,
+    rng: u64,
 }
 
 impl Network {
@@ -24,13 +53,47 @@ impl Network {
     pub fn new(mtu: Mtu) -> Self {
         Self {
             connected: vec![],
+            addresses: Default::default(),
             pending: Default::default(),
+            discipline: Default::default(),
+            limit: Default::default(),
+            averages: Default::default(),
+            impairments: Default::default(),
+            held: vec![],
+            stats: Default::default(),
+            rng: RED_SEED,
             mtu,
         }
     }
 
+    /// Seeds the generator deciding RED drops and which messages are
+    /// impaired.
+    pub fn set_seed(&mut self, seed: u64) {
+        // Xorshift never leaves zero
+        self.rng = if seed == 0 { RED_SEED } else { seed };
+    }
+
+    /// Sets how many messages the network holds for each machine.
+    pub fn set_limit(&mut self, limit: QueueLimit) {
+        self.limit = limit;
+    }
+
+    /// Sets the faults the network injects into the messages it carries.
+    pub fn set_impairments(&mut self, impairments: Impairments) {
+        self.impairments = impairments;
+    }
+
+    /// Sets the order in which machines send their queued messages onto the
+    /// network.
+    pub fn with_discipline(mut self, discipline: QueueDiscipline) -> Self {
+        self.discipline = discipline;
+        self
+    }
+
     pub fn attach(&mut self, machine: &Machine) {
         self.connected.push(machine.id());
+        self.addresses
+            .insert(machine.physical_address(), machine.id());
     }
 
     /// The network's maximum transmission unit.
@@ -38,52 +101,555 @@ impl Network {
         self.mtu
     }
 
+    /// The order in which machines send their queued messages onto the network.
+    pub fn discipline(&self) -> QueueDiscipline {
+        self.discipline
+    }
+
+    /// The messages the network delivered and dropped so far.
+    pub fn stats(&self) -> DropStats {
+        self.stats
+    }
+
     /// The list of connected machines.
     pub fn connected_machines(&self) -> &[MachineId] {
         &self.connected
     }
 
     /// Send a `message` to the machine or machines identified by `address`.
-    pub fn send(&mut self, address: PhysicalAddress, message: Message) {
+    /// Messages for an address no machine on the network has are dropped.
+    ///
+    /// Returns the machines for which the message was dropped, if any, and
+    /// why.
+    pub fn send(
+        &mut self,
+        address: PhysicalAddress,
+        delivery: Delivery,
+    ) -> Vec<(Option<MachineId>, DropReason<'static>)> {
         // TODO(hardint): Check that the message is shorter than MTU
-        match address {
-            PhysicalAddress::Recipient(mac) => send_to_mac(mac, &mut self.pending, message),
-            PhysicalAddress::Broadcast => {
-                for &mac in self.connected.iter() {
-                    send_to_mac(mac, &mut self.pending, message.clone())
+        if address.is_broadcast() {
+            (0..self.connected.len())
+                .filter_map(|i| {
+                    let mac = self.connected[i];
+                    Some((Some(mac), self.enqueue(mac, delivery.clone())?))
+                })
+                .collect()
+        } else if let Some(&mac) = self.addresses.get(&address) {
+            self.enqueue(mac, delivery)
+                .map(|reason| (Some(mac), reason))
+                .into_iter()
+                .collect()
+        } else {
+            vec![(None, DropReason::NoSuchAddress)]
+        }
+    }
+
+    /// Adds a message to the queue for `mac`, unless the queue limit drops it,
+    /// and a copy of it too if the network duplicates it.
+    fn enqueue(&mut self, mac: MachineId, delivery: Delivery) -> Option<DropReason<'static>> {
+        let duplication = self.impairments.duplication;
+        if duplication <= 0.0 || self.random() >= duplication {
+            return self.admit(mac, delivery);
+        }
+        let drop = self.admit(mac, delivery.clone());
+        if drop.is_none() {
+            // The sender only hears about the message it sent, not the copy
+            self.stats.duplicated += 1;
+            self.admit(mac, delivery);
+        }
+        drop
+    }
+
+    /// Adds a message to the queue for `mac`, unless the queue limit drops it.
+    fn admit(&mut self, mac: MachineId, delivery: Delivery) -> Option<DropReason<'static>> {
+        let queued = self.pending.get(&mac).map_or(0, Vec::len);
+        let drop = match self.limit {
+            QueueLimit::Unbounded => None,
+            QueueLimit::DropTail(capacity) => (queued >= capacity).then_some(DropReason::QueueFull),
+            QueueLimit::Red(red) => {
+                let average = self.averages.entry(mac).or_insert(0.0);
+                *average += red.weight * (queued as f64 - *average);
+                let average = *average;
+                if queued >= red.capacity {
+                    Some(DropReason::QueueFull)
+                } else if self.red_drops(red, average) {
+                    Some(DropReason::EarlyDrop)
+                } else {
+                    None
                 }
             }
+        };
+        match drop {
+            Some(DropReason::EarlyDrop) => self.stats.early_dropped += 1,
+            Some(_) => self.stats.tail_dropped += 1,
+            None => {
+                self.stats.queued += 1;
+                match self.hold_back() {
+                    Some(slots) => {
+                        self.stats.reordered += 1;
+                        self.held.push(Held {
+                            mac,
+                            slots,
+                            delivery,
+                        });
+                    }
+                    None => send_to_mac(mac, &mut self.pending, delivery),
+                }
+            }
+        }
+        drop
+    }
+
+    /// How many delivery slots to hold back an arriving message for, if the
+    /// reordering picks it.
+    fn hold_back(&mut self) -> Option<u32> {
+        let reordering = self
+            .impairments
+            .reordering
+            .filter(|reordering| reordering.max_delay > 0)?;
+        if self.random() >= reordering.fraction {
+            return None;
+        }
+        let slots = (self.random() * reordering.max_delay as f64) as u32 + 1;
+        Some(slots.min(reordering.max_delay))
+    }
+
+    /// Whether RED drops a message arriving at a queue with the given average
+    /// length. The chance grows linearly from nothing at the minimum
+    /// threshold to the maximum probability at the maximum threshold, above
+    /// which every message is dropped.
+    fn red_drops(&mut self, red: Red, average: f64) -> bool {
+        let (min, max) = (red.min_threshold as f64, red.max_threshold as f64);
+        if average < min {
+            return false;
+        }
+        if average >= max {
+            return true;
         }
+        let probability = red.max_probability * (average - min) / (max - min);
+        self.random() <= probability
+    }
+
+    /// A number from 0 up to but not including 1.
+    fn random(&mut self) -> f64 {
+        // xorshift64, good enough to spread drops out and reproducible
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
     }
 
     /// Remove and return the list messages not yet processed that are destined
     /// for delivery to `address`.
-    pub fn take_queue(&mut self, address: MachineId) -> Vec<Message> {
+    ///
+    /// Each call is a delivery slot for the machine. Messages the reordering
+    /// held back come once their slots are up, after the rest of the queue.
+    pub fn take_queue(&mut self, address: MachineId) -> Vec<Delivery> {
         // TODO(hardint): Allow only taking individual messages as a speed control
         // mechanism
-        match self.pending.entry(address) {
+        let mut queue = match self.pending.entry(address) {
             Entry::Occupied(entry) => entry.remove(),
             Entry::Vacant(_) => vec![],
+        };
+        if self.held.is_empty() {
+            return queue;
         }
+        let (released, mut held): (Vec<_>, Vec<_>) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition(|held| held.mac == address && held.slots == 0);
+        for held in held.iter_mut().filter(|held| held.mac == address) {
+            held.slots -= 1;
+        }
+        self.held = held;
+        queue.extend(released.into_iter().map(|held| held.delivery));
+        queue
     }
 }
 
-fn send_to_mac(mac: MachineId, pending: &mut Pending, message: Message) {
+fn send_to_mac(mac: MachineId, pending: &mut Pending, delivery: Delivery) {
     match pending.entry(mac) {
         Entry::Occupied(mut entry) => {
-            entry.get_mut().push(message);
+            entry.get_mut().push(delivery);
         }
         Entry::Vacant(entry) => {
-            entry.insert(vec![message]);
+            entry.insert(vec![delivery]);
+        }
+    }
+}
+
+/// A message the reordering of a [`Network`] holds back.
+#[derive(Debug, Clone)]
+struct Held {
+    mac: MachineId,
+    /// The delivery slots the machine has to wait for it
+    slots: u32,
+    delivery: Delivery,
+}
+
+/// A message on its way across a [`Network`], along with where and when it
+/// came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    /// The machine that sent the message
+    pub source: MachineId,
+    /// The round the message was sent in
+    pub sent: Round,
+    /// Whether the message was sent to every machine on the network
+    pub broadcast: bool,
+    pub message: Message,
+}
+
+/// A six byte, MAC-style address saying to whom to send a [`Message`] across a
+/// [`Network`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PhysicalAddress([u8; 6]);
+
+impl PhysicalAddress {
+    /// The address of every machine on the network.
+    pub const BROADCAST: Self = Self([0xff; 6]);
+
+    /// The address of the machine with the given ID. Machine addresses are
+    /// locally administered unicast addresses ending in the machine ID.
+    pub fn for_machine(machine: MachineId) -> Self {
+        let [.., a, b, c, d] = (machine as u64).to_be_bytes();
+        Self([0x02, 0x00, a, b, c, d])
+    }
+
+    /// Whether messages to this address go to every machine on the network.
+    pub fn is_broadcast(self) -> bool {
+        self == Self::BROADCAST
+    }
+
+    /// Gets the address as a `[u8; 6]`.
+    pub fn to_bytes(self) -> [u8; 6] {
+        self.0
+    }
+}
+
+impl Display for PhysicalAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+impl From<[u8; 6]> for PhysicalAddress {
+    fn from(address: [u8; 6]) -> Self {
+        Self(address)
+    }
+}
+
+impl From<PhysicalAddress> for [u8; 6] {
+    fn from(address: PhysicalAddress) -> Self {
+        address.0
+    }
+}
+
+impl From<PhysicalAddress> for u64 {
+    fn from(address: PhysicalAddress) -> Self {
+        let [a, b, c, d, e, f] = address.0;
+        u64::from_be_bytes([0, 0, a, b, c, d, e, f])
+    }
+}
+
+impl TryFrom<Primitive> for PhysicalAddress {
+    type Error = PrimitiveError;
+
+    fn try_from(value: Primitive) -> Result<Self, Self::Error> {
+        let [_, _, a, b, c, d, e, f] = value.ok_u64()?.to_be_bytes();
+        Ok(Self([a, b, c, d, e, f]))
+    }
+}
+
+impl From<PhysicalAddress> for Primitive {
+    fn from(address: PhysicalAddress) -> Self {
+        Primitive::U64(address.into())
+    }
+}
+
+/// How many messages a [`Network`] holds for each machine before it starts
+/// dropping the ones that arrive.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum QueueLimit {
+    /// Hold any number of messages
+    #[default]
+    Unbounded,
+    /// Hold up to this many messages and drop the ones arriving after that
+    DropTail(usize),
+    /// Drop messages early and at random as the queue fills up
+    Red(Red),
+}
+
+/// Settings for Random Early Detection, which drops a growing share of
+/// arriving messages as the average queue length rises. Senders that back off
+/// when their messages are lost then slow down before the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Red {
+    /// The most messages the queue ever holds
+    pub capacity: usize,
+    /// The average length below which nothing is dropped early
+    pub min_threshold: usize,
+    /// The average length from which every arriving message is dropped
+    pub max_threshold: usize,
+    /// The chance of a drop just below the maximum threshold
+    pub max_probability: f64,
+    /// How much each arriving message moves the average towards the current
+    /// queue length, between 0 and 1
+    pub weight: f64,
+}
+
+impl Red {
+    /// RED settings with the given queue capacity and thresholds, a maximum
+    /// drop probability of 10%, and an average weight of 0.2.
+    pub fn new(capacity: usize, min_threshold: usize, max_threshold: usize) -> Self {
+        Self {
+            capacity,
+            min_threshold,
+            max_threshold,
+            max_probability: 0.1,
+            weight: 0.2,
         }
     }
 }
 
-/// Describes to whom to send a [`Message`] across a [`Network`].
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum PhysicalAddress {
-    /// Send the message to a particular machine on the network
-    Recipient(MachineId),
-    /// Send the message to all machines on the network
-    Broadcast,
+/// Faults a [`Network`] injects into the messages it carries, so protocols can
+/// be tested against what a perfect network never does. Each is off unless
+/// set, and which messages they hit is random, from the network's seed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Impairments {
+    /// Hold back some messages so they arrive after ones sent later
+    pub reordering: Option<Reordering>,
+    /// The share of messages delivered twice, between 0 and 1
+    pub duplication: f64,
+}
+
+/// Settings for holding back a share of the messages a [`Network`] carries, so
+/// they arrive after messages sent later as they might over several paths.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reordering {
+    /// The share of messages held back, between 0 and 1
+    pub fraction: f64,
+    /// The most delivery slots a message is held back for. Each machine has
+    /// one slot a round, when it takes its queue.
+    pub max_delay: u32,
+}
+
+/// How many messages a [`Network`] queued for delivery and how many it
+/// dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DropStats {
+    /// Messages added to a machine's queue
+    pub queued: u64,
+    /// Messages dropped because the queue was full
+    pub tail_dropped: u64,
+    /// Messages dropped early by RED
+    pub early_dropped: u64,
+    /// Queued messages the reordering held back
+    pub reordered: u64,
+    /// Messages the network queued a second copy of
+    pub duplicated: u64,
+}
+
+/// How a machine orders the messages waiting to go out on a [`Network`].
+///
+/// Messages carry a precedence from 0 to 7, taken from the IPv4 type of
+/// service for IP traffic. Each protocol sending through the machine's tap
+/// counts as its own flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum QueueDiscipline {
+    /// Send messages in the order they were queued
+    #[default]
+    Fifo,
+    /// Send higher precedence messages first, in the order they were queued
+    /// within a precedence
+    Priority,
+    /// Take turns sending one message from each flow so no flow starves the
+    /// others
+    FairQueueing,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::message::Chunk;
+
+    fn delivery(text: impl Into<Chunk>) -> Delivery {
+        Delivery {
+            source: 0,
+            sent: 0,
+            broadcast: false,
+            message: Message::new(text),
+        }
+    }
+
+    fn messages(deliveries: Vec<Delivery>) -> Vec<Message> {
+        deliveries
+            .into_iter()
+            .map(|delivery| delivery.message)
+            .collect()
+    }
+
+    #[test]
+    fn delivers_to_the_addressed_machine_only() {
+        let mut network = Network::new(1500);
+        for id in 0..3 {
+            network.attach(&Machine::new([], id));
+        }
+        network.send(PhysicalAddress::for_machine(1), delivery(b"unicast"));
+        network.send(PhysicalAddress::BROADCAST, delivery(b"broadcast"));
+        let drops = network.send(PhysicalAddress::for_machine(7), delivery(b"nobody"));
+        assert!(matches!(drops[..], [(None, DropReason::NoSuchAddress)]));
+        assert_eq!(
+            messages(network.take_queue(1)),
+            [Message::new(b"unicast"), Message::new(b"broadcast")]
+        );
+        assert_eq!(
+            messages(network.take_queue(0)),
+            [Message::new(b"broadcast")]
+        );
+        assert_eq!(
+            messages(network.take_queue(2)),
+            [Message::new(b"broadcast")]
+        );
+    }
+
+    fn flood(limit: QueueLimit, messages: usize) -> (usize, DropStats) {
+        let mut network = Network::new(1500);
+        network.set_limit(limit);
+        network.attach(&Machine::new([], 0));
+        for _ in 0..messages {
+            network.send(PhysicalAddress::BROADCAST, delivery(b"flood"));
+        }
+        (network.take_queue(0).len(), network.stats())
+    }
+
+    #[test]
+    fn limits_queues() {
+        assert_eq!(
+            flood(QueueLimit::Unbounded, 50),
+            (
+                50,
+                DropStats {
+                    queued: 50,
+                    ..Default::default()
+                }
+            )
+        );
+        assert_eq!(
+            flood(QueueLimit::DropTail(10), 50),
+            (
+                10,
+                DropStats {
+                    queued: 10,
+                    tail_dropped: 40,
+                    early_dropped: 0,
+                    reordered: 0,
+                    duplicated: 0,
+                }
+            )
+        );
+
+        let (delivered, stats) = flood(QueueLimit::Red(Red::new(40, 5, 30)), 100);
+        assert_eq!(delivered as u64, stats.queued);
+        assert_eq!(stats.queued + stats.early_dropped + stats.tail_dropped, 100);
+        assert!(stats.early_dropped > 0);
+        // The average reaches the maximum threshold before the queue fills
+        assert!(delivered < 40);
+        assert_eq!(
+            flood(QueueLimit::Red(Red::new(40, 5, 30)), 100),
+            (delivered, stats)
+        );
+    }
+
+    /// Sends 30 numbered messages to machine 0, one every slot, and returns
+    /// the number and slot each arrived in.
+    fn reorder(seed: u64) -> (Vec<(u8, usize)>, DropStats) {
+        let mut network = Network::new(1500);
+        network.set_seed(seed);
+        network.set_impairments(Impairments {
+            reordering: Some(Reordering {
+                fraction: 0.3,
+                max_delay: 3,
+            }),
+            ..Default::default()
+        });
+        network.attach(&Machine::new([], 0));
+        let mut arrived = vec![];
+        for slot in 0..40 {
+            if slot < 30 {
+                network.send(PhysicalAddress::BROADCAST, delivery(vec![slot as u8]));
+            }
+            for delivery in network.take_queue(0) {
+                arrived.push((delivery.message.iter().next().unwrap(), slot));
+            }
+        }
+        (arrived, network.stats())
+    }
+
+    #[test]
+    fn reorders_a_share_of_messages() {
+        let (arrived, stats) = reorder(11);
+        // Every message arrives, no later than the most slots it can be held
+        let mut numbers: Vec<u8> = arrived.iter().map(|&(number, _)| number).collect();
+        assert_ne!(numbers, (0..30).collect::<Vec<_>>());
+        numbers.sort();
+        assert_eq!(numbers, (0..30).collect::<Vec<_>>());
+        assert!(arrived
+            .iter()
+            .all(|&(number, slot)| (number as usize..=number as usize + 3).contains(&slot)));
+        assert_eq!(
+            arrived
+                .iter()
+                .filter(|&&(number, slot)| slot > number as usize)
+                .count() as u64,
+            stats.reordered
+        );
+        assert_eq!(stats.queued, 30);
+        // The same seed reorders the same messages
+        assert_eq!(reorder(11), (arrived, stats));
+    }
+
+    #[test]
+    fn duplicates_a_share_of_messages() {
+        let mut network = Network::new(1500);
+        network.set_impairments(Impairments {
+            duplication: 0.25,
+            ..Default::default()
+        });
+        network.set_limit(QueueLimit::DropTail(100));
+        network.attach(&Machine::new([], 0));
+        for number in 0..40u8 {
+            assert!(network
+                .send(PhysicalAddress::BROADCAST, delivery(vec![number]))
+                .is_empty());
+        }
+        let stats = network.stats();
+        assert!(stats.duplicated > 0);
+        assert_eq!(stats.queued, 40 + stats.duplicated);
+        // Copies follow right behind the message they copy
+        let numbers: Vec<u8> = network
+            .take_queue(0)
+            .iter()
+            .map(|delivery| delivery.message.iter().next().unwrap())
+            .collect();
+        assert_eq!(numbers.len() as u64, stats.queued);
+        assert!(numbers
+            .windows(2)
+            .all(|pair| pair[1] == pair[0] || pair[1] == pair[0] + 1));
+        assert_eq!((numbers[0], numbers[numbers.len() - 1]), (0, 39));
+    }
+
+    #[test]
+    fn formats_addresses() {
+        assert_eq!(
+            PhysicalAddress::for_machine(0x1234).to_string(),
+            "02:00:00:00:12:34"
+        );
+        assert_eq!(PhysicalAddress::BROADCAST.to_string(), "ff:ff:ff:ff:ff:ff");
+        let primitive = Primitive::from(PhysicalAddress::for_machine(3));
+        assert_eq!(
+            PhysicalAddress::try_from(primitive).unwrap(),
+            PhysicalAddress::for_machine(3)
+        );
+    }
 }
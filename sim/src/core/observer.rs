@@ -0,0 +1,131 @@
+use super::{message::Message, MachineId, PhysicalAddress, ProtocolId, SimError};
+use std::sync::{Arc, Mutex};
+
+/// A round of the simulation, counting from zero. Every machine is awoken once
+/// per round.
+pub type Round = u64;
+
+/// Watches a simulation as it runs.
+///
+/// Observers are registered with
+/// [`Internet::observe`](super::Internet::observe) and called back as messages
+/// move through the simulation, which makes it possible to gather metrics or
+/// draw the simulation without touching protocol code. Every method does
+/// nothing by default, so an observer only implements the events it cares
+/// about.
+///
+/// Under [`Internet::run_parallel`](super::Internet::run_parallel), the
+/// events of different machines in the same round may arrive in any order.
+/// Messages sent and dropped on a network are still reported in machine order.
+pub trait Observer: Send {
+    /// Called when a machine is awoken, before its protocols run.
+    fn machine_awoken(&mut self, _round: Round, _machine: MachineId) {}
+
+    /// Called when a machine puts a message on a network.
+    fn message_sent(&mut self, _event: &Sent) {}
+
+    /// Called when a machine takes a message off a network, before its
+    /// protocols handle it.
+    fn message_received(&mut self, _event: &Received) {}
+
+    /// Called when a message is lost, either on a network or because the
+    /// machine it arrived at could not handle it.
+    fn message_dropped(&mut self, _event: &Dropped) {}
+
+    /// Called when a protocol opens a session. Protocols report this through
+    /// [`ProtocolContext::session_opened`](super::ProtocolContext::session_opened),
+    /// as UDP, IPv4, and IPv6 do.
+    fn session_opened(&mut self, _event: &SessionEvent) {}
+
+    /// Called when a protocol closes a session. None of the protocols that
+    /// come with Elvis close their sessions yet.
+    fn session_closed(&mut self, _event: &SessionEvent) {}
+}
+
+/// A shared handle to an [`Observer`].
+pub type SharedObserver = Arc<Mutex<dyn Observer>>;
+
+/// A message a machine put on a network.
+#[derive(Debug, Clone, Copy)]
+pub struct Sent<'a> {
+    pub round: Round,
+    /// The sending machine
+    pub machine: MachineId,
+    /// The index of the network, as returned by
+    /// [`Internet::network`](super::Internet::network)
+    pub network: usize,
+    pub destination: PhysicalAddress,
+    pub message: &'a Message,
+}
+
+/// A message a machine took off a network.
+#[derive(Debug, Clone, Copy)]
+pub struct Received<'a> {
+    pub round: Round,
+    /// The receiving machine
+    pub machine: MachineId,
+    /// The machine that sent the message
+    pub source: MachineId,
+    /// The network the message came over
+    pub network: usize,
+    /// The round the message was sent in
+    pub sent: Round,
+    pub message: &'a Message,
+}
+
+/// A message that was lost.
+#[derive(Debug, Clone, Copy)]
+pub struct Dropped<'a> {
+    pub round: Round,
+    /// The machine that sent the message
+    pub source: MachineId,
+    /// The machine the message was for, if any machine has its address
+    pub machine: Option<MachineId>,
+    /// The network the message was lost on, or `None` if it reached the
+    /// machine
+    pub network: Option<usize>,
+    pub reason: DropReason<'a>,
+    pub message: &'a Message,
+}
+
+/// Why a message was lost.
+#[derive(Debug, Clone, Copy)]
+pub enum DropReason<'a> {
+    /// The machine's queue on the network was full
+    QueueFull,
+    /// The network dropped the message early by RED
+    EarlyDrop,
+    /// No machine on the network has the destination address
+    NoSuchAddress,
+    /// The machine received the message but its protocols failed to handle
+    /// it
+    Rejected(&'a SimError),
+}
+
+/// A session a protocol opened or closed.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionEvent {
+    pub round: Round,
+    pub machine: MachineId,
+    /// The protocol the session belongs to
+    pub protocol: ProtocolId,
+    /// The protocol the session delivers messages to
+    pub upstream: ProtocolId,
+}
+
+/// The observers of a simulation.
+#[derive(Clone, Default)]
+pub(super) struct Observers(Arc<Vec<SharedObserver>>);
+
+impl Observers {
+    pub fn new(observers: Vec<SharedObserver>) -> Self {
+        Self(Arc::new(observers))
+    }
+
+    /// Calls `event` on each observer in the order they were registered.
+    pub fn notify(&self, mut event: impl FnMut(&mut dyn Observer)) {
+        for observer in self.0.iter() {
+            event(&mut *observer.lock().unwrap());
+        }
+    }
+}
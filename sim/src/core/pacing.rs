@@ -0,0 +1,127 @@
+use super::Round;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+use thiserror::Error as ThisError;
+
+/// How the rounds of a simulation line up with the wall clock.
+///
+/// Rounds are the only clock a simulation has. Left unpaced, they run back to
+/// back as fast as the machines allow, which suits tests and experiments. To
+/// watch a demo unfold live, each round can instead stand for some span of
+/// time and be played back at real time or a multiple of it.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Pacing {
+    /// Run each round as soon as the last one finishes
+    #[default]
+    Unpaced,
+    /// Start each round once the wall clock has caught up with it
+    RealTime {
+        /// The time each round stands for
+        round: Duration,
+        /// How many times faster than real time to play the rounds back, 2.0
+        /// for twice as fast. Must be positive, and not so small that a round
+        /// would take longer than a [`Duration`] can hold.
+        speedup: f64,
+    },
+}
+
+impl Pacing {
+    /// Plays rounds that each stand for `round` back at real time.
+    pub fn real_time(round: Duration) -> Self {
+        Self::RealTime {
+            round,
+            speedup: 1.0,
+        }
+    }
+
+    /// The wall-clock time between the starts of rounds, if paced.
+    pub(crate) fn interval(self) -> Result<Option<Duration>, InvalidSpeedup> {
+        match self {
+            Self::Unpaced => Ok(None),
+            Self::RealTime { round, speedup } => {
+                if !(speedup.is_finite() && speedup > 0.0) {
+                    Err(InvalidSpeedup(speedup))?
+                }
+                Duration::try_from_secs_f64(round.as_secs_f64() / speedup)
+                    .map(Some)
+                    .map_err(|_| InvalidSpeedup(speedup))
+            }
+        }
+    }
+}
+
+/// The speedup of [`Pacing::RealTime`] was not a positive number, or was too
+/// small for the length of a round to be held in a [`Duration`].
+#[derive(Debug, Clone, Copy, PartialEq, ThisError)]
+#[error("Expected a positive speedup, found `{0}`")]
+pub struct InvalidSpeedup(pub f64);
+
+/// Holds a run back so that its rounds start on the schedule its [`Pacing`]
+/// sets.
+pub(super) struct Pacer {
+    interval: Option<Duration>,
+    start: Instant,
+    first_round: Round,
+}
+
+impl Pacer {
+    /// Starts the schedule now, at `round`, with rounds `interval` apart or
+    /// unpaced without one.
+    pub fn new(interval: Option<Duration>, round: Round) -> Self {
+        Self {
+            interval,
+            start: Instant::now(),
+            first_round: round,
+        }
+    }
+
+    /// Waits until it's time for `round` to start. Rounds are scheduled from
+    /// the start of the run rather than from each other, so a round that runs
+    /// long is made up for by shorter waits after it.
+    pub fn wait_for(&self, round: Round) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let due = self.start + interval.mul_f64((round - self.first_round) as f64);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speeds_rounds_up() {
+        let pacing = Pacing::RealTime {
+            round: Duration::from_millis(100),
+            speedup: 4.0,
+        };
+        assert_eq!(pacing.interval(), Ok(Some(Duration::from_millis(25))));
+        assert_eq!(
+            Pacing::real_time(Duration::from_secs(1)).interval(),
+            Ok(Some(Duration::from_secs(1)))
+        );
+        assert_eq!(Pacing::default().interval(), Ok(None));
+    }
+
+    #[test]
+    fn rejects_speedups_that_are_not_positive() {
+        let speedup = |speedup| {
+            Pacing::RealTime {
+                round: Duration::from_secs(1),
+                speedup,
+            }
+            .interval()
+        };
+        assert_eq!(speedup(0.0), Err(InvalidSpeedup(0.0)));
+        assert_eq!(speedup(-2.0), Err(InvalidSpeedup(-2.0)));
+        assert_eq!(speedup(f64::INFINITY), Err(InvalidSpeedup(f64::INFINITY)));
+        assert!(speedup(f64::NAN).is_err());
+        assert_eq!(speedup(1e-300), Err(InvalidSpeedup(1e-300)));
+    }
+}
@@ -1,50 +1,85 @@
 use super::{
     control::make_key, message::Message, session::ControlFlow, Control, ProtocolContext,
-    SharedSession,
+    SharedSession, SimError,
 };
-use std::{cell::RefCell, error::Error, rc::Rc};
+use std::sync::{Arc, Mutex};
 
 /// A unique identifier for a [`Protocol`].
+///
+/// A machine may run several instances of the same protocol, for example two
+/// user processes running the same application. The ID identifies both the
+/// kind of protocol and which of its instances is meant. IDs created with
+/// [`new`](Self::new) or [`from_string`](Self::from_string) refer to the first
+/// instance, which is the only one in most machines.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct ProtocolId(u64);
+pub struct ProtocolId {
+    id: u64,
+    instance: u32,
+}
 
 impl ProtocolId {
     /// Creates a new protocol ID with the given number.
     pub const fn new(id: u64) -> Self {
-        Self(id)
+        Self { id, instance: 0 }
     }
 
     /// Creates a pseudorandom ID by hashing the string identifier.
     pub const fn from_string(string: &'static str) -> Self {
-        Self(make_key(string))
+        Self::new(make_key(string))
+    }
+
+    /// The ID of the given instance of the same protocol. Instances are
+    /// numbered from zero in the order they were given to the machine.
+    pub const fn with_instance(self, instance: u32) -> Self {
+        Self {
+            id: self.id,
+            instance,
+        }
+    }
+
+    /// The ID of the protocol's first instance. Use this to check which kind
+    /// of protocol an ID refers to, regardless of instance.
+    pub const fn base(self) -> Self {
+        self.with_instance(0)
+    }
+
+    /// Gets which instance of the protocol the ID refers to.
+    pub fn instance(self) -> u32 {
+        self.instance
     }
 
-    /// Gets the underlying ID number.
+    /// Gets the underlying ID number. This does not include the instance.
     pub fn into_inner(self) -> u64 {
-        self.0
+        self.id
     }
 }
 
 impl From<u64> for ProtocolId {
     fn from(n: u64) -> Self {
-        Self(n)
+        Self::new(n)
     }
 }
 
 impl From<ProtocolId> for u64 {
     fn from(id: ProtocolId) -> Self {
-        id.0
+        id.id
     }
 }
 
 /// A shared handle to a [`Protocol`].
-pub type RcProtocol = Rc<RefCell<dyn Protocol>>;
+pub type SharedProtocol = Arc<Mutex<dyn Protocol>>;
 
 /// A member of a networking protocol stack.
 ///
 /// A protocol is responsible for creating new [`Session`](super::Session)s and
-/// demultiplexing requests to the correct session.
-pub trait Protocol {
+/// demultiplexing requests to the correct session. Protocols must be [`Send`]
+/// so that [`Internet::run_parallel`](super::Internet::run_parallel) can awake
+/// machines on other threads.
+///
+/// Failures are reported as a [`SimError`], which the
+/// [`Machine`](super::Machine) uses to decide whether to drop the message at
+/// hand or end the simulation.
+pub trait Protocol: Send {
     // TODO(hardint): We need methods that allow other protocols to query info about a
     // protocol and its sessions. For example, a TCP or an IP protocol will want
     // a method to learn about a Tap's MTU.
@@ -71,7 +106,7 @@ pub trait Protocol {
         upstream: ProtocolId,
         participants: Control,
         context: &mut ProtocolContext,
-    ) -> Result<SharedSession, Box<dyn Error>>;
+    ) -> Result<SharedSession, SimError>;
 
     /// Listen for new connections.
     ///
@@ -93,7 +128,7 @@ pub trait Protocol {
         upstream: ProtocolId,
         participants: Control,
         context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>>;
+    ) -> Result<(), SimError>;
 
     /// Identifies the session that a message belongs to and forwards the
     /// message to it.
@@ -113,11 +148,7 @@ pub trait Protocol {
     ///   asked to receive the message by calling [`listen`](Protocol::listen)
     ///   at an earlier time. If so, a new session should be created.
     /// - Call [`receive`](super::Session::receive) on the selected session.
-    fn demux(
-        &mut self,
-        message: Message,
-        context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>>;
+    fn demux(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError>;
 
     /// Called to allow the protocol to do some arbitrary work.
     ///
@@ -125,5 +156,20 @@ pub trait Protocol {
     /// from the network or sending a message from a user program. For example,
     /// a TCP session may need to advertise window sizes or retransmit data. A
     /// call to `awake` is its time to complete such tasks.
-    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>>;
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, SimError>;
+
+    /// Called with a message this protocol sent that its destination refused
+    /// because nothing there was listening for it, headers and all.
+    ///
+    /// Like [`demux`](Protocol::demux), a protocol removes its header, finds
+    /// the session the message was sent from, and hands the rest to it with
+    /// [`refused`](super::Session::refused), so the refusal makes its way back
+    /// up to the sender. Protocols that don't track refusals ignore them.
+    fn refused(
+        &mut self,
+        _message: Message,
+        _context: &mut ProtocolContext,
+    ) -> Result<(), SimError> {
+        Ok(())
+    }
 }
@@ -1,4 +1,11 @@
-use super::{protocol::RcProtocol, Control, ProtocolId, ProtocolMap, SharedSession};
+use super::{
+    call_trace::{SharedCallTrace, Traced},
+    free_instance,
+    observer::Observers,
+    protocol::SharedProtocol,
+    Control, MachineId, ProcessState, ProcessStates, ProtocolId, ProtocolMap, Round, SessionEvent,
+    SharedSession,
+};
 
 /// Provides a [`Protocol`](super::Protocol) with information about its
 /// execution environment.
@@ -6,6 +13,13 @@ use super::{protocol::RcProtocol, Control, ProtocolId, ProtocolMap, SharedSessio
 pub struct ProtocolContext {
     protocols: ProtocolMap,
     session_stack: Vec<SharedSession>,
+    current_protocol: Option<ProtocolId>,
+    states: ProcessStates,
+    spawned: Vec<(ProtocolId, SharedProtocol)>,
+    machine: MachineId,
+    round: Round,
+    observers: Observers,
+    call_trace: Option<SharedCallTrace>,
     /// A key-value store for exchanging unstructured information between
     /// [`Protocol`](super::Protocol)s.
     pub info: Control,
@@ -13,17 +27,134 @@ pub struct ProtocolContext {
 
 impl ProtocolContext {
     /// Create a new protocol context.
-    pub(super) fn new(protocols: ProtocolMap) -> Self {
+    pub(super) fn new(
+        protocols: ProtocolMap,
+        states: ProcessStates,
+        machine: MachineId,
+        round: Round,
+        observers: Observers,
+        call_trace: Option<SharedCallTrace>,
+    ) -> Self {
         Self {
             protocols,
             info: Control::new(),
             session_stack: vec![],
+            current_protocol: None,
+            states,
+            spawned: vec![],
+            machine,
+            round,
+            observers,
+            call_trace,
         }
     }
 
-    /// Get a handle to the protocol identified by `id`.
-    pub fn protocol(&self, id: ProtocolId) -> Option<RcProtocol> {
-        self.protocols.get(&id).cloned()
+    /// Get a handle to the protocol identified by `id`. Plain protocol IDs
+    /// such as [`Udp::ID`](crate::protocols::udp::Udp::ID) refer to the first
+    /// instance of a protocol.
+    ///
+    /// When the simulation [traces calls](super::Internet::trace_calls), the
+    /// handle records the calls made through it.
+    pub fn protocol(&self, id: ProtocolId) -> Option<SharedProtocol> {
+        let protocol = self.protocols.get(&id).cloned()?;
+        Some(match &self.call_trace {
+            Some(trace) => Traced::new_shared(id, protocol, trace.clone()),
+            None => protocol,
+        })
+    }
+
+    /// The IDs of every instance of the protocol with the given ID on this
+    /// machine, in instance order.
+    pub fn instances(&self, id: ProtocolId) -> Vec<ProtocolId> {
+        let mut instances: Vec<_> = self
+            .protocols
+            .keys()
+            .filter(|instance| instance.base() == id.base())
+            .copied()
+            .collect();
+        instances.sort();
+        instances
+    }
+
+    /// The instance-qualified ID of the protocol the machine is currently
+    /// [`awake`](super::Protocol::awake)ning, if any.
+    ///
+    /// A protocol that may run as one of several instances should pass this
+    /// rather than its plain ID as the `upstream` when opening or listening,
+    /// so that messages find their way back to the right instance.
+    pub fn current_protocol(&self) -> Option<ProtocolId> {
+        self.current_protocol
+    }
+
+    /// Adds a new protocol, typically a
+    /// [`UserProcess`](crate::protocols::user_process::UserProcess), to the
+    /// running machine and returns its instance-qualified ID. A server might
+    /// use this to start a handler for each new connection.
+    ///
+    /// The protocol starts out [`Starting`](ProcessState::Starting) and is
+    /// first awoken the next time the machine runs.
+    pub fn spawn(&mut self, protocol: SharedProtocol) -> ProtocolId {
+        let base = protocol.lock().unwrap().id();
+        let id = free_instance(base, |id| {
+            self.protocols.contains_key(&id)
+                || self.spawned.iter().any(|&(spawned, _)| spawned == id)
+        });
+        self.spawned.push((id, protocol));
+        id
+    }
+
+    /// The lifecycle state of the protocol with the given ID, or `None` if
+    /// there is no such protocol on this machine.
+    pub fn process_state(&self, id: ProtocolId) -> Option<ProcessState> {
+        if self.spawned.iter().any(|&(spawned, _)| spawned == id) {
+            return Some(ProcessState::Starting);
+        }
+        self.states.lock().unwrap().get(&id).copied()
+    }
+
+    /// The machine the protocols are running on.
+    pub fn machine(&self) -> MachineId {
+        self.machine
+    }
+
+    /// The round the simulation is in.
+    pub fn round(&self) -> Round {
+        self.round
+    }
+
+    /// Tells the simulation's [`Observer`](super::Observer)s that `protocol`
+    /// opened a session delivering to `upstream`.
+    pub fn session_opened(&self, protocol: ProtocolId, upstream: ProtocolId) {
+        let event = self.session_event(protocol, upstream);
+        self.observers
+            .notify(|observer| observer.session_opened(&event));
+    }
+
+    /// Tells the simulation's [`Observer`](super::Observer)s that `protocol`
+    /// closed a session delivering to `upstream`.
+    pub fn session_closed(&self, protocol: ProtocolId, upstream: ProtocolId) {
+        let event = self.session_event(protocol, upstream);
+        self.observers
+            .notify(|observer| observer.session_closed(&event));
+    }
+
+    fn session_event(&self, protocol: ProtocolId, upstream: ProtocolId) -> SessionEvent {
+        SessionEvent {
+            round: self.round,
+            machine: self.machine,
+            protocol,
+            upstream,
+        }
+    }
+
+    /// Remove the protocols spawned through this context.
+    pub(super) fn take_spawned(&mut self) -> Vec<(ProtocolId, SharedProtocol)> {
+        std::mem::take(&mut self.spawned)
+    }
+
+    /// Set which protocol is currently awake.
+    pub(super) fn set_current_protocol(&mut self, id: Option<ProtocolId>) {
+        self.current_protocol = id;
     }
 
     /// Get a handle to the currently executing [`Session`](super::Session).
@@ -33,12 +164,12 @@ impl ProtocolContext {
 
     /// Add a new session to the top of the list of currently executing
     /// [`Session`](super::Session)s.
-    pub(super) fn push_session(&mut self, session: SharedSession) {
+    pub(crate) fn push_session(&mut self, session: SharedSession) {
         self.session_stack.push(session)
     }
 
     /// Remove the topmost currently executing [`Session`](super::Session)s.
-    pub(super) fn pop_session(&mut self) {
+    pub(crate) fn pop_session(&mut self) {
         self.session_stack.pop();
     }
 }
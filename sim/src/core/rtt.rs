@@ -0,0 +1,173 @@
+use super::Round;
+
+/// The retransmission timeout before any round trip has been measured.
+const INITIAL_RTO: Round = 3;
+/// The shortest retransmission timeout, and the clock granularity `G` of RFC
+/// 6298, since time only moves in whole rounds.
+const MIN_RTO: Round = 1;
+/// The longest retransmission timeout, however far it has backed off.
+const MAX_RTO: Round = 64;
+/// The weight of a new sample in the smoothed round trip time, alpha in RFC
+/// 6298
+const ALPHA: f64 = 1.0 / 8.0;
+/// The weight of a new sample in the round trip time variation, beta in RFC
+/// 6298
+const BETA: f64 = 1.0 / 4.0;
+/// How many variations the timeout allows on top of the smoothed round trip
+/// time, K in RFC 6298
+const K: f64 = 4.0;
+
+/// Estimates the round trip time of a connection and the retransmission
+/// timeout that follows from it, as in RFC 6298, with time counted in rounds.
+///
+/// A sender measures a sample when an acknowledgement arrives for a segment,
+/// usually from the round the segment was sent in, which the transport layer
+/// stamps on each message as its [`timestamp`](super::Message::timestamp).
+/// Following Karn's algorithm, segments that were retransmitted should not be
+/// sampled, since there is no telling which transmission was acknowledged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RttEstimator {
+    srtt: Option<f64>,
+    rttvar: f64,
+    rto: Round,
+    min_rto: Round,
+    max_rto: Round,
+    samples: u64,
+}
+
+/// A snapshot of an [`RttEstimator`], for sessions to report.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RttStats {
+    /// Round trip times measured so far
+    pub samples: u64,
+    /// The smoothed round trip time in rounds, if any were measured
+    pub srtt: Option<f64>,
+    /// The round trip time variation in rounds, if any were measured
+    pub rttvar: Option<f64>,
+    /// The current retransmission timeout
+    pub rto: Round,
+}
+
+impl RttEstimator {
+    /// Creates an estimator with no samples yet.
+    pub fn new() -> Self {
+        Self {
+            srtt: None,
+            rttvar: 0.0,
+            rto: INITIAL_RTO,
+            min_rto: MIN_RTO,
+            max_rto: MAX_RTO,
+            samples: 0,
+        }
+    }
+
+    /// Keeps the retransmission timeout between `min` and `max` rounds
+    /// instead of the defaults.
+    pub fn with_bounds(mut self, min: Round, max: Round) -> Self {
+        self.min_rto = min.max(MIN_RTO);
+        self.max_rto = max.max(self.min_rto);
+        self.rto = self.rto.clamp(self.min_rto, self.max_rto);
+        self
+    }
+
+    /// Updates the estimate with a measured round trip time.
+    pub fn sample(&mut self, rtt: Round) {
+        let rtt = rtt as f64;
+        let srtt = match self.srtt {
+            None => {
+                self.rttvar = rtt / 2.0;
+                rtt
+            }
+            Some(srtt) => {
+                self.rttvar = (1.0 - BETA) * self.rttvar + BETA * (srtt - rtt).abs();
+                (1.0 - ALPHA) * srtt + ALPHA * rtt
+            }
+        };
+        self.srtt = Some(srtt);
+        self.samples += 1;
+        let rto = srtt + (K * self.rttvar).max(MIN_RTO as f64);
+        self.rto = (rto.ceil() as Round).clamp(self.min_rto, self.max_rto);
+    }
+
+    /// Updates the estimate with a round trip from `sent` to `now`, such as
+    /// a message's timestamp and the round its acknowledgement arrived in.
+    pub fn sample_since(&mut self, sent: Round, now: Round) {
+        self.sample(now.saturating_sub(sent));
+    }
+
+    /// Doubles the retransmission timeout, up to the maximum, after the
+    /// retransmission timer runs out.
+    pub fn back_off(&mut self) {
+        self.rto = self.rto.saturating_mul(2).min(self.max_rto);
+    }
+
+    /// How many rounds to wait for an acknowledgement before retransmitting.
+    pub fn rto(&self) -> Round {
+        self.rto
+    }
+
+    /// The smoothed round trip time in rounds, if any were measured.
+    pub fn srtt(&self) -> Option<f64> {
+        self.srtt
+    }
+
+    /// The round trip time variation in rounds, if any were measured.
+    pub fn rttvar(&self) -> Option<f64> {
+        self.srtt.map(|_| self.rttvar)
+    }
+
+    pub fn stats(&self) -> RttStats {
+        RttStats {
+            samples: self.samples,
+            srtt: self.srtt(),
+            rttvar: self.rttvar(),
+            rto: self.rto,
+        }
+    }
+}
+
+impl Default for RttEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_rfc_6298() {
+        let mut estimator = RttEstimator::new();
+        assert_eq!(estimator.rto(), INITIAL_RTO);
+        assert_eq!(estimator.srtt(), None);
+
+        estimator.sample(4);
+        assert_eq!(estimator.srtt(), Some(4.0));
+        assert_eq!(estimator.rttvar(), Some(2.0));
+        assert_eq!(estimator.rto(), 12);
+
+        estimator.sample_since(10, 12);
+        // RTTVAR = 3/4 * 2 + 1/4 * |4 - 2|, SRTT = 7/8 * 4 + 1/8 * 2
+        assert_eq!(estimator.rttvar(), Some(2.0));
+        assert_eq!(estimator.srtt(), Some(3.75));
+        assert_eq!(estimator.rto(), 12);
+        assert_eq!(estimator.stats().samples, 2);
+    }
+
+    #[test]
+    fn settles_and_backs_off_within_bounds() {
+        let mut estimator = RttEstimator::new().with_bounds(2, 10);
+        for _ in 0..100 {
+            estimator.sample(1);
+        }
+        // The variation decays toward zero, leaving the granularity on top
+        assert_eq!(estimator.rto(), 2);
+        estimator.back_off();
+        assert_eq!(estimator.rto(), 4);
+        for _ in 0..10 {
+            estimator.back_off();
+        }
+        assert_eq!(estimator.rto(), 10);
+    }
+}
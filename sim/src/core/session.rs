@@ -1,5 +1,4 @@
-use super::{Message, ProtocolContext};
-use std::error::Error;
+use super::{Message, ProtocolContext, SimError};
 
 /// Holds the state for a particular connection.
 ///
@@ -8,23 +7,15 @@ use std::error::Error;
 /// messages with a particular pair of local and remote addresses. A session is
 /// in charge of appending headers to outgoing messages, deciding which protocol
 /// to use for demuxing incoming messages, and keeping track of state such as
-/// TCP windows.
-pub trait Session {
+/// TCP windows. Like protocols, sessions must be [`Send`].
+pub trait Session: Send {
     /// Takes the message, appends headers, and forwards it to the next session
     /// in the chain for further processing.
-    fn send(
-        &mut self,
-        message: Message,
-        context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>>;
+    fn send(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError>;
 
     /// Takes an incoming message and decides which protocol to send it to for
     /// further processing.
-    fn receive(
-        &mut self,
-        message: Message,
-        context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>>;
+    fn receive(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError>;
 
     /// Called to allow a session to carry out some work outside the context of
     /// responding to a message.
@@ -32,15 +23,44 @@ pub trait Session {
     /// As an example, TCP may decide to retransmit packets or poll empty window
     /// sizes even when no new messages are being sent or received. This
     /// lifecycle method is a session's opportunity to carry out such tasks.
-    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>>;
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, SimError>;
+
+    /// Takes a message this session sent that its destination refused, with
+    /// the session's own header already removed, and passes it on to the
+    /// upstream protocol's [`refused`](super::Protocol::refused). Sessions
+    /// that don't track refusals ignore them.
+    fn refused(
+        &mut self,
+        _message: Message,
+        _context: &mut ProtocolContext,
+    ) -> Result<(), SimError> {
+        Ok(())
+    }
 }
 
 /// Expresses what to do after a protocol is called on to run.
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum ControlFlow {
     /// Keep running the simulation
     #[default]
     Continue,
     /// Stop running the simulation
     EndSimulation,
+    /// Stop awaking the protocol that returned this, typically a user process
+    /// that is done. The rest of the simulation keeps running. Sessions
+    /// returning this are treated as if they returned
+    /// [`Continue`](Self::Continue).
+    Exit,
+}
+
+/// Where a [`Protocol`](super::Protocol) is in its lifecycle on a machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProcessState {
+    /// Added to the machine, for example by
+    /// [`spawn`](super::ProtocolContext::spawn), but not yet awoken
+    Starting,
+    /// Awoken at least once and still running
+    Running,
+    /// Returned [`ControlFlow::Exit`] and will not be awoken again
+    Exited,
 }
@@ -1,21 +1,22 @@
-use super::{Message, ProtocolContext, Session};
-use std::{cell::RefCell, error::Error, rc::Rc};
+use super::{Message, ProtocolContext, Session, SimError};
+use std::sync::{Arc, Mutex};
 
 /// A shared handle to a [`Session`].
 ///
 /// In addition to facilitating multiple ownership, a shared session also acts a
 /// proxy to the underlying session and makes sure that the correct current
-/// session is applied to the context.
+/// session is applied to the context, and taken off again once the call
+/// returns, even if it fails.
 #[derive(Clone)]
 pub struct SharedSession {
-    session: Rc<RefCell<dyn Session>>,
+    session: Arc<Mutex<dyn Session>>,
 }
 
 impl SharedSession {
     /// Creates a new shared session
     pub fn new(session: impl Session + 'static) -> Self {
         Self {
-            session: Rc::new(RefCell::new(session)),
+            session: Arc::new(Mutex::new(session)),
         }
     }
 
@@ -25,11 +26,11 @@ impl SharedSession {
         &mut self,
         message: Message,
         context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), SimError> {
         context.push_session(self.clone());
-        self.session.borrow_mut().send(message, context)?;
+        let result = self.session.lock().unwrap().send(message, context);
         context.pop_session();
-        Ok(())
+        result
     }
 
     /// Updates the current session on the context and calls
@@ -38,34 +39,47 @@ impl SharedSession {
         &mut self,
         message: Message,
         context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), SimError> {
         context.push_session(self.clone());
-        self.session.borrow_mut().receive(message, context)?;
+        let result = self.session.lock().unwrap().receive(message, context);
         context.pop_session();
-        Ok(())
+        result
+    }
+
+    /// Updates the current session on the context and calls
+    /// [`refused`](Session::refused) on the underlying session.
+    pub fn refused(
+        &mut self,
+        message: Message,
+        context: &mut ProtocolContext,
+    ) -> Result<(), SimError> {
+        context.push_session(self.clone());
+        let result = self.session.lock().unwrap().refused(message, context);
+        context.pop_session();
+        result
     }
 
     /// Updates the current session on the context and calls
     /// [`awake`](Session::awake) on the underlying session.
-    pub fn awake(&mut self, context: &mut ProtocolContext) -> Result<(), Box<dyn Error>> {
+    pub fn awake(&mut self, context: &mut ProtocolContext) -> Result<(), SimError> {
         context.push_session(self.clone());
-        self.session.borrow_mut().awake(context)?;
+        let result = self.session.lock().unwrap().awake(context);
         context.pop_session();
-        Ok(())
+        result.map(|_| ())
     }
 }
 
-impl From<Rc<RefCell<dyn Session>>> for SharedSession {
-    fn from(session: Rc<RefCell<dyn Session>>) -> Self {
+impl From<Arc<Mutex<dyn Session>>> for SharedSession {
+    fn from(session: Arc<Mutex<dyn Session>>) -> Self {
         Self { session }
     }
 }
 
-impl<T> From<Rc<RefCell<T>>> for SharedSession
+impl<T> From<Arc<Mutex<T>>> for SharedSession
 where
     T: Session + 'static,
 {
-    fn from(session: Rc<RefCell<T>>) -> Self {
+    fn from(session: Arc<Mutex<T>>) -> Self {
         Self { session }
     }
 }
@@ -0,0 +1,114 @@
+use super::ProtocolId;
+use crate::protocols::{
+    compression::CompressionError, ipv4::Ipv4Error, ipv6::Ipv6Error, tap::TapError, tls::TlsError,
+    udp::UdpError,
+};
+use std::error::Error;
+use thiserror::Error as ThisError;
+
+/// An error from a [`Protocol`](super::Protocol) or
+/// [`Session`](super::Session).
+///
+/// The protocols that come with Elvis each have a variant, so callers can
+/// match on what went wrong. Errors from applications and other protocols end
+/// up in [`Other`](SimError::Other).
+#[derive(Debug, ThisError)]
+pub enum SimError {
+    #[error(transparent)]
+    Tap(#[from] TapError),
+    #[error(transparent)]
+    Ipv4(#[from] Ipv4Error),
+    #[error(transparent)]
+    Ipv6(#[from] Ipv6Error),
+    #[error(transparent)]
+    Udp(#[from] UdpError),
+    #[error(transparent)]
+    Compression(#[from] CompressionError),
+    #[error(transparent)]
+    Tls(#[from] TlsError),
+    /// A call on a protocol that was already locked further up the call
+    /// chain, which a [`CallTrace`](super::CallTrace) caught before it could
+    /// deadlock
+    #[error("Calling {0:?} would deadlock, it is already locked up the call chain")]
+    Reentrant(ProtocolId),
+    #[error("{0}")]
+    Other(Box<dyn Error>),
+}
+
+impl SimError {
+    /// Whether the error should halt the simulation.
+    ///
+    /// Most errors come from a message that was malformed or that nobody was
+    /// waiting for, and the machine drops the message and carries on. Trying
+    /// to open a session or listen binding twice means a protocol was set up
+    /// wrong, which would only keep failing, so the machine ends the
+    /// simulation instead. So does a call that would have deadlocked.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Self::Ipv4(Ipv4Error::BindingExists(_) | Ipv4Error::SessionExists(..))
+                | Self::Ipv6(
+                    Ipv6Error::BindingExists(_)
+                        | Ipv6Error::SessionExists(..)
+                        | Ipv6Error::NoNextHeader(_)
+                )
+                | Self::Udp(UdpError::BindingExists | UdpError::SessionExists)
+                | Self::Compression(
+                    CompressionError::BindingExists(_) | CompressionError::SessionExists
+                )
+                | Self::Tls(TlsError::BindingExists(_) | TlsError::SessionExists)
+                | Self::Reentrant(_)
+        )
+    }
+
+    /// Whether the error means the destination refused the message because
+    /// nothing there was listening for it. The machine tells the sender about
+    /// refused messages that were addressed to it alone, through
+    /// [`Protocol::refused`](super::Protocol::refused).
+    pub fn is_refusal(&self) -> bool {
+        matches!(
+            self,
+            Self::Ipv4(Ipv4Error::MissingListenBinding(_))
+                | Self::Ipv6(Ipv6Error::MissingListenBinding(_))
+                | Self::Udp(UdpError::MissingSession)
+        )
+    }
+
+    /// Whether the error means a session's send queue is full, so the message
+    /// wasn't sent but may be once the machine sends what is queued. See
+    /// [`SendQueueCapacity`](crate::protocols::tap::SendQueueCapacity).
+    pub fn is_would_block(&self) -> bool {
+        matches!(self, Self::Tap(TapError::WouldBlock))
+    }
+}
+
+impl From<Box<dyn Error>> for SimError {
+    /// Recovers the error kind of a `SimError` that was boxed, for example by
+    /// an application passing it on with `?`.
+    fn from(error: Box<dyn Error>) -> Self {
+        match error.downcast::<SimError>() {
+            Ok(error) => *error,
+            Err(error) => Self::Other(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boxed_errors_keep_their_kind() {
+        let boxed: Box<dyn Error> = SimError::from(UdpError::SessionExists).into();
+        let error = SimError::from(boxed);
+        assert!(matches!(error, SimError::Udp(UdpError::SessionExists)));
+        assert!(error.is_fatal());
+
+        let error = SimError::from(Box::<dyn Error>::from("Something else"));
+        assert!(matches!(error, SimError::Other(_)));
+        assert!(!error.is_fatal());
+        assert!(!SimError::from(UdpError::MissingSession).is_fatal());
+        assert!(SimError::from(UdpError::MissingSession).is_refusal());
+        assert!(!SimError::from(UdpError::LengthMismatch).is_refusal());
+    }
+}
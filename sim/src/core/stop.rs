@@ -0,0 +1,266 @@
+use super::{Dropped, MachineId, Observer, Received, Round, Sent, SessionEvent};
+
+/// A reason to end a simulation, set with
+/// [`Internet::stop_when`](super::Internet::stop_when).
+///
+/// Conditions are checked at the start of each round, so a simulation always
+/// ends on a round boundary and runs the same number of rounds whether it
+/// runs in parallel or not. Conditions combine with [`or`](Self::or) and
+/// [`and`](Self::and). A condition that counts or matches events stays met
+/// once it is met.
+pub enum StopCondition {
+    /// After this many rounds
+    Rounds(Round),
+    /// Once machines have received this many messages
+    Delivered(u64),
+    /// Once no machine has sent or received a message for this many rounds in
+    /// a row, for simulations whose applications have all gone quiet
+    Idle(Round),
+    /// Once the predicate returns true for an event. Under
+    /// [`Internet::run_parallel`](super::Internet::run_parallel), events of
+    /// the same round may arrive in any order.
+    Event(Box<dyn FnMut(&Event) -> bool + Send>),
+    /// Once any of the conditions is met
+    Any(Vec<StopCondition>),
+    /// Once all of the conditions are met
+    All(Vec<StopCondition>),
+}
+
+impl StopCondition {
+    /// Stops once `predicate` returns true for an event.
+    pub fn event(predicate: impl FnMut(&Event) -> bool + Send + 'static) -> Self {
+        Self::Event(Box::new(predicate))
+    }
+
+    /// Stops once either condition is met.
+    pub fn or(self, other: Self) -> Self {
+        match self {
+            Self::Any(mut conditions) => {
+                conditions.push(other);
+                Self::Any(conditions)
+            }
+            condition => Self::Any(vec![condition, other]),
+        }
+    }
+
+    /// Stops once both conditions are met.
+    pub fn and(self, other: Self) -> Self {
+        match self {
+            Self::All(mut conditions) => {
+                conditions.push(other);
+                Self::All(conditions)
+            }
+            condition => Self::All(vec![condition, other]),
+        }
+    }
+}
+
+/// Something that happened in a simulation, as seen by an [`Observer`].
+#[derive(Debug, Clone, Copy)]
+pub enum Event<'a> {
+    MachineAwoken { round: Round, machine: MachineId },
+    MessageSent(&'a Sent<'a>),
+    MessageReceived(&'a Received<'a>),
+    MessageDropped(&'a Dropped<'a>),
+    SessionOpened(&'a SessionEvent),
+    SessionClosed(&'a SessionEvent),
+}
+
+/// A [`StopCondition`] along with what has been seen of it so far.
+enum Watched {
+    Rounds(Round),
+    Delivered(u64),
+    Idle(Round),
+    Event {
+        predicate: Box<dyn FnMut(&Event) -> bool + Send>,
+        matched: bool,
+    },
+    Any(Vec<Watched>),
+    All(Vec<Watched>),
+}
+
+impl Watched {
+    fn new(condition: StopCondition) -> Self {
+        match condition {
+            StopCondition::Rounds(rounds) => Self::Rounds(rounds),
+            StopCondition::Delivered(count) => Self::Delivered(count),
+            StopCondition::Idle(rounds) => Self::Idle(rounds),
+            StopCondition::Event(predicate) => Self::Event {
+                predicate,
+                matched: false,
+            },
+            StopCondition::Any(conditions) => {
+                Self::Any(conditions.into_iter().map(Self::new).collect())
+            }
+            StopCondition::All(conditions) => {
+                Self::All(conditions.into_iter().map(Self::new).collect())
+            }
+        }
+    }
+
+    fn observe(&mut self, event: &Event) {
+        match self {
+            Self::Event { predicate, matched } => {
+                if !*matched {
+                    *matched = predicate(event);
+                }
+            }
+            Self::Any(conditions) | Self::All(conditions) => {
+                for condition in conditions {
+                    condition.observe(event);
+                }
+            }
+            Self::Rounds(_) | Self::Delivered(_) | Self::Idle(_) => {}
+        }
+    }
+
+    fn is_met(&self, watch: &StopWatch, round: Round) -> bool {
+        match self {
+            Self::Rounds(rounds) => round >= *rounds,
+            Self::Delivered(count) => watch.delivered >= *count,
+            Self::Idle(rounds) => {
+                // The rounds before this one that had no traffic
+                let quiet = watch
+                    .last_active
+                    .map_or(round, |active| round.saturating_sub(active + 1));
+                quiet >= *rounds
+            }
+            Self::Event { matched, .. } => *matched,
+            Self::Any(conditions) => conditions.iter().any(|c| c.is_met(watch, round)),
+            Self::All(conditions) => conditions.iter().all(|c| c.is_met(watch, round)),
+        }
+    }
+}
+
+/// Watches a simulation for its [`StopCondition`]s. The simulation ends once
+/// any of them is met.
+#[derive(Default)]
+pub(super) struct StopWatch {
+    conditions: Vec<Watched>,
+    /// Messages received so far
+    delivered: u64,
+    /// The last round a message was sent or received in
+    last_active: Option<Round>,
+}
+
+impl StopWatch {
+    pub fn add(&mut self, condition: StopCondition) {
+        self.conditions.push(Watched::new(condition));
+    }
+
+    /// Whether the simulation should end before running `round`.
+    pub fn should_stop(&self, round: Round) -> bool {
+        self.conditions.iter().any(|c| c.is_met(self, round))
+    }
+
+    fn notify(&mut self, event: Event) {
+        for condition in self.conditions.iter_mut() {
+            condition.observe(&event);
+        }
+    }
+
+    fn active(&mut self, round: Round) {
+        self.last_active = Some(self.last_active.map_or(round, |active| active.max(round)));
+    }
+}
+
+impl Observer for StopWatch {
+    fn machine_awoken(&mut self, round: Round, machine: MachineId) {
+        self.notify(Event::MachineAwoken { round, machine });
+    }
+
+    fn message_sent(&mut self, event: &Sent) {
+        self.active(event.round);
+        self.notify(Event::MessageSent(event));
+    }
+
+    fn message_received(&mut self, event: &Received) {
+        self.delivered += 1;
+        self.active(event.round);
+        self.notify(Event::MessageReceived(event));
+    }
+
+    fn message_dropped(&mut self, event: &Dropped) {
+        self.notify(Event::MessageDropped(event));
+    }
+
+    fn session_opened(&mut self, event: &SessionEvent) {
+        self.notify(Event::SessionOpened(event));
+    }
+
+    fn session_closed(&mut self, event: &SessionEvent) {
+        self.notify(Event::SessionClosed(event));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{message::Message, PhysicalAddress};
+
+    fn receive(watch: &mut StopWatch, round: Round) {
+        let message = Message::new("Hello");
+        watch.message_received(&Received {
+            round,
+            machine: 1,
+            source: 0,
+            network: 0,
+            sent: round,
+            message: &message,
+        });
+    }
+
+    #[test]
+    fn counts_rounds_and_deliveries() {
+        let mut watch = StopWatch::default();
+        watch.add(StopCondition::Rounds(5).or(StopCondition::Delivered(2)));
+        assert!(!watch.should_stop(0));
+        receive(&mut watch, 0);
+        assert!(!watch.should_stop(1));
+        receive(&mut watch, 1);
+        assert!(watch.should_stop(2));
+
+        let mut watch = StopWatch::default();
+        watch.add(StopCondition::Rounds(5).or(StopCondition::Delivered(2)));
+        assert!(!watch.should_stop(4));
+        assert!(watch.should_stop(5));
+    }
+
+    #[test]
+    fn waits_for_quiet_rounds() {
+        let mut watch = StopWatch::default();
+        watch.add(StopCondition::Idle(3));
+        assert!(!watch.should_stop(2));
+        assert!(watch.should_stop(3));
+
+        let mut watch = StopWatch::default();
+        watch.add(StopCondition::Idle(3));
+        receive(&mut watch, 4);
+        assert!(!watch.should_stop(7));
+        assert!(watch.should_stop(8));
+    }
+
+    #[test]
+    fn matched_events_stay_matched() {
+        let mut watch = StopWatch::default();
+        watch.add(
+            StopCondition::event(|event| {
+                matches!(event, Event::MachineAwoken { machine: 2, .. })
+            })
+            .and(StopCondition::event(|event| {
+                matches!(event, Event::MessageSent(sent) if sent.destination.is_broadcast())
+            })),
+        );
+        watch.machine_awoken(0, 2);
+        assert!(!watch.should_stop(1));
+        let message = Message::new("Hello");
+        watch.message_sent(&Sent {
+            round: 1,
+            machine: 0,
+            network: 0,
+            destination: PhysicalAddress::BROADCAST,
+            message: &message,
+        });
+        assert!(watch.should_stop(2));
+    }
+}
@@ -0,0 +1,23 @@
+//! Entry points for the fuzz targets in `fuzz/`, built with the `fuzzing`
+//! feature. Each takes arbitrary bytes and panics if the parser it exercises
+//! panics or disagrees with the matching serializer.
+
+use crate::protocols::{ipv4, udp};
+
+/// Parses `data` as an IPv4 header and checks it builds back into the same
+/// bytes.
+pub fn ipv4_header(data: &[u8]) {
+    ipv4::check_round_trip(data);
+}
+
+/// Parses all but the first eight bytes of `data` as a UDP header and payload,
+/// with the first eight as the source and destination addresses, and checks
+/// the header builds back into the same bytes.
+pub fn udp_header(data: &[u8]) {
+    let Some((addresses, segment)) = data.split_first_chunk::<8>() else {
+        return;
+    };
+    let source: [u8; 4] = addresses[..4].try_into().unwrap();
+    let destination: [u8; 4] = addresses[4..].try_into().unwrap();
+    udp::check_round_trip(segment, source.into(), destination.into());
+}
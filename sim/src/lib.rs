@@ -13,5 +13,11 @@
 
 pub mod applications;
 pub mod core;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+pub mod prelude;
 pub mod protocols;
+pub mod scenario;
 pub mod simulation;
+pub mod timeline;
@@ -1,10 +1,43 @@
-use std::env;
+use elvis::simulation::ScenarioFile;
+use std::{env, fs, path::PathBuf, process::ExitCode};
 
-/// Without arguments, main runs the default simulation
+/// Without arguments, main runs the default simulation. Given a scenario
+/// file, it runs that instead and writes `events.jsonl` and `stats.json` to
+/// the output directory, the current one by default.
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     println!("Elvis v{}", env!("CARGO_PKG_VERSION"));
-    println!("Running default simulation...");
-    elvis::simulation::default_simulation().await;
-    println!("Done");
+    let mut args = env::args().skip(1);
+    let Some(path) = args.next() else {
+        println!("Running default simulation...");
+        elvis::simulation::default_simulation().await;
+        println!("Done");
+        return ExitCode::SUCCESS;
+    };
+    let output = args
+        .next()
+        .map_or_else(|| PathBuf::from("."), PathBuf::from);
+    if args.next().is_some() {
+        eprintln!("Usage: elvis [scenario-file [output-directory]]");
+        return ExitCode::FAILURE;
+    }
+
+    let scenario = match ScenarioFile::load(&path) {
+        Ok(scenario) => scenario,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("Running {path}...");
+    let report = scenario.run();
+    let written = fs::create_dir_all(&output)
+        .and_then(|_| fs::write(output.join("events.jsonl"), &report.events))
+        .and_then(|_| fs::write(output.join("stats.json"), &report.stats));
+    if let Err(e) = written {
+        eprintln!("Could not write results to {}: {e}", output.display());
+        return ExitCode::FAILURE;
+    }
+    println!("Done, results are in {}", output.display());
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,36 @@
+//! The types needed to write protocols and applications outside of Elvis.
+//!
+//! ```
+//! use elvis::prelude::*;
+//! ```
+//!
+//! Protocols written in other crates plug into a simulation the same way the
+//! ones that come with Elvis do: each machine is given its protocols as
+//! [`SharedProtocol`] trait objects when it is added with
+//! [`Internet::machine`], and they find one another by [`ProtocolId`]
+//! through the [`ProtocolContext`].
+//!
+//! - A protocol implements [`Protocol`], opening and listening through the
+//!   protocol below it and demuxing messages to the [`Session`]s it creates.
+//!   Sessions are wrapped in a [`SharedSession`] with
+//!   [`SharedSession::new`].
+//! - A program that sits at the top of the stack implements [`Application`]
+//!   and runs in a [`UserProcess`].
+//! - Values exchanged through a [`Control`], such as participants, are
+//!   [`ControlValue`]s under a key from [`make_key`]. The
+//!   [`from_impls`](crate::from_impls) macro converts them to and from the
+//!   types they wrap, and [`key_collisions`] checks new keys against the ones
+//!   Elvis already uses.
+//! - Failures are reported as [`SimError::Other`], which boxes any error.
+//!
+//! The `rot13` example in `examples/` is a complete protocol written this way.
+
+pub use crate::{
+    core::{
+        control::{key_collisions, make_key, ControlValue, Primitive, PrimitiveError},
+        message::Message,
+        Control, ControlFlow, Internet, Protocol, ProtocolContext, ProtocolId, Session,
+        SharedProtocol, SharedSession, SimError,
+    },
+    protocols::user_process::{Application, UserProcess},
+};
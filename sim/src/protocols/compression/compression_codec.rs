@@ -0,0 +1,193 @@
+//! A small LZ77-style codec in the spirit of LZSS.
+//!
+//! The compressed stream is a sequence of tokens, each starting with a byte
+//! `t`:
+//!
+//! - If `t < 0x80`, the next `t + 1` bytes are copied to the output as is.
+//! - Otherwise, `(t & 0x7f) + MIN_MATCH` bytes are copied from earlier in the
+//!   output, starting the big-endian `u16` that follows `t` bytes back. The
+//!   copy may overlap the bytes it produces, which encodes runs.
+
+use super::CompressionError;
+use std::collections::HashMap;
+
+/// The shortest back-reference worth encoding, since each takes three bytes.
+const MIN_MATCH: usize = 3;
+/// The longest back-reference a token can encode.
+const MAX_MATCH: usize = 0x7f + MIN_MATCH;
+/// The longest literal run a token can encode.
+const MAX_LITERALS: usize = 0x80;
+/// How far back a match may start.
+const WINDOW: usize = u16::MAX as usize;
+/// How many earlier positions with the same prefix to try for each match.
+const MAX_CHAIN: usize = 32;
+
+/// Compresses `input`. Data without repetition grows by one byte in 128.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    // The last position each three-byte prefix was seen at, and for each
+    // position the one before it with the same prefix
+    let mut heads: HashMap<[u8; MIN_MATCH], usize> = HashMap::new();
+    let mut previous = vec![usize::MAX; input.len()];
+    let mut literals_start = 0;
+    let mut i = 0;
+    while i < input.len() {
+        let (length, distance) = longest_match(input, i, &heads, &previous);
+        if length < MIN_MATCH {
+            insert(input, i, &mut heads, &mut previous);
+            i += 1;
+            continue;
+        }
+        push_literals(&mut output, &input[literals_start..i]);
+        output.push(0x80 | (length - MIN_MATCH) as u8);
+        output.extend_from_slice(&(distance as u16).to_be_bytes());
+        for position in i..i + length {
+            insert(input, position, &mut heads, &mut previous);
+        }
+        i += length;
+        literals_start = i;
+    }
+    push_literals(&mut output, &input[literals_start..]);
+    output
+}
+
+/// Reverses [`compress`].
+pub fn decompress(input: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    let mut output = Vec::with_capacity(input.len() * 2);
+    let mut bytes = input.iter();
+    while let Some(&token) = bytes.next() {
+        if token < 0x80 {
+            let count = token as usize + 1;
+            let literals = bytes.as_slice().get(..count);
+            output.extend_from_slice(literals.ok_or(CompressionError::Truncated)?);
+            bytes.nth(count - 1);
+        } else {
+            let length = (token & 0x7f) as usize + MIN_MATCH;
+            let distance = match (bytes.next(), bytes.next()) {
+                (Some(&high), Some(&low)) => u16::from_be_bytes([high, low]) as usize,
+                _ => Err(CompressionError::Truncated)?,
+            };
+            if distance == 0 || distance > output.len() {
+                Err(CompressionError::InvalidDistance(distance))?
+            }
+            let start = output.len() - distance;
+            for i in start..start + length {
+                output.push(output[i]);
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Finds the longest earlier match for the bytes at `i`, as its length and
+/// how far back it starts.
+fn longest_match(
+    input: &[u8],
+    i: usize,
+    heads: &HashMap<[u8; MIN_MATCH], usize>,
+    previous: &[usize],
+) -> (usize, usize) {
+    let Some(prefix) = input
+        .get(i..i + MIN_MATCH)
+        .and_then(|p| <[u8; MIN_MATCH]>::try_from(p).ok())
+    else {
+        return (0, 0);
+    };
+    let longest_possible = MAX_MATCH.min(input.len() - i);
+    let mut best = (0, 0);
+    let mut candidate = heads.get(&prefix).copied().unwrap_or(usize::MAX);
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::MAX || i - candidate > WINDOW {
+            break;
+        }
+        let length = input[candidate..]
+            .iter()
+            .zip(&input[i..i + longest_possible])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if length > best.0 {
+            best = (length, i - candidate);
+            if length == longest_possible {
+                break;
+            }
+        }
+        candidate = previous[candidate];
+    }
+    best
+}
+
+/// Records that the prefix at `i` was seen there.
+fn insert(
+    input: &[u8],
+    i: usize,
+    heads: &mut HashMap<[u8; MIN_MATCH], usize>,
+    previous: &mut [usize],
+) {
+    if let Some(prefix) = input.get(i..i + MIN_MATCH) {
+        if let Some(head) = heads.insert(prefix.try_into().unwrap(), i) {
+            previous[i] = head;
+        }
+    }
+}
+
+fn push_literals(output: &mut Vec<u8>, literals: &[u8]) {
+    for run in literals.chunks(MAX_LITERALS) {
+        output.push((run.len() - 1) as u8);
+        output.extend_from_slice(run);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::{collection::vec, prelude::*};
+
+    #[test]
+    fn shrinks_repetitive_data() {
+        let input = b"Hello, Hello, Hello, Hello, Hello, Hello!".repeat(10);
+        let compressed = compress(&input);
+        assert!(compressed.len() < input.len() / 4);
+        assert_eq!(decompress(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn encodes_runs_with_overlapping_copies() {
+        let compressed = compress(&[7; 100]);
+        // One literal and a match copying from a single byte back
+        assert_eq!(compressed, [0, 7, 0x80 | (99 - MIN_MATCH) as u8, 0, 1]);
+        assert_eq!(decompress(&compressed).unwrap(), [7; 100]);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert!(matches!(
+            decompress(&[3, b'a']),
+            Err(CompressionError::Truncated)
+        ));
+        assert!(matches!(
+            decompress(&[0x80, 0]),
+            Err(CompressionError::Truncated)
+        ));
+        assert!(matches!(
+            decompress(&[0, b'a', 0x80, 0, 2]),
+            Err(CompressionError::InvalidDistance(2))
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn round_trips(input in vec(0..4u8, 0..2000)) {
+            prop_assert_eq!(decompress(&compress(&input)).unwrap(), input);
+        }
+
+        #[test]
+        fn round_trips_arbitrary_bytes(input in vec(any::<u8>(), 0..600)) {
+            prop_assert_eq!(decompress(&compress(&input)).unwrap(), input);
+        }
+
+        #[test]
+        fn never_panics_decompressing(input in vec(any::<u8>(), 0..200)) {
+            let _ = decompress(&input);
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum CompressionError {
+    #[error("Tried to create an existing session")]
+    SessionExists,
+    #[error("Port {0} is already bound to another protocol")]
+    BindingExists(u16),
+    #[error("Tried to demux with a missing session and no listen binding")]
+    MissingSession,
+    #[error("Expected a one byte header")]
+    HeaderTooShort,
+    #[error("Unknown flags {0:#04x} in the header")]
+    UnknownFlags(u8),
+    #[error("The compressed payload ended in the middle of a token")]
+    Truncated,
+    #[error("The compressed payload refers {0} bytes back, before its start")]
+    InvalidDistance(usize),
+}
@@ -0,0 +1,135 @@
+use super::{compression_codec, CompressionError, FLAG_COMPRESSED};
+use crate::{
+    core::{
+        message::Message, Control, ControlFlow, ProtocolContext, ProtocolId, Session,
+        SharedSession, SimError,
+    },
+    protocols::{
+        ipv4::{LocalAddress, RemoteAddress},
+        udp::{LocalPort, RemotePort},
+    },
+};
+
+pub(super) struct CompressionSession {
+    pub upstream: ProtocolId,
+    pub downstream: SharedSession,
+    /// The smallest payload worth compressing
+    pub threshold: usize,
+}
+
+impl Session for CompressionSession {
+    fn send(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        let message = compress(message, self.threshold);
+        self.downstream.send(message, context)
+    }
+
+    fn receive(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        let message = decompress(message)?;
+        context
+            .protocol(self.upstream)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .demux(message, context)
+    }
+
+    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        Ok(ControlFlow::Continue)
+    }
+
+    fn refused(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        // Hand back the message as the application sent it
+        let message = decompress(message)?;
+        context
+            .protocol(self.upstream)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .refused(message, context)
+    }
+}
+
+/// Adds the header to a message, compressing the payload if it is at least
+/// `threshold` bytes long and compression makes it smaller.
+fn compress(message: Message, threshold: usize) -> Message {
+    let payload: Vec<_> = message.iter().collect();
+    if payload.len() >= threshold {
+        let compressed = compression_codec::compress(&payload);
+        if compressed.len() < payload.len() {
+            return Message::new(compressed).with_header(&[FLAG_COMPRESSED]);
+        }
+    }
+    message.with_header(&[0])
+}
+
+/// Removes the header from a message, decompressing the payload if the header
+/// says it is compressed.
+fn decompress(message: Message) -> Result<Message, CompressionError> {
+    let flags = message
+        .iter()
+        .next()
+        .ok_or(CompressionError::HeaderTooShort)?;
+    let payload = message.slice(1..);
+    match flags {
+        0 => Ok(payload),
+        FLAG_COMPRESSED => {
+            let payload: Vec<_> = payload.iter().collect();
+            Ok(Message::new(compression_codec::decompress(&payload)?))
+        }
+        flags => Err(CompressionError::UnknownFlags(flags)),
+    }
+}
+
+/// Identifies a session by the UDP connection it sends over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) struct SessionId {
+    pub local_address: LocalAddress,
+    pub local_port: LocalPort,
+    pub remote_address: RemoteAddress,
+    pub remote_port: RemotePort,
+}
+
+impl SessionId {
+    /// Takes the addresses and ports from participants or a context's info.
+    pub fn new(control: &Control) -> Self {
+        Self {
+            local_address: LocalAddress::try_from(control).unwrap(),
+            local_port: LocalPort::try_from(control).unwrap(),
+            remote_address: RemoteAddress::try_from(control).unwrap(),
+            remote_port: RemotePort::try_from(control).unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compresses_only_when_it_pays() {
+        let long = Message::new("abcabcabcabcabcabcabcabcabcabcabc");
+        let compressed = compress(long.clone(), 8);
+        assert_eq!(compressed.iter().next(), Some(FLAG_COMPRESSED));
+        assert!(compressed.iter().count() < long.iter().count());
+        assert_eq!(decompress(compressed).unwrap(), long);
+
+        // Below the threshold, and where compression would not help
+        for (message, threshold) in [(long.clone(), 64), (Message::new("abcdefgh"), 1)] {
+            let sent = compress(message.clone(), threshold);
+            assert_eq!(sent.iter().next(), Some(0));
+            assert_eq!(decompress(sent).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_flags() {
+        assert!(matches!(
+            decompress(Message::new(&[0x02, b'a'])),
+            Err(CompressionError::UnknownFlags(0x02))
+        ));
+        assert!(matches!(
+            decompress(Message::new(&[])),
+            Err(CompressionError::HeaderTooShort)
+        ));
+    }
+}
@@ -0,0 +1,171 @@
+//! A layer between [`Udp`] and applications that compresses payloads.
+
+use crate::{
+    core::{
+        message::Message, Control, ControlFlow, Protocol, ProtocolContext, ProtocolId,
+        SharedSession, SimError,
+    },
+    protocols::udp::{LocalPort, Udp},
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex},
+};
+
+mod compression_codec;
+
+mod compression_misc;
+pub use compression_misc::CompressionError;
+
+mod compression_session;
+use compression_session::{CompressionSession, SessionId};
+
+/// The payloads smaller than this are sent as they are by default.
+pub const DEFAULT_THRESHOLD: usize = 128;
+
+/// The header flag saying the payload is compressed.
+const FLAG_COMPRESSED: u8 = 0x01;
+
+/// Compresses the payloads of applications that send through it, on top of
+/// [`Udp`].
+///
+/// Each message gets a one byte header of flags. Payloads of at least the
+/// threshold size are compressed with a small LZ77-style codec and flagged as
+/// such, unless compressing would not make them smaller. The receiver reads
+/// the flag to know whether to decompress, so the two ends don't need to
+/// agree on a threshold.
+///
+/// Applications open and listen on the protocol just as they would on UDP,
+/// with the same participants, and UDP sees the protocol as its upstream.
+/// Only one protocol may listen on each local port.
+#[derive(Clone)]
+pub struct Compression {
+    threshold: usize,
+    listen_bindings: HashMap<LocalPort, ProtocolId>,
+    sessions: HashMap<SessionId, SharedSession>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+            listen_bindings: Default::default(),
+            sessions: Default::default(),
+        }
+    }
+}
+
+impl Compression {
+    /// A unique identifier for the protocol.
+    pub const ID: ProtocolId = ProtocolId::from_string("Compression");
+
+    /// Creates a new instance of the protocol.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a new shared handle to an instance of the protocol.
+    pub fn new_shared() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// Compresses payloads of at least `threshold` bytes instead of
+    /// [`DEFAULT_THRESHOLD`].
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+}
+
+impl Protocol for Compression {
+    fn id(&self) -> ProtocolId {
+        Self::ID
+    }
+
+    fn open(
+        &mut self,
+        upstream: ProtocolId,
+        participants: Control,
+        context: &mut ProtocolContext,
+    ) -> Result<SharedSession, SimError> {
+        let identifier = SessionId::new(&participants);
+        match self.sessions.entry(identifier) {
+            Entry::Occupied(_) => Err(CompressionError::SessionExists)?,
+            Entry::Vacant(entry) => {
+                let downstream = context
+                    .protocol(Udp::ID)
+                    .expect("No such protocol")
+                    .lock()
+                    .unwrap()
+                    .open(Self::ID, participants, context)?;
+                let session = SharedSession::new(CompressionSession {
+                    upstream,
+                    downstream,
+                    threshold: self.threshold,
+                });
+                entry.insert(session.clone());
+                context.session_opened(Self::ID, upstream);
+                Ok(session)
+            }
+        }
+    }
+
+    fn listen(
+        &mut self,
+        upstream: ProtocolId,
+        participants: Control,
+        context: &mut ProtocolContext,
+    ) -> Result<(), SimError> {
+        let local_port = LocalPort::try_from(&participants).unwrap();
+        match self.listen_bindings.entry(local_port) {
+            Entry::Occupied(entry) if *entry.get() != upstream => {
+                Err(CompressionError::BindingExists(local_port.into()))?
+            }
+            entry => {
+                entry.or_insert(upstream);
+            }
+        }
+
+        context
+            .protocol(Udp::ID)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .listen(Self::ID, participants, context)
+    }
+
+    fn demux(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        // UDP and IPv4 leave the connection's addresses and ports on the
+        // context
+        let identifier = SessionId::new(&context.info);
+        let mut session = match self.sessions.entry(identifier) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => match self.listen_bindings.get(&identifier.local_port) {
+                Some(&binding) => {
+                    let session = SharedSession::new(CompressionSession {
+                        upstream: binding,
+                        downstream: context.current_session().expect("No current session"),
+                        threshold: self.threshold,
+                    });
+                    entry.insert(session.clone());
+                    context.session_opened(Self::ID, binding);
+                    session
+                }
+                None => Err(CompressionError::MissingSession)?,
+            },
+        };
+        session.receive(message, context)
+    }
+
+    fn refused(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        let identifier = SessionId::new(&context.info);
+        match self.sessions.get(&identifier) {
+            Some(session) => session.clone().refused(message, context),
+            None => Ok(()),
+        }
+    }
+
+    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        Ok(ControlFlow::Continue)
+    }
+}
@@ -0,0 +1,307 @@
+//! Congestion control algorithms for reliable transport protocols.
+//!
+//! A sender may only have as many unacknowledged bytes in flight as its
+//! congestion window allows. The algorithms here decide how that window grows
+//! as acknowledgements arrive and shrinks when a loss is detected. They sit
+//! behind the [`CongestionControl`] trait so that a transport session can be
+//! handed any of them, and a simulation can run several side by side to
+//! compare them. A session picks its algorithm from the [`Algorithm`] control
+//! value in the participants it was opened with.
+//!
+//! There is no TCP in Elvis yet. These are the hook it is meant to use, so
+//! that the protocol does not have to be restructured to compare algorithms.
+
+use crate::core::control::{from_impls, make_key, ControlValue, Primitive, PrimitiveError};
+use thiserror::Error as ThisError;
+
+/// Decides the congestion window of one transport session.
+pub trait CongestionControl: Send {
+    /// The number of unacknowledged bytes the sender may have in flight.
+    fn window(&self) -> u32;
+
+    /// Called when `acked` more bytes have been acknowledged.
+    fn on_ack(&mut self, acked: u32);
+
+    /// Called when the sender detects that a segment was lost.
+    fn on_loss(&mut self, loss: Loss);
+}
+
+/// How a lost segment was detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Loss {
+    /// Three duplicate acknowledgements arrived, so later segments are still
+    /// getting through
+    DuplicateAcks,
+    /// The retransmission timer ran out, so nothing may be getting through
+    Timeout,
+}
+
+/// The congestion control algorithms to choose from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CongestionAlgorithm {
+    /// Slow start, additive increase, and halving on loss, as in RFC5681
+    #[default]
+    Reno,
+    /// The cubic window growth of RFC8312, counting time in round trips
+    CubicLite,
+    /// A window that never changes, as a baseline
+    FixedWindow,
+}
+
+impl CongestionAlgorithm {
+    /// Creates the algorithm's state for a session sending segments of up to
+    /// `mss` bytes.
+    pub fn build(self, mss: u32) -> Box<dyn CongestionControl> {
+        match self {
+            Self::Reno => Box::new(Reno::new(mss)),
+            Self::CubicLite => Box::new(CubicLite::new(mss)),
+            Self::FixedWindow => Box::new(FixedWindow::new(INITIAL_SEGMENTS * mss)),
+        }
+    }
+}
+
+impl TryFrom<Primitive> for CongestionAlgorithm {
+    type Error = AlgorithmError;
+
+    fn try_from(value: Primitive) -> Result<Self, Self::Error> {
+        match value.ok_u8()? {
+            0 => Ok(Self::Reno),
+            1 => Ok(Self::CubicLite),
+            2 => Ok(Self::FixedWindow),
+            other => Err(AlgorithmError::Unknown(other)),
+        }
+    }
+}
+
+impl From<CongestionAlgorithm> for Primitive {
+    fn from(algorithm: CongestionAlgorithm) -> Self {
+        Primitive::U8(algorithm as u8)
+    }
+}
+
+const ALGORITHM_KEY: u64 = make_key("Congestion Control Algorithm");
+/// A [`ControlValue`] for the congestion control algorithm a session should
+/// use. Sessions opened without one use [`CongestionAlgorithm::Reno`].
+pub type Algorithm = ControlValue<ALGORITHM_KEY, CongestionAlgorithm>;
+from_impls!(Algorithm, CongestionAlgorithm);
+
+#[derive(Debug, ThisError)]
+pub enum AlgorithmError {
+    #[error("{0}")]
+    Primitive(#[from] PrimitiveError),
+    #[error("There is no congestion control algorithm numbered {0}")]
+    Unknown(u8),
+}
+
+/// The window a connection starts with, in segments, as in RFC6928
+const INITIAL_SEGMENTS: u32 = 10;
+
+/// TCP Reno.
+#[derive(Debug, Clone)]
+pub struct Reno {
+    mss: u32,
+    window: u32,
+    slow_start_threshold: u32,
+    /// Bytes acknowledged towards the next increase in congestion avoidance
+    acked: u32,
+}
+
+impl Reno {
+    pub fn new(mss: u32) -> Self {
+        Self {
+            mss,
+            window: INITIAL_SEGMENTS * mss,
+            slow_start_threshold: u32::MAX,
+            acked: 0,
+        }
+    }
+}
+
+impl CongestionControl for Reno {
+    fn window(&self) -> u32 {
+        self.window
+    }
+
+    fn on_ack(&mut self, acked: u32) {
+        if self.window < self.slow_start_threshold {
+            // Slow start doubles the window every round trip
+            self.window = self.window.saturating_add(acked.min(self.mss));
+        } else {
+            // Congestion avoidance adds a segment every round trip
+            self.acked += acked;
+            if self.acked >= self.window {
+                self.acked -= self.window;
+                self.window = self.window.saturating_add(self.mss);
+            }
+        }
+    }
+
+    fn on_loss(&mut self, loss: Loss) {
+        self.slow_start_threshold = (self.window / 2).max(2 * self.mss);
+        self.acked = 0;
+        self.window = match loss {
+            Loss::DuplicateAcks => self.slow_start_threshold,
+            Loss::Timeout => self.mss,
+        };
+    }
+}
+
+/// A simplified CUBIC. The window follows the cubic curve of RFC8312 around
+/// the window at the last loss, without its TCP-friendly region, and with time
+/// measured in round trips, each taken to last one window's worth of
+/// acknowledgements.
+#[derive(Debug, Clone)]
+pub struct CubicLite {
+    mss: u32,
+    window: u32,
+    slow_start_threshold: u32,
+    /// The window just before the last loss, in segments
+    last_max: f64,
+    /// Round trips since the last loss
+    rounds: u32,
+    /// Bytes acknowledged in the current round trip
+    acked: u32,
+}
+
+impl CubicLite {
+    /// How aggressively the window grows away from the last maximum
+    const C: f64 = 0.4;
+    /// How much of the window is kept on loss
+    const BETA: f64 = 0.7;
+
+    pub fn new(mss: u32) -> Self {
+        Self {
+            mss,
+            window: INITIAL_SEGMENTS * mss,
+            slow_start_threshold: u32::MAX,
+            last_max: 0.0,
+            rounds: 0,
+            acked: 0,
+        }
+    }
+
+    /// The window in segments `rounds` round trips after the last loss.
+    fn cubic(&self, rounds: u32) -> f64 {
+        let k = (self.last_max * (1.0 - Self::BETA) / Self::C).cbrt();
+        Self::C * (rounds as f64 - k).powi(3) + self.last_max
+    }
+}
+
+impl CongestionControl for CubicLite {
+    fn window(&self) -> u32 {
+        self.window
+    }
+
+    fn on_ack(&mut self, acked: u32) {
+        if self.window < self.slow_start_threshold {
+            self.window = self.window.saturating_add(acked.min(self.mss));
+            return;
+        }
+        self.acked += acked;
+        if self.acked >= self.window {
+            self.acked -= self.window;
+            self.rounds += 1;
+            let target = (self.cubic(self.rounds) * self.mss as f64) as u32;
+            // Never shrink on an acknowledgement, and grow at least a little
+            self.window = target.max(self.window + self.mss / 2);
+        }
+    }
+
+    fn on_loss(&mut self, loss: Loss) {
+        self.last_max = self.window as f64 / self.mss as f64;
+        self.slow_start_threshold = ((self.window as f64 * Self::BETA) as u32).max(2 * self.mss);
+        self.rounds = 0;
+        self.acked = 0;
+        self.window = match loss {
+            Loss::DuplicateAcks => self.slow_start_threshold,
+            Loss::Timeout => self.mss,
+        };
+    }
+}
+
+/// A window of fixed size, ignoring acknowledgements and losses.
+#[derive(Debug, Clone)]
+pub struct FixedWindow {
+    window: u32,
+}
+
+impl FixedWindow {
+    pub fn new(window: u32) -> Self {
+        Self { window }
+    }
+}
+
+impl CongestionControl for FixedWindow {
+    fn window(&self) -> u32 {
+        self.window
+    }
+
+    fn on_ack(&mut self, _acked: u32) {}
+
+    fn on_loss(&mut self, _loss: Loss) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Control;
+
+    const MSS: u32 = 1000;
+
+    /// Acknowledges a full window `rounds` times.
+    fn run_rounds(algorithm: &mut dyn CongestionControl, rounds: u32) {
+        for _ in 0..rounds {
+            let window = algorithm.window();
+            for _ in 0..window.div_ceil(MSS) {
+                algorithm.on_ack(MSS);
+            }
+        }
+    }
+
+    #[test]
+    fn reno_slow_start_and_avoidance() {
+        let mut reno = Reno::new(MSS);
+        run_rounds(&mut reno, 2);
+        assert_eq!(reno.window(), 40 * MSS);
+        reno.on_loss(Loss::DuplicateAcks);
+        assert_eq!(reno.window(), 20 * MSS);
+        run_rounds(&mut reno, 3);
+        assert_eq!(reno.window(), 23 * MSS);
+        reno.on_loss(Loss::Timeout);
+        assert_eq!(reno.window(), MSS);
+    }
+
+    #[test]
+    fn cubic_recovers_towards_the_last_maximum() {
+        let mut cubic = CubicLite::new(MSS);
+        run_rounds(&mut cubic, 2);
+        assert_eq!(cubic.window(), 40 * MSS);
+        cubic.on_loss(Loss::DuplicateAcks);
+        assert_eq!(cubic.window(), 28 * MSS);
+        let mut windows = vec![];
+        for _ in 0..8 {
+            run_rounds(&mut cubic, 1);
+            windows.push(cubic.window() / MSS);
+        }
+        // Fast at first, flat around the old maximum, then probing past it
+        assert_eq!(windows, [36, 39, 39, 40, 42, 49, 63, 86]);
+    }
+
+    #[test]
+    fn fixed_window_and_selection() {
+        let mut participants = Control::new();
+        Algorithm::set(&mut participants, CongestionAlgorithm::FixedWindow);
+        let algorithm = Algorithm::try_from(&participants).map_or_else(
+            |_| CongestionAlgorithm::default(),
+            CongestionAlgorithm::from,
+        );
+        let mut fixed = algorithm.build(MSS);
+        run_rounds(fixed.as_mut(), 3);
+        fixed.on_loss(Loss::Timeout);
+        assert_eq!(fixed.window(), 10 * MSS);
+        assert!(matches!(
+            CongestionAlgorithm::try_from(Primitive::U8(9)),
+            Err(AlgorithmError::Unknown(9))
+        ));
+    }
+}
@@ -0,0 +1,177 @@
+use crate::{
+    core::{message::Message, ProtocolId},
+    protocols::ipv4::Ipv4Address,
+};
+
+/// What to do with a packet a [`Rule`] matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Action {
+    /// Let the packet through
+    #[default]
+    Allow,
+    /// Drop the packet
+    Deny,
+}
+
+/// Which way a packet is going through the firewall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Arriving from the network
+    Incoming,
+    /// Leaving for the network
+    Outgoing,
+}
+
+/// A firewall rule, matching the packets that fit all of its criteria.
+///
+/// A rule starts out matching every packet, and each criterion narrows it
+/// down. Addresses and ports are from the point of view of this machine, so
+/// the remote address is the source of incoming packets and the destination
+/// of outgoing ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rule {
+    action: Action,
+    direction: Option<Direction>,
+    local_address: Option<Ipv4Address>,
+    remote_address: Option<Ipv4Address>,
+    local_port: Option<u16>,
+    remote_port: Option<u16>,
+    protocol: Option<ProtocolId>,
+}
+
+impl Rule {
+    fn new(action: Action) -> Self {
+        Self {
+            action,
+            direction: None,
+            local_address: None,
+            remote_address: None,
+            local_port: None,
+            remote_port: None,
+            protocol: None,
+        }
+    }
+
+    /// A rule letting the matching packets through.
+    pub fn allow() -> Self {
+        Self::new(Action::Allow)
+    }
+
+    /// A rule dropping the matching packets.
+    pub fn deny() -> Self {
+        Self::new(Action::Deny)
+    }
+
+    /// Only match packets arriving from the network.
+    pub fn incoming(mut self) -> Self {
+        self.direction = Some(Direction::Incoming);
+        self
+    }
+
+    /// Only match packets leaving for the network.
+    pub fn outgoing(mut self) -> Self {
+        self.direction = Some(Direction::Outgoing);
+        self
+    }
+
+    /// Only match packets to or from this machine's `address`.
+    pub fn local_address(mut self, address: impl Into<Ipv4Address>) -> Self {
+        self.local_address = Some(address.into());
+        self
+    }
+
+    /// Only match packets exchanged with the machine at `address`.
+    pub fn remote_address(mut self, address: impl Into<Ipv4Address>) -> Self {
+        self.remote_address = Some(address.into());
+        self
+    }
+
+    /// Only match UDP or TCP packets using `port` on this machine.
+    pub fn local_port(mut self, port: u16) -> Self {
+        self.local_port = Some(port);
+        self
+    }
+
+    /// Only match UDP or TCP packets using `port` on the other machine.
+    pub fn remote_port(mut self, port: u16) -> Self {
+        self.remote_port = Some(port);
+        self
+    }
+
+    /// Only match packets carrying `protocol`, such as
+    /// [`Udp::ID`](crate::protocols::udp::Udp::ID). Protocols are compared by
+    /// their IP protocol number, so only IDs that fit in a byte match anything.
+    pub fn protocol(mut self, protocol: ProtocolId) -> Self {
+        self.protocol = Some(protocol.base());
+        self
+    }
+
+    pub(super) fn action(&self) -> Action {
+        self.action
+    }
+
+    pub(super) fn matches(&self, packet: &Packet) -> bool {
+        fn fits<T: PartialEq>(criterion: Option<T>, value: Option<T>) -> bool {
+            criterion.is_none() || criterion == value
+        }
+        fits(self.direction, Some(packet.direction))
+            && fits(self.local_address, Some(packet.local_address))
+            && fits(self.remote_address, Some(packet.remote_address))
+            && fits(self.local_port, packet.local_port)
+            && fits(self.remote_port, packet.remote_port)
+            && fits(self.protocol, Some(ProtocolId::new(packet.protocol as u64)))
+    }
+}
+
+/// The parts of an IPv4 packet that rules look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Packet {
+    pub direction: Direction,
+    pub local_address: Ipv4Address,
+    pub remote_address: Ipv4Address,
+    pub local_port: Option<u16>,
+    pub remote_port: Option<u16>,
+    pub protocol: u8,
+}
+
+impl Packet {
+    /// Reads the IPv4 header and, for UDP and TCP, the ports of a packet.
+    /// Returns `None` if the message is too short to be an IPv4 packet.
+    pub fn from_message(message: &Message, direction: Direction) -> Option<Self> {
+        const TCP: u8 = 6;
+        const UDP: u8 = 17;
+        let bytes: Vec<u8> = message.iter().take(64).collect();
+        let header_length = (*bytes.first()? & 0b1111) as usize * 4;
+        let protocol = *bytes.get(9)?;
+        let address = |at: usize| -> Option<Ipv4Address> {
+            let octets: [u8; 4] = bytes.get(at..at + 4)?.try_into().ok()?;
+            Some(octets.into())
+        };
+        let source = address(12)?;
+        let destination = address(16)?;
+        let port = |at: usize| -> Option<u16> {
+            matches!(protocol, TCP | UDP)
+                .then(|| Some(u16::from_be_bytes([*bytes.get(at)?, *bytes.get(at + 1)?])))?
+        };
+        let source_port = port(header_length);
+        let destination_port = port(header_length + 2);
+        Some(match direction {
+            Direction::Incoming => Self {
+                direction,
+                local_address: destination,
+                remote_address: source,
+                local_port: destination_port,
+                remote_port: source_port,
+                protocol,
+            },
+            Direction::Outgoing => Self {
+                direction,
+                local_address: source,
+                remote_address: destination,
+                local_port: source_port,
+                remote_port: destination_port,
+                protocol,
+            },
+        })
+    }
+}
@@ -0,0 +1,50 @@
+use super::{firewall_rules::Direction, SharedFilter};
+use crate::core::{
+    message::Message, ControlFlow, ProtocolContext, ProtocolId, Session, SharedSession, SimError,
+};
+
+/// Passes the packets of one upstream protocol on one network through the
+/// firewall's rules.
+pub(super) struct FirewallSession {
+    upstream: ProtocolId,
+    downstream: SharedSession,
+    filter: SharedFilter,
+}
+
+impl FirewallSession {
+    pub fn new(upstream: ProtocolId, downstream: SharedSession, filter: SharedFilter) -> Self {
+        Self {
+            upstream,
+            downstream,
+            filter,
+        }
+    }
+}
+
+impl Session for FirewallSession {
+    fn send(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        if self
+            .filter
+            .lock()
+            .unwrap()
+            .allows(&message, Direction::Outgoing)
+        {
+            self.downstream.send(message, context)?;
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        // Incoming messages were already checked by the firewall's demux
+        context
+            .protocol(self.upstream)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .demux(message, context)
+    }
+
+    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        Ok(ControlFlow::Continue)
+    }
+}
@@ -0,0 +1,210 @@
+//! A packet filter to put between the [`Tap`] and [`Ipv4`].
+
+use crate::{
+    core::{
+        message::Message, Control, ControlFlow, Protocol, ProtocolContext, ProtocolId,
+        SharedSession, SimError,
+    },
+    protocols::{
+        ipv4::Ipv4,
+        tap::{Intercept, NetworkIndex, Tap},
+    },
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex},
+};
+
+mod firewall_rules;
+use firewall_rules::Packet;
+pub use firewall_rules::{Action, Direction, Rule};
+
+mod firewall_session;
+use firewall_session::FirewallSession;
+
+/// A per-machine firewall filtering IPv4 packets by a list of [`Rule`]s.
+///
+/// Each packet going through the firewall, in either direction, gets the
+/// [`Action`] of the first rule matching it, or the default action if none
+/// does. The firewall counts the packets it drops.
+///
+/// The firewall sits between the [`Tap`] and [`Ipv4`]. It intercepts incoming
+/// IPv4 packets at the tap once it is first awoken, and sees outgoing ones if
+/// the machine's IPv4 sends through it:
+///
+/// ```
+/// # use elvis::protocols::{firewall::{Firewall, Rule}, ipv4::Ipv4};
+/// let firewall = Firewall::new().with_rule(Rule::deny().incoming().local_port(23));
+/// let ipv4 = Ipv4::new().with_downstream(Firewall::ID);
+/// ```
+pub struct Firewall {
+    filter: SharedFilter,
+    sessions: HashMap<(ProtocolId, NetworkIndex), SharedSession>,
+    intercepting: bool,
+}
+
+impl Firewall {
+    /// A unique identifier for the protocol.
+    pub const ID: ProtocolId = ProtocolId::from_string("Firewall");
+
+    /// Creates a firewall without rules, which allows every packet.
+    pub fn new() -> Self {
+        Self {
+            filter: Default::default(),
+            sessions: Default::default(),
+            intercepting: false,
+        }
+    }
+
+    /// Creates a new shared handle to a firewall without rules.
+    pub fn new_shared() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// Adds a rule, to be checked after the rules added before it.
+    pub fn with_rule(self, rule: Rule) -> Self {
+        self.filter.lock().unwrap().rules.push(rule);
+        self
+    }
+
+    /// Sets the action for packets no rule matches, [`Action::Allow`] by
+    /// default.
+    pub fn with_default(self, action: Action) -> Self {
+        self.filter.lock().unwrap().default = action;
+        self
+    }
+
+    /// The number of packets dropped so far.
+    pub fn dropped(&self) -> DropCounts {
+        self.filter.lock().unwrap().dropped
+    }
+}
+
+impl Default for Firewall {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How many packets a [`Firewall`] dropped in each direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DropCounts {
+    pub incoming: u64,
+    pub outgoing: u64,
+}
+
+/// The rules and counters, shared by the firewall and its sessions.
+type SharedFilter = Arc<Mutex<Filter>>;
+
+#[derive(Debug, Default)]
+struct Filter {
+    rules: Vec<Rule>,
+    default: Action,
+    dropped: DropCounts,
+}
+
+impl Filter {
+    /// Whether the rules let the message through, counting it if not.
+    /// Messages too short to be IPv4 packets are left for IPv4 to reject.
+    fn allows(&mut self, message: &Message, direction: Direction) -> bool {
+        let Some(packet) = Packet::from_message(message, direction) else {
+            return true;
+        };
+        let action = self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(&packet))
+            .map_or(self.default, Rule::action);
+        if action == Action::Deny {
+            match direction {
+                Direction::Incoming => self.dropped.incoming += 1,
+                Direction::Outgoing => self.dropped.outgoing += 1,
+            }
+        }
+        action == Action::Allow
+    }
+}
+
+impl Protocol for Firewall {
+    fn id(&self) -> ProtocolId {
+        Self::ID
+    }
+
+    fn open(
+        &mut self,
+        upstream: ProtocolId,
+        participants: Control,
+        context: &mut ProtocolContext,
+    ) -> Result<SharedSession, SimError> {
+        let network = NetworkIndex::try_from(&participants).unwrap_or_else(|_| 0.into());
+        match self.sessions.entry((upstream, network)) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                // The tap labels messages with the upstream protocol, so the
+                // firewall is invisible to other machines
+                let tap_session = context
+                    .protocol(Tap::ID)
+                    .expect("No such protocol")
+                    .lock()
+                    .unwrap()
+                    .open(upstream, participants, context)?;
+                let session = SharedSession::new(FirewallSession::new(
+                    upstream,
+                    tap_session,
+                    self.filter.clone(),
+                ));
+                entry.insert(session.clone());
+                Ok(session)
+            }
+        }
+    }
+
+    fn listen(
+        &mut self,
+        _upstream: ProtocolId,
+        _participants: Control,
+        _context: &mut ProtocolContext,
+    ) -> Result<(), SimError> {
+        // Incoming packets reach the firewall through its intercept already
+        Ok(())
+    }
+
+    fn demux(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        if !self
+            .filter
+            .lock()
+            .unwrap()
+            .allows(&message, Direction::Incoming)
+        {
+            return Ok(());
+        }
+        let network = NetworkIndex::try_from(&context.info).unwrap_or_else(|_| 0.into());
+        let mut session = self
+            .sessions
+            .entry((Ipv4::ID, network))
+            .or_insert_with(|| {
+                SharedSession::new(FirewallSession::new(
+                    Ipv4::ID,
+                    context.current_session().expect("No current session"),
+                    self.filter.clone(),
+                ))
+            })
+            .clone();
+        session.receive(message, context)
+    }
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        if !self.intercepting {
+            self.intercepting = true;
+            let mut participants = Control::new();
+            Intercept::set(&mut participants, Ipv4::ID.into_inner());
+            context
+                .protocol(Tap::ID)
+                .expect("No such protocol")
+                .lock()
+                .unwrap()
+                .listen(Self::ID, participants, context)?;
+        }
+        Ok(ControlFlow::Continue)
+    }
+}
@@ -33,6 +33,11 @@ impl Ipv4Address {
     pub fn to_bytes(self) -> [u8; 4] {
         self.into()
     }
+
+    /// Whether the address is in the loopback block `127.0.0.0/8`.
+    pub fn is_loopback(self) -> bool {
+        self.0[0] == 127
+    }
 }
 
 impl Display for Ipv4Address {
@@ -0,0 +1,70 @@
+use super::Ipv4Address;
+use crate::core::{message::Message, Round, SharedSession};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+/// Messages an [`Ipv4`](super::Ipv4) protocol sent to its own machine, shared
+/// between the protocol and its sessions.
+#[derive(Default)]
+pub(super) struct Loopback {
+    /// Addresses the protocol listens on besides the loopback block
+    addresses: HashSet<Ipv4Address>,
+    /// Messages waiting for the protocol to demux them, along with the session
+    /// they would have gone out through and the round they were sent in
+    queue: Vec<(Message, SharedSession, Round)>,
+}
+
+pub(super) type SharedLoopback = Arc<Mutex<Loopback>>;
+
+impl Loopback {
+    /// Counts `address` as belonging to this machine. Listening on
+    /// [`CURRENT_NETWORK`](Ipv4Address::CURRENT_NETWORK) stands for any
+    /// address rather than that one, so it is never counted.
+    pub fn add_address(&mut self, address: Ipv4Address) {
+        if address != Ipv4Address::CURRENT_NETWORK {
+            self.addresses.insert(address);
+        }
+    }
+
+    /// Whether a message for `address` stays on this machine.
+    pub fn is_local(&self, address: Ipv4Address) -> bool {
+        address.is_loopback() || self.addresses.contains(&address)
+    }
+
+    /// Queues a message sent in `round` for local delivery.
+    pub fn push(&mut self, message: Message, downstream: SharedSession, round: Round) {
+        self.queue.push((message, downstream, round));
+    }
+
+    /// Takes the messages sent before `round`, oldest first. Like messages
+    /// on a network, they arrive the round after they were sent, once every
+    /// protocol on the machine has had a chance to set up its bindings.
+    pub fn take(&mut self, round: Round) -> Vec<(Message, SharedSession)> {
+        let (ready, waiting) = std::mem::take(&mut self.queue)
+            .into_iter()
+            .partition(|&(_, _, sent)| sent < round);
+        self.queue = waiting;
+        ready
+            .into_iter()
+            .map(|(message, downstream, _)| (message, downstream))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listening_on_any_address_keeps_it_off_the_machine() {
+        let mut loopback = Loopback::default();
+        let address = Ipv4Address::new([10, 0, 0, 1]);
+        loopback.add_address(address);
+        loopback.add_address(Ipv4Address::CURRENT_NETWORK);
+        assert!(loopback.is_local(address));
+        assert!(loopback.is_local(Ipv4Address::LOCALHOST));
+        assert!(!loopback.is_local(Ipv4Address::CURRENT_NETWORK));
+    }
+}
@@ -16,8 +16,15 @@ from_impls!(RemoteAddress, Ipv4Address);
 from_impls!(RemoteAddress, [u8; 4]);
 from_impls!(RemoteAddress, u32);
 
+const TYPE_OF_SERVICE_KEY: u64 = make_key("IPv4 Type of Service");
+/// A [`ControlValue`] for the type of service byte to use on a session's
+/// messages. Its top three bits are the precedence, which decides how soon the
+/// message leaves the machine on networks that queue by priority.
+pub type ServiceType = ControlValue<TYPE_OF_SERVICE_KEY, u8>;
+from_impls!(ServiceType, u8);
+
 #[derive(Debug, ThisError)]
-pub(super) enum Ipv4Error {
+pub enum Ipv4Error {
     #[error("Could not find a listen binding for the local address: {0}")]
     MissingListenBinding(LocalAddress),
     #[error("Attempting to create a binding that already exists for local address {0}")]
@@ -42,6 +49,8 @@ pub(super) enum Ipv4Error {
     UsedReservedFlag,
     #[error("Expected 5 bytes for IPv4 header")]
     InvalidHeaderLength,
+    #[error("The total length {0} is shorter than the header")]
+    TotalLengthTooShort(u16),
     #[error(
         "The header checksum {expected:#06x} does not match the calculated checksum {actual:#06x}"
     )]
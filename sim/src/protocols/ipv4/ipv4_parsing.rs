@@ -1,5 +1,5 @@
 use super::{ipv4_misc::Ipv4Error, Ipv4Address};
-use crate::protocols::utility::Checksum;
+use crate::core::Checksum;
 
 // Note: There are many #[allow(dead_code)] flags in this file. None of this
 // stuff is public and not all of it is being used internally, but we want to
@@ -52,6 +52,9 @@ impl Ipv4Header {
         checksum.add_u8(version_and_ihl, type_of_service_byte);
 
         let total_length = u16::from_be_bytes([next()?, next()?]);
+        if total_length < BASE_OCTETS {
+            Err(Ipv4Error::TotalLengthTooShort(total_length))?
+        }
         checksum.add_u16(total_length);
 
         let identification = u16::from_be_bytes([next()?, next()?]);
@@ -160,44 +163,58 @@ impl Ipv4HeaderBuilder {
     }
 
     pub fn build(self) -> Result<Vec<u8>, Ipv4Error> {
-        let mut checksum = Checksum::new();
-
         let version_and_ihl = (4u8 << 4) | BASE_WORDS;
-        let type_of_service = self.type_of_service.as_u8();
-        checksum.add_u8(version_and_ihl, type_of_service);
-
         let total_length = self
             .payload_length
             .checked_add(BASE_OCTETS)
             .ok_or(Ipv4Error::OverlyLongPayload)?;
-        checksum.add_u16(total_length);
-
-        checksum.add_u16(self.identification);
-
         if self.fragment_offset > FRAGMENT_OFFSET_MASK {
             Err(Ipv4Error::OverlyLongFragmentOffset)?
         }
         let flags_and_fragment_offset =
             ((self.flags.as_u8() as u16) << 13) | (self.fragment_offset & FRAGMENT_OFFSET_MASK);
-        checksum.add_u16(flags_and_fragment_offset);
 
-        checksum.add_u8(self.time_to_live, self.protocol as u8);
-        checksum.add_u32(self.source.into());
-        checksum.add_u32(self.destination.into());
-
-        let mut out = vec![version_and_ihl, type_of_service];
+        let mut out = vec![version_and_ihl, self.type_of_service.as_u8()];
         out.extend_from_slice(&total_length.to_be_bytes());
         out.extend_from_slice(&self.identification.to_be_bytes());
         out.extend_from_slice(&flags_and_fragment_offset.to_be_bytes());
         out.push(self.time_to_live);
-        out.push(self.protocol as u8);
-        out.extend_from_slice(&checksum.as_u16().to_be_bytes());
+        out.push(self.protocol);
+        // The checksum is computed with its own field as zero
+        out.extend_from_slice(&[0, 0]);
         out.extend_from_slice(&self.source.to_u32().to_be_bytes());
         out.extend_from_slice(&self.destination.to_u32().to_be_bytes());
+        let checksum = Checksum::of(&out);
+        out[10..12].copy_from_slice(&checksum.to_be_bytes());
         Ok(out)
     }
 }
 
+/// Parses `bytes` as an IPv4 header and, if that succeeds, checks that
+/// building the same header gives back the bytes it was parsed from. Panics if
+/// parsing panics or the round trip fails. Shared by the property tests and
+/// the fuzz targets.
+#[cfg(any(test, feature = "fuzzing"))]
+pub(crate) fn check_round_trip(bytes: &[u8]) {
+    let Ok(header) = Ipv4Header::from_bytes(bytes.iter().cloned()) else {
+        return;
+    };
+    let mut builder = Ipv4HeaderBuilder::new(
+        header.source,
+        header.destination,
+        ProtocolNumber::Udp,
+        header.total_length - BASE_OCTETS,
+    )
+    .type_of_service(header.type_of_service)
+    .identification(header.identification)
+    .fragment_offset(header.fragment_offset)
+    .flags(header.flags);
+    builder.time_to_live = header.time_to_live;
+    builder.protocol = header.protocol;
+    let built = builder.build().expect("A parsed header should build");
+    assert_eq!(built, bytes[..BASE_OCTETS as usize]);
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(u8)]
 pub(super) enum ProtocolNumber {
@@ -286,7 +303,6 @@ impl TypeOfService {
         )
     }
 
-    #[allow(dead_code)]
     pub fn precedence(&self) -> Precedence {
         (self.0 >> 5).try_into().unwrap()
     }
@@ -422,6 +438,7 @@ impl TryFrom<u8> for Precedence {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::{collection::vec, prelude::*};
 
     fn make_header() -> (etherparse::Ipv4Header, Vec<u8>, u16) {
         let payload = "Hello, world!";
@@ -492,4 +509,99 @@ mod tests {
         assert_eq!(actual, expected);
         Ok(())
     }
+
+    fn type_of_service() -> impl Strategy<Value = TypeOfService> {
+        (0u8..8, 0u8..2, 0u8..2, 0u8..2).prop_map(|(precedence, delay, throughput, reliability)| {
+            TypeOfService::new(
+                precedence.try_into().unwrap(),
+                delay.try_into().unwrap(),
+                throughput.try_into().unwrap(),
+                reliability.try_into().unwrap(),
+            )
+        })
+    }
+
+    fn built_header() -> impl Strategy<Value = Vec<u8>> {
+        (
+            any::<[u8; 4]>(),
+            any::<[u8; 4]>(),
+            0..=u16::MAX - BASE_OCTETS,
+            any::<u16>(),
+            0..=FRAGMENT_OFFSET_MASK,
+            any::<bool>(),
+            any::<bool>(),
+            type_of_service(),
+        )
+            .prop_map(
+                |(source, destination, payload_length, identification, offset, may, last, tos)| {
+                    Ipv4HeaderBuilder::new(
+                        Ipv4Address::new(source),
+                        Ipv4Address::new(destination),
+                        ProtocolNumber::Udp,
+                        payload_length,
+                    )
+                    .identification(identification)
+                    .fragment_offset(offset)
+                    .flags(ControlFlags::new(may, last))
+                    .type_of_service(tos)
+                    .build()
+                    .unwrap()
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn parses_what_it_builds(
+            source: [u8; 4],
+            destination: [u8; 4],
+            payload_length in 0..=u16::MAX - BASE_OCTETS,
+            identification: u16,
+            fragment_offset in 0..=FRAGMENT_OFFSET_MASK,
+            may_fragment: bool,
+            is_last_fragment: bool,
+            type_of_service in type_of_service(),
+        ) {
+            let bytes = Ipv4HeaderBuilder::new(
+                Ipv4Address::new(source),
+                Ipv4Address::new(destination),
+                ProtocolNumber::Udp,
+                payload_length,
+            )
+            .identification(identification)
+            .fragment_offset(fragment_offset)
+            .flags(ControlFlags::new(may_fragment, is_last_fragment))
+            .type_of_service(type_of_service)
+            .build()
+            .unwrap();
+            let header = Ipv4Header::from_bytes(bytes.iter().cloned()).unwrap();
+            prop_assert_eq!(header.source, Ipv4Address::new(source));
+            prop_assert_eq!(header.destination, Ipv4Address::new(destination));
+            prop_assert_eq!(header.total_length, payload_length + BASE_OCTETS);
+            prop_assert_eq!(header.identification, identification);
+            prop_assert_eq!(header.fragment_offset, fragment_offset);
+            prop_assert_eq!(header.flags.may_fragment(), may_fragment);
+            prop_assert_eq!(header.flags.is_last_fragment(), is_last_fragment);
+            prop_assert_eq!(header.type_of_service, type_of_service);
+            prop_assert_eq!(header.protocol, ProtocolNumber::Udp as u8);
+            check_round_trip(&bytes);
+        }
+
+        #[test]
+        fn rejects_any_changed_byte(bytes in built_header(), index in 0..20usize, change in 1..=u8::MAX) {
+            let mut bytes = bytes;
+            bytes[index] ^= change;
+            prop_assert!(Ipv4Header::from_bytes(bytes.iter().cloned()).is_err());
+        }
+
+        #[test]
+        fn survives_arbitrary_bytes(bytes in vec(any::<u8>(), 0..48)) {
+            check_round_trip(&bytes);
+        }
+
+        #[test]
+        fn survives_arbitrary_bytes_after_the_version(bytes in vec(any::<u8>(), 0..48)) {
+            check_round_trip(&[&[0x45][..], &bytes].concat());
+        }
+    }
 }
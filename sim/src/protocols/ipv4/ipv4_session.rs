@@ -1,17 +1,22 @@
 use super::{
-    ipv4_parsing::{Ipv4HeaderBuilder, ProtocolNumber},
+    ipv4_loopback::SharedLoopback,
+    ipv4_parsing::{Ipv4HeaderBuilder, ProtocolNumber, TypeOfService},
     LocalAddress, RemoteAddress,
 };
 use crate::{
-    core::{message::Message, ControlFlow, ProtocolContext, ProtocolId, Session, SharedSession},
-    protocols::udp::Udp,
+    core::{
+        message::Message, ControlFlow, ProtocolContext, ProtocolId, Session, SharedSession,
+        SimError,
+    },
+    protocols::{tap::Precedence, udp::Udp},
 };
-use std::error::Error;
 
 pub struct Ipv4Session {
     upstream: ProtocolId,
     downstream: SharedSession,
     identifier: SessionId,
+    type_of_service: TypeOfService,
+    loopback: Option<SharedLoopback>,
 }
 
 impl Ipv4Session {
@@ -24,18 +29,29 @@ impl Ipv4Session {
             upstream,
             downstream,
             identifier,
+            type_of_service: Default::default(),
+            loopback: None,
         }
     }
+
+    /// Sets the type of service to put in the headers of outgoing messages.
+    pub(super) fn with_type_of_service(mut self, type_of_service: TypeOfService) -> Self {
+        self.type_of_service = type_of_service;
+        self
+    }
+
+    /// Queues messages for addresses on this machine on `loopback` instead of
+    /// sending them downstream.
+    pub(super) fn with_loopback(mut self, loopback: Option<SharedLoopback>) -> Self {
+        self.loopback = loopback;
+        self
+    }
 }
 
 impl Session for Ipv4Session {
-    fn send(
-        &mut self,
-        message: Message,
-        context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    fn send(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
         let length = message.iter().count();
-        let protocol_number = match self.upstream {
+        let protocol_number = match self.upstream.base() {
             Udp::ID => ProtocolNumber::Udp,
             _ => panic!("Unknown upstream protocol"),
         };
@@ -45,28 +61,43 @@ impl Session for Ipv4Session {
             protocol_number,
             length as u16,
         )
+        .type_of_service(self.type_of_service)
         .build()?;
         let message = message.with_header(header);
+        if let Some(loopback) = &self.loopback {
+            let mut loopback = loopback.lock().unwrap();
+            if loopback.is_local(self.identifier.remote.into()) {
+                loopback.push(message, self.downstream.clone(), context.round());
+                return Ok(());
+            }
+        }
+        Precedence::set(&mut context.info, self.type_of_service.precedence() as u8);
         self.downstream.send(message, context)?;
         Ok(())
     }
 
-    fn receive(
-        &mut self,
-        message: Message,
-        context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    fn receive(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
         context
             .protocol(self.upstream)
             .expect("No such protocol")
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .demux(message, context)?;
         Ok(())
     }
 
-    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
         Ok(ControlFlow::Continue)
     }
+
+    fn refused(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        context
+            .protocol(self.upstream)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .refused(message, context)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -4,37 +4,63 @@
 use crate::{
     core::{
         message::Message, Control, ControlFlow, Protocol, ProtocolContext, ProtocolId,
-        SharedSession,
+        SharedSession, SimError,
     },
     protocols::tap::Tap,
 };
 use std::{
-    cell::RefCell,
     collections::{hash_map::Entry, HashMap},
-    error::Error,
-    rc::Rc,
+    sync::{Arc, Mutex},
 };
 
 mod ipv4_parsing;
+#[cfg(feature = "fuzzing")]
+pub(crate) use ipv4_parsing::check_round_trip;
 use ipv4_parsing::Ipv4Header;
 
 mod ipv4_address;
 pub use ipv4_address::Ipv4Address;
 
 mod ipv4_misc;
-use ipv4_misc::Ipv4Error;
-pub use ipv4_misc::{LocalAddress, RemoteAddress};
+pub use ipv4_misc::{Ipv4Error, LocalAddress, RemoteAddress, ServiceType};
 
 mod ipv4_session;
 use ipv4_session::{Ipv4Session, SessionId};
 
+mod ipv4_loopback;
+use ipv4_loopback::SharedLoopback;
+
 use super::tap::NetworkIndex;
 
 /// An implementation of the Internet Protocol.
-#[derive(Default, Clone)]
+///
+/// Listening without a local address, or on
+/// [`CURRENT_NETWORK`](Ipv4Address::CURRENT_NETWORK), accepts messages for any
+/// local address that has no binding of its own.
+///
+/// Sessions send through the [`Tap`] unless the protocol is created
+/// [`with_downstream`](Ipv4::with_downstream) set to a protocol in between,
+/// such as a [`Firewall`](super::firewall::Firewall).
+///
+/// A protocol created [`with_loopback`](Ipv4::with_loopback) delivers
+/// messages for its own machine without sending them onto a network.
+#[derive(Clone)]
 pub struct Ipv4 {
     listen_bindings: HashMap<LocalAddress, ProtocolId>,
     sessions: HashMap<SessionId, SharedSession>,
+    downstream: ProtocolId,
+    loopback: Option<SharedLoopback>,
+}
+
+impl Default for Ipv4 {
+    fn default() -> Self {
+        Self {
+            listen_bindings: Default::default(),
+            sessions: Default::default(),
+            downstream: Tap::ID,
+            loopback: None,
+        }
+    }
 }
 
 impl Ipv4 {
@@ -47,8 +73,27 @@ impl Ipv4 {
     }
 
     /// Creates a new shared handle to an instance of the protocol.
-    pub fn new_shared() -> Rc<RefCell<Self>> {
-        Rc::new(RefCell::new(Self::new()))
+    pub fn new_shared() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// Sends through the protocol `downstream` instead of the tap. The
+    /// downstream protocol must pass messages on to the tap.
+    pub fn with_downstream(mut self, downstream: ProtocolId) -> Self {
+        self.downstream = downstream;
+        self
+    }
+
+    /// Delivers messages for addresses on this machine, meaning the loopback
+    /// block `127.0.0.0/8` and any address the protocol listens on, straight
+    /// to the local demux instead of sending them onto a network. They arrive
+    /// when the protocol is awoken in the following round.
+    ///
+    /// This is off by default because the applications that come with Elvis
+    /// send to [`LOCALHOST`](Ipv4Address::LOCALHOST) to reach other machines.
+    pub fn with_loopback(mut self) -> Self {
+        self.loopback = Some(Default::default());
+        self
     }
 }
 
@@ -62,22 +107,29 @@ impl Protocol for Ipv4 {
         upstream: ProtocolId,
         mut participants: Control,
         context: &mut ProtocolContext,
-    ) -> Result<SharedSession, Box<dyn Error>> {
+    ) -> Result<SharedSession, SimError> {
         let local = LocalAddress::try_from(&participants).unwrap();
         let remote = RemoteAddress::try_from(&participants).unwrap();
+        let type_of_service = ServiceType::try_from(&participants).map_or(0, u8::from);
         let key = SessionId { local, remote };
         match self.sessions.entry(key) {
             Entry::Occupied(_) => Err(Ipv4Error::SessionExists(key.local, key.remote))?,
             Entry::Vacant(entry) => {
                 // TODO(hardint): Actually pick the right network index
                 NetworkIndex::set(&mut participants, 0);
-                let tap_session = context
-                    .protocol(Tap::ID)
+                let downstream = context
+                    .protocol(self.downstream)
                     .expect("No such protocol")
-                    .borrow_mut()
+                    .lock()
+                    .unwrap()
                     .open(Self::ID, participants, context)?;
-                let session = SharedSession::new(Ipv4Session::new(tap_session, upstream, key));
+                let session = SharedSession::new(
+                    Ipv4Session::new(downstream, upstream, key)
+                        .with_type_of_service(type_of_service.into())
+                        .with_loopback(self.loopback.clone()),
+                );
                 entry.insert(session.clone());
+                context.session_opened(Self::ID, upstream);
                 Ok(session)
             }
         }
@@ -88,28 +140,31 @@ impl Protocol for Ipv4 {
         upstream: ProtocolId,
         participants: Control,
         context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
-        let local = LocalAddress::try_from(&participants).unwrap();
+    ) -> Result<(), SimError> {
+        let local = LocalAddress::try_from(&participants)
+            .unwrap_or_else(|_| Ipv4Address::CURRENT_NETWORK.into());
         match self.listen_bindings.entry(local) {
+            // Several upstream bindings, such as UDP ports, share an address
+            Entry::Occupied(entry) if *entry.get() == upstream => {}
             Entry::Occupied(_) => Err(Ipv4Error::BindingExists(local))?,
             Entry::Vacant(entry) => {
                 entry.insert(upstream);
             }
         }
+        if let Some(loopback) = &self.loopback {
+            loopback.lock().unwrap().add_address(local.into());
+        }
 
         // Essentially a no-op but good for completeness and as an example
         context
-            .protocol(Tap::ID)
+            .protocol(self.downstream)
             .expect("No such protocol")
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .listen(Self::ID, participants, context)
     }
 
-    fn demux(
-        &mut self,
-        message: Message,
-        context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    fn demux(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
         let header = Ipv4Header::from_bytes(message.iter())?;
         let remote = RemoteAddress::from(header.source);
         let local = LocalAddress::from(header.destination);
@@ -119,14 +174,21 @@ impl Protocol for Ipv4 {
         let message = message.slice(header.ihl as usize * 4..);
         let mut session = match self.sessions.entry(identifier) {
             Entry::Occupied(entry) => entry.get().clone(),
-            Entry::Vacant(entry) => match self.listen_bindings.get(&local) {
+            Entry::Vacant(entry) => match self.listen_bindings.get(&local).or_else(|| {
+                self.listen_bindings
+                    .get(&Ipv4Address::CURRENT_NETWORK.into())
+            }) {
                 Some(&binding) => {
-                    let session = SharedSession::new(Ipv4Session::new(
-                        context.current_session().expect("No current session"),
-                        binding,
-                        identifier,
-                    ));
+                    let session = SharedSession::new(
+                        Ipv4Session::new(
+                            context.current_session().expect("No current session"),
+                            binding,
+                            identifier,
+                        )
+                        .with_loopback(self.loopback.clone()),
+                    );
                     entry.insert(session.clone());
+                    context.session_opened(Self::ID, binding);
                     session
                 }
                 None => Err(Ipv4Error::MissingListenBinding(local))?,
@@ -136,7 +198,39 @@ impl Protocol for Ipv4 {
         Ok(())
     }
 
-    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
-        Ok(ControlFlow::Continue)
+    fn refused(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        // The message is one we sent, so its source is our local address
+        let header = Ipv4Header::from_bytes(message.iter())?;
+        let local = LocalAddress::from(header.source);
+        let remote = RemoteAddress::from(header.destination);
+        local.apply(&mut context.info);
+        remote.apply(&mut context.info);
+        let message = message.slice(header.ihl as usize * 4..);
+        match self.sessions.get(&SessionId { local, remote }) {
+            Some(session) => session.clone().refused(message, context),
+            // The session is gone, so there is nobody left to tell
+            None => Ok(()),
+        }
+    }
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        let Some(loopback) = &self.loopback else {
+            return Ok(ControlFlow::Continue);
+        };
+        let queued = loopback.lock().unwrap().take(context.round());
+        // Every queued message is delivered even if an earlier one fails, and
+        // the first failure is reported
+        let mut result = Ok(());
+        for (message, downstream) in queued {
+            // Sessions opened for these messages reply through the session
+            // the message would have gone out on
+            context.push_session(downstream);
+            let delivered = self.demux(message, context);
+            context.pop_session();
+            if result.is_ok() {
+                result = delivered;
+            }
+        }
+        result.map(|_| ControlFlow::Continue)
     }
 }
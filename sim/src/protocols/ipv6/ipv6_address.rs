@@ -0,0 +1,113 @@
+use crate::core::control::{Primitive, PrimitiveError};
+use std::{
+    fmt::{self, Display},
+    net::Ipv6Addr,
+};
+
+/// Represents an address used by the [`Ipv6`](super::Ipv6) protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ipv6Address([u8; 16]);
+
+impl Ipv6Address {
+    /// The unspecified address `::`.
+    pub const UNSPECIFIED: Self = Self([0u8; 16]);
+
+    /// The loopback address `::1`.
+    pub const LOCALHOST: Self = Self([0u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+    /// Creates a new address. The number can be provided as a `[u8; 16]`, a
+    /// `[u16; 8]` of segments, or a `u128`.
+    pub fn new(address: impl Into<Self>) -> Self {
+        address.into()
+    }
+
+    /// Gets the address as a `u128`.
+    pub fn to_u128(self) -> u128 {
+        self.into()
+    }
+
+    /// Gets the address as a `[u8; 16]`.
+    pub fn to_bytes(self) -> [u8; 16] {
+        self.into()
+    }
+
+    /// Gets the address as its eight 16-bit segments.
+    pub fn to_segments(self) -> [u16; 8] {
+        self.into()
+    }
+}
+
+impl Display for Ipv6Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The standard library already knows how to shorten runs of zeros
+        Display::fmt(&Ipv6Addr::from(self.0), f)
+    }
+}
+
+impl From<u128> for Ipv6Address {
+    fn from(n: u128) -> Self {
+        Self::from(n.to_be_bytes())
+    }
+}
+
+impl From<[u8; 16]> for Ipv6Address {
+    fn from(n: [u8; 16]) -> Self {
+        Self(n)
+    }
+}
+
+impl From<[u16; 8]> for Ipv6Address {
+    fn from(segments: [u16; 8]) -> Self {
+        Self(Ipv6Addr::from(segments).octets())
+    }
+}
+
+impl From<Ipv6Address> for u128 {
+    fn from(address: Ipv6Address) -> Self {
+        u128::from_be_bytes(address.0)
+    }
+}
+
+impl From<Ipv6Address> for [u8; 16] {
+    fn from(address: Ipv6Address) -> Self {
+        address.0
+    }
+}
+
+impl From<Ipv6Address> for [u16; 8] {
+    fn from(address: Ipv6Address) -> Self {
+        Ipv6Addr::from(address.0).segments()
+    }
+}
+
+impl TryFrom<Primitive> for Ipv6Address {
+    type Error = PrimitiveError;
+
+    fn try_from(value: Primitive) -> Result<Self, Self::Error> {
+        Ok(value.ok_u128()?.into())
+    }
+}
+
+impl From<Ipv6Address> for Primitive {
+    fn from(address: Ipv6Address) -> Self {
+        Primitive::U128(address.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_between_representations() {
+        let address = Ipv6Address::new([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(address.to_string(), "2001:db8::1");
+        assert_eq!(address.to_u128(), 0x2001_0db8_0000_0000_0000_0000_0000_0001);
+        assert_eq!(Ipv6Address::new(address.to_bytes()), address);
+        assert_eq!(
+            Ipv6Address::try_from(Primitive::from(address)).unwrap(),
+            address
+        );
+        assert_eq!(Ipv6Address::LOCALHOST.to_string(), "::1");
+    }
+}
@@ -0,0 +1,48 @@
+use super::ipv6_address::Ipv6Address;
+use crate::core::{
+    control::{from_impls, make_key, ControlValue},
+    ProtocolId,
+};
+use thiserror::Error as ThisError;
+
+const LOCAL_ADDRESS_KEY: u64 = make_key("IPv6 Local Address");
+/// A [`ControlValue`] for the local IPv6 address.
+pub type LocalAddress = ControlValue<LOCAL_ADDRESS_KEY, Ipv6Address>;
+from_impls!(LocalAddress, Ipv6Address);
+from_impls!(LocalAddress, [u8; 16]);
+from_impls!(LocalAddress, [u16; 8]);
+from_impls!(LocalAddress, u128);
+
+const REMOTE_ADDRESS_KEY: u64 = make_key("IPv6 Remote Address");
+/// A [`ControlValue`] for the remote IPv6 address.
+pub type RemoteAddress = ControlValue<REMOTE_ADDRESS_KEY, Ipv6Address>;
+from_impls!(RemoteAddress, Ipv6Address);
+from_impls!(RemoteAddress, [u8; 16]);
+from_impls!(RemoteAddress, [u16; 8]);
+from_impls!(RemoteAddress, u128);
+
+const TRAFFIC_CLASS_KEY: u64 = make_key("IPv6 Traffic Class");
+/// A [`ControlValue`] for the traffic class byte to use on a session's
+/// messages. As with the IPv4 type of service, its top three bits are the
+/// precedence, which decides how soon the message leaves the machine on
+/// networks that queue by priority.
+pub type TrafficClass = ControlValue<TRAFFIC_CLASS_KEY, u8>;
+from_impls!(TrafficClass, u8);
+
+#[derive(Debug, ThisError)]
+pub enum Ipv6Error {
+    #[error("Could not find a listen binding for the local address: {0}")]
+    MissingListenBinding(LocalAddress),
+    #[error("Attempting to create a binding that already exists for local address {0}")]
+    BindingExists(LocalAddress),
+    #[error("Attempting to create a session that already exists for {0} -> {1}")]
+    SessionExists(LocalAddress, RemoteAddress),
+    #[error("The IPv6 header is incomplete")]
+    HeaderTooShort,
+    #[error("Expected version 6 in IPv6 header")]
+    IncorrectIpv6Version,
+    #[error("The payload is longer than is allowed")]
+    OverlyLongPayload,
+    #[error("The upstream protocol {0:?} has no IPv6 next header number")]
+    NoNextHeader(ProtocolId),
+}
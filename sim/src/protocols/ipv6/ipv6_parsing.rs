@@ -0,0 +1,179 @@
+use super::{ipv6_misc::Ipv6Error, Ipv6Address};
+
+/// The size of the fixed IPv6 header in octets.
+pub(super) const HEADER_OCTETS: usize = 40;
+const FLOW_LABEL_MASK: u32 = 0xf_ffff;
+
+/// An IPv6 header, as described in RFC8200 s3. Extension headers are not
+/// supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) struct Ipv6Header {
+    pub traffic_class: u8,
+    pub flow_label: u32,
+    pub payload_length: u16,
+    pub next_header: u8,
+    pub hop_limit: u8,
+    pub source: Ipv6Address,
+    pub destination: Ipv6Address,
+}
+
+impl Ipv6Header {
+    pub fn from_bytes(mut bytes: impl Iterator<Item = u8>) -> Result<Self, Ipv6Error> {
+        let mut next =
+            || -> Result<u8, Ipv6Error> { bytes.next().ok_or(Ipv6Error::HeaderTooShort) };
+
+        let first_word = u32::from_be_bytes([next()?, next()?, next()?, next()?]);
+        let version = first_word >> 28;
+        if version != 6 {
+            Err(Ipv6Error::IncorrectIpv6Version)?
+        }
+        let traffic_class = (first_word >> 20) as u8;
+        let flow_label = first_word & FLOW_LABEL_MASK;
+
+        let payload_length = u16::from_be_bytes([next()?, next()?]);
+        let next_header = next()?;
+        let hop_limit = next()?;
+
+        let mut address = || -> Result<Ipv6Address, Ipv6Error> {
+            let mut octets = [0u8; 16];
+            for octet in octets.iter_mut() {
+                *octet = next()?;
+            }
+            Ok(octets.into())
+        };
+        let source = address()?;
+        let destination = address()?;
+
+        Ok(Self {
+            traffic_class,
+            flow_label,
+            payload_length,
+            next_header,
+            hop_limit,
+            source,
+            destination,
+        })
+    }
+}
+
+/// Builds the serialized form of an [`Ipv6Header`]. Messages are not
+/// labelled as part of a flow, so the flow label is always zero.
+pub(super) struct Ipv6HeaderBuilder {
+    traffic_class: u8,
+    payload_length: usize,
+    next_header: u8,
+    hop_limit: u8,
+    source: Ipv6Address,
+    destination: Ipv6Address,
+}
+
+impl Ipv6HeaderBuilder {
+    pub fn new(
+        source: Ipv6Address,
+        destination: Ipv6Address,
+        next_header: u8,
+        payload_length: usize,
+    ) -> Self {
+        Self {
+            traffic_class: 0,
+            payload_length,
+            next_header,
+            hop_limit: 30,
+            source,
+            destination,
+        }
+    }
+
+    pub fn traffic_class(mut self, traffic_class: u8) -> Self {
+        self.traffic_class = traffic_class;
+        self
+    }
+
+    pub fn build(self) -> Result<Vec<u8>, Ipv6Error> {
+        let payload_length =
+            u16::try_from(self.payload_length).map_err(|_| Ipv6Error::OverlyLongPayload)?;
+        let first_word = (6u32 << 28) | ((self.traffic_class as u32) << 20);
+
+        let mut out = Vec::with_capacity(HEADER_OCTETS);
+        out.extend_from_slice(&first_word.to_be_bytes());
+        out.extend_from_slice(&payload_length.to_be_bytes());
+        out.push(self.next_header);
+        out.push(self.hop_limit);
+        out.extend_from_slice(&self.source.to_bytes());
+        out.extend_from_slice(&self.destination.to_bytes());
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    const DESTINATION: [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+    fn make_header() -> (etherparse::Ipv6Header, Vec<u8>) {
+        let header = etherparse::Ipv6Header {
+            traffic_class: 0xb8,
+            flow_label: 0x12345,
+            payload_length: "Hello, world!".len() as u16,
+            next_header: etherparse::IpNumber::Udp as u8,
+            hop_limit: 30,
+            source: SOURCE,
+            destination: DESTINATION,
+        };
+        let mut serial_header = vec![];
+        header.write(&mut serial_header).unwrap();
+        (header, serial_header)
+    }
+
+    #[test]
+    fn parses_basic_header() -> anyhow::Result<()> {
+        let (valid_header, serial_header) = make_header();
+        let parsed = Ipv6Header::from_bytes(serial_header.iter().cloned())?;
+        assert_eq!(parsed.traffic_class, valid_header.traffic_class);
+        assert_eq!(parsed.flow_label, valid_header.flow_label);
+        assert_eq!(parsed.payload_length, valid_header.payload_length);
+        assert_eq!(parsed.next_header, valid_header.next_header);
+        assert_eq!(parsed.hop_limit, valid_header.hop_limit);
+        assert_eq!(parsed.source.to_bytes(), valid_header.source);
+        assert_eq!(parsed.destination.to_bytes(), valid_header.destination);
+        Ok(())
+    }
+
+    #[test]
+    fn generates_basic_header() -> anyhow::Result<()> {
+        let (mut valid_header, _) = make_header();
+        valid_header.flow_label = 0;
+        let mut expected = vec![];
+        valid_header.write(&mut expected)?;
+        let actual = Ipv6HeaderBuilder::new(
+            SOURCE.into(),
+            DESTINATION.into(),
+            valid_header.next_header,
+            valid_header.payload_length as usize,
+        )
+        .traffic_class(valid_header.traffic_class)
+        .build()?;
+        assert_eq!(actual, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let (_, mut serial_header) = make_header();
+        assert!(matches!(
+            Ipv6Header::from_bytes(serial_header[..39].iter().cloned()),
+            Err(Ipv6Error::HeaderTooShort)
+        ));
+        serial_header[0] = 0x45;
+        assert!(matches!(
+            Ipv6Header::from_bytes(serial_header.iter().cloned()),
+            Err(Ipv6Error::IncorrectIpv6Version)
+        ));
+        assert!(matches!(
+            Ipv6HeaderBuilder::new(SOURCE.into(), DESTINATION.into(), 17, 70_000).build(),
+            Err(Ipv6Error::OverlyLongPayload)
+        ));
+    }
+}
@@ -0,0 +1,77 @@
+use super::{ipv6_misc::Ipv6Error, ipv6_parsing::Ipv6HeaderBuilder, LocalAddress, RemoteAddress};
+use crate::{
+    core::{
+        message::Message, ControlFlow, ProtocolContext, ProtocolId, Session, SharedSession,
+        SimError,
+    },
+    protocols::tap::Precedence,
+};
+
+pub struct Ipv6Session {
+    upstream: ProtocolId,
+    downstream: SharedSession,
+    identifier: SessionId,
+    traffic_class: u8,
+}
+
+impl Ipv6Session {
+    pub(super) fn new(
+        downstream: SharedSession,
+        upstream: ProtocolId,
+        identifier: SessionId,
+    ) -> Self {
+        Self {
+            upstream,
+            downstream,
+            identifier,
+            traffic_class: 0,
+        }
+    }
+
+    /// Sets the traffic class to put in the headers of outgoing messages.
+    pub(super) fn with_traffic_class(mut self, traffic_class: u8) -> Self {
+        self.traffic_class = traffic_class;
+        self
+    }
+}
+
+impl Session for Ipv6Session {
+    fn send(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        let length = message.iter().count();
+        // Protocol IDs that fit in a byte are their IP protocol numbers
+        let next_header = u8::try_from(self.upstream.base().into_inner())
+            .map_err(|_| Ipv6Error::NoNextHeader(self.upstream))?;
+        let header = Ipv6HeaderBuilder::new(
+            self.identifier.local.into(),
+            self.identifier.remote.into(),
+            next_header,
+            length,
+        )
+        .traffic_class(self.traffic_class)
+        .build()?;
+        let message = message.with_header(header);
+        Precedence::set(&mut context.info, self.traffic_class >> 5);
+        self.downstream.send(message, context)?;
+        Ok(())
+    }
+
+    fn receive(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        context
+            .protocol(self.upstream)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .demux(message, context)?;
+        Ok(())
+    }
+
+    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) struct SessionId {
+    pub local: LocalAddress,
+    pub remote: RemoteAddress,
+}
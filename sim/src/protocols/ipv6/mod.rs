@@ -0,0 +1,158 @@
+//! An implementation of [Internet Protocol version
+//! 6](https://datatracker.ietf.org/doc/html/rfc8200).
+
+use crate::{
+    core::{
+        message::Message, Control, ControlFlow, Protocol, ProtocolContext, ProtocolId,
+        SharedSession, SimError,
+    },
+    protocols::tap::Tap,
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex},
+};
+
+mod ipv6_parsing;
+use ipv6_parsing::{Ipv6Header, HEADER_OCTETS};
+
+mod ipv6_address;
+pub use ipv6_address::Ipv6Address;
+
+mod ipv6_misc;
+pub use ipv6_misc::{Ipv6Error, LocalAddress, RemoteAddress, TrafficClass};
+
+mod ipv6_session;
+use ipv6_session::{Ipv6Session, SessionId};
+
+use super::tap::NetworkIndex;
+
+/// An implementation of the Internet Protocol, version 6.
+///
+/// IPv6 runs alongside [`Ipv4`](super::ipv4::Ipv4) on the same machine. The
+/// [`Tap`] header of an incoming message names the protocol that sent it, so
+/// messages from IPv6 sessions reach this protocol and those from IPv4
+/// sessions reach IPv4. Upstream protocols are identified in the next header
+/// field by their [`ProtocolId`], which must fit in a byte. UDP only runs
+/// over IPv4 for now.
+///
+/// Listening without a local address, or on
+/// [`UNSPECIFIED`](Ipv6Address::UNSPECIFIED), accepts messages for any local
+/// address that has no binding of its own.
+#[derive(Default, Clone)]
+pub struct Ipv6 {
+    listen_bindings: HashMap<LocalAddress, ProtocolId>,
+    sessions: HashMap<SessionId, SharedSession>,
+}
+
+impl Ipv6 {
+    /// A unique identifier for the protocol.
+    pub const ID: ProtocolId = ProtocolId::new(41);
+
+    /// Creates a new instance of the protocol.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a new shared handle to an instance of the protocol.
+    pub fn new_shared() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::new()))
+    }
+}
+
+impl Protocol for Ipv6 {
+    fn id(&self) -> ProtocolId {
+        Self::ID
+    }
+
+    fn open(
+        &mut self,
+        upstream: ProtocolId,
+        mut participants: Control,
+        context: &mut ProtocolContext,
+    ) -> Result<SharedSession, SimError> {
+        let local = LocalAddress::try_from(&participants).unwrap();
+        let remote = RemoteAddress::try_from(&participants).unwrap();
+        let traffic_class = TrafficClass::try_from(&participants).map_or(0, u8::from);
+        let key = SessionId { local, remote };
+        match self.sessions.entry(key) {
+            Entry::Occupied(_) => Err(Ipv6Error::SessionExists(key.local, key.remote))?,
+            Entry::Vacant(entry) => {
+                // TODO(hardint): Actually pick the right network index
+                NetworkIndex::set(&mut participants, 0);
+                let tap_session = context
+                    .protocol(Tap::ID)
+                    .expect("No such protocol")
+                    .lock()
+                    .unwrap()
+                    .open(Self::ID, participants, context)?;
+                let session = SharedSession::new(
+                    Ipv6Session::new(tap_session, upstream, key).with_traffic_class(traffic_class),
+                );
+                entry.insert(session.clone());
+                context.session_opened(Self::ID, upstream);
+                Ok(session)
+            }
+        }
+    }
+
+    fn listen(
+        &mut self,
+        upstream: ProtocolId,
+        participants: Control,
+        context: &mut ProtocolContext,
+    ) -> Result<(), SimError> {
+        let local = LocalAddress::try_from(&participants)
+            .unwrap_or_else(|_| Ipv6Address::UNSPECIFIED.into());
+        match self.listen_bindings.entry(local) {
+            Entry::Occupied(entry) if *entry.get() == upstream => {}
+            Entry::Occupied(_) => Err(Ipv6Error::BindingExists(local))?,
+            Entry::Vacant(entry) => {
+                entry.insert(upstream);
+            }
+        }
+
+        context
+            .protocol(Tap::ID)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .listen(Self::ID, participants, context)
+    }
+
+    fn demux(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        let header = Ipv6Header::from_bytes(message.iter())?;
+        let remote = RemoteAddress::from(header.source);
+        let local = LocalAddress::from(header.destination);
+        let identifier = SessionId { local, remote };
+        local.apply(&mut context.info);
+        remote.apply(&mut context.info);
+        let message = message.slice(HEADER_OCTETS..);
+        let mut session = match self.sessions.entry(identifier) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => match self
+                .listen_bindings
+                .get(&local)
+                .or_else(|| self.listen_bindings.get(&Ipv6Address::UNSPECIFIED.into()))
+            {
+                Some(&binding) => {
+                    let session = SharedSession::new(Ipv6Session::new(
+                        context.current_session().expect("No current session"),
+                        binding,
+                        identifier,
+                    ));
+                    entry.insert(session.clone());
+                    context.session_opened(Self::ID, binding);
+                    session
+                }
+                None => Err(Ipv6Error::MissingListenBinding(local))?,
+            },
+        };
+        session.receive(message, context)?;
+        Ok(())
+    }
+
+    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        Ok(ControlFlow::Continue)
+    }
+}
@@ -1,7 +1,11 @@
 //! Fundatmental Internet protocols to be used by most simulations.
 
+pub mod compression;
+pub mod congestion;
+pub mod firewall;
 pub mod ipv4;
+pub mod ipv6;
 pub mod tap;
+pub mod tls;
 pub mod udp;
 pub mod user_process;
-mod utility;
@@ -2,22 +2,33 @@
 
 use crate::core::{
     message::Message, Control, ControlFlow, Mtu, Network, Protocol, ProtocolContext, ProtocolId,
-    SharedSession,
+    QueueDiscipline, SharedSession, SimError,
 };
 use std::{
-    cell::{Ref, RefCell},
-    collections::{hash_map::Entry, HashMap},
-    error::Error,
-    rc::Rc,
+    collections::HashMap,
+    sync::{Arc, Mutex},
 };
 
 mod tap_misc;
-pub use tap_misc::NetworkIndex;
+pub use tap_misc::{
+    Intercept, NetworkIndex, PhysicalDestination, Precedence, SendQueueCapacity, TapError,
+};
+
+mod tap_queue;
+use tap_queue::{Frame, SharedQueue};
 
 mod tap_session;
 use tap_session::TapSession;
 
-use self::{tap_misc::TapError, tap_session::SessionId};
+use self::tap_session::SessionId;
+
+#[cfg(feature = "hop-trace")]
+use crate::core::MachineId;
+
+/// The most machines a message may pass through before it is dropped. Only
+/// used with the `hop-trace` feature.
+#[cfg(feature = "hop-trace")]
+pub const MAX_HOPS: usize = 64;
 
 /// Represents something akin to an Ethernet tap or a network interface card.
 ///
@@ -27,12 +38,41 @@ use self::{tap_misc::TapError, tap_session::SessionId};
 /// network, for example IPv4 or IPv6. The header is very simple, adding only a
 /// u32 that specifies the `ProtocolId` of the protocol that should receive the
 /// message.
+///
+/// Outgoing messages wait in a queue for each network until the machine sends
+/// them, in the order set by the network's [`QueueDiscipline`]. Sessions above
+/// the tap mark each message's importance by setting [`Precedence`] on the
+/// [`ProtocolContext`] before sending, and choose the machine it goes to by
+/// setting its [`PhysicalDestination`]. Messages without a destination are
+/// broadcast to every machine on the network.
+///
+/// Sessions opened with a [`SendQueueCapacity`] apply backpressure: once that
+/// many of their messages are waiting, sending fails with
+/// [`TapError::WouldBlock`] until the machine next sends its queued messages,
+/// so a fast application can't queue messages without bound.
+///
+/// A protocol can put itself between the tap and another protocol by
+/// listening on the tap with an [`Intercept`] naming that protocol. Incoming
+/// messages for the intercepted protocol then go to the listener instead,
+/// which is how a [`Firewall`](super::firewall::Firewall) sees every packet
+/// before [`Ipv4`](super::ipv4::Ipv4) does.
+///
+/// With the `hop-trace` feature, the tap also adds its machine to the
+/// [trace](Message::trace) of each message it sends. A message that already
+/// passed through this machine, or that has been through [`MAX_HOPS`]
+/// machines, is looping and gets dropped with an error logged instead.
 #[derive(Default)]
 pub struct Tap {
     // TODO(hardint): Add an interface for accessing the MTUs
     #[allow(dead_code)]
     network_mtus: Vec<Mtu>,
-    sessions: HashMap<SessionId, Rc<RefCell<TapSession>>>,
+    network_disciplines: Vec<QueueDiscipline>,
+    queues: HashMap<NetworkIndex, SharedQueue>,
+    sessions: HashMap<SessionId, Arc<Mutex<TapSession>>>,
+    /// The protocol to deliver each intercepted protocol's messages to
+    intercepts: HashMap<ProtocolId, ProtocolId>,
+    #[cfg(feature = "hop-trace")]
+    machine: MachineId,
 }
 
 impl Tap {
@@ -44,23 +84,64 @@ impl Tap {
         Default::default()
     }
 
-    pub fn attach(&mut self, network: Ref<Network>) {
+    pub fn attach(&mut self, network: &Network) {
         // TODO(hardint): Also store a channel to send on
         self.network_mtus.push(network.mtu());
+        self.network_disciplines.push(network.discipline());
+    }
+
+    /// Sets the machine this tap belongs to, which is recorded in the trace of
+    /// outgoing messages.
+    #[cfg(feature = "hop-trace")]
+    pub fn set_machine(&mut self, machine: MachineId) {
+        self.machine = machine;
     }
 
     /// Gets a list of the pending, outgoing messages that have been sent on the
     /// tap.
-    pub fn outgoing(&mut self) -> Vec<(NetworkIndex, Vec<Message>)> {
-        self.sessions
-            .values()
-            .map(|session| {
-                let mut session = session.borrow_mut();
-                (session.network(), session.outgoing())
+    pub fn outgoing(&mut self) -> Vec<(NetworkIndex, Vec<Frame>)> {
+        self.queues
+            .iter()
+            .map(|(&network, queue)| {
+                let discipline = self
+                    .network_disciplines
+                    .get(u8::from(network) as usize)
+                    .copied()
+                    .unwrap_or_default();
+                let messages = queue.lock().unwrap().drain(discipline);
+                #[cfg(feature = "hop-trace")]
+                let messages = messages
+                    .into_iter()
+                    .filter_map(
+                        |(address, message)| match record_hop(&message, self.machine) {
+                            Ok(message) => Some((address, message)),
+                            Err(e) => {
+                                eprintln!("{:?} -> {}", e, e);
+                                None
+                            }
+                        },
+                    )
+                    .collect();
+                (network, messages)
             })
             .collect()
     }
 
+    /// The queue of outgoing messages for the given network.
+    fn queue(&mut self, network: NetworkIndex) -> SharedQueue {
+        self.queues.entry(network).or_default().clone()
+    }
+
+    /// The session for `upstream` on the given network, created if needed.
+    fn session(&mut self, upstream: ProtocolId, network: NetworkIndex) -> Arc<Mutex<TapSession>> {
+        let queue = self.queue(network);
+        let receiver = self.intercepts.get(&upstream).copied().unwrap_or(upstream);
+        self.sessions
+            .entry(SessionId::new(upstream, network))
+            .or_insert_with(|| Arc::new(Mutex::new(TapSession::new(upstream, receiver, queue))))
+            .clone()
+    }
+
     /// Delivers a message to the network for delivery up the protocol stack.
     /// The tap will demux the message and forward it to the appropriate
     /// protocol.
@@ -69,23 +150,32 @@ impl Tap {
         message: Message,
         network: u8,
         context: &mut ProtocolContext,
-    ) -> Result<(), TapError> {
+    ) -> Result<(), SimError> {
         let header = take_header(&message).ok_or(TapError::HeaderLength)?;
         NetworkIndex::set(&mut context.info, network);
         let message = message.slice(8..);
-        let session_id = SessionId::new(header, network.into());
-        let session = match self.sessions.entry(session_id) {
-            Entry::Occupied(entry) => entry.get().clone(),
-            Entry::Vacant(entry) => {
-                let session = Rc::new(RefCell::new(TapSession::new(header, network.into())));
-                entry.insert(session.clone());
-                session
-            }
-        };
+        let session = self.session(header, network.into());
         let mut session = SharedSession::from(session);
         session.receive(message, context)?;
         Ok(())
     }
+
+    /// Hands a message this machine sent back up the protocol stack that sent
+    /// it, after its destination refused it.
+    pub fn accept_refused(
+        &mut self,
+        message: Message,
+        network: u8,
+        context: &mut ProtocolContext,
+    ) -> Result<(), SimError> {
+        let header = take_header(&message).ok_or(TapError::HeaderLength)?;
+        NetworkIndex::set(&mut context.info, network);
+        let message = message.slice(8..);
+        let session = self.session(header, network.into());
+        let mut session = SharedSession::from(session);
+        session.refused(message, context)?;
+        Ok(())
+    }
 }
 
 impl Protocol for Tap {
@@ -98,34 +188,39 @@ impl Protocol for Tap {
         upstream: ProtocolId,
         participants: Control,
         _context: &mut ProtocolContext,
-    ) -> Result<SharedSession, Box<dyn Error>> {
+    ) -> Result<SharedSession, SimError> {
         let network = NetworkIndex::get(&participants);
-        let session_id = SessionId::new(upstream, network.into());
-        match self.sessions.entry(session_id) {
-            Entry::Occupied(entry) => Ok(entry.get().clone().into()),
-            Entry::Vacant(entry) => {
-                let session = Rc::new(RefCell::new(TapSession::new(upstream, network.into())));
-                entry.insert(session.clone());
-                Ok(session.into())
-            }
+        let session = self.session(upstream, network.into());
+        if let Ok(capacity) = SendQueueCapacity::try_from(&participants) {
+            session.lock().unwrap().set_capacity(capacity.into());
         }
+        Ok(session.into())
     }
 
     fn listen(
         &mut self,
-        _upstream: ProtocolId,
-        _participants: Control,
+        upstream: ProtocolId,
+        participants: Control,
         _context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
-        // This is a no-op because nobody can call open_passive on us anyway
+    ) -> Result<(), SimError> {
+        // Other than for intercepts this is a no-op, because every incoming
+        // message already names the protocol to receive it
+        if let Ok(intercepted) = Intercept::try_from(&participants) {
+            let intercepted = ProtocolId::from(u64::from(intercepted));
+            self.intercepts.insert(intercepted, upstream);
+            for session in self
+                .sessions
+                .iter()
+                .filter(|(id, _)| id.upstream() == intercepted)
+                .map(|(_, session)| session)
+            {
+                session.lock().unwrap().set_receiver(upstream);
+            }
+        }
         Ok(())
     }
 
-    fn demux(
-        &mut self,
-        _message: Message,
-        _context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    fn demux(&mut self, _message: Message, _context: &mut ProtocolContext) -> Result<(), SimError> {
         // We use accept_incoming instead of demux because there are no
         // protocols under this one that would ask Tap to demux a message and
         // because, semantically, demux chooses one of its own sessions to
@@ -134,11 +229,28 @@ impl Protocol for Tap {
         panic!("Cannot demux on a Tap")
     }
 
-    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
         Ok(ControlFlow::Continue)
     }
 }
 
+/// Adds `machine` to the trace of a message about to be sent, or fails if the
+/// message is looping.
+#[cfg(feature = "hop-trace")]
+fn record_hop(message: &Message, machine: MachineId) -> Result<Message, TapError> {
+    let trace = message.trace();
+    if trace.contains(&machine) {
+        Err(TapError::Loop {
+            machine,
+            trace: trace.to_vec(),
+        })?
+    }
+    if trace.len() >= MAX_HOPS {
+        Err(TapError::HopLimit(trace.to_vec()))?
+    }
+    Ok(message.with_hop(machine))
+}
+
 fn take_header(message: &Message) -> Option<ProtocolId> {
     let mut iter = message.iter();
     Some(
@@ -155,3 +267,31 @@ fn take_header(message: &Message) -> Option<ProtocolId> {
         .into(),
     )
 }
+
+#[cfg(all(test, feature = "hop-trace"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_hops_and_drops_loops() {
+        let message = Message::new(b"Body");
+        let message = record_hop(&message, 0).unwrap();
+        let message = record_hop(&message.with_header(b"Header"), 1).unwrap();
+        assert_eq!(message.trace(), [0, 1]);
+        assert!(matches!(
+            record_hop(&message.slice(6..), 0),
+            Err(TapError::Loop { machine: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn drops_messages_past_the_hop_limit() {
+        let message = (0..MAX_HOPS).fold(Message::new(b"Body"), |message, machine| {
+            record_hop(&message, machine).unwrap()
+        });
+        assert!(matches!(
+            record_hop(&message, MAX_HOPS),
+            Err(TapError::HopLimit(_))
+        ));
+    }
+}
@@ -1,8 +1,7 @@
 use crate::core::{
     control::{from_impls, make_key, ControlValue},
-    ProtocolId,
+    PhysicalAddress, ProtocolId,
 };
-use std::error::Error;
 use thiserror::Error as ThisError;
 
 const NETWORK_INDEX_KEY: u64 = make_key("Tap Network Index");
@@ -11,12 +10,49 @@ const NETWORK_INDEX_KEY: u64 = make_key("Tap Network Index");
 pub type NetworkIndex = ControlValue<NETWORK_INDEX_KEY, u8>;
 from_impls!(NetworkIndex, u8);
 
+const PRECEDENCE_KEY: u64 = make_key("Tap Precedence");
+/// A [`ControlValue`] for how important an outgoing message is, from 0 for
+/// routine traffic to 7 for network control. Messages sent without one have
+/// precedence 0.
+pub type Precedence = ControlValue<PRECEDENCE_KEY, u8>;
+from_impls!(Precedence, u8);
+
+const PHYSICAL_DESTINATION_KEY: u64 = make_key("Tap Physical Destination");
+/// A [`ControlValue`] for the physical address of the machine an outgoing
+/// message is for. Messages sent without one are broadcast to every machine on
+/// the network.
+pub type PhysicalDestination = ControlValue<PHYSICAL_DESTINATION_KEY, PhysicalAddress>;
+from_impls!(PhysicalDestination, PhysicalAddress);
+
+const INTERCEPT_KEY: u64 = make_key("Tap Intercept");
+/// A [`ControlValue`] for the [`ProtocolId`] of the protocol whose incoming
+/// messages a protocol listening on the tap wants to receive instead.
+pub type Intercept = ControlValue<INTERCEPT_KEY, u64>;
+from_impls!(Intercept, u64);
+
+const SEND_QUEUE_CAPACITY_KEY: u64 = make_key("Tap Send Queue Capacity");
+/// A [`ControlValue`] for how many messages a session may have waiting to go
+/// out at once. Once that many are waiting, sending fails with
+/// [`TapError::WouldBlock`] until the machine sends some. Sessions opened
+/// without one may queue any number.
+pub type SendQueueCapacity = ControlValue<SEND_QUEUE_CAPACITY_KEY, u32>;
+from_impls!(SendQueueCapacity, u32);
+
 #[derive(Debug, ThisError)]
 pub enum TapError {
     #[error("Expected two bytes for the header")]
     HeaderLength,
     #[error("Could not find a protocol for the protocol ID: {0:?}")]
     NoSuchProtocol(ProtocolId),
-    #[error("{0}")]
-    Other(#[from] Box<dyn Error>),
+    #[error("The session's send queue is full")]
+    WouldBlock,
+    #[cfg(feature = "hop-trace")]
+    #[error("Machine {machine} saw the message again after it took the path {trace:?}")]
+    Loop {
+        machine: crate::core::MachineId,
+        trace: Vec<crate::core::MachineId>,
+    },
+    #[cfg(feature = "hop-trace")]
+    #[error("The message passed the hop limit after taking the path {0:?}")]
+    HopLimit(Vec<crate::core::MachineId>),
 }
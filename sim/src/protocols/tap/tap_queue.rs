@@ -0,0 +1,137 @@
+use crate::core::{message::Message, PhysicalAddress, ProtocolId, QueueDiscipline};
+use std::{
+    cmp::Reverse,
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// The messages waiting to go out on one network, shared by the tap sessions
+/// for that network.
+pub(super) type SharedQueue = Arc<Mutex<OutgoingQueue>>;
+
+#[derive(Debug, Default)]
+pub(super) struct OutgoingQueue {
+    messages: Vec<Queued>,
+}
+
+/// A message and the physical address it goes to.
+pub(super) type Frame = (PhysicalAddress, Message);
+
+#[derive(Debug)]
+struct Queued {
+    precedence: u8,
+    flow: ProtocolId,
+    frame: Frame,
+}
+
+impl OutgoingQueue {
+    pub fn push(
+        &mut self,
+        message: Message,
+        destination: PhysicalAddress,
+        precedence: u8,
+        flow: ProtocolId,
+    ) {
+        self.messages.push(Queued {
+            precedence,
+            flow,
+            frame: (destination, message),
+        });
+    }
+
+    /// The number of messages from `flow` waiting to go out.
+    pub fn queued(&self, flow: ProtocolId) -> usize {
+        self.messages
+            .iter()
+            .filter(|queued| queued.flow == flow)
+            .count()
+    }
+
+    /// Removes every queued message in the order the `discipline` sends them.
+    pub fn drain(&mut self, discipline: QueueDiscipline) -> Vec<Frame> {
+        let mut messages = std::mem::take(&mut self.messages);
+        match discipline {
+            QueueDiscipline::Fifo => {}
+            QueueDiscipline::Priority => {
+                // The sort is stable, so queue order holds within a precedence
+                messages.sort_by_key(|queued| Reverse(queued.precedence));
+            }
+            QueueDiscipline::FairQueueing => return round_robin(messages),
+        }
+        messages.into_iter().map(|queued| queued.frame).collect()
+    }
+}
+
+/// Takes one message from each flow in turn, visiting flows in the order they
+/// first queued a message.
+fn round_robin(messages: Vec<Queued>) -> Vec<Frame> {
+    let mut flows: Vec<(ProtocolId, VecDeque<Frame>)> = vec![];
+    for queued in messages {
+        match flows.iter_mut().find(|(flow, _)| *flow == queued.flow) {
+            Some((_, flow)) => flow.push_back(queued.frame),
+            None => flows.push((queued.flow, VecDeque::from([queued.frame]))),
+        }
+    }
+    let mut out = vec![];
+    while !flows.is_empty() {
+        for (_, flow) in flows.iter_mut() {
+            out.extend(flow.pop_front());
+        }
+        flows.retain(|(_, flow)| !flow.is_empty());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: ProtocolId = ProtocolId::new(1);
+    const B: ProtocolId = ProtocolId::new(2);
+    const TO: PhysicalAddress = PhysicalAddress::BROADCAST;
+
+    fn queue() -> OutgoingQueue {
+        let mut queue = OutgoingQueue::default();
+        queue.push(Message::new(b"a1"), TO, 0, A);
+        queue.push(Message::new(b"a2"), TO, 0, A);
+        queue.push(Message::new(b"b1"), TO, 5, B);
+        queue.push(Message::new(b"a3"), TO, 5, A);
+        queue
+    }
+
+    fn drain(discipline: QueueDiscipline) -> Vec<Message> {
+        queue()
+            .drain(discipline)
+            .into_iter()
+            .map(|(_, message)| message)
+            .collect()
+    }
+
+    fn messages(bodies: [&'static [u8]; 4]) -> Vec<Message> {
+        bodies.into_iter().map(Message::new).collect()
+    }
+
+    #[test]
+    fn counts_queued_messages_by_flow() {
+        let mut queue = queue();
+        assert_eq!((queue.queued(A), queue.queued(B)), (3, 1));
+        queue.drain(QueueDiscipline::Fifo);
+        assert_eq!(queue.queued(A), 0);
+    }
+
+    #[test]
+    fn disciplines() {
+        assert_eq!(
+            drain(QueueDiscipline::Fifo),
+            messages([b"a1", b"a2", b"b1", b"a3"])
+        );
+        assert_eq!(
+            drain(QueueDiscipline::Priority),
+            messages([b"b1", b"a3", b"a1", b"a2"])
+        );
+        assert_eq!(
+            drain(QueueDiscipline::FairQueueing),
+            messages([b"a1", b"b1", b"a2", b"a3"])
+        );
+    }
+}
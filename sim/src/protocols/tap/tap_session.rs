@@ -1,58 +1,82 @@
-use super::{tap_misc::TapError, NetworkIndex};
-use crate::core::{message::Message, ControlFlow, ProtocolContext, ProtocolId, Session};
-use std::{error::Error, mem};
+use super::{
+    tap_misc::TapError, tap_queue::SharedQueue, NetworkIndex, PhysicalDestination, Precedence,
+};
+use crate::core::{
+    message::Message, ControlFlow, PhysicalAddress, ProtocolContext, ProtocolId, Session, SimError,
+};
 
 #[derive(Clone)]
 pub struct TapSession {
-    network: NetworkIndex,
-    outgoing: Vec<Message>,
+    queue: SharedQueue,
     upstream: ProtocolId,
+    /// The protocol incoming messages go to, which differs from the upstream
+    /// protocol if another protocol intercepts it
+    receiver: ProtocolId,
+    /// The most messages the session may have waiting to go out
+    capacity: Option<u32>,
 }
 
 impl TapSession {
-    pub(super) fn new(upstream: ProtocolId, network: NetworkIndex) -> Self {
+    pub(super) fn new(upstream: ProtocolId, receiver: ProtocolId, queue: SharedQueue) -> Self {
         Self {
             upstream,
-            network,
-            outgoing: vec![],
+            receiver,
+            queue,
+            capacity: None,
         }
     }
 
-    pub fn network(&self) -> NetworkIndex {
-        self.network
+    pub(super) fn set_capacity(&mut self, capacity: u32) {
+        self.capacity = Some(capacity);
     }
 
-    pub fn outgoing(&mut self) -> Vec<Message> {
-        mem::take(&mut self.outgoing)
+    pub(super) fn set_receiver(&mut self, receiver: ProtocolId) {
+        self.receiver = receiver;
     }
 }
 
 impl Session for TapSession {
-    fn send(
-        &mut self,
-        message: Message,
-        _context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    fn send(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        // The precedence and destination apply to this message only
+        let precedence = Precedence::try_from(&context.info).map_or(0, u8::from);
+        Precedence::remove(&mut context.info);
+        let destination = PhysicalDestination::try_from(&context.info)
+            .map_or(PhysicalAddress::BROADCAST, PhysicalAddress::from);
+        PhysicalDestination::remove(&mut context.info);
+        // Only the kind of protocol goes on the wire, so the receiving machine
+        // delivers to its first instance of that protocol
+        let mut queue = self.queue.lock().unwrap();
+        if let Some(capacity) = self.capacity {
+            if queue.queued(self.upstream) >= capacity as usize {
+                Err(TapError::WouldBlock)?
+            }
+        }
         let message = message.with_header(&self.upstream.into_inner().to_be_bytes());
-        self.outgoing.push(message);
+        queue.push(message, destination, precedence, self.upstream);
         Ok(())
     }
 
-    fn receive(
-        &mut self,
-        message: Message,
-        context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    fn receive(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
         let protocol = context
-            .protocol(self.upstream)
-            .ok_or(TapError::NoSuchProtocol(self.upstream))?;
-        let mut protocol = protocol.borrow_mut();
+            .protocol(self.receiver)
+            .ok_or(TapError::NoSuchProtocol(self.receiver))?;
+        let mut protocol = protocol.lock().unwrap();
         protocol.demux(message, context)
     }
 
-    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
         Ok(ControlFlow::Continue)
     }
+
+    fn refused(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        // Refusals go back to the protocol that sent the message, even if
+        // another protocol intercepts its incoming messages
+        let protocol = context
+            .protocol(self.upstream)
+            .ok_or(TapError::NoSuchProtocol(self.upstream))?;
+        let mut protocol = protocol.lock().unwrap();
+        protocol.refused(message, context)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -65,4 +89,8 @@ impl SessionId {
     pub fn new(upstream: ProtocolId, network: NetworkIndex) -> Self {
         Self { upstream, network }
     }
+
+    pub fn upstream(&self) -> ProtocolId {
+        self.upstream
+    }
 }
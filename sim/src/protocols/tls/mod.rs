@@ -0,0 +1,198 @@
+//! A toy security layer in the shape of TLS, to stack above a transport.
+
+use crate::{
+    core::{
+        message::Message, Control, ControlFlow, Protocol, ProtocolContext, ProtocolId,
+        SharedSession, SimError,
+    },
+    protocols::udp::{LocalPort, Udp},
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex},
+};
+
+mod tls_crypto;
+use tls_crypto::Nonce;
+
+mod tls_misc;
+pub use tls_misc::TlsError;
+
+mod tls_session;
+use tls_session::{SessionId, TlsSession};
+
+/// A simplified version of TLS that authenticates both ends with a
+/// pre-shared key and protects the integrity and privacy of their messages.
+///
+/// **The cryptography is a toy and offers no real security.** The protocol is
+/// meant to show how a secure session works, not to provide one.
+///
+/// Opening a session starts a handshake:
+///
+/// 1. The client sends a hello with a fresh nonce.
+/// 2. The server answers with its own nonce and a MAC over both nonces,
+///    proving it knows the key.
+/// 3. The client checks the MAC and sends a MAC of its own, proving it knows
+///    the key too.
+///
+/// Each end then derives a session key from the pre-shared key and the
+/// nonces. Application messages become data records, encrypted and
+/// authenticated with the session key and numbered so that replayed records
+/// are rejected. Messages an application sends before the handshake finishes
+/// wait in its session.
+///
+/// Applications open and listen on the protocol just as they would on UDP,
+/// with the same participants. The protocol sends through UDP unless it is
+/// created [`with_downstream`](Tls::with_downstream) set to another protocol
+/// taking the same participants, such as
+/// [`Compression`](super::compression::Compression). Only one protocol may
+/// listen on each local port.
+pub struct Tls {
+    psk: Arc<[u8]>,
+    downstream: ProtocolId,
+    listen_bindings: HashMap<LocalPort, ProtocolId>,
+    sessions: HashMap<SessionId, SharedSession>,
+    /// How many nonces the protocol has made
+    nonces: u64,
+}
+
+impl Tls {
+    /// A unique identifier for the protocol.
+    pub const ID: ProtocolId = ProtocolId::from_string("TLS");
+
+    /// Creates a new instance of the protocol that shares the key `psk` with
+    /// its peers.
+    pub fn new(psk: &[u8]) -> Self {
+        Self {
+            psk: psk.into(),
+            downstream: Udp::ID,
+            listen_bindings: Default::default(),
+            sessions: Default::default(),
+            nonces: 0,
+        }
+    }
+
+    /// Creates a new shared handle to an instance of the protocol.
+    pub fn new_shared(psk: &[u8]) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::new(psk)))
+    }
+
+    /// Sends through the protocol `downstream` instead of UDP.
+    pub fn with_downstream(mut self, downstream: ProtocolId) -> Self {
+        self.downstream = downstream;
+        self
+    }
+
+    /// Makes a nonce unique to this protocol and connection. The simulation
+    /// is deterministic, so the nonce is derived rather than random.
+    fn nonce(&mut self, role: &[u8], identifier: SessionId, round: u64) -> Nonce {
+        self.nonces += 1;
+        tls_crypto::mac(
+            &self.psk,
+            &[
+                role,
+                &self.nonces.to_be_bytes(),
+                &round.to_be_bytes(),
+                &<[u8; 4]>::from(identifier.local_address),
+                &u16::from(identifier.local_port).to_be_bytes(),
+            ],
+        )
+    }
+}
+
+impl Protocol for Tls {
+    fn id(&self) -> ProtocolId {
+        Self::ID
+    }
+
+    fn open(
+        &mut self,
+        upstream: ProtocolId,
+        participants: Control,
+        context: &mut ProtocolContext,
+    ) -> Result<SharedSession, SimError> {
+        let identifier = SessionId::new(&participants);
+        if self.sessions.contains_key(&identifier) {
+            Err(TlsError::SessionExists)?
+        }
+        let downstream = context
+            .protocol(self.downstream)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .open(Self::ID, participants, context)?;
+        let client_nonce = self.nonce(b"client", identifier, context.round());
+        let session = SharedSession::new(TlsSession::connect(
+            upstream,
+            downstream,
+            self.psk.clone(),
+            client_nonce,
+            context,
+        )?);
+        self.sessions.insert(identifier, session.clone());
+        context.session_opened(Self::ID, upstream);
+        Ok(session)
+    }
+
+    fn listen(
+        &mut self,
+        upstream: ProtocolId,
+        participants: Control,
+        context: &mut ProtocolContext,
+    ) -> Result<(), SimError> {
+        let local_port = LocalPort::try_from(&participants).unwrap();
+        match self.listen_bindings.entry(local_port) {
+            Entry::Occupied(entry) if *entry.get() != upstream => {
+                Err(TlsError::BindingExists(local_port.into()))?
+            }
+            entry => {
+                entry.or_insert(upstream);
+            }
+        }
+
+        context
+            .protocol(self.downstream)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .listen(Self::ID, participants, context)
+    }
+
+    fn demux(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        let identifier = SessionId::new(&context.info);
+        let mut session = match self.sessions.get(&identifier) {
+            Some(session) => session.clone(),
+            None => match self.listen_bindings.get(&identifier.local_port) {
+                Some(&binding) => {
+                    let server_nonce = self.nonce(b"server", identifier, context.round());
+                    let session = SharedSession::new(TlsSession::accept(
+                        binding,
+                        context.current_session().expect("No current session"),
+                        self.psk.clone(),
+                        server_nonce,
+                    ));
+                    self.sessions.insert(identifier, session.clone());
+                    context.session_opened(Self::ID, binding);
+                    session
+                }
+                None => Err(TlsError::MissingSession)?,
+            },
+        };
+        session.receive(message, context)
+    }
+
+    fn refused(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        match self.sessions.get(&SessionId::new(&context.info)) {
+            Some(session) => session.clone().refused(message, context),
+            None => Ok(()),
+        }
+    }
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        // Sessions send the handshake records they queued while receiving
+        for session in self.sessions.values() {
+            session.clone().awake(context)?;
+        }
+        Ok(ControlFlow::Continue)
+    }
+}
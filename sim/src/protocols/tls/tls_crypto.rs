@@ -0,0 +1,72 @@
+//! Toy cryptography for [`Tls`](super::Tls), built on FNV-1a.
+//!
+//! None of this is secure. FNV-1a is not a cryptographic hash, so these
+//! functions only have the shape of the real thing: a keyed MAC nested like
+//! HMAC, and a stream cipher that XORs data with a keystream derived from the
+//! key and a sequence number.
+
+use const_fnv1a_hash::fnv1a_hash_64;
+
+/// Bytes in a nonce
+pub const NONCE_LENGTH: usize = 8;
+/// Bytes in a MAC tag
+pub const TAG_LENGTH: usize = 8;
+
+pub type Nonce = [u8; NONCE_LENGTH];
+pub type Tag = [u8; TAG_LENGTH];
+
+/// Computes a tag over `parts` with `key`.
+pub fn mac(key: &[u8], parts: &[&[u8]]) -> Tag {
+    let mut inner = key.iter().map(|byte| byte ^ 0x36).collect::<Vec<_>>();
+    for part in parts {
+        inner.extend_from_slice(part);
+    }
+    let mut outer = key.iter().map(|byte| byte ^ 0x5c).collect::<Vec<_>>();
+    outer.extend_from_slice(&fnv1a_hash_64(&inner, None).to_be_bytes());
+    fnv1a_hash_64(&outer, None).to_be_bytes()
+}
+
+/// Whether `tag` is the tag over `parts` with `key`.
+pub fn verify(key: &[u8], parts: &[&[u8]], tag: &[u8]) -> bool {
+    // A real implementation compares in constant time
+    mac(key, parts) == tag
+}
+
+/// Encrypts or decrypts `data` in place with the keystream for `sequence`.
+pub fn apply_keystream(key: &[u8], sequence: u64, data: &mut [u8]) {
+    for (block, chunk) in data.chunks_mut(8).enumerate() {
+        let keystream = mac(
+            key,
+            &[
+                b"stream",
+                &sequence.to_be_bytes(),
+                &(block as u64).to_be_bytes(),
+            ],
+        );
+        for (byte, key_byte) in chunk.iter_mut().zip(keystream) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keystream_round_trips() {
+        let mut data = b"Attack at dawn, or maybe after lunch".to_vec();
+        apply_keystream(b"key", 7, &mut data);
+        assert_ne!(data, b"Attack at dawn, or maybe after lunch");
+        apply_keystream(b"key", 7, &mut data);
+        assert_eq!(data, b"Attack at dawn, or maybe after lunch");
+    }
+
+    #[test]
+    fn tags_depend_on_key_and_data() {
+        let tag = mac(b"key", &[b"data"]);
+        assert!(verify(b"key", &[b"data"], &tag));
+        assert!(!verify(b"other key", &[b"data"], &tag));
+        assert!(!verify(b"key", &[b"datb"], &tag));
+    }
+}
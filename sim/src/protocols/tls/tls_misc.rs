@@ -0,0 +1,24 @@
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum TlsError {
+    #[error("Tried to create an existing session")]
+    SessionExists,
+    #[error("Port {0} is already bound to another protocol")]
+    BindingExists(u16),
+    #[error("Tried to demux with a missing session and no listen binding")]
+    MissingSession,
+    #[error("The record is too short for its type")]
+    RecordTooShort,
+    #[error("Unknown record type {0}")]
+    UnknownRecord(u8),
+    #[error("Got a {record} record while {state}")]
+    UnexpectedRecord {
+        record: &'static str,
+        state: &'static str,
+    },
+    #[error("The record failed its integrity check")]
+    BadMac,
+    #[error("Expected sequence number {expected} or later but got {actual}")]
+    Replay { expected: u64, actual: u64 },
+}
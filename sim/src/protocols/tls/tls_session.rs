@@ -0,0 +1,330 @@
+use super::{
+    tls_crypto::{self, Nonce, NONCE_LENGTH, TAG_LENGTH},
+    TlsError,
+};
+use crate::{
+    core::{
+        message::Message, Control, ControlFlow, ProtocolContext, ProtocolId, Session,
+        SharedSession, SimError,
+    },
+    protocols::{
+        ipv4::{LocalAddress, RemoteAddress},
+        udp::{LocalPort, RemotePort},
+    },
+};
+use std::sync::Arc;
+
+/// Starts a handshake with the client's nonce.
+const CLIENT_HELLO: u8 = 1;
+/// Answers a client hello with the server's nonce and proof that the server
+/// knows the key.
+const SERVER_HELLO: u8 = 2;
+/// Ends a handshake with proof that the client knows the key.
+const FINISHED: u8 = 3;
+/// Carries encrypted application data.
+const DATA: u8 = 4;
+
+/// Where a session is in its handshake.
+enum State {
+    /// A client waiting for the server to answer its hello
+    AwaitingServerHello { client_nonce: Nonce },
+    /// A server waiting for a client hello
+    Listening { server_nonce: Nonce },
+    /// A server waiting for the client to finish the handshake
+    AwaitingFinished {
+        client_nonce: Nonce,
+        server_nonce: Nonce,
+    },
+    /// Both ends share a session key and exchange data
+    Established { key: Vec<u8> },
+}
+
+impl State {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::AwaitingServerHello { .. } => "awaiting a server hello",
+            Self::Listening { .. } => "awaiting a client hello",
+            Self::AwaitingFinished { .. } => "awaiting the end of the handshake",
+            Self::Established { .. } => "established",
+        }
+    }
+}
+
+pub(super) struct TlsSession {
+    upstream: ProtocolId,
+    downstream: SharedSession,
+    psk: Arc<[u8]>,
+    state: State,
+    /// Application messages waiting for the handshake to finish
+    queued: Vec<Message>,
+    /// Records waiting to be sent the next time the session is awoken, since
+    /// a session can't send while a message it received is being delivered
+    outgoing: Vec<Message>,
+    /// The sequence number of the next data record to send
+    send_sequence: u64,
+    /// The lowest sequence number of a data record not yet received
+    receive_sequence: u64,
+}
+
+impl TlsSession {
+    /// Creates a client session and sends its hello.
+    pub fn connect(
+        upstream: ProtocolId,
+        mut downstream: SharedSession,
+        psk: Arc<[u8]>,
+        client_nonce: Nonce,
+        context: &mut ProtocolContext,
+    ) -> Result<Self, SimError> {
+        downstream.send(record(CLIENT_HELLO, &[&client_nonce]), context)?;
+        Ok(Self::new(
+            upstream,
+            downstream,
+            psk,
+            State::AwaitingServerHello { client_nonce },
+        ))
+    }
+
+    /// Creates a server session waiting for a client hello.
+    pub fn accept(
+        upstream: ProtocolId,
+        downstream: SharedSession,
+        psk: Arc<[u8]>,
+        server_nonce: Nonce,
+    ) -> Self {
+        Self::new(upstream, downstream, psk, State::Listening { server_nonce })
+    }
+
+    fn new(upstream: ProtocolId, downstream: SharedSession, psk: Arc<[u8]>, state: State) -> Self {
+        Self {
+            upstream,
+            downstream,
+            psk,
+            state,
+            queued: vec![],
+            outgoing: vec![],
+            send_sequence: 0,
+            receive_sequence: 0,
+        }
+    }
+
+    /// Derives the session key and queues the messages sent during the
+    /// handshake.
+    fn establish(&mut self, client_nonce: Nonce, server_nonce: Nonce) {
+        let key = tls_crypto::mac(&self.psk, &[b"key", &client_nonce, &server_nonce]).to_vec();
+        for message in std::mem::take(&mut self.queued) {
+            let sealed = seal(&key, self.send_sequence, message);
+            self.send_sequence += 1;
+            self.outgoing.push(sealed);
+        }
+        self.state = State::Established { key };
+    }
+}
+
+impl Session for TlsSession {
+    fn send(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        match &self.state {
+            State::Established { key } => {
+                let sealed = seal(key, self.send_sequence, message);
+                self.send_sequence += 1;
+                self.downstream.send(sealed, context)
+            }
+            _ => {
+                self.queued.push(message);
+                Ok(())
+            }
+        }
+    }
+
+    fn receive(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        let bytes: Vec<_> = message.iter().collect();
+        let (&kind, body) = bytes.split_first().ok_or(TlsError::RecordTooShort)?;
+        match (kind, &self.state) {
+            (CLIENT_HELLO, &State::Listening { server_nonce }) => {
+                let client_nonce = take_nonce(body)?;
+                let tag = tls_crypto::mac(&self.psk, &[b"server", &client_nonce, &server_nonce]);
+                self.outgoing
+                    .push(record(SERVER_HELLO, &[&server_nonce, &tag]));
+                self.state = State::AwaitingFinished {
+                    client_nonce,
+                    server_nonce,
+                };
+                Ok(())
+            }
+            (SERVER_HELLO, &State::AwaitingServerHello { client_nonce }) => {
+                let server_nonce = take_nonce(body)?;
+                let tag = &body[NONCE_LENGTH..];
+                if !tls_crypto::verify(&self.psk, &[b"server", &client_nonce, &server_nonce], tag) {
+                    Err(TlsError::BadMac)?
+                }
+                let tag = tls_crypto::mac(&self.psk, &[b"client", &client_nonce, &server_nonce]);
+                self.outgoing.push(record(FINISHED, &[&tag]));
+                self.establish(client_nonce, server_nonce);
+                Ok(())
+            }
+            (
+                FINISHED,
+                &State::AwaitingFinished {
+                    client_nonce,
+                    server_nonce,
+                },
+            ) => {
+                if !tls_crypto::verify(&self.psk, &[b"client", &client_nonce, &server_nonce], body)
+                {
+                    Err(TlsError::BadMac)?
+                }
+                self.establish(client_nonce, server_nonce);
+                Ok(())
+            }
+            (DATA, State::Established { key }) => {
+                let (sequence, plaintext) = open(key, body)?;
+                if sequence < self.receive_sequence {
+                    Err(TlsError::Replay {
+                        expected: self.receive_sequence,
+                        actual: sequence,
+                    })?
+                }
+                self.receive_sequence = sequence + 1;
+                context
+                    .protocol(self.upstream)
+                    .expect("No such protocol")
+                    .lock()
+                    .unwrap()
+                    .demux(Message::new(plaintext), context)
+            }
+            (CLIENT_HELLO | SERVER_HELLO | FINISHED | DATA, state) => {
+                Err(TlsError::UnexpectedRecord {
+                    record: record_name(kind),
+                    state: state.name(),
+                })?
+            }
+            (kind, _) => Err(TlsError::UnknownRecord(kind))?,
+        }
+    }
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        for message in std::mem::take(&mut self.outgoing) {
+            self.downstream.send(message, context)?;
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    fn refused(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        let bytes: Vec<_> = message.iter().collect();
+        let refused = match (bytes.split_first(), &self.state) {
+            // Nobody is there to finish the handshake, so none of the
+            // messages waiting on it will go anywhere
+            (Some((&CLIENT_HELLO, _)), _) => std::mem::take(&mut self.queued),
+            (Some((&DATA, body)), State::Established { key }) => {
+                vec![Message::new(open(key, body)?.1)]
+            }
+            _ => vec![],
+        };
+        let protocol = context.protocol(self.upstream).expect("No such protocol");
+        let mut protocol = protocol.lock().unwrap();
+        for message in refused {
+            protocol.refused(message, context)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds a record of the given kind out of `parts`.
+fn record(kind: u8, parts: &[&[u8]]) -> Message {
+    let mut bytes = vec![kind];
+    for part in parts {
+        bytes.extend_from_slice(part);
+    }
+    Message::new(bytes)
+}
+
+/// Encrypts a message into a data record with the given sequence number.
+fn seal(key: &[u8], sequence: u64, message: Message) -> Message {
+    let mut ciphertext: Vec<_> = message.iter().collect();
+    tls_crypto::apply_keystream(key, sequence, &mut ciphertext);
+    let sequence = sequence.to_be_bytes();
+    let tag = tls_crypto::mac(key, &[&[DATA], &sequence, &ciphertext]);
+    record(DATA, &[&sequence, &ciphertext, &tag])
+}
+
+/// Checks and decrypts the body of a data record, returning its sequence
+/// number and plaintext.
+fn open(key: &[u8], body: &[u8]) -> Result<(u64, Vec<u8>), TlsError> {
+    if body.len() < 8 + TAG_LENGTH {
+        Err(TlsError::RecordTooShort)?
+    }
+    let (sequence, rest) = body.split_at(8);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+    if !tls_crypto::verify(key, &[&[DATA], sequence, ciphertext], tag) {
+        Err(TlsError::BadMac)?
+    }
+    let sequence = u64::from_be_bytes(sequence.try_into().unwrap());
+    let mut plaintext = ciphertext.to_vec();
+    tls_crypto::apply_keystream(key, sequence, &mut plaintext);
+    Ok((sequence, plaintext))
+}
+
+fn take_nonce(body: &[u8]) -> Result<Nonce, TlsError> {
+    body.get(..NONCE_LENGTH)
+        .and_then(|nonce| nonce.try_into().ok())
+        .ok_or(TlsError::RecordTooShort)
+}
+
+fn record_name(kind: u8) -> &'static str {
+    match kind {
+        CLIENT_HELLO => "client hello",
+        SERVER_HELLO => "server hello",
+        FINISHED => "finished",
+        _ => "data",
+    }
+}
+
+/// Identifies a session by the connection it sends over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) struct SessionId {
+    pub local_address: LocalAddress,
+    pub local_port: LocalPort,
+    pub remote_address: RemoteAddress,
+    pub remote_port: RemotePort,
+}
+
+impl SessionId {
+    /// Takes the addresses and ports from participants or a context's info.
+    pub fn new(control: &Control) -> Self {
+        Self {
+            local_address: LocalAddress::try_from(control).unwrap(),
+            local_port: LocalPort::try_from(control).unwrap(),
+            remote_address: RemoteAddress::try_from(control).unwrap(),
+            remote_port: RemotePort::try_from(control).unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_what_it_seals() {
+        let sealed = seal(b"key", 3, Message::new("Secret"));
+        let bytes: Vec<_> = sealed.iter().collect();
+        assert_eq!(bytes[0], DATA);
+        assert!(!bytes.windows(6).any(|window| window == b"Secret"));
+        assert_eq!(open(b"key", &bytes[1..]).unwrap(), (3, b"Secret".to_vec()));
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let sealed = seal(b"key", 0, Message::new("Secret"));
+        let mut bytes: Vec<_> = sealed.iter().collect();
+        bytes[10] ^= 1;
+        assert!(matches!(open(b"key", &bytes[1..]), Err(TlsError::BadMac)));
+        assert!(matches!(
+            open(b"other key", &bytes[1..]),
+            Err(TlsError::BadMac)
+        ));
+        assert!(matches!(
+            open(b"key", &bytes[1..10]),
+            Err(TlsError::RecordTooShort)
+        ));
+    }
+}
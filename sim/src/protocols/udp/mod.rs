@@ -4,20 +4,17 @@
 use crate::{
     core::{
         message::Message, Control, ControlFlow, Protocol, ProtocolContext, ProtocolId,
-        SharedSession,
+        SharedSession, SimError,
     },
-    protocols::ipv4::{Ipv4, LocalAddress, RemoteAddress},
+    protocols::ipv4::{Ipv4, Ipv4Address, LocalAddress, RemoteAddress},
 };
 use std::{
-    cell::RefCell,
     collections::{hash_map::Entry, HashMap},
-    error::Error,
-    rc::Rc,
+    sync::{Arc, Mutex},
 };
 
 mod udp_misc;
-use udp_misc::UdpError;
-pub use udp_misc::{LocalPort, RemotePort};
+pub use udp_misc::{LocalPort, RemotePort, UdpError};
 
 mod udp_session;
 use udp_session::{SessionId, UdpSession};
@@ -25,8 +22,26 @@ use udp_session::{SessionId, UdpSession};
 use self::udp_parsing::UdpHeader;
 
 mod udp_parsing;
+#[cfg(feature = "fuzzing")]
+pub(crate) use udp_parsing::check_round_trip;
 
 /// An implementation of the User Datagram Protocol.
+///
+/// # Demultiplexing
+///
+/// An incoming message goes to the first of the following that matches it:
+///
+/// 1. The session opened for its exact local address, local port, remote
+///    address, and remote port.
+/// 2. A listen binding for its local address and port. Among these, a binding
+///    that also names the remote address wins over one that doesn't, and one
+///    that names the remote port too wins over both.
+/// 3. A listen binding for only its local port, with the local address left
+///    unspecified. The remote address and port break ties as above.
+///
+/// If nothing matches, the message is dropped with an error and handed back
+/// to the sending machine, where its session passes the refusal up to the
+/// application that sent it.
 #[derive(Default, Clone)]
 pub struct Udp {
     listen_bindings: HashMap<ListenId, ProtocolId>,
@@ -43,8 +58,39 @@ impl Udp {
     }
 
     /// Creates a new shared handle to an instance of the protocol.
-    pub fn new_shared() -> Rc<RefCell<Self>> {
-        Rc::new(RefCell::new(Self::new()))
+    pub fn new_shared() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// Decides where a message for the given connection should go, following
+    /// the precedence order described on [`Udp`].
+    fn resolve(&self, session_id: SessionId) -> Option<Resolution> {
+        match self.sessions.get(&session_id) {
+            Some(session) => Some(Resolution::Session(session.clone())),
+            None => self.listen_binding(session_id).map(Resolution::Listen),
+        }
+    }
+
+    /// Finds the most specific listen binding matching the connection.
+    /// Bindings may leave the local address, remote address, and remote port
+    /// unspecified to match any value.
+    fn listen_binding(&self, session_id: SessionId) -> Option<ProtocolId> {
+        for local_address in [Some(session_id.local_address), None] {
+            for remote_address in [Some(session_id.remote_address), None] {
+                for remote_port in [Some(session_id.remote_port), None] {
+                    let identifier = ListenId {
+                        local_address,
+                        local_port: session_id.local_port,
+                        remote_address,
+                        remote_port,
+                    };
+                    if let Some(&binding) = self.listen_bindings.get(&identifier) {
+                        return Some(binding);
+                    }
+                }
+            }
+        }
+        None
     }
 }
 
@@ -58,7 +104,7 @@ impl Protocol for Udp {
         upstream: ProtocolId,
         participants: Control,
         context: &mut ProtocolContext,
-    ) -> Result<SharedSession, Box<dyn Error>> {
+    ) -> Result<SharedSession, SimError> {
         let identifier = SessionId {
             local_port: LocalPort::try_from(&participants).unwrap(),
             remote_port: RemotePort::try_from(&participants).unwrap(),
@@ -71,7 +117,8 @@ impl Protocol for Udp {
                 let downstream = context
                     .protocol(Ipv4::ID)
                     .expect("No such protocol")
-                    .borrow_mut()
+                    .lock()
+                    .unwrap()
                     .open(Self::ID, participants, context)?;
                 let session = SharedSession::new(UdpSession {
                     upstream,
@@ -79,6 +126,7 @@ impl Protocol for Udp {
                     identifier,
                 });
                 entry.insert(session.clone());
+                context.session_opened(Self::ID, upstream);
                 Ok(session)
             }
         }
@@ -89,25 +137,31 @@ impl Protocol for Udp {
         upstream: ProtocolId,
         participants: Control,
         context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), SimError> {
         let identifier = ListenId {
-            port: LocalPort::try_from(&participants).unwrap(),
-            address: LocalAddress::try_from(&participants).unwrap(),
+            local_address: LocalAddress::try_from(&participants)
+                .ok()
+                .filter(|&address| address != Ipv4Address::CURRENT_NETWORK.into()),
+            local_port: LocalPort::try_from(&participants).unwrap(),
+            remote_address: RemoteAddress::try_from(&participants).ok(),
+            remote_port: RemotePort::try_from(&participants).ok(),
         };
-        self.listen_bindings.insert(identifier, upstream);
+        match self.listen_bindings.entry(identifier) {
+            Entry::Occupied(_) => Err(UdpError::BindingExists)?,
+            Entry::Vacant(entry) => {
+                entry.insert(upstream);
+            }
+        }
 
         context
             .protocol(Ipv4::ID)
             .expect("No such protocol")
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .listen(Self::ID, participants, context)
     }
 
-    fn demux(
-        &mut self,
-        message: Message,
-        context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    fn demux(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
         let local_address = LocalAddress::try_from(&context.info).unwrap();
         let remote_address = RemoteAddress::try_from(&context.info).unwrap();
         let header = UdpHeader::from_bytes_ipv4(
@@ -126,41 +180,195 @@ impl Protocol for Udp {
         local_port.apply(&mut context.info);
         remote_port.apply(&mut context.info);
         let message = message.slice(8..);
-        let mut session = match self.sessions.entry(session_id) {
-            Entry::Occupied(entry) => {
-                let session = entry.get().clone();
+        let mut session = match self.resolve(session_id) {
+            Some(Resolution::Session(session)) => session,
+            Some(Resolution::Listen(binding)) => {
+                let session = SharedSession::new(UdpSession {
+                    upstream: binding,
+                    downstream: context.current_session().expect("No current session"),
+                    identifier: session_id,
+                });
+                self.sessions.insert(session_id, session.clone());
+                context.session_opened(Self::ID, binding);
                 session
             }
-            Entry::Vacant(session_entry) => {
-                let listen_id = ListenId {
-                    address: local_address,
-                    port: local_port,
-                };
-                match self.listen_bindings.entry(listen_id) {
-                    Entry::Occupied(listen_entry) => {
-                        let session = SharedSession::new(UdpSession {
-                            upstream: *listen_entry.get(),
-                            downstream: context.current_session().expect("No current session"),
-                            identifier: session_id,
-                        });
-                        session_entry.insert(session.clone());
-                        session
-                    }
-                    Entry::Vacant(_) => Err(UdpError::MissingSession)?,
-                }
-            }
+            None => Err(UdpError::MissingSession)?,
         };
         session.receive(message, context)?;
         Ok(())
     }
 
-    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+    fn refused(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        // The message is one we sent, so its source is our local end
+        let local_address = LocalAddress::try_from(&context.info).unwrap();
+        let remote_address = RemoteAddress::try_from(&context.info).unwrap();
+        let header = UdpHeader::from_bytes_ipv4(
+            message.iter(),
+            local_address.into(),
+            remote_address.into(),
+        )?;
+        let local_port = LocalPort::new(header.source);
+        let remote_port = RemotePort::new(header.destination);
+        let session_id = SessionId {
+            local_address,
+            local_port,
+            remote_address,
+            remote_port,
+        };
+        local_port.apply(&mut context.info);
+        remote_port.apply(&mut context.info);
+        match self.sessions.get(&session_id) {
+            Some(session) => session.clone().refused(message.slice(8..), context),
+            None => Ok(()),
+        }
+    }
+
+    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
         Ok(ControlFlow::Continue)
     }
 }
 
+/// Where an incoming message should be delivered.
+enum Resolution {
+    /// To an existing session
+    Session(SharedSession),
+    /// To a new session for the upstream protocol that is listening
+    Listen(ProtocolId),
+}
+
+/// Identifies a listen binding. A field left as `None` matches any value.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct ListenId {
-    address: LocalAddress,
-    port: LocalPort,
+    local_address: Option<LocalAddress>,
+    local_port: LocalPort,
+    remote_address: Option<RemoteAddress>,
+    remote_port: Option<RemotePort>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Session;
+
+    const LOCAL: [u8; 4] = [10, 0, 0, 1];
+    const REMOTE: [u8; 4] = [10, 0, 0, 2];
+
+    struct NullSession;
+
+    impl Session for NullSession {
+        fn send(&mut self, _: Message, _: &mut ProtocolContext) -> Result<(), SimError> {
+            Ok(())
+        }
+
+        fn receive(&mut self, _: Message, _: &mut ProtocolContext) -> Result<(), SimError> {
+            Ok(())
+        }
+
+        fn awake(&mut self, _: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+            Ok(ControlFlow::Continue)
+        }
+    }
+
+    fn connection(local: [u8; 4], remote: [u8; 4], remote_port: u16) -> SessionId {
+        SessionId {
+            local_address: local.into(),
+            local_port: LocalPort::new(80),
+            remote_address: remote.into(),
+            remote_port: RemotePort::new(remote_port),
+        }
+    }
+
+    fn bind(
+        udp: &mut Udp,
+        local_address: Option<[u8; 4]>,
+        remote_address: Option<[u8; 4]>,
+        remote_port: Option<u16>,
+        upstream: u64,
+    ) {
+        let identifier = ListenId {
+            local_address: local_address.map(LocalAddress::from),
+            local_port: LocalPort::new(80),
+            remote_address: remote_address.map(RemoteAddress::from),
+            remote_port: remote_port.map(RemotePort::new),
+        };
+        udp.listen_bindings
+            .insert(identifier, ProtocolId::new(upstream));
+    }
+
+    fn listener(udp: &Udp, session_id: SessionId) -> Option<u64> {
+        match udp.resolve(session_id)? {
+            Resolution::Listen(upstream) => Some(upstream.into_inner()),
+            Resolution::Session(_) => None,
+        }
+    }
+
+    #[test]
+    fn session_beats_listen_bindings() {
+        let mut udp = Udp::new();
+        bind(&mut udp, Some(LOCAL), Some(REMOTE), Some(5000), 1);
+        let session_id = connection(LOCAL, REMOTE, 5000);
+        let session = SharedSession::new(UdpSession {
+            upstream: ProtocolId::new(2),
+            downstream: SharedSession::new(NullSession),
+            identifier: session_id,
+        });
+        udp.sessions.insert(session_id, session);
+        assert!(matches!(
+            udp.resolve(session_id),
+            Some(Resolution::Session(_))
+        ));
+        // The session covers only its own connection
+        assert!(udp.resolve(connection(LOCAL, REMOTE, 5001)).is_none());
+    }
+
+    #[test]
+    fn address_and_port_binding_beats_port_only() {
+        let mut udp = Udp::new();
+        bind(&mut udp, None, None, None, 1);
+        bind(&mut udp, Some(LOCAL), None, None, 2);
+        assert_eq!(listener(&udp, connection(LOCAL, REMOTE, 5000)), Some(2));
+        assert_eq!(
+            listener(&udp, connection([10, 0, 0, 9], REMOTE, 5000)),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn remote_restrictions_break_ties() {
+        let mut udp = Udp::new();
+        bind(&mut udp, Some(LOCAL), None, None, 1);
+        bind(&mut udp, Some(LOCAL), Some(REMOTE), None, 2);
+        bind(&mut udp, Some(LOCAL), Some(REMOTE), Some(5000), 3);
+        assert_eq!(listener(&udp, connection(LOCAL, REMOTE, 5000)), Some(3));
+        assert_eq!(listener(&udp, connection(LOCAL, REMOTE, 5001)), Some(2));
+        assert_eq!(
+            listener(&udp, connection(LOCAL, [10, 0, 0, 3], 5000)),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn exact_local_address_beats_remote_restrictions() {
+        let mut udp = Udp::new();
+        bind(&mut udp, None, Some(REMOTE), Some(5000), 1);
+        bind(&mut udp, Some(LOCAL), None, None, 2);
+        assert_eq!(listener(&udp, connection(LOCAL, REMOTE, 5000)), Some(2));
+        assert_eq!(
+            listener(&udp, connection([10, 0, 0, 9], REMOTE, 5000)),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn no_matching_binding() {
+        let mut udp = Udp::new();
+        assert!(udp.resolve(connection(LOCAL, REMOTE, 5000)).is_none());
+        bind(&mut udp, Some(LOCAL), Some(REMOTE), None, 1);
+        assert!(udp
+            .resolve(connection([10, 0, 0, 9], REMOTE, 5000))
+            .is_none());
+        assert!(udp
+            .resolve(connection(LOCAL, [10, 0, 0, 3], 5000))
+            .is_none());
+    }
 }
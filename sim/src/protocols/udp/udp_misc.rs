@@ -12,9 +12,11 @@ pub type RemotePort = ControlValue<REMOTE_PORT_KEY, u16>;
 from_impls!(RemotePort, u16);
 
 #[derive(Debug, ThisError)]
-pub(super) enum UdpError {
+pub enum UdpError {
     #[error("Tried to create an existing session")]
     SessionExists,
+    #[error("Tried to create an existing listen binding")]
+    BindingExists,
     #[error("Tried to demux with a missing session and no listen bindings")]
     MissingSession,
     #[error("Too few bytes to constitute a UDP header")]
@@ -1,7 +1,9 @@
 use super::udp_misc::UdpError;
-use crate::protocols::{ipv4::Ipv4Address, utility::Checksum};
+use crate::{core::Checksum, protocols::ipv4::Ipv4Address};
 
 const HEADER_OCTETS: u16 = 8;
+/// The IPv4 protocol number for UDP, used in the pseudo header
+const PROTOCOL_NUMBER: u8 = 17;
 
 pub(super) struct UdpHeader {
     pub source: u16,
@@ -32,21 +34,18 @@ impl UdpHeader {
 
         let length = u16::from_be_bytes([next()?, next()?]);
         checksum.add_u16(length);
-        // This is used a second time in the pseudo header
-        checksum.add_u16(length);
 
         let expected_checksum = u16::from_be_bytes([next()?, next()?]);
 
-        // Pseudo header parts
-        checksum.add_u32(source_address.into());
-        checksum.add_u32(destination_address.into());
-
-        // [zero, UDP protocol number] from pseudo header
-        checksum.add_u8(0, 17);
-
-        let bytes_consumed = next_padded(&mut bytes, &mut checksum) + 8;
+        checksum.add_ipv4_pseudo_header(
+            source_address.into(),
+            destination_address.into(),
+            PROTOCOL_NUMBER,
+            length,
+        );
 
-        if bytes_consumed != length || bytes.next().is_some() {
+        let payload_length = add_payload(bytes, &mut checksum);
+        if payload_length + HEADER_OCTETS as usize != length as usize {
             Err(UdpError::LengthMismatch)?
         }
 
@@ -72,24 +71,23 @@ pub(super) fn build_udp_header(
     source_port: u16,
     destination_address: Ipv4Address,
     destination_port: u16,
-    mut payload: impl Iterator<Item = u8>,
+    payload: impl Iterator<Item = u8>,
 ) -> Result<Vec<u8>, UdpError> {
     let mut checksum = Checksum::new();
-    let length = next_padded(&mut payload, &mut checksum);
-
-    let length = HEADER_OCTETS
-        .checked_add(length)
+    let length = u16::try_from(add_payload(payload, &mut checksum))
+        .ok()
+        .and_then(|length| HEADER_OCTETS.checked_add(length))
         .ok_or(UdpError::OverlyLongPayload)?;
 
-    // Once for the header, again for the pseudo header
-    checksum.add_u16(length);
-    checksum.add_u16(length);
-
-    checksum.add_u32(source_address.into());
-    checksum.add_u32(destination_address.into());
-    checksum.add_u8(0, 17);
     checksum.add_u16(source_port);
     checksum.add_u16(destination_port);
+    checksum.add_u16(length);
+    checksum.add_ipv4_pseudo_header(
+        source_address.into(),
+        destination_address.into(),
+        PROTOCOL_NUMBER,
+        length,
+    );
 
     let mut out = vec![];
     out.extend_from_slice(&source_port.to_be_bytes());
@@ -99,27 +97,44 @@ pub(super) fn build_udp_header(
     Ok(out)
 }
 
-fn next_padded(payload: &mut impl Iterator<Item = u8>, checksum: &mut Checksum) -> u16 {
+/// Adds the payload to the checksum and returns its length in bytes. The
+/// length is counted as a usize since nothing stops a payload from being
+/// longer than a UDP length can say.
+fn add_payload(payload: impl Iterator<Item = u8>, checksum: &mut Checksum) -> usize {
     let mut length = 0;
-    while let Some(first) = payload.next() {
-        let second = match payload.next() {
-            Some(second) => {
-                length += 2;
-                second
-            }
-            None => {
-                length += 1;
-                0
-            }
-        };
-        checksum.add_u8(first, second);
+    for byte in payload {
+        checksum.add_byte(byte);
+        length += 1;
     }
     length
 }
 
+/// Parses `bytes` as a UDP header and payload between `source` and
+/// `destination` and, if that succeeds, checks that building a header for the
+/// same ports and payload gives back the header it was parsed from. Panics if
+/// parsing panics or the round trip fails. Shared by the property tests and
+/// the fuzz targets.
+#[cfg(any(test, feature = "fuzzing"))]
+pub(crate) fn check_round_trip(bytes: &[u8], source: Ipv4Address, destination: Ipv4Address) {
+    let Ok(header) = UdpHeader::from_bytes_ipv4(bytes.iter().cloned(), source, destination) else {
+        return;
+    };
+    let header_octets = HEADER_OCTETS as usize;
+    let built = build_udp_header(
+        source,
+        header.source,
+        destination,
+        header.destination,
+        bytes[header_octets..].iter().cloned(),
+    )
+    .expect("A parsed header should build");
+    assert_eq!(built, bytes[..header_octets]);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::{collection::vec, prelude::*};
 
     const SOURCE_ADDRESS: [u8; 4] = [127, 0, 0, 1];
     const SOURCE_PORT: u16 = 12345;
@@ -192,4 +207,58 @@ mod tests {
         assert_eq!(actual, expected);
         Ok(())
     }
+
+    #[test]
+    fn rejects_payloads_too_long_for_the_length_field() {
+        let payload = vec![0u8; u16::MAX as usize];
+        let header = build_udp_header(
+            SOURCE_ADDRESS.into(),
+            SOURCE_PORT,
+            DESTINATION_ADDRESS.into(),
+            DESTINATION_PORT,
+            payload.iter().cloned(),
+        );
+        assert!(matches!(header, Err(UdpError::OverlyLongPayload)));
+
+        let segment = [&[0, 1, 0, 2, 0xff, 0xff, 0, 0][..], &payload].concat();
+        let parsed = UdpHeader::from_bytes_ipv4(
+            segment.into_iter(),
+            SOURCE_ADDRESS.into(),
+            DESTINATION_ADDRESS.into(),
+        );
+        assert!(matches!(parsed, Err(UdpError::LengthMismatch)));
+    }
+
+    proptest! {
+        #[test]
+        fn parses_what_it_builds(
+            source: [u8; 4],
+            destination: [u8; 4],
+            source_port: u16,
+            destination_port: u16,
+            payload in vec(any::<u8>(), 0..256),
+        ) {
+            let header = build_udp_header(
+                source.into(),
+                source_port,
+                destination.into(),
+                destination_port,
+                payload.iter().cloned(),
+            )
+            .unwrap();
+            let segment = [header, payload.clone()].concat();
+            let parsed =
+                UdpHeader::from_bytes_ipv4(segment.iter().cloned(), source.into(), destination.into())
+                    .unwrap();
+            prop_assert_eq!(parsed.source, source_port);
+            prop_assert_eq!(parsed.destination, destination_port);
+            prop_assert_eq!(parsed.length as usize, payload.len() + 8);
+            check_round_trip(&segment, source.into(), destination.into());
+        }
+
+        #[test]
+        fn survives_arbitrary_bytes(source: [u8; 4], destination: [u8; 4], bytes in vec(any::<u8>(), 0..64)) {
+            check_round_trip(&bytes, source.into(), destination.into());
+        }
+    }
 }
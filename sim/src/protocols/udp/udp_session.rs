@@ -3,10 +3,12 @@ use super::{
     udp_parsing::build_udp_header,
 };
 use crate::{
-    core::{message::Message, ControlFlow, ProtocolContext, ProtocolId, Session, SharedSession},
+    core::{
+        message::Message, ControlFlow, ProtocolContext, ProtocolId, Session, SharedSession,
+        SimError,
+    },
     protocols::ipv4::{LocalAddress, RemoteAddress},
 };
-use std::error::Error;
 
 pub(super) struct UdpSession {
     pub upstream: ProtocolId,
@@ -15,11 +17,7 @@ pub(super) struct UdpSession {
 }
 
 impl Session for UdpSession {
-    fn send(
-        &mut self,
-        message: Message,
-        context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    fn send(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
         let id = self.identifier;
         let header = build_udp_header(
             self.identifier.local_address.into(),
@@ -28,27 +26,33 @@ impl Session for UdpSession {
             id.remote_port.into(),
             message.iter(),
         )?;
-        let message = message.with_header(header);
+        let message = message.with_header(header).with_timestamp(context.round());
         self.downstream.send(message, context)?;
         Ok(())
     }
 
-    fn receive(
-        &mut self,
-        message: Message,
-        context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    fn receive(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
         context
             .protocol(self.upstream)
             .expect("No such protocol")
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .demux(message, context)?;
         Ok(())
     }
 
-    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+    fn awake(&mut self, _context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
         Ok(ControlFlow::Continue)
     }
+
+    fn refused(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        context
+            .protocol(self.upstream)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .refused(message, context)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
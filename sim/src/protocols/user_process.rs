@@ -3,8 +3,12 @@
 
 use crate::core::{
     message::Message, Control, ControlFlow, Protocol, ProtocolContext, ProtocolId, SharedSession,
+    SimError,
+};
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
 };
-use std::{cell::RefCell, error::Error, rc::Rc};
 
 /// A program being run in a [`UserProcess`].
 ///
@@ -12,7 +16,7 @@ use std::{cell::RefCell, error::Error, rc::Rc};
 /// [`Session`](crate::core::Session). It runs when messages come in over the
 /// network or when the containing machine awakens the
 /// application to give it time to run.
-pub trait Application {
+pub trait Application: Send {
     /// A unique identifier for the application.
     const ID: ProtocolId;
 
@@ -27,6 +31,17 @@ pub trait Application {
         message: Message,
         context: &mut ProtocolContext,
     ) -> Result<(), Box<dyn Error>>;
+
+    /// Called when a message the application sent was refused by its
+    /// destination, such as for a port nobody there listens on. The message
+    /// is the one the application sent. Refusals are ignored by default.
+    fn refused(
+        &mut self,
+        _message: Message,
+        _context: &mut ProtocolContext,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
 }
 
 /// A user-level process that sits at the top of the networking stack.
@@ -49,8 +64,8 @@ impl<A: Application> UserProcess<A> {
 
     /// Creates a new user process running the given application behind a shared
     /// handle.
-    pub fn new_shared(application: A) -> Rc<RefCell<Self>> {
-        Rc::new(RefCell::new(Self::new(application)))
+    pub fn new_shared(application: A) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::new(application)))
     }
 
     /// Gets the application the user process is running.
@@ -69,7 +84,7 @@ impl<A: Application> Protocol for UserProcess<A> {
         _upstream: ProtocolId,
         _participants: Control,
         _context: &mut ProtocolContext,
-    ) -> Result<SharedSession, Box<dyn Error>> {
+    ) -> Result<SharedSession, SimError> {
         panic!("Cannot active open on a user process")
     }
 
@@ -78,19 +93,19 @@ impl<A: Application> Protocol for UserProcess<A> {
         _upstream: ProtocolId,
         _participants: Control,
         _context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), SimError> {
         panic!("Cannot listen on a user process")
     }
 
-    fn demux(
-        &mut self,
-        message: Message,
-        context: &mut ProtocolContext,
-    ) -> Result<(), Box<dyn Error>> {
-        self.application.recv(message, context)
+    fn demux(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        Ok(self.application.recv(message, context)?)
+    }
+
+    fn refused(&mut self, message: Message, context: &mut ProtocolContext) -> Result<(), SimError> {
+        Ok(self.application.refused(message, context)?)
     }
 
-    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
-        self.application.awake(context)
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, SimError> {
+        Ok(self.application.awake(context)?)
     }
 }
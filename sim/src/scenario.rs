@@ -0,0 +1,452 @@
+//! Declarative checks of how a simulation behaved.
+//!
+//! A [`Scenario`] is an [`Observer`] that records every message sent during a
+//! run. Afterwards, a test lists what it expected to see with [`Expect`] and
+//! checks the recording against it:
+//!
+//! ```
+//! # use elvis::{
+//! #     applications::{Capture, SendMessage},
+//! #     core::{Internet, SharedProtocol},
+//! #     protocols::{ipv4::Ipv4, udp::Udp},
+//! #     scenario::{Expect, Scenario},
+//! # };
+//! let scenario = Scenario::new_shared();
+//! let mut internet = Internet::new();
+//! internet.observe(scenario.clone());
+//! let network = internet.network(1500);
+//! internet.machine(
+//!     [Udp::new_shared() as SharedProtocol, Ipv4::new_shared(), SendMessage::new_shared("Hi")],
+//!     [network],
+//! );
+//! internet.machine(
+//!     [Udp::new_shared() as SharedProtocol, Ipv4::new_shared(), Capture::new_shared()],
+//!     [network],
+//! );
+//! internet.run();
+//!
+//! scenario
+//!     .lock()
+//!     .unwrap()
+//!     .check([
+//!         Expect::udp().from(0).to(1).destination_port(0xbeef).within(10),
+//!         Expect::mtu(1500),
+//!         Expect::udp().from(1).never(),
+//!     ])
+//!     .unwrap();
+//! ```
+//!
+//! Time is counted in rounds of the simulation, the only clock Elvis has.
+
+use crate::{
+    core::{message::Message, MachineId, Observer, PhysicalAddress, ProtocolId, Round, Sent},
+    protocols::{ipv4::Ipv4, ipv6::Ipv6, udp::Udp},
+};
+use std::{
+    fmt::{self, Display},
+    sync::{Arc, Mutex},
+};
+use thiserror::Error as ThisError;
+
+/// Bytes of the header the tap puts in front of each message
+const TAP_HEADER: usize = 8;
+/// Bytes of an IPv6 header without extension headers
+const IPV6_HEADER: usize = 40;
+
+/// What a message sent onto a network carried, as far as the protocols that
+/// come with Elvis can tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Packet {
+    /// The protocol the tap delivers the message to, such as IPv4
+    pub protocol: Option<ProtocolId>,
+    /// The protocol the IP packet carries, such as UDP
+    pub transport: Option<ProtocolId>,
+    /// The source and destination ports of a UDP datagram
+    pub ports: Option<(u16, u16)>,
+    /// Bytes of the message after the tap's header, or of the whole message
+    /// if it is too short to have one
+    pub length: usize,
+}
+
+impl Packet {
+    /// Reads the headers of a message as it went over the network.
+    pub fn decode(message: &Message) -> Self {
+        let bytes: Vec<u8> = message.iter().collect();
+        let mut packet = Self {
+            protocol: None,
+            transport: None,
+            ports: None,
+            length: bytes.len(),
+        };
+        let Some(header) = bytes.get(..TAP_HEADER) else {
+            return packet;
+        };
+        packet.length -= TAP_HEADER;
+        let protocol = ProtocolId::new(u64::from_be_bytes(header.try_into().unwrap()));
+        packet.protocol = Some(protocol);
+        let ip = &bytes[TAP_HEADER..];
+        // Where each IP version keeps the protocol it carries and how long
+        // its header is
+        let (transport, header_length) = match protocol {
+            Ipv4::ID => (ip.get(9), ip.first().map(|&b| (b & 0xf) as usize * 4)),
+            Ipv6::ID => (ip.get(6), Some(IPV6_HEADER)),
+            _ => return packet,
+        };
+        packet.transport = transport.map(|&n| ProtocolId::new(n.into()));
+        if packet.transport == Some(Udp::ID) {
+            let port = |at: usize| -> Option<u16> {
+                let at = header_length? + at;
+                Some(u16::from_be_bytes(ip.get(at..at + 2)?.try_into().ok()?))
+            };
+            packet.ports = port(0).zip(port(2));
+        }
+        packet
+    }
+}
+
+impl Display for Packet {
+    /// Names the protocols in the packet and gives its length, such as
+    /// `UDP over IPv4, 34 bytes`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let network = match self.protocol {
+            Some(Ipv4::ID) => "IPv4",
+            Some(Ipv6::ID) => "IPv6",
+            Some(other) => {
+                return write!(
+                    f,
+                    "Protocol {:#x}, {} bytes",
+                    other.into_inner(),
+                    self.length
+                )
+            }
+            None => return write!(f, "{} bytes", self.length),
+        };
+        match self.transport {
+            Some(Udp::ID) => write!(f, "UDP over {network}, {} bytes", self.length),
+            _ => write!(f, "{network}, {} bytes", self.length),
+        }
+    }
+}
+
+/// A message a [`Scenario`] saw being sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sending {
+    pub round: Round,
+    pub from: MachineId,
+    pub network: usize,
+    pub destination: PhysicalAddress,
+    pub packet: Packet,
+}
+
+/// Records the messages sent during a simulation so they can be checked
+/// against [`Expect`]ations.
+#[derive(Debug, Clone, Default)]
+pub struct Scenario {
+    sendings: Vec<Sending>,
+}
+
+impl Scenario {
+    /// Creates an empty recording.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates an empty recording behind a shared handle, ready to be passed
+    /// to [`Internet::observe`](crate::core::Internet::observe).
+    pub fn new_shared() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// The messages sent so far, in the order they were sent.
+    pub fn sendings(&self) -> &[Sending] {
+        &self.sendings
+    }
+
+    /// Checks every expectation and reports all of those that failed.
+    pub fn check(
+        &self,
+        expectations: impl IntoIterator<Item = Expect>,
+    ) -> Result<(), ScenarioError> {
+        let failures: Vec<_> = expectations
+            .into_iter()
+            .filter_map(|expect| {
+                let seen = self
+                    .sendings
+                    .iter()
+                    .filter(|sending| expect.matches(sending))
+                    .count();
+                (!expect.count.allows(seen)).then(|| format!("Expected {expect}, but saw {seen}"))
+            })
+            .collect();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(ScenarioError::Failed(failures))
+        }
+    }
+}
+
+impl Observer for Scenario {
+    fn message_sent(&mut self, event: &Sent) {
+        self.sendings.push(Sending {
+            round: event.round,
+            from: event.machine,
+            network: event.network,
+            destination: event.destination,
+            packet: Packet::decode(event.message),
+        });
+    }
+}
+
+/// How many matching messages an [`Expect`] allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Count {
+    AtLeast(usize),
+    AtMost(usize),
+    Exactly(usize),
+}
+
+impl Count {
+    fn allows(self, seen: usize) -> bool {
+        match self {
+            Self::AtLeast(n) => seen >= n,
+            Self::AtMost(n) => seen <= n,
+            Self::Exactly(n) => seen == n,
+        }
+    }
+}
+
+/// An expectation about the messages sent during a simulation.
+///
+/// An expectation picks out messages with filters such as
+/// [`from`](Self::from) and [`destination_port`](Self::destination_port), then
+/// says how many of them there should be. Without a count, at least one
+/// matching message is expected.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Expect {
+    transport: Option<ProtocolId>,
+    from: Option<MachineId>,
+    to: Option<MachineId>,
+    source_port: Option<u16>,
+    destination_port: Option<u16>,
+    longer_than: Option<usize>,
+    within: Option<Round>,
+    count: Count,
+}
+
+impl Expect {
+    /// Expects messages of any kind.
+    pub fn message() -> Self {
+        Self {
+            transport: None,
+            from: None,
+            to: None,
+            source_port: None,
+            destination_port: None,
+            longer_than: None,
+            within: None,
+            count: Count::AtLeast(1),
+        }
+    }
+
+    /// Expects UDP datagrams over either version of IP.
+    pub fn udp() -> Self {
+        Self::message().transport(Udp::ID)
+    }
+
+    /// Expects that no packet is longer than `mtu` bytes.
+    pub fn mtu(mtu: u32) -> Self {
+        Self::message().longer_than(mtu as usize).never()
+    }
+
+    /// Only matches packets carrying the given transport protocol.
+    pub fn transport(mut self, protocol: ProtocolId) -> Self {
+        self.transport = Some(protocol);
+        self
+    }
+
+    /// Only matches messages sent by the given machine.
+    pub fn from(mut self, machine: MachineId) -> Self {
+        self.from = Some(machine);
+        self
+    }
+
+    /// Only matches messages sent to the given machine, either directly or
+    /// by broadcast.
+    pub fn to(mut self, machine: MachineId) -> Self {
+        self.to = Some(machine);
+        self
+    }
+
+    /// Only matches datagrams from the given port.
+    pub fn source_port(mut self, port: u16) -> Self {
+        self.source_port = Some(port);
+        self
+    }
+
+    /// Only matches datagrams to the given port.
+    pub fn destination_port(mut self, port: u16) -> Self {
+        self.destination_port = Some(port);
+        self
+    }
+
+    /// Only matches packets longer than `length` bytes.
+    pub fn longer_than(mut self, length: usize) -> Self {
+        self.longer_than = Some(length);
+        self
+    }
+
+    /// Only matches messages sent in the first `rounds` rounds.
+    pub fn within(mut self, rounds: Round) -> Self {
+        self.within = Some(rounds);
+        self
+    }
+
+    /// Expects at least `n` matching messages.
+    pub fn at_least(mut self, n: usize) -> Self {
+        self.count = Count::AtLeast(n);
+        self
+    }
+
+    /// Expects at most `n` matching messages. Useful to bound retransmissions.
+    pub fn at_most(mut self, n: usize) -> Self {
+        self.count = Count::AtMost(n);
+        self
+    }
+
+    /// Expects exactly `n` matching messages.
+    pub fn exactly(mut self, n: usize) -> Self {
+        self.count = Count::Exactly(n);
+        self
+    }
+
+    /// Expects no matching messages.
+    pub fn never(self) -> Self {
+        self.exactly(0)
+    }
+
+    fn matches(&self, sending: &Sending) -> bool {
+        let packet = &sending.packet;
+        let ports = packet.ports;
+        self.transport.is_none_or(|t| packet.transport == Some(t))
+            && self.from.is_none_or(|from| sending.from == from)
+            && self.to.is_none_or(|to| {
+                sending.destination.is_broadcast()
+                    || sending.destination == PhysicalAddress::for_machine(to)
+            })
+            && self
+                .source_port
+                .is_none_or(|port| ports.map(|(source, _)| source) == Some(port))
+            && self
+                .destination_port
+                .is_none_or(|port| ports.map(|(_, destination)| destination) == Some(port))
+            && self.longer_than.is_none_or(|length| packet.length > length)
+            && self.within.is_none_or(|rounds| sending.round < rounds)
+    }
+}
+
+impl Display for Expect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.count {
+            Count::AtLeast(n) => write!(f, "at least {n}")?,
+            Count::AtMost(n) => write!(f, "at most {n}")?,
+            Count::Exactly(n) => write!(f, "exactly {n}")?,
+        }
+        match self.transport {
+            Some(Udp::ID) => write!(f, " UDP datagrams")?,
+            Some(other) => write!(f, " packets of protocol {:#x}", other.into_inner())?,
+            None => write!(f, " messages")?,
+        }
+        if let Some(from) = self.from {
+            write!(f, " from machine {from}")?;
+        }
+        if let Some(to) = self.to {
+            write!(f, " to machine {to}")?;
+        }
+        if let Some(port) = self.source_port {
+            write!(f, " from port {port}")?;
+        }
+        if let Some(port) = self.destination_port {
+            write!(f, " to port {port}")?;
+        }
+        if let Some(length) = self.longer_than {
+            write!(f, " longer than {length} bytes")?;
+        }
+        if let Some(rounds) = self.within {
+            write!(f, " within {rounds} rounds")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum ScenarioError {
+    #[error("{} expectations failed:\n{}", .0.len(), .0.join("\n"))]
+    Failed(Vec<String>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_udp_over_ip() {
+        let datagram = Message::new(b"Hi")
+            .with_header(&[0xbe, 0xef, 0x00, 0x35, 0, 10, 0, 0])
+            .with_header(&{
+                let mut header = [0u8; 20];
+                header[0] = 0x45;
+                header[9] = 17;
+                header
+            })
+            .with_header(&Ipv4::ID.into_inner().to_be_bytes());
+        let packet = Packet::decode(&datagram);
+        assert_eq!(packet.protocol, Some(Ipv4::ID));
+        assert_eq!(packet.transport, Some(Udp::ID));
+        assert_eq!(packet.ports, Some((0xbeef, 53)));
+        assert_eq!(packet.length, 30);
+        assert_eq!(packet.to_string(), "UDP over IPv4, 30 bytes");
+        assert_eq!(
+            Packet::decode(&Message::new(b"tiny")).to_string(),
+            "4 bytes"
+        );
+    }
+
+    #[test]
+    fn reports_failed_expectations() {
+        let mut scenario = Scenario::new();
+        scenario.sendings.push(Sending {
+            round: 3,
+            from: 0,
+            network: 0,
+            destination: PhysicalAddress::BROADCAST,
+            packet: Packet {
+                protocol: Some(Ipv4::ID),
+                transport: Some(Udp::ID),
+                ports: Some((1000, 53)),
+                length: 40,
+            },
+        });
+        assert!(scenario
+            .check([
+                Expect::udp().from(0).to(1).destination_port(53).within(5),
+                Expect::mtu(40),
+                Expect::message().from(1).never(),
+            ])
+            .is_ok());
+        let Err(ScenarioError::Failed(failures)) = scenario.check([
+            Expect::udp().within(3),
+            Expect::mtu(39),
+            Expect::udp().source_port(1000).at_most(1),
+        ]) else {
+            panic!("Expected the check to fail");
+        };
+        assert_eq!(
+            failures,
+            [
+                "Expected at least 1 UDP datagrams within 3 rounds, but saw 0",
+                "Expected exactly 0 messages longer than 39 bytes, but saw 1",
+            ]
+        );
+    }
+}
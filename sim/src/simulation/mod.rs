@@ -2,9 +2,14 @@
 /// the default simulation, which creates a UDP sender and a UDP receiver. The
 /// sender sends one string to the receiver, and the contents are checked.
 use crate::{
-    applications::{Capture, SendMessage},
-    core::{message::Message, Internet, RcProtocol},
-    protocols::{ipv4::Ipv4, udp::Udp},
+    applications::{Capture, Count, SendMessage},
+    core::{message::Message, Internet, SharedProtocol},
+    protocols::{ipv4::Ipv4, udp::Udp, user_process::UserProcess},
+};
+
+mod scenario_file;
+pub use scenario_file::{
+    AppSpec, MachineSpec, NetworkSpec, Report, ScenarioFile, ScenarioFileError,
 };
 
 pub async fn default_simulation() {
@@ -13,7 +18,7 @@ pub async fn default_simulation() {
 
     internet.machine(
         [
-            Udp::new_shared() as RcProtocol,
+            Udp::new_shared() as SharedProtocol,
             Ipv4::new_shared(),
             SendMessage::new_shared("Hello!"),
         ],
@@ -23,7 +28,7 @@ pub async fn default_simulation() {
     let capture = Capture::new_shared();
     internet.machine(
         [
-            Udp::new_shared() as RcProtocol,
+            Udp::new_shared() as SharedProtocol,
             Ipv4::new_shared(),
             capture.clone(),
         ],
@@ -32,7 +37,37 @@ pub async fn default_simulation() {
 
     internet.run();
     assert_eq!(
-        capture.borrow().application().message().unwrap(),
+        capture.lock().unwrap().application().message().unwrap(),
         Message::new("Hello!")
     );
 }
+
+/// Sends `messages` copies of a message from one machine to another over UDP
+/// and returns how many arrived. Used to measure the core message path.
+pub fn flood_simulation(messages: u32) -> u32 {
+    let mut internet = Internet::new();
+    let network = internet.network(1500);
+
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            UserProcess::new_shared(SendMessage::new("Hello!").with_count(messages)),
+        ],
+        [network],
+    );
+
+    let count = Count::new_shared(messages);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            count.clone(),
+        ],
+        [network],
+    );
+
+    internet.run();
+    let received = count.lock().unwrap().application().received();
+    received
+}
@@ -0,0 +1,592 @@
+//! Simulations described in a file instead of in Rust.
+//!
+//! A scenario file lays out networks and the machines on them, one per line,
+//! along with how long to run and the seed for random choices:
+//!
+//! ```text
+//! # Ten messages from one machine to another over a small queue
+//! seed 7
+//! rounds 50
+//! network lan mtu 1500 queue drop-tail 4
+//! machine sender on lan run send "Hello!" count 10
+//! machine receiver on lan run count 10
+//! ```
+//!
+//! Networks take an optional `mtu` (1500 by default), `queue` (`unbounded`,
+//! `drop-tail <capacity>`, or `red <capacity> <min> <max>`), `discipline`
+//! (`fifo`, `priority`, or `fair`), `reorder <fraction> <max delay>` and
+//! `duplicate <fraction>`. Machines run UDP over IPv4 and one application:
+//! `send "<text>" [count <n>]`, `capture`, or `count <n>`. A machine on several
+//! networks lists them separated by commas.
+//!
+//! A scenario runs as fast as it can unless it has a `pace <milliseconds per
+//! round> [speedup <factor>]` line, which plays the rounds back against the
+//! wall clock so the run can be watched live.
+//!
+//! Running a scenario gives a log of every message sent, received, and
+//! dropped, and statistics for the run, both as JSON.
+
+use crate::{
+    applications::{Capture, Count, SendMessage},
+    core::{
+        message::Message, DropReason, Dropped, Impairments, Internet, MachineId, Observer, Pacing,
+        QueueDiscipline, QueueLimit, Received, Red, Reordering, Round, Sent, SharedProtocol,
+    },
+    protocols::{ipv4::Ipv4, udp::Udp, user_process::UserProcess},
+    timeline::write_json_string,
+};
+use std::{
+    fmt::Write,
+    fs, io,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use thiserror::Error as ThisError;
+
+/// How many rounds a scenario runs for when its file doesn't say.
+const DEFAULT_ROUNDS: Round = 1000;
+
+#[derive(Debug, ThisError)]
+pub enum ScenarioFileError {
+    #[error("Line {line}: {message}")]
+    Syntax { line: usize, message: String },
+    #[error("Could not read the scenario file: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// A network in a scenario file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkSpec {
+    pub name: String,
+    pub mtu: u32,
+    pub limit: QueueLimit,
+    pub discipline: QueueDiscipline,
+    pub impairments: Impairments,
+}
+
+/// The application a machine in a scenario file runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppSpec {
+    /// [`SendMessage`] with the text and count
+    Send { text: String, count: u32 },
+    /// [`Capture`]
+    Capture,
+    /// [`Count`] waiting for this many messages
+    Count(u32),
+}
+
+/// A machine in a scenario file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineSpec {
+    pub name: String,
+    /// Indices into [`ScenarioFile::networks`]
+    pub networks: Vec<usize>,
+    pub app: AppSpec,
+}
+
+/// A parsed scenario file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScenarioFile {
+    pub seed: Option<u64>,
+    /// The most rounds the simulation runs for
+    pub rounds: Round,
+    /// How the rounds line up with the wall clock
+    pub pacing: Pacing,
+    pub networks: Vec<NetworkSpec>,
+    pub machines: Vec<MachineSpec>,
+}
+
+/// The results of running a scenario file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// One JSON object per line for each message sent, received, or dropped
+    pub events: String,
+    /// A JSON object with the totals of the run, per network and per machine
+    pub stats: String,
+}
+
+impl ScenarioFile {
+    /// Reads and parses the scenario file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ScenarioFileError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses the text of a scenario file.
+    pub fn parse(text: &str) -> Result<Self, ScenarioFileError> {
+        let mut scenario = Self {
+            seed: None,
+            rounds: DEFAULT_ROUNDS,
+            pacing: Pacing::Unpaced,
+            networks: vec![],
+            machines: vec![],
+        };
+        for (i, line) in text.lines().enumerate() {
+            let syntax = |message: String| ScenarioFileError::Syntax {
+                line: i + 1,
+                message,
+            };
+            let words = split_words(line).map_err(syntax)?;
+            let mut words = Words(words.iter().map(String::as_str).collect(), 0);
+            match words.next() {
+                None => {}
+                Some("seed") => scenario.seed = Some(words.number("seed").map_err(syntax)?),
+                Some("rounds") => scenario.rounds = words.number("rounds").map_err(syntax)?,
+                Some("pace") => scenario.pacing = parse_pacing(&mut words).map_err(syntax)?,
+                Some("network") => {
+                    let network = scenario.parse_network(&mut words).map_err(syntax)?;
+                    scenario.networks.push(network);
+                }
+                Some("machine") => {
+                    let machine = scenario.parse_machine(&mut words).map_err(syntax)?;
+                    scenario.machines.push(machine);
+                }
+                Some(other) => Err(syntax(format!("Unknown statement `{other}`")))?,
+            }
+            if let Some(extra) = words.next() {
+                Err(syntax(format!("Unexpected `{extra}`")))?
+            }
+        }
+        Ok(scenario)
+    }
+
+    fn parse_network(&self, words: &mut Words) -> Result<NetworkSpec, String> {
+        let name = words.name("network")?;
+        if self.networks.iter().any(|network| network.name == name) {
+            Err(format!("Network `{name}` is declared twice"))?
+        }
+        let mut network = NetworkSpec {
+            name,
+            mtu: 1500,
+            limit: QueueLimit::Unbounded,
+            discipline: QueueDiscipline::Fifo,
+            impairments: Impairments::default(),
+        };
+        while let Some(option) = words.next() {
+            match option {
+                "mtu" => network.mtu = words.number("mtu")?,
+                "queue" => {
+                    network.limit = match words.next() {
+                        Some("unbounded") => QueueLimit::Unbounded,
+                        Some("drop-tail") => QueueLimit::DropTail(words.number("capacity")?),
+                        Some("red") => QueueLimit::Red(Red::new(
+                            words.number("capacity")?,
+                            words.number("minimum threshold")?,
+                            words.number("maximum threshold")?,
+                        )),
+                        _ => Err("Expected a queue of unbounded, drop-tail, or red")?,
+                    }
+                }
+                "discipline" => {
+                    network.discipline = match words.next() {
+                        Some("fifo") => QueueDiscipline::Fifo,
+                        Some("priority") => QueueDiscipline::Priority,
+                        Some("fair") => QueueDiscipline::FairQueueing,
+                        _ => Err("Expected a discipline of fifo, priority, or fair")?,
+                    }
+                }
+                "reorder" => {
+                    network.impairments.reordering = Some(Reordering {
+                        fraction: words.fraction("reorder")?,
+                        max_delay: words.number("maximum delay")?,
+                    });
+                }
+                "duplicate" => network.impairments.duplication = words.fraction("duplicate")?,
+                other => Err(format!("Unknown network option `{other}`"))?,
+            }
+        }
+        Ok(network)
+    }
+
+    fn parse_machine(&self, words: &mut Words) -> Result<MachineSpec, String> {
+        let name = words.name("machine")?;
+        if self.machines.iter().any(|machine| machine.name == name) {
+            Err(format!("Machine `{name}` is declared twice"))?
+        }
+        words.expect("on")?;
+        let networks = words
+            .name("networks")?
+            .split(',')
+            .map(|network| {
+                self.networks
+                    .iter()
+                    .position(|declared| declared.name == network)
+                    .ok_or_else(|| format!("No network named `{network}`"))
+            })
+            .collect::<Result<_, _>>()?;
+        words.expect("run")?;
+        let app = match words.next() {
+            Some("send") => {
+                let text = words.name("text to send")?;
+                let count = match words.peek() {
+                    Some("count") => {
+                        words.next();
+                        words.number("count")?
+                    }
+                    _ => 1,
+                };
+                AppSpec::Send { text, count }
+            }
+            Some("capture") => AppSpec::Capture,
+            Some("count") => AppSpec::Count(words.number("count")?),
+            _ => Err("Expected an application of send, capture, or count")?,
+        };
+        Ok(MachineSpec {
+            name,
+            networks,
+            app,
+        })
+    }
+
+    /// Runs the scenario until a machine ends it or the round limit is
+    /// reached.
+    pub fn run(&self) -> Report {
+        let log = Arc::new(Mutex::new(EventLog {
+            machines: self.machines.iter().map(|m| m.name.clone()).collect(),
+            networks: self.networks.iter().map(|n| n.name.clone()).collect(),
+            events: String::new(),
+            totals: vec![Totals::default(); self.machines.len()],
+        }));
+        let mut internet = Internet::new();
+        internet.observe(log.clone());
+        if let Some(seed) = self.seed {
+            internet.seed(seed);
+        }
+        internet.limit_rounds(self.rounds);
+        internet
+            .set_pacing(self.pacing)
+            .expect("Pacing is checked when parsing");
+        for spec in &self.networks {
+            let network = internet.network_with_discipline(spec.mtu, spec.discipline);
+            internet.set_queue_limit(network, spec.limit);
+            internet.set_impairments(network, spec.impairments);
+        }
+        for machine in &self.machines {
+            let app: SharedProtocol = match &machine.app {
+                AppSpec::Send { text, count } => {
+                    UserProcess::new_shared(SendMessage::new(text).with_count(*count))
+                }
+                AppSpec::Capture => Capture::new_shared(),
+                AppSpec::Count(expected) => Count::new_shared(*expected),
+            };
+            internet.machine(
+                [Udp::new_shared() as SharedProtocol, Ipv4::new_shared(), app],
+                machine.networks.iter().copied(),
+            );
+        }
+        internet.run();
+
+        let log = log.lock().unwrap();
+        let mut stats = format!(r#"{{"rounds":{},"networks":["#, internet.rounds());
+        for (i, name) in log.networks.iter().enumerate() {
+            let network = internet.network_stats(i);
+            if i > 0 {
+                stats.push(',');
+            }
+            stats.push_str(r#"{"name":"#);
+            write_json_string(&mut stats, name);
+            write!(
+                stats,
+                r#","queued":{},"tail_dropped":{},"early_dropped":{},"reordered":{},"duplicated":{}}}"#,
+                network.queued,
+                network.tail_dropped,
+                network.early_dropped,
+                network.reordered,
+                network.duplicated
+            )
+            .unwrap();
+        }
+        stats.push_str(r#"],"machines":["#);
+        for (i, (name, totals)) in log.machines.iter().zip(&log.totals).enumerate() {
+            if i > 0 {
+                stats.push(',');
+            }
+            stats.push_str(r#"{"name":"#);
+            write_json_string(&mut stats, name);
+            write!(
+                stats,
+                r#","sent":{},"received":{},"dropped":{}}}"#,
+                totals.sent, totals.received, totals.dropped
+            )
+            .unwrap();
+        }
+        stats.push_str("]}");
+        Report {
+            events: log.events.clone(),
+            stats,
+        }
+    }
+}
+
+/// The messages each machine sent, received, and lost.
+#[derive(Debug, Clone, Copy, Default)]
+struct Totals {
+    sent: u64,
+    received: u64,
+    /// Messages sent by this machine that were lost
+    dropped: u64,
+}
+
+/// Writes each message event as a line of JSON.
+struct EventLog {
+    machines: Vec<String>,
+    networks: Vec<String>,
+    events: String,
+    totals: Vec<Totals>,
+}
+
+impl EventLog {
+    /// Starts the line for an event, up to and including the machine.
+    fn start(&mut self, round: Round, event: &str, machine: Option<MachineId>) {
+        write!(
+            self.events,
+            r#"{{"round":{round},"event":"{event}","machine":"#
+        )
+        .unwrap();
+        self.name(machine.map(|machine| self.machines[machine].clone()));
+    }
+
+    fn field(&mut self, key: &str, value: Option<String>) {
+        write!(self.events, r#","{key}":"#).unwrap();
+        self.name(value);
+    }
+
+    fn name(&mut self, name: Option<String>) {
+        match name {
+            Some(name) => write_json_string(&mut self.events, &name),
+            None => self.events.push_str("null"),
+        }
+    }
+
+    fn end(&mut self, message: &Message) {
+        writeln!(self.events, r#","bytes":{}}}"#, message.iter().count()).unwrap();
+    }
+}
+
+impl Observer for EventLog {
+    fn message_sent(&mut self, event: &Sent) {
+        self.totals[event.machine].sent += 1;
+        self.start(event.round, "sent", Some(event.machine));
+        self.field("network", Some(self.networks[event.network].clone()));
+        self.end(event.message);
+    }
+
+    fn message_received(&mut self, event: &Received) {
+        self.totals[event.machine].received += 1;
+        self.start(event.round, "received", Some(event.machine));
+        self.field("from", Some(self.machines[event.source].clone()));
+        self.field("network", Some(self.networks[event.network].clone()));
+        write!(self.events, r#","sent":{}"#, event.sent).unwrap();
+        self.end(event.message);
+    }
+
+    fn message_dropped(&mut self, event: &Dropped) {
+        self.totals[event.source].dropped += 1;
+        self.start(event.round, "dropped", event.machine);
+        self.field("from", Some(self.machines[event.source].clone()));
+        let network = event.network.map(|network| self.networks[network].clone());
+        self.field("network", network);
+        let reason = match event.reason {
+            DropReason::QueueFull => "queue full".to_string(),
+            DropReason::EarlyDrop => "early drop".to_string(),
+            DropReason::NoSuchAddress => "no such address".to_string(),
+            DropReason::Rejected(e) => e.to_string(),
+        };
+        self.field("reason", Some(reason));
+        self.end(event.message);
+    }
+}
+
+fn parse_pacing(words: &mut Words) -> Result<Pacing, String> {
+    let millis: u64 = words.number("milliseconds per round")?;
+    let mut speedup = 1.0;
+    if words.peek() == Some("speedup") {
+        words.next();
+        speedup = words.number("speedup")?;
+    }
+    let pacing = Pacing::RealTime {
+        round: Duration::from_millis(millis),
+        speedup,
+    };
+    pacing.interval().map_err(|e| e.to_string())?;
+    Ok(pacing)
+}
+
+/// The words of a line, in order, with a cursor.
+struct Words<'a>(Vec<&'a str>, usize);
+
+impl<'a> Words<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let word = self.peek()?;
+        self.1 += 1;
+        Some(word)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.0.get(self.1).copied()
+    }
+
+    fn name(&mut self, what: &str) -> Result<String, String> {
+        self.next()
+            .map(str::to_string)
+            .ok_or_else(|| format!("Expected a {what}"))
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, what: &str) -> Result<T, String> {
+        let word = self.next().ok_or_else(|| format!("Expected a {what}"))?;
+        word.parse()
+            .map_err(|_| format!("Expected a {what}, found `{word}`"))
+    }
+
+    /// A share of messages, from 0 to 1, for the option `what`.
+    fn fraction(&mut self, what: &str) -> Result<f64, String> {
+        let fraction: f64 = self.number(&format!("fraction to {what}"))?;
+        if !(0.0..=1.0).contains(&fraction) {
+            Err(format!(
+                "Expected a fraction to {what} from 0 to 1, found `{fraction}`"
+            ))?
+        }
+        Ok(fraction)
+    }
+
+    fn expect(&mut self, keyword: &str) -> Result<(), String> {
+        match self.next() {
+            Some(word) if word == keyword => Ok(()),
+            Some(word) => Err(format!("Expected `{keyword}`, found `{word}`")),
+            None => Err(format!("Expected `{keyword}`")),
+        }
+    }
+}
+
+/// Splits a line into words at whitespace, keeping double-quoted text
+/// together and leaving off a trailing comment.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut chars = line.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut word = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => word.extend(chars.next()),
+                    Some(c) => word.push(c),
+                    None => Err("Unterminated quote")?,
+                }
+            }
+            words.push(word);
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek().filter(|c| !c.is_whitespace()) {
+                word.push(c);
+                chars.next();
+            }
+            words.push(word);
+        }
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_scenario() {
+        let scenario = ScenarioFile::parse(
+            r#"
+            # comment
+            seed 7
+            rounds 20
+            pace 100 speedup 4
+            network lan mtu 1400 queue red 10 2 8 discipline fair
+            network wan reorder 0.25 4 duplicate 0.1
+            machine a on lan,wan run send "Hi there" count 3  # trailing comment
+            machine b on wan run count 3
+            "#,
+        )
+        .unwrap();
+        assert_eq!(scenario.seed, Some(7));
+        assert_eq!(scenario.rounds, 20);
+        assert_eq!(
+            scenario.pacing,
+            Pacing::RealTime {
+                round: Duration::from_millis(100),
+                speedup: 4.0
+            }
+        );
+        assert_eq!(scenario.networks[0].mtu, 1400);
+        assert_eq!(
+            scenario.networks[0].limit,
+            QueueLimit::Red(Red::new(10, 2, 8))
+        );
+        assert_eq!(
+            scenario.networks[0].discipline,
+            QueueDiscipline::FairQueueing
+        );
+        assert_eq!(scenario.networks[1].limit, QueueLimit::Unbounded);
+        assert_eq!(scenario.networks[0].impairments, Impairments::default());
+        assert_eq!(
+            scenario.networks[1].impairments,
+            Impairments {
+                reordering: Some(Reordering {
+                    fraction: 0.25,
+                    max_delay: 4
+                }),
+                duplication: 0.1,
+            }
+        );
+        assert_eq!(
+            scenario.machines[0],
+            MachineSpec {
+                name: "a".to_string(),
+                networks: vec![0, 1],
+                app: AppSpec::Send {
+                    text: "Hi there".to_string(),
+                    count: 3
+                },
+            }
+        );
+        assert_eq!(scenario.machines[1].app, AppSpec::Count(3));
+    }
+
+    #[test]
+    fn reports_the_line_of_an_error() {
+        let error = |text| ScenarioFile::parse(text).unwrap_err().to_string();
+        assert_eq!(
+            error("network lan\nmachine a on wan run capture"),
+            "Line 2: No network named `wan`"
+        );
+        assert_eq!(
+            error("rounds many"),
+            "Line 1: Expected a rounds, found `many`"
+        );
+        assert_eq!(error("machine a on"), "Line 1: Expected a networks");
+        assert_eq!(
+            error("network lan mtu 1500 extra"),
+            "Line 1: Unknown network option `extra`"
+        );
+        assert_eq!(error("seed 1 2"), "Line 1: Unexpected `2`");
+        assert_eq!(
+            error("pace 100 speedup 0"),
+            "Line 1: Expected a positive speedup, found `0`"
+        );
+        assert_eq!(
+            error("network lan reorder 1.5 2"),
+            "Line 1: Expected a fraction to reorder from 0 to 1, found `1.5`"
+        );
+        assert_eq!(
+            error("network lan duplicate -0.1"),
+            "Line 1: Expected a fraction to duplicate from 0 to 1, found `-0.1`"
+        );
+        assert_eq!(
+            error(r#"machine a on lan run send "oops"#),
+            "Line 1: Unterminated quote"
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Timelines of the messages machines exchange during a simulation.
+//!
+//! A [`Timeline`] is an [`Observer`] that records every message one machine
+//! sends another, keyed by the rounds it was sent and received in. Once the
+//! simulation has run, the timeline can be written out as a [Mermaid] sequence
+//! diagram, to show students the back and forth of a protocol, or as JSON for
+//! other tools to draw.
+//!
+//! [Mermaid]: https://mermaid.js.org/syntax/sequenceDiagram.html
+
+use crate::{
+    core::{message::Message, DropReason, Dropped, MachineId, Observer, Received, Round},
+    scenario::Packet,
+};
+use std::{
+    collections::BTreeSet,
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+/// Describes a message for a timeline.
+pub type Labeler = Box<dyn Fn(&Message) -> String + Send>;
+
+/// What became of a message in a [`Timeline`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// The receiving machine handled the message
+    Delivered,
+    /// The network dropped the message before it arrived
+    Lost,
+    /// The message arrived but the receiving machine's protocols failed to
+    /// handle it
+    Rejected,
+}
+
+impl Outcome {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Delivered => "delivered",
+            Self::Lost => "lost",
+            Self::Rejected => "rejected",
+        }
+    }
+}
+
+/// One message from one machine to another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    pub from: MachineId,
+    pub to: MachineId,
+    /// The index of the network the message went over
+    pub network: usize,
+    /// The round the message was sent in
+    pub sent: Round,
+    /// The round the message was received in, unless it was lost
+    pub received: Option<Round>,
+    pub outcome: Outcome,
+    pub label: String,
+}
+
+/// Records the messages machines exchange.
+///
+/// A broadcast message makes an exchange with every machine that received
+/// it. Messages sent to an address no machine has are left out, since they
+/// have nowhere to go on a diagram.
+pub struct Timeline {
+    exchanges: Vec<Exchange>,
+    labeler: Labeler,
+}
+
+impl Timeline {
+    /// Creates an empty timeline that labels messages with their protocols
+    /// and lengths.
+    pub fn new() -> Self {
+        Self {
+            exchanges: vec![],
+            labeler: Box::new(|message| Packet::decode(message).to_string()),
+        }
+    }
+
+    /// Labels messages with `labeler` instead. It is given the message as it
+    /// went over the network, headers and all.
+    pub fn with_labeler(mut self, labeler: impl Fn(&Message) -> String + Send + 'static) -> Self {
+        self.labeler = Box::new(labeler);
+        self
+    }
+
+    /// Creates an empty timeline behind a shared handle, ready to be passed to
+    /// [`Internet::observe`](crate::core::Internet::observe).
+    pub fn new_shared() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// The exchanges so far, ordered by the round they were sent in, then
+    /// the round they were received in, then sender and receiver.
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        let mut exchanges = self.exchanges.clone();
+        // The sort is stable, so each machine's messages keep their order
+        exchanges.sort_by_key(|exchange| {
+            (
+                exchange.sent,
+                exchange.received.unwrap_or(exchange.sent),
+                exchange.from,
+                exchange.to,
+            )
+        });
+        exchanges
+    }
+
+    /// The timeline as a Mermaid sequence diagram with a participant for each
+    /// machine. A note marks the start of each round in which messages were
+    /// sent.
+    pub fn to_mermaid(&self) -> String {
+        let exchanges = self.exchanges();
+        let machines: BTreeSet<_> = exchanges
+            .iter()
+            .flat_map(|exchange| [exchange.from, exchange.to])
+            .collect();
+        let mut out = String::from("sequenceDiagram\n");
+        for machine in &machines {
+            writeln!(out, "    participant M{machine} as Machine {machine}").unwrap();
+        }
+        let span = match (machines.first(), machines.last()) {
+            (Some(first), Some(last)) if first != last => format!("M{first},M{last}"),
+            (Some(only), _) => format!("M{only}"),
+            _ => return out,
+        };
+        let mut round = None;
+        for exchange in &exchanges {
+            if round != Some(exchange.sent) {
+                round = Some(exchange.sent);
+                writeln!(out, "    Note over {span}: Round {}", exchange.sent).unwrap();
+            }
+            let arrow = match exchange.outcome {
+                Outcome::Delivered => "->>",
+                Outcome::Lost | Outcome::Rejected => "-x",
+            };
+            let label = match exchange.outcome {
+                Outcome::Delivered => exchange.label.clone(),
+                outcome => format!("{} ({})", exchange.label, outcome.name()),
+            };
+            writeln!(
+                out,
+                "    M{}{arrow}M{}: {}",
+                exchange.from,
+                exchange.to,
+                // Mermaid ends a message at a line break or semicolon
+                label.replace(['\n', ';'], " ")
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// The timeline as a JSON array of exchanges, each an object with the
+    /// fields of [`Exchange`]. Lost messages have a `received` of `null`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("[");
+        for (i, exchange) in self.exchanges().iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let received = exchange
+                .received
+                .map_or_else(|| "null".to_string(), |round| round.to_string());
+            write!(
+                out,
+                r#"{{"from":{},"to":{},"network":{},"sent":{},"received":{},"outcome":"{}","label":"#,
+                exchange.from,
+                exchange.to,
+                exchange.network,
+                exchange.sent,
+                received,
+                exchange.outcome.name(),
+            )
+            .unwrap();
+            write_json_string(&mut out, &exchange.label);
+            out.push('}');
+        }
+        out.push(']');
+        out
+    }
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer for Timeline {
+    fn message_received(&mut self, event: &Received) {
+        self.exchanges.push(Exchange {
+            from: event.source,
+            to: event.machine,
+            network: event.network,
+            sent: event.sent,
+            received: Some(event.round),
+            outcome: Outcome::Delivered,
+            label: (self.labeler)(event.message),
+        });
+    }
+
+    fn message_dropped(&mut self, event: &Dropped) {
+        let Some(to) = event.machine else {
+            return;
+        };
+        match event.reason {
+            DropReason::Rejected(_) => {
+                // The machine reports a message it could not handle right
+                // after receiving it
+                if let Some(exchange) =
+                    self.exchanges.iter_mut().rev().find(|exchange| {
+                        exchange.to == to && exchange.received == Some(event.round)
+                    })
+                {
+                    exchange.outcome = Outcome::Rejected;
+                }
+            }
+            _ => self.exchanges.push(Exchange {
+                from: event.source,
+                to,
+                network: event.network.unwrap_or_default(),
+                sent: event.round,
+                received: None,
+                outcome: Outcome::Lost,
+                label: (self.labeler)(event.message),
+            }),
+        }
+    }
+}
+
+/// Writes `text` as a quoted JSON string.
+pub(crate) fn write_json_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str(r#"\""#),
+            '\\' => out.push_str(r"\\"),
+            '\n' => out.push_str(r"\n"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -0,0 +1,64 @@
+use elvis::{
+    applications::{Count, SendMessage},
+    core::{Internet, Observer, Round, Sent, SharedProtocol},
+    protocols::{ipv4::Ipv4, udp::Udp, user_process::UserProcess},
+};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// Counts the messages sent in each round.
+#[derive(Default)]
+struct SentPerRound(BTreeMap<Round, u32>);
+
+impl Observer for SentPerRound {
+    fn message_sent(&mut self, event: &Sent) {
+        *self.0.entry(event.round).or_default() += 1;
+    }
+}
+
+/// Sends `count` messages through a send queue of the given capacity and
+/// returns how many arrived along with how many went out in each round.
+fn send_through_queue(count: u32, capacity: Option<u32>) -> (u32, Vec<u32>) {
+    let mut internet = Internet::new();
+    let sent = Arc::new(Mutex::new(SentPerRound::default()));
+    internet.observe(sent.clone());
+    let network = internet.network(1500);
+    let sender = SendMessage::new("Hello!").with_count(count);
+    let sender = match capacity {
+        Some(capacity) => sender.with_queue_capacity(capacity),
+        None => sender,
+    };
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            UserProcess::new_shared(sender),
+        ],
+        [network],
+    );
+    let receiver = Count::new_shared(count);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            receiver.clone(),
+        ],
+        [network],
+    );
+    internet.run();
+    let received = receiver.lock().unwrap().application().received();
+    let sent = sent.lock().unwrap().0.values().copied().collect();
+    (received, sent)
+}
+
+#[test]
+fn full_send_queues_hold_the_rest_back() {
+    assert_eq!(send_through_queue(20, Some(8)), (20, vec![8, 8, 4]));
+}
+
+#[test]
+fn send_queues_are_unbounded_by_default() {
+    assert_eq!(send_through_queue(20, None), (20, vec![20]));
+}
@@ -0,0 +1,124 @@
+use elvis::{
+    applications::{Count, SendMessage},
+    core::{
+        message::Message, Call, CallTrace, Control, ControlFlow, Internet, ProtocolContext,
+        ProtocolId, SharedProtocol,
+    },
+    protocols::{
+        ipv4::Ipv4,
+        tap::Tap,
+        udp::Udp,
+        user_process::{Application, UserProcess},
+    },
+};
+use std::error::Error;
+
+#[test]
+fn records_call_chains_down_the_stack() {
+    let trace = CallTrace::new_shared();
+    let mut internet = Internet::new();
+    internet.trace_calls(trace.clone());
+    let network = internet.network(1500);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            UserProcess::new_shared(SendMessage::new("Hello!")),
+        ],
+        [network],
+    );
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            Count::new_shared(1),
+        ],
+        [network],
+    );
+    internet.run();
+
+    let mut trace = trace.lock().unwrap();
+    trace.name_protocol(SendMessage::ID, "Sender");
+    trace.name_protocol(Count::ID, "Counter");
+    assert_eq!(trace.deadlocks().count(), 0);
+    let chain = |machine, call| -> Vec<_> {
+        trace
+            .calls()
+            .iter()
+            .filter(|record| record.machine == machine && record.call == call)
+            .map(|record| record.callee)
+            .collect()
+    };
+    assert_eq!(chain(0, Call::Open), [Udp::ID, Ipv4::ID, Tap::ID]);
+    assert_eq!(chain(1, Call::Listen), [Udp::ID, Ipv4::ID, Tap::ID]);
+    assert_eq!(chain(1, Call::Demux), [Ipv4::ID, Udp::ID, Count::ID]);
+
+    let opened = trace
+        .calls()
+        .iter()
+        .find(|record| record.call == Call::Open && record.callee == Tap::ID)
+        .unwrap();
+    assert_eq!(opened.caller, Some(Ipv4::ID));
+    assert_eq!(opened.held, [SendMessage::ID, Udp::ID, Ipv4::ID]);
+
+    let diagram = trace.to_mermaid();
+    assert!(diagram.starts_with("sequenceDiagram\n    participant Sender\n    participant UDP\n"));
+    assert!(diagram.contains("    Note over Sender,Machine: Round 0, machine 0\n"));
+    assert!(diagram.contains("    UDP->>IPv4: open\n"));
+}
+
+/// Listens through itself, which would deadlock on its own lock.
+struct Reentrant;
+
+impl Application for Reentrant {
+    const ID: ProtocolId = ProtocolId::from_string("Reentrant");
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        let id = context.current_protocol().unwrap();
+        context
+            .protocol(id)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .listen(id, Control::new(), context)?;
+        Ok(ControlFlow::Continue)
+    }
+
+    fn recv(&mut self, _: Message, _: &mut ProtocolContext) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+#[test]
+fn reports_calls_that_would_deadlock() {
+    let trace = CallTrace::new_shared();
+    let mut internet = Internet::new();
+    internet.trace_calls(trace.clone());
+    internet.limit_rounds(10);
+    let network = internet.network(1500);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            UserProcess::new_shared(Reentrant),
+        ],
+        [network],
+    );
+    internet.run();
+
+    // The failed call is fatal, so the simulation ends on the first round
+    assert_eq!(internet.rounds(), 1);
+    let mut trace = trace.lock().unwrap();
+    trace.name_protocol(Reentrant::ID, "Reentrant");
+    let deadlocks: Vec<_> = trace.deadlocks().collect();
+    assert_eq!(deadlocks.len(), 1);
+    assert_eq!(deadlocks[0].held, [Reentrant::ID]);
+    assert_eq!(
+        trace.report(),
+        "Round 0, machine 0: Reentrant called listen on Reentrant, which is already locked \
+         (locked in order: Reentrant)\n"
+    );
+    assert!(trace
+        .to_mermaid()
+        .contains("Reentrant-xReentrant: listen (deadlock)"));
+}
@@ -0,0 +1,70 @@
+use elvis::{
+    applications::{Chat, ChatStep, Speaker},
+    core::{Internet, SharedProtocol},
+    protocols::{ipv4::Ipv4, udp::Udp},
+};
+
+#[test]
+fn chat_follows_both_scripts() {
+    let alice = Chat::new_shared(
+        0xa11c,
+        0xb0b0,
+        [
+            ChatStep::Say("Hi Bob!"),
+            ChatStep::Await,
+            ChatStep::Say("Great, see you at the simulation."),
+        ],
+    );
+    let bob = Chat::new_shared(
+        0xb0b0,
+        0xa11c,
+        [
+            ChatStep::Await,
+            ChatStep::Say("Hey Alice, how is it going?"),
+            ChatStep::Await,
+        ],
+    );
+    let mut internet = Internet::new();
+    let network = internet.network(1500);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            alice.clone(),
+        ],
+        [network],
+    );
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            bob.clone(),
+        ],
+        [network],
+    );
+    internet.run();
+
+    let lines = [
+        "Hi Bob!",
+        "Hey Alice, how is it going?",
+        "Great, see you at the simulation.",
+    ];
+    let transcript = |speakers: [Speaker; 3]| -> Vec<(Speaker, String)> {
+        speakers
+            .into_iter()
+            .zip(lines)
+            .map(|(speaker, line)| (speaker, line.to_string()))
+            .collect()
+    };
+    let alice = alice.lock().unwrap();
+    let bob = bob.lock().unwrap();
+    assert_eq!(
+        alice.application().transcript(),
+        transcript([Speaker::Me, Speaker::Peer, Speaker::Me])
+    );
+    assert_eq!(
+        bob.application().transcript(),
+        transcript([Speaker::Peer, Speaker::Me, Speaker::Peer])
+    );
+    assert!(alice.application().is_over() || bob.application().is_over());
+}
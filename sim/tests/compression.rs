@@ -0,0 +1,68 @@
+use elvis::{
+    applications::{Capture, SendMessage},
+    core::{message::Message, Internet, Observer, Sent, SharedProtocol},
+    protocols::{compression::Compression, ipv4::Ipv4, udp::Udp, user_process::UserProcess},
+};
+use std::sync::{Arc, Mutex};
+
+/// Sends `text` through a compression layer with the given threshold on the
+/// sender, and returns what the capture got along with the bytes the network
+/// carried.
+fn send_compressed(text: &str, threshold: usize) -> (Option<Message>, usize) {
+    let mut internet = Internet::new();
+    internet.limit_rounds(10);
+    let network = internet.network(1500);
+    let wire = Arc::new(Mutex::new(0));
+    internet.observe(Arc::new(Mutex::new(WireBytes(wire.clone()))));
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            Arc::new(Mutex::new(Compression::new().with_threshold(threshold))),
+            UserProcess::new_shared(SendMessage::new(text).with_transport(Compression::ID)),
+        ],
+        [network],
+    );
+    let capture = UserProcess::new_shared(Capture::new().with_transport(Compression::ID));
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            Compression::new_shared(),
+            capture.clone(),
+        ],
+        [network],
+    );
+    internet.run();
+    let message = capture.lock().unwrap().application().message();
+    let wire = *wire.lock().unwrap();
+    (message, wire)
+}
+
+/// Counts the bytes of the first message sent.
+struct WireBytes(Arc<Mutex<usize>>);
+
+impl Observer for WireBytes {
+    fn message_sent(&mut self, event: &Sent) {
+        let mut bytes = self.0.lock().unwrap();
+        if *bytes == 0 {
+            *bytes = event.message.iter().count();
+        }
+    }
+}
+
+#[test]
+fn compresses_large_payloads_on_the_wire() {
+    let text = "All work and no play makes Jack a dull boy. ".repeat(20);
+    let (compressed, compressed_wire) = send_compressed(&text, 64);
+    assert_eq!(compressed, Some(Message::new(text.as_str())));
+    let (plain, plain_wire) = send_compressed(&text, usize::MAX);
+    assert_eq!(plain, Some(Message::new(text.as_str())));
+    assert!(compressed_wire < plain_wire / 4);
+}
+
+#[test]
+fn passes_small_payloads_through() {
+    let (message, _) = send_compressed("Hello!", 64);
+    assert_eq!(message, Some(Message::new("Hello!")));
+}
@@ -0,0 +1,68 @@
+use elvis::{
+    core::{
+        message::Message, Control, ControlFlow, Internet, ProtocolContext, ProtocolId,
+        SharedProtocol, SimError,
+    },
+    protocols::{
+        ipv4::{Ipv4, Ipv4Address, LocalAddress, RemoteAddress},
+        udp::{LocalPort, RemotePort, Udp, UdpError},
+        user_process::{Application, UserProcess},
+    },
+};
+use std::error::Error;
+
+/// Opens the same UDP session every round, which fails from the second round
+/// on.
+#[derive(Default)]
+struct Reopen {
+    awakes: u32,
+    saw_session_exists: bool,
+}
+
+impl Application for Reopen {
+    const ID: ProtocolId = ProtocolId::from_string("Reopen");
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        self.awakes += 1;
+        let mut participants = Control::new();
+        LocalAddress::set(&mut participants, Ipv4Address::LOCALHOST);
+        RemoteAddress::set(&mut participants, Ipv4Address::LOCALHOST);
+        LocalPort::set(&mut participants, 0xbeef);
+        RemotePort::set(&mut participants, 0xdead);
+        let result = context
+            .protocol(Udp::ID)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .open(Self::ID, participants, context);
+        if let Err(e) = result {
+            self.saw_session_exists = matches!(e, SimError::Udp(UdpError::SessionExists));
+            Err(e)?
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    fn recv(&mut self, _: Message, _: &mut ProtocolContext) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+#[test]
+fn fatal_errors_end_the_simulation() {
+    let reopen = UserProcess::new_shared(Reopen::default());
+    let mut internet = Internet::new();
+    let network = internet.network(1500);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            reopen.clone(),
+        ],
+        [network],
+    );
+    internet.run();
+
+    let reopen = reopen.lock().unwrap();
+    assert_eq!(reopen.application().awakes, 2);
+    assert!(reopen.application().saw_session_exists);
+}
@@ -0,0 +1,38 @@
+use elvis::{
+    applications::{FileReceiver, FileSender},
+    core::{Internet, SharedProtocol},
+    protocols::{ipv4::Ipv4, udp::Udp, user_process::UserProcess},
+};
+
+#[test]
+fn file_arrives_intact() {
+    let data: Vec<u8> = (0..20_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    let mut internet = Internet::new();
+    let network = internet.network(500);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            UserProcess::new_shared(FileSender::new(data.clone(), 500).with_chunks_per_round(10)),
+        ],
+        [network],
+    );
+    let receiver = FileReceiver::new_shared();
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            receiver.clone(),
+        ],
+        [network],
+    );
+    internet.run();
+
+    let receiver = receiver.lock().unwrap();
+    let report = receiver.application().report().unwrap();
+    assert!(report.intact);
+    assert_eq!(report.data, data);
+    // 20000 bytes in chunks of 463 bytes take 44 chunks, 10 per round
+    assert_eq!(report.rounds, 5);
+    assert_eq!(report.throughput(), 4000.0);
+}
@@ -0,0 +1,124 @@
+use elvis::{
+    applications::{Capture, SendMessage},
+    core::{message::Message, ControlFlow, Internet, ProtocolContext, ProtocolId, SharedProtocol},
+    protocols::{
+        firewall::{Action, DropCounts, Firewall, Rule},
+        ipv4::{Ipv4, Ipv4Address},
+        udp::Udp,
+        user_process::{Application, UserProcess},
+    },
+};
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+/// Ends the simulation after a few rounds, for when nothing arrives.
+#[derive(Default)]
+struct Timeout {
+    rounds: u32,
+}
+
+impl Application for Timeout {
+    const ID: ProtocolId = ProtocolId::from_string("Timeout");
+
+    fn awake(&mut self, _: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        self.rounds += 1;
+        Ok(if self.rounds > 5 {
+            ControlFlow::EndSimulation
+        } else {
+            ControlFlow::Continue
+        })
+    }
+
+    fn recv(&mut self, _: Message, _: &mut ProtocolContext) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Sends one UDP message from a machine behind `sender` to one behind
+/// `receiver` and returns what arrived.
+fn send_through(sender: Firewall, receiver: Firewall) -> (Option<Message>, DropCounts, DropCounts) {
+    let sender = Arc::new(Mutex::new(sender));
+    let receiver = Arc::new(Mutex::new(receiver));
+    let mut internet = Internet::new();
+    let network = internet.network(1500);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Arc::new(Mutex::new(Ipv4::new().with_downstream(Firewall::ID))),
+            sender.clone(),
+            SendMessage::new_shared("Hello!"),
+        ],
+        [network],
+    );
+    let capture = Capture::new_shared();
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Arc::new(Mutex::new(Ipv4::new().with_downstream(Firewall::ID))),
+            receiver.clone(),
+            capture.clone(),
+            UserProcess::new_shared(Timeout::default()),
+        ],
+        [network],
+    );
+    internet.run();
+
+    let message = capture.lock().unwrap().application().message();
+    let sent = sender.lock().unwrap().dropped();
+    let received = receiver.lock().unwrap().dropped();
+    (message, sent, received)
+}
+
+#[test]
+fn allows_by_default() {
+    let (message, sent, received) = send_through(Firewall::new(), Firewall::new());
+    assert_eq!(message, Some(Message::new("Hello!")));
+    assert_eq!(sent, DropCounts::default());
+    assert_eq!(received, DropCounts::default());
+}
+
+#[test]
+fn drops_incoming_packets_for_a_port() {
+    let (message, _, received) = send_through(
+        Firewall::new(),
+        Firewall::new().with_rule(Rule::deny().incoming().local_port(0xbeef)),
+    );
+    assert_eq!(message, None);
+    assert_eq!(received.incoming, 1);
+}
+
+#[test]
+fn drops_outgoing_packets_by_protocol_and_address() {
+    let (message, sent, _) = send_through(
+        Firewall::new().with_rule(
+            Rule::deny()
+                .outgoing()
+                .protocol(Udp::ID)
+                .remote_address(Ipv4Address::LOCALHOST),
+        ),
+        Firewall::new(),
+    );
+    assert_eq!(message, None);
+    assert_eq!(
+        sent,
+        DropCounts {
+            incoming: 0,
+            outgoing: 1
+        }
+    );
+}
+
+#[test]
+fn first_matching_rule_wins() {
+    let (message, _, received) = send_through(
+        Firewall::new(),
+        Firewall::new()
+            .with_rule(Rule::allow().remote_port(0xdead))
+            .with_rule(Rule::deny())
+            .with_default(Action::Deny),
+    );
+    assert_eq!(message, Some(Message::new("Hello!")));
+    assert_eq!(received, DropCounts::default());
+}
@@ -0,0 +1,52 @@
+use elvis::{
+    applications::{Count, SendMessage},
+    core::{Impairments, Internet, SharedProtocol},
+    protocols::{ipv4::Ipv4, udp::Udp, user_process::UserProcess},
+};
+
+/// Sends twenty messages over a network that duplicates some of them and
+/// returns how many the receiver got and how many copies the network made.
+fn send_over_duplicating_network(seed: u64) -> (u32, u64) {
+    let mut internet = Internet::new();
+    internet.seed(seed);
+    internet.limit_rounds(10);
+    let network = internet.network(1500);
+    internet.set_impairments(
+        network,
+        Impairments {
+            duplication: 0.5,
+            ..Default::default()
+        },
+    );
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            UserProcess::new_shared(SendMessage::new("Hello!").with_count(20)),
+        ],
+        [network],
+    );
+    // Expects more than it will get so it keeps counting until the end
+    let count = Count::new_shared(1000);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            count.clone(),
+        ],
+        [network],
+    );
+    internet.run();
+    let received = count.lock().unwrap().application().received();
+    (received, internet.network_stats(network).duplicated)
+}
+
+#[test]
+fn udp_passes_duplicates_up() {
+    let (received, duplicated) = send_over_duplicating_network(7);
+    // The network broadcasts, so some copies go back to the sender, which
+    // drops them along with the originals
+    assert!(received > 20);
+    assert!(u64::from(received) <= 20 + duplicated);
+    assert_eq!(send_over_duplicating_network(7), (received, duplicated));
+}
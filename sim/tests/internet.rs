@@ -1,4 +1,36 @@
+use elvis::{
+    applications::{Capture, SendMessage},
+    core::{Internet, SharedProtocol},
+    protocols::{ipv4::Ipv4, udp::Udp},
+};
+
 #[tokio::test]
 pub async fn internet() {
     elvis::simulation::default_simulation().await;
 }
+
+#[test]
+fn udp_stamps_the_send_round() {
+    let mut internet = Internet::new();
+    let network = internet.network(1500);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            SendMessage::new_shared("Hello!"),
+        ],
+        [network],
+    );
+    let capture = Capture::new_shared();
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            capture.clone(),
+        ],
+        [network],
+    );
+    internet.run();
+    let message = capture.lock().unwrap().application().message().unwrap();
+    assert_eq!(message.timestamp(), Some(0));
+}
@@ -0,0 +1,151 @@
+use elvis::{
+    applications::{Capture, SendMessage},
+    core::{
+        message::Message, Control, ControlFlow, Internet, ProtocolContext, ProtocolId,
+        SharedProtocol,
+    },
+    protocols::{
+        ipv4::Ipv4,
+        ipv6::{Ipv6, Ipv6Address, LocalAddress, RemoteAddress},
+        udp::Udp,
+        user_process::{Application, UserProcess},
+    },
+};
+use std::error::Error;
+
+// 2001:db8::1 and 2001:db8::2, from the range reserved for documentation
+const SENDER: u128 = 0x2001_0db8 << 96 | 1;
+const RECEIVER: u128 = 0x2001_0db8 << 96 | 2;
+
+/// Sends one message straight over IPv6, without a transport protocol.
+#[derive(Default)]
+struct Ping {
+    did_send: bool,
+}
+
+impl Application for Ping {
+    // IP protocol numbers 253 and 254 are reserved for experiments
+    const ID: ProtocolId = ProtocolId::new(253);
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        if !self.did_send {
+            self.did_send = true;
+            let mut participants = Control::new();
+            LocalAddress::set(&mut participants, Ipv6Address::new(SENDER));
+            RemoteAddress::set(&mut participants, Ipv6Address::new(RECEIVER));
+            let mut session = context
+                .protocol(Ipv6::ID)
+                .expect("No such protocol")
+                .lock()
+                .unwrap()
+                .open(Self::ID, participants, context)?;
+            session.send(Message::new("Hello over IPv6!"), context)?;
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    fn recv(&mut self, _: Message, _: &mut ProtocolContext) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Listens on IPv6 and records the message and its source address.
+#[derive(Default)]
+struct Pong {
+    did_listen: bool,
+    received: Option<(Message, Option<RemoteAddress>)>,
+}
+
+impl Application for Pong {
+    const ID: ProtocolId = ProtocolId::new(253);
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        if !self.did_listen {
+            self.did_listen = true;
+            let mut participants = Control::new();
+            LocalAddress::set(&mut participants, Ipv6Address::new(RECEIVER));
+            context
+                .protocol(Ipv6::ID)
+                .expect("No such protocol")
+                .lock()
+                .unwrap()
+                .listen(Self::ID, participants, context)?;
+        }
+        Ok(if self.received.is_some() {
+            ControlFlow::EndSimulation
+        } else {
+            ControlFlow::Continue
+        })
+    }
+
+    fn recv(
+        &mut self,
+        message: Message,
+        context: &mut ProtocolContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let source = RemoteAddress::try_from(&context.info).ok();
+        self.received = Some((message, source));
+        Ok(())
+    }
+}
+
+#[test]
+fn message_arrives_over_ipv6() {
+    let mut internet = Internet::new();
+    let network = internet.network(1500);
+    internet.machine(
+        [
+            Ipv6::new_shared() as SharedProtocol,
+            UserProcess::new_shared(Ping::default()),
+        ],
+        [network],
+    );
+    let pong = UserProcess::new_shared(Pong::default());
+    internet.machine(
+        [Ipv6::new_shared() as SharedProtocol, pong.clone()],
+        [network],
+    );
+    internet.run();
+
+    let pong = pong.lock().unwrap();
+    let (message, source) = pong.application().received.clone().unwrap();
+    assert_eq!(message, Message::new("Hello over IPv6!"));
+    assert_eq!(source, Some(Ipv6Address::new(SENDER).into()));
+}
+
+#[test]
+fn ipv4_and_ipv6_share_a_machine() {
+    let mut internet = Internet::new();
+    let network = internet.network(1500);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            Ipv6::new_shared(),
+            SendMessage::new_shared("Hello!"),
+            UserProcess::new_shared(Ping::default()),
+        ],
+        [network],
+    );
+    let capture = Capture::new_shared();
+    let pong = UserProcess::new_shared(Pong::default());
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            Ipv6::new_shared(),
+            capture.clone(),
+            pong.clone(),
+        ],
+        [network],
+    );
+    internet.run();
+
+    assert_eq!(
+        capture.lock().unwrap().application().message(),
+        Some(Message::new("Hello!"))
+    );
+    let pong = pong.lock().unwrap();
+    let (message, _) = pong.application().received.clone().unwrap();
+    assert_eq!(message, Message::new("Hello over IPv6!"));
+}
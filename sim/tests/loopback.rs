@@ -0,0 +1,40 @@
+use elvis::{
+    applications::{Capture, SendMessage},
+    core::{message::Message, Internet, SharedProtocol},
+    protocols::{ipv4::Ipv4, udp::Udp},
+};
+use std::sync::{Arc, Mutex};
+
+/// Runs a sender and a capture on one machine and returns what the capture got
+/// and how many messages the network queued.
+fn send_to_self(ipv4: Ipv4) -> (Option<Message>, u64) {
+    let mut internet = Internet::new();
+    internet.limit_rounds(10);
+    let network = internet.network(1500);
+    let capture = Capture::new_shared();
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Arc::new(Mutex::new(ipv4)),
+            SendMessage::new_shared("Hello!"),
+            capture.clone(),
+        ],
+        [network],
+    );
+    internet.run();
+    let message = capture.lock().unwrap().application().message();
+    (message, internet.network_stats(network).queued)
+}
+
+#[test]
+fn delivers_to_the_same_machine_without_the_network() {
+    let (message, queued) = send_to_self(Ipv4::new().with_loopback());
+    assert_eq!(message, Some(Message::new("Hello!")));
+    assert_eq!(queued, 0);
+}
+
+#[test]
+fn sends_localhost_onto_the_network_by_default() {
+    let (_, queued) = send_to_self(Ipv4::new());
+    assert_eq!(queued, 1);
+}
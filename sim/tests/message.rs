@@ -19,3 +19,12 @@ fn mixed_operations() {
     let expected = b"derHe";
     assert!(message.iter().eq(expected.iter().cloned()));
 }
+
+#[test]
+fn keeps_timestamp() {
+    let message = Message::new(b"Body").with_timestamp(7);
+    assert_eq!(Message::new(b"Body").timestamp(), None);
+    let wrapped = message.with_header(b"Header").slice(2..8);
+    assert_eq!(wrapped.timestamp(), Some(7));
+    assert_eq!(message, Message::new(b"Body"));
+}
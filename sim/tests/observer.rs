@@ -0,0 +1,114 @@
+use elvis::{
+    applications::{Capture, SendMessage},
+    core::{
+        message::Message, DropReason, Dropped, Internet, MachineId, Observer, QueueLimit, Received,
+        Round, Sent, SessionEvent, SharedProtocol,
+    },
+    protocols::{ipv4::Ipv4, udp::Udp, user_process::UserProcess},
+};
+use std::sync::{Arc, Mutex};
+
+/// Records what it sees.
+#[derive(Default)]
+struct Tally {
+    awoken: Vec<(Round, MachineId)>,
+    sent: Vec<(MachineId, Message)>,
+    received: Vec<MachineId>,
+    dropped: Vec<(Option<MachineId>, Option<usize>, String)>,
+    opened: Vec<SessionEvent>,
+}
+
+impl Observer for Tally {
+    fn machine_awoken(&mut self, round: Round, machine: MachineId) {
+        self.awoken.push((round, machine));
+    }
+
+    fn message_sent(&mut self, event: &Sent) {
+        self.sent.push((event.machine, event.message.clone()));
+    }
+
+    fn message_received(&mut self, event: &Received) {
+        self.received.push(event.machine);
+    }
+
+    fn message_dropped(&mut self, event: &Dropped) {
+        let reason = match event.reason {
+            DropReason::QueueFull => "queue full".to_string(),
+            DropReason::EarlyDrop => "early drop".to_string(),
+            DropReason::NoSuchAddress => "no such address".to_string(),
+            DropReason::Rejected(e) => e.to_string(),
+        };
+        self.dropped.push((event.machine, event.network, reason));
+    }
+
+    fn session_opened(&mut self, event: &SessionEvent) {
+        self.opened.push(*event);
+    }
+}
+
+/// Sends `count` messages from one machine to another with the given queue
+/// limit and returns what the observer saw.
+fn observe(count: u32, limit: QueueLimit) -> Arc<Mutex<Tally>> {
+    let tally = Arc::new(Mutex::new(Tally::default()));
+    let mut internet = Internet::new();
+    internet.observe(tally.clone());
+    let network = internet.network(1500);
+    internet.set_queue_limit(network, limit);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            UserProcess::new_shared(SendMessage::new("Hello!").with_count(count)),
+        ],
+        [network],
+    );
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            Capture::new_shared(),
+        ],
+        [network],
+    );
+    internet.run();
+    tally
+}
+
+#[test]
+fn observes_a_message_exchange() {
+    let tally = observe(1, QueueLimit::Unbounded);
+    let tally = tally.lock().unwrap();
+    assert_eq!(&tally.awoken[..4], [(0, 0), (0, 1), (1, 0), (1, 1)]);
+    assert_eq!(tally.sent.len(), 1);
+    assert_eq!(tally.sent[0].0, 0);
+    // The message is broadcast, so the sender gets a copy it has no use for
+    assert_eq!(tally.received, [1, 0]);
+    assert_eq!(tally.dropped.len(), 1);
+    assert_eq!(tally.dropped[0].0, Some(0));
+    assert_eq!(tally.dropped[0].1, None);
+    let protocols: Vec<_> = tally
+        .opened
+        .iter()
+        .map(|event| (event.machine, event.protocol))
+        .collect();
+    assert!(protocols.contains(&(0, Udp::ID)));
+    assert!(protocols.contains(&(0, Ipv4::ID)));
+    assert!(protocols.contains(&(1, Udp::ID)));
+    assert!(protocols.contains(&(1, Ipv4::ID)));
+}
+
+#[test]
+fn observes_drops() {
+    let tally = observe(3, QueueLimit::DropTail(1));
+    let tally = tally.lock().unwrap();
+    assert_eq!(tally.sent.len(), 3);
+    let full: Vec<_> = tally
+        .dropped
+        .iter()
+        .filter(|(_, _, reason)| reason == "queue full")
+        .collect();
+    assert!(!full.is_empty());
+    assert!(full
+        .iter()
+        .all(|&&(machine, network, _)| machine.is_some() && network == Some(0)));
+}
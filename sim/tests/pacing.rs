@@ -0,0 +1,43 @@
+use elvis::core::{Internet, Pacing};
+use std::time::{Duration, Instant};
+
+/// Runs an empty internet for `rounds` rounds and returns how long it took.
+fn time_run(rounds: u64, pacing: Pacing) -> Duration {
+    let mut internet = Internet::new();
+    internet.limit_rounds(rounds);
+    internet.set_pacing(pacing).unwrap();
+    let start = Instant::now();
+    internet.run();
+    assert_eq!(internet.rounds(), rounds);
+    start.elapsed()
+}
+
+#[test]
+fn paced_rounds_keep_up_with_the_wall_clock() {
+    // Twenty milliseconds a round at double speed puts each round 10ms after
+    // the last, so the fifth starts no sooner than 40ms in
+    let pacing = Pacing::RealTime {
+        round: Duration::from_millis(20),
+        speedup: 2.0,
+    };
+    assert!(time_run(5, pacing) >= Duration::from_millis(40));
+}
+
+#[test]
+fn rejects_a_speedup_of_zero() {
+    let mut internet = Internet::new();
+    let pacing = Pacing::RealTime {
+        round: Duration::from_millis(20),
+        speedup: 0.0,
+    };
+    assert!(internet.set_pacing(pacing).is_err());
+    // The simulation still runs, unpaced
+    internet.limit_rounds(3);
+    internet.run();
+    assert_eq!(internet.rounds(), 3);
+}
+
+#[test]
+fn unpaced_rounds_do_not_wait() {
+    assert!(time_run(1000, Pacing::Unpaced) < Duration::from_secs(10));
+}
@@ -0,0 +1,111 @@
+use elvis::{
+    applications::{Count, SendMessage},
+    core::{
+        Impairments, Internet, MachineId, Observer, Received, Reordering, Round, SharedProtocol,
+    },
+    protocols::{ipv4::Ipv4, udp::Udp, user_process::UserProcess},
+};
+use std::{
+    collections::BTreeMap,
+    mem,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// The messages each machine received, in the order it received them, with
+/// the round and the machine that sent each.
+#[derive(Default, Debug, PartialEq)]
+struct Arrivals(BTreeMap<MachineId, Vec<(Round, MachineId, Vec<u8>)>>);
+
+impl Observer for Arrivals {
+    fn message_received(&mut self, event: &Received) {
+        self.0.entry(event.machine).or_default().push((
+            event.round,
+            event.source,
+            event.message.iter().collect(),
+        ));
+    }
+}
+
+/// Sends messages from two senders to a receiver on each of `pairs` networks,
+/// running the machines on `threads` threads. The networks hold back some of
+/// the messages, so those from the two senders arrive interleaved. Returns how
+/// many messages each receiver got and the order everything arrived in.
+fn run_pairs(pairs: u32, threads: usize) -> (Vec<u32>, Arrivals) {
+    let mut internet = Internet::new();
+    internet.seed(7);
+    internet.limit_rounds(10);
+    let arrivals = Arc::new(Mutex::new(Arrivals::default()));
+    internet.observe(arrivals.clone());
+    let counts: Vec<_> = (0..pairs)
+        .map(|pair| {
+            let network = internet.network(1500);
+            internet.set_impairments(
+                network,
+                Impairments {
+                    reordering: Some(Reordering {
+                        fraction: 0.5,
+                        max_delay: 3,
+                    }),
+                    ..Default::default()
+                },
+            );
+            for (text, count) in [("Ping", 100 + pair), ("Pong", 10)] {
+                internet.machine(
+                    [
+                        Udp::new_shared() as SharedProtocol,
+                        Ipv4::new_shared(),
+                        UserProcess::new_shared(
+                            SendMessage::new(&format!("{text} {pair}")).with_count(count),
+                        ),
+                    ],
+                    [network],
+                );
+            }
+            // Expects more than it will get so it keeps counting until the end
+            let count = Count::new_shared(1000);
+            internet.machine(
+                [
+                    Udp::new_shared() as SharedProtocol,
+                    Ipv4::new_shared(),
+                    count.clone(),
+                ],
+                [network],
+            );
+            count
+        })
+        .collect();
+
+    internet.run_parallel(NonZeroUsize::new(threads).unwrap());
+    let counts = counts
+        .iter()
+        .map(|count| count.lock().unwrap().application().received())
+        .collect();
+    let arrivals = mem::take(&mut *arrivals.lock().unwrap());
+    (counts, arrivals)
+}
+
+#[test]
+fn parallel_run_is_deterministic() {
+    let (counts, arrivals) = run_pairs(4, 1);
+    // Each sender sends everything in the first round and none is held back
+    // past the last, so every receiver has all of its messages by the end
+    assert_eq!(counts, [110, 111, 112, 113]);
+    // Some messages were held back behind later ones from the other sender,
+    // so it takes more than one switch between senders to list them in order
+    let sources: Vec<_> = arrivals.0[&2].iter().map(|(_, source, _)| source).collect();
+    assert!(sources.windows(2).filter(|pair| pair[0] != pair[1]).count() > 1);
+    for threads in [3, 8] {
+        let (parallel_counts, parallel_arrivals) = run_pairs(4, threads);
+        assert_eq!(parallel_counts, counts);
+        assert_eq!(parallel_arrivals, arrivals);
+    }
+}
+
+#[test]
+fn protocols_can_be_sent_to_other_threads() {
+    let count = Count::new_shared(1);
+    let received = thread::spawn(move || count.lock().unwrap().application().received());
+    assert_eq!(received.join().unwrap(), 0);
+}
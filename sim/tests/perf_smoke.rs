@@ -0,0 +1,18 @@
+//! Quick checks that the core message path hasn't become drastically slower.
+//! The limits are loose enough for a debug build on a slow CI machine; use the
+//! criterion benchmarks for real measurements.
+
+use elvis::simulation::flood_simulation;
+use std::time::{Duration, Instant};
+
+#[test]
+fn flood_of_messages() {
+    let start = Instant::now();
+    assert_eq!(flood_simulation(10_000), 10_000);
+    let elapsed = start.elapsed();
+    assert!(
+        elapsed < Duration::from_secs(20),
+        "Delivering 10,000 messages took {:?}",
+        elapsed
+    );
+}
@@ -0,0 +1,78 @@
+use elvis::{
+    core::{
+        message::Message, ControlFlow, Internet, ProcessState, ProtocolContext, ProtocolId,
+        SharedProtocol,
+    },
+    protocols::user_process::{Application, UserProcess},
+};
+use std::error::Error;
+
+/// Counts how often it is awoken and exits right away.
+#[derive(Default)]
+struct Child {
+    awakes: u32,
+}
+
+impl Application for Child {
+    const ID: ProtocolId = ProtocolId::from_string("Child");
+
+    fn awake(&mut self, _: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        self.awakes += 1;
+        Ok(ControlFlow::Exit)
+    }
+
+    fn recv(&mut self, _: Message, _: &mut ProtocolContext) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Spawns two children and ends the simulation once both have exited.
+#[derive(Default)]
+struct Parent {
+    children: Vec<ProtocolId>,
+    states: Vec<Option<ProcessState>>,
+}
+
+impl Application for Parent {
+    const ID: ProtocolId = ProtocolId::from_string("Parent");
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        if self.children.is_empty() {
+            for _ in 0..2 {
+                let child = UserProcess::new_shared(Child::default());
+                self.children.push(context.spawn(child));
+            }
+        }
+        self.states = self
+            .children
+            .iter()
+            .map(|&child| context.process_state(child))
+            .collect();
+        if self
+            .states
+            .iter()
+            .all(|&state| state == Some(ProcessState::Exited))
+        {
+            Ok(ControlFlow::EndSimulation)
+        } else {
+            Ok(ControlFlow::Continue)
+        }
+    }
+
+    fn recv(&mut self, _: Message, _: &mut ProtocolContext) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+#[test]
+fn spawned_processes_run_and_exit() {
+    let parent = UserProcess::new_shared(Parent::default());
+    let mut internet = Internet::new();
+    internet.machine([parent.clone() as SharedProtocol], []);
+    internet.run();
+
+    let parent = parent.lock().unwrap();
+    let parent = parent.application();
+    assert_eq!(parent.children, [Child::ID, Child::ID.with_instance(1)]);
+    assert_eq!(parent.states, [Some(ProcessState::Exited); 2]);
+}
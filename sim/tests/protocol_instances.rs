@@ -0,0 +1,44 @@
+use elvis::{
+    core::{message::Message, ControlFlow, Internet, ProtocolContext, ProtocolId, SharedProtocol},
+    protocols::user_process::{Application, UserProcess},
+};
+use std::error::Error;
+
+/// Records which protocol instance it was awoken as and ends the simulation.
+#[derive(Default)]
+struct Whoami {
+    me: Option<ProtocolId>,
+    instances: Vec<ProtocolId>,
+}
+
+impl Application for Whoami {
+    const ID: ProtocolId = ProtocolId::from_string("Whoami");
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        self.me = context.current_protocol();
+        self.instances = context.instances(Self::ID);
+        Ok(ControlFlow::EndSimulation)
+    }
+
+    fn recv(&mut self, _: Message, _: &mut ProtocolContext) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+#[test]
+fn two_instances_of_one_application() {
+    let first = UserProcess::new_shared(Whoami::default());
+    let second = UserProcess::new_shared(Whoami::default());
+    let mut internet = Internet::new();
+    internet.machine([first.clone() as SharedProtocol, second.clone()], []);
+    internet.run();
+
+    let both = vec![Whoami::ID, Whoami::ID.with_instance(1)];
+    assert_eq!(first.lock().unwrap().application().me, Some(Whoami::ID));
+    assert_eq!(
+        second.lock().unwrap().application().me,
+        Some(Whoami::ID.with_instance(1))
+    );
+    assert_eq!(first.lock().unwrap().application().instances, both);
+    assert_eq!(Whoami::ID.with_instance(1).base(), Whoami::ID);
+}
@@ -0,0 +1,104 @@
+use elvis::{
+    applications::Capture,
+    core::{
+        message::Message, Control, ControlFlow, Internet, PhysicalAddress, ProtocolContext,
+        ProtocolId, SharedProtocol,
+    },
+    protocols::{
+        ipv4::{Ipv4, Ipv4Address, LocalAddress, RemoteAddress},
+        tap::PhysicalDestination,
+        udp::{LocalPort, RemotePort, Udp},
+        user_process::{Application, UserProcess},
+    },
+};
+use std::error::Error;
+
+/// Sends one message to port `0xbeef` and remembers the messages refused.
+struct Probe {
+    destination: Option<PhysicalAddress>,
+    did_send: bool,
+    refused: Vec<Message>,
+}
+
+impl Application for Probe {
+    const ID: ProtocolId = ProtocolId::from_string("Probe");
+
+    fn awake(&mut self, context: &mut ProtocolContext) -> Result<ControlFlow, Box<dyn Error>> {
+        if self.did_send {
+            return Ok(ControlFlow::Continue);
+        }
+        self.did_send = true;
+        let mut participants = Control::new();
+        LocalAddress::set(&mut participants, Ipv4Address::LOCALHOST);
+        RemoteAddress::set(&mut participants, Ipv4Address::LOCALHOST);
+        LocalPort::set(&mut participants, 0xdeadu16);
+        RemotePort::set(&mut participants, 0xbeefu16);
+        let mut session = context
+            .protocol(Udp::ID)
+            .expect("No such protocol")
+            .lock()
+            .unwrap()
+            .open(Self::ID, participants, context)?;
+        if let Some(destination) = self.destination {
+            PhysicalDestination::set(&mut context.info, destination);
+        }
+        session.send(Message::new("Anyone there?"), context)?;
+        Ok(ControlFlow::Continue)
+    }
+
+    fn recv(&mut self, _: Message, _: &mut ProtocolContext) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn refused(&mut self, message: Message, _: &mut ProtocolContext) -> Result<(), Box<dyn Error>> {
+        self.refused.push(message);
+        Ok(())
+    }
+}
+
+/// Sends from machine 0 to machine 1, which runs `listener` if given, and
+/// returns the messages refused back to the sender.
+fn probe(destination: Option<PhysicalAddress>, listener: Option<SharedProtocol>) -> Vec<Message> {
+    let mut internet = Internet::new();
+    internet.limit_rounds(10);
+    let network = internet.network(1500);
+    let probe = UserProcess::new_shared(Probe {
+        destination,
+        did_send: false,
+        refused: vec![],
+    });
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            probe.clone(),
+        ],
+        [network],
+    );
+    let mut protocols = vec![Udp::new_shared() as SharedProtocol, Ipv4::new_shared()];
+    protocols.extend(listener);
+    internet.machine(protocols, [network]);
+    internet.run();
+    let refused = probe.lock().unwrap().application().refused.clone();
+    refused
+}
+
+#[test]
+fn tells_the_sender_when_nobody_listens() {
+    let refused = probe(Some(PhysicalAddress::for_machine(1)), None);
+    assert_eq!(refused, [Message::new("Anyone there?")]);
+}
+
+#[test]
+fn says_nothing_when_the_message_is_delivered() {
+    let refused = probe(
+        Some(PhysicalAddress::for_machine(1)),
+        Some(Capture::new_shared()),
+    );
+    assert!(refused.is_empty());
+}
+
+#[test]
+fn does_not_refuse_broadcasts() {
+    assert!(probe(None, None).is_empty());
+}
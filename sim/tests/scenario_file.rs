@@ -0,0 +1,38 @@
+use elvis::simulation::ScenarioFile;
+
+#[test]
+fn runs_a_scenario_file() {
+    let scenario = ScenarioFile::load(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/scenarios/hello.scenario"
+    ))
+    .unwrap();
+    let report = scenario.run();
+
+    let lines: Vec<_> = report.events.lines().collect();
+    let count = |event: &str| {
+        lines
+            .iter()
+            .filter(|line| line.contains(&format!(r#""event":"{event}""#)))
+            .count()
+    };
+    assert_eq!(count("sent"), 10);
+    assert!(count("received") >= 10);
+    assert!(lines[0].starts_with(r#"{"round":0,"event":"sent","machine":"sender","network":"lan""#));
+    assert!(report.stats.starts_with(r#"{"rounds":"#));
+    assert!(report
+        .stats
+        .contains(r#"{"name":"receiver","sent":0,"received":10,"dropped":0}"#));
+
+    // The same seed gives the same run
+    assert_eq!(scenario.run(), report);
+}
+
+#[test]
+fn stops_at_the_round_limit() {
+    let scenario = ScenarioFile::parse(
+        "rounds 3\nnetwork lan\nmachine a on lan run capture\nmachine b on lan run capture",
+    )
+    .unwrap();
+    assert!(scenario.run().stats.starts_with(r#"{"rounds":3,"#));
+}
@@ -0,0 +1,67 @@
+use elvis::{
+    applications::SendMessage,
+    core::{Event, Internet, SharedProtocol, StopCondition},
+    protocols::{ipv4::Ipv4, udp::Udp, user_process::UserProcess},
+};
+use std::num::NonZeroUsize;
+
+/// A simulation that never ends by itself: a machine sends five messages to
+/// another that has nothing listening for them.
+fn runaway() -> Internet {
+    let mut internet = Internet::new();
+    let network = internet.network(1500);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            UserProcess::new_shared(SendMessage::new("Hello!").with_count(5)),
+        ],
+        [network],
+    );
+    internet.machine(
+        [Udp::new_shared() as SharedProtocol, Ipv4::new_shared()],
+        [network],
+    );
+    internet
+}
+
+#[test]
+fn stops_once_enough_messages_are_delivered() {
+    let mut internet = runaway();
+    internet.stop_when(StopCondition::Delivered(3).or(StopCondition::Rounds(100)));
+    internet.run();
+    assert!(internet.rounds() < 100);
+}
+
+#[test]
+fn stops_once_idle_on_the_same_round_either_way() {
+    let mut internet = runaway();
+    internet.stop_when(StopCondition::Idle(3));
+    internet.run();
+    let rounds = internet.rounds();
+    assert!(rounds >= 3);
+
+    let mut internet = runaway();
+    internet.stop_when(StopCondition::Idle(3));
+    internet.run_parallel(NonZeroUsize::new(2).unwrap());
+    assert_eq!(internet.rounds(), rounds);
+}
+
+#[test]
+fn stops_on_a_matching_event() {
+    let mut internet = runaway();
+    internet.limit_rounds(100);
+    internet.stop_when(StopCondition::event(
+        |event| matches!(event, Event::MessageDropped(dropped) if dropped.machine == Some(1)),
+    ));
+    internet.run();
+    assert!(internet.rounds() < 100);
+}
+
+#[test]
+fn waits_for_every_condition() {
+    let mut internet = runaway();
+    internet.stop_when(StopCondition::Idle(1).and(StopCondition::Rounds(20)));
+    internet.run();
+    assert_eq!(internet.rounds(), 20);
+}
@@ -0,0 +1,84 @@
+use elvis::{
+    applications::{Capture, SendMessage},
+    core::{Internet, QueueLimit, SharedProtocol},
+    protocols::{ipv4::Ipv4, udp::Udp, user_process::UserProcess},
+    timeline::{Outcome, Timeline},
+};
+use std::sync::{Arc, Mutex};
+
+/// Sends `count` messages from one machine to another and records them on
+/// the timeline.
+fn record(timeline: Timeline, count: u32, limit: QueueLimit) -> Arc<Mutex<Timeline>> {
+    let timeline = Arc::new(Mutex::new(timeline));
+    let mut internet = Internet::new();
+    internet.observe(timeline.clone());
+    let network = internet.network(1500);
+    internet.set_queue_limit(network, limit);
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            UserProcess::new_shared(SendMessage::new("Hello!").with_count(count)),
+        ],
+        [network],
+    );
+    internet.machine(
+        [
+            Udp::new_shared() as SharedProtocol,
+            Ipv4::new_shared(),
+            Capture::new_shared(),
+        ],
+        [network],
+    );
+    internet.run();
+    timeline
+}
+
+#[test]
+fn draws_a_sequence_diagram() {
+    let timeline = record(Timeline::new(), 1, QueueLimit::Unbounded);
+    let timeline = timeline.lock().unwrap();
+    assert_eq!(
+        timeline.to_mermaid(),
+        "sequenceDiagram
+    participant M0 as Machine 0
+    participant M1 as Machine 1
+    Note over M0,M1: Round 0
+    M0->>M1: UDP over IPv4, 34 bytes
+    M0-xM0: UDP over IPv4, 34 bytes (rejected)
+"
+    );
+}
+
+#[test]
+fn exports_json() {
+    let timeline = record(Timeline::new(), 1, QueueLimit::Unbounded);
+    let timeline = timeline.lock().unwrap();
+    // The receiver runs after the sender, so it gets the message in the same
+    // round, while the sender only sees its own copy in the next
+    assert_eq!(
+        timeline.to_json(),
+        concat!(
+            r#"[{"from":0,"to":1,"network":0,"sent":0,"received":0,"outcome":"delivered","label":"UDP over IPv4, 34 bytes"},"#,
+            r#"{"from":0,"to":0,"network":0,"sent":0,"received":1,"outcome":"rejected","label":"UDP over IPv4, 34 bytes"}]"#
+        )
+    );
+}
+
+#[test]
+fn shows_lost_messages() {
+    let timeline = record(
+        Timeline::new().with_labeler(|_| "Hello".to_string()),
+        3,
+        QueueLimit::DropTail(1),
+    );
+    let timeline = timeline.lock().unwrap();
+    let outcomes: Vec<_> = timeline
+        .exchanges()
+        .iter()
+        .map(|exchange| (exchange.to, exchange.outcome))
+        .collect();
+    assert!(outcomes.contains(&(1, Outcome::Lost)));
+    assert!(outcomes.contains(&(1, Outcome::Delivered)));
+    assert!(timeline.to_mermaid().contains("M0-xM1: Hello (lost)"));
+}
@@ -0,0 +1,92 @@
+use elvis::{
+    applications::{Capture, SendMessage},
+    core::{message::Message, Internet, Observer, Sent, SharedProtocol},
+    protocols::{
+        compression::Compression, ipv4::Ipv4, tls::Tls, udp::Udp, user_process::UserProcess,
+    },
+};
+use std::sync::{Arc, Mutex};
+
+const SECRET: &str = "The eagle lands at midnight";
+
+/// Collects every message put on a network.
+#[derive(Default)]
+struct Wire(Vec<Vec<u8>>);
+
+impl Observer for Wire {
+    fn message_sent(&mut self, event: &Sent) {
+        self.0.push(event.message.iter().collect());
+    }
+}
+
+/// Sends the secret from a machine with `client` to one with `server`, with
+/// the extra protocols on both, and returns what the server's capture got
+/// along with what went over the network.
+fn send_secret(
+    client: Tls,
+    server: Tls,
+    extra: impl Fn() -> Vec<SharedProtocol>,
+) -> (Option<Message>, Vec<Vec<u8>>) {
+    let mut internet = Internet::new();
+    internet.limit_rounds(20);
+    let network = internet.network(1500);
+    let wire = Arc::new(Mutex::new(Wire::default()));
+    internet.observe(wire.clone());
+
+    let mut protocols = vec![
+        Udp::new_shared() as SharedProtocol,
+        Ipv4::new_shared(),
+        Arc::new(Mutex::new(client)),
+        UserProcess::new_shared(SendMessage::new(SECRET).with_transport(Tls::ID)),
+    ];
+    protocols.extend(extra());
+    internet.machine(protocols, [network]);
+
+    let capture = UserProcess::new_shared(Capture::new().with_transport(Tls::ID));
+    let mut protocols = vec![
+        Udp::new_shared() as SharedProtocol,
+        Ipv4::new_shared(),
+        Arc::new(Mutex::new(server)),
+        capture.clone(),
+    ];
+    protocols.extend(extra());
+    internet.machine(protocols, [network]);
+
+    internet.run();
+    let message = capture.lock().unwrap().application().message();
+    let wire = std::mem::take(&mut wire.lock().unwrap().0);
+    (message, wire)
+}
+
+fn contains_secret(packet: &[u8]) -> bool {
+    packet
+        .windows(SECRET.len())
+        .any(|window| window == SECRET.as_bytes())
+}
+
+#[test]
+fn delivers_after_a_handshake_without_exposing_the_payload() {
+    let (message, wire) = send_secret(Tls::new(b"hunter2"), Tls::new(b"hunter2"), Vec::new);
+    assert_eq!(message, Some(Message::new(SECRET)));
+    // Client hello, server hello, finished, and data
+    assert_eq!(wire.len(), 4);
+    assert!(!wire.iter().any(|packet| contains_secret(packet)));
+}
+
+#[test]
+fn rejects_a_peer_with_another_key() {
+    let (message, wire) = send_secret(Tls::new(b"hunter2"), Tls::new(b"password"), Vec::new);
+    assert_eq!(message, None);
+    // The client never finishes the handshake, so the data is never sent
+    assert_eq!(wire.len(), 2);
+}
+
+#[test]
+fn stacks_on_compression() {
+    let (message, _) = send_secret(
+        Tls::new(b"hunter2").with_downstream(Compression::ID),
+        Tls::new(b"hunter2").with_downstream(Compression::ID),
+        || vec![Compression::new_shared() as SharedProtocol],
+    );
+    assert_eq!(message, Some(Message::new(SECRET)));
+}
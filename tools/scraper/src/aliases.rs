@@ -0,0 +1,81 @@
+//! Host alias groups, with --host-alias. www.yahoo.com and yahoo.com, the
+//! regional mirrors and the CDN hosts serving a site are one site to whoever
+//! reads a report, but each is a host of its own to the crawl, and per host
+//! the statistics end up scattered over trivially different names.
+//!
+//! A group names the site and the hosts in it, `yahoo.com=www.yahoo.com,*.yimg.com`,
+//! where * matches any run of characters and the site's own name is always
+//! part of it. Reports count a group as one site, while the records keep the
+//! literal host every request went to.
+
+use std::borrow::Cow;
+use crate::blacklist::glob_match;
+
+/// The groups of hosts to report as one site
+#[derive(Debug, Default, Clone)]
+pub struct HostAliases {
+    groups: Vec<(String, Vec<String>)>, //site name and the host patterns in it, in the order given
+}
+
+impl HostAliases {
+    /// Groups from SITE=HOST,HOST,... rules. A host two groups claim belongs to the first.
+    pub fn parse<'a>(rules: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut groups = Vec::new();
+        for rule in rules {
+            let (site, hosts) = rule.split_once('=')
+                .ok_or_else(|| format!("Host alias {} is not SITE=HOST,HOST,...", rule))?;
+            let site = site.trim().to_ascii_lowercase();
+            let hosts: Vec<String> = hosts.split(',')
+                .map(|host| host.trim().to_ascii_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect();
+            if site.is_empty() || hosts.is_empty() {
+                return Err(format!("Host alias {} needs a site name and at least one host", rule));
+            }
+            groups.push((site, hosts));
+        }
+        Ok(Self { groups })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// The rules as given to parse, for the run manifest
+    pub fn rules(&self) -> Vec<String> {
+        self.groups.iter().map(|(site, hosts)| format!("{}={}", site, hosts.join(","))).collect()
+    }
+
+    /// The site host is reported under, the host itself when no group takes it
+    pub fn site<'a>(&'a self, host: &'a str) -> Cow<'a, str> {
+        let host = match host.bytes().any(|b| b.is_ascii_uppercase()) {
+            true => Cow::Owned(host.to_ascii_lowercase()),
+            false => Cow::Borrowed(host),
+        };
+        let group = self.groups.iter()
+            .find(|(site, hosts)| *site == host || hosts.iter().any(|pattern| glob_match(pattern, &host)));
+        match group {
+            Some((site, _)) => Cow::Borrowed(site),
+            None => host,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_hosts_into_sites() {
+        let aliases = HostAliases::parse(["yahoo.com=www.yahoo.com, *.yimg.com", "news=news.yahoo.com,*.news.yahoo.com"]).unwrap();
+        assert_eq!(aliases.site("www.yahoo.com"), "yahoo.com");
+        assert_eq!(aliases.site("yahoo.com"), "yahoo.com");
+        assert_eq!(aliases.site("S.YIMG.COM"), "yahoo.com");
+        assert_eq!(aliases.site("uk.news.yahoo.com"), "news");
+        assert_eq!(aliases.site("finance.yahoo.com"), "finance.yahoo.com");
+        assert_eq!(aliases.rules(), ["yahoo.com=www.yahoo.com,*.yimg.com", "news=news.yahoo.com,*.news.yahoo.com"]);
+
+        assert!(HostAliases::parse(["www.yahoo.com"]).is_err());
+        assert!(HostAliases::parse(["yahoo.com="]).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! News articles, with --articles. A page is an article when its JSON-LD
+//! says so, or failing that its og:type, and then its headline, authors,
+//! dates, section and body text are written to articles.ndjson, one article
+//! per line. Each field is taken from the JSON-LD first and from the meta
+//! tags and markup of the page after that.
+
+use select::document::Document;
+use select::predicate::{Attr, Class, Name};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::structured::Metadata;
+use crate::text;
+
+/// schema.org types that are articles
+const ARTICLE_TYPES: [&str; 6] = ["Article", "NewsArticle", "ReportageNewsArticle", "AnalysisNewsArticle", "OpinionNewsArticle", "BlogPosting"];
+
+/// One article as written to articles.ndjson
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Article {
+    pub url: String,
+    /// The fetch that got the article, same ID as in log.txt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headline: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<String>,
+    /// As the page gives it, usually ISO 8601
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+    pub word_count: usize,
+    /// The body paragraphs, separated by blank lines
+    pub body: String,
+}
+
+/// The article on the page, None if it isn't one or has no body text
+pub fn extract(document: &Document, url: &str) -> Option<Article> {
+    let metadata = crate::structured::extract(document).unwrap_or_default();
+    let ld = article_ld(&metadata);
+    if ld.is_none() && metadata.open_graph.get("og:type").is_none_or(|kind| kind != "article") {
+        return None;
+    }
+    let ld_string = |key: &str| ld.and_then(|ld| first_string(ld.get(key)?));
+
+    //Yahoo keeps the story in .caas-body, other sites get the generic content paragraphs
+    let paragraphs = match document.find(Class("caas-body")).next() {
+        Some(body) => text::paragraphs_in(body),
+        None => text::paragraphs(document),
+    };
+    if paragraphs.is_empty() {
+        return None;
+    }
+
+    let mut authors = ld.and_then(|ld| ld.get("author")).map(names).unwrap_or_default();
+    if authors.is_empty() {
+        authors.extend(meta(document, "author"));
+    }
+    if authors.is_empty() {
+        authors.extend(document.find(Attr("rel", "author")).map(|node| clean(&node.text())).filter(|name| !name.is_empty()));
+    }
+
+    Some(Article {
+        url: url.to_string(),
+        request_id: None,
+        headline: ld_string("headline")
+            .or_else(|| metadata.open_graph.get("og:title").cloned())
+            .or_else(|| document.find(Name("h1")).next().map(|node| clean(&node.text()))),
+        authors,
+        published: ld_string("datePublished")
+            .or_else(|| meta(document, "article:published_time"))
+            .or_else(|| document.find(Name("time")).find_map(|node| node.attr("datetime").map(str::to_string))),
+        modified: ld_string("dateModified").or_else(|| meta(document, "article:modified_time")),
+        section: ld_string("articleSection").or_else(|| meta(document, "article:section")),
+        word_count: paragraphs.iter().map(|paragraph| paragraph.split_whitespace().count()).sum(),
+        body: paragraphs.join("\n\n"),
+    })
+}
+
+//the first JSON-LD object typed as an article, looking inside @graph too
+fn article_ld(metadata: &Metadata) -> Option<&Value> {
+    metadata.json_ld.iter()
+        .flat_map(|item| match item.get("@graph") {
+            Some(Value::Array(graph)) => graph.iter().collect(),
+            _ => vec![item],
+        })
+        .find(|item| match item.get("@type") {
+            Some(Value::String(kind)) => ARTICLE_TYPES.contains(&kind.as_str()),
+            Some(Value::Array(kinds)) => kinds.iter().any(|kind| kind.as_str().is_some_and(|kind| ARTICLE_TYPES.contains(&kind))),
+            _ => false,
+        })
+}
+
+//a string, or the first of an array of them
+fn first_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(clean(text)).filter(|text| !text.is_empty()),
+        Value::Array(values) => values.iter().find_map(first_string),
+        _ => None,
+    }
+}
+
+//author names from a JSON-LD author: a name, a Person, or an array of either
+fn names(author: &Value) -> Vec<String> {
+    match author {
+        Value::String(name) => vec![clean(name)],
+        Value::Object(person) => person.get("name").and_then(first_string).into_iter().collect(),
+        Value::Array(authors) => authors.iter().flat_map(names).collect(),
+        _ => Vec::new(),
+    }
+}
+
+//content of the meta tag with this name or property
+fn meta(document: &Document, key: &str) -> Option<String> {
+    document.find(Name("meta"))
+        .find(|meta| meta.attr("property").or_else(|| meta.attr("name")) == Some(key))
+        .and_then(|meta| meta.attr("content"))
+        .map(clean)
+        .filter(|content| !content.is_empty())
+}
+
+fn clean(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_from_json_ld() {
+        let page = r#"<html><head>
+            <script type="application/ld+json">{"@context": "https://schema.org", "@graph": [
+                {"@type": "WebPage", "name": "Yahoo News"},
+                {"@type": ["NewsArticle"], "headline": "Markets  rally", "datePublished": "2024-05-01T13:00:00Z",
+                 "author": [{"@type": "Person", "name": "Jane Doe"}, "John Roe"], "articleSection": ["Business"]}
+            ]}</script>
+            <meta property="og:title" content="Markets rally - Yahoo">
+            </head><body><nav><p>Home News Finance Sports Entertainment Life</p></nav>
+            <div class="caas-body"><p>Stocks rallied on Tuesday as investors weighed new data.</p><p>Read more</p>
+            <p>The Nasdaq closed up two percent for the session.</p></div></body></html>"#;
+        let article = extract(&Document::from(page), "https://news.yahoo.com/markets-rally.html").unwrap();
+        assert_eq!(article, Article {
+            url: "https://news.yahoo.com/markets-rally.html".to_string(),
+            request_id: None,
+            headline: Some("Markets rally".to_string()),
+            authors: vec!["Jane Doe".to_string(), "John Roe".to_string()],
+            published: Some("2024-05-01T13:00:00Z".to_string()),
+            modified: None,
+            section: Some("Business".to_string()),
+            word_count: 18,
+            body: "Stocks rallied on Tuesday as investors weighed new data.\n\nThe Nasdaq closed up two percent for the session.".to_string(),
+        });
+    }
+
+    #[test]
+    fn falls_back_to_meta_tags() {
+        let page = r#"<html><head><meta property="og:type" content="article">
+            <meta name="author" content="Jane Doe"><meta property="article:section" content="Sports">
+            </head><body><h1>Late goal wins it</h1><time datetime="2024-05-02">May 2</time>
+            <article><p>A late goal settled the match in the final minute of play.</p></article></body></html>"#;
+        let article = extract(&Document::from(page), "https://sports.yahoo.com/late-goal.html").unwrap();
+        assert_eq!(article.headline.as_deref(), Some("Late goal wins it"));
+        assert_eq!(article.authors, ["Jane Doe"]);
+        assert_eq!(article.published.as_deref(), Some("2024-05-02"));
+        assert_eq!(article.section.as_deref(), Some("Sports"));
+
+        let front_page = r#"<html><head><meta property="og:type" content="website"></head>
+            <body><p>Top stories from around the world, updated all day long.</p></body></html>"#;
+        assert_eq!(extract(&Document::from(front_page), "https://www.yahoo.com/"), None);
+    }
+}
@@ -0,0 +1,294 @@
+//! The politeness audit: what the crawl asked of each host and how it paced
+//! itself, written to politeness.json so a crawl can be shown to have stayed
+//! within its etiquette after the fact.
+//!
+//! It also remembers when a host asked to be left alone for a while, with
+//! Retry-After or its rate limit headers, so requests to it wait until then.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use url::Url;
+use crate::aliases::HostAliases;
+use crate::etiquette::RobotsDirectives;
+use crate::rate_limit::RateLimit;
+
+/// What happened on one host while it was being crawled
+#[derive(Debug, Default)]
+struct HostLog {
+    requests: u64,
+    bytes: u64,
+    last_request: Option<Instant>,
+    //time between consecutive requests, summed for the average
+    total_gap: Duration,
+    gaps: u64,
+    shortest_gap: Option<Duration>,
+    noindex: u64,
+    nofollow: u64,
+    throttled: Vec<Throttle>,
+    blacklisted: bool,
+    addresses: BTreeSet<IpAddr>,
+    protocols: BTreeMap<String, u64>,
+    rate_limit: Option<RateLimits>, //None until a response announces a limit
+    resume_at: Option<Instant>,     //no requests before this, the host asked us to wait
+}
+
+/// A response telling us to slow down, and how long we waited before trying again
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Throttle {
+    pub status: u16,
+    pub waited_ms: u128,
+}
+
+/// The limits a host announced and how long we waited on them
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct RateLimits {
+    /// Requests per window, as last announced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    /// The fewest requests left in a window any response announced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lowest_remaining: Option<u64>,
+    /// Requests that waited for the host's limits, and how long in total
+    pub held_off: u64,
+    pub held_off_ms: u128,
+}
+
+/// The robots rules a host's pages carried, counted per page
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct RobotsRules {
+    pub noindex: u64,
+    pub nofollow: u64,
+}
+
+/// One host's entry in politeness.json
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HostSummary {
+    /// The site a --host-alias group puts the host in, absent when it's a site of its own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site: Option<String>,
+    pub requests: u64,
+    pub bytes: u64,
+    /// Average time between two requests to the host, absent with fewer than two requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_delay_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shortest_delay_ms: Option<u128>,
+    /// Whether no two requests to the host came closer together than --delay
+    pub within_delay: bool,
+    pub robots: RobotsRules,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub throttled: Vec<Throttle>,
+    /// The status policy told us to stay away from the host
+    pub blacklisted: bool,
+    /// The addresses the host's responses came from
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<IpAddr>,
+    /// Responses per negotiated HTTP version, ie: "HTTP/2.0": 12
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub protocols: BTreeMap<String, u64>,
+    /// Retry-After and X-RateLimit-* headers seen, absent when the host sent none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimits>,
+}
+
+/// Everything the crawl sent out, per host
+#[derive(Debug)]
+pub struct Audit {
+    delay: Duration,
+    hosts: BTreeMap<String, HostLog>,
+    bytes: u64, //response bodies from every host together
+}
+
+impl Audit {
+    /// An audit of a crawl meant to wait `delay` between requests
+    pub fn new(delay: Duration) -> Self {
+        Self { delay, hosts: BTreeMap::new(), bytes: 0 }
+    }
+
+    /// Note a request to url sent at `at`
+    pub fn request(&mut self, url: &str, at: Instant) {
+        let Some(host) = self.host(url) else {
+            return;
+        };
+        host.requests += 1;
+        if let Some(gap) = host.last_request.map(|last| at.saturating_duration_since(last)) {
+            host.total_gap += gap;
+            host.gaps += 1;
+            host.shortest_gap = Some(host.shortest_gap.map_or(gap, |shortest| shortest.min(gap)));
+        }
+        host.last_request = Some(at);
+    }
+
+    /// Note the size of a response body from url
+    pub fn received(&mut self, url: &str, bytes: usize) {
+        self.bytes += bytes as u64;
+        if let Some(host) = self.host(url) {
+            host.bytes += bytes as u64;
+        }
+    }
+
+    /// Bytes of response bodies received so far, what --max-bytes is counted against
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Note that url answered with a status asking us to back off, and how long we did
+    pub fn throttled(&mut self, url: &str, status: u16, waited: Duration) {
+        if let Some(host) = self.host(url) {
+            host.throttled.push(Throttle { status, waited_ms: waited.as_millis() });
+        }
+    }
+
+    /// Note the robots rules that applied to the page at url
+    pub fn robots(&mut self, url: &str, robots: RobotsDirectives) {
+        if let Some(host) = self.host(url) {
+            host.noindex += u64::from(robots.noindex);
+            host.nofollow += u64::from(robots.nofollow);
+        }
+    }
+
+    /// Note the address a response from url came from
+    pub fn connected(&mut self, url: &str, addr: IpAddr) {
+        if let Some(host) = self.host(url) {
+            host.addresses.insert(addr);
+        }
+    }
+
+    /// Note the HTTP version a response from url came over
+    pub fn protocol(&mut self, url: &str, protocol: &str) {
+        if let Some(host) = self.host(url) {
+            *host.protocols.entry(protocol.to_string()).or_default() += 1;
+        }
+    }
+
+    /// Note the limits a response from url announced at `at`, and hold
+    /// requests to its host back if they ask us to wait
+    pub fn rate_limit(&mut self, url: &str, limit: &RateLimit, at: Instant) {
+        let Some(host) = self.host(url) else {
+            return;
+        };
+        let seen = host.rate_limit.get_or_insert_with(RateLimits::default);
+        seen.limit = limit.limit.or(seen.limit);
+        seen.lowest_remaining = limit.remaining.into_iter().chain(seen.lowest_remaining).min();
+        if let Some(wait) = limit.hold_off() {
+            host.resume_at = host.resume_at.max(Some(at + wait));
+        }
+    }
+
+    /// How much longer requests to the host of url have to wait, None if they don't
+    pub fn hold_off(&self, url: &str, now: Instant) -> Option<Duration> {
+        let host = Url::parse(url).ok()?.host_str()?.to_ascii_lowercase();
+        let wait = self.hosts.get(&host)?.resume_at?.checked_duration_since(now)?;
+        (!wait.is_zero()).then_some(wait)
+    }
+
+    /// Note that a request to url waited this long for its host's limits
+    pub fn held_off(&mut self, url: &str, waited: Duration) {
+        if let Some(seen) = self.host(url).and_then(|host| host.rate_limit.as_mut()) {
+            seen.held_off += 1;
+            seen.held_off_ms += waited.as_millis();
+        }
+    }
+
+    /// Note that the host of url got blacklisted
+    pub fn blacklisted(&mut self, url: &str) {
+        if let Some(host) = self.host(url) {
+            host.blacklisted = true;
+        }
+    }
+
+    /// The summary of every host, as written to politeness.json, with the site `aliases` put it in
+    pub fn summary(&self, aliases: &HostAliases) -> BTreeMap<&str, HostSummary> {
+        self.hosts.iter()
+            .map(|(name, host)| (name.as_str(), HostSummary {
+                site: Some(aliases.site(name).into_owned()).filter(|site| site != name),
+                requests: host.requests,
+                bytes: host.bytes,
+                average_delay_ms: (host.gaps > 0).then(|| host.total_gap.as_millis() / u128::from(host.gaps)),
+                shortest_delay_ms: host.shortest_gap.map(|gap| gap.as_millis()),
+                within_delay: host.shortest_gap.is_none_or(|gap| gap >= self.delay),
+                robots: RobotsRules { noindex: host.noindex, nofollow: host.nofollow },
+                throttled: host.throttled.clone(),
+                blacklisted: host.blacklisted,
+                addresses: host.addresses.iter().copied().collect(),
+                protocols: host.protocols.clone(),
+                rate_limit: host.rate_limit.clone(),
+            }))
+            .collect()
+    }
+
+    fn host(&mut self, url: &str) -> Option<&mut HostLog> {
+        let host = Url::parse(url).ok()?.host_str()?.to_ascii_lowercase();
+        Some(self.hosts.entry(host).or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_each_host() {
+        let start = Instant::now();
+        let mut audit = Audit::new(Duration::from_millis(500));
+        audit.request("https://news.yahoo.com/", start);
+        audit.received("https://news.yahoo.com/", 1000);
+        audit.request("https://news.yahoo.com/world", start + Duration::from_millis(600));
+        audit.throttled("https://news.yahoo.com/world", 429, Duration::from_secs(2));
+        audit.request("https://news.yahoo.com/world", start + Duration::from_millis(2600));
+        audit.received("https://news.yahoo.com/world", 500);
+        audit.connected("https://news.yahoo.com/world", "87.248.100.215".parse().unwrap());
+        audit.connected("https://news.yahoo.com/", "87.248.100.215".parse().unwrap());
+        audit.protocol("https://news.yahoo.com/", "HTTP/2.0");
+        audit.protocol("https://news.yahoo.com/world", "HTTP/2.0");
+        audit.protocol("https://news.yahoo.com/world", "HTTP/3.0");
+        audit.robots("https://news.yahoo.com/world", RobotsDirectives { noindex: true, nofollow: false });
+        audit.request("https://finance.yahoo.com/", start + Duration::from_millis(100));
+        audit.request("https://finance.yahoo.com/quote", start + Duration::from_millis(200));
+        audit.blacklisted("https://finance.yahoo.com/quote");
+        audit.request("not a url", start);
+        assert_eq!(audit.bytes_received(), 1500);
+
+        let aliases = HostAliases::parse(["yahoo.com=www.yahoo.com,finance.yahoo.com"]).unwrap();
+        let summary = audit.summary(&aliases);
+        assert_eq!(summary.len(), 2);
+        assert_eq!(summary["news.yahoo.com"], HostSummary {
+            site: None,
+            requests: 3,
+            bytes: 1500,
+            average_delay_ms: Some(1300),
+            shortest_delay_ms: Some(600),
+            within_delay: true,
+            robots: RobotsRules { noindex: 1, nofollow: 0 },
+            throttled: vec![Throttle { status: 429, waited_ms: 2000 }],
+            blacklisted: false,
+            addresses: vec!["87.248.100.215".parse().unwrap()],
+            protocols: BTreeMap::from([("HTTP/2.0".to_string(), 2), ("HTTP/3.0".to_string(), 1)]),
+            rate_limit: None,
+        });
+        let finance = &summary["finance.yahoo.com"];
+        assert!(!finance.within_delay);
+        assert!(finance.blacklisted);
+        assert_eq!(finance.site.as_deref(), Some("yahoo.com"));
+    }
+
+    #[test]
+    fn holds_off_a_host_that_asked() {
+        let start = Instant::now();
+        let mut audit = Audit::new(Duration::from_millis(500));
+        let announced = |remaining, reset| RateLimit { limit: Some(100), remaining: Some(remaining), reset: Some(Duration::from_secs(reset)), retry_after: None };
+        audit.rate_limit("https://news.yahoo.com/", &announced(3, 40), start);
+        assert_eq!(audit.hold_off("https://news.yahoo.com/world", start), None);
+
+        audit.rate_limit("https://news.yahoo.com/world", &announced(0, 20), start);
+        assert_eq!(audit.hold_off("https://news.yahoo.com/", start + Duration::from_secs(5)), Some(Duration::from_secs(15)));
+        assert_eq!(audit.hold_off("https://news.yahoo.com/", start + Duration::from_secs(20)), None);
+        assert_eq!(audit.hold_off("https://finance.yahoo.com/", start), None);
+        audit.held_off("https://news.yahoo.com/", Duration::from_secs(15));
+
+        let summary = audit.summary(&HostAliases::default());
+        assert_eq!(summary["news.yahoo.com"].rate_limit, Some(RateLimits { limit: Some(100), lowest_remaining: Some(0), held_off: 1, held_off_ms: 15000 }));
+    }
+}
@@ -0,0 +1,213 @@
+//! Crawling pages that are behind a login.
+//!
+//! The auth block either logs in once before the crawl starts, by posting
+//! credentials to a login form and keeping the session cookie it hands out,
+//! or sends static headers such as a bearer token with every request. Both
+//! can be used together. Tokens are only sent to the auth domain, so they
+//! don't leak to image CDNs or wherever a redirect ends up.
+
+use std::env;
+use clap::ArgMatches;
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use select::document::Document;
+use select::node::Node;
+use select::predicate::Name;
+use url::Url;
+use crate::etiquette::Etiquette;
+
+/// Environment variable the bearer token is read from when --auth-token isn't given
+pub const TOKEN_VAR: &str = "SCRAPER_AUTH_TOKEN";
+
+/// The auth block: how the crawl proves who it is
+#[derive(Debug, Clone, Default)]
+pub struct Auth {
+    /// Headers sent with every request to the auth domain
+    headers: Vec<(HeaderName, HeaderValue)>,
+    /// Host the headers are sent to, subdomains included
+    domain: String,
+    /// Form to log in with before the crawl starts
+    login: Option<FormLogin>,
+}
+
+/// A login form and the credentials to fill it in with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormLogin {
+    /// Page the login form is on
+    url: String,
+    /// Credentials and any other fields to post, on top of the form's hidden ones
+    fields: Vec<(String, String)>,
+}
+
+impl Auth {
+    /// Build the auth block from the command line. Field values starting
+    /// with $ are read from that environment variable, so passwords don't
+    /// have to show up in the process list.
+    pub fn from_args(args: &ArgMatches) -> Result<Self, String> {
+        let mut auth = Auth {
+            domain: args.value_of("auth-domain").unwrap_or("yahoo.com").to_ascii_lowercase(),
+            ..Auth::default()
+        };
+
+        let token = match args.value_of("auth-token") {
+            Some(token) => Some(token.to_string()),
+            None => env::var(TOKEN_VAR).ok().filter(|token| !token.is_empty()),
+        };
+        if let Some(token) = token {
+            let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|_| "Auth token is not a valid header value".to_string())?;
+            auth.headers.push((AUTHORIZATION, value));
+        }
+        for header in args.values_of("auth-header").into_iter().flatten() {
+            auth.headers.push(parse_header(header)?);
+        }
+
+        if let Some(url) = args.value_of("login-url") {
+            Url::parse(url).map_err(|_| format!("--login-url is not a valid URL: {}", url))?;
+            let fields = args.values_of("login-field").into_iter().flatten()
+                .map(parse_field)
+                .collect::<Result<_, _>>()?;
+            auth.login = Some(FormLogin { url: url.to_string(), fields });
+        }
+        Ok(auth)
+    }
+
+    /// Whether the crawl logs in, and so needs to keep cookies
+    pub fn needs_cookies(&self) -> bool {
+        self.login.is_some()
+    }
+
+    /// The auth headers for a request to `url`, none when it goes outside
+    /// the auth domain
+    pub fn headers(&self, url: &str) -> HeaderMap {
+        if self.headers.is_empty() || !self.covers(url) {
+            return HeaderMap::new();
+        }
+        self.headers.iter().map(|(name, value)| (name.clone(), value.clone())).collect()
+    }
+
+    /// Add the auth headers to a request for `url`, unless it goes outside
+    /// the auth domain
+    pub fn apply(&self, request: RequestBuilder, url: &str) -> RequestBuilder {
+        request.headers(self.headers(url))
+    }
+
+    //whether url is on the auth domain or one of its subdomains
+    fn covers(&self, url: &str) -> bool {
+        Url::parse(url).ok()
+            .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+            .is_some_and(|host| host == self.domain || host.ends_with(&format!(".{}", self.domain)))
+    }
+
+    /// Log in through the login form, if there is one. The session cookie
+    /// ends up in the client's jar, so the client must keep cookies.
+    pub fn login(&self, client: &Client, etiquette: &Etiquette) -> Result<(), String> {
+        let Some(login) = &self.login else {
+            return Ok(());
+        };
+        let page = self.apply(etiquette.apply(client.get(&login.url)), &login.url).send()
+            .and_then(|rep| Ok((rep.url().to_string(), rep.text()?)))
+            .map_err(|e| format!("Could not fetch login page {}: {}", login.url, e))?;
+        let (action, mut fields) = find_login_form(&page.0, &page.1)
+            .ok_or_else(|| format!("No login form found at {}", page.0))?;
+        //the credentials win over hidden fields of the same name
+        fields.retain(|(name, _)| !login.fields.iter().any(|(field, _)| field == name));
+        fields.extend(login.fields.iter().cloned());
+
+        let rep = self.apply(etiquette.apply(client.post(&action)), &action).form(&fields).send()
+            .map_err(|e| format!("Could not submit login form: {}", e))?;
+        if !rep.status().is_success() {
+            return Err(format!("Login failed: {} answered {}", action, rep.status()));
+        }
+        let url = rep.url().to_string();
+        let body = rep.text().map_err(|e| format!("Could not read login response: {}", e))?;
+        //a login form that's still there means the credentials were turned down
+        if find_login_form(&url, &body).is_some() {
+            return Err(format!("Login failed: still on the login form at {}", url));
+        }
+        Ok(())
+    }
+}
+
+/* find the login form on a page fetched from page_url: the first form with a
+    password field. Returns where to post it and its hidden fields, which
+    usually carry a CSRF token
+*/
+fn find_login_form(page_url: &str, html: &str) -> Option<(String, Vec<(String, String)>)> {
+    let base = Url::parse(page_url).ok()?;
+    let document = Document::from(html);
+    let form = document.find(Name("form"))
+        .find(|form| form.find(Name("input")).any(|input| input_type(&input) == "password"))?;
+    //a form without an action posts back to the page it is on
+    let action = base.join(form.attr("action").unwrap_or("")).ok()?.to_string();
+    let fields = form.find(Name("input"))
+        .filter(|input| input_type(input) == "hidden")
+        .filter_map(|input| Some((input.attr("name")?.to_string(), input.attr("value").unwrap_or("").to_string())))
+        .collect();
+    Some((action, fields))
+}
+
+fn input_type(input: &Node) -> String {
+    input.attr("type").unwrap_or("text").to_ascii_lowercase()
+}
+
+//parse a "Name: value" header
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let invalid = || format!("--auth-header is not a valid header: {}", header);
+    let (name, value) = header.split_once(':').ok_or_else(invalid)?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| invalid())?;
+    let value = HeaderValue::from_str(&resolve(value.trim())?).map_err(|_| invalid())?;
+    Ok((name, value))
+}
+
+//parse a "name=value" login field
+fn parse_field(field: &str) -> Result<(String, String), String> {
+    let (name, value) = field.split_once('=')
+        .ok_or_else(|| format!("--login-field is not name=value: {}", field))?;
+    Ok((name.to_string(), resolve(value)?))
+}
+
+//a value of $VAR is read from the environment
+fn resolve(value: &str) -> Result<String, String> {
+    match value.strip_prefix('$') {
+        Some(var) => env::var(var).map_err(|_| format!("Environment variable {} is not set", var)),
+        None => Ok(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGIN_PAGE: &str = r#"<html><body>
+        <form action="/search"><input type="text" name="p"></form>
+        <form method="post">
+            <input type="hidden" name="crumb" value="xyz789">
+            <input type="text" name="username">
+            <input type="password" name="password">
+        </form>
+    </body></html>"#;
+
+    #[test]
+    fn finds_the_login_form() {
+        assert_eq!(find_login_form("https://login.yahoo.com/account", LOGIN_PAGE), Some((
+            "https://login.yahoo.com/account".to_string(),
+            vec![("crumb".to_string(), "xyz789".to_string())],
+        )));
+        assert_eq!(find_login_form("https://login.yahoo.com/", "<form action=\"/search\"></form>"), None);
+    }
+
+    #[test]
+    fn keeps_headers_on_the_auth_domain() {
+        let auth = Auth { domain: "yahoo.com".to_string(), ..Auth::default() };
+        assert!(auth.covers("https://yahoo.com/"));
+        assert!(auth.covers("https://football.fantasysports.yahoo.com/f1"));
+        assert!(!auth.covers("https://s.yimg.com/logo.png"));
+        assert!(!auth.covers("https://notyahoo.com/"));
+
+        let (name, value) = parse_header("X-Api-Key: abc").unwrap();
+        assert_eq!(name, "x-api-key");
+        assert_eq!(value, "abc");
+        assert!(parse_header("no colon").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! A cap on how fast the crawl reads, with --max-bandwidth. The delay between
+//! requests bounds how often we ask, not how much comes back: a few large
+//! pages and images can still fill the uplink. Every response body is read
+//! through one token bucket instead, so the crawl as a whole never takes more
+//! than the given rate, however many connections are open.
+//!
+//! The bucket holds one second's worth of bytes, so a crawl that was idle can
+//! read that much at full speed before it is slowed down.
+//!
+//! Slowed down, a large page can take longer to read than the usual fetch
+//! timeout allows in all, so page fetches under a cap are only given up on
+//! when the server goes quiet.
+
+use std::io::{self, Read};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use encoding_rs::{Encoding, UTF_8};
+use reqwest::blocking::Response;
+
+/// Largest read taken from the bucket at once, small enough to keep the rate smooth
+const CHUNK: usize = 16 * 1024;
+
+/// A token bucket shared by everything that reads response bodies
+#[derive(Debug)]
+pub struct Bandwidth {
+    bytes_per_sec: u64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes that can be read right away, negative when reads are booked ahead
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bandwidth {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let bucket = Bucket { tokens: bytes_per_sec as f64, refilled: Instant::now() };
+        Self { bytes_per_sec: bytes_per_sec.max(1), bucket: Mutex::new(bucket) }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    /// Book `bytes` just read, waiting until the bucket has them. The wait
+    /// happens outside the lock, so readers queue up by booking in turn.
+    pub fn take(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let rate = self.bytes_per_sec as f64;
+            let now = Instant::now();
+            bucket.tokens = (bucket.tokens + now.duration_since(bucket.refilled).as_secs_f64() * rate).min(rate);
+            bucket.refilled = now;
+            bucket.tokens -= bytes as f64;
+            (bucket.tokens < 0.0).then(|| Duration::from_secs_f64(-bucket.tokens / rate))
+        };
+        if let Some(wait) = wait {
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// Read the whole body of a response, no faster than `limit` allows when there is one
+pub fn read_body(mut rep: Response, limit: Option<&Bandwidth>) -> io::Result<Vec<u8>> {
+    let mut body = Vec::with_capacity(rep.content_length().unwrap_or(0).min(1 << 20) as usize);
+    let Some(limit) = limit else {
+        rep.read_to_end(&mut body)?;
+        return Ok(body);
+    };
+    let mut chunk = [0; CHUNK];
+    loop {
+        let read = match rep.read(&mut chunk) {
+            Ok(0) => return Ok(body),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        body.extend_from_slice(&chunk[..read]);
+        limit.take(read);
+    }
+}
+
+/// Decode a body the way Response::text does: in the charset the Content-Type
+/// names, UTF-8 when it names none or one we don't know. Also says whether
+/// any of it wasn't valid in that charset.
+pub fn decode(body: &[u8], content_type: Option<&str>) -> (String, bool) {
+    let encoding = content_type.and_then(charset).and_then(|charset| Encoding::for_label(charset.as_bytes())).unwrap_or(UTF_8);
+    let (text, _, malformed) = encoding.decode(body);
+    (text.into_owned(), malformed)
+}
+
+/// The charset parameter of a Content-Type, ie: utf-8 in "text/html; charset=utf-8"
+pub fn charset(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim().eq_ignore_ascii_case("charset").then(|| value.trim().trim_matches('"'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_reads_to_the_rate() {
+        let bandwidth = Bandwidth::new(100_000);
+        let started = Instant::now();
+        //the first second's worth comes out of the full bucket, the next 50KB take half a second
+        bandwidth.take(100_000);
+        bandwidth.take(50_000);
+        let took = started.elapsed();
+        assert!(took >= Duration::from_millis(450) && took < Duration::from_millis(1500), "took {:?}", took);
+    }
+
+    #[test]
+    fn decodes_the_declared_charset() {
+        assert_eq!(decode(b"caf\xe9", Some("text/html; charset=ISO-8859-1")), ("café".to_string(), false));
+        assert_eq!(decode("café".as_bytes(), Some("text/html")), ("café".to_string(), false));
+        assert_eq!(decode("café".as_bytes(), None), ("café".to_string(), false));
+        assert_eq!(decode(b"caf\xe9", Some("text/html; charset=\"utf-8\"")), ("caf\u{fffd}".to_string(), true));
+    }
+}
@@ -0,0 +1,182 @@
+//! URLs and hosts the crawler should never fetch, kept in a file across runs.
+//!
+//! The file is plain text with one record per line, so it can be edited by
+//! hand as well as appended to by the crawler:
+//!
+//! ```text
+//! # comments and blank lines are ignored
+//! host <host>      the host and all of its subdomains are blacklisted
+//! url <pattern>    URLs matching the pattern are blacklisted, * matches anything
+//! fail <url>       the url failed in one run
+//! ok <url>         the url was fetched fine, forget its earlier failures
+//! ```
+//!
+//! A URL that fails in enough runs without succeeding in between gets a `url`
+//! record of its own, so known-bad endpoints stop being retried.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Blacklisted hosts and URL patterns, plus the failure tally of URLs that
+/// aren't blacklisted yet
+#[derive(Debug, Default)]
+pub struct Blacklist {
+    hosts: Vec<String>,
+    patterns: Vec<String>,
+    failures: HashMap<String, u32>,
+    path: Option<PathBuf>,
+}
+
+impl Blacklist {
+    /// Load the blacklist at `path`, new records get appended to it. A missing
+    /// file is an empty blacklist.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut blacklist = Self { path: Some(path.to_path_buf()), ..Default::default() };
+        if path.exists() {
+            for line in fs::read_to_string(path)?.lines() {
+                blacklist.apply(line.trim());
+            }
+        }
+        Ok(blacklist)
+    }
+
+    fn apply(&mut self, line: &str) {
+        let Some((kind, value)) = line.split_once(' ') else {
+            return;
+        };
+        let value = value.trim().to_string();
+        match kind {
+            "host" => self.hosts.push(value.to_ascii_lowercase()),
+            "url" => {
+                self.failures.remove(&value);
+                self.patterns.push(value);
+            },
+            "fail" => *self.failures.entry(value).or_default() += 1,
+            "ok" => {
+                self.failures.remove(&value);
+            },
+            _ => {},
+        }
+    }
+
+    //add a record to the file, a blacklist without one only lives in memory
+    fn append(&mut self, kind: &str, value: &str) -> io::Result<()> {
+        self.apply(&format!("{} {}", kind, value));
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{} {}", kind, value)?;
+        }
+        Ok(())
+    }
+
+    pub fn hosts(&self) -> &[String] {
+        &self.hosts
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Whether the url is on a blacklisted host or matches a blacklisted pattern
+    pub fn is_blocked(&self, url: &str) -> bool {
+        let host = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_ascii_lowercase));
+        let on_host = host.is_some_and(|host| {
+            self.hosts.iter().any(|blocked| host == *blocked || host.ends_with(&format!(".{}", blocked)))
+        });
+        on_host || self.patterns.iter().any(|pattern| glob_match(pattern, url))
+    }
+
+    /// Blacklist a host by hand
+    pub fn add_host(&mut self, host: &str) -> io::Result<()> {
+        self.append("host", host)
+    }
+
+    /// Blacklist a URL pattern by hand
+    pub fn add_pattern(&mut self, pattern: &str) -> io::Result<()> {
+        self.append("url", pattern)
+    }
+
+    /// Note that the url failed in this run. Once it has failed `threshold`
+    /// times it is blacklisted, returns whether that happened now.
+    pub fn record_failure(&mut self, url: &str, threshold: u32) -> io::Result<bool> {
+        if self.is_blocked(url) {
+            return Ok(false);
+        }
+        self.append("fail", url)?;
+        if self.failures.get(url).is_some_and(|&count| count >= threshold) {
+            self.append("url", url)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Note that the url was fetched fine, so its earlier failures don't count
+    pub fn record_success(&mut self, url: &str) -> io::Result<()> {
+        if self.failures.contains_key(url) {
+            self.append("ok", url)?;
+        }
+        Ok(())
+    }
+}
+
+/// Match text against a pattern where * stands for any run of characters
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        //no * at all, the pattern has to match exactly
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_hosts_patterns_and_repeat_failures() {
+        let path = std::env::temp_dir().join(format!("blacklist-test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        fs::write(&path, "# known bad\nhost beap.gemini.yahoo.com\nurl https://consent.yahoo.com/*\n").unwrap();
+
+        let mut blacklist = Blacklist::open(&path).unwrap();
+        assert!(blacklist.is_blocked("https://beap.gemini.yahoo.com/mbclk?x=1"));
+        assert!(blacklist.is_blocked("https://a.beap.gemini.yahoo.com/"));
+        assert!(blacklist.is_blocked("https://consent.yahoo.com/v2/collectConsent?sessionId=1"));
+        assert!(!blacklist.is_blocked("https://news.yahoo.com/"));
+
+        let flaky = "https://news.yahoo.com/flaky.html";
+        assert!(!blacklist.record_failure(flaky, 2).unwrap());
+        blacklist.record_success(flaky).unwrap();
+        assert!(!blacklist.record_failure(flaky, 2).unwrap());
+        assert!(blacklist.record_failure(flaky, 2).unwrap());
+
+        //everything made it to the file
+        let reopened = Blacklist::open(&path).unwrap();
+        assert!(reopened.is_blocked(flaky));
+        assert!(!reopened.is_blocked("https://news.yahoo.com/flaky.html?page=2"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("https://*.yahoo.com/*/track*", "https://s.yahoo.com/a/b/track?id=1"));
+        assert!(glob_match("exact", "exact"));
+        assert!(!glob_match("exact", "exactly"));
+        assert!(!glob_match("https://*/ads", "https://yahoo.com/ads/more"));
+    }
+}
@@ -0,0 +1,93 @@
+//! Keeping every file a crawl writes under one directory. With
+//! --confine-output, output files land in that directory instead of the
+//! current one, and any path given on the command line for a file the crawl
+//! writes has to resolve inside it, `..` and symlinks included.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Where a crawl may write
+#[derive(Debug, Default, Clone)]
+pub struct Confinement {
+    //canonical, None when writes go wherever their paths point
+    root: Option<PathBuf>,
+}
+
+impl Confinement {
+    /// Confine writes to `root`, creating it if needed
+    pub fn new(root: &Path) -> io::Result<Self> {
+        fs::create_dir_all(root)?;
+        Ok(Self { root: Some(root.canonicalize()?) })
+    }
+
+    /// Where a file the crawl writes at `path` really goes. Relative paths
+    /// are taken from the confinement directory, and the result has to stay
+    /// inside it. Without confinement, the path as given.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> Result<PathBuf, String> {
+        let path = path.as_ref();
+        let Some(root) = &self.root else {
+            return Ok(path.to_path_buf());
+        };
+        let outside = || format!("{} is outside the --confine-output directory {}", path.display(), root.display());
+        let resolved = normalize(&root.join(path)).ok_or_else(outside)?;
+        if !resolved.starts_with(root) {
+            return Err(outside());
+        }
+        //a symlink inside the directory may still point out of it
+        let existing = resolved.ancestors().find(|ancestor| ancestor.exists()).unwrap_or(root);
+        let real = existing.canonicalize().map_err(|e| format!("Could not resolve {}: {}", existing.display(), e))?;
+        if !real.starts_with(root) {
+            return Err(outside());
+        }
+        Ok(resolved)
+    }
+}
+
+//resolve . and .. without touching the file system, None if .. climbs past the root of the path
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            },
+            component => normalized.push(component),
+        }
+    }
+    Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_writes_inside_the_directory() {
+        let dir = std::env::temp_dir().join(format!("confine-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let confinement = Confinement::new(&dir.join("out")).unwrap();
+        let root = dir.join("out").canonicalize().unwrap();
+
+        assert_eq!(confinement.resolve("visited.json").unwrap(), root.join("visited.json"));
+        assert_eq!(confinement.resolve("state/./seen.db").unwrap(), root.join("state/seen.db"));
+        assert_eq!(confinement.resolve("a/../log.txt").unwrap(), root.join("log.txt"));
+        assert_eq!(confinement.resolve(root.join("frontier.log")).unwrap(), root.join("frontier.log"));
+        assert!(confinement.resolve("../escaped.json").is_err());
+        assert!(confinement.resolve("a/../../escaped.json").is_err());
+        assert!(confinement.resolve("/etc/passwd").is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&dir, root.join("link")).unwrap();
+            assert!(confinement.resolve("link/escaped.json").is_err());
+        }
+
+        let unconfined = Confinement::default();
+        assert_eq!(unconfined.resolve("../anywhere.json").unwrap(), Path::new("../anywhere.json"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+//! Yahoo's consent (GDPR) interstitial.
+//!
+//! Crawls from the EU get redirected to consent.yahoo.com before they see any
+//! content. The interstitial is either recorded as such, so it doesn't pass
+//! for the page it stands in front of, or answered by posting its form. The
+//! answer lives in cookies on yahoo.com, which are kept in a file so later
+//! runs don't see the interstitial again.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use reqwest::blocking::{Client, Response};
+use reqwest::cookie::{CookieStore, Jar};
+use select::document::Document;
+use select::predicate::Name;
+use url::Url;
+use crate::etiquette::Etiquette;
+
+/// The cookies that record consent are set for this site
+const COOKIE_URL: &str = "https://www.yahoo.com/";
+
+/// What to do about consent interstitials
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsentMode {
+    /// Record the page as a consent wall without following it
+    Mark,
+    /// Accept the consent form and crawl the page behind it
+    Agree,
+    /// Decline the consent form and crawl the page behind it
+    Reject,
+}
+
+impl ConsentMode {
+    pub fn from_name(name: &str) -> Option<ConsentMode> {
+        match name {
+            "mark" => Some(ConsentMode::Mark),
+            "agree" => Some(ConsentMode::Agree),
+            "reject" => Some(ConsentMode::Reject),
+            _ => None,
+        }
+    }
+
+    //name of the form button for this answer
+    fn button(self) -> Option<&'static str> {
+        match self {
+            ConsentMode::Mark => None,
+            ConsentMode::Agree => Some("agree"),
+            ConsentMode::Reject => Some("reject"),
+        }
+    }
+}
+
+/// Whether a fetched page is the consent interstitial rather than the page
+/// that was asked for. `url` is where the request ended up after redirects.
+pub fn is_consent_page(url: &str, html: &str) -> bool {
+    let consent_host = Url::parse(url).ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .is_some_and(|host| host == "consent.yahoo.com" || host == "guce.yahoo.com");
+    consent_host || html.contains("collectConsent")
+}
+
+/// The consent form of an interstitial, ready to be posted
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConsentForm {
+    action: String,
+    fields: Vec<(String, String)>,
+}
+
+impl ConsentForm {
+    /// Find the consent form on an interstitial fetched from `page_url`
+    pub fn find(page_url: &str, html: &str) -> Option<ConsentForm> {
+        let base = Url::parse(page_url).ok()?;
+        let document = Document::from(html);
+        let form = document.find(Name("form"))
+            .find(|form| form.attr("action").is_some_and(|action| action.contains("consent") || action.contains("Consent")))?;
+        let action = base.join(form.attr("action")?).ok()?.to_string();
+        //hidden inputs carry the session and where to go afterwards
+        let fields = form.find(Name("input"))
+            .filter(|input| input.attr("type").is_some_and(|t| t.eq_ignore_ascii_case("hidden")))
+            .filter_map(|input| Some((input.attr("name")?.to_string(), input.attr("value").unwrap_or("").to_string())))
+            .collect();
+        Some(ConsentForm { action, fields })
+    }
+
+    /// Answer the form the way `mode` says. The response is the page the
+    /// interstitial was hiding, if the answer was taken.
+    pub fn submit(&self, mode: ConsentMode, client: &Client, etiquette: &Etiquette) -> Option<reqwest::Result<Response>> {
+        let button = mode.button()?;
+        let mut fields = self.fields.clone();
+        fields.push((button.to_string(), button.to_string()));
+        Some(etiquette.apply(client.post(&self.action)).form(&fields).send())
+    }
+}
+
+/// Load cookies saved by an earlier run into the jar. A missing file is fine.
+pub fn load_cookies(jar: &Jar, path: &Path) -> io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let url = Url::parse(COOKIE_URL).unwrap();
+    for cookie in fs::read_to_string(path)?.lines().filter(|line| !line.trim().is_empty()) {
+        jar.add_cookie_str(&format!("{}; Domain=yahoo.com; Path=/", cookie.trim()), &url);
+    }
+    Ok(())
+}
+
+/// Save the yahoo.com cookies in the jar, one name=value per line
+pub fn save_cookies(jar: &Arc<Jar>, path: &Path) -> io::Result<()> {
+    let url = Url::parse(COOKIE_URL).unwrap();
+    let mut file = fs::File::create(path)?;
+    if let Some(header) = jar.cookies(&url) {
+        for cookie in header.to_str().unwrap_or("").split("; ").filter(|cookie| !cookie.is_empty()) {
+            writeln!(file, "{}", cookie)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERSTITIAL: &str = r#"<html><body>
+        <form method="post" action="/v2/collectConsent?sessionId=3_cc-session">
+            <input type="hidden" name="csrfToken" value="abc123">
+            <input type="hidden" name="originalDoneUrl" value="https://news.yahoo.com/">
+            <input type="text" name="search">
+            <button type="submit" name="agree" value="agree">Accept all</button>
+            <button type="submit" name="reject" value="reject">Reject all</button>
+        </form>
+    </body></html>"#;
+
+    #[test]
+    fn finds_the_consent_form() {
+        let url = "https://consent.yahoo.com/v2/collectConsent?sessionId=3_cc-session";
+        assert!(is_consent_page(url, INTERSTITIAL));
+        assert!(is_consent_page("https://news.yahoo.com/", INTERSTITIAL));
+        assert!(!is_consent_page("https://news.yahoo.com/", "<html>news</html>"));
+
+        assert_eq!(ConsentForm::find(url, INTERSTITIAL), Some(ConsentForm {
+            action: "https://consent.yahoo.com/v2/collectConsent?sessionId=3_cc-session".to_string(),
+            fields: vec![
+                ("csrfToken".to_string(), "abc123".to_string()),
+                ("originalDoneUrl".to_string(), "https://news.yahoo.com/".to_string()),
+            ],
+        }));
+        assert_eq!(ConsentForm::find(url, "<form action=\"/search\"></form>"), None);
+    }
+}
@@ -0,0 +1,265 @@
+//! A small HTTP API on localhost for steering a running crawl, started with
+//! --control. Requests are read on a thread of their own and handed to the
+//! crawl loop, which answers them between fetches, so an answer can take as
+//! long as the fetch in progress.
+//!
+//! ```text
+//! GET  /status            paused or not, queue depth, URLs in flight, counts so far
+//! GET  /queue?limit=N     queued URLs, front first (default limit: 100)
+//! POST /pause             stop leasing URLs, pages in flight still finish
+//! POST /resume            carry on
+//! POST /inject            queue the URLs in the body, one per line
+//! POST /remove            drop the URLs in the body, one per line, from the queue
+//! ```
+//!
+//! The crawl still ends once its queue runs dry, there is nothing to inject into after that.
+//!
+//! Requests have to name the API's own address in their Host header and carry
+//! no Origin header. A web page open in a browser on the same machine can
+//! still send requests to localhost, this turns them down, along with those
+//! from names an attacker rebinds to 127.0.0.1.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use serde_json::{json, Value};
+use url::Url;
+
+/// Queued URLs listed by /queue without a limit
+const DEFAULT_QUEUE_LIMIT: usize = 100;
+
+/// Largest request body read, enough for a long list of URLs to inject
+const MAX_BODY: usize = 1 << 20;
+
+/// How long a client waits for the crawl loop to get to its request
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What a client asked of the crawl
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Status,
+    Queue { limit: usize },
+    Pause,
+    Resume,
+    Inject(Vec<String>),
+    Remove(Vec<String>),
+}
+
+/// A command waiting for the crawl loop to answer it
+#[derive(Debug)]
+pub struct Request {
+    pub command: Command,
+    reply: Sender<Value>,
+}
+
+impl Request {
+    /// Send the answer back to the client, who may have given up waiting
+    pub fn answer(self, body: Value) {
+        let _ = self.reply.send(body);
+    }
+}
+
+/// The receiving end of the control API
+#[derive(Debug)]
+pub struct Control {
+    requests: Receiver<Request>,
+    addr: SocketAddr,
+}
+
+impl Control {
+    /// Listen on addr, which has to be a loopback address: anyone who can
+    /// reach the API can point the crawler anywhere
+    pub fn listen(addr: &str) -> Result<Self, String> {
+        let addr: SocketAddr = addr.parse().map_err(|_| format!("{} is not an address and port, ie: 127.0.0.1:7878", addr))?;
+        if !addr.ip().is_loopback() {
+            return Err(format!("{} is not a loopback address, the control API only listens on localhost", addr));
+        }
+        let listener = TcpListener::bind(addr).map_err(|e| format!("Could not listen on {}: {}", addr, e))?;
+        let addr = listener.local_addr().map_err(|e| e.to_string())?;
+        let (requests, queue) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                //one client at a time, the crawl loop answers one request at a time anyway
+                if let Err(e) = serve(stream, addr, &requests) {
+                    status!("Control API: {}", e);
+                }
+            }
+        });
+        Ok(Self { requests: queue, addr })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// A request waiting to be answered, if any
+    pub fn try_recv(&self) -> Option<Request> {
+        self.requests.try_recv().ok()
+    }
+
+    /// Wait up to `timeout` for a request
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Request> {
+        self.requests.recv_timeout(timeout).ok()
+    }
+}
+
+//read one request off the connection, pass it on and write back the answer
+fn serve(stream: TcpStream, addr: SocketAddr, requests: &Sender<Request>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (status, body) = match read_request(&mut reader, addr) {
+        Ok(command) => {
+            let (reply, answer) = mpsc::channel();
+            if requests.send(Request { command, reply }).is_err() {
+                (503, json!({"error": "the crawl is over"}))
+            } else {
+                match answer.recv_timeout(ANSWER_TIMEOUT) {
+                    Ok(body) => (200, body),
+                    Err(_) => (503, json!({"error": "the crawl did not get to the request in time"})),
+                }
+            }
+        },
+        Err((status, message)) => (status, json!({"error": message})),
+    };
+    write_response(stream, status, &body)
+}
+
+//the command an HTTP request stands for, or the status and message to turn it down with
+//only requests addressed to addr itself are taken, browsers add an Origin to anything a page sends
+fn read_request(reader: &mut impl BufRead, addr: SocketAddr) -> Result<Command, (u16, String)> {
+    let bad_request = |e: io::Error| (400, e.to_string());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).map_err(bad_request)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err((400, "malformed request line".to_string()));
+    };
+    let mut content_length = 0;
+    let (mut host, mut origin) = (None, false);
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).map_err(bad_request)? == 0 {
+            break;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(|_| (400, "bad Content-Length".to_string()))?;
+            } else if name.eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_ascii_lowercase());
+            } else if name.eq_ignore_ascii_case("origin") {
+                origin = true;
+            }
+        }
+    }
+    if origin {
+        return Err((403, "requests from web pages are not allowed".to_string()));
+    }
+    let allowed_hosts = [addr.to_string(), format!("localhost:{}", addr.port())];
+    match host {
+        Some(host) if allowed_hosts.contains(&host) => {},
+        Some(host) => return Err((403, format!("Host {} is not {}", host, addr))),
+        None => return Err((400, "no Host header".to_string())),
+    }
+    if content_length > MAX_BODY {
+        return Err((413, format!("bodies are limited to {} bytes", MAX_BODY)));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).map_err(bad_request)?;
+    let body = String::from_utf8_lossy(&body);
+    let urls = || body.lines().map(str::trim).filter(|line| !line.is_empty()).map(str::to_string).collect();
+
+    let target = Url::parse(&format!("http://localhost{}", target)).map_err(|_| (400, format!("bad path {}", target)))?;
+    match (method, target.path()) {
+        ("GET", "/status") => Ok(Command::Status),
+        ("GET", "/queue") => {
+            let limit = match target.query_pairs().find(|(name, _)| name == "limit") {
+                Some((_, limit)) => limit.parse().map_err(|_| (400, format!("limit {} is not a number", limit)))?,
+                None => DEFAULT_QUEUE_LIMIT,
+            };
+            Ok(Command::Queue { limit })
+        },
+        ("POST", "/pause") => Ok(Command::Pause),
+        ("POST", "/resume") => Ok(Command::Resume),
+        ("POST", "/inject") => Ok(Command::Inject(urls())),
+        ("POST", "/remove") => Ok(Command::Remove(urls())),
+        (_, "/status" | "/queue" | "/pause" | "/resume" | "/inject" | "/remove") => Err((405, format!("{} is not allowed on {}", method, target.path()))),
+        (_, path) => Err((404, format!("no such endpoint {}", path))),
+    }
+}
+
+fn write_response(mut stream: TcpStream, status: u16, body: &Value) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Service Unavailable",
+    };
+    let body = format!("{}\n", body);
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, reason, body.len(), body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn parse(request: &str) -> Result<Command, (u16, String)> {
+        read_request(&mut request.as_bytes(), "127.0.0.1:7878".parse().unwrap())
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse("GET /status HTTP/1.1\r\nHost: 127.0.0.1:7878\r\n\r\n"), Ok(Command::Status));
+        assert_eq!(parse("GET /queue HTTP/1.1\r\nHost: localhost:7878\r\n\r\n"), Ok(Command::Queue { limit: DEFAULT_QUEUE_LIMIT }));
+        assert_eq!(parse("GET /queue?limit=5 HTTP/1.1\r\nHost: 127.0.0.1:7878\r\n\r\n"), Ok(Command::Queue { limit: 5 }));
+        assert_eq!(parse("POST /pause HTTP/1.1\r\nHost: 127.0.0.1:7878\r\n\r\n"), Ok(Command::Pause));
+        let inject = "POST /inject HTTP/1.1\r\nHost: 127.0.0.1:7878\r\nContent-Length: 51\r\n\r\nhttps://news.yahoo.com/\n\nhttps://sports.yahoo.com/\n";
+        assert_eq!(parse(inject), Ok(Command::Inject(vec!["https://news.yahoo.com/".to_string(), "https://sports.yahoo.com/".to_string()])));
+        assert_eq!(parse("GET /pause HTTP/1.1\r\nHost: 127.0.0.1:7878\r\n\r\n").unwrap_err().0, 405);
+        assert_eq!(parse("GET /secrets HTTP/1.1\r\nHost: 127.0.0.1:7878\r\n\r\n").unwrap_err().0, 404);
+        assert_eq!(parse("GET /queue?limit=all HTTP/1.1\r\nHost: 127.0.0.1:7878\r\n\r\n").unwrap_err().0, 400);
+        assert_eq!(parse("nonsense\r\n\r\n").unwrap_err().0, 400);
+    }
+
+    #[test]
+    fn turns_down_requests_from_web_pages() {
+        //a page posting to the API, without a preflight
+        let from_page = "POST /inject HTTP/1.1\r\nHost: 127.0.0.1:7878\r\nOrigin: https://evil.example\r\nContent-Type: text/plain\r\nContent-Length: 19\r\n\r\nhttp://10.0.0.1/\n\n\n";
+        assert_eq!(parse(from_page).unwrap_err().0, 403);
+        //a name rebound to 127.0.0.1
+        assert_eq!(parse("POST /pause HTTP/1.1\r\nHost: evil.example:7878\r\n\r\n").unwrap_err().0, 403);
+        assert_eq!(parse("POST /pause HTTP/1.1\r\nHost: 127.0.0.1:8080\r\n\r\n").unwrap_err().0, 403);
+        assert_eq!(parse("POST /pause HTTP/1.1\r\n\r\n").unwrap_err().0, 400);
+    }
+
+    #[test]
+    fn answers_over_http() {
+        assert!(Control::listen("0.0.0.0:0").is_err());
+        let control = Control::listen("127.0.0.1:0").unwrap();
+        let addr = control.addr();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(format!("POST /pause HTTP/1.1\r\nHost: {}\r\n\r\n", addr).as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        let request = control.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request.command, Command::Pause);
+        request.answer(json!({"paused": true}));
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n{\"paused\":true}\n"));
+    }
+}
@@ -0,0 +1,152 @@
+//! Images referenced from CSS, in stylesheets, <style> elements and style
+//! attributes. A background image never shows up as an <img>, so without
+//! this a page whose pictures are all backgrounds looks like it has none.
+//! Only properties that paint an image count, a url() in a @font-face src
+//! is a font.
+
+use std::borrow::Cow;
+
+/// Properties whose url() values are images
+const IMAGE_PROPERTIES: &[&str] = &[
+    "background",
+    "background-image",
+    "border-image",
+    "border-image-source",
+    "content",
+    "cursor",
+    "list-style",
+    "list-style-image",
+    "mask",
+    "mask-image",
+];
+
+/// What a piece of CSS refers to, URLs as written
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CssRefs {
+    pub images: Vec<String>,
+    /// Stylesheets pulled in with @import
+    pub imports: Vec<String>,
+}
+
+/// The images and imports in a stylesheet, or in the declarations of a style attribute
+pub fn refs(css: &str) -> CssRefs {
+    let css = strip_comments(css);
+    let mut refs = CssRefs::default();
+    for statement in statements(&css) {
+        let statement = statement.trim();
+        if let Some(import) = statement.strip_prefix("@import") {
+            //either @import url(a.css) or @import "a.css", maybe followed by a media query
+            let import = import.trim_start();
+            match urls(import).into_iter().next() {
+                Some(url) => refs.imports.push(url),
+                None => refs.imports.extend(quoted(import)),
+            }
+            continue;
+        }
+        let Some((property, value)) = statement.split_once(':') else {
+            continue;
+        };
+        if IMAGE_PROPERTIES.contains(&property.trim().to_ascii_lowercase().as_str()) {
+            refs.images.extend(urls(value));
+        }
+    }
+    refs
+}
+
+//drop /* comments */, they may well hold commented out url()s
+fn strip_comments(css: &str) -> Cow<'_, str> {
+    if !css.contains("/*") {
+        return Cow::Borrowed(css);
+    }
+    let mut stripped = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        rest = rest[start + 2..].find("*/").map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    stripped.push_str(rest);
+    Cow::Owned(stripped)
+}
+
+//split at ; { and }, except inside strings and parentheses where a data: URI may have a ;
+fn statements(css: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let (mut start, mut depth, mut quote) = (0, 0usize, None);
+    for (i, c) in css.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {},
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth = depth.saturating_sub(1),
+            (None, ';' | '{' | '}') if depth == 0 => {
+                statements.push(&css[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    statements.push(&css[start..]);
+    statements
+}
+
+//every url(...) in a value, quotes taken off
+fn urls(value: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    let lower = value.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(start) = lower[from..].find("url(").map(|start| from + start + 4) {
+        let Some(end) = value[start..].find(')').map(|end| start + end) else {
+            break;
+        };
+        let url = value[start..end].trim().trim_matches(|c| c == '"' || c == '\'').trim();
+        if !url.is_empty() {
+            urls.push(url.to_string());
+        }
+        from = end + 1;
+    }
+    urls
+}
+
+//the first quoted string in a value
+fn quoted(value: &str) -> Option<String> {
+    let open = value.find(['"', '\''])?;
+    let quote = value[open..].chars().next()?;
+    let close = value[open + 1..].find(quote)?;
+    Some(value[open + 1..open + 1 + close].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_background_images() {
+        let css = r#"
+            @import url("theme.css") screen;
+            @import 'print.css' print;
+            /* .old { background: url(old.png) } */
+            .hero{background:#000 URL( "https://s.yimg.com/hero.jpg" ) no-repeat;color:red}
+            .icon { background-image: url(data:image/svg+xml;base64,PHN2Zz4=), url('/icons/star.png') }
+            @font-face { font-family: x; src: url(font.woff2) format("woff2") }
+            ul { list-style: square url(bullet.gif) }
+            a:hover { cursor: pointer }
+        "#;
+        assert_eq!(refs(css), CssRefs {
+            images: vec![
+                "https://s.yimg.com/hero.jpg".to_string(),
+                "data:image/svg+xml;base64,PHN2Zz4=".to_string(),
+                "/icons/star.png".to_string(),
+                "bullet.gif".to_string(),
+            ],
+            imports: vec!["theme.css".to_string(), "print.css".to_string()],
+        });
+    }
+
+    #[test]
+    fn reads_style_attributes() {
+        assert_eq!(refs("background-image: url(/a.png); width: 10px").images, ["/a.png"]);
+        assert_eq!(refs("color: red").images, Vec::<String>::new());
+        assert_eq!(refs("background: url(unterminated").images, Vec::<String>::new());
+    }
+}
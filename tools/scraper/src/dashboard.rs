@@ -0,0 +1,211 @@
+//! Live terminal dashboard for long crawls, shown with --tui.
+//!
+//! The dashboard takes over the whole terminal, so while it is up the
+//! `status!` messages the crawl normally prints are swallowed.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::widgets::{Block, List, Paragraph, Sparkline};
+use ratatui::{DefaultTerminal, Frame};
+
+/// Redraw at most this often, a fast crawl would otherwise spend its time drawing
+const REDRAW_EVERY: Duration = Duration::from_millis(100);
+
+/// Seconds of throughput history kept for the graph
+const HISTORY_SECS: usize = 300;
+
+/// Failed URLs listed in the recent failures pane
+const RECENT_FAILURES: usize = 50;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether a dashboard currently owns the terminal
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Crawl progress drawn to the terminal as it happens
+pub struct Dashboard {
+    terminal: DefaultTerminal,
+    last_draw: Option<Instant>,
+    stats: Stats,
+    quit: bool,
+}
+
+//what the dashboard shows, kept apart from the terminal so drawing can borrow both
+struct Stats {
+    started: Instant,
+    pages: usize,
+    bytes: usize,
+    images: usize,
+    queued: usize,
+    page_in_flight: Option<String>,
+    image_in_flight: Option<String>,
+    throughput: VecDeque<u64>, //pages finished in each second of the crawl, newest last
+    throughput_second: u64,    //elapsed second the newest bucket counts
+    errors: BTreeMap<&'static str, usize>,
+    recent_failures: VecDeque<String>,
+}
+
+impl Dashboard {
+    /// Switch the terminal to the dashboard. It is restored when the dashboard
+    /// is dropped.
+    pub fn start() -> io::Result<Self> {
+        let terminal = ratatui::try_init()?;
+        ACTIVE.store(true, Ordering::Relaxed);
+        Ok(Self {
+            terminal,
+            last_draw: None,
+            stats: Stats {
+                started: Instant::now(),
+                pages: 0,
+                bytes: 0,
+                images: 0,
+                queued: 0,
+                page_in_flight: None,
+                image_in_flight: None,
+                throughput: VecDeque::from([0]),
+                throughput_second: 0,
+                errors: BTreeMap::new(),
+                recent_failures: VecDeque::new(),
+            },
+            quit: false,
+        })
+    }
+
+    /// A page is being fetched with `queued` more URLs waiting behind it
+    pub fn fetching(&mut self, url: &str, queued: usize) {
+        self.stats.page_in_flight = Some(url.to_string());
+        self.stats.image_in_flight = None;
+        self.stats.queued = queued;
+        self.refresh();
+    }
+
+    pub fn fetching_image(&mut self, url: &str) {
+        self.stats.image_in_flight = Some(url.to_string());
+        self.refresh();
+    }
+
+    pub fn page_done(&mut self, size: usize) {
+        self.stats.pages += 1;
+        self.stats.bytes += size;
+        self.stats.advance_throughput();
+        if let Some(count) = self.stats.throughput.back_mut() {
+            *count += 1;
+        }
+        self.refresh();
+    }
+
+    pub fn image_done(&mut self) {
+        self.stats.images += 1;
+        self.stats.image_in_flight = None;
+        self.refresh();
+    }
+
+    /// Tally a failure of the given kind, ie: "page", "image", "tls"
+    pub fn failed(&mut self, kind: &'static str, url: &str) {
+        *self.stats.errors.entry(kind).or_default() += 1;
+        self.stats.recent_failures.push_front(format!("{:<5} {}", kind, url));
+        self.stats.recent_failures.truncate(RECENT_FAILURES);
+        self.refresh();
+    }
+
+    /// Whether the user pressed q, Esc or Ctrl-C. Raw mode keeps Ctrl-C from
+    /// interrupting the process, so the crawl has to check for it.
+    pub fn quit_requested(&mut self) -> bool {
+        while let Ok(true) = event::poll(Duration::ZERO) {
+            let Ok(Event::Key(key)) = event::read() else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if ctrl_c || key.code == KeyCode::Char('q') || key.code == KeyCode::Esc {
+                self.quit = true;
+            }
+        }
+        self.quit
+    }
+
+    fn refresh(&mut self) {
+        if self.last_draw.is_some_and(|last| last.elapsed() < REDRAW_EVERY) {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+        self.stats.advance_throughput();
+        let stats = &self.stats;
+        //a failed draw only costs us one frame
+        let _ = self.terminal.draw(|frame| stats.draw(frame));
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        ACTIVE.store(false, Ordering::Relaxed);
+        ratatui::restore();
+    }
+}
+
+impl Stats {
+    //start new per-second buckets for the seconds that went by
+    fn advance_throughput(&mut self) {
+        let now = self.started.elapsed().as_secs();
+        while self.throughput_second < now {
+            self.throughput.push_back(0);
+            self.throughput_second += 1;
+        }
+        while self.throughput.len() > HISTORY_SECS {
+            self.throughput.pop_front();
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [summary, graph, details] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Length(8),
+            Constraint::Min(6),
+        ]).areas(frame.area());
+        let [current, failures] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(details);
+
+        let elapsed = self.started.elapsed().as_secs();
+        let rate = self.pages as f64 / elapsed.max(1) as f64;
+        let summary_text = format!(
+            "Elapsed {:02}:{:02}:{:02}   Pages {}   Queued {}   Images {}   Downloaded {:.1} MB   {:.2} pages/s\nPress q to stop",
+            elapsed / 3600, elapsed / 60 % 60, elapsed % 60,
+            self.pages, self.queued, self.images, self.bytes as f64 / 1_000_000.0, rate,
+        );
+        frame.render_widget(Paragraph::new(summary_text).block(Block::bordered().title(" Crawl ")), summary);
+
+        //newest seconds on the right, as many as fit
+        let width = graph.width.saturating_sub(2) as usize;
+        let history: Vec<u64> = self.throughput.iter().skip(self.throughput.len().saturating_sub(width)).copied().collect();
+        frame.render_widget(
+            Sparkline::default().block(Block::bordered().title(" Pages per second ")).data(&history),
+            graph,
+        );
+
+        let mut lines = vec![
+            format!("Page:  {}", self.page_in_flight.as_deref().unwrap_or("-")),
+            format!("Image: {}", self.image_in_flight.as_deref().unwrap_or("-")),
+            String::new(),
+            "Errors:".to_string(),
+        ];
+        if self.errors.is_empty() {
+            lines.push("    none".to_string());
+        }
+        for (kind, count) in &self.errors {
+            lines.push(format!("    {:<6} {}", kind, count));
+        }
+        frame.render_widget(Paragraph::new(lines.join("\n")).block(Block::bordered().title(" In flight ")), current);
+
+        frame.render_widget(
+            List::new(self.recent_failures.iter().map(String::as_str)).block(Block::bordered().title(" Recent failures ")),
+            failures,
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! data: URIs (RFC 2397): images written into the page itself instead of
+//! linked from it, `data:image/png;base64,iVBORw0KGgo...`. There's nothing to
+//! fetch, so instead of going to the image queue they're recorded on the page
+//! with the media type they declare and the size of their data.
+
+use serde::{Deserialize, Serialize};
+use crate::finance::percent_decode_bytes;
+
+/// An image inlined in a page as a data: URI
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InlineImage {
+    pub media_type: String, //ie: image/png, text/plain when the URI names none, as RFC 2397 says
+    pub size: usize,        //bytes of data once decoded
+}
+
+/// What a data: URI holds, None for any other kind of URL or a URI without data
+pub fn parse(src: &str) -> Option<InlineImage> {
+    let src = src.trim();
+    let rest = src.get(..5).filter(|scheme| scheme.eq_ignore_ascii_case("data:")).map(|_| &src[5..])?;
+    let (header, data) = rest.split_once(',')?;
+    let mut params = header.split(';').map(str::trim);
+    let media_type = params.next().filter(|media_type| !media_type.is_empty()).unwrap_or("text/plain").to_ascii_lowercase();
+    let size = if params.any(|param| param.eq_ignore_ascii_case("base64")) {
+        base64_len(&percent_decode_bytes(data))
+    } else {
+        percent_decode_bytes(data).len()
+    };
+    Some(InlineImage { media_type, size })
+}
+
+//bytes the base64 text decodes to, counting the digits and ignoring whitespace and padding
+fn base64_len(data: &[u8]) -> usize {
+    let digits = data.iter().filter(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'/' | b'-' | b'_')).count();
+    digits * 3 / 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_type_and_size_from_data_uris() {
+        //a 1x1 transparent gif, 42 bytes
+        let gif = "data:image/gif;base64,R0lGODlhAQABAIAAAAAAAP///yH5BAEAAAAALAAAAAABAAEAAAIBRAA7";
+        assert_eq!(parse(gif), Some(InlineImage { media_type: "image/gif".to_string(), size: 42 }));
+        assert_eq!(parse(" DATA:Image/PNG;Base64,iVBORw0KGgo= ").map(|image| (image.media_type, image.size)), Some(("image/png".to_string(), 8)));
+        assert_eq!(parse("data:image/svg+xml;charset=utf-8,%3Csvg%20xmlns%3D%22http%3A%2F%2Fwww.w3.org%2F2000%2Fsvg%22%2F%3E").unwrap().size, 41);
+        assert_eq!(parse("data:,hello").unwrap().media_type, "text/plain");
+
+        assert_eq!(parse("https://s.yimg.com/a.png"), None);
+        assert_eq!(parse("data:image/png;base64"), None);
+    }
+}
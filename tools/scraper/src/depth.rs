@@ -0,0 +1,149 @@
+//! Depth budgets for classes of links.
+//!
+//! Not every href is worth the same. A link that only changes the query of
+//! the page it's on is usually pagination or a sort order, and following
+//! ?page=2, ?page=3, ... eats the crawl budget on pages nobody wants. Depth
+//! rules cap how many hops in a row the crawl takes through links of one
+//! class matching a URL pattern, ie: `query:*/news/*=3` follows news
+//! pagination 3 pages deep. Links no rule matches are followed without limit.
+
+use url::Url;
+use crate::blacklist::glob_match;
+
+/// How a link relates to the page it was found on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkClass {
+    /// Same page, different query string, ie: ?page=417
+    Query,
+    /// Anything else: another path or another host
+    Path,
+}
+
+impl LinkClass {
+    pub fn from_name(name: &str) -> Option<LinkClass> {
+        match name {
+            "query" => Some(LinkClass::Query),
+            "path" => Some(LinkClass::Path),
+            _ => None,
+        }
+    }
+
+    /// Classify `link` as found on `page`
+    pub fn of(page: &str, link: &str) -> LinkClass {
+        match (Url::parse(page), Url::parse(link)) {
+            (Ok(page), Ok(link)) if page.scheme() == link.scheme()
+                && page.host_str() == link.host_str()
+                && page.port() == link.port()
+                && page.path() == link.path()
+                && page.query() != link.query() => LinkClass::Query,
+            _ => LinkClass::Path,
+        }
+    }
+}
+
+/// Links of a class matching a pattern are followed at most `budget` hops in a row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepthRule {
+    pub class: LinkClass,
+    /// URL pattern, * matches anything
+    pub pattern: String,
+    pub budget: u32,
+}
+
+impl DepthRule {
+    /// Parse a rule written as class:pattern=budget, ie: query:*?page=*=3
+    pub fn parse(rule: &str) -> Result<DepthRule, String> {
+        let invalid = || format!("--link-depth is not class:pattern=budget: {}", rule);
+        let (class, rest) = rule.split_once(':').ok_or_else(invalid)?;
+        //the pattern may hold = itself, the budget is after the last one
+        let (pattern, budget) = rest.rsplit_once('=').ok_or_else(invalid)?;
+        let class = LinkClass::from_name(class)
+            .ok_or_else(|| format!("Unknown link class {}, expected query or path", class))?;
+        let budget = budget.parse::<u32>().map_err(|_| invalid())?;
+        Ok(DepthRule { class, pattern: pattern.to_string(), budget })
+    }
+
+    fn matches(&self, class: LinkClass, link: &str) -> bool {
+        self.class == class && glob_match(&self.pattern, link)
+    }
+}
+
+/// How deep into a budgeted chain of links a URL is
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Depth {
+    /// Index of the rule the URL was reached under, None if no rule limits it
+    rule: Option<usize>,
+    /// Hops taken in a row under that rule
+    hops: u32,
+}
+
+impl Depth {
+    pub fn is_limited(&self) -> bool {
+        self.rule.is_some()
+    }
+}
+
+/// The depth rules of a crawl, the first matching rule applies
+#[derive(Debug, Default, Clone)]
+pub struct DepthRules {
+    rules: Vec<DepthRule>,
+}
+
+impl DepthRules {
+    pub fn new<'a>(rules: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        Ok(Self { rules: rules.into_iter().map(DepthRule::parse).collect::<Result<_, _>>()? })
+    }
+
+    /// Decide whether `link`, found on `page` which is `page_depth` deep,
+    /// still fits its budget. Returns how deep the link is, None when it
+    /// went over.
+    pub fn admit(&self, page: &str, page_depth: Depth, link: &str) -> Option<Depth> {
+        let class = LinkClass::of(page, link);
+        let Some(rule) = self.rules.iter().position(|rule| rule.matches(class, link)) else {
+            return Some(Depth::default());
+        };
+        //a hop under another rule, or none, starts a new chain
+        let hops = if page_depth.rule == Some(rule) { page_depth.hops + 1 } else { 1 };
+        (hops <= self.rules[rule].budget).then_some(Depth { rule: Some(rule), hops })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_links() {
+        let page = "https://news.yahoo.com/world?page=2";
+        assert_eq!(LinkClass::of(page, "https://news.yahoo.com/world?page=3"), LinkClass::Query);
+        assert_eq!(LinkClass::of(page, "https://news.yahoo.com/world"), LinkClass::Query);
+        assert_eq!(LinkClass::of(page, "https://news.yahoo.com/us?page=2"), LinkClass::Path);
+        assert_eq!(LinkClass::of(page, "https://sports.yahoo.com/world?page=3"), LinkClass::Path);
+    }
+
+    #[test]
+    fn follows_pagination_only_as_deep_as_the_budget() {
+        let rules = DepthRules::new(["query:*?page=*=2"]).unwrap();
+        let page = |n: u32| format!("https://news.yahoo.com/?page={}", n);
+
+        let first = rules.admit(&page(1), Depth::default(), &page(2)).unwrap();
+        assert!(first.is_limited());
+        let second = rules.admit(&page(2), first, &page(3)).unwrap();
+        assert_eq!(rules.admit(&page(3), second, &page(4)), None);
+        //a path link is unlimited and starts any later pagination afresh
+        let article = rules.admit(&page(3), second, "https://news.yahoo.com/story.html").unwrap();
+        assert_eq!(article, Depth::default());
+        assert_eq!(rules.admit("https://news.yahoo.com/story.html", article, "https://news.yahoo.com/story.html?page=2"), Some(first));
+    }
+
+    #[test]
+    fn parses_rules() {
+        assert_eq!(DepthRule::parse("query:*?page=*=3"), Ok(DepthRule {
+            class: LinkClass::Query,
+            pattern: "*?page=*".to_string(),
+            budget: 3,
+        }));
+        assert!(DepthRule::parse("query:*").is_err());
+        assert!(DepthRule::parse("fragment:*=3").is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! HTML validity and charset diagnostics, with --html-diagnostics. The
+//! parsers the crawl extracts with recover from broken markup without a word,
+//! so a second, validating pass counts what they had to recover from: parse
+//! errors by kind, elements still open where they should have been closed,
+//! the doctype, and whether the charset the server sent agrees with the one
+//! the page declares for itself.
+
+use std::collections::BTreeMap;
+use encoding_rs::Encoding;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use crate::bandwidth;
+
+/// What the validating pass found wrong with a page, for site-quality audits
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct HtmlDiagnostics {
+    /// The doctype as written, ie: "html" or "html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\"", absent without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doctype: Option<String>,
+    pub parse_errors: usize,
+    /// Elements left open, closed implicitly by the end of the page or by closing their parent
+    pub unclosed_tags: usize,
+    /// Parse errors counted by kind
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, usize>,
+    /// The charset in the Content-Type header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header_charset: Option<String>,
+    /// The charset a <meta> tag in the page declares
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta_charset: Option<String>,
+    /// The header and the page name different charsets
+    pub charset_mismatch: bool,
+    /// Some bytes weren't valid in the charset the page was decoded as
+    pub undecodable: bool,
+}
+
+impl HtmlDiagnostics {
+    /// Check a page decoded from a response with this Content-Type
+    pub fn check(body: &str, content_type: Option<&str>, undecodable: bool) -> Self {
+        let html = Html::parse_document(body);
+        let mut errors: BTreeMap<String, usize> = BTreeMap::new();
+        for error in &html.errors {
+            *errors.entry(error.to_string()).or_default() += 1;
+        }
+        let unclosed_tags = errors.iter()
+            .filter(|(kind, _)| kind.starts_with("Unexpected open"))
+            .map(|(_, count)| count)
+            .sum();
+        let doctype = html.tree.root().children()
+            .find_map(|node| node.value().as_doctype().map(|doctype| written_doctype(doctype.name(), doctype.public_id(), doctype.system_id())));
+        let header_charset = content_type.and_then(bandwidth::charset).map(str::to_string);
+        let meta_charset = meta_charset(&html);
+        let charset_mismatch = match (header_charset.as_deref().map(canonical), meta_charset.as_deref().map(canonical)) {
+            (Some(header), Some(meta)) => header != meta,
+            _ => false,
+        };
+        Self {
+            doctype,
+            parse_errors: html.errors.len(),
+            unclosed_tags,
+            errors,
+            header_charset,
+            meta_charset,
+            charset_mismatch,
+            undecodable,
+        }
+    }
+}
+
+//a doctype the way it's written after <!DOCTYPE
+fn written_doctype(name: &str, public_id: &str, system_id: &str) -> String {
+    match (public_id, system_id) {
+        ("", "") => name.to_string(),
+        ("", system_id) => format!("{} SYSTEM \"{}\"", name, system_id),
+        (public_id, "") => format!("{} PUBLIC \"{}\"", name, public_id),
+        (public_id, system_id) => format!("{} PUBLIC \"{}\" \"{}\"", name, public_id, system_id),
+    }
+}
+
+//<meta charset="..."> or the older <meta http-equiv="Content-Type" content="...; charset=...">
+fn meta_charset(html: &Html) -> Option<String> {
+    let charset = Selector::parse("meta[charset]").unwrap();
+    let http_equiv = Selector::parse("meta[http-equiv][content]").unwrap();
+    html.select(&charset).find_map(|meta| meta.value().attr("charset"))
+        .map(|charset| charset.trim().to_string())
+        .or_else(|| html.select(&http_equiv)
+            .filter(|meta| meta.value().attr("http-equiv").is_some_and(|name| name.eq_ignore_ascii_case("content-type")))
+            .find_map(|meta| bandwidth::charset(meta.value().attr("content")?).map(str::to_string)))
+}
+
+//labels naming the same encoding compare equal, ie: latin1 and ISO-8859-1
+fn canonical(charset: &str) -> String {
+    Encoding::for_label(charset.trim().as_bytes()).map_or_else(|| charset.trim().to_ascii_lowercase(), |encoding| encoding.name().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_what_the_parser_recovered_from() {
+        let clean = HtmlDiagnostics::check("<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>t</title></head><body><p>hi</p></body></html>", Some("text/html; charset=UTF-8"), false);
+        assert_eq!(clean, HtmlDiagnostics {
+            doctype: Some("html".to_string()),
+            header_charset: Some("UTF-8".to_string()),
+            meta_charset: Some("utf-8".to_string()),
+            ..HtmlDiagnostics::default()
+        });
+
+        let broken = HtmlDiagnostics::check(
+            "<html><head><meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1252\"></head><body><div><span>open</body></html>",
+            Some("text/html; charset=utf-8"),
+            true,
+        );
+        assert_eq!(broken.doctype, None);
+        assert!(broken.parse_errors >= 2);
+        assert!(broken.unclosed_tags >= 1);
+        assert_eq!(broken.errors.values().sum::<usize>(), broken.parse_errors);
+        assert_eq!(broken.meta_charset.as_deref(), Some("windows-1252"));
+        assert!(broken.charset_mismatch);
+        assert!(broken.undecodable);
+
+        //latin1 is another name for windows-1252
+        let aliased = HtmlDiagnostics::check(
+            "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\"><meta charset=latin1>",
+            Some("text/html; charset=windows-1252"),
+            false,
+        );
+        assert_eq!(aliased.doctype.as_deref(), Some("html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\""));
+        assert!(!aliased.charset_mismatch);
+    }
+}
@@ -0,0 +1,204 @@
+//! Dry runs: replay the link graph of an earlier crawl under new scope
+//! settings to see what a crawl would fetch, without sending a request.
+//!
+//! The graph is only as good as the crawl it came from. Pages it never
+//! fetched, or fetched outside its focus and so didn't record, would still be
+//! fetched, but their links are unknown and the simulation can't go past them.
+//! Feeds and sitemaps aren't followed either.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::Write as _;
+use crate::blacklist::Blacklist;
+use crate::depth::{Depth, DepthRules};
+use crate::fingerprint::UrlFingerprint;
+use crate::focus::Focus;
+use crate::{tls, Page};
+
+/// The settings that decide which links get queued, as a crawl would use them
+pub struct Scope<'a> {
+    pub blacklist: &'a Blacklist,
+    pub focus: &'a Focus,
+    pub depth_rules: &'a DepthRules,
+    pub fingerprint: &'a UrlFingerprint,
+    pub require_https: bool,
+}
+
+/// What the earlier crawl knows about a URL the simulation would fetch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Known {
+    /// Its links were recorded, so the simulation followed them
+    Page,
+    /// The earlier crawl failed to fetch it
+    Failed,
+    /// The earlier crawl has no record of it
+    Unknown,
+}
+
+/// A URL the crawl would fetch, in the order it would get to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Planned {
+    pub url: String,
+    pub known: Known,
+    /// Outside --include-only, fetched only for its links
+    pub stepping_stone: bool,
+}
+
+/// Why links were left out, counted per link found
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Skipped {
+    pub blacklisted: u64,
+    pub over_depth: u64,
+    pub outside_focus: u64,
+    pub not_https: u64,
+}
+
+/// The outcome of a dry run
+#[derive(Debug, Default)]
+pub struct DryRun {
+    pub fetched: Vec<Planned>,
+    /// URLs still queued when the page limit was reached
+    pub left_queued: usize,
+    pub skipped: Skipped,
+}
+
+/// Walk `visited`, the pages of an earlier crawl, from `seed` the way a crawl
+/// under `scope` would, stopping after `limit` pages if there is one
+pub fn simulate(seed: &str, visited: &BTreeMap<String, Page>, failed: &HashSet<&str>, scope: &Scope, limit: Option<i32>) -> DryRun {
+    let mut run = DryRun::default();
+    let mut seen = HashSet::new();
+    let mut depths: HashMap<String, Depth> = HashMap::new();
+    let mut stepping_stones = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert(scope.fingerprint.of(seed));
+    queue.push_back(seed.to_string());
+
+    while let Some(url) = queue.pop_front() {
+        if limit.is_some_and(|limit| run.fetched.len() >= limit as usize) {
+            run.left_queued = queue.len() + 1;
+            break;
+        }
+        let depth = depths.remove(&url).unwrap_or_default();
+        let stepping_stone = stepping_stones.contains(&url);
+        let links = visited.get(&url).map(|page| page.links.as_slice());
+        let known = match links {
+            Some(_) => Known::Page,
+            None if failed.contains(url.as_str()) => Known::Failed,
+            None => Known::Unknown,
+        };
+        run.fetched.push(Planned { url: url.clone(), known, stepping_stone });
+
+        //the same decisions enqueue_links makes, in the same order
+        for link in links.unwrap_or_default() {
+            let upgraded;
+            let link = if scope.require_https {
+                match tls::upgrade_to_https(link) {
+                    Some(https) => {
+                        upgraded = https;
+                        &upgraded
+                    },
+                    None => {
+                        run.skipped.not_https += 1;
+                        continue;
+                    }
+                }
+            } else {
+                link
+            };
+            if scope.blacklist.is_blocked(link) {
+                run.skipped.blacklisted += 1;
+                continue;
+            }
+            let Some(link_depth) = scope.depth_rules.admit(&url, depth, link) else {
+                run.skipped.over_depth += 1;
+                continue;
+            };
+            let outside = !scope.focus.includes(link);
+            if outside && stepping_stone {
+                run.skipped.outside_focus += 1;
+                continue;
+            }
+            if seen.insert(scope.fingerprint.of(link)) {
+                if link_depth.is_limited() {
+                    depths.insert(link.to_string(), link_depth);
+                }
+                if outside {
+                    stepping_stones.insert(link.to_string());
+                }
+                queue.push_back(link.to_string());
+            }
+        }
+    }
+    run
+}
+
+impl DryRun {
+    /// A summary followed by every URL the crawl would fetch, unknown ones marked
+    pub fn report(&self) -> String {
+        let count = |known| self.fetched.iter().filter(|planned| planned.known == known).count();
+        let mut out = String::new();
+        let _ = writeln!(out, "Would fetch {} URLs: {} known pages, {} failed before, {} not in the earlier crawl",
+            self.fetched.len(), count(Known::Page), count(Known::Failed), count(Known::Unknown));
+        let stepping_stones = self.fetched.iter().filter(|planned| planned.stepping_stone).count();
+        if stepping_stones > 0 {
+            let _ = writeln!(out, "    {} of them outside --include-only, only for their links", stepping_stones);
+        }
+        if self.left_queued > 0 {
+            let _ = writeln!(out, "    {} more queued when the page limit was reached", self.left_queued);
+        }
+        let skipped = &self.skipped;
+        let _ = writeln!(out, "Links skipped: {} blacklisted, {} over their depth budget, {} too far outside --include-only, {} not available over https",
+            skipped.blacklisted, skipped.over_depth, skipped.outside_focus, skipped.not_https);
+        for planned in &self.fetched {
+            let mark = match planned.known {
+                Known::Page => ' ',
+                Known::Failed => '!',
+                Known::Unknown => '?',
+            };
+            let _ = writeln!(out, "{} {}{}", mark, planned.url, if planned.stepping_stone { " (links only)" } else { "" });
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(pages: &[(&str, &[&str])]) -> BTreeMap<String, Page> {
+        pages.iter()
+            .map(|(url, links)| (url.to_string(), Page::new(0, links.iter().map(|link| link.to_string()).collect(), Vec::new())))
+            .collect()
+    }
+
+    #[test]
+    fn replays_the_link_graph_under_new_rules() {
+        let visited = graph(&[
+            ("https://www.yahoo.com/", &["https://news.yahoo.com/", "https://sports.yahoo.com/", "https://www.yahoo.com/?utm_source=x"]),
+            ("https://news.yahoo.com/", &["https://news.yahoo.com/world", "https://news.yahoo.com/gone", "https://www.yahoo.com/"]),
+            ("https://sports.yahoo.com/", &["https://sports.yahoo.com/nba"]),
+        ]);
+        let failed = HashSet::from(["https://news.yahoo.com/gone"]);
+        let blacklist = Blacklist::default();
+        let focus = Focus::new(["https://news.yahoo.com/*"], []).unwrap();
+        let depth_rules = DepthRules::default();
+        let fingerprint = UrlFingerprint::new(["utm_source"]);
+        let scope = Scope { blacklist: &blacklist, focus: &focus, depth_rules: &depth_rules, fingerprint: &fingerprint, require_https: false };
+
+        let run = simulate("https://www.yahoo.com/", &visited, &failed, &scope, None);
+        let fetched: Vec<_> = run.fetched.iter().map(|planned| (planned.url.as_str(), planned.known, planned.stepping_stone)).collect();
+        assert_eq!(fetched, [
+            ("https://www.yahoo.com/", Known::Page, false),
+            ("https://news.yahoo.com/", Known::Page, false),
+            ("https://sports.yahoo.com/", Known::Page, true),
+            ("https://news.yahoo.com/world", Known::Unknown, false),
+            ("https://news.yahoo.com/gone", Known::Failed, false),
+        ]);
+        //sports.yahoo.com is already one hop outside the focus
+        assert_eq!(run.skipped.outside_focus, 1);
+
+        let run = simulate("https://www.yahoo.com/", &visited, &failed, &scope, Some(2));
+        assert_eq!(run.fetched.len(), 2);
+        assert_eq!(run.left_queued, 3);
+        assert!(run.report().starts_with("Would fetch 2 URLs: 2 known pages, 0 failed before, 0 not in the earlier crawl\n"));
+    }
+}
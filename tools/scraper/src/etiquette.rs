@@ -0,0 +1,226 @@
+//! How the scraper identifies itself to the sites it crawls and how politely
+//! it paces its requests.
+
+use std::time::Duration;
+use clap::ArgMatches;
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderValue, FROM, USER_AGENT};
+use select::document::Document;
+use select::predicate::Name;
+
+/// Name the scraper uses in its User-Agent and to match agent-specific
+/// X-Robots-Tag directives
+pub const BOT_NAME: &str = "YahooScraper";
+
+/// Canned politeness settings, from most to least conservative
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    Polite,
+    Moderate,
+    Aggressive,
+}
+
+impl Preset {
+    pub fn from_name(name: &str) -> Option<Preset> {
+        match name {
+            "polite" => Some(Preset::Polite),
+            "moderate" => Some(Preset::Moderate),
+            "aggressive" => Some(Preset::Aggressive),
+            _ => None,
+        }
+    }
+}
+
+/// The etiquette block: identification headers plus pacing and robots
+/// compliance settings applied to every request the scraper sends
+#[derive(Debug, Clone)]
+pub struct Etiquette {
+    /// Contact page for site operators, advertised in the User-Agent
+    pub contact_url: Option<String>,
+    /// Email address sent in the From header
+    pub from: Option<String>,
+    /// Pause between two consecutive requests
+    pub delay: Duration,
+    /// Maximum number of requests in flight to any one host at once
+    pub per_host: usize,
+    /// Whether X-Robots-Tag noindex/nofollow response headers are honored
+    pub obey_x_robots_tag: bool,
+    /// Whether `<meta name="robots">` tags and rel=nofollow links are honored
+    pub obey_meta_robots: bool,
+}
+
+impl Etiquette {
+    pub fn preset(preset: Preset) -> Self {
+        let delay = match preset {
+            Preset::Polite => Duration::from_millis(1000),
+            Preset::Moderate => Duration::from_millis(250),
+            Preset::Aggressive => Duration::from_millis(0),
+        };
+        Self {
+            contact_url: None,
+            from: None,
+            delay,
+            per_host: 2,
+            obey_x_robots_tag: true,
+            obey_meta_robots: true,
+        }
+    }
+
+    /// Build the etiquette from the command line: start from the chosen preset
+    /// (polite by default) and apply any individual overrides on top of it
+    pub fn from_args(args: &ArgMatches) -> Result<Self, String> {
+        let preset_name = args.value_of("etiquette").unwrap_or("polite");
+        let preset = Preset::from_name(preset_name)
+            .ok_or_else(|| format!("Unknown etiquette preset: {}", preset_name))?;
+        let mut etiquette = Etiquette::preset(preset);
+
+        etiquette.contact_url = args.value_of("contact").map(str::to_string);
+        etiquette.from = args.value_of("from").map(str::to_string);
+        if let Some(ms) = args.value_of("delay") {
+            let ms = ms.parse::<u64>().map_err(|_| format!("Invalid delay: {}", ms))?;
+            etiquette.delay = Duration::from_millis(ms);
+        }
+        if let Some(n) = args.value_of("per-host-concurrency") {
+            etiquette.per_host = n.parse::<usize>().ok().filter(|&n| n > 0)
+                .ok_or_else(|| format!("Invalid per-host concurrency: {}", n))?;
+        }
+        if args.is_present("ignore-x-robots-tag") {
+            etiquette.obey_x_robots_tag = false;
+        }
+        if args.is_present("ignore-meta-robots") {
+            etiquette.obey_meta_robots = false;
+        }
+        Ok(etiquette)
+    }
+
+    /// Descriptive User-Agent, e.g. "YahooScraper/0.1.0 (+https://example.com/bot)"
+    pub fn user_agent(&self) -> String {
+        match &self.contact_url {
+            Some(contact) => format!("{}/{} (+{})", BOT_NAME, env!("CARGO_PKG_VERSION"), contact),
+            None => format!("{}/{}", BOT_NAME, env!("CARGO_PKG_VERSION")),
+        }
+    }
+
+    /// The identification headers every outgoing request carries
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Ok(agent) = HeaderValue::from_str(&self.user_agent()) {
+            headers.insert(USER_AGENT, agent);
+        }
+        if let Some(from) = self.from.as_deref().and_then(|from| HeaderValue::from_str(from).ok()) {
+            headers.insert(FROM, from);
+        }
+        headers
+    }
+
+    /// Add the identification headers to an outgoing request
+    pub fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        request.headers(self.headers())
+    }
+
+    /// Read the X-Robots-Tag headers of a response. Returns no restrictions
+    /// when compliance is turned off.
+    pub fn robots_directives(&self, response: &Response) -> RobotsDirectives {
+        if !self.obey_x_robots_tag {
+            return RobotsDirectives::default();
+        }
+        response.headers()
+            .get_all("X-Robots-Tag")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .fold(RobotsDirectives::default(), |acc, value| acc.merge(RobotsDirectives::parse(value)))
+    }
+
+    /// Read the robots meta tags of a page, the generic `robots` one and the
+    /// one addressed to us by name. Returns no restrictions when compliance
+    /// is turned off.
+    pub fn meta_robots_directives(&self, document: &Document) -> RobotsDirectives {
+        if !self.obey_meta_robots {
+            return RobotsDirectives::default();
+        }
+        document.find(Name("meta"))
+            .filter(|meta| meta.attr("name").is_some_and(|name| name.eq_ignore_ascii_case("robots") || name.eq_ignore_ascii_case(BOT_NAME)))
+            .filter_map(|meta| meta.attr("content"))
+            .fold(RobotsDirectives::default(), |acc, content| acc.merge(RobotsDirectives::parse_rules(content)))
+    }
+}
+
+/// Indexing restrictions a site placed on a single page
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RobotsDirectives {
+    /// The page content should not be recorded
+    pub noindex: bool,
+    /// The links on the page should not be followed
+    pub nofollow: bool,
+}
+
+impl RobotsDirectives {
+    /* parse one header value, ie: "noindex, nofollow" or "otherbot: noindex"
+        directives scoped to another user agent are ignored, "none" means both
+    */
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        let rules = match value.split_once(':') {
+            Some((agent, rules)) if !agent.contains(',') => {
+                if !agent.trim().eq_ignore_ascii_case(BOT_NAME) {
+                    return RobotsDirectives::default();
+                }
+                rules
+            },
+            _ => value,
+        };
+        Self::parse_rules(rules)
+    }
+
+    //parse a comma separated list of rules, ie: "noindex, nofollow"
+    fn parse_rules(rules: &str) -> Self {
+        let mut directives = RobotsDirectives::default();
+        for rule in rules.split(',').map(|rule| rule.trim().to_ascii_lowercase()) {
+            match rule.as_str() {
+                "noindex" => directives.noindex = true,
+                "nofollow" => directives.nofollow = true,
+                "none" => {
+                    directives.noindex = true;
+                    directives.nofollow = true;
+                },
+                _ => {},
+            }
+        }
+        directives
+    }
+
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            noindex: self.noindex || other.noindex,
+            nofollow: self.nofollow || other.nofollow,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_generic_directives() {
+        let directives = RobotsDirectives::parse("noindex, nofollow");
+        assert!(directives.noindex && directives.nofollow);
+        assert_eq!(RobotsDirectives::parse("none"), directives);
+        assert_eq!(RobotsDirectives::parse("noarchive"), RobotsDirectives::default());
+    }
+
+    #[test]
+    fn reads_meta_robots_tags() {
+        let etiquette = Etiquette::preset(Preset::Polite);
+        let page = Document::from(r#"<head><meta name="robots" content="noindex"><meta name="YahooScraper" content="nofollow"><meta name="googlebot" content="none"></head>"#);
+        assert_eq!(etiquette.meta_robots_directives(&page), RobotsDirectives { noindex: true, nofollow: true });
+        let page = Document::from(r#"<head><meta name="googlebot" content="none"></head>"#);
+        assert_eq!(etiquette.meta_robots_directives(&page), RobotsDirectives::default());
+    }
+
+    #[test]
+    fn ignores_other_agents() {
+        assert_eq!(RobotsDirectives::parse("googlebot: noindex"), RobotsDirectives::default());
+        assert!(RobotsDirectives::parse("yahooscraper: nofollow").nofollow);
+    }
+}
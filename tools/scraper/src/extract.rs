@@ -0,0 +1,139 @@
+//! Link extraction for responses that aren't HTML.
+//!
+//! API endpoints found during a crawl answer with JSON or plain text, which
+//! have no anchors to follow. Extraction rules given on the command line pull
+//! URLs out of them instead: regexes run over the raw body, and JSON pointers
+//! pick string values out of JSON bodies. A `*` segment in a pointer matches
+//! every element of an array or every value of an object, so
+//! `/items/*/link` collects the link of each item in a feed.
+
+use regex::Regex;
+use serde_json::Value;
+
+/// User supplied rules for finding URLs in non-HTML responses
+#[derive(Debug, Default, Clone)]
+pub struct ExtractRules {
+    regexes: Vec<Regex>,
+    json_pointers: Vec<String>,
+}
+
+impl ExtractRules {
+    /// Build the rules, failing on the first regex that doesn't compile or
+    /// pointer that doesn't start with '/'
+    pub fn new<'a>(
+        regexes: impl IntoIterator<Item = &'a str>,
+        json_pointers: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, String> {
+        let regexes = regexes.into_iter()
+            .map(|pattern| Regex::new(pattern).map_err(|e| format!("Bad extraction regex {}: {}", pattern, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let json_pointers = json_pointers.into_iter()
+            .map(|pointer| {
+                if pointer.is_empty() || pointer.starts_with('/') {
+                    Ok(pointer.to_string())
+                } else {
+                    Err(format!("Bad JSON pointer {}: must start with /", pointer))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { regexes, json_pointers })
+    }
+
+    /// URLs found in a response body. Regexes yield their first capture group,
+    /// or the whole match when they have none.
+    pub fn extract(&self, body: &str, content_type: Option<&str>) -> Vec<String> {
+        let mut found = Vec::new();
+        if !self.json_pointers.is_empty() && content_type.is_some_and(is_json) {
+            if let Ok(json) = serde_json::from_str::<Value>(body) {
+                for pointer in &self.json_pointers {
+                    let segments: Vec<String> = pointer.split('/').skip(1).map(unescape).collect();
+                    collect_strings(&json, &segments, &mut found);
+                }
+            }
+        }
+        for regex in &self.regexes {
+            for captures in regex.captures_iter(body) {
+                if let Some(m) = captures.get(1).or_else(|| captures.get(0)) {
+                    found.push(m.as_str().to_string());
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Whether a Content-Type header value is HTML. Responses without one are
+/// assumed to be HTML, like they always were.
+pub fn is_html(content_type: Option<&str>) -> bool {
+    content_type.is_none_or(|t| t.contains("html"))
+}
+
+fn is_json(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    mime == "application/json" || mime.ends_with("+json") || mime == "text/json"
+}
+
+//JSON pointer escapes: ~1 is '/' and ~0 is '~'
+fn unescape(segment: &str) -> String {
+    segment.replace("~1", "/").replace("~0", "~")
+}
+
+//walk the pointer segments, collecting every string (or array of strings) it ends on
+fn collect_strings(value: &Value, segments: &[String], found: &mut Vec<String>) {
+    let Some((segment, rest)) = segments.split_first() else {
+        match value {
+            Value::String(s) => found.push(s.clone()),
+            Value::Array(items) => found.extend(items.iter().filter_map(Value::as_str).map(str::to_string)),
+            _ => {},
+        }
+        return;
+    };
+    match value {
+        Value::Array(items) if segment == "*" => {
+            for item in items {
+                collect_strings(item, rest, found);
+            }
+        },
+        Value::Object(fields) if segment == "*" => {
+            for field in fields.values() {
+                collect_strings(field, rest, found);
+            }
+        },
+        Value::Array(items) => {
+            if let Some(item) = segment.parse::<usize>().ok().and_then(|i| items.get(i)) {
+                collect_strings(item, rest, found);
+            }
+        },
+        Value::Object(fields) => {
+            if let Some(field) = fields.get(segment) {
+                collect_strings(field, rest, found);
+            }
+        },
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_from_json_feed_and_text() {
+        let rules = ExtractRules::new(
+            [r#"next=(https://\S+)"#],
+            ["/items/*/link", "/meta/next~1page"],
+        ).unwrap();
+        let feed = r#"{
+            "items": [{"link": "https://news.yahoo.com/a.html"}, {"link": "https://news.yahoo.com/b.html"}, {"title": "no link"}],
+            "meta": {"next/page": "/feeds/news?page=2"}
+        }"#;
+        assert_eq!(rules.extract(feed, Some("application/json; charset=utf-8")), [
+            "https://news.yahoo.com/a.html",
+            "https://news.yahoo.com/b.html",
+            "/feeds/news?page=2",
+        ]);
+        //pointers only apply to JSON, regexes apply to any text
+        assert_eq!(rules.extract("done\nnext=https://news.yahoo.com/c.html\n", Some("text/plain")), ["https://news.yahoo.com/c.html"]);
+        assert!(ExtractRules::new([], ["items"]).is_err());
+    }
+}
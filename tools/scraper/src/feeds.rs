@@ -0,0 +1,180 @@
+//! RSS and Atom feeds. Pages advertise their feeds with
+//! `<link rel="alternate" type="application/rss+xml">` tags, and a feed lists
+//! the newest articles of a section, which makes it the cheapest way to find
+//! fresh pages without crawling every index page on the way to them.
+
+use select::document::Document;
+use select::predicate::Name;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The two feed formats found in the wild
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedKind {
+    Rss,
+    Atom,
+}
+
+impl FeedKind {
+    fn from_mime(mime: &str) -> Option<FeedKind> {
+        match mime.trim().to_ascii_lowercase().as_str() {
+            "application/rss+xml" => Some(FeedKind::Rss),
+            "application/atom+xml" => Some(FeedKind::Atom),
+            _ => None,
+        }
+    }
+}
+
+/// A feed discovered during a crawl, as written to feeds.json
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Feed {
+    pub kind: FeedKind,
+    /// The title the page or the feed itself gives it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The first page found linking to the feed
+    pub found_on: String,
+    /// Number of entries, only known once the feed was fetched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entries: Option<usize>,
+    /// The fetch that got the feed, same ID as in log.txt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// The feeds a page links to, keyed by their absolute URL
+pub fn discover(page_url: &str, html: &str) -> Vec<(String, Feed)> {
+    let Ok(base) = Url::parse(page_url) else {
+        return Vec::new();
+    };
+    Document::from(html).find(Name("link"))
+        .filter(|link| link.attr("rel").is_some_and(|rel| rel.split_whitespace().any(|rel| rel.eq_ignore_ascii_case("alternate"))))
+        .filter_map(|link| {
+            let kind = FeedKind::from_mime(link.attr("type")?)?;
+            let url = base.join(link.attr("href")?).ok()?;
+            let title = link.attr("title").map(str::trim).filter(|title| !title.is_empty()).map(str::to_string);
+            Some((url.to_string(), Feed { kind, title, found_on: page_url.to_string(), entries: None, request_id: None }))
+        })
+        .collect()
+}
+
+/// What a fetched feed says about itself
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FeedContents {
+    pub title: Option<String>,
+    /// The article URL of every entry, in feed order
+    pub entry_urls: Vec<String>,
+}
+
+/// Read an RSS or Atom document, whichever it turns out to be.
+/// `feed_url` resolves relative entry links.
+pub fn parse(feed_url: &str, xml: &str) -> Result<FeedContents, String> {
+    let document = roxmltree::Document::parse(xml).map_err(|e| format!("Could not parse feed {}: {}", feed_url, e))?;
+    let base = Url::parse(feed_url).ok();
+    let resolve = |link: &str| match &base {
+        Some(base) => base.join(link.trim()).ok().map(|url| url.to_string()),
+        None => Url::parse(link.trim()).ok().map(|url| url.to_string()),
+    };
+    let root = document.root_element();
+    let mut contents = FeedContents::default();
+
+    match root.tag_name().name() {
+        //<rss><channel><title/><item><link>url</link></item></channel></rss>
+        "rss" | "RDF" => {
+            let channel = root.children().find(|node| node.has_tag_name("channel"));
+            contents.title = channel.and_then(|channel| child_text(channel, "title"));
+            //RSS 1.0 puts the items next to the channel rather than inside it
+            let items = channel.into_iter().chain([root]).flat_map(|parent| parent.children()).filter(|node| node.has_tag_name("item"));
+            contents.entry_urls = items.filter_map(|item| resolve(&child_text(item, "link")?)).collect();
+        },
+        //<feed><title/><entry><link rel="alternate" href="url"/></entry></feed>
+        "feed" => {
+            contents.title = child_text(root, "title");
+            contents.entry_urls = root.children()
+                .filter(|node| node.has_tag_name("entry"))
+                .filter_map(|entry| {
+                    let link = entry.children()
+                        .filter(|node| node.has_tag_name("link"))
+                        .find(|link| link.attribute("rel").is_none_or(|rel| rel == "alternate"))?;
+                    resolve(link.attribute("href")?)
+                })
+                .collect();
+        },
+        other => return Err(format!("{} is not a feed, its root element is <{}>", feed_url, other)),
+    }
+    Ok(contents)
+}
+
+//trimmed text of the first child element with this name
+fn child_text(node: roxmltree::Node, name: &str) -> Option<String> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovers_feed_links() {
+        let html = r#"<html><head>
+            <link rel="alternate" type="application/rss+xml" title="Yahoo News - Latest" href="/rss">
+            <link rel="alternate" type="application/atom+xml" href="https://finance.yahoo.com/atom.xml">
+            <link rel="alternate" hreflang="fr" href="https://fr.news.yahoo.com/">
+            <link rel="stylesheet" type="application/rss+xml" href="/not-a-feed">
+        </head></html>"#;
+        let feeds = discover("https://news.yahoo.com/world/", html);
+        assert_eq!(feeds, [
+            ("https://news.yahoo.com/rss".to_string(), Feed {
+                kind: FeedKind::Rss,
+                title: Some("Yahoo News - Latest".to_string()),
+                found_on: "https://news.yahoo.com/world/".to_string(),
+                entries: None,
+                request_id: None,
+            }),
+            ("https://finance.yahoo.com/atom.xml".to_string(), Feed {
+                kind: FeedKind::Atom,
+                title: None,
+                found_on: "https://news.yahoo.com/world/".to_string(),
+                entries: None,
+                request_id: None,
+            }),
+        ]);
+    }
+
+    #[test]
+    fn parses_rss_and_atom() {
+        let rss = r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel>
+                <title> Yahoo News </title>
+                <item><title>One</title><link>https://news.yahoo.com/one.html</link></item>
+                <item><title>Two</title><link>/two.html</link></item>
+                <item><title>No link</title></item>
+            </channel></rss>"#;
+        assert_eq!(parse("https://news.yahoo.com/rss", rss).unwrap(), FeedContents {
+            title: Some("Yahoo News".to_string()),
+            entry_urls: vec!["https://news.yahoo.com/one.html".to_string(), "https://news.yahoo.com/two.html".to_string()],
+        });
+
+        let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+                <title>Markets</title>
+                <entry>
+                    <link rel="self" href="https://finance.yahoo.com/api/1"/>
+                    <link rel="alternate" href="https://finance.yahoo.com/news/1.html"/>
+                </entry>
+                <entry><link href="https://finance.yahoo.com/news/2.html"/></entry>
+            </feed>"#;
+        assert_eq!(parse("https://finance.yahoo.com/atom.xml", atom).unwrap(), FeedContents {
+            title: Some("Markets".to_string()),
+            entry_urls: vec!["https://finance.yahoo.com/news/1.html".to_string(), "https://finance.yahoo.com/news/2.html".to_string()],
+        });
+
+        assert!(parse("https://news.yahoo.com/", "<html><body/></html>").is_err());
+        assert!(parse("https://news.yahoo.com/", "not xml").is_err());
+    }
+}
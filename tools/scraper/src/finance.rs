@@ -0,0 +1,221 @@
+//! Quotes from Yahoo Finance quote pages, with --finance-quotes. Every quote
+//! page the crawl comes across becomes one typed record in finance.ndjson:
+//! the symbol, price, change and market cap as numbers rather than whatever
+//! text the page happened to show them as.
+//!
+//! The figures are read from the <fin-streamer> elements the quote page keeps
+//! live, which carry the raw value in data-value, with the data-testid and
+//! data-test markup of other page versions as fallbacks.
+
+use select::document::Document;
+use select::node::Node;
+use select::predicate::{Attr, Name, Predicate, Text};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// One quote as written to finance.ndjson
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub url: String,
+    /// The fetch that got the quote page, same ID as in log.txt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    pub symbol: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    pub price: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<f64>,
+    /// In percent, 1.5 for +1.5%
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_percent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub market_cap: Option<f64>,
+}
+
+/// The symbol of a quote page URL, ie: AAPL for https://finance.yahoo.com/quote/AAPL/.
+/// Pages under a quote, like its history, are not quote pages.
+pub fn quote_symbol(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    if host != "finance.yahoo.com" && !host.ends_with(".finance.yahoo.com") {
+        return None;
+    }
+    let mut segments = url.path_segments()?.filter(|segment| !segment.is_empty());
+    let (Some("quote"), Some(symbol), None) = (segments.next(), segments.next(), segments.next()) else {
+        return None;
+    };
+    //index symbols come percent encoded, %5EGSPC is ^GSPC
+    let symbol = percent_decode(symbol).to_ascii_uppercase();
+    Some(symbol).filter(|symbol| !symbol.is_empty())
+}
+
+/// The quote on a quote page, None if the page isn't one or has no price
+pub fn extract(document: &Document, url: &str) -> Option<Quote> {
+    let symbol = quote_symbol(url)?;
+    let field = |name: &str| streamer(document, &symbol, name);
+    let price = field("regularMarketPrice")
+        .or_else(|| test_id(document, "qsp-price"))
+        .as_deref()
+        .and_then(parse_number)?;
+    let change = field("regularMarketChange")
+        .or_else(|| test_id(document, "qsp-price-change"))
+        .as_deref()
+        .and_then(parse_number);
+    let change_percent = field("regularMarketChangePercent")
+        .or_else(|| test_id(document, "qsp-price-change-percent"))
+        .as_deref()
+        .and_then(parse_number);
+    let market_cap = field("marketCap")
+        .or_else(|| document.find(Attr("data-test", "MARKET_CAP-value")).next().map(|node| node.text()))
+        .as_deref()
+        .and_then(parse_abbreviated);
+    Some(Quote {
+        url: url.to_string(),
+        request_id: None,
+        name: name(document, &symbol),
+        currency: currency(document),
+        symbol,
+        price,
+        change,
+        change_percent,
+        market_cap,
+    })
+}
+
+//a live field of the symbol, its raw value if it has one or else its text
+fn streamer(document: &Document, symbol: &str, field: &str) -> Option<String> {
+    document.find(Name("fin-streamer").and(Attr("data-field", field)))
+        //pages show other symbols' figures too, in headers and comparisons
+        .find(|node| node.attr("data-symbol").is_none_or(|other| other.eq_ignore_ascii_case(symbol)))
+        .map(|node| node.attr("data-value").map_or_else(|| node.text(), str::to_string))
+}
+
+fn test_id(document: &Document, id: &str) -> Option<String> {
+    document.find(Attr("data-testid", id)).next().map(|node| node.text())
+}
+
+//the company name from the heading, "Apple Inc. (AAPL)" -> "Apple Inc."
+fn name(document: &Document, symbol: &str) -> Option<String> {
+    let heading = document.find(Name("h1")).map(|node| node.text()).find(|text| text.contains(&format!("({})", symbol)))?;
+    let name = heading.replace(&format!("({})", symbol), "");
+    Some(name.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+//"Currency in USD", somewhere under the price
+fn currency(document: &Document) -> Option<String> {
+    document.find(Text).filter_map(|node: Node| node.as_text())
+        .find_map(|text| text.split_once("Currency in ").map(|(_, rest)| rest.split_whitespace().next().unwrap_or("").trim_end_matches('.').to_string()))
+        .filter(|currency| !currency.is_empty())
+}
+
+/// A number as a quote page shows it: "1,234.56", "+1.20", "(-0.45%)", "−3.1"
+pub fn parse_number(text: &str) -> Option<f64> {
+    let cleaned: String = text.trim()
+        .trim_start_matches('(').trim_end_matches(')').trim_end_matches('%')
+        .replace('\u{2212}', "-")
+        .chars().filter(|c| *c != ',' && *c != '+' && !c.is_whitespace())
+        .collect();
+    cleaned.parse().ok().filter(|n: &f64| n.is_finite())
+}
+
+/// A number with an optional K, M, B or T suffix, as market caps are shown: "2.95T"
+pub fn parse_abbreviated(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let multiplier = match text.chars().last()?.to_ascii_uppercase() {
+        'K' => 1e3,
+        'M' => 1e6,
+        'B' => 1e9,
+        'T' => 1e12,
+        _ => return parse_number(text),
+    };
+    parse_number(&text[..text.len() - 1]).map(|n| n * multiplier)
+}
+
+/// Undo the %XX escapes in a piece of a URL, the rest left as is
+pub fn percent_decode(segment: &str) -> String {
+    String::from_utf8_lossy(&percent_decode_bytes(segment)).into_owned()
+}
+
+/// The bytes a piece of a URL stands for, which needn't be text
+pub fn percent_decode_bytes(segment: &str) -> Vec<u8> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%').then(|| segment.get(i + 1..i + 3)).flatten().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_quote_pages() {
+        assert_eq!(quote_symbol("https://finance.yahoo.com/quote/AAPL/").as_deref(), Some("AAPL"));
+        assert_eq!(quote_symbol("https://finance.yahoo.com/quote/aapl?p=AAPL").as_deref(), Some("AAPL"));
+        assert_eq!(quote_symbol("https://uk.finance.yahoo.com/quote/%5EFTSE/").as_deref(), Some("^FTSE"));
+        assert_eq!(quote_symbol("https://finance.yahoo.com/quote/AAPL/history/"), None);
+        assert_eq!(quote_symbol("https://finance.yahoo.com/news/"), None);
+        assert_eq!(quote_symbol("https://news.yahoo.com/quote/AAPL/"), None);
+    }
+
+    #[test]
+    fn parses_figures() {
+        assert_eq!(parse_number("1,234.56"), Some(1234.56));
+        assert_eq!(parse_number("+1.20"), Some(1.2));
+        assert_eq!(parse_number("(-0.45%)"), Some(-0.45));
+        assert_eq!(parse_number("\u{2212}3.1"), Some(-3.1));
+        assert_eq!(parse_number("N/A"), None);
+        assert_eq!(parse_abbreviated("2.95T"), Some(2.95e12));
+        assert_eq!(parse_abbreviated("812.4M"), Some(812.4e6));
+        assert_eq!(parse_abbreviated("2950000000000"), Some(2.95e12));
+        assert_eq!(parse_abbreviated("--"), None);
+    }
+
+    #[test]
+    fn extracts_a_quote() {
+        let page = r#"<html><body>
+            <h1>Apple Inc. (AAPL)</h1>
+            <div><span>NasdaqGS - Delayed Quote</span> <span>Currency in USD</span></div>
+            <fin-streamer data-symbol="^GSPC" data-field="regularMarketPrice" data-value="5000.1">5,000.10</fin-streamer>
+            <fin-streamer data-symbol="AAPL" data-field="regularMarketPrice" data-value="189.84">189.84</fin-streamer>
+            <fin-streamer data-symbol="AAPL" data-field="regularMarketChange">+2.31</fin-streamer>
+            <fin-streamer data-symbol="AAPL" data-field="regularMarketChangePercent">(+1.23%)</fin-streamer>
+            <table><tr><td>Market Cap</td><td data-test="MARKET_CAP-value">2.95T</td></tr></table>
+            </body></html>"#;
+        let quote = extract(&Document::from(page), "https://finance.yahoo.com/quote/AAPL/").unwrap();
+        assert_eq!(quote, Quote {
+            url: "https://finance.yahoo.com/quote/AAPL/".to_string(),
+            request_id: None,
+            symbol: "AAPL".to_string(),
+            name: Some("Apple Inc.".to_string()),
+            currency: Some("USD".to_string()),
+            price: 189.84,
+            change: Some(2.31),
+            change_percent: Some(1.23),
+            market_cap: Some(2.95e12),
+        });
+
+        let newer = r#"<section><span data-testid="qsp-price">1,021.50</span><span data-testid="qsp-price-change">-4.00</span></section>"#;
+        let quote = extract(&Document::from(newer), "https://finance.yahoo.com/quote/NVDA").unwrap();
+        assert_eq!((quote.price, quote.change, quote.market_cap), (1021.5, Some(-4.0), None));
+
+        assert_eq!(extract(&Document::from("<p>Symbols similar to 'XYZ'</p>"), "https://finance.yahoo.com/quote/XYZ/"), None);
+        assert_eq!(extract(&Document::from(page), "https://finance.yahoo.com/news/"), None);
+    }
+}
@@ -0,0 +1,84 @@
+//! Canonical fingerprints of URLs for deduplication.
+//!
+//! Yahoo links to the same article with tracking parameters that change from
+//! page to page, and in no particular parameter order, so comparing URLs as
+//! written queues every article several times over. The fingerprint of a URL
+//! drops its fragment and the ignorable parameters and sorts the rest, so all
+//! the spellings of one page share one fingerprint. Only the seen store works
+//! with fingerprints, the crawl still fetches and records the URL as found.
+
+use url::Url;
+
+/// Query parameters that only track where a click came from. A trailing *
+/// matches any parameter starting with the rest.
+pub const DEFAULT_IGNORED: &[&str] = &[
+    "utm_*",
+    "guccounter",
+    "guce_referrer",
+    "guce_referrer_sig",
+    "soc_src",
+    "soc_trk",
+    "tsrc",
+    ".tsrc",
+    "ncid",
+];
+
+/// Computes fingerprints, ignoring a configurable set of query parameters
+#[derive(Debug, Clone)]
+pub struct UrlFingerprint {
+    ignored: Vec<String>,
+}
+
+impl UrlFingerprint {
+    /// Ignore the default tracking parameters plus `extra`
+    pub fn new<S: AsRef<str>>(extra: impl IntoIterator<Item = S>) -> Self {
+        let ignored = DEFAULT_IGNORED.iter().map(|name| name.to_string())
+            .chain(extra.into_iter().map(|name| name.as_ref().to_string()))
+            .collect();
+        Self { ignored }
+    }
+
+    fn is_ignored(&self, param: &str) -> bool {
+        self.ignored.iter().any(|ignored| match ignored.strip_suffix('*') {
+            Some(prefix) => param.starts_with(prefix),
+            None => param == ignored,
+        })
+    }
+
+    /// The fingerprint of a URL. Text that doesn't parse as a URL is its own
+    /// fingerprint.
+    pub fn of(&self, url: &str) -> String {
+        let Ok(mut url) = Url::parse(url) else {
+            return url.to_string();
+        };
+        url.set_fragment(None);
+        let mut params: Vec<(String, String)> = url.query_pairs()
+            .filter(|(name, _)| !self.is_ignored(name))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        params.sort();
+        if params.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(params);
+        }
+        url.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_parameter_order_and_tracking() {
+        let fingerprint = UrlFingerprint::new(["fr"]);
+        let article = fingerprint.of("https://news.yahoo.com/story.html?id=1&page=2");
+        assert_eq!(article, "https://news.yahoo.com/story.html?id=1&page=2");
+        assert_eq!(fingerprint.of("https://news.yahoo.com/story.html?page=2&id=1"), article);
+        assert_eq!(fingerprint.of("https://news.yahoo.com/story.html?utm_source=fb&page=2&guccounter=1&id=1&fr=home#comments"), article);
+        assert_eq!(fingerprint.of("https://news.yahoo.com/story.html?guccounter=1"), "https://news.yahoo.com/story.html");
+        assert_ne!(fingerprint.of("https://news.yahoo.com/story.html?page=3&id=1"), article);
+        assert_eq!(fingerprint.of("not a url"), "not a url");
+    }
+}
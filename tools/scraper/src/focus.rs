@@ -0,0 +1,58 @@
+//! Focused crawls: only pages matching the include-only patterns are crawled.
+//!
+//! Matching pages are rarely linked from other matching pages alone, so a
+//! page outside the patterns may still be fetched one hop away from a
+//! matching one, or from the seed. Only its links are used, and only those
+//! leading back into the patterns are followed.
+
+use regex::Regex;
+use crate::blacklist::glob_match;
+
+/// The include-only patterns, none means everything is included
+#[derive(Debug, Default)]
+pub struct Focus {
+    globs: Vec<String>,
+    regexes: Vec<Regex>,
+}
+
+impl Focus {
+    /// Build the focus from glob patterns, where * matches anything, and
+    /// regexes, failing on the first regex that doesn't compile
+    pub fn new<'a>(globs: impl IntoIterator<Item = &'a str>, regexes: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let regexes = regexes.into_iter()
+            .map(|pattern| Regex::new(pattern).map_err(|e| format!("Invalid --include-only-regex {}: {}", pattern, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { globs: globs.into_iter().map(str::to_string).collect(), regexes })
+    }
+
+    /// Whether the crawl is focused at all
+    pub fn is_active(&self) -> bool {
+        !self.globs.is_empty() || !self.regexes.is_empty()
+    }
+
+    /// Whether url matches any pattern. Everything does when the crawl isn't focused.
+    pub fn includes(&self, url: &str) -> bool {
+        !self.is_active()
+            || self.globs.iter().any(|glob| glob_match(glob, url))
+            || self.regexes.iter().any(|regex| regex.is_match(url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes_urls_matching_any_pattern() {
+        let focus = Focus::new(["https://sports.yahoo.com/fantasy/*"], [r"^https://news\.yahoo\.com/.*-\d+\.html$"]).unwrap();
+        assert!(focus.is_active());
+        assert!(focus.includes("https://sports.yahoo.com/fantasy/football"));
+        assert!(focus.includes("https://news.yahoo.com/some-story-123.html"));
+        assert!(!focus.includes("https://news.yahoo.com/world"));
+
+        let everything = Focus::default();
+        assert!(!everything.is_active());
+        assert!(everything.includes("https://news.yahoo.com/world"));
+        assert!(Focus::new([], ["("]).is_err());
+    }
+}
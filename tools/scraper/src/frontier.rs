@@ -0,0 +1,528 @@
+//! The queue of URLs waiting to be crawled.
+//!
+//! A frontier can be kept purely in memory or backed by a write-ahead log on
+//! disk. Every URL handed out by [`Frontier::pop`] is leased: it only leaves
+//! the frontier for good once the crawler acknowledges it with
+//! [`Frontier::ack`] after the page has been fully processed. If the process
+//! dies in between, reopening the log puts every unacknowledged URL back at the
+//! front of the queue, so an interrupted crawl never loses in-flight work.
+//! What came of each acknowledged URL is kept in the [`Journal`](crate::journal::Journal)
+//! next to the log, so the pages done before the crash aren't lost either.
+//!
+//! The frontier is also where URLs get assigned to workers, so it enforces the
+//! per-host cap: with [`Frontier::set_host_cap`] set, a URL whose host already
+//! has that many leases out is passed over for the next one on another host.
+//! Without it every worker would end up on the seed host, which has by far the
+//! most links queued.
+//!
+//! Queued URLs wait in buckets that take turns: [`Frontier::set_fairness`]
+//! puts each host, or each section of a host, in a bucket of its own, and
+//! every pop serves the bucket whose turn it is before going around again.
+//! A prolific section then gets its share of the fetches instead of every
+//! one of them. By default there is a single bucket and the queue is plain
+//! FIFO.
+//!
+//! Log records are one per line:
+//!
+//! ```text
+//! # frontier v1   layout version, logs without it are from before versioning
+//! E <id> <url>    url enqueued
+//! L <id>          url leased to a worker
+//! A <id>          url acknowledged, done
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Version of the log layout, written at the top of every log
+const LOG_VERSION: u32 = 1;
+
+/// Compact the log once this many acknowledged records piled up in it
+const COMPACT_AFTER: usize = 10_000;
+
+/// Rough cost of keeping one URL beyond its text: the lease, the String and the queue slot
+const ENTRY_OVERHEAD: usize = 64;
+
+/// A URL handed out by the frontier that still has to be acknowledged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub id: u64,
+    pub url: String,
+}
+
+/// What queued URLs are grouped by to take turns
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Fairness {
+    /// One queue, first in first out
+    #[default]
+    Fifo,
+    /// Hosts take turns
+    Host,
+    /// Sections take turns, a section being a host and the first segment of
+    /// the path, ie: news.yahoo.com/world
+    Section,
+}
+
+impl Fairness {
+    pub fn from_name(name: &str) -> Option<Fairness> {
+        match name {
+            "fifo" => Some(Fairness::Fifo),
+            "host" => Some(Fairness::Host),
+            "section" => Some(Fairness::Section),
+            _ => None,
+        }
+    }
+
+    //the bucket a url waits in
+    fn bucket(self, url: &str) -> String {
+        match self {
+            Fairness::Fifo => String::new(),
+            Fairness::Host => host_of(url),
+            Fairness::Section => {
+                let section = Url::parse(url).ok()
+                    .and_then(|url| url.path_segments().and_then(|mut segments| segments.next().map(str::to_string)))
+                    .unwrap_or_default();
+                format!("{}/{}", host_of(url), section)
+            },
+        }
+    }
+}
+
+/// BFS queue of URLs to crawl, optionally persisted to a write-ahead log
+#[derive(Debug, Default)]
+pub struct Frontier {
+    buckets: HashMap<String, VecDeque<Lease>>, //queued URLs by the bucket they wait in, each FIFO
+    turns: VecDeque<String>,    //buckets with URLs queued, the one to serve next first
+    queued: usize,
+    fairness: Fairness,
+    in_flight: HashMap<u64, String>,
+    fetched: HashSet<u64>,  //leased but past their request, they don't count against the host cap
+    host_in_flight: HashMap<String, usize>,
+    host_cap: Option<usize>,
+    next_id: u64,
+    url_bytes: usize,       //length of every URL queued or in flight, for memory()
+    log: Option<Log>,
+}
+
+#[derive(Debug)]
+struct Log {
+    path: PathBuf,
+    file: File,
+    acked: usize,
+}
+
+impl Frontier {
+    /// A volatile frontier, lost when the process exits
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Open or create the frontier persisted at `path`, recovering any URLs
+    /// that were queued or in flight when the previous run stopped
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut enqueued: BTreeMap<u64, String> = BTreeMap::new();
+        let mut leased: Vec<u64> = Vec::new();
+        let mut next_id = 0;
+
+        if path.exists() {
+            for line in fs::read_to_string(path)?.split_inclusive('\n') {
+                //a torn record from a crash mid-write has no newline yet and is skipped
+                let Some(line) = line.strip_suffix('\n') else {
+                    continue;
+                };
+                if let Some(version) = line.strip_prefix("# frontier v").and_then(|v| v.parse::<u32>().ok()) {
+                    if version > LOG_VERSION {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                            "frontier log version {} is newer than this scraper reads ({})", version, LOG_VERSION)));
+                    }
+                    continue;
+                }
+                let mut parts = line.splitn(3, ' ');
+                let (Some(kind), Some(Ok(id))) = (parts.next(), parts.next().map(str::parse::<u64>)) else {
+                    continue;
+                };
+                next_id = next_id.max(id + 1);
+                match (kind, parts.next()) {
+                    ("E", Some(url)) => {
+                        enqueued.insert(id, url.to_string());
+                    },
+                    ("L", None) => leased.push(id),
+                    ("A", None) => {
+                        enqueued.remove(&id);
+                    },
+                    _ => {},
+                }
+            }
+        }
+
+        //leased but never acknowledged urls go first, they were being worked on
+        let mut pending = Vec::new();
+        for id in leased {
+            if let Some(url) = enqueued.remove(&id) {
+                pending.push(Lease { id, url });
+            }
+        }
+        pending.extend(enqueued.into_iter().map(|(id, url)| Lease { id, url }));
+
+        let mut frontier = Self { next_id, ..Self::default() };
+        for lease in pending {
+            frontier.url_bytes += lease.url.len();
+            frontier.enqueue(lease);
+        }
+        frontier.log = Some(frontier.rewrite_log(path)?);
+        Ok(frontier)
+    }
+
+    /// Add a URL to the back of the queue
+    pub fn push(&mut self, url: String) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.record(&format!("E {} {}\n", id, url));
+        self.url_bytes += url.len();
+        self.enqueue(Lease { id, url });
+    }
+
+    //put a lease at the back of its bucket, a bucket that was empty waits its turn behind the others
+    fn enqueue(&mut self, lease: Lease) {
+        let bucket = self.fairness.bucket(&lease.url);
+        let queue = self.buckets.entry(bucket.clone()).or_default();
+        if queue.is_empty() {
+            self.turns.push_back(bucket);
+        }
+        queue.push_back(lease);
+        self.queued += 1;
+    }
+
+    /// Allow at most `cap` URLs of one host to be leased at once
+    pub fn set_host_cap(&mut self, cap: usize) {
+        self.host_cap = Some(cap);
+    }
+
+    /// Group queued URLs into buckets taking turns by this, the URLs already
+    /// queued included
+    pub fn set_fairness(&mut self, fairness: Fairness) {
+        let queued: Vec<Lease> = self.queue_order().into_iter().cloned().collect();
+        self.buckets.clear();
+        self.turns.clear();
+        self.queued = 0;
+        self.fairness = fairness;
+        for lease in queued {
+            self.enqueue(lease);
+        }
+    }
+
+    /// Take the URL nearest the front of the bucket whose turn it is, passing
+    /// over URLs whose host is at the cap and buckets with nothing but those.
+    /// The bucket served goes to the back of the turns. The URL stays in the
+    /// frontier until it is acknowledged. None if the queue is empty or every
+    /// queued host is at its cap.
+    pub fn pop(&mut self) -> Option<Lease> {
+        let (turn, index) = self.turns.iter().enumerate().find_map(|(turn, bucket)| {
+            let queue = &self.buckets[bucket];
+            let index = match self.host_cap {
+                Some(cap) => queue.iter().position(|lease| self.host_in_flight.get(&host_of(&lease.url)).is_none_or(|&n| n < cap))?,
+                None => 0,
+            };
+            Some((turn, index))
+        })?;
+        let bucket = self.turns.remove(turn).unwrap();
+        let queue = self.buckets.get_mut(&bucket).unwrap();
+        let lease = queue.remove(index).unwrap();
+        if queue.is_empty() {
+            self.buckets.remove(&bucket);
+        } else {
+            self.turns.push_back(bucket);
+        }
+        self.queued -= 1;
+        self.record(&format!("L {}\n", lease.id));
+        *self.host_in_flight.entry(host_of(&lease.url)).or_default() += 1;
+        self.in_flight.insert(lease.id, lease.url.clone());
+        Some(lease)
+    }
+
+    /// Note that the request for a leased URL is done. The URL stays leased
+    /// until it's acknowledged, but while its page is being processed it no
+    /// longer counts against the per-host cap.
+    pub fn fetched(&mut self, id: u64) {
+        let Some(host) = self.in_flight.get(&id).map(|url| host_of(url)) else {
+            return;
+        };
+        if self.fetched.insert(id) {
+            self.release_host(&host);
+        }
+    }
+
+    /// Mark a leased URL as done so it is never handed out again
+    pub fn ack(&mut self, id: u64) {
+        let Some(url) = self.in_flight.remove(&id) else {
+            return;
+        };
+        if !self.fetched.remove(&id) {
+            self.release_host(&host_of(&url));
+        }
+        self.url_bytes -= url.len();
+        self.record(&format!("A {}\n", id));
+        let should_compact = match &mut self.log {
+            Some(log) => {
+                log.acked += 1;
+                log.acked >= COMPACT_AFTER
+            },
+            None => false,
+        };
+        if should_compact {
+            self.compact();
+        }
+    }
+
+    fn release_host(&mut self, host: &str) {
+        if let Some(n) = self.host_in_flight.get_mut(host) {
+            *n -= 1;
+            if *n == 0 {
+                self.host_in_flight.remove(host);
+            }
+        }
+    }
+
+    /// Number of URLs waiting to be leased
+    pub fn len(&self) -> usize {
+        self.queued
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queued == 0
+    }
+
+    /// Roughly how much memory the queued and in flight URLs take
+    pub fn memory(&self) -> usize {
+        self.url_bytes + (self.queued + self.in_flight.len()) * ENTRY_OVERHEAD
+    }
+
+    /// URLs waiting to be leased, in the order they would be with no host at its cap
+    pub fn pending_urls(&self) -> impl Iterator<Item = &str> {
+        self.queue_order().into_iter().map(|lease| lease.url.as_str())
+    }
+
+    //the buckets in turn, one URL from each on every round
+    fn queue_order(&self) -> Vec<&Lease> {
+        let queues: Vec<&VecDeque<Lease>> = self.turns.iter().map(|bucket| &self.buckets[bucket]).collect();
+        let rounds = queues.iter().map(|queue| queue.len()).max().unwrap_or(0);
+        (0..rounds).flat_map(|round| queues.iter().filter_map(move |queue| queue.get(round))).collect()
+    }
+
+    /// URLs leased and not yet acknowledged, oldest lease first
+    pub fn in_flight_urls(&self) -> Vec<&str> {
+        let mut in_flight: Vec<(&u64, &String)> = self.in_flight.iter().collect();
+        in_flight.sort_unstable();
+        in_flight.into_iter().map(|(_, url)| url.as_str()).collect()
+    }
+
+    /// Take a queued URL out of the frontier for good, as if it had been
+    /// crawled. False if it isn't waiting to be leased.
+    pub fn remove(&mut self, url: &str) -> bool {
+        let bucket = self.fairness.bucket(url);
+        let Some(queue) = self.buckets.get_mut(&bucket) else {
+            return false;
+        };
+        let Some(index) = queue.iter().position(|lease| lease.url == url) else {
+            return false;
+        };
+        let lease = queue.remove(index).unwrap();
+        if queue.is_empty() {
+            self.buckets.remove(&bucket);
+            self.turns.retain(|turn| *turn != bucket);
+        }
+        self.queued -= 1;
+        self.url_bytes -= lease.url.len();
+        self.record(&format!("A {}\n", lease.id));
+        true
+    }
+
+    fn record(&mut self, record: &str) {
+        if let Some(log) = &mut self.log {
+            //one write per record so a crash can only tear the last line
+            if let Err(e) = log.file.write_all(record.as_bytes()) {
+                status!("Frontier log write failed! {}", e);
+            }
+        }
+    }
+
+    //drop acknowledged records by rewriting the log with only live entries
+    fn compact(&mut self) {
+        let Some(path) = self.log.as_ref().map(|log| log.path.clone()) else {
+            return;
+        };
+        match self.rewrite_log(&path) {
+            Ok(log) => self.log = Some(log),
+            Err(e) => status!("Frontier log compaction failed! {}", e),
+        }
+    }
+
+    //write the live entries to a temporary file and atomically swap it in
+    fn rewrite_log(&self, path: &Path) -> io::Result<Log> {
+        let tmp_path = path.with_extension("tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        writeln!(tmp, "# frontier v{}", LOG_VERSION)?;
+        let mut live: Vec<(u64, &str)> = self.in_flight.iter().map(|(&id, url)| (id, url.as_str())).collect();
+        live.sort_unstable();
+        for (id, url) in live {
+            writeln!(tmp, "E {} {}", id, url)?;
+            writeln!(tmp, "L {}", id)?;
+        }
+        for lease in self.queue_order() {
+            writeln!(tmp, "E {} {}", lease.id, lease.url)?;
+        }
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Log {
+            path: path.to_path_buf(),
+            file,
+            acked: 0,
+        })
+    }
+}
+
+//the host a url counts against for the per-host cap, unparsable urls share one
+fn host_of(url: &str) -> String {
+    Url::parse(url).ok()
+        .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("frontier-{}-{}.log", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn recovers_unacknowledged_urls() {
+        let path = log_path("recover");
+        {
+            let mut frontier = Frontier::open(&path).unwrap();
+            frontier.push("https://www.yahoo.com/".to_string());
+            frontier.push("https://news.yahoo.com/".to_string());
+            frontier.push("https://finance.yahoo.com/".to_string());
+            let done = frontier.pop().unwrap();
+            frontier.ack(done.id);
+            //leased but the process "crashes" before acknowledging it
+            frontier.pop().unwrap();
+        }
+        let mut frontier = Frontier::open(&path).unwrap();
+        assert_eq!(
+            frontier.pending_urls().collect::<Vec<_>>(),
+            ["https://news.yahoo.com/", "https://finance.yahoo.com/"]
+        );
+        frontier.push("https://sports.yahoo.com/".to_string());
+        assert_eq!(frontier.len(), 3);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn caps_leases_per_host() {
+        let mut frontier = Frontier::in_memory();
+        frontier.set_host_cap(2);
+        for url in ["https://www.yahoo.com/a", "https://www.yahoo.com/b", "https://www.yahoo.com/c", "https://news.yahoo.com/"] {
+            frontier.push(url.to_string());
+        }
+        let a = frontier.pop().unwrap();
+        frontier.pop().unwrap();
+        //www.yahoo.com is at its cap, so the worker gets the next host instead
+        assert_eq!(frontier.pop().unwrap().url, "https://news.yahoo.com/");
+        assert_eq!(frontier.pop(), None);
+        assert_eq!(frontier.len(), 1);
+        //a fetched page being processed doesn't hold on to its slot
+        frontier.fetched(a.id);
+        assert_eq!(frontier.pop().unwrap().url, "https://www.yahoo.com/c");
+        frontier.push("https://www.yahoo.com/d".to_string());
+        frontier.ack(a.id);
+        assert_eq!(frontier.pop(), None);
+    }
+
+    #[test]
+    fn takes_turns_between_buckets() {
+        let mut frontier = Frontier::in_memory();
+        for url in ["https://news.yahoo.com/world/a", "https://news.yahoo.com/world/b", "https://news.yahoo.com/world/c",
+                    "https://news.yahoo.com/us/a", "https://finance.yahoo.com/quote/AAPL"] {
+            frontier.push(url.to_string());
+        }
+        frontier.set_fairness(Fairness::Section);
+        assert_eq!(frontier.pending_urls().collect::<Vec<_>>(), [
+            "https://news.yahoo.com/world/a", "https://news.yahoo.com/us/a", "https://finance.yahoo.com/quote/AAPL",
+            "https://news.yahoo.com/world/b", "https://news.yahoo.com/world/c",
+        ]);
+        assert_eq!(frontier.pop().unwrap().url, "https://news.yahoo.com/world/a");
+        //a bucket that empties and fills again waits behind the others
+        assert!(frontier.remove("https://news.yahoo.com/us/a"));
+        frontier.push("https://news.yahoo.com/us/b".to_string());
+        let order: Vec<String> = std::iter::from_fn(|| frontier.pop()).map(|lease| lease.url).collect();
+        assert_eq!(order, [
+            "https://finance.yahoo.com/quote/AAPL", "https://news.yahoo.com/world/b", "https://news.yahoo.com/us/b", "https://news.yahoo.com/world/c",
+        ]);
+
+        //with hosts taking turns, a host at its cap gives up its turn
+        let mut frontier = Frontier::in_memory();
+        frontier.set_fairness(Fairness::Host);
+        frontier.set_host_cap(1);
+        for url in ["https://news.yahoo.com/a", "https://news.yahoo.com/b", "https://finance.yahoo.com/", "https://sports.yahoo.com/"] {
+            frontier.push(url.to_string());
+        }
+        let news = frontier.pop().unwrap();
+        assert_eq!(frontier.pop().unwrap().url, "https://finance.yahoo.com/");
+        frontier.ack(news.id);
+        assert_eq!(frontier.pop().unwrap().url, "https://sports.yahoo.com/");
+        assert_eq!(frontier.pop().unwrap().url, "https://news.yahoo.com/b");
+        assert!(frontier.is_empty());
+    }
+
+    #[test]
+    fn skips_torn_records() {
+        let path = log_path("torn");
+        fs::write(&path, "E 0 https://www.yahoo.com/\nE 1 https://news.yah").unwrap();
+        let frontier = Frontier::open(&path).unwrap();
+        assert_eq!(frontier.pending_urls().collect::<Vec<_>>(), ["https://www.yahoo.com/"]);
+        fs::write(&path, "E 0 https://www.yahoo.com/\nA 0").unwrap();
+        let frontier = Frontier::open(&path).unwrap();
+        assert_eq!(frontier.len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn removes_queued_urls_for_good() {
+        let path = log_path("remove");
+        {
+            let mut frontier = Frontier::open(&path).unwrap();
+            frontier.push("https://www.yahoo.com/".to_string());
+            frontier.push("https://news.yahoo.com/".to_string());
+            frontier.push("https://finance.yahoo.com/".to_string());
+            let lease = frontier.pop().unwrap();
+            assert_eq!(frontier.in_flight_urls(), [lease.url.as_str()]);
+            assert!(!frontier.remove(&lease.url));
+            assert!(frontier.remove("https://finance.yahoo.com/"));
+            assert!(!frontier.remove("https://finance.yahoo.com/"));
+            assert_eq!(frontier.memory(), "https://www.yahoo.com/".len() + "https://news.yahoo.com/".len() + 2 * ENTRY_OVERHEAD);
+        }
+        let frontier = Frontier::open(&path).unwrap();
+        assert_eq!(frontier.pending_urls().collect::<Vec<_>>(), ["https://www.yahoo.com/", "https://news.yahoo.com/"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn refuses_logs_from_newer_versions() {
+        let path = log_path("version");
+        Frontier::open(&path).unwrap().push("https://www.yahoo.com/".to_string());
+        assert!(fs::read_to_string(&path).unwrap().starts_with("# frontier v1\n"));
+        assert_eq!(Frontier::open(&path).unwrap().len(), 1);
+        fs::write(&path, format!("# frontier v{}\nE 0 https://www.yahoo.com/\n", LOG_VERSION + 1)).unwrap();
+        assert_eq!(Frontier::open(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+//! The shared HTTP client: one connection pool for the whole crawl and a DNS
+//! cache that resolves hosts as soon as they are discovered, so fetches don't
+//! pay for fresh DNS lookups and TCP/TLS handshakes every time. The cache is
+//! also where excluded address ranges are enforced, as every connection the
+//! client opens gets its address from it.
+//!
+//! Builds with the http3 feature can speak HTTP/3 over QUIC instead, to every
+//! host, for comparing how a crawl fares over each protocol.
+//!
+//! Page fetches go out through the [`Fetcher`] trait rather than the client
+//! itself, so the fetch logic can be pointed at a mock server in tests.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use hyper::client::connect::dns::Name;
+use reqwest::blocking::{Client, Response};
+use reqwest::cookie::Jar;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::header::HeaderMap;
+use reqwest::redirect::Policy;
+use crate::ip_range::ExcludedRanges;
+
+/// Redirects followed before giving up, the same as reqwest's default
+const MAX_REDIRECTS: usize = 10;
+
+/// Sends the page requests of a crawl
+pub trait Fetcher {
+    /// Send a GET for url with these headers, giving up on it after `timeout`
+    /// in all, body included. Without one it is only given up on once the
+    /// server goes quiet for as long as the client's own timeout.
+    fn fetch(&self, url: &str, headers: HeaderMap, timeout: Option<Duration>) -> reqwest::Result<Response>;
+}
+
+impl Fetcher for Client {
+    fn fetch(&self, url: &str, headers: HeaderMap, timeout: Option<Duration>) -> reqwest::Result<Response> {
+        let request = self.get(url).headers(headers);
+        match timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        }.send()
+    }
+}
+
+/// Connection pool settings of the HTTP client
+#[derive(Debug, Clone)]
+pub struct PoolSettings {
+    /// Idle connections kept open per host
+    pub max_idle_per_host: usize,
+    /// How long an idle connection is kept before it's closed
+    pub idle_timeout: Duration,
+    /// TCP keepalive interval, None to turn keepalive off
+    pub keepalive: Option<Duration>,
+    /// Speak HTTP/3 to every host, only honoured in builds with the http3 feature
+    pub http3: bool,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: 8,
+            idle_timeout: Duration::from_secs(90),
+            keepalive: Some(Duration::from_secs(60)),
+            http3: false,
+        }
+    }
+}
+
+/// Build the one client every request of the crawl goes through. With
+/// `https_only` it refuses to send anything over plain http. Cookies are only
+/// kept when a jar is given. Redirects to an address in a range the DNS
+/// cache excludes are refused.
+pub fn build_client(pool: &PoolSettings, dns: Arc<DnsCache>, https_only: bool, cookies: Option<Arc<Jar>>) -> reqwest::Result<Client> {
+    //a redirect to a host name gets checked when it is resolved, one to a literal address never is
+    let excluded = dns.excluded.clone();
+    let redirects = Policy::custom(move |attempt| {
+        if let Some(range) = excluded.matching_url(attempt.url()) {
+            let error = format!("redirect to {} is in excluded range {}", attempt.url(), range);
+            attempt.error(error)
+        } else if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else {
+            attempt.follow()
+        }
+    });
+    //the blocking builder can't take a resolver, so configure the async one and convert
+    let mut builder = reqwest::ClientBuilder::new()
+        .redirect(redirects)
+        .dns_resolver(dns)
+        .pool_max_idle_per_host(pool.max_idle_per_host)
+        .pool_idle_timeout(pool.idle_timeout)
+        .tcp_keepalive(pool.keepalive)
+        .https_only(https_only);
+    if let Some(jar) = cookies {
+        builder = builder.cookie_provider(jar);
+    }
+    //HTTP/3 only, hosts without QUIC fail rather than fall back, so the protocols don't mix in one crawl
+    #[cfg(feature = "http3")]
+    if pool.http3 {
+        builder = builder.http3_prior_knowledge();
+    }
+    reqwest::blocking::ClientBuilder::from(builder).build()
+}
+
+/// Resolved addresses of every host the crawl talked to, each kept for `ttl`.
+/// Addresses in an excluded range are never handed out.
+#[derive(Debug)]
+pub struct DnsCache {
+    entries: Mutex<HashMap<String, (Instant, Vec<SocketAddr>)>>,
+    prefetching: Mutex<HashSet<String>>,
+    ttl: Duration,
+    excluded: ExcludedRanges,
+}
+
+impl DnsCache {
+    pub fn new(ttl: Duration, excluded: ExcludedRanges) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            prefetching: Mutex::new(HashSet::new()),
+            ttl,
+            excluded,
+        }
+    }
+
+    /// The ranges no address is handed out from
+    pub fn excluded(&self) -> &ExcludedRanges {
+        &self.excluded
+    }
+
+    /// Resolve the host in the background so the address is ready by the time
+    /// the first URL on it is fetched
+    pub fn prefetch(self: &Arc<Self>, host: &str) {
+        if self.cached(host).is_some() || !self.prefetching.lock().unwrap().insert(host.to_string()) {
+            return;
+        }
+        let cache = Arc::clone(self);
+        let host = host.to_string();
+        thread::spawn(move || {
+            //a failed prefetch is fine, the fetch itself will resolve and report it
+            let _ = cache.lookup(&host);
+            cache.prefetching.lock().unwrap().remove(&host);
+        });
+    }
+
+    /// The addresses of host a connection may go to, as the client gets them
+    pub fn resolve(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        self.lookup(host).and_then(|addrs| self.allowed(host, addrs))
+    }
+
+    fn cached(&self, host: &str) -> Option<Vec<SocketAddr>> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(host)
+            .filter(|(resolved_at, _)| resolved_at.elapsed() < self.ttl)
+            .map(|(_, addrs)| addrs.clone())
+    }
+
+    //resolve through the system resolver and remember the answer
+    fn lookup(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        if let Some(addrs) = self.cached(host) {
+            return Ok(addrs);
+        }
+        //the port is replaced by the connector, only the addresses matter
+        let addrs: Vec<SocketAddr> = (host, 0).to_socket_addrs()?.collect();
+        self.entries.lock().unwrap().insert(host.to_string(), (Instant::now(), addrs.clone()));
+        Ok(addrs)
+    }
+
+    //the addresses outside the excluded ranges, an error if that leaves none
+    fn allowed(&self, host: &str, addrs: Vec<SocketAddr>) -> io::Result<Vec<SocketAddr>> {
+        let (excluded, allowed): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs.into_iter()
+            .partition(|addr| self.excluded.matching(addr.ip()).is_some());
+        match (allowed.is_empty(), excluded.first()) {
+            (true, Some(addr)) => {
+                let range = self.excluded.matching(addr.ip()).unwrap();
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} resolves to {}, in excluded range {}", host, addr.ip(), range)))
+            },
+            _ => Ok(allowed),
+        }
+    }
+}
+
+impl Resolve for DnsCache {
+    fn resolve(&self, name: Name) -> Resolving {
+        let result = self.resolve(name.as_str())
+            .map(|addrs| Box::new(addrs.into_iter()) as Addrs)
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>);
+        Box::pin(std::future::ready(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hands_out_only_allowed_addresses() {
+        let dns = DnsCache::new(Duration::from_secs(60), ExcludedRanges::new(["private"]).unwrap());
+        let addr = |addr: &str| SocketAddr::new(addr.parse().unwrap(), 0);
+        assert_eq!(dns.allowed("mixed.yahoo.com", vec![addr("10.0.0.1"), addr("87.248.100.215")]).unwrap(), [addr("87.248.100.215")]);
+        let e = dns.allowed("internal.yahoo.com", vec![addr("10.0.0.1"), addr("::1")]).unwrap_err();
+        assert_eq!(e.to_string(), "internal.yahoo.com resolves to 10.0.0.1, in excluded range 10.0.0.0/8");
+        let open = DnsCache::new(Duration::from_secs(60), ExcludedRanges::default());
+        assert_eq!(open.allowed("internal.yahoo.com", vec![addr("10.0.0.1")]).unwrap(), [addr("10.0.0.1")]);
+    }
+
+    #[test]
+    fn resolves_only_to_allowed_addresses() {
+        let dns = DnsCache::new(Duration::from_secs(60), ExcludedRanges::new(["private"]).unwrap());
+        assert!(dns.resolve("127.0.0.1").is_err());
+        let open = DnsCache::new(Duration::from_secs(60), ExcludedRanges::default());
+        assert_eq!(open.resolve("127.0.0.1").unwrap(), [SocketAddr::new("127.0.0.1".parse().unwrap(), 0)]);
+    }
+}
@@ -0,0 +1,156 @@
+//! Which images get downloaded. An image is checked three times: by its URL
+//! before it is fetched, by the Content-Type of the response, and by the
+//! header of the downloaded bytes, so a type that can't be told from the URL
+//! is still caught before it is recorded.
+
+use std::fmt;
+use url::Url;
+use crate::blacklist::glob_match;
+use crate::image_meta::ImageMeta;
+
+/// Images are only downloaded from here unless --image-url says otherwise
+const DEFAULT_IMAGE_PATTERN: &str = "https://s.yimg.com/*";
+
+/// Why an image was left out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Skip {
+    /// The URL matches none of the --image-url patterns
+    NotIncluded,
+    /// The URL matches this --skip-image-url pattern
+    Excluded(String),
+    /// The image is of a type --skip-image-type names
+    Type(String),
+    /// The image is smaller than the minimum dimensions
+    TooSmall { width: usize, height: usize },
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Skip::NotIncluded => write!(f, "not matching --image-url"),
+            Skip::Excluded(pattern) => write!(f, "matching --skip-image-url {}", pattern),
+            Skip::Type(kind) => write!(f, "{} skipped by type", kind),
+            Skip::TooSmall { width, height } => write!(f, "too small at {}x{}", width, height),
+        }
+    }
+}
+
+/// The image filters of a crawl
+#[derive(Debug, Clone)]
+pub struct ImageFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    //normalized with image_type
+    skip_types: Vec<String>,
+    min_width: usize,
+    min_height: usize,
+}
+
+impl ImageFilter {
+    /// Build the filter from glob patterns image URLs must match, where * matches
+    /// anything, patterns they must not match, types to skip as extensions or MIME
+    /// types, and the minimum dimensions. Without include patterns only images on
+    /// s.yimg.com are downloaded.
+    pub fn new<'a>(
+        include: impl IntoIterator<Item = &'a str>,
+        exclude: impl IntoIterator<Item = &'a str>,
+        skip_types: impl IntoIterator<Item = &'a str>,
+        min_width: usize,
+        min_height: usize,
+    ) -> Self {
+        let mut include: Vec<String> = include.into_iter().map(str::to_string).collect();
+        if include.is_empty() {
+            include.push(DEFAULT_IMAGE_PATTERN.to_string());
+        }
+        Self {
+            include,
+            exclude: exclude.into_iter().map(str::to_string).collect(),
+            skip_types: skip_types.into_iter().map(image_type).collect(),
+            min_width,
+            min_height,
+        }
+    }
+
+    /// Check an image URL before it is fetched, by pattern and by the extension of its path
+    pub fn check_url(&self, url: &str) -> Result<(), Skip> {
+        if !self.include.iter().any(|pattern| glob_match(pattern, url)) {
+            return Err(Skip::NotIncluded);
+        }
+        if let Some(pattern) = self.exclude.iter().find(|pattern| glob_match(pattern, url)) {
+            return Err(Skip::Excluded(pattern.clone()));
+        }
+        let extension = Url::parse(url).ok()
+            .and_then(|url| url.path().rsplit_once('.').map(|(_, extension)| extension.to_string()));
+        match extension {
+            Some(extension) => self.check_type(&extension),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the Content-Type a server sent with an image
+    pub fn check_content_type(&self, content_type: &str) -> Result<(), Skip> {
+        self.check_type(content_type.split(';').next().unwrap_or(""))
+    }
+
+    /// Check what the downloaded bytes turned out to be
+    pub fn check_meta(&self, meta: &ImageMeta) -> Result<(), Skip> {
+        self.check_type(&meta.format)?;
+        if meta.width < self.min_width || meta.height < self.min_height {
+            return Err(Skip::TooSmall { width: meta.width, height: meta.height });
+        }
+        Ok(())
+    }
+
+    fn check_type(&self, kind: &str) -> Result<(), Skip> {
+        let kind = image_type(kind);
+        match self.skip_types.contains(&kind) {
+            true => Err(Skip::Type(kind)),
+            false => Ok(()),
+        }
+    }
+}
+
+//the same name for an extension, a MIME type and a detected format: "SVG", "image/svg+xml" -> "svg"
+fn image_type(kind: &str) -> String {
+    let kind = kind.trim().to_ascii_lowercase();
+    let kind = kind.strip_prefix("image/").unwrap_or(&kind);
+    let kind = kind.strip_suffix("+xml").unwrap_or(kind);
+    match kind {
+        "jpg" | "jpe" => "jpeg".to_string(),
+        "tif" => "tiff".to_string(),
+        kind => kind.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn meta(format: &str, width: usize, height: usize) -> ImageMeta {
+        ImageMeta { width, height, format: format.to_string(), exif: BTreeMap::new() }
+    }
+
+    #[test]
+    fn defaults_to_yimg() {
+        let filter = ImageFilter::new([], [], [], 2, 2);
+        assert_eq!(filter.check_url("https://s.yimg.com/logo.png"), Ok(()));
+        assert_eq!(filter.check_url("https://example.com/logo.png"), Err(Skip::NotIncluded));
+        assert_eq!(filter.check_meta(&meta("png", 1, 1)), Err(Skip::TooSmall { width: 1, height: 1 }));
+    }
+
+    #[test]
+    fn filters_by_pattern_type_and_size() {
+        let filter = ImageFilter::new(["https://s.yimg.com/*", "https://*.yahoo.com/*"], ["*/ads/*"], ["SVG", "image/gif", "jpg"], 100, 50);
+        assert_eq!(filter.check_url("https://media.yahoo.com/photo.webp?w=300"), Ok(()));
+        assert_eq!(filter.check_url("https://s.yimg.com/ads/banner.png"), Err(Skip::Excluded("*/ads/*".to_string())));
+        assert_eq!(filter.check_url("https://s.yimg.com/icon.SVG"), Err(Skip::Type("svg".to_string())));
+        assert_eq!(filter.check_url("https://s.yimg.com/spinner.gif?v=2"), Err(Skip::Type("gif".to_string())));
+        assert_eq!(filter.check_url("https://s.yimg.com/no-extension"), Ok(()));
+        assert_eq!(filter.check_content_type("image/svg+xml; charset=utf-8"), Err(Skip::Type("svg".to_string())));
+        assert_eq!(filter.check_content_type("image/png"), Ok(()));
+        assert_eq!(filter.check_meta(&meta("jpeg", 400, 300)), Err(Skip::Type("jpeg".to_string())));
+        assert_eq!(filter.check_meta(&meta("png", 400, 40)), Err(Skip::TooSmall { width: 400, height: 40 }));
+        assert_eq!(filter.check_meta(&meta("png", 100, 50)), Ok(()));
+    }
+}
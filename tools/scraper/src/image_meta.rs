@@ -0,0 +1,80 @@
+//! Decoding the headers of downloaded images to learn what they actually are.
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use exif::{In, Reader, Tag};
+
+/// EXIF fields worth keeping, the rest is mostly camera internals
+const EXIF_TAGS: [Tag; 6] = [
+    Tag::Make,
+    Tag::Model,
+    Tag::DateTimeOriginal,
+    Tag::Orientation,
+    Tag::Software,
+    Tag::Copyright,
+];
+
+/// What the image header says about an image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageMeta {
+    pub width: usize,
+    pub height: usize,
+    /// Lowercase format name, ie: "jpeg", "png", "webp"
+    pub format: String,
+    /// Selected EXIF fields by tag name, empty if the image has none
+    pub exif: BTreeMap<String, String>,
+}
+
+impl ImageMeta {
+    /// Tracking pixels and spacers are tiny in at least one direction
+    pub fn is_smaller_than(&self, min_dimension: usize) -> bool {
+        self.width < min_dimension || self.height < min_dimension
+    }
+}
+
+/// Read dimensions, format and EXIF from the image bytes. Returns None if the
+/// bytes are not an image format we recognize.
+pub fn inspect(bytes: &[u8]) -> Option<ImageMeta> {
+    let format = imagesize::image_type(bytes).ok()?;
+    let size = imagesize::blob_size(bytes).ok()?;
+    Some(ImageMeta {
+        width: size.width,
+        height: size.height,
+        format: format!("{:?}", format).to_lowercase(),
+        exif: read_exif(bytes),
+    })
+}
+
+fn read_exif(bytes: &[u8]) -> BTreeMap<String, String> {
+    let Ok(exif) = Reader::new().read_from_container(&mut Cursor::new(bytes)) else {
+        return BTreeMap::new();
+    };
+    EXIF_TAGS.iter()
+        .filter_map(|&tag| {
+            let field = exif.get_field(tag, In::PRIMARY)?;
+            Some((tag.to_string(), field.display_value().with_unit(&exif).to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //smallest valid GIF: a single transparent pixel, the classic tracking pixel
+    const PIXEL_GIF: &[u8] = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff!\xf9\x04\x01\x00\x00\x00\x00,\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02D\x01\x00;";
+
+    #[test]
+    fn recognizes_tracking_pixel() {
+        let meta = inspect(PIXEL_GIF).unwrap();
+        assert_eq!((meta.width, meta.height), (1, 1));
+        assert_eq!(meta.format, "gif");
+        assert!(meta.exif.is_empty());
+        assert!(meta.is_smaller_than(2));
+    }
+
+    #[test]
+    fn rejects_non_images() {
+        assert_eq!(inspect(b"<html></html>"), None);
+    }
+}
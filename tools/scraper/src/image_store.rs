@@ -0,0 +1,120 @@
+//! Keeping the images a crawl downloads, with --save-images. Every image is
+//! stored once under the SHA-256 of its bytes, `objects/ab/cd/abcd...`, no
+//! matter how many URLs serve it, and index.json next to the objects maps
+//! each URL to the hash it had when last fetched.
+//!
+//! Objects never change once written, so a store can be shared by crawl after
+//! crawl and copied around with rsync, which only sends the new ones. The
+//! index is read when the store is opened and written back when the crawl
+//! ends, keeping the URLs earlier crawls stored.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use sha2::{Digest, Sha256};
+use crate::results;
+
+/// A content-addressed directory of images
+#[derive(Debug)]
+pub struct ImageStore {
+    root: PathBuf,
+    index: BTreeMap<String, String>, //URL to the hash of its bytes
+    stored: usize,                   //objects written by this crawl
+    deduplicated: usize,             //images this crawl found already stored
+}
+
+impl ImageStore {
+    /// Open the store at root, creating it if needed
+    pub fn open(root: &Path) -> Result<Self, String> {
+        fs::create_dir_all(root.join("objects")).map_err(|e| format!("Could not create {}: {}", root.display(), e))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            index: results::load_optional(&root.join("index.json"))?,
+            stored: 0,
+            deduplicated: 0,
+        })
+    }
+
+    /// Store the bytes url served and return their hash, writing nothing
+    /// if the same bytes are already there
+    pub fn put(&mut self, url: &str, bytes: &[u8]) -> io::Result<String> {
+        let hash = hex(&Sha256::digest(bytes));
+        let path = self.object_path(&hash);
+        if path.exists() {
+            self.deduplicated += 1;
+        } else {
+            fs::create_dir_all(path.parent().unwrap())?;
+            //a crawl killed halfway through leaves a stray .tmp, never a torn object
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, bytes)?;
+            fs::rename(&tmp, &path)?;
+            self.stored += 1;
+        }
+        self.index.insert(url.to_string(), hash.clone());
+        Ok(hash)
+    }
+
+    /// Whether the object with this hash is in the store
+    pub fn contains(&self, hash: &str) -> bool {
+        self.object_path(hash).exists()
+    }
+
+    /// Note that url still serves the stored object hash, for an image that wasn't downloaded again
+    pub fn keep(&mut self, url: &str, hash: &str) {
+        self.index.insert(url.to_string(), hash.to_string());
+    }
+
+    /// Objects written and images already stored, this crawl
+    pub fn counts(&self) -> (usize, usize) {
+        (self.stored, self.deduplicated)
+    }
+
+    /// Write index.json back
+    pub fn save_index(&self) -> Result<(), String> {
+        let path = self.root.join("index.json");
+        let file = File::create(&path).map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+        results::write_json(file, &self.index).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+
+    /// Where the object with this hash is kept: two levels of directories
+    /// named after its first four hex digits, so none grows too large
+    pub fn object_path(&self, hash: &str) -> PathBuf {
+        self.root.join("objects").join(&hash[..2]).join(&hash[2..4]).join(hash)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_each_image_once() {
+        let dir = std::env::temp_dir().join(format!("image-store-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut store = ImageStore::open(&dir).unwrap();
+        let hash = store.put("https://s.yimg.com/a.png", b"png bytes").unwrap();
+        assert_eq!(hash, "d013614dc14a37ee20fe92005737ab7d3427e7e93580ad56ef8a42205e7f7a4e");
+        assert_eq!(store.put("https://s.yimg.com/mirror/a.png", b"png bytes").unwrap(), hash);
+        store.put("https://s.yimg.com/b.png", b"other bytes").unwrap();
+        assert_eq!(store.counts(), (2, 1));
+        let path = store.object_path(&hash);
+        assert_eq!(path, dir.join("objects").join(&hash[..2]).join(&hash[2..4]).join(&hash));
+        assert_eq!(fs::read(&path).unwrap(), b"png bytes");
+        store.save_index().unwrap();
+
+        //a later crawl sharing the store keeps what earlier ones indexed
+        let mut store = ImageStore::open(&dir).unwrap();
+        assert!(store.contains(&hash));
+        store.keep("https://s.yimg.com/c.png", &hash);
+        store.save_index().unwrap();
+        let index: BTreeMap<String, String> = results::load_optional(&dir.join("index.json")).unwrap();
+        assert_eq!(index.len(), 4);
+        assert_eq!(index["https://s.yimg.com/mirror/a.png"], hash);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
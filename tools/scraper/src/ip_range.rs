@@ -0,0 +1,154 @@
+//! Address ranges the crawler must not connect to. A page can link to, or a
+//! server redirect to, a name that resolves to an internal address, and
+//! following it would let an outside page steer the crawler into the network
+//! it runs in. Ranges are checked on every DNS answer and on links and
+//! redirects to literal addresses, so a link can't get around them.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// What `private` stands for in --exclude-ip-range: loopback, RFC 1918,
+/// carrier-grade NAT, link-local and their IPv6 counterparts
+const PRIVATE: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "::/128",
+    "::1/128",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+/// A block of addresses in CIDR notation, ie: 10.0.0.0/8 or fd00::/8
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        //an IPv4 address written as IPv6 (::ffff:10.0.0.1) is still that IPv4 address
+        match (self.network, addr.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => same_prefix(u32::from(network).into(), u32::from(addr).into(), 32, self.prefix),
+            (IpAddr::V6(network), IpAddr::V6(addr)) => same_prefix(u128::from(network), u128::from(addr), 128, self.prefix),
+            _ => false,
+        }
+    }
+}
+
+//whether the first prefix of bits bits of a and b agree
+fn same_prefix(a: u128, b: u128, bits: u8, prefix: u8) -> bool {
+    let shift = bits - prefix;
+    shift == bits || (a >> shift) == (b >> shift)
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    /// Parse a range, a bare address being a range of one
+    fn from_str(range: &str) -> Result<Self, String> {
+        let invalid = || format!("{} is not an IP range, expected an address or address/prefix", range);
+        let (network, prefix) = match range.split_once('/') {
+            Some((network, prefix)) => (network, Some(prefix.parse::<u8>().map_err(|_| invalid())?)),
+            None => (range, None),
+        };
+        let network: IpAddr = network.parse().map_err(|_| invalid())?;
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        if prefix > bits {
+            return Err(invalid());
+        }
+        Ok(Self { network, prefix })
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// The ranges a crawl stays out of
+#[derive(Debug, Clone, Default)]
+pub struct ExcludedRanges {
+    ranges: Vec<IpRange>,
+}
+
+impl ExcludedRanges {
+    /// Build from ranges as given on the command line, where `private` adds
+    /// every internal range
+    pub fn new<'a>(ranges: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut excluded = Vec::new();
+        for range in ranges {
+            match range {
+                "private" => excluded.extend(PRIVATE.iter().map(|range| range.parse::<IpRange>().unwrap())),
+                range => excluded.push(range.parse()?),
+            }
+        }
+        Ok(Self { ranges: excluded })
+    }
+
+    /// The range addr falls in, if it is excluded
+    pub fn matching(&self, addr: IpAddr) -> Option<&IpRange> {
+        self.ranges.iter().find(|range| range.contains(addr))
+    }
+
+    /// The range the host of url falls in, when the host is written as an address
+    pub fn matching_url(&self, url: &url::Url) -> Option<&IpRange> {
+        match url.host()? {
+            url::Host::Ipv4(addr) => self.matching(IpAddr::V4(addr)),
+            url::Host::Ipv6(addr) => self.matching(IpAddr::V6(addr)),
+            url::Host::Domain(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!("10.0.0.0/8".parse::<IpRange>().unwrap().to_string(), "10.0.0.0/8");
+        assert_eq!("192.168.1.5".parse::<IpRange>().unwrap().to_string(), "192.168.1.5/32");
+        assert_eq!("fd00::/8".parse::<IpRange>().unwrap().to_string(), "fd00::/8");
+        assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+        assert!("intranet".parse::<IpRange>().is_err());
+        assert!(ExcludedRanges::new(["private", "nope/8"]).is_err());
+    }
+
+    #[test]
+    fn matches_addresses_in_range() {
+        let range: IpRange = "172.16.0.0/12".parse().unwrap();
+        assert!(range.contains(ip("172.31.255.255")));
+        assert!(!range.contains(ip("172.32.0.0")));
+        assert!(range.contains(ip("::ffff:172.16.0.1")));
+        assert!(!range.contains(ip("fe80::1")));
+        assert!("0.0.0.0/0".parse::<IpRange>().unwrap().contains(ip("8.8.8.8")));
+
+        let private = ExcludedRanges::new(["private"]).unwrap();
+        for addr in ["10.1.2.3", "127.0.0.1", "169.254.169.254", "192.168.0.10", "::1", "fd12::1", "fe80::abcd"] {
+            assert!(private.matching(ip(addr)).is_some(), "{}", addr);
+        }
+        for addr in [IpAddr::V4(Ipv4Addr::UNSPECIFIED), IpAddr::V6(Ipv6Addr::UNSPECIFIED)] {
+            assert!(private.matching(addr).is_some());
+        }
+        for addr in ["87.248.100.215", "2001:4998:44:3507::8000"] {
+            assert!(private.matching(ip(addr)).is_none(), "{}", addr);
+        }
+        assert_eq!(private.matching_url(&url::Url::parse("http://10.0.0.1/admin").unwrap()).unwrap().to_string(), "10.0.0.0/8");
+        assert_eq!(private.matching_url(&url::Url::parse("http://[::1]:8080/").unwrap()).unwrap().to_string(), "::1/128");
+        assert!(private.matching_url(&url::Url::parse("https://www.yahoo.com/").unwrap()).is_none());
+    }
+}
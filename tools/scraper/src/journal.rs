@@ -0,0 +1,120 @@
+//! What became of every URL a crawl with --frontier is done with, kept next
+//! to the frontier log. The frontier lets go of a URL for good once it is
+//! acknowledged, but visited.json is only written when the crawl ends, so a
+//! crash in between would lose every page acknowledged so far. Each URL is
+//! journaled, and synced to disk, before it is acknowledged instead, and the
+//! next crawl reads whatever the journal holds back: the pages go into its
+//! results, and every URL into the seen store so a link to it doesn't get it
+//! fetched again. The journal is only cleared once a crawl that left nothing
+//! in the frontier has written its results.
+//!
+//! Records are one JSON object per line, with the page record for pages that
+//! got one:
+//!
+//! ```text
+//! {"url":"https://news.yahoo.com/","page":{...}}
+//! {"url":"https://news.yahoo.com/gone"}
+//! ```
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Record<V> {
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<V>,
+}
+
+/// The URLs read back from a journal, each with its page record if it got one
+pub type Journaled<V> = Vec<(String, Option<V>)>;
+
+/// The journal file, open for appending
+#[derive(Debug)]
+pub struct Journal {
+    file: File,
+}
+
+impl Journal {
+    /// The journal kept next to the frontier log at frontier_path
+    pub fn path_for(frontier_path: &Path) -> PathBuf {
+        frontier_path.with_extension("journal.ndjson")
+    }
+
+    /// Open the journal at path and read back the records already in it
+    pub fn open<V: DeserializeOwned>(path: &Path) -> io::Result<(Self, Journaled<V>)> {
+        let mut recovered = Vec::new();
+        let mut complete = 0;
+        if path.exists() {
+            let contents = fs::read_to_string(path)?;
+            for line in contents.split_inclusive('\n') {
+                //a torn record from a crash mid-write has no newline yet, it never got acknowledged
+                let Some(line) = line.strip_suffix('\n') else {
+                    break;
+                };
+                complete += line.len() + 1;
+                if let Ok(record) = serde_json::from_str::<Record<V>>(line) {
+                    recovered.push((record.url, record.page));
+                }
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        //cut off the torn record, so appends start on a line of their own
+        file.set_len(complete as u64)?;
+        Ok((Self { file }, recovered))
+    }
+
+    /// Add what came of url, its page record if it got one
+    pub fn append<V: Serialize>(&mut self, url: &str, page: Option<&V>) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&Record { url: url.to_string(), page })?;
+        line.push(b'\n');
+        //one write per record, so a crash can only tear the last one
+        self.file.write_all(&line)?;
+        //on disk before the frontier lets go of the url, power loss included
+        self.file.sync_data()
+    }
+
+    /// Forget every record, once the results they went into are on disk
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_what_was_journaled_until_cleared() {
+        let path = std::env::temp_dir().join(format!("journal-test-{}.ndjson", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let (mut journal, recovered) = Journal::open::<Vec<u32>>(&path).unwrap();
+        assert!(recovered.is_empty());
+        journal.append("https://yahoo.com/", Some(&vec![1, 2])).unwrap();
+        journal.append::<Vec<u32>>("https://yahoo.com/gone", None).unwrap();
+        drop(journal);
+        //a crash halfway through the next record
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"url\":\"https://yahoo.com/to").unwrap();
+
+        let (mut journal, recovered) = Journal::open::<Vec<u32>>(&path).unwrap();
+        assert_eq!(recovered, [("https://yahoo.com/".to_string(), Some(vec![1, 2])), ("https://yahoo.com/gone".to_string(), None)]);
+        journal.append("https://yahoo.com/news", Some(&vec![3])).unwrap();
+        drop(journal);
+        let (_, recovered) = Journal::open::<Vec<u32>>(&path).unwrap();
+        assert_eq!(recovered.len(), 3);
+        assert_eq!(recovered[2], ("https://yahoo.com/news".to_string(), Some(vec![3])));
+
+        //cleared once the results are written, the next crawl starts on an empty journal
+        let (mut journal, _) = Journal::open::<Vec<u32>>(&path).unwrap();
+        journal.clear().unwrap();
+        journal.append("https://yahoo.com/sports", Some(&vec![4])).unwrap();
+        drop(journal);
+        let (_, recovered) = Journal::open::<Vec<u32>>(&path).unwrap();
+        assert_eq!(recovered, [("https://yahoo.com/sports".to_string(), Some(vec![4]))]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,40 +1,317 @@
-use std::collections::{HashMap, VecDeque};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::Write;
-use std::rc::Rc;
-use std::time::Duration;
-use reqwest;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use select::document::{Document};
-use select::predicate::{Name};
+use select::predicate::{Attr, Name};
 use url::Url;
 use serde::{Serialize, Deserialize};
-use clap::{Command, Arg};
+use serde_json::json;
+use clap::{Command, Arg, ArgMatches};
+use reqwest::blocking::{Client, Response};
+use reqwest::cookie::Jar;
+use reqwest::header::HeaderValue;
+
+//println that stays quiet while the --tui dashboard owns the terminal
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !crate::dashboard::is_active() {
+            println!($($arg)*);
+        }
+    };
+}
+
+//status! for an error about a url, quiet once the same error on the same host has been shown a few times
+macro_rules! failure {
+    ($url:expr, $($arg:tt)*) => {
+        if let Some(line) = crate::noise::note($url, &format!($($arg)*)) {
+            status!("{}", line);
+        }
+    };
+}
+
+mod aliases;
+use aliases::HostAliases;
+mod article;
+use article::Article;
+mod audit;
+use audit::Audit;
+mod auth;
+use auth::Auth;
+mod bandwidth;
+use bandwidth::Bandwidth;
+mod blacklist;
+use blacklist::Blacklist;
+mod confine;
+use confine::Confinement;
+mod consent;
+mod control;
+use control::{Command as ControlCommand, Control, Request};
+use consent::{ConsentForm, ConsentMode};
+mod dashboard;
+use dashboard::Dashboard;
+mod css;
+mod data_uri;
+use data_uri::InlineImage;
+mod depth;
+use depth::{Depth, DepthRules};
+mod diagnostics;
+use diagnostics::HtmlDiagnostics;
+mod dryrun;
+mod etiquette;
+use etiquette::{Etiquette, Preset, RobotsDirectives};
+mod http;
+use http::{DnsCache, Fetcher, PoolSettings};
+mod image_filter;
+use image_filter::{ImageFilter, Skip};
+mod image_meta;
+use image_meta::ImageMeta;
+mod image_store;
+use image_store::ImageStore;
+mod ip_range;
+use ip_range::ExcludedRanges;
+mod journal;
+use journal::Journal;
+mod manifest;
+use manifest::{Manifest, Outcome};
+#[cfg(test)]
+mod mock;
+mod noise;
+mod extract;
+use extract::ExtractRules;
+mod feeds;
+use feeds::Feed;
+mod finance;
+use finance::Quote;
+mod fingerprint;
+use fingerprint::UrlFingerprint;
+mod focus;
+use focus::Focus;
+mod frontier;
+use frontier::{Fairness, Frontier, Lease};
+mod pipeline;
+use pipeline::Pipeline;
+mod policy;
+use policy::{StatusAction, StatusPolicy};
+mod previous;
+use previous::{Carried, PreviousCrawl};
+mod rate_limit;
+use rate_limit::RateLimit;
+mod redirectors;
+mod schedule;
+use schedule::{Change, Classes, IntervalRule, Refresh, Schedule};
+mod seen;
+use seen::SeenStore;
+mod sharded;
+use sharded::ShardedMap;
+mod sitemaps;
+mod spill;
+mod srcset;
+use srcset::{ResponsiveImage, SrcsetPolicy};
+use spill::{Spill, WithSpilled};
+mod structured;
+use structured::Metadata;
+mod text;
+use text::TextStats;
+mod report;
+mod results;
+use results::CrawlResults;
+mod tls;
+use tls::TlsDetails;
+mod trace;
+use trace::RequestIds;
+mod workspace;
+use workspace::Workspace;
 
 #[derive(Serialize, Deserialize, Debug)]
  struct Page {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>, //the fetch that got this page, same ID as in log.txt
     size: usize,
     links: Vec<String>,  //list of all website urls found
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    externals: Vec<String>, //links to other sites, recorded but not followed
     images: Vec<String>, //list of all images urls found
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    inline_images: Vec<InlineImage>, //images written into the page as data: URIs, never fetched
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    responsive_images: Vec<ResponsiveImage>, //every variant of the images with a srcset, whichever got downloaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<TextStats>, //readable text summary, only with --extract-text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<Metadata>, //JSON-LD, microdata and OpenGraph/Twitter tags, only with --structured-data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    diagnostics: Option<HtmlDiagnostics>, //parse errors, doctype and charset agreement, only with --html-diagnostics
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    consent_wall: bool,      //we only got the consent interstitial, not the page itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    protocol: Option<String>, //HTTP version the page came over, ie: HTTP/2.0
+    //validators the server sent, so the next crawl can ask whether the page changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    not_modified: bool,      //unchanged since the --previous crawl, the record is carried forward from it
  }
  #[derive(Serialize, Deserialize, Debug)]
  struct Image{
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>, //the fetch that got this image, same ID as in log.txt
     size: usize,
+    //what the image header says, missing if it couldn't be decoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    width: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    height: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    exif: BTreeMap<String, String>,
+    //validators the server sent, so the next crawl can ask whether the image changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    protocol: Option<String>, //HTTP version the image came over
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>, //hash of its bytes, naming the object in the --save-images store
+ }
+ //a URL we couldn't fetch, with the request that tried
+ #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+ struct Failure {
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+ }
+
+ //everything a crawl accumulates while it runs
+ struct CrawlState {
+    visited: ShardedMap<Arc<Page>>,      //list of visited website, sharded so workers can share it
+    seen: Box<dyn SeenStore>,            //set of URLs already queued
+    frontier: Frontier,                  //URLs waiting to be crawled
+    journal: Option<Journal>,            //what came of every acknowledged URL, only with --frontier
+    downloaded: ShardedMap<Image>,       //list of downloaded images
+    cached_imgs: BTreeMap<String, Image>, //images an earlier crawl downloaded, only fetched again if they changed
+    previous: PreviousCrawl,             //pages of an earlier crawl, only fetched in full if they changed
+    skipped_imgs: HashSet<String>,       //images dropped by the image filters or as tracking pixels, so they aren't fetched or logged again
+    blocked_hosts: HashSet<String>,      //hosts the status policy blacklisted during this crawl
+    baddies: Vec<Failure>,               //list of failed URLs
+    log_file: File,
+    request_ids: RequestIds,             //IDs tying a fetch's log lines and records together
+    client: Client,                      //shared connection pool for every request
+    dns: Arc<DnsCache>,                  //resolved hosts, shared with the client
+    audit: Audit,                        //requests, pacing and robots rules per host, for politeness.json
+    tls: BTreeMap<String, Option<TlsDetails>>, //TLS details per https host, None if the probe failed
+    feeds: BTreeMap<String, Feed>,       //RSS/Atom feeds pages linked to
+    redirects: BTreeMap<String, String>, //redirector links found, and the target queued instead of each
+    finance_file: Option<File>,          //finance.ndjson, only with --finance-quotes
+    articles_file: Option<File>,         //articles.ndjson, only with --articles
+    stylesheets: HashMap<String, Vec<String>>, //background images of every stylesheet fetched, shared by the pages linking to it
+    depths: HashMap<String, Depth>,      //how deep queued URLs are into a depth rule's budget, unlimited ones are left out
+    stepping_stones: HashSet<String>,    //queued URLs outside --include-only, fetched only for their links
+    sitemap_hosts: HashSet<String>,      //other hosts the seed's robots.txt listed sitemaps on, crawled even outside --include-only
+    dashboard: Option<Dashboard>,        //live view of the crawl, only with --tui
+    control: Option<Control>,            //requests to the control API, only with --control
+    spill: Option<Spill>,                //where page records go when over the memory budget, only with --memory-budget
+    pending_bytes: usize,                //bodies of the pages handed to the parser threads and not finished yet
+    image_store: Option<ImageStore>,     //where downloaded images are kept, only with --save-images
+ }
+
+ //settings a crawl runs with, taken from the command line
+ struct CrawlConfig {
+    limit: Option<i32>,     //max number of pages to crawl, no limit if None
+    excerpt_len: Option<usize>, //extract page text with excerpts this long, skip text if None
+    structured_data: bool,  //record the structured data embedded in each page
+    html_diagnostics: bool, //record how broken each page's markup is and whether its charsets agree
+    finance_quotes: bool,   //write the quote of every Yahoo Finance quote page to finance.ndjson
+    articles: bool,         //write every news article's headline, byline, dates and body to articles.ndjson
+    follow_feeds: bool,     //fetch discovered feeds and queue their entries
+    seed_sitemap_hosts: bool, //crawl the other hosts the seed's robots.txt lists sitemaps on, seeded from those sitemaps
+    min_image_dim: usize,   //images narrower or shorter than this are tracking pixels
+    image_filter: ImageFilter, //which images are worth downloading
+    srcset: SrcsetPolicy,   //which variants of a responsive image to download
+    etiquette: Etiquette,
+    bandwidth: Option<Bandwidth>, //shared cap on how fast response bodies are read, none if None
+    max_bytes: Option<u64>, //start no new fetches once this many bytes of responses came in, no limit if None
+    record_tls: bool,       //probe the TLS setup of every https host we fetch from
+    require_https: bool,    //upgrade plain http links to https, never fetch over http
+    extract_rules: ExtractRules, //how to find links in JSON and plain text responses
+    soft_deadline: Option<Instant>, //after this, finish the current page but start nothing new
+    hard_deadline: Option<Instant>, //after this, stop right away and write out what we have
+    blacklist: Blacklist,   //URLs and hosts never to fetch, empty without --blacklist
+    fingerprint: UrlFingerprint, //what the seen store keys URLs by, so tracking parameters don't count
+    consent: ConsentMode,   //what to do about consent interstitials
+    auth: Auth,             //login and token headers for pages behind an account
+    depth_rules: DepthRules, //how many hops in a row each class of links is followed
+    parser_threads: usize,  //threads parsing fetched pages while the next ones are fetched
+    status_policy: StatusPolicy, //what to do with each HTTP status a page fetch ends in
+    focus: Focus,           //the only URLs worth recording, everything when not focused
+ }
+
+ impl CrawlConfig {
+    fn past_soft_deadline(&self) -> bool {
+        self.soft_deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    fn past_hard_deadline(&self) -> bool {
+        self.hard_deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    fn out_of_bytes(&self, audit: &Audit) -> bool {
+        self.max_bytes.is_some_and(|budget| audit.bytes_received() >= budget)
+    }
  }
 
  impl Page {
     fn new(size: usize, links: Vec<String>, images:Vec<String> ) -> Self{
-        Self { size, links, images}
+        Self { request_id: None, size, links, externals: Vec::new(), images, inline_images: Vec::new(), responsive_images: Vec::new(), text: None, metadata: None, diagnostics: None, consent_wall: false, protocol: None, etag: None, last_modified: None, not_modified: false}
     }
 
     //get method for list of urls found on a page
+    #[allow(dead_code)]
     fn get_urls(&mut self) -> & Vec<String>{
         &self.links
     }
  }
 
+ //a page body as fetched, with the indexing restrictions the server sent along
+ struct FetchedPage {
+    url: String,    //where we ended up after redirects
+    body: String,
+    content_type: Option<String>,
+    robots: RobotsDirectives,
+    protocol: String,   //HTTP version it came over
+    etag: Option<String>,
+    last_modified: Option<String>,
+    undecodable: bool,  //some bytes weren't valid in the charset the body was decoded as
+ }
+
  impl Image {
-    fn new(size: usize) -> Image{
-        Self {size}
+    fn new(size: usize, meta: Option<ImageMeta>) -> Image{
+        match meta {
+            Some(meta) => Self {
+                request_id: None,
+                size,
+                width: Some(meta.width),
+                height: Some(meta.height),
+                format: Some(meta.format),
+                exif: meta.exif,
+                etag: None,
+                last_modified: None,
+                protocol: None,
+                sha256: None,
+            },
+            None => Self {request_id: None, size, width: None, height: None, format: None, exif: BTreeMap::new(), etag: None, last_modified: None, protocol: None, sha256: None},
+        }
     }
  }
 
@@ -47,74 +324,215 @@ use clap::{Command, Arg};
 
     We will use this function inside filter_map() to filter out these 2 kinds of URL (no https and not yahoo related)
     filter_map() takes Option<> as an arg so filter_url() has to return this type
+
+    most hrefs on a page get rejected, so nothing is allocated until a link is known to be kept,
+    and a link that is already a normalized absolute url is handed back as is
     */
-fn filter_url(link: &str) -> Option<String>{
-    let url = Url::parse(link);
-    match  url {
-        //if the url is valid, aka has https:// then check if it points to yahoo.com
-        Ok(url) =>{
-            if url.has_host() && url.host_str().unwrap().ends_with("yahoo.com") && !url.to_string().contains("beap.gemini"){       //points to yahoo
-                Some(url.to_string())
-            }else{ // discard if not yahoo-related
-                None
-            }
-        },
-        //if the url is not valid, add https:// to it so it can used with reqwest
-        Err(_e) =>{
-            if link.starts_with("/"){//..or ends with .html
-                Some(format!("https://yahoo.com{}",link))
-            }else{//..not even a link, ex: javascript:void(0)
-                None
-            }
+fn filter_url(link: &str) -> Option<Cow<'_, str>>{
+    //a relative link can't parse on its own, add https://yahoo.com to it so it can used with reqwest
+    //protocol-relative links (//host/path) aren't paths, absolute_link gives them a scheme first
+    if link.starts_with('/') && !link.starts_with("//"){//..or ends with .html
+        return Some(Cow::Owned(format!("https://yahoo.com{}",link)));
+    }
+    //a link that doesn't even mention yahoo can't point to it, no need to parse it
+    if !link.as_bytes().windows(9).any(|w| w.eq_ignore_ascii_case(b"yahoo.com")) || link.contains("beap.gemini"){
+        return None;
+    }
+    //..not even a link, ex: javascript:void(0)
+    let url = Url::parse(link).ok()?;
+    //if the url is valid, aka has https:// then check if it points to yahoo.com
+    if url.host_str().is_some_and(|host| host.ends_with("yahoo.com")) && !url.as_str().contains("beap.gemini"){       //points to yahoo
+        if url.as_str() == link {
+            Some(Cow::Borrowed(link))
+        }else{
+            Some(Cow::Owned(url.into()))
         }
+    }else{ // discard if not yahoo-related
+        None
     }
 }
 
-//discard any invalid image url
-fn filter_img_url(link: &str) -> Option<String>{
-    if link.contains("https://s.yimg.com") {
-        Some(link.to_string())
-    }else {
-        None
-    }
+//how a page fetch ended
+enum Fetch {
+    Page(FetchedPage),
+    Redirect(String),   //the server pointed elsewhere and the client didn't follow, this is where
+    NotModified(String), //unchanged since the earlier crawl the request was made conditional on, with the HTTP version the answer came over
+    Failed,
+    HostBlacklisted,    //the status policy says to stay away from the host
 }
 
-//send http request to the url and receive response. Return html in string and the size of the page in bytes
-//if the response give error, tries the link again 3 time, if still fails, add to fail list
-fn http_requester(link: &str, mut tries:u32, baddies: &mut Vec<String>) -> Option<String>{
+//send http request to the url and receive response. Return html in string and the robots directives sent with the page
+//what happens to a response that isn't a page is up to the status policy: retried with backoff, skipped, followed or the host blacklisted
+//if the request itself fails, tries the link again 3 time, if still fails, add to fail list
+//the caller records the failure, under the ID of its request
+//with the record of an earlier crawl the request is conditional on the validators it kept
+fn http_requester(link: &str, mut tries:u32, fetcher: &impl Fetcher, audit: &mut Audit, config: &CrawlConfig, previous: Option<&Page>) -> Fetch{
+    let etiquette = &config.etiquette;
 
     if tries == 4{
-        baddies.push(link.to_string());
-        return None;
+        return Fetch::Failed;
     }
 
-    let client = reqwest::blocking::Client::new();
-    let request = client.get(link)
-    .header("User-Agent", "Mozilla/5.0")
-    .timeout(Duration::new(3, 0));  //if the request sent is hung for more than 3 seconds, stop and return time out error
+    //be polite: wait between every request we send out
+    thread::sleep(etiquette.delay);
+
+    let mut headers = etiquette.headers();
+    headers.extend(config.auth.headers(link));
+    let validator = |value: Option<&String>| value.and_then(|value| HeaderValue::from_str(value).ok());
+    if let Some(etag) = validator(previous.and_then(|page| page.etag.as_ref())) {
+        headers.insert(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = validator(previous.and_then(|page| page.last_modified.as_ref())) {
+        headers.insert(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+    }
 
-    let response = request.send();
+    wait_for_rate_limit(audit, link);
+    audit.request(link, Instant::now());
+    //if the request sent is hung for more than 3 seconds, stop and return time out error
+    //a body read under --max-bandwidth can take longer than that, it only times out when the server goes quiet
+    let timeout = if config.bandwidth.is_some() { None } else { Some(Duration::new(3, 0)) };
+    let response = fetcher.fetch(link, headers, timeout);
     //println!("request sent!");
 
     //had to manually handle error in case we get 404 url, which will make the program crash if we just use unwrap()
     match response {
         Ok(rep) =>{
-            match rep.text(){
-                Ok(txt) =>{
-                    //println!("got text");
-                    Some(txt)
+            if let Some(addr) = rep.remote_addr() {
+                audit.connected(rep.url().as_str(), addr.ip());
+            }
+            audit.protocol(rep.url().as_str(), &protocol_name(rep.version()));
+            if let Some(limit) = RateLimit::from_headers(rep.headers(), SystemTime::now()) {
+                audit.rate_limit(rep.url().as_str(), &limit, Instant::now());
+            }
+            let code = rep.status();
+            if code == reqwest::StatusCode::NOT_MODIFIED && previous.is_some() {
+                return Fetch::NotModified(protocol_name(rep.version()));
+            }
+            match config.status_policy.action(code) {
+                StatusAction::Accept => match read_page(rep, config){
+                    Ok(page) =>{
+                        //println!("got text");
+                        audit.received(link, page.body.len());
+                        Fetch::Page(page)
+                    },
+                    Err(_e) =>{ //try the link 3 times then stop if still gives error
+                        failure!(link, "Fail! {}", _e);
+                        tries +=1;
+                        http_requester(link, tries, fetcher, audit, config, previous)
+                    }
                 },
-                Err(_e) =>{ //try the link 3 times then stop if still gives error
-                    println!("Fail! {}", _e);
+                StatusAction::Retry => {
+                    let retry_after = rep.headers().get(reqwest::header::RETRY_AFTER).and_then(|value| value.to_str().ok());
+                    let wait = policy::backoff(tries, retry_after);
+                    failure!(link, "Fail! {}, trying again in {:?}", code, wait);
+                    audit.throttled(link, code.as_u16(), wait);
+                    thread::sleep(wait);
                     tries +=1;
-                    http_requester(link, tries, baddies)
-                }
+                    http_requester(link, tries, fetcher, audit, config, previous)
+                },
+                StatusAction::Skip => {
+                    failure!(link, "Fail! {}", code);
+                    Fetch::Failed
+                },
+                StatusAction::Follow => {
+                    let target = rep.headers().get(reqwest::header::LOCATION)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|location| rep.url().join(location).ok());
+                    match target {
+                        Some(target) => Fetch::Redirect(target.into()),
+                        None => {
+                            failure!(link, "Fail! {} without a Location", code);
+                            Fetch::Failed
+                        }
+                    }
+                },
+                StatusAction::BlacklistHost => {
+                    failure!(link, "Fail! {}, blacklisting the host", code);
+                    Fetch::HostBlacklisted
+                },
             }
         },
         Err(_e) =>{
-            println!("Fail! {}", _e);
+            failure!(link, "Fail! {}", _e);
             tries +=1;
-            http_requester(link, tries, baddies)
+            http_requester(link, tries, fetcher, audit, config, previous)
+        }
+    }
+}
+
+//before a request to url, wait out whatever its host asked for with Retry-After or its rate limit headers
+fn wait_for_rate_limit(audit: &mut Audit, url: &str){
+    if let Some(wait) = audit.hold_off(url, Instant::now()) {
+        status!("Host asked us to hold off, waiting {:?} before {}", wait, url);
+        thread::sleep(wait);
+        audit.held_off(url, wait);
+    }
+}
+
+//stop fetching from the host of url for the rest of the crawl
+fn block_host(state: &mut CrawlState, url: &str){
+    state.audit.blacklisted(url);
+    if let Some(host) = Url::parse(url).ok().as_ref().and_then(Url::host_str) {
+        state.blocked_hosts.insert(host.to_ascii_lowercase());
+    }
+}
+
+//whether url is blacklisted, by the blacklist file or by the status policy during this crawl
+//a host written as an address in an excluded range counts too, those never go through the DNS cache
+fn is_blocked(url: &str, state: &CrawlState, config: &CrawlConfig) -> bool{
+    let parsed = Url::parse(url).ok();
+    config.blacklist.is_blocked(url)
+        || parsed.as_ref().and_then(Url::host_str).is_some_and(|host| state.blocked_hosts.contains(&host.to_ascii_lowercase()))
+        || parsed.as_ref().is_some_and(|url| state.dns.excluded().matching_url(url).is_some())
+}
+
+//note a failed fetch everywhere it shows up: baddies.json, the log and the dashboard
+fn record_failure(state: &mut CrawlState, kind: &'static str, url: &str, request_id: &str){
+    state.baddies.push(Failure { url: url.to_string(), request_id: Some(request_id.to_string()) });
+    state.log_file.write_fmt(format_args!("[{}] FAILED {}: {}\n", request_id, kind, url)).expect("write failure failed");
+    if let Some(dashboard) = &mut state.dashboard {
+        dashboard.failed(kind, url);
+    }
+}
+
+//read the body of a response along with the headers we care about
+fn read_page(rep: Response, config: &CrawlConfig) -> std::io::Result<FetchedPage>{
+    let url = rep.url().to_string();
+    let robots = config.etiquette.robots_directives(&rep);
+    let protocol = protocol_name(rep.version());
+    let header = |name| rep.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+    let (content_type, etag) = (header(reqwest::header::CONTENT_TYPE), header(reqwest::header::ETAG));
+    let last_modified = header(reqwest::header::LAST_MODIFIED);
+    let body = bandwidth::read_body(rep, config.bandwidth.as_ref())?;
+    let (body, undecodable) = bandwidth::decode(&body, content_type.as_deref());
+    Ok(FetchedPage { url, body, content_type, robots, protocol, etag, last_modified, undecodable })
+}
+
+//how an HTTP version reads in the records, ie: HTTP/2.0
+fn protocol_name(version: reqwest::Version) -> String {
+    format!("{:?}", version)
+}
+
+//answer a consent interstitial the way the config says, returning the page behind it
+//None means we are stuck with the interstitial
+fn pass_consent(res: &FetchedPage, state: &CrawlState, config: &CrawlConfig) -> Option<FetchedPage>{
+    if config.consent == ConsentMode::Mark {
+        return None;
+    }
+    let Some(form) = ConsentForm::find(&res.url, &res.body) else {
+        status!("No consent form found at {}", res.url);
+        return None;
+    };
+    thread::sleep(config.etiquette.delay);
+    match form.submit(config.consent, &state.client, &config.etiquette)?.map_err(std::io::Error::other).and_then(|rep| read_page(rep, config)) {
+        Ok(page) if !consent::is_consent_page(&page.url, &page.body) => Some(page),
+        Ok(_) => {
+            status!("Consent form at {} did not let us through", res.url);
+            None
+        },
+        Err(e) => {
+            status!("Could not submit consent form: {}", e);
+            None
         }
     }
 }
@@ -122,30 +540,128 @@ fn http_requester(link: &str, mut tries:u32, baddies: &mut Vec<String>) -> Optio
 
 //extract urls from the given html
 //change to Option<Vec<String>>? in case there's no link at all in a page???
-fn extract_urls(html: &str) -> Vec<String>{
-    //form a html document
-    let document = Document::from(html);
-
+//links marked rel=nofollow are left out when obey_nofollow is set
+//redirector links are swapped for their targets, each noted in found along with the links out of scope
+fn extract_urls(document: &Document, page_url: &str, obey_nofollow: bool, found: &mut FoundLinks) -> Vec<String>{
     //extracting all links in the yahoo page and filter out bad urls
     //NOTE: use HashMap to avoid duplicate value, aka visted pages
     let found_urls= document.find(Name("a"))
+    .filter(|node| !obey_nofollow || !node.attr("rel").is_some_and(|rel| rel.split_whitespace().any(|rel| rel.eq_ignore_ascii_case("nofollow"))))
     .filter_map(|node| node.attr("href"))
-    .filter_map(|link| filter_url(link))
+    .filter_map(|href| queued_link(href, page_url, found))
     .collect();    
 
-    return found_urls;
+    found_urls
+}
+
+//what became of a page's links besides the ones queued
+#[derive(Default)]
+struct FoundLinks {
+    redirects: Vec<(String, String)>, //redirector links and the target queued in place of each
+    externals: Vec<String>,           //links out of scope, recorded but never followed
+}
+
+//a link as it gets queued: the target in place of a redirector, which is never fetched itself
+//None for links out of scope, the absolute ones among them are noted as externals
+fn queued_link(link: &str, page_url: &str, found: &mut FoundLinks) -> Option<String>{
+    let link = absolute_link(link, page_url);
+    let expanded = redirectors::expand(&link);
+    let target = expanded.as_deref().unwrap_or(&link);
+    let queued = filter_url(target).map(Cow::into_owned);
+    if queued.is_none() {
+        found.externals.extend(external_url(target));
+    }
+    if let Some(target) = expanded {
+        found.redirects.push((link.into_owned(), target));
+    }
+    queued
+}
+
+//a protocol-relative link (//host/path) with the scheme of the page it's on, other links as they are
+fn absolute_link<'a>(link: &'a str, page_url: &str) -> Cow<'a, str>{
+    if !link.starts_with("//") {
+        return Cow::Borrowed(link);
+    }
+    let scheme = Url::parse(page_url).map_or("https", |page| if page.scheme() == "http" { "http" } else { "https" });
+    Cow::Owned(format!("{}:{}", scheme, link))
+}
+
+//a link to another site, as opposed to a relative one, an ad or a javascript: one
+fn external_url(link: &str) -> Option<String>{
+    let url = Url::parse(link).ok()?;
+    let external = matches!(url.scheme(), "http" | "https") && url.host_str().is_some_and(|host| !host.ends_with("yahoo.com"));
+    external.then(|| url.into())
 }
 
 //extracting all images from a page
-fn extract_images(html: &str) -> Vec<String>{
-    let document = Document::from(html);
-    
-    let found_images = document.find(Name("img"))
-    .filter_map(|node| node.attr("src"))
-    .filter_map(|link| filter_img_url(link))
-    .collect();
+//the images on a page, resolved against its url, split into the ones the image filter lets through and the ones it doesn't
+//backgrounds in <style> elements and style attributes count too, linked stylesheets are fetched later by finish_page
+//of an img with a srcset, or in a picture, the variants policy picks are downloaded, and all of them come back to be recorded
+fn extract_images(document: &Document, page_url: &str, filter: &ImageFilter, policy: SrcsetPolicy) -> (Vec<String>, Vec<(String, Skip)>, Vec<ResponsiveImage>){
+    let Ok(base) = Url::parse(page_url) else {
+        return (Vec::new(), Vec::new(), Vec::new());
+    };
+    let mut imgs: Vec<String> = Vec::new();
+    let mut responsive = Vec::new();
+    for node in document.find(Name("img")) {
+        match srcset::responsive_image(node, &base) {
+            Some(image) => {
+                imgs.extend(image.select(policy).into_iter().map(str::to_string));
+                responsive.push(image);
+            },
+            None => imgs.extend(node.attr("src").map(str::to_string)),
+        }
+    }
+    let css: Vec<String> = document.find(Name("style")).map(|node| node.text())
+        .chain(document.find(Attr("style", ())).filter_map(|node| node.attr("style").map(str::to_string)))
+        .flat_map(|css| css::refs(&css).images)
+        .collect();
+    let srcs = imgs.iter().chain(&css).map(String::as_str);
+    let (images, skipped) = filter_images(&base, srcs, filter);
+    (images, skipped, responsive)
+}
+
+//resolve image urls against base and run them through the image filter, dropping duplicates
+fn filter_images<'a>(base: &Url, srcs: impl IntoIterator<Item = &'a str>, filter: &ImageFilter) -> (Vec<String>, Vec<(String, Skip)>){
+    let mut found_images: Vec<String> = Vec::new();
+    let mut skipped: Vec<(String, Skip)> = Vec::new();
+    for src in srcs {
+        //data: URIs and the like are part of the page, not something to download
+        let Some(img) = base.join(src.trim()).ok().filter(|img| matches!(img.scheme(), "http" | "https")) else {
+            continue;
+        };
+        let img = String::from(img);
+        if found_images.contains(&img) || skipped.iter().any(|(skipped, _)| *skipped == img) {
+            continue;
+        }
+        match filter.check_url(&img) {
+            Ok(()) => found_images.push(img),
+            Err(skip) => skipped.push((img, skip)),
+        }
+    }
+    (found_images, skipped)
+}
+
+//the images written into a page as data: URIs, which filter_images leaves out
+fn extract_inline_images(document: &Document) -> Vec<InlineImage>{
+    document.find(Name("img"))
+        .filter_map(|node| node.attr("src"))
+        .filter_map(data_uri::parse)
+        .collect()
+}
 
-    return found_images;
+//the stylesheets a page links to, resolved against its url
+fn extract_stylesheets(document: &Document, page_url: &str) -> Vec<String>{
+    let Ok(base) = Url::parse(page_url) else {
+        return Vec::new();
+    };
+    document.find(Name("link"))
+        .filter(|node| node.attr("rel").is_some_and(|rel| rel.split_whitespace().any(|rel| rel.eq_ignore_ascii_case("stylesheet"))))
+        .filter_map(|node| node.attr("href"))
+        .filter_map(|href| base.join(href.trim()).ok())
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .map(String::from)
+        .collect()
 }
 
 /*
@@ -153,38 +669,121 @@ fn extract_images(html: &str) -> Vec<String>{
         if it's not:
             download the image to a folder
             retrieve size of image once downloaded
-            make a new Image() and add to 'downloaded'
+            make a new Image() and add to 'downloaded', unless the header says it's a tracking pixel
     add to the list of found images in a page (regardless of whether it was downloaded before or not)
  */
-fn download_img(img_urls: &Vec<String>, downloaded: &mut HashMap<String, Image>, baddies:&mut Vec<String>){
+fn download_img(img_urls: &[String], state: &mut CrawlState, config: &CrawlConfig){
+    let etiquette = &config.etiquette;
     for img in img_urls{
-        if !downloaded.contains_key(img){
+        if config.past_hard_deadline() || config.out_of_bytes(&state.audit) {
+            return;
+        }
+        if !state.downloaded.contains_key(img) && !state.skipped_imgs.contains(img) && !is_blocked(img, state, config){
 
-            println!("Processing IMG...{}", img);
+            let request_id = state.request_ids.next_id();
+            status!("Processing IMG...{} [{}]", img, request_id);
+            if let Some(dashboard) = &mut state.dashboard {
+                dashboard.fetching_image(img);
+            }
 
             //"download" the image
             //let img_bytes = reqwest::blocking::get(img).unwrap().bytes().unwrap();
 
             //TODO: check for error here instead of unwrap()
-            match reqwest::blocking::get(img) {
+            thread::sleep(etiquette.delay);
+            //ask for the image only if it changed since the earlier crawl got it
+            let mut request = config.auth.apply(etiquette.apply(state.client.get(img)), img);
+            //an image the store doesn't have yet is downloaded in full, changed or not
+            let storable = |cached: &&Image| state.image_store.as_ref().is_none_or(|store| cached.sha256.as_ref().is_some_and(|hash| store.contains(hash)));
+            if let Some(cached) = state.cached_imgs.get(img).filter(storable) {
+                if let Some(etag) = &cached.etag {
+                    request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = &cached.last_modified {
+                    request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+            wait_for_rate_limit(&mut state.audit, img);
+            state.audit.request(img, Instant::now());
+            let response = request.send();
+            if let Some((rep, addr)) = response.as_ref().ok().and_then(|rep| Some((rep, rep.remote_addr()?))) {
+                state.audit.connected(rep.url().as_str(), addr.ip());
+            }
+            if let Some((rep, limit)) = response.as_ref().ok().and_then(|rep| Some((rep, RateLimit::from_headers(rep.headers(), SystemTime::now())?))) {
+                state.audit.rate_limit(rep.url().as_str(), &limit, Instant::now());
+            }
+            let protocol = response.as_ref().ok().map(|rep| protocol_name(rep.version()));
+            if let Some((rep, protocol)) = response.as_ref().ok().zip(protocol.as_deref()) {
+                state.audit.protocol(rep.url().as_str(), protocol);
+            }
+            match response {
+                Ok(rep) if rep.status() == reqwest::StatusCode::NOT_MODIFIED && state.cached_imgs.contains_key(img) => {
+                    //unchanged, the earlier record still describes it
+                    let mut image = state.cached_imgs.remove(img).unwrap();
+                    image.request_id = Some(request_id.clone());
+                    image.protocol = protocol;
+                    if let Some((store, hash)) = state.image_store.as_mut().zip(image.sha256.as_ref()) {
+                        store.keep(img, hash);
+                    }
+                    state.log_file.write_fmt(format_args!("[{}] IMG: {} - Size: {} (not modified)\n", request_id, img, image.size)).expect("write image failed");
+                    status!("Not modified -> size: {}", image.size);
+                    state.downloaded.insert(img.to_string(), image);
+                    if let Some(dashboard) = &mut state.dashboard {
+                        dashboard.image_done();
+                    }
+                },
                 Ok(rep) => {
-                    match rep.bytes() {
+                    let header = |name| rep.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+                    let etag = header(reqwest::header::ETAG);
+                    let last_modified = header(reqwest::header::LAST_MODIFIED);
+                    //the type the server says it is, no need to read the body of one we don't want
+                    if let Some(Err(skip)) = header(reqwest::header::CONTENT_TYPE).map(|content_type| config.image_filter.check_content_type(&content_type)) {
+                        skip_img(state, &request_id, img, skip);
+                        continue;
+                    }
+                    match bandwidth::read_body(rep, config.bandwidth.as_ref()) {
                         Ok(img_bytes) =>{
-                            //get size of image just downloaded and update the downloaded list
+                            //get size and header info of image just downloaded and update the downloaded list
                             let size = img_bytes.len();
-                            downloaded.insert(img.to_string(), Image::new(size));
+                            state.audit.received(img, size);
+                            let meta = image_meta::inspect(&img_bytes);
+                            if let Some(meta) = meta.as_ref().filter(|meta| meta.is_smaller_than(config.min_image_dim)) {
+                                status!("Skipped tracking pixel -> {}x{}", meta.width, meta.height);
+                                skip_img(state, &request_id, img, "tracking pixel");
+                                continue;
+                            }
+                            if let Some(Err(skip)) = meta.as_ref().map(|meta| config.image_filter.check_meta(meta)) {
+                                skip_img(state, &request_id, img, skip);
+                                continue;
+                            }
+                            let mut image = Image::new(size, meta);
+                            image.request_id = Some(request_id.clone());
+                            image.etag = etag;
+                            image.last_modified = last_modified;
+                            image.protocol = protocol;
+                            if let Some(store) = &mut state.image_store {
+                                match store.put(img, &img_bytes) {
+                                    Ok(hash) => image.sha256 = Some(hash),
+                                    Err(e) => failure!(img, "Could not store image: {}", e),
+                                }
+                            }
+                            state.downloaded.insert(img.to_string(), image);
+                            state.log_file.write_fmt(format_args!("[{}] IMG: {} - Size: {}\n", request_id, img, size)).expect("write image failed");
                             //testing
-                            println!("Success! -> size: {}",size);
+                            status!("Success! -> size: {}",size);
+                            if let Some(dashboard) = &mut state.dashboard {
+                                dashboard.image_done();
+                            }
                         },
                         Err(_e) =>{
-                            println!("Fail! {}", _e);
-                            baddies.push(img.to_string());
+                            failure!(img, "Fail! {}", _e);
+                            record_failure(state, "image", img, &request_id);
                         }
                     }
                 },
                 Err(_e) =>{
-                    println!("Fail! {}", _e);
-                    baddies.push(img.to_string());
+                    failure!(img, "Fail! {}", _e);
+                    record_failure(state, "image", img, &request_id);
                 }
             }
         }
@@ -192,56 +791,17 @@ fn download_img(img_urls: &Vec<String>, downloaded: &mut HashMap<String, Image>,
 }
 
 
-/* DEPRECATED
-    check if the current link has been visited
-        if visited, return
-    if not visted,
-        fetch html document via https request
-        mark as visted 
-        extract all links on the current url
-    recursively scrap each links in the current url
-        using for loop?
-        stop recursion when there's no more link to go to
-    
-*/
-fn recursive_scraper(link: &str, visited: &mut HashMap<String,Rc<Page>>, downloaded: &mut HashMap<String, Image>, baddies: &mut Vec<String>){
-    if !visited.contains_key(link){
-        
-        println!("Processing...{}", link);      //checking which link is being scraped in case it crashes
-
-        let res = http_requester(link, 1, baddies);
-        
-        if res.is_none(){//ignore invalid url 404
-            return;
-        }
-
-        //scrap urls and imgs on a page
-        let res_text = res.unwrap();
-        let found_urls = extract_urls(&res_text);
-        let found_imgs = extract_images(&res_text);
-        let size = res_text.len();
-
-        //printing links in hashmap, should NOT have dups
-        println!("Sucess! -> Size:{}", size);
-
-        //download all images found
-        println!("*******Images found within this link*******");
-        download_img(&found_imgs, downloaded, baddies);
-
-        //use Rc<Page> so we can share the page between 'visisted' and the recurive loop
-        let new_page = Rc::new(Page::new(size, found_urls, found_imgs));
-        visited.insert(link.to_string(), new_page.clone());
-
-
-        for url in &new_page.links {
-            recursive_scraper(&url,visited, downloaded, baddies);
-        }
+//note that an image was filtered out, once, so it isn't considered again
+fn skip_img(state: &mut CrawlState, request_id: &str, img: &str, reason: impl std::fmt::Display){
+    if state.skipped_imgs.insert(img.to_string()) {
+        status!("Skipped image {} -> {}", img, reason);
+        state.log_file.write_fmt(format_args!("[{}] IMG SKIPPED: {} ({})\n", request_id, img, reason)).expect("write image failed");
     }
-
-    return;
-
 }
 
+//how often a paused crawl looks up from the control API to check its deadlines and the dashboard
+const PAUSED_POLL: Duration = Duration::from_millis(200);
+
 /*non-recursive bfs scraper
     local lists: found_urls -> list of urls found in a page, may or may not have been visited
     start with yahoo.com, add it to found_urls
@@ -251,188 +811,2715 @@ fn recursive_scraper(link: &str, visited: &mut HashMap<String,Rc<Page>>, downloa
                 Download all the image on this page too
                 Then add this url to list of visted website
             if vististed, then skip this url and move on to the next one on the list
+    stop after 'limit' pages when a limit is given
+
+    fetching and parsing are separate stages: fetched pages go to the parser threads
+    and we keep fetching while they work, recording each page once it comes back parsed
+
+    false when the crawl was cut short: by the hard deadline, or by quitting from the dashboard
 */
-fn bfs_scraper(link: &str, visited: &mut HashMap<String,Rc<Page>>, downloaded: &mut HashMap<String, Image>, baddies: &mut Vec<String>, mut log_file:File){
-    let mut found_urls: VecDeque<String> = VecDeque::new();
-    found_urls.push_back(link.to_string());
+fn bfs_scraper(link: &str, state: &mut CrawlState, config: &CrawlConfig) -> bool{
+    let mut limit = config.limit;
+
+    //a resumed frontier already knows where to go, only seed a fresh one
+    //unless the journal says a crawl that crashed before writing its results got through the seed already
+    if state.frontier.is_empty() {
+        if state.seen.insert(&config.fingerprint.of(link)) {
+            state.frontier.push(link.to_string());
+            if config.seed_sitemap_hosts {
+                seed_sitemap_hosts(link, state, config);
+            }
+        }
+    } else {
+        status!("Resuming with {} queued URLs", state.frontier.len());
+        let CrawlState { frontier, seen, .. } = state;
+        for url in frontier.pending_urls() {
+            seen.insert(&config.fingerprint.of(url));
+        }
+    }
+
+    let parser = PageParser::new(config);
+    let mut pipeline = Pipeline::new(config.parser_threads, move |page| parser.parse(page));
+    let mut stopping = false;
+    let mut aborted = false;
+    let mut paused = false;
+
+    loop {
+        //record whatever the parsers are done with before fetching more
+        while let Some(page) = pipeline.try_recv() {
+            if !finish_page(page, state, config) {
+                return false;
+            }
+        }
+        while let Some(request) = state.control.as_ref().and_then(Control::try_recv) {
+            answer_control(request, &mut paused, state, config);
+        }
+        if config.past_hard_deadline() {
+            status!("Deadline reached, stopping with {} URLs still queued", state.frontier.len());
+            return false;
+        }
+        //q on the dashboard stops the crawl, everything found so far still gets written
+        //the soft deadline is a planned stop, quitting isn't
+        if !stopping {
+            aborted = state.dashboard.as_mut().is_some_and(Dashboard::quit_requested);
+            if aborted || config.past_soft_deadline() {
+                status!("Stopping with {} URLs still queued, finishing the {} being parsed", state.frontier.len(), pipeline.in_flight());
+                stopping = true;
+            } else if config.out_of_bytes(&state.audit) {
+                status!("Transfer budget used up at {} bytes, stopping with {} URLs still queued, finishing the {} being parsed",
+                    state.audit.bytes_received(), state.frontier.len(), pipeline.in_flight());
+                stopping = true;
+            }
+        }
 
-    while !found_urls.is_empty(){
-        let url = found_urls.pop_front().unwrap();
+        let lease = if stopping || paused || pipeline.is_full() || limit.is_some_and(|n| n <= 0) {
+            None
+        } else {
+            state.frontier.pop()
+        };
+        //nothing to fetch right now: wait for a parsed page, its links may refill the queue
+        let Some(Lease { id, url }) = lease else {
+            //paused with nothing left to parse: wait on the control API, still watching the deadlines and the dashboard
+            if paused && !stopping && pipeline.in_flight() == 0 {
+                if let Some(request) = state.control.as_ref().and_then(|control| control.recv_timeout(PAUSED_POLL)) {
+                    answer_control(request, &mut paused, state, config);
+                }
+                continue;
+            }
+            match pipeline.recv() {
+                Some(page) => {
+                    if !finish_page(page, state, config) {
+                        return false;
+                    }
+                    continue;
+                },
+                None => {
+                    //every queued host at its cap can't happen once nothing is in flight
+                    if !stopping && !state.frontier.is_empty() && limit.is_none_or(|n| n > 0) {
+                        status!("Every queued host is at its request cap, stopping");
+                    }
+                    return !aborted;
+                }
+            }
+        };
+        if let Some(dashboard) = &mut state.dashboard {
+            dashboard.fetching(&url, state.frontier.len());
+        }
 
-        println!("Processing URL...{}", url);      //checking which link is being scraped in case it crashes
+        //a resumed frontier doesn't know how deep its urls were, they start over
+        let depth = state.depths.remove(&url).unwrap_or_default();
+        let request_id = state.request_ids.next_id();
+        status!("Processing URL...{} [{}]", url, request_id);      //checking which link is being scraped in case it crashes
 
-        let res = http_requester(&url, 1, baddies);
-        
-        if res.is_none(){//ignore invalid url 404
+        //queued before its host got blacklisted
+        if is_blocked(&url, state, config) {
+            status!("Host is blacklisted, skipping");
+            ack(state, id, &url, None);
             continue;
         }
 
-        //scrap urls and imgs on a page
-        let res_text = res.unwrap();
-        let scraped_urls = extract_urls(&res_text);
-        let scraped_imgs = extract_images(&res_text);
-        let size = res_text.len();
+        let previous = state.previous.page(&url, config.articles, config.finance_quotes);
+        let res = http_requester(&url, 1, &state.client, &mut state.audit, config, previous);
+        state.frontier.fetched(id);
+        
+        //ignore invalid url 404, once it's in baddies we're done with it
+        let res = match res {
+            Fetch::Page(page) => page,
+            Fetch::NotModified(protocol) => {
+                //nothing to parse, the earlier crawl's record stands in for the page
+                status!("Not modified since the previous crawl");
+                let carried = state.previous.take(&url).unwrap();
+                let page = carried_page(id, url, request_id, depth, protocol, carried);
+                if !finish_page(page, state, config) {
+                    return false;
+                }
+                if let Some(n) = limit.as_mut() {
+                    *n -= 1;
+                }
+                continue;
+            },
+            Fetch::Redirect(target) => {
+                status!("Redirected to {}", target);
+                state.log_file.write_fmt(format_args!("[{}] REDIRECT: {} -> {}\n", request_id, url, target)).expect("write redirect failed");
+                let mut found = FoundLinks::default();
+                let target: Vec<String> = queued_link(&target, &url, &mut found).into_iter().collect();
+                state.redirects.extend(found.redirects);
+                enqueue_links(&url, depth, &target, state, config);
+                ack(state, id, &url, None);
+                continue;
+            },
+            Fetch::Failed | Fetch::HostBlacklisted => {
+                if matches!(res, Fetch::HostBlacklisted) {
+                    block_host(state, &url);
+                }
+                record_failure(state, "page", &url, &request_id);
+                ack(state, id, &url, None);
+                continue;
+            },
+        };
+
+        //a consent interstitial is not the page we asked for, get past it or record it as such
+        let (res, consent_wall) = if consent::is_consent_page(&res.url, &res.body) {
+            match pass_consent(&res, state, config) {
+                Some(page) => (page, false),
+                None => {
+                    status!("Consent wall at {}", url);
+                    (res, true)
+                }
+            }
+        } else {
+            (res, false)
+        };
+
+        state.pending_bytes += res.body.len();
+        pipeline.submit(FetchedJob { lease_id: id, url, request_id, depth, res, consent_wall });
+        if let Some(n) = limit.as_mut() {
+            *n -= 1;
+        }
+    }
+}
 
-        //printing links in hashmap, should NOT have dups
-        println!("Sucess! -> Size:{}", size);
+//a fetched page on its way to the parser threads
+struct FetchedJob {
+    lease_id: u64,
+    url: String,
+    request_id: String,
+    depth: Depth,
+    res: FetchedPage,
+    consent_wall: bool,     //we only got the consent interstitial
+}
 
-        //download all images found
-        println!("*******Images found within this link*******");
-        download_img(&scraped_imgs, downloaded, baddies);
+//what the parser threads made of a fetched page
+struct ParsedPage {
+    lease_id: u64,
+    url: String,
+    request_id: String,
+    depth: Depth,
+    size: usize,
+    protocol: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    not_modified: bool,         //carried forward from an earlier crawl rather than parsed
+    robots: RobotsDirectives,   //headers and meta tags together
+    consent_wall: bool,
+    links: Vec<String>,
+    redirects: Vec<(String, String)>, //redirector links on the page and the targets among links in their place
+    externals: Vec<String>,     //links out of scope, not followed
+    images: Vec<String>,
+    inline_images: Vec<InlineImage>,
+    responsive_images: Vec<ResponsiveImage>,
+    skipped_images: Vec<(String, Skip)>, //images the image filter turned down by their url
+    stylesheets: Vec<String>,   //linked stylesheets, whose background images count as the page's
+    text: Option<TextStats>,
+    metadata: Option<Metadata>,
+    diagnostics: Option<HtmlDiagnostics>,
+    quote: Option<Quote>,       //the quote on a Yahoo Finance quote page, only with --finance-quotes
+    article: Option<Article>,   //the news article on the page, only with --articles
+    feeds: Vec<(String, Feed)>, //feeds the page links to, unless it's nofollow
+}
 
-        //write page info to a log file
-        log_file.write_fmt(format_args!("URL: {} - Size: {}: ", &url, size)).expect("write url failed");
-        log_file.write_fmt(format_args!("URLS List: {:?} ,", &scraped_urls)).expect("write url list failed");
-        log_file.write_fmt(format_args!("IMG List: {:?} \n", &scraped_imgs)).expect("write images failed");
-        
-        let new_page = Rc::new(Page::new(size, scraped_urls, scraped_imgs));
-        visited.insert(url, new_page.clone());
+//the settings parsing a page needs, owned so the parser threads can share them
+struct PageParser {
+    etiquette: Etiquette,
+    excerpt_len: Option<usize>,
+    structured_data: bool,
+    html_diagnostics: bool,
+    finance_quotes: bool,
+    articles: bool,
+    extract_rules: ExtractRules,
+    image_filter: ImageFilter,
+    srcset: SrcsetPolicy,
+}
 
-        //add unvisited urls from scraped_urls to found_urls
-        for new in &new_page.links{
+impl PageParser {
+    fn new(config: &CrawlConfig) -> Self {
+        Self {
+            etiquette: config.etiquette.clone(),
+            excerpt_len: config.excerpt_len,
+            structured_data: config.structured_data,
+            html_diagnostics: config.html_diagnostics,
+            finance_quotes: config.finance_quotes,
+            articles: config.articles,
+            extract_rules: config.extract_rules.clone(),
+            image_filter: config.image_filter.clone(),
+            srcset: config.srcset,
+        }
+    }
 
-            if !visited.contains_key(new){
-                found_urls.push_back(new.to_string());
-            }
+    //scrap urls, imgs, text and feeds from a fetched page, runs on a parser thread
+    fn parse(&self, job: FetchedJob) -> ParsedPage {
+        let FetchedJob { lease_id, url, request_id, depth, res, consent_wall } = job;
+
+        //scrap urls and imgs on a page, unless the site asked us not to (X-Robots-Tag)
+        //JSON and plain text have no anchors or images, the extraction rules find their links
+        let is_html = extract::is_html(res.content_type.as_deref());
+        //parse the page once, links, images, text and structured data all come from the same document
+        let document = (is_html && !consent_wall).then(|| Document::from(res.body.as_str()));
+        //the page's robots meta tags add to whatever the headers said
+        let robots = match &document {
+            Some(document) => res.robots.merge(self.etiquette.meta_robots_directives(document)),
+            None => res.robots,
+        };
+        let mut found = FoundLinks::default();
+        let links = if robots.nofollow || consent_wall {
+            Vec::new()
+        } else if let Some(document) = &document {
+            extract_urls(document, &url, self.etiquette.obey_meta_robots, &mut found)
+        } else {
+            self.extract_rules.extract(&res.body, res.content_type.as_deref()).iter()
+                .filter_map(|link| queued_link(link, &url, &mut found))
+                .collect()
+        };
+        let FoundLinks { redirects, externals } = found;
+        //a noindex page keeps its links but nothing of its content
+        let content = document.as_ref().filter(|_| !robots.noindex);
+        let (images, skipped_images, responsive_images) = content.map(|document| extract_images(document, &url, &self.image_filter, self.srcset)).unwrap_or_default();
+        let inline_images = content.map(extract_inline_images).unwrap_or_default();
+        let stylesheets = content.map(|document| extract_stylesheets(document, &url)).unwrap_or_default();
+        let text = self.excerpt_len.zip(content).map(|(len, document)| text::extract_text(document, len));
+        let metadata = content.filter(|_| self.structured_data).and_then(structured::extract);
+        let quote = content.filter(|_| self.finance_quotes).and_then(|document| finance::extract(document, &url));
+        let article = content.filter(|_| self.articles).and_then(|document| article::extract(document, &url));
+        //a validating pass of its own, the document above was parsed without keeping any errors
+        let diagnostics = (is_html && !consent_wall && self.html_diagnostics)
+            .then(|| HtmlDiagnostics::check(&res.body, res.content_type.as_deref(), res.undecodable));
+        //feeds are linked from the page head, nofollow covers them like any other link
+        let feeds = if is_html && !robots.nofollow && !consent_wall {
+            feeds::discover(&url, &res.body)
+        } else {
+            Vec::new()
+        };
+
+        ParsedPage {
+            lease_id, url, request_id, depth,
+            size: res.body.len(),
+            protocol: res.protocol,
+            etag: res.etag,
+            last_modified: res.last_modified,
+            not_modified: false,
+            robots, consent_wall, links, redirects, externals, images, inline_images, responsive_images, skipped_images, stylesheets, text, metadata, diagnostics, quote, article, feeds,
         }
-    
     }
+}
 
+//a page the server says is unchanged since the earlier crawl, as if parsed again from its record
+fn carried_page(lease_id: u64, url: String, request_id: String, depth: Depth, protocol: String, carried: Carried) -> ParsedPage {
+    let Carried { page, article, quote, feeds } = carried;
+    ParsedPage {
+        lease_id, url, request_id, depth, protocol,
+        size: page.size,
+        etag: page.etag,
+        last_modified: page.last_modified,
+        not_modified: true,
+        robots: RobotsDirectives::default(),
+        consent_wall: page.consent_wall,
+        links: page.links,
+        redirects: Vec::new(),      //their targets are already among the links
+        externals: page.externals,
+        images: page.images,
+        inline_images: page.inline_images,
+        responsive_images: page.responsive_images,
+        skipped_images: Vec::new(),
+        stylesheets: Vec::new(),    //their images are already among the page's
+        text: page.text,
+        metadata: page.metadata,
+        diagnostics: page.diagnostics,
+        quote, article, feeds,
+    }
 }
 
-fn bfs_scraper_with_limit(link: &str, visited: &mut HashMap<String,Rc<Page>>, downloaded: &mut HashMap<String, Image>, baddies: &mut Vec<String>, mut limit:i32, mut log_file:File){
-    let mut found_urls: VecDeque<String> = VecDeque::new();
-    found_urls.push_back(link.to_string());
+//do what a control API request asks and answer it
+fn answer_control(request: Request, paused: &mut bool, state: &mut CrawlState, config: &CrawlConfig){
+    let answer = match &request.command {
+        ControlCommand::Status => json!({
+            "paused": *paused,
+            "queued": state.frontier.len(),
+            "in_flight": state.frontier.in_flight_urls(),
+            "pages": page_count(state),
+            "images": state.downloaded.lock_all().iter().count(),
+            "failures": state.baddies.len(),
+        }),
+        ControlCommand::Queue { limit } => json!({
+            "queued": state.frontier.len(),
+            "urls": state.frontier.pending_urls().take(*limit).collect::<Vec<_>>(),
+        }),
+        ControlCommand::Pause | ControlCommand::Resume => {
+            *paused = request.command == ControlCommand::Pause;
+            status!("{} through the control API", if *paused { "Paused" } else { "Resumed" });
+            json!({"paused": *paused})
+        },
+        ControlCommand::Inject(urls) => {
+            let (mut queued, mut seen, mut rejected) = (Vec::new(), Vec::new(), Vec::new());
+            for given in urls {
+                //injected urls skip the scope checks, but not the blacklist, excluded ranges or https only
+                let url = Url::parse(given).ok()
+                    .filter(|url| matches!(url.scheme(), "http" | "https"))
+                    .and_then(|url| if config.require_https { tls::upgrade_to_https(url.as_str()) } else { Some(url.into()) })
+                    .filter(|url| !is_blocked(url, state, config));
+                let Some(url) = url else {
+                    rejected.push(given.clone());
+                    continue;
+                };
+                if state.seen.insert(&config.fingerprint.of(&url)) {
+                    status!("Queued {} through the control API", url);
+                    state.frontier.push(url.clone());
+                    queued.push(url);
+                } else {
+                    seen.push(url);
+                }
+            }
+            json!({"queued": queued, "already_seen": seen, "rejected": rejected})
+        },
+        ControlCommand::Remove(urls) => {
+            let (removed, not_queued): (Vec<&String>, Vec<&String>) = urls.iter().partition(|url| state.frontier.remove(url));
+            for url in &removed {
+                status!("Removed {} through the control API", url);
+            }
+            json!({"removed": removed, "not_queued": not_queued})
+        },
+    };
+    request.answer(answer);
+}
 
-    while !found_urls.is_empty() && limit > 0{
-        let url = found_urls.pop_front().unwrap();
+//record a parsed page: download its images, log it, queue its links and feeds
+//false when the hard deadline hit halfway through and the crawl has to stop
+fn finish_page(page: ParsedPage, state: &mut CrawlState, config: &CrawlConfig) -> bool{
+    let ParsedPage { lease_id, url, request_id, depth, size, protocol, etag, last_modified, not_modified, robots, consent_wall, links, redirects, externals, mut images, inline_images, responsive_images, mut skipped_images, stylesheets, text, metadata, diagnostics, quote, article, feeds } = page;
+    if !not_modified {
+        state.pending_bytes = state.pending_bytes.saturating_sub(size);
+    }
+    let stepping_stone = state.stepping_stones.contains(&url);
+    state.audit.robots(&url, robots);
 
-        println!("Processing URL...{}", url);      //checking which link is being scraped in case it crashes
+    //printing links in hashmap, should NOT have dups
+    status!("Sucess! -> {} Size:{}", url, size);
+    if let Some(dashboard) = &mut state.dashboard {
+        dashboard.page_done(size);
+    }
 
-        let res = http_requester(&url, 1, baddies);
-        
-        if res.is_none(){//ignore invalid url 404
-            continue;
+    if robots.noindex {
+        status!("Page is noindex, not recording it, only following its links");
+    } else if consent_wall {
+        status!("Not recording the consent interstitial as page content");
+    } else if stepping_stone {
+        status!("Page is outside --include-only, only following its links");
+    } else {
+        //backgrounds from the page's stylesheets are the page's images as much as its <img>s
+        for stylesheet in &stylesheets {
+            let srcs = stylesheet_images(stylesheet, state, config);
+            let Ok(base) = Url::parse(stylesheet) else {
+                continue;
+            };
+            let (found, skipped) = filter_images(&base, srcs.iter().map(String::as_str), &config.image_filter);
+            images.extend(found.into_iter().filter(|img| !images.contains(img)).collect::<Vec<_>>());
+            skipped_images.extend(skipped);
         }
 
-        //scrap urls and imgs on a page
-        let res_text = res.unwrap();
-        let scraped_urls = extract_urls(&res_text);
-        let scraped_imgs = extract_images(&res_text);
-        let size = res_text.len();
+        //download all images found
+        status!("*******Images found within this link*******");
+        for (img, skip) in skipped_images {
+            skip_img(state, &request_id, &img, skip);
+        }
+        download_img(&images, state, config);
 
-        //printing links in hashmap, should NOT have dups
-        println!("Sucess! -> Size:{}", size);
+        //out of time halfway through the page: leave it unacknowledged so a resumed crawl redoes it
+        if config.past_hard_deadline() {
+            status!("Hard deadline reached, abandoning {}", url);
+            return false;
+        }
 
-        //download all images found
-        println!("*******Images found within this link*******");
-        download_img(&scraped_imgs, downloaded, baddies);
+        if let (Some(mut quote), Some(file)) = (quote, state.finance_file.as_mut()) {
+            status!("Quote: {} {}", quote.symbol, quote.price);
+            quote.request_id = Some(request_id.clone());
+            //a line at a time, so the quotes so far are there to read while the crawl goes on
+            writeln!(file, "{}", serde_json::to_string(&quote).unwrap()).expect("write quote failed");
+        }
+        if let (Some(mut article), Some(file)) = (article, state.articles_file.as_mut()) {
+            status!("Article: {} ({} words)", article.headline.as_deref().unwrap_or("no headline"), article.word_count);
+            article.request_id = Some(request_id.clone());
+            writeln!(file, "{}", serde_json::to_string(&article).unwrap()).expect("write article failed");
+        }
 
         //write page info to a log file
-        log_file.write_fmt(format_args!("URL: {} - Size: {}: ", &url, size)).expect("write url failed");
-        log_file.write_fmt(format_args!("URLS List: {:?} ,", &scraped_urls)).expect("write url list failed");
-        log_file.write_fmt(format_args!("IMG List: {:?} \n", &scraped_imgs)).expect("write images failed");
-        
-        let new_page = Rc::new(Page::new(size, scraped_urls, scraped_imgs));
-        visited.insert(url, new_page.clone());
+        let unchanged = if not_modified { " (not modified)" } else { "" };
+        state.log_file.write_fmt(format_args!("[{}] URL: {} - Size: {}{}: ", request_id, &url, size, unchanged)).expect("write url failed");
+        state.log_file.write_fmt(format_args!("URLS List: {:?} ,", &links)).expect("write url list failed");
+        state.log_file.write_fmt(format_args!("IMG List: {:?} \n", &images)).expect("write images failed");
+    }
+    
+    //record the TLS setup of each host once, the first time we got a page from it
+    if config.record_tls {
+        record_tls(&url, state);
+    }
 
-        //add unvisited urls from scraped_urls to found_urls
-        for new in &new_page.links{
-            if !visited.contains_key(new){
-                found_urls.push_back(new.to_string());
-            }
+    let mut new_page = Page::new(size, links, images);
+    new_page.request_id = Some(request_id);
+    new_page.text = text;
+    new_page.metadata = metadata;
+    new_page.diagnostics = diagnostics;
+    new_page.externals = externals;
+    new_page.inline_images = inline_images;
+    new_page.responsive_images = responsive_images;
+    new_page.consent_wall = consent_wall;
+    new_page.protocol = Some(protocol);
+    new_page.etag = etag;
+    new_page.last_modified = last_modified;
+    new_page.not_modified = not_modified;
+    let new_page = Arc::new(new_page);
+    //pages outside the focus were only fetched for their links, and noindex ones asked to stay out of the records
+    let recorded = !stepping_stone && !robots.noindex;
+    let spilled = state.spill.as_ref().is_some_and(|spill| spill.contains(&url));
+    if recorded && !spilled && state.visited.insert_new(url.clone(), new_page.clone()) {
+        if let Some(spill) = &mut state.spill {
+            spill.added(&url, &*new_page);
         }
+        enforce_memory_budget(state);
+    }
 
-        limit -=1;
-    
+    //a redirector is no page of its own, only where it points gets crawled
+    state.redirects.extend(redirects);
+    enqueue_links(&url, depth, &new_page.links, state, config);
+    state.stepping_stones.remove(&url);
+
+    for (feed_url, feed) in feeds {
+        if state.feeds.contains_key(&feed_url) || is_blocked(&feed_url, state, config) {
+            continue;
+        }
+        status!("Found {:?} feed {}", feed.kind, feed_url);
+        state.feeds.insert(feed_url.clone(), feed);
+        if config.follow_feeds && !config.past_soft_deadline() && !config.out_of_bytes(&state.audit) {
+            let entries = fetch_feed(&feed_url, state, config);
+            enqueue_links(&feed_url, Depth::default(), &entries, state, config);
+        }
     }
+    ack(state, lease_id, &url, recorded.then_some(&*new_page));
+    true
+}
 
+//done with a leased url: journal what came of it first, so a crash after the ack can't lose it
+fn ack(state: &mut CrawlState, lease_id: u64, url: &str, page: Option<&Page>){
+    if let Some(journal) = &mut state.journal {
+        if let Err(e) = journal.append(url, page) {
+            //left leased, a resumed crawl does it again rather than losing it
+            status!("Could not journal {}, leaving it unacknowledged: {}", url, e);
+            return;
+        }
+    }
+    state.frontier.ack(lease_id);
 }
-fn main() {
 
-    //parsing arguments using CLAP
-    let arg_matcher = Command::new("Web Crawl Test")
-        .about("Web crawler")
-        .author("Min Nguyen")
-        //.allow_missing_positional(true)
-        .arg(Arg::with_name("max")
-            .short('m')
-            .long("max")
-            .takes_value(true)
-            .help("Max number of web page to crawl"))
-        .arg(Arg::with_name("url")
-            //.required(true)
-            .short('u')
-            .long("url")
-            .takes_value(true)
-            .help("The url of the root website to crawl from"))
-        .get_matches();
-    
-    //fetching the url from the user: need to start with http:/ or https:/
-    let url = arg_matcher.value_of("url").unwrap();
-    let http_head = &(url)[..4];
-    
-    if http_head.ne("http"){
-        print!("Not URL!");
+//put what an interrupted run journaled back: its pages in the results, every url in the seen store
+fn recover_journaled(journaled: journal::Journaled<Page>, state: &mut CrawlState, config: &CrawlConfig){
+    if journaled.is_empty() {
         return;
     }
-    
-    //see how many page to be crawled
-    let max = arg_matcher.value_of("max");
-    let mut limit = match max {
-        None => {
-            println!("No limit!");
-            0
-        },
-        Some(s) => {
-            match s.parse::<i32>(){
-                Ok(n) => {
-                    if n <= 0 {
-                        println!("No negative nor zero");
-                        return;
-                    }
-                    println!("Crawling {} pages...", n);
-                    n
-                },
-                Err(_) =>{
-                    println!("Not an integer");
-                    return;
-                }
+    status!("Recovered {} URLs the interrupted crawl was done with", journaled.len());
+    for (url, page) in journaled {
+        state.seen.insert(&config.fingerprint.of(&url));
+        let Some(page) = page.map(Arc::new) else {
+            continue;
+        };
+        if state.visited.insert_new(url.clone(), page.clone()) {
+            if let Some(spill) = &mut state.spill {
+                spill.added(&url, &*page);
             }
+            enforce_memory_budget(state);
         }
-    };
-
-
-    //list of visited website
-    let mut visited: HashMap<String, Rc<Page>> = HashMap::new();
-    //list of downloaded images
-    let mut downloaded: HashMap<String, Image> = HashMap::new();
-    //list of failed URLs
-    let mut baddies: Vec<String> = Vec::new();
-
-    //file to write results to
-    let mut log_file = File::create("log.txt").unwrap();
-    let pages_file = File::create("visited.json").unwrap();
-    let imgs_file = File::create("downloaded.json").unwrap();
-    let fails_file = File::create("baddies.json").unwrap();
-    
-    //recursive_scraper(&url, &mut visited, &mut downloaded, &mut baddies);
-    if limit == 0{
-        bfs_scraper(&url, &mut visited, &mut downloaded, &mut baddies, log_file);
-    }else{
-        bfs_scraper_with_limit(&url, &mut visited, &mut downloaded, &mut baddies, limit, log_file);
     }
-    
-
-    //serialize result as JSON string to the created paths
-    let pages_cerealizer = serde_json::ser::to_writer_pretty(pages_file, &visited).unwrap();
-    let imgs_cerealizer = serde_json::ser::to_writer_pretty(imgs_file, &downloaded).unwrap();
-    let fail_cerealizer = serde_json::ser::to_writer_pretty(fails_file, &baddies).unwrap();
+}
 
+//once the crawl holds more than its memory budget, move the oldest page records to the spill file
+fn enforce_memory_budget(state: &mut CrawlState){
+    let Some(spill) = &mut state.spill else {
+        return;
+    };
+    match spill.enforce(&state.visited, state.frontier.memory() + state.pending_bytes) {
+        Ok(0) => {},
+        Ok(moved) => status!("Over the memory budget, spilled {} page records to disk ({} so far)", moved, spill.len()),
+        Err(e) => status!("Could not spill page records, keeping them in memory: {}", e),
+    }
+}
 
+//pages recorded so far, in memory or spilled to disk
+fn page_count(state: &CrawlState) -> usize {
+    state.visited.lock_all().iter().count() + state.spill.as_ref().map_or(0, Spill::len)
 }
 
-/*
-serde to serialize data
-pull request 
-*/
\ No newline at end of file
+//add unseen urls found on page to the frontier, marking them seen so they are only queued once
+//links over their depth budget are left unseen, a shorter way there may still turn up
+fn enqueue_links(page: &str, page_depth: Depth, links: &[String], state: &mut CrawlState, config: &CrawlConfig){
+    //past the soft deadline we are only draining, nothing new gets queued
+    if config.past_soft_deadline() {
+        return;
+    }
+    for new in links{
+        let upgraded;
+        let new = if config.require_https {
+            match tls::upgrade_to_https(new) {
+                Some(https) => {
+                    upgraded = https;
+                    &upgraded
+                },
+                None => continue,
+            }
+        } else {
+            new
+        };
+        if is_blocked(new, state, config) {
+            continue;
+        }
+        let Some(depth) = config.depth_rules.admit(page, page_depth, new) else {
+            continue;
+        };
+        //outside the focus is only worth one hop, from a page inside it or the seed
+        let stepping_stone = !config.focus.includes(new) && !on_sitemap_host(new, state);
+        if stepping_stone && state.stepping_stones.contains(page) {
+            continue;
+        }
+        if state.seen.insert(&config.fingerprint.of(new)){
+            if depth.is_limited() {
+                state.depths.insert(new.to_string(), depth);
+            }
+            if stepping_stone {
+                state.stepping_stones.insert(new.to_string());
+            }
+            //look the host up now so the address is cached when we get to this url
+            if let Some(host) = Url::parse(new).ok().as_ref().and_then(Url::host_str) {
+                state.dns.prefetch(host);
+            }
+            state.frontier.push(new.to_string());
+        }
+    }
+}
+
+//fetch a discovered feed, note what it holds and return the urls of its entries worth crawling
+fn fetch_feed(feed_url: &str, state: &mut CrawlState, config: &CrawlConfig) -> Vec<String>{
+    let request_id = state.request_ids.next_id();
+    status!("Fetching feed...{} [{}]", feed_url, request_id);
+    let res = match http_requester(feed_url, 1, &state.client, &mut state.audit, config, None) {
+        Fetch::Page(res) => res,
+        res => {
+            if matches!(res, Fetch::HostBlacklisted) {
+                block_host(state, feed_url);
+            }
+            record_failure(state, "feed", feed_url, &request_id);
+            return Vec::new();
+        }
+    };
+    let contents = match feeds::parse(feed_url, &res.body) {
+        Ok(contents) => contents,
+        Err(e) => {
+            status!("{}", e);
+            state.log_file.write_fmt(format_args!("[{}] BAD FEED: {}\n", request_id, feed_url)).expect("write feed failed");
+            if let Some(dashboard) = &mut state.dashboard {
+                dashboard.failed("feed", feed_url);
+            }
+            return Vec::new();
+        }
+    };
+    status!("Feed has {} entries", contents.entry_urls.len());
+    state.log_file.write_fmt(format_args!("[{}] FEED: {} - Entries: {}\n", request_id, feed_url, contents.entry_urls.len())).expect("write feed failed");
+    if let Some(feed) = state.feeds.get_mut(feed_url) {
+        feed.request_id = Some(request_id);
+        feed.entries = Some(contents.entry_urls.len());
+        //the page's title for the feed is usually better, the feed's own is the fallback
+        if feed.title.is_none() {
+            feed.title = contents.title;
+        }
+    }
+    contents.entry_urls.iter().filter_map(|link| filter_url(link).map(Cow::into_owned)).collect()
+}
+
+//whether url is on one of the hosts the seed's robots.txt pointed us to
+fn on_sitemap_host(url: &str, state: &CrawlState) -> bool{
+    !state.sitemap_hosts.is_empty()
+        && Url::parse(url).ok().as_ref().and_then(Url::host_str).is_some_and(|host| state.sitemap_hosts.contains(&host.to_ascii_lowercase()))
+}
+
+//read the Sitemap: lines of the seed's robots.txt, and for each other yahoo host they are on,
+//bring the host into scope and queue the pages its sitemaps list
+fn seed_sitemap_hosts(seed: &str, state: &mut CrawlState, config: &CrawlConfig){
+    let seed_host = Url::parse(seed).ok().as_ref().and_then(Url::host_str).map(str::to_ascii_lowercase);
+    let Some(robots_url) = sitemaps::robots_url(seed) else {
+        return;
+    };
+    let Some((_, robots)) = fetch_text(&robots_url, "robots", state, config) else {
+        return;
+    };
+
+    //the sitemaps of each other host, in the order robots.txt lists them
+    let mut hosts: BTreeMap<String, VecDeque<String>> = BTreeMap::new();
+    for sitemap in sitemaps::from_robots(&robots) {
+        let Some(sitemap) = filter_url(&sitemap).map(Cow::into_owned) else {
+            continue;
+        };
+        let Some(host) = Url::parse(&sitemap).ok().as_ref().and_then(Url::host_str).map(str::to_ascii_lowercase) else {
+            continue;
+        };
+        if Some(&host) != seed_host.as_ref() && !is_blocked(&sitemap, state, config) {
+            hosts.entry(host).or_default().push_back(sitemap);
+        }
+    }
+
+    for (host, mut queue) in hosts {
+        status!("robots.txt lists sitemaps on {}, adding it to the crawl", host);
+        state.sitemap_hosts.insert(host.clone());
+        let mut fetched = HashSet::new();
+        while let Some(sitemap) = queue.pop_front() {
+            if fetched.len() == sitemaps::MAX_SITEMAPS_PER_HOST || config.past_soft_deadline() {
+                break;
+            }
+            if !fetched.insert(sitemap.clone()) {
+                continue;
+            }
+            let Some((request_id, body)) = fetch_text(&sitemap, "sitemap", state, config) else {
+                continue;
+            };
+            let contents = match sitemaps::parse(&sitemap, &body) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    status!("{}", e);
+                    continue;
+                }
+            };
+            status!("Sitemap has {} pages", contents.page_urls.len());
+            state.log_file.write_fmt(format_args!("[{}] SITEMAP: {} - Pages: {}\n", request_id, sitemap, contents.page_urls.len())).expect("write sitemap failed");
+            //an index only gets to point further into its own host
+            queue.extend(contents.sitemap_urls.into_iter()
+                .filter(|nested| Url::parse(nested).ok().as_ref().and_then(Url::host_str).is_some_and(|nested| nested.eq_ignore_ascii_case(&host))));
+            let pages: Vec<String> = contents.page_urls.iter().filter_map(|link| filter_url(link).map(Cow::into_owned)).collect();
+            enqueue_links(&sitemap, Depth::default(), &pages, state, config);
+        }
+    }
+}
+
+//fetch a file that isn't a page, robots.txt or a sitemap, along with the ID of the request
+//noting the failure if it couldn't be had
+fn fetch_text(url: &str, kind: &'static str, state: &mut CrawlState, config: &CrawlConfig) -> Option<(String, String)>{
+    let request_id = state.request_ids.next_id();
+    status!("Fetching {}...{} [{}]", kind, url, request_id);
+    match http_requester(url, 1, &state.client, &mut state.audit, config, None) {
+        Fetch::Page(res) => Some((request_id, res.body)),
+        res => {
+            if matches!(res, Fetch::HostBlacklisted) {
+                block_host(state, url);
+            }
+            record_failure(state, kind, url, &request_id);
+            None
+        }
+    }
+}
+
+//the background images of a stylesheet and the ones it @imports, as written, fetched once per crawl
+fn stylesheet_images(url: &str, state: &mut CrawlState, config: &CrawlConfig) -> Vec<String>{
+    if let Some(images) = state.stylesheets.get(url) {
+        return images.clone();
+    }
+    //in before fetching, so stylesheets importing each other don't go round forever
+    state.stylesheets.insert(url.to_string(), Vec::new());
+    if config.past_soft_deadline() || is_blocked(url, state, config) {
+        return Vec::new();
+    }
+    let Some((request_id, body)) = fetch_text(url, "stylesheet", state, config) else {
+        return Vec::new();
+    };
+    let refs = css::refs(&body);
+    let Ok(base) = Url::parse(url) else {
+        return Vec::new();
+    };
+    //relative urls in a stylesheet are relative to it, not to the page using it
+    let resolve = |src: &String| base.join(src.trim()).ok().map(String::from);
+    let mut images: Vec<String> = refs.images.iter().filter_map(resolve).collect();
+    state.log_file.write_fmt(format_args!("[{}] STYLESHEET: {} - IMG List: {:?}\n", request_id, url, images)).expect("write stylesheet failed");
+    for import in refs.imports.iter().filter_map(resolve) {
+        images.extend(stylesheet_images(&import, state, config));
+    }
+    state.stylesheets.insert(url.to_string(), images.clone());
+    images
+}
+
+//probe the TLS setup of the url's host unless it was already probed
+fn record_tls(link: &str, state: &mut CrawlState){
+    let Ok(url) = Url::parse(link) else {
+        return;
+    };
+    let Some(host) = url.host_str().filter(|_| url.scheme() == "https") else {
+        return;
+    };
+    if state.tls.contains_key(host) {
+        return;
+    }
+    //through the DNS cache, so the probe is held to the excluded ranges like the fetch was
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addr = state.dns.resolve(host).map_err(|e| e.to_string())
+        .and_then(|addrs| addrs.first().map(|addr| SocketAddr::new(addr.ip(), port)).ok_or_else(|| format!("{} has no addresses", host)));
+    let details = match addr.and_then(|addr| tls::probe(host, addr)) {
+        Ok(details) => {
+            status!("TLS: {} - {} {}, expires {}", host, details.protocol, details.cipher_suite, details.not_after);
+            if let Some(e) = &details.validation_error {
+                status!("TLS certificate of {} doesn't validate: {}", host, e);
+            }
+            Some(details)
+        },
+        Err(e) => {
+            status!("TLS probe failed for {}: {}", host, e);
+            if let Some(dashboard) = &mut state.dashboard {
+                dashboard.failed("tls", host);
+            }
+            None
+        }
+    };
+    state.tls.insert(host.to_string(), details);
+}
+
+//parse a duration like "90", "90s", "30m", "2h" or "1d", bare numbers are seconds
+fn parse_duration(value: &str) -> Option<Duration> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let number = number.parse::<u64>().ok()?;
+    let secs = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        "d" => number * 60 * 60 * 24,
+        _ => return None,
+    };
+    Some(Duration::from_secs(secs))
+}
+
+//parse a size like "512MB", "2GiB" or "1048576", bare numbers are bytes
+//KB, MB and GB are powers of 1000, KiB, MiB and GiB powers of 1024
+fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1e3,
+        "m" | "mb" => 1e6,
+        "g" | "gb" => 1e9,
+        "kib" => 1024.0,
+        "mib" => 1024.0 * 1024.0,
+        "gib" => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    let bytes = (number * multiplier).round();
+    (bytes >= 1.0 && bytes.is_finite()).then_some(bytes as u64)
+}
+
+//parse an optional duration flag, None when it's not given
+fn duration_arg(args: &ArgMatches, name: &str) -> Result<Option<Duration>, String> {
+    match args.value_of(name) {
+        Some(value) => parse_duration(value).map(Some).ok_or_else(|| format!("--{} is not a valid duration: {}", name, value)),
+        None => Ok(None),
+    }
+}
+
+//parse an optional numeric flag, falling back to the default when it's not given
+fn number_arg<T: FromStr>(args: &ArgMatches, name: &str, default: T) -> Result<T, String> {
+    match args.value_of(name) {
+        Some(value) => value.parse::<T>().map_err(|_| format!("--{} is not a valid number: {}", name, value)),
+        None => Ok(default),
+    }
+}
+
+//connection pool settings and DNS cache ttl from the command line
+fn pool_settings(args: &ArgMatches) -> Result<(PoolSettings, Duration), String> {
+    let defaults = PoolSettings::default();
+    let keepalive = defaults.keepalive.map_or(0, |d| d.as_secs());
+    let pool = PoolSettings {
+        max_idle_per_host: number_arg(args, "pool-max-idle", defaults.max_idle_per_host)?,
+        idle_timeout: Duration::from_secs(number_arg(args, "pool-idle-timeout", defaults.idle_timeout.as_secs())?),
+        keepalive: Some(Duration::from_secs(number_arg(args, "tcp-keepalive", keepalive)?)).filter(|d| !d.is_zero()),
+        http3: args.is_present("http3"),
+    };
+    if pool.http3 && !cfg!(feature = "http3") {
+        return Err("--http3 needs a scraper built with the http3 feature: RUSTFLAGS=\"--cfg reqwest_unstable\" cargo build --features http3".to_string());
+    }
+    Ok((pool, Duration::from_secs(number_arg(args, "dns-ttl", 300)?)))
+}
+
+fn main() -> ExitCode {
+
+    //parsing arguments using CLAP
+    let arg_matcher = Command::new("Web Crawl Test")
+        .about("Web crawler")
+        .author("Min Nguyen")
+        .subcommand_required(true)
+        .subcommand(Command::new("crawl")
+            .about("Crawl from a root url, writing the results to the current directory, or to a directory of its own in a --workspace")
+            .after_help(manifest::EXIT_CODES_HELP)
+            .arg(Arg::with_name("max")
+                .short('m')
+                .long("max")
+                .takes_value(true)
+                .help("Max number of web page to crawl"))
+            .arg(Arg::with_name("url")
+                //.required(true)
+                .short('u')
+                .long("url")
+                .takes_value(true)
+                .help("The url of the root website to crawl from"))
+            .arg(Arg::with_name("seen-store")
+                .long("seen-store")
+                .takes_value(true)
+                .possible_values(["memory", "bloom", "sled"])
+                .help("How to remember already seen URLs (default: memory)"))
+            .arg(Arg::with_name("seen-capacity")
+                .long("seen-capacity")
+                .takes_value(true)
+                .help("Number of URLs the bloom filter is sized for (default: 10000000)"))
+            .arg(Arg::with_name("seen-path")
+                .long("seen-path")
+                .takes_value(true)
+                .help("Directory of the sled seen store (default: seen.db), cleared unless resuming a --frontier"))
+            .arg(Arg::with_name("confine-output")
+                .long("confine-output")
+                .takes_value(true)
+                .help("Write every output file under this directory, refusing paths that lead out of it"))
+            .arg(Arg::with_name("frontier")
+                .long("frontier")
+                .takes_value(true)
+                .help("Persist the URL queue to this file, and the pages done to a journal next to it, and resume from them if they exist"))
+            .arg(Arg::with_name("ignore-param")
+                .long("ignore-param")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Query parameter that doesn't make a URL a different page, on top of the usual tracking ones, ie: utm_*"))
+            .arg(Arg::with_name("extract-text")
+                .long("extract-text")
+                .help("Record word count, paragraph count and an excerpt of each page's main text"))
+            .arg(Arg::with_name("excerpt-len")
+                .long("excerpt-len")
+                .takes_value(true)
+                .requires("extract-text")
+                .help("Number of characters kept in the text excerpt (default: 200)"))
+            .arg(Arg::with_name("structured-data")
+                .long("structured-data")
+                .help("Record each page's JSON-LD, microdata and OpenGraph/Twitter card tags"))
+            .arg(Arg::with_name("html-diagnostics")
+                .long("html-diagnostics")
+                .help("Record each page's HTML parse errors, unclosed tags, doctype and whether its declared charset matches the header's"))
+            .arg(Arg::with_name("finance-quotes")
+                .long("finance-quotes")
+                .help("Write the symbol, price, change and market cap of every Yahoo Finance quote page to finance.ndjson"))
+            .arg(Arg::with_name("articles")
+                .long("articles")
+                .help("Write the headline, authors, dates, section and body text of every news article to articles.ndjson"))
+            .arg(Arg::with_name("follow-feeds")
+                .long("follow-feeds")
+                .help("Fetch the RSS/Atom feeds pages link to and crawl their entries too"))
+            .arg(Arg::with_name("seed-sitemap-hosts")
+                .long("seed-sitemap-hosts")
+                .help("Crawl the other yahoo hosts the seed's robots.txt lists sitemaps on, even outside --include-only, starting from the pages in those sitemaps"))
+            .arg(Arg::with_name("min-image-dim")
+                .long("min-image-dim")
+                .takes_value(true)
+                .help("Drop images narrower or shorter than this many pixels as tracking pixels (default: 2)"))
+            .arg(Arg::with_name("image-url")
+                .long("image-url")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Only download images whose URL matches this pattern, * matches anything (default: https://s.yimg.com/*)"))
+            .arg(Arg::with_name("skip-image-url")
+                .long("skip-image-url")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Don't download images whose URL matches this pattern, * matches anything"))
+            .arg(Arg::with_name("skip-image-type")
+                .long("skip-image-type")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Don't download images of this type, as an extension or MIME type, ie: svg or image/gif"))
+            .arg(Arg::with_name("min-image-width")
+                .long("min-image-width")
+                .takes_value(true)
+                .help("Drop downloaded images narrower than this many pixels"))
+            .arg(Arg::with_name("min-image-height")
+                .long("min-image-height")
+                .takes_value(true)
+                .help("Drop downloaded images shorter than this many pixels"))
+            .arg(Arg::with_name("srcset")
+                .long("srcset")
+                .takes_value(true)
+                .possible_values(["largest", "smallest", "all"])
+                .help("Which variants of an image with a srcset, or in a <picture>, to download (default: largest)"))
+            .arg(Arg::with_name("pool-max-idle")
+                .long("pool-max-idle")
+                .takes_value(true)
+                .help("Idle connections kept open per host (default: 8)"))
+            .arg(Arg::with_name("pool-idle-timeout")
+                .long("pool-idle-timeout")
+                .takes_value(true)
+                .help("Seconds an idle connection is kept open (default: 90)"))
+            .arg(Arg::with_name("tcp-keepalive")
+                .long("tcp-keepalive")
+                .takes_value(true)
+                .help("TCP keepalive interval in seconds, 0 turns it off (default: 60)"))
+            .arg(Arg::with_name("http3")
+                .long("http3")
+                .help("Fetch over HTTP/3 (QUIC) only, hosts without it fail. Needs a build with the http3 feature"))
+            .arg(Arg::with_name("dns-ttl")
+                .long("dns-ttl")
+                .takes_value(true)
+                .help("Seconds a resolved host address is cached (default: 300)"))
+            .arg(Arg::with_name("exclude-ip-range")
+                .long("exclude-ip-range")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Never connect to addresses in this range, ie: 10.0.0.0/8, or private for every internal range"))
+            .arg(Arg::with_name("etiquette")
+                .long("etiquette")
+                .takes_value(true)
+                .possible_values(["polite", "moderate", "aggressive"])
+                .help("Politeness preset for the delay between requests (default: polite)"))
+            .arg(Arg::with_name("contact")
+                .long("contact")
+                .takes_value(true)
+                .help("Contact URL advertised to site operators in the User-Agent"))
+            .arg(Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .help("Email address sent in the From header"))
+            .arg(Arg::with_name("per-host-concurrency")
+                .long("per-host-concurrency")
+                .takes_value(true)
+                .help("Max requests in flight to one host (default: 2)"))
+            .arg(Arg::with_name("delay")
+                .long("delay")
+                .takes_value(true)
+                .help("Milliseconds to wait between requests, overrides the preset"))
+            .arg(Arg::with_name("max-bandwidth")
+                .long("max-bandwidth")
+                .takes_value(true)
+                .help("Read responses no faster than this in total, ie: 2MB/s or 500KiB/s"))
+            .arg(Arg::with_name("max-bytes")
+                .long("max-bytes")
+                .takes_value(true)
+                .help("Transfer budget, ie: 5GB. Once pages and images add up to this much, finishes the pages in progress but starts no new fetches"))
+            .arg(Arg::with_name("ignore-x-robots-tag")
+                .long("ignore-x-robots-tag")
+                .help("Don't honor X-Robots-Tag noindex/nofollow response headers"))
+            .arg(Arg::with_name("ignore-meta-robots")
+                .long("ignore-meta-robots")
+                .help("Don't honor robots meta tags or rel=nofollow on links"))
+            .arg(Arg::with_name("record-tls")
+                .long("record-tls")
+                .help("Record TLS version, cipher and certificate of every https host to tls.json"))
+            .arg(Arg::with_name("require-https")
+                .long("require-https")
+                .help("Only fetch over https, upgrading plain http links and dropping those that can't be"))
+            .arg(Arg::with_name("dry-run")
+                .long("dry-run")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Fetch nothing: replay the links of the crawl in DIR under these settings and list what would be fetched"))
+            .arg(Arg::with_name("extract-regex")
+                .long("extract-regex")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Regex finding links in JSON and plain text responses, capture group 1 is the link"))
+            .arg(Arg::with_name("extract-json-pointer")
+                .long("extract-json-pointer")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("JSON pointer to links in JSON responses, * matches every element, ie: /items/*/link"))
+            .arg(Arg::with_name("max-duration")
+                .long("max-duration")
+                .takes_value(true)
+                .help("Hard time limit, ie: 30m. Stops immediately and writes the results"))
+            .arg(Arg::with_name("soft-deadline")
+                .long("soft-deadline")
+                .takes_value(true)
+                .help("Soft time limit, ie: 25m. Finishes the page in progress but starts no new ones"))
+            .arg(Arg::with_name("tui")
+                .long("tui")
+                .help("Show a live dashboard instead of scrolling output, q stops the crawl"))
+            .arg(Arg::with_name("control")
+                .long("control")
+                .takes_value(true)
+                .help("Serve an HTTP API on this localhost address to inspect, pause and resume the crawl and add or drop queued URLs, ie: 127.0.0.1:7878"))
+            .arg(Arg::with_name("blacklist")
+                .long("blacklist")
+                .takes_value(true)
+                .help("File of blacklisted hosts and URL patterns, URLs that keep failing are added to it"))
+            .arg(Arg::with_name("blacklist-after")
+                .long("blacklist-after")
+                .takes_value(true)
+                .requires("blacklist")
+                .help("Blacklist a URL once it failed in this many runs (default: 3)"))
+            .arg(Arg::with_name("consent")
+                .long("consent")
+                .takes_value(true)
+                .possible_values(["mark", "agree", "reject"])
+                .help("Record consent interstitials as such, or answer them to get the page behind (default: mark)"))
+            .arg(Arg::with_name("fair-by")
+                .long("fair-by")
+                .takes_value(true)
+                .possible_values(["fifo", "host", "section"])
+                .help("Fetch round-robin across hosts, or across sections (a host and the first segment of the path), instead of first come first served (default: fifo)"))
+            .arg(Arg::with_name("consent-cookies")
+                .long("consent-cookies")
+                .takes_value(true)
+                .help("File keeping the consent cookies between runs (default: consent-cookies.txt)"))
+            .arg(Arg::with_name("auth-token")
+                .long("auth-token")
+                .takes_value(true)
+                .help("Bearer token sent in the Authorization header (default: $SCRAPER_AUTH_TOKEN)"))
+            .arg(Arg::with_name("auth-header")
+                .long("auth-header")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Extra header sent to the auth domain, ie: \"X-Api-Key: $API_KEY\""))
+            .arg(Arg::with_name("auth-domain")
+                .long("auth-domain")
+                .takes_value(true)
+                .help("Only send auth headers to this host and its subdomains (default: yahoo.com)"))
+            .arg(Arg::with_name("login-url")
+                .long("login-url")
+                .takes_value(true)
+                .help("Page with the login form to post before crawling, the session cookie is kept"))
+            .arg(Arg::with_name("login-field")
+                .long("login-field")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .requires("login-url")
+                .help("Field to fill in on the login form, ie: username=me or password=$YAHOO_PASSWORD"))
+            .arg(Arg::with_name("on-status")
+                .long("on-status")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("What to do with pages ending in a status or status class: accept, retry, skip, follow or blacklist-host, ie: 503=skip or 4xx=accept"))
+            .arg(Arg::with_name("include-only")
+                .long("include-only")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Only record URLs matching this pattern, * matches anything. Other pages one link away are fetched just for their links"))
+            .arg(Arg::with_name("include-only-regex")
+                .long("include-only-regex")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Like --include-only, with a regex"))
+            .arg(Arg::with_name("save-images")
+                .long("save-images")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Keep downloaded images in DIR, each stored once under the SHA-256 of its bytes with an index.json of URL to hash, shareable between crawls"))
+            .arg(Arg::with_name("image-cache")
+                .long("image-cache")
+                .takes_value(true)
+                .help("downloaded.json of an earlier crawl, its images are only downloaded again if they changed"))
+            .arg(Arg::with_name("host-alias")
+                .long("host-alias")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("SITE=HOST,...")
+                .help("Note in politeness.json that these hosts are one site, * matches anything, ie: yahoo.com=www.yahoo.com,*.yimg.com"))
+            .arg(Arg::with_name("workspace")
+                .long("workspace")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Write this run's results, log and manifest to a new directory under DIR/runs, named after when it started and its --tag"))
+            .arg(Arg::with_name("tag")
+                .long("tag")
+                .takes_value(true)
+                .requires("workspace")
+                .help("Label for this run, added to the name of its directory in the workspace and to its manifest"))
+            .arg(Arg::with_name("previous")
+                .long("previous")
+                .takes_value(true)
+                .help("Output directory of an earlier crawl: pages it recorded are asked for conditionally and carried forward unparsed if unchanged, its downloaded.json is the image cache (default when resuming: the output directory)"))
+            .arg(Arg::with_name("memory-budget")
+                .long("memory-budget")
+                .takes_value(true)
+                .help("Keep the crawl's page records, queue and pending pages within about this much memory, spilling the oldest records to disk, ie: 2GB"))
+            .arg(Arg::with_name("parser-threads")
+                .long("parser-threads")
+                .takes_value(true)
+                .help("Threads parsing fetched pages, so fetching doesn't wait on parsing (default: number of CPUs)"))
+            .arg(Arg::with_name("link-depth")
+                .long("link-depth")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Follow query-only or path links matching a pattern at most this many hops in a row, ie: query:*?page=*=3")))
+        .subcommand(Command::new("recrawl")
+            .about("Run as a daemon keeping URLs fresh, fetching each again once the interval of its class is up")
+            .after_help(manifest::EXIT_CODES_HELP)
+            .arg(Arg::with_name("store")
+                .long("store")
+                .takes_value(true)
+                .default_value("schedule.json")
+                .help("Schedule store, remembering when each URL is due, kept across restarts"))
+            .arg(Arg::with_name("interval")
+                .long("interval")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Refresh URLs matching a pattern this often as one class, the first matching one wins, ie: front:https://www.yahoo.com/=1h"))
+            .arg(Arg::with_name("default-interval")
+                .long("default-interval")
+                .takes_value(true)
+                .help("Refresh URLs no --interval matches this often, they aren't scheduled otherwise"))
+            .arg(Arg::with_name("add")
+                .long("add")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Schedule this URL"))
+            .arg(Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .help("Schedule every page in this visited.json"))
+            .arg(Arg::with_name("delay")
+                .long("delay")
+                .takes_value(true)
+                .help("Milliseconds between two requests (default: 1000)"))
+            .arg(Arg::with_name("once")
+                .long("once")
+                .help("Refresh the URLs due now and exit, for running from cron"))
+            .arg(Arg::with_name("webhook")
+                .long("webhook")
+                .takes_value(true)
+                .help("POST each change written to changes.ndjson to this URL as well"))
+            .arg(Arg::with_name("articles")
+                .long("articles")
+                .help("Append changed news articles to articles.ndjson"))
+            .arg(Arg::with_name("finance-quotes")
+                .long("finance-quotes")
+                .help("Append the quotes of refreshed Yahoo Finance quote pages to finance.ndjson"))
+            .arg(Arg::with_name("confine-output")
+                .long("confine-output")
+                .takes_value(true)
+                .help("Write every output file under this directory, refusing paths that lead out of it")))
+        .subcommand(Command::new("diff")
+            .about("Compare the visited.json of two crawls")
+            .arg(Arg::with_name("old")
+                .required(true)
+                .help("visited.json of the earlier crawl"))
+            .arg(Arg::with_name("new")
+                .required(true)
+                .help("visited.json of the later crawl"))
+            .arg(Arg::with_name("output")
+                .short('o')
+                .long("output")
+                .takes_value(true)
+                .help("Also write the full diff as JSON to this file")))
+        .subcommand(Command::new("merge")
+            .about("Combine the results of several partial crawls into one deduplicated dataset")
+            .arg(Arg::with_name("dirs")
+                .required(true)
+                .multiple_values(true)
+                .help("Output directories of the crawls, later ones win when they saw the same page"))
+            .arg(Arg::with_name("out")
+                .long("out")
+                .takes_value(true)
+                .default_value("merged")
+                .help("Directory to write the merged results to")))
+        .subcommand(Command::new("blacklist")
+            .about("Show a blacklist file, or flag hosts and URL patterns in it by hand")
+            .arg(Arg::with_name("file")
+                .required(true)
+                .help("The blacklist file, created if it doesn't exist"))
+            .arg(Arg::with_name("host")
+                .long("host")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Blacklist this host and its subdomains"))
+            .arg(Arg::with_name("pattern")
+                .long("url")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Blacklist URLs matching this pattern, * matches anything")))
+        .subcommand(Command::new("runs")
+            .about("List the runs in a workspace, oldest first")
+            .arg(Arg::with_name("workspace")
+                .default_value(".")
+                .help("The workspace directory")))
+        .subcommand(Command::new("inspect")
+            .about("Check the output files of a crawl against this version's schema, then summarize them or look up records")
+            .arg(Arg::with_name("dir")
+                .default_value(".")
+                .help("Output directory of the crawl"))
+            .arg(Arg::with_name("url")
+                .long("url")
+                .takes_value(true)
+                .help("Print the records of URLs matching this pattern instead of the summary, * matches anything")))
+        .subcommand(Command::new("report")
+            .about("Summarize the results of a crawl")
+            .arg(Arg::with_name("dir")
+                .default_value(".")
+                .help("Output directory of the crawl"))
+            .arg(Arg::with_name("html")
+                .long("html")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Also write the report as a self-contained HTML page with charts to FILE"))
+            .arg(Arg::with_name("host-alias")
+                .long("host-alias")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("SITE=HOST,...")
+                .help("Report these hosts as one site, * matches anything, ie: yahoo.com=www.yahoo.com,*.yimg.com")))
+        .get_matches();
+
+    match arg_matcher.subcommand() {
+        Some(("crawl", args)) => return crawl(args).into(),
+        Some(("recrawl", args)) => return recrawl(args).into(),
+        Some(("diff", args)) => diff_crawls(args),
+        Some(("merge", args)) => merge_crawls(args),
+        Some(("report", args)) => report_crawl(args),
+        Some(("inspect", args)) => inspect_crawl(args),
+        Some(("blacklist", args)) => edit_blacklist(args),
+        Some(("runs", args)) => list_runs(args),
+        _ => unreachable!("clap requires a subcommand"),
+    }
+    ExitCode::SUCCESS
+}
+
+//crawl from the root url given on the command line
+fn crawl(arg_matcher: &ArgMatches) -> Outcome {
+    //fetching the url from the user: need to start with http:/ or https:/
+    let Some(url) = arg_matcher.value_of("url") else {
+        println!("No --url to crawl from");
+        return Outcome::ConfigError;
+    };
+    if !url.starts_with("http"){
+        println!("Not URL!");
+        return Outcome::ConfigError;
+    }
+
+    //https only: start from the upgraded seed too
+    let require_https = arg_matcher.is_present("require-https");
+    let url = if require_https {
+        match tls::upgrade_to_https(url) {
+            Some(url) => url,
+            None => {
+                println!("Can't crawl {} over https", url);
+                return Outcome::ConfigError;
+            }
+        }
+    } else {
+        url.to_string()
+    };
+    
+    //see how many page to be crawled
+    let max = arg_matcher.value_of("max");
+    let limit = match max {
+        None => {
+            println!("No limit!");
+            None
+        },
+        Some(s) => {
+            match s.parse::<i32>(){
+                Ok(n) => {
+                    if n <= 0 {
+                        println!("No negative nor zero");
+                        return Outcome::ConfigError;
+                    }
+                    println!("Crawling {} pages...", n);
+                    Some(n)
+                },
+                Err(_) =>{
+                    println!("Not an integer");
+                    return Outcome::ConfigError;
+                }
+            }
+        }
+    };
+
+    //where files may be written, checked before anything is
+    let Some(confinement) = output_confinement(arg_matcher) else {
+        return Outcome::ConfigError;
+    };
+    let output = |path: &str| confinement.resolve(path).map_err(|e| println!("{}", e)).ok();
+    //runs kept side by side, the workspace is confined like any other output
+    let workspace = match arg_matcher.value_of("workspace").map(output) {
+        Some(None) => return Outcome::ConfigError,
+        dir => dir.flatten().map(|dir| Workspace::new(&dir)),
+    };
+
+    //how we identify ourselves and pace requests
+    let etiquette = match Etiquette::from_args(arg_matcher) {
+        Ok(etiquette) => etiquette,
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+    println!("User-Agent: {} - delay: {:?}, per host: {}", etiquette.user_agent(), etiquette.delay, etiquette.per_host);
+
+
+    //set of URLs already queued, kept separately so huge crawls don't need every page in memory to dedup
+    let seen_capacity = match arg_matcher.value_of("seen-capacity").unwrap_or("10000000").parse::<usize>() {
+        Ok(n) => n,
+        Err(_) => {
+            println!("Seen capacity is not an integer");
+            return Outcome::ConfigError;
+        }
+    };
+    let Some(seen_path) = output(arg_matcher.value_of("seen-path").unwrap_or("seen.db")) else {
+        return Outcome::ConfigError;
+    };
+
+    //text extraction is opt-in since it costs a walk over every page and bloats visited.json
+    let excerpt_len = if arg_matcher.is_present("extract-text") {
+        match arg_matcher.value_of("excerpt-len").unwrap_or("200").parse::<usize>() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                println!("Excerpt length is not an integer");
+                return Outcome::ConfigError;
+            }
+        }
+    } else {
+        None
+    };
+
+    //images below this size are tracking pixels, not content
+    let min_image_dim = match arg_matcher.value_of("min-image-dim").unwrap_or("2").parse::<usize>() {
+        Ok(n) => n,
+        Err(_) => {
+            println!("Minimum image dimension is not an integer");
+            return Outcome::ConfigError;
+        }
+    };
+
+    //which images are worth downloading at all, every decision ends up in the log
+    let (min_image_width, min_image_height) = match (number_arg(arg_matcher, "min-image-width", 0usize), number_arg(arg_matcher, "min-image-height", 0usize)) {
+        (Ok(width), Ok(height)) => (width, height),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+    let image_filter = ImageFilter::new(
+        arg_matcher.values_of("image-url").into_iter().flatten(),
+        arg_matcher.values_of("skip-image-url").into_iter().flatten(),
+        arg_matcher.values_of("skip-image-type").into_iter().flatten(),
+        min_image_width,
+        min_image_height,
+    );
+    let srcset = SrcsetPolicy::from_name(arg_matcher.value_of("srcset").unwrap_or("largest")).unwrap();
+
+    //link extraction for responses that aren't HTML
+    let extract_rules = match ExtractRules::new(
+        arg_matcher.values_of("extract-regex").into_iter().flatten(),
+        arg_matcher.values_of("extract-json-pointer").into_iter().flatten(),
+    ) {
+        Ok(rules) => rules,
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+
+    //how far to follow each class of links
+    let depth_rules = match DepthRules::new(arg_matcher.values_of("link-depth").into_iter().flatten()) {
+        Ok(rules) => rules,
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+
+    //a rate is a size per second, the /s is optional
+    let bandwidth = match arg_matcher.value_of("max-bandwidth").map(|rate| (rate, parse_size(rate.strip_suffix("/s").unwrap_or(rate)))) {
+        Some((_, Some(rate))) => Some(Bandwidth::new(rate)),
+        Some((rate, None)) => {
+            println!("--max-bandwidth {} is not a rate, expected something like 2MB/s", rate);
+            return Outcome::ConfigError;
+        },
+        None => None,
+    };
+    let max_bytes = match arg_matcher.value_of("max-bytes").map(|budget| (budget, parse_size(budget))) {
+        Some((_, Some(budget))) => Some(budget),
+        Some((budget, None)) => {
+            println!("--max-bytes {} is not a size, expected something like 5GB", budget);
+            return Outcome::ConfigError;
+        },
+        None => None,
+    };
+
+    //parsing runs next to fetching, one thread per core unless told otherwise
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+    let parser_threads = match number_arg(arg_matcher, "parser-threads", cpus) {
+        Ok(n) if n > 0 => n,
+        Ok(_) => {
+            println!("--parser-threads must be at least 1");
+            return Outcome::ConfigError;
+        },
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+
+    //what to do with responses that aren't pages
+    let status_policy = match StatusPolicy::new(arg_matcher.values_of("on-status").into_iter().flatten()) {
+        Ok(policy) => policy,
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+
+    //focused crawl: the pages we are after, and nothing else
+    let focus = match Focus::new(
+        arg_matcher.values_of("include-only").into_iter().flatten(),
+        arg_matcher.values_of("include-only-regex").into_iter().flatten(),
+    ) {
+        Ok(focus) => focus,
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+
+    //how long we are allowed to run
+    let (max_duration, soft_deadline) = match (duration_arg(arg_matcher, "max-duration"), duration_arg(arg_matcher, "soft-deadline")) {
+        (Ok(max_duration), Ok(soft_deadline)) => (max_duration, soft_deadline),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+
+    //known-bad URLs from earlier runs
+    let blacklist_path = match arg_matcher.value_of("blacklist").map(output) {
+        Some(None) => return Outcome::ConfigError,
+        path => path.flatten(),
+    };
+    let blacklist = match &blacklist_path {
+        Some(path) => match Blacklist::open(path) {
+            Ok(blacklist) => blacklist,
+            Err(e) => {
+                println!("Could not open blacklist at {}: {}", path.display(), e);
+                return Outcome::ConfigError;
+            }
+        },
+        None => Blacklist::default(),
+    };
+    let blacklist_after = match number_arg(arg_matcher, "blacklist-after", 3u32) {
+        Ok(n) => n,
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+    if blacklist.is_blocked(&url) {
+        println!("{} is blacklisted", url);
+        return Outcome::ConfigError;
+    }
+    let fingerprint = UrlFingerprint::new(arg_matcher.values_of("ignore-param").into_iter().flatten());
+
+    //tuning the scope: see what these settings would fetch using an earlier crawl's links, before writing any output
+    if let Some(dir) = arg_matcher.value_of("dry-run") {
+        let earlier = match CrawlResults::load(Path::new(dir)) {
+            Ok(results) => results,
+            Err(e) => {
+                println!("{}", e);
+                return Outcome::ConfigError;
+            }
+        };
+        let failed = earlier.baddies.iter().map(|failure| failure.url.as_str()).collect();
+        let scope = dryrun::Scope { blacklist: &blacklist, focus: &focus, depth_rules: &depth_rules, fingerprint: &fingerprint, require_https };
+        print!("{}", dryrun::simulate(&url, &earlier.visited, &failed, &scope, limit).report());
+        return Outcome::Success;
+    }
+
+    //credentials for pages behind an account
+    let auth = match Auth::from_args(arg_matcher) {
+        Ok(auth) => auth,
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+
+    //answering consent forms needs cookies, and they are worth keeping for the next run
+    //logging in needs them too, the session cookie only gets saved along with consent cookies
+    let consent = ConsentMode::from_name(arg_matcher.value_of("consent").unwrap_or("mark")).unwrap();
+    let Some(cookie_path) = output(arg_matcher.value_of("consent-cookies").unwrap_or("consent-cookies.txt")) else {
+        return Outcome::ConfigError;
+    };
+    let cookies = if consent == ConsentMode::Mark && !auth.needs_cookies() {
+        None
+    } else {
+        let jar = Arc::new(Jar::default());
+        if consent != ConsentMode::Mark {
+            if let Err(e) = consent::load_cookies(&jar, &cookie_path) {
+                println!("Could not load consent cookies from {}: {}", cookie_path.display(), e);
+                return Outcome::ConfigError;
+            }
+        }
+        Some(jar)
+    };
+
+    //hosts to report as one site, each still recorded under its own name
+    let host_aliases = match HostAliases::parse(arg_matcher.values_of("host-alias").into_iter().flatten()) {
+        Ok(aliases) => aliases,
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+
+    //one client for the whole crawl so connections and DNS answers get reused
+    let (pool, dns_ttl) = match pool_settings(arg_matcher) {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+    //hosts resolving into these ranges, and redirects to them, are refused at connection time
+    let excluded_ips = match ExcludedRanges::new(arg_matcher.values_of("exclude-ip-range").into_iter().flatten()) {
+        Ok(excluded) => excluded,
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+    if let Some(range) = Url::parse(&url).ok().and_then(|seed| excluded_ips.matching_url(&seed).copied()) {
+        println!("{} is in excluded range {}", url, range);
+        return Outcome::ConfigError;
+    }
+    let dns = Arc::new(DnsCache::new(dns_ttl, excluded_ips));
+    let client = match http::build_client(&pool, dns.clone(), require_https, cookies.clone()) {
+        Ok(client) => client,
+        Err(e) => {
+            println!("Could not build HTTP client: {}", e);
+            return Outcome::ConfigError;
+        }
+    };
+    if let Err(e) = auth.login(&client, &etiquette) {
+        println!("{}", e);
+        return Outcome::ConfigError;
+    }
+
+    //queue of URLs to crawl, on disk if we want to survive crashes
+    let frontier_path = match arg_matcher.value_of("frontier").map(output) {
+        Some(None) => return Outcome::ConfigError,
+        path => path.flatten(),
+    };
+    let mut frontier = match &frontier_path {
+        Some(path) => match Frontier::open(path) {
+            Ok(frontier) => frontier,
+            Err(e) => {
+                println!("Could not open frontier at {}: {}", path.display(), e);
+                return Outcome::ConfigError;
+            }
+        },
+        None => Frontier::in_memory(),
+    };
+    //URLs get handed out by the frontier, so it is what keeps us off any one host
+    frontier.set_host_cap(etiquette.per_host);
+    //unless told otherwise the queue stays FIFO, the way the crawl always went
+    let fairness = Fairness::from_name(arg_matcher.value_of("fair-by").unwrap_or("fifo")).unwrap();
+    frontier.set_fairness(fairness);
+    //what came of each url the frontier lets go of, anything still in it is from a crawl that never wrote its results
+    let journal_path = frontier_path.as_deref().map(Journal::path_for);
+    let (journal, journaled) = match &journal_path {
+        Some(path) => match Journal::open::<Page>(path) {
+            Ok((journal, journaled)) => (Some(journal), journaled),
+            Err(e) => {
+                println!("Could not open journal at {}: {}", path.display(), e);
+                return Outcome::ConfigError;
+            }
+        },
+        None => (None, Vec::new()),
+    };
+    //a sled store left on disk by an earlier crawl only carries over into its resumption
+    let seen = match seen::open_store(arg_matcher.value_of("seen-store").unwrap_or("memory"), seen_capacity, &seen_path, !frontier.is_empty()) {
+        Ok(seen) => seen,
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+
+    //an earlier crawl to make page requests conditional on, a resumed crawl picks up its own results
+    //read before the output files get overwritten, they may well be the same files
+    let previous_dir = match arg_matcher.value_of("previous") {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None if !frontier.is_empty() => match &workspace {
+            //a new run directory has nothing yet, the last run that got as far as writing results does
+            Some(workspace) => workspace.runs().unwrap_or_default().into_iter()
+                .rev()
+                .map(|run| run.dir)
+                .find(|dir| dir.join("visited.json").exists()),
+            None => output("visited.json")
+                .filter(|path| path.exists())
+                .and_then(|path| path.parent().map(Path::to_path_buf)),
+        },
+        None => None,
+    };
+    let mut previous = match &previous_dir {
+        Some(dir) => match PreviousCrawl::load(dir) {
+            Ok(previous) => {
+                println!("Asking for the {} pages of {} conditionally", previous.len(), dir.display());
+                previous
+            },
+            Err(e) => {
+                println!("{}", e);
+                return Outcome::ConfigError;
+            }
+        },
+        None => PreviousCrawl::default(),
+    };
+    let cached_imgs = match arg_matcher.value_of("image-cache") {
+        Some(path) => match results::load_downloaded(Path::new(path)) {
+            Ok(images) => images,
+            Err(e) => {
+                println!("{}", e);
+                return Outcome::ConfigError;
+            }
+        },
+        None => previous.take_images(),
+    };
+
+    //runtime control of the crawl, localhost only
+    let control = match arg_matcher.value_of("control").map(Control::listen) {
+        Some(Ok(control)) => {
+            println!("Control API listening on http://{}/", control.addr());
+            Some(control)
+        },
+        Some(Err(e)) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        },
+        None => None,
+    };
+
+    //with a workspace every run writes to a directory of its own, otherwise to the current one
+    let run_dir = match &workspace {
+        Some(workspace) => match workspace.create_run(manifest::now(), arg_matcher.value_of("tag")) {
+            Ok(dir) => {
+                println!("Writing this run to {}", dir.display());
+                Some(dir)
+            },
+            Err(e) => {
+                println!("Could not create a run directory in the workspace: {}", e);
+                return Outcome::ConfigError;
+            }
+        },
+        None => None,
+    };
+    let run_output = |file_name: &str| match &run_dir {
+        Some(dir) => Some(dir.join(file_name)),
+        None => output(file_name),
+    };
+
+    //file to write results to
+    let record_tls = arg_matcher.is_present("record-tls");
+    let finance_quotes = arg_matcher.is_present("finance-quotes");
+    let articles = arg_matcher.is_present("articles");
+    let mut outputs = BTreeMap::new();
+    for (output_name, file_name) in [
+        ("pages", "visited.json"),
+        ("images", "downloaded.json"),
+        ("failures", "baddies.json"),
+        ("image_pages", "image_pages.json"),
+        ("feeds", "feeds.json"),
+        ("redirects", "redirects.json"),
+        ("politeness", "politeness.json"),
+        ("log", "log.txt"),
+        ("manifest", "run-manifest.json"),
+        ("tls", "tls.json"),
+        ("finance", "finance.ndjson"),
+        ("articles", "articles.ndjson"),
+    ] {
+        let wanted = match output_name {
+            "tls" => record_tls,
+            "finance" => finance_quotes,
+            "articles" => articles,
+            _ => true,
+        };
+        if !wanted {
+            continue;
+        }
+        let Some(path) = run_output(file_name) else {
+            return Outcome::ConfigError;
+        };
+        outputs.insert(output_name, path);
+    }
+    //the manifest is written last, everything else is created up front so a bad path fails before crawling
+    let mut files = BTreeMap::new();
+    for (&output_name, path) in outputs.iter().filter(|(&output_name, _)| output_name != "manifest") {
+        match File::create(path) {
+            Ok(file) => files.insert(output_name, file),
+            Err(e) => {
+                println!("Could not create {}: {}", path.display(), e);
+                return Outcome::ConfigError;
+            }
+        };
+    }
+    let mut file = |output_name| files.remove(output_name).expect("every wanted output was created");
+    let pages_file = file("pages");
+    let imgs_file = file("images");
+    let fails_file = file("failures");
+    let image_pages_file = file("image_pages");
+    let feeds_file = file("feeds");
+    let redirects_file = file("redirects");
+    let politeness_file = file("politeness");
+    let log_file = file("log");
+    let tls_file = files.remove("tls");
+    let finance_file = files.remove("finance");
+    let articles_file = files.remove("articles");
+
+    //page records over the budget go to a spill file next to the results, deleted once they are written out
+    let spill = match arg_matcher.value_of("memory-budget").map(|budget| (budget, parse_size(budget))) {
+        Some((_, Some(budget))) => {
+            let Some(path) = run_output("visited.spill.ndjson") else {
+                return Outcome::ConfigError;
+            };
+            match Spill::create(&path, budget as usize) {
+                Ok(spill) => Some(spill),
+                Err(e) => {
+                    println!("Could not create spill file {}: {}", path.display(), e);
+                    return Outcome::ConfigError;
+                }
+            }
+        },
+        Some((budget, None)) => {
+            println!("--memory-budget {} is not a size, expected something like 2GB", budget);
+            return Outcome::ConfigError;
+        },
+        None => None,
+    };
+
+    //images go to a store of their own, possibly shared with other crawls
+    let image_store_path = match arg_matcher.value_of("save-images").map(output) {
+        Some(None) => return Outcome::ConfigError,
+        path => path.flatten(),
+    };
+    let image_store = match image_store_path.as_deref().map(ImageStore::open) {
+        Some(Ok(store)) => Some(store),
+        Some(Err(e)) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        },
+        None => None,
+    };
+
+    let mut state = CrawlState {
+        visited: ShardedMap::new(),
+        seen,
+        frontier,
+        journal,
+        downloaded: ShardedMap::new(),
+        cached_imgs,
+        previous,
+        skipped_imgs: HashSet::new(),
+        blocked_hosts: HashSet::new(),
+        baddies: Vec::new(),
+        log_file,
+        request_ids: RequestIds::new(),
+        client,
+        dns,
+        audit: Audit::new(etiquette.delay),
+        tls: BTreeMap::new(),
+        feeds: BTreeMap::new(),
+        redirects: BTreeMap::new(),
+        finance_file,
+        articles_file,
+        stylesheets: HashMap::new(),
+        depths: HashMap::new(),
+        stepping_stones: HashSet::new(),
+        sitemap_hosts: HashSet::new(),
+        dashboard: None,
+        control,
+        spill,
+        pending_bytes: 0,
+        image_store,
+    };
+    //time limits count from the moment the crawl starts
+    let started = Instant::now();
+    let started_at = manifest::now();
+    let config = CrawlConfig {
+        limit,
+        excerpt_len,
+        structured_data: arg_matcher.is_present("structured-data"),
+        html_diagnostics: arg_matcher.is_present("html-diagnostics"),
+        finance_quotes,
+        articles,
+        follow_feeds: arg_matcher.is_present("follow-feeds"),
+        seed_sitemap_hosts: arg_matcher.is_present("seed-sitemap-hosts"),
+        min_image_dim,
+        image_filter,
+        srcset,
+        etiquette,
+        bandwidth,
+        max_bytes,
+        record_tls,
+        require_https,
+        extract_rules,
+        soft_deadline: soft_deadline.map(|d| started + d),
+        hard_deadline: max_duration.map(|d| started + d),
+        blacklist,
+        consent,
+        auth,
+        depth_rules,
+        parser_threads,
+        status_policy,
+        focus,
+        fingerprint,
+    };
+
+    recover_journaled(journaled, &mut state, &config);
+
+    //everything before this point still prints normally, setup errors stay readable
+    if arg_matcher.is_present("tui") {
+        match Dashboard::start() {
+            Ok(dashboard) => state.dashboard = Some(dashboard),
+            Err(e) => println!("Could not start dashboard, falling back to plain output: {}", e),
+        }
+    }
+
+    let completed = bfs_scraper(&url, &mut state, &config);
+    //put the terminal back before anything else is printed
+    state.dashboard = None;
+
+    //errors that stopped being shown after the first few times, with how often they really happened
+    for (error, count) in noise::repeated() {
+        println!("{} repeated {} times", error, count);
+        state.log_file.write_fmt(format_args!("REPEATED {} times: {}\n", count, error)).expect("write repeats failed");
+    }
+
+    //downloaded.json doesn't say where an image was used, this does
+    let spilled = state.spill.iter().flat_map(|spill| spill.keys().filter_map(|url| Some((url, Arc::new(spill.get::<Page>(url)?.ok()?)))));
+    let image_pages = results::image_index(state.visited.lock_all().iter().map(|(url, page)| (url, page.clone())).chain(spilled));
+    //serialize result as JSON string to the created paths, a crawl whose results didn't all make it to disk was aborted
+    let written = results::write_json(pages_file, &WithSpilled { map: &state.visited, spill: state.spill.as_ref() })
+        .and_then(|_| results::write_json(imgs_file, &state.downloaded))
+        .and_then(|_| results::write_json(fails_file, &state.baddies))
+        .and_then(|_| results::write_json(image_pages_file, &image_pages))
+        .and_then(|_| results::write_json(feeds_file, &state.feeds))
+        .and_then(|_| results::write_json(redirects_file, &state.redirects))
+        //what we asked of each host and how we paced it, for anyone checking the crawl behaved
+        .and_then(|_| results::write_json(politeness_file, &state.audit.summary(&host_aliases)))
+        .and_then(|_| tls_file.map_or(Ok(()), |tls_file| results::write_json(tls_file, &state.tls)));
+    if let Err(e) = &written {
+        println!("Could not write the results: {}", e);
+    }
+    //the journaled pages are in the results now, and with nothing left in the frontier there's nothing to resume
+    let finished = state.frontier.is_empty() && state.frontier.in_flight_urls().is_empty();
+    if let Some(journal) = state.journal.as_mut().filter(|_| written.is_ok() && finished) {
+        if let Err(e) = journal.clear() {
+            println!("Could not clear the journal: {}", e);
+        }
+    }
+    if let Some(store) = &state.image_store {
+        let (stored, deduplicated) = store.counts();
+        println!("Stored {} new images, {} were already in the image store", stored, deduplicated);
+        if let Err(e) = store.save_index() {
+            println!("{}", e);
+        }
+    }
+    if let Some(jar) = cookies.filter(|_| consent != ConsentMode::Mark) {
+        if let Err(e) = consent::save_cookies(&jar, &cookie_path) {
+            println!("Could not save consent cookies to {}: {}", cookie_path.display(), e);
+        }
+    }
+
+    //tally this run's failures so URLs that keep failing get blacklisted
+    if arg_matcher.is_present("blacklist") {
+        let mut blacklist = config.blacklist;
+        if let Err(e) = tally_failures(&mut blacklist, &state, blacklist_after) {
+            println!("Could not update blacklist: {}", e);
+        }
+    }
+
+    //how the run went, for whatever started it
+    let outcome = match (completed && written.is_ok(), state.baddies.is_empty()) {
+        (false, _) => Outcome::Aborted,
+        (true, true) => Outcome::Success,
+        (true, false) => Outcome::CompletedWithErrors,
+    };
+    let mut manifest = Manifest::new(outcome, &url, started_at);
+    manifest.tag = arg_matcher.value_of("tag").map(str::to_string);
+    manifest.pages = page_count(&state);
+    manifest.images = state.downloaded.lock_all().iter().count();
+    manifest.failures = state.baddies.len();
+    manifest.config = BTreeMap::from([
+        ("limit", json!(limit)),
+        ("user_agent", json!(config.etiquette.user_agent())),
+        ("delay_ms", json!(config.etiquette.delay.as_millis())),
+        ("per_host", json!(config.etiquette.per_host)),
+        ("fair_by", json!(format!("{:?}", fairness).to_lowercase())),
+        ("http3", json!(arg_matcher.is_present("http3"))),
+        ("host_aliases", json!(host_aliases.rules())),
+        ("previous", json!(previous_dir.as_ref().map(|dir| dir.display().to_string()))),
+        ("save_images", json!(arg_matcher.value_of("save-images"))),
+        ("memory_budget_bytes", json!(state.spill.as_ref().map(Spill::budget))),
+        ("max_bandwidth_bytes_per_sec", json!(config.bandwidth.as_ref().map(Bandwidth::bytes_per_sec))),
+        ("max_bytes", json!(config.max_bytes)),
+        ("parser_threads", json!(config.parser_threads)),
+        ("require_https", json!(config.require_https)),
+        ("follow_feeds", json!(config.follow_feeds)),
+        ("seed_sitemap_hosts", json!(config.seed_sitemap_hosts)),
+        ("structured_data", json!(config.structured_data)),
+        ("html_diagnostics", json!(config.html_diagnostics)),
+        ("finance_quotes", json!(config.finance_quotes)),
+        ("articles", json!(config.articles)),
+        ("excerpt_len", json!(config.excerpt_len)),
+        ("min_image_dim", json!(config.min_image_dim)),
+        ("srcset", json!(format!("{:?}", config.srcset).to_lowercase())),
+        ("record_tls", json!(config.record_tls)),
+        ("consent", json!(format!("{:?}", consent).to_lowercase())),
+        ("max_duration_secs", json!(max_duration.map(|d| d.as_secs()))),
+        ("soft_deadline_secs", json!(soft_deadline.map(|d| d.as_secs()))),
+        ("include_only", json!(arg_matcher.values_of("include-only").into_iter().flatten().chain(arg_matcher.values_of("include-only-regex").into_iter().flatten()).collect::<Vec<_>>())),
+        ("link_depth", json!(arg_matcher.values_of("link-depth").into_iter().flatten().collect::<Vec<_>>())),
+        ("on_status", json!(arg_matcher.values_of("on-status").into_iter().flatten().collect::<Vec<_>>())),
+        ("exclude_ip_range", json!(arg_matcher.values_of("exclude-ip-range").into_iter().flatten().collect::<Vec<_>>())),
+        ("control", json!(arg_matcher.value_of("control"))),
+        ("confine_output", json!(arg_matcher.value_of("confine-output"))),
+    ]);
+    let here = std::env::current_dir().unwrap_or_default();
+    let manifest_path = outputs.remove("manifest").unwrap();
+    manifest.outputs = outputs.into_iter().map(|(output, path)| (output, here.join(path))).collect();
+    for (output, path) in [("frontier", frontier_path), ("journal", journal_path), ("blacklist", blacklist_path), ("image_store", image_store_path)] {
+        if let Some(path) = path {
+            manifest.outputs.insert(output, here.join(path));
+        }
+    }
+    if consent != ConsentMode::Mark {
+        manifest.outputs.insert("consent_cookies", here.join(&cookie_path));
+    }
+    match File::create(&manifest_path) {
+        Ok(file) => {
+            if let Err(e) = serde_json::to_writer_pretty(file, &manifest) {
+                println!("Could not write {}: {}", manifest_path.display(), e);
+            }
+        },
+        Err(e) => println!("Could not write {}: {}", manifest_path.display(), e),
+    }
+    //the spilled records are all in visited.json now
+    if let Some(spill) = state.spill.take() {
+        println!("{} page records were spilled to disk to stay within the memory budget", spill.len());
+        if let Err(e) = spill.remove() {
+            println!("Could not remove the spill file: {}", e);
+        }
+    }
+    outcome
+}
+
+//keep the URLs of a schedule fresh, fetching each again once its class's interval is up
+//the directory --confine-output keeps written files under, None if it can't be used
+fn output_confinement(args: &ArgMatches) -> Option<Confinement> {
+    match args.value_of("confine-output") {
+        Some(dir) => match Confinement::new(Path::new(dir)) {
+            Ok(confinement) => Some(confinement),
+            Err(e) => {
+                println!("Could not use {} for output: {}", dir, e);
+                None
+            }
+        },
+        None => Some(Confinement::default()),
+    }
+}
+
+fn recrawl(args: &ArgMatches) -> Outcome {
+    let Some(confinement) = output_confinement(args) else {
+        return Outcome::ConfigError;
+    };
+    let mut rules = Vec::new();
+    for rule in args.values_of("interval").into_iter().flatten() {
+        match IntervalRule::parse(rule, parse_duration) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => {
+                println!("{}", e);
+                return Outcome::ConfigError;
+            }
+        }
+    }
+    let default = match duration_arg(args, "default-interval") {
+        Ok(default) => default.filter(|every| !every.is_zero()),
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+    if rules.is_empty() && default.is_none() {
+        println!("Nothing would ever be scheduled, give an --interval or a --default-interval");
+        return Outcome::ConfigError;
+    }
+    let classes = Classes::new(rules, default);
+
+    let Ok(store) = confinement.resolve(args.value_of("store").unwrap()).map_err(|e| println!("{}", e)) else {
+        return Outcome::ConfigError;
+    };
+    let mut schedule = match Schedule::open(&store) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    };
+    let dropped = schedule.reclassify(&classes);
+    if dropped > 0 {
+        println!("Dropped {} URLs no interval matches any more", dropped);
+    }
+    let now = manifest::now();
+    let mut new_urls: Vec<String> = args.values_of("add").into_iter().flatten().map(str::to_string).collect();
+    if let Some(path) = args.value_of("from") {
+        match results::load_visited(Path::new(path)) {
+            Ok(visited) => new_urls.extend(visited.into_keys()),
+            Err(e) => {
+                println!("{}", e);
+                return Outcome::ConfigError;
+            }
+        }
+    }
+    let added = new_urls.iter().filter(|url| schedule.add(url, &classes, now)).count();
+    println!("{} URLs scheduled, {} of them new", schedule.len(), added);
+
+    let mut etiquette = Etiquette::preset(Preset::Polite);
+    match number_arg(args, "delay", etiquette.delay.as_millis() as u64) {
+        Ok(ms) => etiquette.delay = Duration::from_millis(ms),
+        Err(e) => {
+            println!("{}", e);
+            return Outcome::ConfigError;
+        }
+    }
+    let dns = Arc::new(DnsCache::new(Duration::from_secs(300), ExcludedRanges::default()));
+    let client = match http::build_client(&PoolSettings::default(), dns, false, None) {
+        Ok(client) => client,
+        Err(e) => {
+            println!("Could not build HTTP client: {}", e);
+            return Outcome::ConfigError;
+        }
+    };
+    let webhook = match args.value_of("webhook").map(Url::parse) {
+        Some(Ok(url)) if matches!(url.scheme(), "http" | "https") => Some(url),
+        Some(_) => {
+            println!("--webhook takes an http or https URL");
+            return Outcome::ConfigError;
+        },
+        None => None,
+    };
+    //appended to, so they grow into a history of the pages across restarts
+    let append = |path: &str| confinement.resolve(path)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::PermissionDenied, e))
+        .and_then(|path| std::fs::OpenOptions::new().create(true).append(true).open(path));
+    let (log, changes, articles, quotes) = match (
+        append("recrawl.ndjson"),
+        append("changes.ndjson"),
+        args.is_present("articles").then(|| append("articles.ndjson")).transpose(),
+        args.is_present("finance-quotes").then(|| append("finance.ndjson")).transpose(),
+    ) {
+        (Ok(log), Ok(changes), Ok(articles), Ok(quotes)) => (log, changes, articles, quotes),
+        (Err(e), _, _, _) | (_, Err(e), _, _) | (_, _, Err(e), _) | (_, _, _, Err(e)) => {
+            println!("Could not open output: {}", e);
+            return Outcome::ConfigError;
+        }
+    };
+    let mut outputs = RecrawlOutputs { log, changes, webhook, articles, quotes };
+
+    loop {
+        for url in schedule.due(manifest::now()) {
+            thread::sleep(etiquette.delay);
+            if let Err(e) = refresh_url(&url, &mut schedule, &client, &etiquette, &mut outputs) {
+                println!("Could not write what came of {}: {}", url, e);
+                return Outcome::Aborted;
+            }
+            if let Err(e) = schedule.save() {
+                println!("Could not save the schedule: {}", e);
+                return Outcome::Aborted;
+            }
+        }
+        if args.is_present("once") {
+            return Outcome::Success;
+        }
+        let Some(next_due) = schedule.next_due() else {
+            println!("Nothing scheduled");
+            return Outcome::Success;
+        };
+        let wait = next_due.saturating_sub(manifest::now());
+        if wait > 0 {
+            status!("Next refresh in {:?}", Duration::from_secs(wait));
+            thread::sleep(Duration::from_secs(wait));
+        }
+    }
+}
+
+//where the recrawl daemon writes what it finds
+struct RecrawlOutputs {
+    log: File,                  //recrawl.ndjson, one line per fetch
+    changes: File,              //changes.ndjson, one line per page that changed
+    webhook: Option<Url>,       //where changes are POSTed too, with --webhook
+    articles: Option<File>,     //articles.ndjson, only with --articles
+    quotes: Option<File>,       //finance.ndjson, only with --finance-quotes
+}
+
+//fetch a scheduled url again, conditionally when we have its validators, and note how it went
+//an error is a record that couldn't be written, the fetch itself failing is only noted
+fn refresh_url(url: &str, schedule: &mut Schedule, client: &Client, etiquette: &Etiquette, outputs: &mut RecrawlOutputs) -> std::io::Result<()>{
+    let Some(entry) = schedule.get(url).cloned() else {
+        return Ok(());
+    };
+    status!("Refreshing [{}] {}", entry.class, url);
+    let mut request = etiquette.apply(client.get(url)).timeout(Duration::from_secs(10));
+    if let Some(etag) = &entry.etag {
+        request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &entry.last_modified {
+        request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+    }
+    let now = manifest::now();
+    let response = request.send().map_err(|e| e.to_string()).and_then(|rep| {
+        let status = rep.status();
+        if status == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok((status, Refresh { etag: entry.etag.clone(), last_modified: entry.last_modified.clone(), ..Refresh::default() }));
+        }
+        if !status.is_success() {
+            return Err(status.to_string());
+        }
+        let header = |name| rep.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let (etag, last_modified) = (header(reqwest::header::ETAG), header(reqwest::header::LAST_MODIFIED));
+        let body = rep.text().map_err(|e| e.to_string())?;
+        Ok((status, Refresh { etag, last_modified, body: Some(body), ..Refresh::default() }))
+    });
+    let record = match response {
+        Ok((status, mut refresh)) => {
+            let body = refresh.body.take();
+            let document = body.as_deref().map(Document::from);
+            if let Some(document) = &document {
+                refresh.links = extract_urls(document, url, false, &mut FoundLinks::default());
+                refresh.paragraphs = text::paragraphs(document);
+            }
+            refresh.body = body;
+            let size = refresh.body.as_ref().map(String::len);
+            let change = schedule.refreshed(url, now, refresh);
+            let changed = change.is_some();
+            if let Some(change) = &change {
+                report_change(change, client, etiquette, outputs)?;
+            }
+            //new content is a first fetch or a changed page, only that is worth extracting again
+            if let Some(document) = document.filter(|_| changed || entry.last_fetched.is_none()) {
+                if let Some(file) = outputs.articles.as_mut() {
+                    if let Some(article) = article::extract(&document, url) {
+                        writeln!(file, "{}", serde_json::to_string(&article).unwrap())?;
+                    }
+                }
+                if let Some(file) = outputs.quotes.as_mut() {
+                    if let Some(quote) = finance::extract(&document, url) {
+                        writeln!(file, "{}", serde_json::to_string(&quote).unwrap())?;
+                    }
+                }
+            }
+            status!("{} {}", status, if changed { "changed" } else { "unchanged" });
+            json!({"url": url, "class": entry.class, "fetched_at": now, "status": status.as_u16(), "changed": changed, "size": size})
+        },
+        Err(e) => {
+            failure!(url, "Fail! {}", e);
+            schedule.failed(url, now);
+            json!({"url": url, "class": entry.class, "fetched_at": now, "error": e})
+        }
+    };
+    writeln!(outputs.log, "{}", record)
+}
+
+//add a change to the feed, and send it to the webhook if there is one
+//an error is the feed that couldn't be written, the webhook failing is only noted
+fn report_change(change: &Change, client: &Client, etiquette: &Etiquette, outputs: &mut RecrawlOutputs) -> std::io::Result<()>{
+    let json = serde_json::to_string(change).unwrap();
+    writeln!(outputs.changes, "{}", json)?;
+    let Some(webhook) = &outputs.webhook else {
+        return Ok(());
+    };
+    //a webhook that is down loses the change, changes.ndjson still has it
+    let sent = etiquette.apply(client.post(webhook.clone()))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(json)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .and_then(Response::error_for_status);
+    if let Err(e) = sent {
+        failure!(webhook.as_str(), "Webhook failed! {}", e);
+    }
+    Ok(())
+}
+
+//print what changed between two crawls
+fn diff_crawls(args: &ArgMatches) {
+    let old_path = Path::new(args.value_of("old").unwrap());
+    let new_path = Path::new(args.value_of("new").unwrap());
+    let (old, new) = match (results::load_visited(old_path), results::load_visited(new_path)) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    let diff = results::diff(&old, &new);
+    for url in &diff.added {
+        println!("+ {}", url);
+    }
+    for url in &diff.removed {
+        println!("- {}", url);
+    }
+    for change in &diff.changed {
+        println!("~ {} size {} -> {}, {} links added, {} removed",
+            change.url, change.old_size, change.new_size, change.links_added.len(), change.links_removed.len());
+    }
+    if diff.is_empty() {
+        println!("No differences");
+    } else {
+        println!("{} added, {} removed, {} changed", diff.added.len(), diff.removed.len(), diff.changed.len());
+    }
+
+    if let Some(output) = args.value_of("output") {
+        match File::create(output) {
+            Ok(file) => results::write_json(file, &diff).unwrap(),
+            Err(e) => println!("Could not create {}: {}", output, e),
+        }
+    }
+}
+
+//merge several crawl output directories into one
+fn merge_crawls(args: &ArgMatches) {
+    let mut merged = CrawlResults::default();
+    for dir in args.values_of("dirs").unwrap() {
+        match CrawlResults::load(Path::new(dir)) {
+            Ok(results) => {
+                println!("{}: {} pages, {} images", dir, results.visited.len(), results.downloaded.len());
+                merged.merge(results);
+            },
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+    }
+
+    let out = args.value_of("out").unwrap();
+    if let Err(e) = merged.save(Path::new(out)) {
+        println!("{}", e);
+        return;
+    }
+    println!("Merged into {}: {} pages, {} images, {} failed URLs", out, merged.visited.len(), merged.downloaded.len(), merged.baddies.len());
+}
+
+//forget the failures of URLs fetched fine this run, count the ones that failed again
+//and keep the hosts the status policy blacklisted
+fn tally_failures(blacklist: &mut Blacklist, state: &CrawlState, threshold: u32) -> std::io::Result<()> {
+    let (visited, downloaded) = (state.visited.lock_all(), state.downloaded.lock_all());
+    let spilled = state.spill.iter().flat_map(Spill::keys);
+    for url in visited.keys().chain(spilled).chain(downloaded.keys()) {
+        blacklist.record_success(url)?;
+    }
+    for Failure { url, .. } in &state.baddies {
+        if blacklist.record_failure(url, threshold)? {
+            println!("Blacklisted {}", url);
+        }
+    }
+    //hosts the status policy gave up on stay blacklisted
+    for host in &state.blocked_hosts {
+        blacklist.add_host(host)?;
+        println!("Blacklisted host {}", host);
+    }
+    Ok(())
+}
+
+//flag hosts and URL patterns by hand, then list what the blacklist holds
+fn edit_blacklist(args: &ArgMatches) {
+    let path = args.value_of("file").unwrap();
+    let mut blacklist = match Blacklist::open(Path::new(path)) {
+        Ok(blacklist) => blacklist,
+        Err(e) => {
+            println!("Could not open blacklist at {}: {}", path, e);
+            return;
+        }
+    };
+    for host in args.values_of("host").into_iter().flatten() {
+        if let Err(e) = blacklist.add_host(host) {
+            println!("Could not update blacklist: {}", e);
+            return;
+        }
+    }
+    for pattern in args.values_of("pattern").into_iter().flatten() {
+        if let Err(e) = blacklist.add_pattern(pattern) {
+            println!("Could not update blacklist: {}", e);
+            return;
+        }
+    }
+    for host in blacklist.hosts() {
+        println!("host {}", host);
+    }
+    for pattern in blacklist.patterns() {
+        println!("url  {}", pattern);
+    }
+}
+
+//list the runs in a workspace, with how each went
+fn list_runs(args: &ArgMatches) {
+    let dir = args.value_of("workspace").unwrap();
+    let runs = match Workspace::new(Path::new(dir)).runs() {
+        Ok(runs) => runs,
+        Err(e) => {
+            println!("Could not read the workspace {}: {}", dir, e);
+            return;
+        }
+    };
+    if runs.is_empty() {
+        println!("No runs in {}", dir);
+    }
+    for run in runs {
+        match run.outcome {
+            Some(outcome) => println!("{}  {}  {} pages, {} images, {} failures  {}",
+                run.name, outcome, run.pages, run.images, run.failures, run.seed.unwrap_or_default()),
+            None => println!("{}  unfinished", run.name),
+        }
+    }
+}
+
+//print a summary of a crawl's results
+fn report_crawl(args: &ArgMatches) {
+    let dir = args.value_of("dir").unwrap();
+    let aliases = match HostAliases::parse(args.values_of("host-alias").into_iter().flatten()) {
+        Ok(aliases) => aliases,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    match CrawlResults::load(Path::new(dir)) {
+        Ok(results) => {
+            print!("{}", results::report(&results, &aliases));
+            if let Some(file) = args.value_of("html") {
+                match std::fs::write(file, report::html(&results, &aliases)) {
+                    Ok(()) => println!("HTML report written to {}", file),
+                    Err(e) => println!("Could not write {}: {}", file, e),
+                }
+            }
+        }
+        Err(e) => println!("{}", e),
+    }
+}
+
+//validate the output of a crawl, then print its summary or the records matching a URL pattern
+fn inspect_crawl(args: &ArgMatches) {
+    let dir = Path::new(args.value_of("dir").unwrap());
+    let issues = match results::validate(dir) {
+        Ok(issues) => issues,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    if issues.is_empty() {
+        println!("Schema OK");
+    } else {
+        println!("Schema issues: {}", issues.len());
+        for issue in &issues {
+            if issue.record.is_empty() {
+                println!("    {}: {}", issue.file, issue.problem);
+            } else {
+                println!("    {} {}: {}", issue.file, issue.record, issue.problem);
+            }
+        }
+    }
+
+    //records that didn't validate may still stop the whole file from loading
+    let results = match CrawlResults::load(dir) {
+        Ok(results) => results,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    let Some(pattern) = args.value_of("url") else {
+        print!("{}", results::report(&results, &HostAliases::default()));
+        return;
+    };
+    let matches = |url: &String| blacklist::glob_match(pattern, url);
+    let found = serde_json::json!({
+        "visited": results.visited.iter().filter(|(url, _)| matches(url)).collect::<BTreeMap<_, _>>(),
+        "downloaded": results.downloaded.iter().filter(|(url, _)| matches(url)).collect::<BTreeMap<_, _>>(),
+        "baddies": results.baddies.iter().filter(|failure| matches(&failure.url)).collect::<Vec<_>>(),
+        "feeds": results.feeds.iter().filter(|(url, _)| matches(url)).collect::<BTreeMap<_, _>>(),
+    });
+    println!("{}", serde_json::to_string_pretty(&found).unwrap());
+}
+
+/*
+serde to serialize data
+pull request 
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockSite;
+
+    //the settings of a plain crawl, with no delay between requests
+    fn test_config() -> CrawlConfig {
+        CrawlConfig {
+            limit: None,
+            excerpt_len: Some(100),
+            structured_data: true,
+            html_diagnostics: false,
+            finance_quotes: false,
+            articles: true,
+            follow_feeds: false,
+            seed_sitemap_hosts: false,
+            min_image_dim: 2,
+            image_filter: ImageFilter::new([], [], [], 0, 0),
+            srcset: SrcsetPolicy::Largest,
+            etiquette: Etiquette::preset(Preset::Aggressive),
+            bandwidth: None,
+            max_bytes: None,
+            record_tls: false,
+            require_https: false,
+            extract_rules: ExtractRules::default(),
+            soft_deadline: None,
+            hard_deadline: None,
+            blacklist: Blacklist::default(),
+            fingerprint: UrlFingerprint::new(Vec::<String>::new()),
+            consent: ConsentMode::Mark,
+            auth: Auth::default(),
+            depth_rules: DepthRules::default(),
+            parser_threads: 1,
+            status_policy: StatusPolicy::default(),
+            focus: Focus::default(),
+        }
+    }
+
+    //fetch url the way the crawl does and hand back the page, parsed
+    fn fetch_and_parse(url: &str, config: &CrawlConfig) -> ParsedPage {
+        let mut audit = Audit::new(Duration::ZERO);
+        let Fetch::Page(res) = http_requester(url, 1, &Client::new(), &mut audit, config, None) else {
+            panic!("{} wasn't fetched", url);
+        };
+        let job = FetchedJob { lease_id: 0, url: url.to_string(), request_id: "test-000001".to_string(), depth: Depth::default(), res, consent_wall: false };
+        PageParser::new(config).parse(job)
+    }
+
+    #[test]
+    fn fetches_and_extracts_a_fixture_page() {
+        let mut site = MockSite::new();
+        site.page("/", "home.html");
+        let page = fetch_and_parse(&site.url("/"), &test_config());
+
+        //only yahoo links are followed, and not the rel=nofollow ones
+        //a protocol-relative link takes the scheme the page came over
+        assert_eq!(page.links, ["https://mail.yahoo.com/", "https://news.yahoo.com/", "https://yahoo.com/sports", "http://weather.yahoo.com/", "https://finance.yahoo.com/quote/AAPL/"]);
+        //images off the Yahoo CDNs are turned down by the default filter
+        assert!(page.images.contains(&"https://s.yimg.com/ny/api/res/1.2/lead.jpg".to_string()));
+        assert!(page.images.contains(&"https://s.yimg.com/cv/hero.jpg".to_string()));
+        assert_eq!(page.skipped_images, [(site.url("/images/logo.png"), Skip::NotIncluded)]);
+        //the inline spacer gif is recorded, not fetched
+        assert!(page.images.iter().chain(page.skipped_images.iter().map(|(image, _)| image)).all(|image| !image.starts_with("data:")));
+        assert_eq!(page.inline_images, [InlineImage { media_type: "image/gif".to_string(), size: 42 }]);
+        assert_eq!(page.metadata.unwrap().open_graph.get("og:title").map(String::as_str), Some("Yahoo"));
+        assert!(page.text.unwrap().excerpt.starts_with("Stocks rallied on Tuesday"));
+        assert_eq!(page.article, None);
+    }
+
+    #[test]
+    fn extracts_articles_and_feeds_from_a_fixture_page() {
+        let mut site = MockSite::new();
+        site.page("/news/markets-rally.html", "article.html");
+        let page = fetch_and_parse(&site.url("/news/markets-rally.html"), &test_config());
+
+        let article = page.article.unwrap();
+        assert_eq!(article.headline.as_deref(), Some("Markets rally as inflation cools"));
+        assert_eq!(article.authors, ["Jane Doe"]);
+        assert_eq!(article.word_count, 27);
+        assert_eq!(page.feeds.len(), 1);
+        assert_eq!(page.feeds[0].0, "https://finance.yahoo.com/news/rss");
+        //the target of the search redirector is queued, not the redirector
+        assert_eq!(page.links, ["https://finance.yahoo.com/markets/", "https://news.yahoo.com/cpi-report-what-it-means.html"]);
+        assert_eq!(page.redirects.len(), 2);
+        assert!(page.redirects.iter().all(|(redirector, _)| !page.links.contains(redirector)));
+        //the reddit out-link leaves Yahoo, so its target is only recorded
+        assert_eq!(page.externals, ["https://www.bls.gov/cpi/"]);
+    }
+
+    #[test]
+    fn holds_off_a_host_out_of_requests() {
+        let mut site = MockSite::new();
+        let config = test_config();
+        let mut audit = Audit::new(Duration::ZERO);
+        let exhausted = site.status("/", 200, &[("X-RateLimit-Limit", "10"), ("X-RateLimit-Remaining", "0"), ("X-RateLimit-Reset", "1")]);
+        assert!(matches!(http_requester(&site.url("/"), 1, &Client::new(), &mut audit, &config, None), Fetch::Page(_)));
+        exhausted.assert();
+
+        //the next request to the host waits for the window to reset
+        site.page("/next", "home.html");
+        let asked = Instant::now();
+        assert!(matches!(http_requester(&site.url("/next"), 1, &Client::new(), &mut audit, &config, None), Fetch::Page(_)));
+        assert!(asked.elapsed() >= Duration::from_millis(900));
+        let limits = audit.summary(&HostAliases::default())["127.0.0.1"].rate_limit.clone().unwrap();
+        assert_eq!((limits.limit, limits.lowest_remaining, limits.held_off), (Some(10), Some(0), 1));
+    }
+
+    #[test]
+    fn retries_by_the_status_policy() {
+        let mut site = MockSite::new();
+        let config = test_config();
+        let mut audit = Audit::new(Duration::ZERO);
+
+        //throttled once, then served
+        let throttled = site.status("/busy", 503, &[("Retry-After", "0")]);
+        site.page("/busy", "home.html");
+        assert!(matches!(http_requester(&site.url("/busy"), 1, &Client::new(), &mut audit, &config, None), Fetch::Page(_)));
+        throttled.assert();
+        let summary = audit.summary(&HostAliases::default());
+        assert_eq!(summary["127.0.0.1"].throttled.len(), 1);
+
+        //three tries and no more
+        let down = site.server().mock("GET", "/down").with_status(500).with_header("Retry-After", "0").expect(3).create();
+        assert!(matches!(http_requester(&site.url("/down"), 1, &Client::new(), &mut audit, &config, None), Fetch::Failed));
+        down.assert();
+
+        //not found is given up on straight away, a rule can blacklist the host instead
+        let gone = site.server().mock("GET", "/gone").with_status(410).expect(2).create();
+        assert!(matches!(http_requester(&site.url("/gone"), 1, &Client::new(), &mut audit, &config, None), Fetch::Failed));
+        let config = CrawlConfig { status_policy: StatusPolicy::new(["410=blacklist-host"]).unwrap(), ..test_config() };
+        assert!(matches!(http_requester(&site.url("/gone"), 1, &Client::new(), &mut audit, &config, None), Fetch::HostBlacklisted));
+        gone.assert();
+    }
+
+    #[test]
+    fn reads_throttled_pages_for_longer_than_the_fetch_timeout() {
+        let mut site = MockSite::new();
+        let config = CrawlConfig { bandwidth: Some(Bandwidth::new(10_000)), ..test_config() };
+        let mut audit = Audit::new(Duration::ZERO);
+        //the first 10KB come out of the full bucket, the other 35KB take 3.5s at 10KB/s
+        site.server().mock("GET", "/big").with_header("Content-Type", "text/html").with_body("a".repeat(45_000)).create();
+        let started = Instant::now();
+        let Fetch::Page(page) = http_requester(&site.url("/big"), 1, &Client::new(), &mut audit, &config, None) else {
+            panic!("the throttled page wasn't fetched");
+        };
+        assert_eq!(page.body.len(), 45_000);
+        assert!(started.elapsed() >= Duration::from_secs(3));
+    }
+
+    #[test]
+    fn asks_conditionally_with_the_previous_validators() {
+        let mut site = MockSite::new();
+        let config = test_config();
+        let mut audit = Audit::new(Duration::ZERO);
+        site.server().mock("GET", "/").match_header("If-None-Match", mockito::Matcher::Missing)
+            .with_header("ETag", "\"v2\"").with_body(mock::fixture("home.html")).create();
+        site.server().mock("GET", "/").match_header("If-None-Match", "\"v1\"").with_status(304).create();
+
+        let mut previous = Page::new(10, Vec::new(), Vec::new());
+        previous.etag = Some("\"v1\"".to_string());
+        assert!(matches!(http_requester(&site.url("/"), 1, &Client::new(), &mut audit, &config, Some(&previous)), Fetch::NotModified(_)));
+        let Fetch::Page(res) = http_requester(&site.url("/"), 1, &Client::new(), &mut audit, &config, None) else {
+            panic!("no page without validators");
+        };
+        assert_eq!(res.etag.as_deref(), Some("\"v2\""));
+    }
+
+    //saved Yahoo pages in fixtures/ and the URL each was fetched from
+    const GOLDEN_PAGES: [(&str, &str); 4] = [
+        ("home", "https://www.yahoo.com/"),
+        ("article", "https://news.yahoo.com/markets-rally-as-inflation-cools.html"),
+        ("finance_quote", "https://finance.yahoo.com/quote/AAPL/"),
+        ("consent_wall", "https://consent.yahoo.com/v2/collectConsent?sessionId=3_cc-session_abc123"),
+    ];
+
+    //everything the crawl takes from a page, as the golden files have it
+    fn extraction(page: &ParsedPage) -> serde_json::Value {
+        json!({
+            "consent_wall": page.consent_wall,
+            "noindex": page.robots.noindex,
+            "nofollow": page.robots.nofollow,
+            "links": page.links,
+            "redirects": page.redirects,
+            "externals": page.externals,
+            "inline_images": page.inline_images,
+            "responsive_images": page.responsive_images,
+            "images": page.images,
+            "skipped_images": page.skipped_images.iter().map(|(url, skip)| json!({"url": url, "reason": skip.to_string()})).collect::<Vec<_>>(),
+            "stylesheets": page.stylesheets,
+            "text": page.text,
+            "metadata": page.metadata,
+            "diagnostics": page.diagnostics,
+            "quote": page.quote,
+            "article": page.article,
+            "feeds": page.feeds.iter().map(|(url, feed)| json!({"url": url, "feed": feed})).collect::<Vec<_>>(),
+        })
+    }
+
+    #[test]
+    fn extraction_matches_the_golden_files() {
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        let config = CrawlConfig { finance_quotes: true, html_diagnostics: true, ..test_config() };
+        let mut differing = Vec::new();
+        for (name, url) in GOLDEN_PAGES {
+            let body = mock::fixture(&format!("{}.html", name));
+            let consent_wall = consent::is_consent_page(url, &body);
+            let res = FetchedPage {
+                url: url.to_string(),
+                body,
+                content_type: Some("text/html; charset=utf-8".to_string()),
+                robots: RobotsDirectives::default(),
+                protocol: "HTTP/2.0".to_string(),
+                etag: None,
+                last_modified: None,
+                undecodable: false,
+            };
+            let job = FetchedJob { lease_id: 0, url: url.to_string(), request_id: "test-000001".to_string(), depth: Depth::default(), res, consent_wall };
+            let found = extraction(&PageParser::new(&config).parse(job));
+            let golden_name = format!("{}.golden.json", name);
+            if update {
+                std::fs::write(mock::fixture_path(&golden_name), serde_json::to_string_pretty(&found).unwrap() + "\n").unwrap();
+                continue;
+            }
+            let golden: serde_json::Value = serde_json::from_str(&mock::fixture(&golden_name)).unwrap();
+            if found != golden {
+                differing.push(format!("{} now extracts:\n{}", name, serde_json::to_string_pretty(&found).unwrap()));
+            }
+        }
+        assert!(differing.is_empty(), "extraction differs from the golden files, rerun with UPDATE_GOLDEN=1 if that's intended\n{}", differing.join("\n"));
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("2MB"), Some(2_000_000));
+        assert_eq!(parse_size("500KiB"), Some(512_000));
+        assert_eq!(parse_size("1.5 gb"), Some(1_500_000_000));
+        assert_eq!(parse_size("1048576"), Some(1_048_576));
+        assert_eq!(parse_size("0"), None);
+        assert_eq!(parse_size("lots"), None);
+        assert_eq!(parse_size("2MB/h"), None);
+    }
+
+    #[test]
+    fn filters_urls_without_copying_the_ones_kept_as_is() {
+        assert!(matches!(filter_url("https://news.yahoo.com/world"), Some(Cow::Borrowed("https://news.yahoo.com/world"))));
+        assert_eq!(filter_url("https://NEWS.Yahoo.com").as_deref(), Some("https://news.yahoo.com/"));
+        assert_eq!(filter_url("/sports").as_deref(), Some("https://yahoo.com/sports"));
+        assert_eq!(filter_url("https://www.facebook.com/yahoo"), None);
+        assert_eq!(filter_url("https://www.facebook.com/?next=yahoo.com"), None);
+        assert_eq!(filter_url("https://beap.gemini.yahoo.com/mbclk"), None);
+        assert_eq!(filter_url("javascript:void(0)"), None);
+        //not a path on yahoo.com, only absolute_link can make sense of it
+        assert_eq!(filter_url("//sports.yahoo.com/nba/"), None);
+        assert_eq!(absolute_link("//sports.yahoo.com/nba/", "http://www.yahoo.com/"), "http://sports.yahoo.com/nba/");
+        assert_eq!(absolute_link("//sports.yahoo.com/nba/", "https://www.yahoo.com/"), "https://sports.yahoo.com/nba/");
+        assert!(matches!(absolute_link("/sports", "https://www.yahoo.com/"), Cow::Borrowed("/sports")));
+    }
+
+    #[test]
+    fn finds_images_in_css_and_linked_stylesheets() {
+        let document = Document::from(r#"<html><head>
+            <link rel="stylesheet" href="/css/main.css"><link rel="icon" href="/favicon.ico">
+            <style>.hero { background-image: url(https://s.yimg.com/hero.jpg) }</style>
+            </head><body>
+            <div style="background: url('//s.yimg.com/card.png') center"></div>
+            <img src="https://s.yimg.com/hero.jpg"><img src="https://example.com/pixel.gif">
+            </body></html>"#);
+        let filter = ImageFilter::new([], [], [], 2, 2);
+        let (images, skipped, _) = extract_images(&document, "https://www.yahoo.com/", &filter, SrcsetPolicy::Largest);
+        assert_eq!(images, ["https://s.yimg.com/hero.jpg", "https://s.yimg.com/card.png"]);
+        assert_eq!(skipped, [("https://example.com/pixel.gif".to_string(), Skip::NotIncluded)]);
+        assert_eq!(extract_stylesheets(&document, "https://www.yahoo.com/"), ["https://www.yahoo.com/css/main.css"]);
+    }
+}
@@ -0,0 +1,126 @@
+//! How a run ended, for whatever wraps the crawler: the process exit code,
+//! and run-manifest.json describing the run in full.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use serde_json::Value;
+use crate::results::SCHEMA_VERSION;
+
+/// How a run ended. Each has its own exit code:
+///
+/// | code | outcome                                                |
+/// |------|--------------------------------------------------------|
+/// | 0    | success                                                |
+/// | 1    | completed with errors, some URLs failed                |
+/// | 2    | config error, bad arguments or an unusable input file  |
+/// | 3    | aborted, stopped before the crawl was done             |
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Success,
+    CompletedWithErrors,
+    ConfigError,
+    Aborted,
+}
+
+impl Outcome {
+    pub fn code(self) -> u8 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::CompletedWithErrors => 1,
+            Outcome::ConfigError => 2,
+            Outcome::Aborted => 3,
+        }
+    }
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        ExitCode::from(outcome.code())
+    }
+}
+
+/// The help text listing the exit codes
+pub const EXIT_CODES_HELP: &str = "EXIT CODES:
+    0    success
+    1    completed, but some URLs failed
+    2    config error, nothing was crawled
+    3    aborted before the crawl was done";
+
+/// Seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0)
+}
+
+/// What run-manifest.json holds
+#[derive(Serialize, Debug)]
+pub struct Manifest {
+    pub outcome: Outcome,
+    pub exit_code: u8,
+    /// Version of the crawler that ran
+    pub version: &'static str,
+    /// Version of the layout of the output files
+    pub schema_version: u64,
+    pub seed: String,
+    /// The --tag the run was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// The command line as given
+    pub arguments: Vec<String>,
+    /// The settings the crawl ran with, defaults filled in
+    pub config: BTreeMap<&'static str, Value>,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    pub ended_at: u64,
+    pub pages: usize,
+    pub images: usize,
+    pub failures: usize,
+    /// Every file the run wrote, by what it holds
+    pub outputs: BTreeMap<&'static str, PathBuf>,
+}
+
+impl Manifest {
+    pub fn new(outcome: Outcome, seed: &str, started_at: u64) -> Self {
+        Self {
+            outcome,
+            exit_code: outcome.code(),
+            version: env!("CARGO_PKG_VERSION"),
+            schema_version: SCHEMA_VERSION,
+            seed: seed.to_string(),
+            tag: None,
+            arguments: std::env::args().skip(1).collect(),
+            config: BTreeMap::new(),
+            started_at,
+            ended_at: now(),
+            pages: 0,
+            images: 0,
+            failures: 0,
+            outputs: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outcomes_have_distinct_codes() {
+        let outcomes = [Outcome::Success, Outcome::CompletedWithErrors, Outcome::ConfigError, Outcome::Aborted];
+        let mut codes: Vec<u8> = outcomes.iter().map(|outcome| outcome.code()).collect();
+        codes.dedup();
+        assert_eq!(codes, [0, 1, 2, 3]);
+        for outcome in outcomes {
+            assert!(EXIT_CODES_HELP.contains(&format!("    {}    ", outcome.code())));
+        }
+
+        let manifest = Manifest::new(Outcome::CompletedWithErrors, "https://www.yahoo.com/", 1);
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(json["outcome"], "completed-with-errors");
+        assert_eq!(json["exit_code"], 1);
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    }
+}
@@ -0,0 +1,61 @@
+//! Test harness for the fetch path: a mock site on a local port answering
+//! with canned pages from fixtures/, or whatever status and headers a test
+//! asks for, so fetching, retries and extraction are tested without the
+//! live internet.
+//!
+//! fixtures/ also keeps a golden file next to each saved Yahoo page, with
+//! exactly what the crawl extracts from it. After a deliberate change to the
+//! extraction, `UPDATE_GOLDEN=1 cargo test golden` writes them anew.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use mockito::{Mock, Server, ServerGuard};
+
+/// A local server standing in for the sites a crawl visits
+pub struct MockSite {
+    server: ServerGuard,
+}
+
+impl MockSite {
+    pub fn new() -> Self {
+        Self { server: Server::new() }
+    }
+
+    /// The absolute URL of path on the site
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.server.url(), path)
+    }
+
+    /// Serve a fixture as HTML at path, every time it's asked for
+    pub fn page(&mut self, path: &str, fixture_name: &str) -> Mock {
+        self.server.mock("GET", path)
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .with_body(fixture(fixture_name))
+            .create()
+    }
+
+    /// Answer the next request for path with status and these headers, and
+    /// nothing else. Answers queued for the same path are given in turn.
+    pub fn status(&mut self, path: &str, status: usize, headers: &[(&str, &str)]) -> Mock {
+        headers.iter()
+            .fold(self.server.mock("GET", path).with_status(status), |mock, &(name, value)| mock.with_header(name, value))
+            .expect(1)
+            .create()
+    }
+
+    /// The server itself, for mocks the helpers don't cover
+    pub fn server(&mut self) -> &mut ServerGuard {
+        &mut self.server
+    }
+}
+
+/// Where a fixture is kept
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)
+}
+
+/// A canned page from fixtures/
+pub fn fixture(name: &str) -> String {
+    let path = fixture_path(name);
+    fs::read_to_string(&path).unwrap_or_else(|e| panic!("Could not read fixture {}: {}", path.display(), e))
+}
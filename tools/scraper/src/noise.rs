@@ -0,0 +1,77 @@
+//! Keeping repeated errors from drowning out the rest of the output. A dead
+//! host fails every request sent its way with the same error, so after the
+//! first few times an error is only counted, and the counts are summarized
+//! once the crawl is over.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use url::Url;
+
+/// How many times the same error on the same host is shown before it is only counted
+pub const SHOWN: u64 = 3;
+
+/// Times each error was seen, by its key
+static REPEATS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+/// Count an error about url and return the line to show for it, None once
+/// it has been shown enough times
+pub fn note(url: &str, message: &str) -> Option<String> {
+    let mut repeats = REPEATS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let count = repeats.entry(key(url, message)).or_default();
+    *count += 1;
+    match *count {
+        n if n < SHOWN => Some(message.to_string()),
+        SHOWN => Some(format!("{} (again, further repeats on this host are only counted)", message)),
+        _ => None,
+    }
+}
+
+/// The errors seen more often than they were shown, most frequent first
+pub fn repeated() -> Vec<(String, u64)> {
+    let repeats = REPEATS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut repeated: Vec<(String, u64)> = repeats.iter()
+        .filter(|(_, &count)| count > SHOWN)
+        .map(|(key, &count)| (key.clone(), count))
+        .collect();
+    repeated.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    repeated
+}
+
+//the same error on the same host, whatever the path: URLs in the message are cut down to their origin
+fn key(url: &str, message: &str) -> String {
+    let host = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_ascii_lowercase)).unwrap_or_default();
+    let message: Vec<String> = message.split(' ')
+        .map(|word| {
+            let trimmed = word.trim_matches(|c: char| matches!(c, '(' | ')' | '"' | '\'' | ',' | ':' | '<' | '>'));
+            match Url::parse(trimmed) {
+                Ok(url) if url.has_host() => word.replace(trimmed, &url.origin().ascii_serialization()),
+                _ => word.to_string(),
+            }
+        })
+        .collect();
+    format!("{}: {}", host, message.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_ignore_paths() {
+        assert_eq!(
+            key("https://dead.yahoo.com/a", "Fail! error sending request for url (https://dead.yahoo.com/a?x=1): operation timed out"),
+            key("https://dead.yahoo.com/b", "Fail! error sending request for url (https://dead.yahoo.com/b): operation timed out"),
+        );
+        assert_ne!(key("https://dead.yahoo.com/a", "Fail! 404 Not Found"), key("https://news.yahoo.com/a", "Fail! 404 Not Found"));
+        assert_eq!(key("https://news.yahoo.com/a", "Fail! 404 Not Found"), "news.yahoo.com: Fail! 404 Not Found");
+    }
+
+    #[test]
+    fn shows_the_first_few_and_counts_the_rest() {
+        let message = "Fail! 503 Service Unavailable";
+        let shown: Vec<_> = (0..10).filter_map(|i| note(&format!("https://noise-test.yahoo.com/{}", i), message)).collect();
+        assert_eq!(shown.len() as u64, SHOWN);
+        assert!(shown.last().unwrap().starts_with(message));
+        assert!(repeated().contains(&("noise-test.yahoo.com: Fail! 503 Service Unavailable".to_string(), 10)));
+    }
+}
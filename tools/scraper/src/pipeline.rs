@@ -0,0 +1,119 @@
+//! A pool of threads working through jobs handed to it over a channel.
+//!
+//! Parsing a large page with select is CPU-bound and takes longer than
+//! fetching the next one, so the crawl hands fetched pages to a pipeline of
+//! parser threads and keeps fetching while they work. Results come back over
+//! a second channel in whatever order the threads finish them.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Threads running `work` on every job submitted
+pub struct Pipeline<I, O> {
+    jobs: Option<Sender<I>>,
+    results: Receiver<thread::Result<O>>,
+    workers: Vec<JoinHandle<()>>,
+    in_flight: usize,
+}
+
+impl<I: Send + 'static, O: Send + 'static> Pipeline<I, O> {
+    /// Start `threads` threads, at least one, waiting for jobs
+    pub fn new(threads: usize, work: impl Fn(I) -> O + Send + Sync + 'static) -> Self {
+        let (jobs, queue) = mpsc::channel::<I>();
+        let queue = Arc::new(Mutex::new(queue));
+        let (done, results) = mpsc::channel();
+        let work = Arc::new(work);
+        let workers = (0..threads.max(1)).map(|_| {
+            let queue = Arc::clone(&queue);
+            let done = done.clone();
+            let work = Arc::clone(&work);
+            thread::spawn(move || loop {
+                //the lock is only held while waiting, not while working
+                let Ok(job) = queue.lock().unwrap().recv() else {
+                    break;
+                };
+                //a panicking job is passed on to whoever waits for its result, not lost with the thread
+                if done.send(panic::catch_unwind(AssertUnwindSafe(|| work(job)))).is_err() {
+                    break;
+                }
+            })
+        }).collect();
+        Self { jobs: Some(jobs), results, workers, in_flight: 0 }
+    }
+
+    /// Number of threads working on jobs
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Jobs submitted whose results haven't been taken yet
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Whether enough jobs are waiting to keep every thread busy, so
+    /// submitting more would only pile them up in memory
+    pub fn is_full(&self) -> bool {
+        self.in_flight >= 2 * self.threads()
+    }
+
+    /// Queue a job for the next free thread
+    pub fn submit(&mut self, job: I) {
+        self.jobs.as_ref().unwrap().send(job).expect("pipeline threads are gone");
+        self.in_flight += 1;
+    }
+
+    /// A finished result if there is one, without waiting
+    pub fn try_recv(&mut self) -> Option<O> {
+        let result = self.results.try_recv().ok()?;
+        self.finish(result)
+    }
+
+    /// Wait for the next result. None if no job is in flight.
+    pub fn recv(&mut self) -> Option<O> {
+        if self.in_flight == 0 {
+            return None;
+        }
+        let result = self.results.recv().expect("pipeline threads are gone");
+        self.finish(result)
+    }
+
+    fn finish(&mut self, result: thread::Result<O>) -> Option<O> {
+        self.in_flight -= 1;
+        match result {
+            Ok(output) => Some(output),
+            Err(e) => panic::resume_unwind(e),
+        }
+    }
+}
+
+impl<I, O> Drop for Pipeline<I, O> {
+    fn drop(&mut self) {
+        //closing the job channel lets the threads run out of work and exit
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn works_through_every_job() {
+        let mut pipeline = Pipeline::new(3, |n: u32| n * n);
+        assert_eq!(pipeline.threads(), 3);
+        for n in 0..10 {
+            pipeline.submit(n);
+        }
+        assert_eq!(pipeline.in_flight(), 10);
+        let mut squares: Vec<u32> = std::iter::from_fn(|| pipeline.recv()).collect();
+        squares.sort();
+        assert_eq!(squares, (0..10).map(|n| n * n).collect::<Vec<_>>());
+        assert_eq!(pipeline.recv(), None);
+    }
+}
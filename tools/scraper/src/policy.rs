@@ -0,0 +1,153 @@
+//! What to do about each HTTP status a page fetch can end in.
+//!
+//! A status is looked up by its exact code first, then by its class (4xx,
+//! 5xx, ...). The defaults record 2xx pages, follow redirects the client
+//! didn't, give up on 404 and 410 straight away and retry 429 and 5xx after
+//! backing off. Rules given on the command line, ie: `503=skip`, go first.
+
+use std::time::{Duration, SystemTime};
+use reqwest::StatusCode;
+use crate::rate_limit::{self, MAX_HOLD_OFF};
+
+/// What to do with a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusAction {
+    /// Record the response as the page
+    Accept,
+    /// Back off and ask again, up to the retry limit
+    Retry,
+    /// Record the URL as failed without asking again
+    Skip,
+    /// Record the redirect and queue where it points
+    Follow,
+    /// Record the URL as failed and fetch nothing more from its host
+    BlacklistHost,
+}
+
+impl StatusAction {
+    pub fn from_name(name: &str) -> Option<StatusAction> {
+        match name {
+            "accept" => Some(StatusAction::Accept),
+            "retry" => Some(StatusAction::Retry),
+            "skip" => Some(StatusAction::Skip),
+            "follow" => Some(StatusAction::Follow),
+            "blacklist-host" => Some(StatusAction::BlacklistHost),
+            _ => None,
+        }
+    }
+}
+
+/// Which statuses a rule covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StatusMatch {
+    /// One status code, ie: 410
+    Code(u16),
+    /// Every code of a class, ie: 5 for 5xx
+    Class(u16),
+}
+
+/// The status policy table, first matching rule wins
+#[derive(Debug, Clone)]
+pub struct StatusPolicy {
+    rules: Vec<(StatusMatch, StatusAction)>,
+}
+
+impl Default for StatusPolicy {
+    fn default() -> Self {
+        use StatusAction::*;
+        use StatusMatch::*;
+        Self {
+            rules: vec![
+                (Code(404), Skip),
+                (Code(410), Skip),
+                (Code(429), Retry),
+                (Class(2), Accept),
+                (Class(3), Follow),
+                (Class(4), Skip),
+                (Class(5), Retry),
+            ],
+        }
+    }
+}
+
+impl StatusPolicy {
+    /// The default table with `rules` written as status=action in front,
+    /// ie: 503=skip or 4xx=accept
+    pub fn new<'a>(rules: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut policy = Self { rules: Vec::new() };
+        for rule in rules {
+            policy.rules.push(parse_rule(rule)?);
+        }
+        policy.rules.extend(Self::default().rules);
+        Ok(policy)
+    }
+
+    /// The action for a status. Codes outside 1xx-5xx are skipped.
+    pub fn action(&self, status: StatusCode) -> StatusAction {
+        let code = status.as_u16();
+        //exact codes beat classes, whichever order they were given in
+        let exact = self.rules.iter().find(|(on, _)| *on == StatusMatch::Code(code));
+        let class = || self.rules.iter().find(|(on, _)| *on == StatusMatch::Class(code / 100));
+        exact.or_else(class).map_or(StatusAction::Skip, |&(_, action)| action)
+    }
+}
+
+/// How long to wait before retry number `attempt`, counting from 1. A
+/// Retry-After header, in seconds or as a date, is honored up to a minute,
+/// otherwise the wait doubles from one second.
+pub fn backoff(attempt: u32, retry_after: Option<&str>) -> Duration {
+    match retry_after.and_then(|value| rate_limit::retry_after(value, SystemTime::now())) {
+        Some(wait) => wait.min(MAX_HOLD_OFF),
+        None => Duration::from_secs(1 << attempt.saturating_sub(1).min(6)),
+    }
+}
+
+//parse a "410=skip" or "5xx=retry" rule
+fn parse_rule(rule: &str) -> Result<(StatusMatch, StatusAction), String> {
+    let invalid = || format!("--on-status is not status=action: {}", rule);
+    let (status, action) = rule.split_once('=').ok_or_else(invalid)?;
+    let action = StatusAction::from_name(action)
+        .ok_or_else(|| format!("Unknown status action {}, expected accept, retry, skip, follow or blacklist-host", action))?;
+    let on = match status.to_ascii_lowercase().strip_suffix("xx") {
+        Some(class) => StatusMatch::Class(class.parse().map_err(|_| invalid())?),
+        None => StatusMatch::Code(status.parse().map_err(|_| invalid())?),
+    };
+    Ok((on, action))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(code: u16) -> StatusCode {
+        StatusCode::from_u16(code).unwrap()
+    }
+
+    #[test]
+    fn looks_up_codes_before_classes() {
+        let policy = StatusPolicy::default();
+        assert_eq!(policy.action(status(200)), StatusAction::Accept);
+        assert_eq!(policy.action(status(301)), StatusAction::Follow);
+        assert_eq!(policy.action(status(410)), StatusAction::Skip);
+        assert_eq!(policy.action(status(429)), StatusAction::Retry);
+        assert_eq!(policy.action(status(503)), StatusAction::Retry);
+
+        let policy = StatusPolicy::new(["5xx=skip", "503=retry", "403=blacklist-host"]).unwrap();
+        assert_eq!(policy.action(status(500)), StatusAction::Skip);
+        assert_eq!(policy.action(status(503)), StatusAction::Retry);
+        assert_eq!(policy.action(status(403)), StatusAction::BlacklistHost);
+        assert_eq!(policy.action(status(404)), StatusAction::Skip);
+
+        assert!(StatusPolicy::new(["410"]).is_err());
+        assert!(StatusPolicy::new(["410=ignore"]).is_err());
+        assert!(StatusPolicy::new(["5yy=skip"]).is_err());
+    }
+
+    #[test]
+    fn backs_off() {
+        assert_eq!(backoff(1, None), Duration::from_secs(1));
+        assert_eq!(backoff(3, None), Duration::from_secs(4));
+        assert_eq!(backoff(1, Some("120")), MAX_HOLD_OFF);
+        assert_eq!(backoff(2, Some("Wed, 21 Oct 2015 07:28:00 GMT")), Duration::from_secs(2));
+    }
+}
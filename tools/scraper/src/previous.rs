@@ -0,0 +1,146 @@
+//! Recrawling against an earlier crawl, with --previous or when resuming a
+//! crawl whose results are already in the output directory. Every page the
+//! earlier crawl recorded a validator for is asked for conditionally, and a
+//! 304 means it is not downloaded or parsed again: its record, along with the
+//! article and quote extracted from it, is carried forward as it was.
+//!
+//! Its downloaded.json serves as the image cache unless --image-cache names
+//! another, so the images of a carried page are only fetched if they changed.
+//!
+//! Pages of the earlier crawl are handed out once. Whatever is left at the
+//! end was not reached this time and is simply dropped.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use serde::de::DeserializeOwned;
+use crate::article::Article;
+use crate::feeds::Feed;
+use crate::finance::Quote;
+use crate::results::CrawlResults;
+use crate::{Image, Page};
+
+/// The records of an earlier crawl that a page can be carried forward from
+#[derive(Debug, Default)]
+pub struct PreviousCrawl {
+    pages: BTreeMap<String, Page>,
+    images: BTreeMap<String, Image>,
+    feeds: BTreeMap<String, Vec<(String, Feed)>>, //feeds by the page they were found on
+    articles: Option<BTreeMap<String, Article>>, //None when that crawl didn't look for articles
+    quotes: Option<BTreeMap<String, Quote>>,     //None when that crawl didn't look for quotes
+}
+
+/// What an unchanged page had extracted from it last time
+#[derive(Debug)]
+pub struct Carried {
+    pub page: Page,
+    pub article: Option<Article>,
+    pub quote: Option<Quote>,
+    pub feeds: Vec<(String, Feed)>,
+}
+
+impl PreviousCrawl {
+    /// Load the crawl written to `dir`. visited.json is required, the
+    /// images, feeds, articles and quotes are read when they are there.
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let results = CrawlResults::load(dir)?;
+        let mut feeds: BTreeMap<String, Vec<(String, Feed)>> = BTreeMap::new();
+        for (feed_url, mut feed) in results.feeds {
+            //fetched again by this crawl if it follows feeds
+            feed.entries = None;
+            feed.request_id = None;
+            feeds.entry(feed.found_on.clone()).or_default().push((feed_url, feed));
+        }
+        Ok(Self {
+            pages: results.visited,
+            images: results.downloaded,
+            feeds,
+            articles: load_ndjson(&dir.join("articles.ndjson"), |article: &Article| article.url.clone())?,
+            quotes: load_ndjson(&dir.join("finance.ndjson"), |quote: &Quote| quote.url.clone())?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// The images the earlier crawl downloaded, to use as the image cache
+    pub fn take_images(&mut self) -> BTreeMap<String, Image> {
+        std::mem::take(&mut self.images)
+    }
+
+    /// The record to make the request for url conditional on. None when there
+    /// is none with a validator, or when this crawl extracts articles or quotes
+    /// and the earlier one didn't, so carrying the page forward would lose them.
+    pub fn page(&self, url: &str, articles: bool, quotes: bool) -> Option<&Page> {
+        if (articles && self.articles.is_none()) || (quotes && self.quotes.is_none()) {
+            return None;
+        }
+        self.pages.get(url).filter(|page| page.etag.is_some() || page.last_modified.is_some())
+    }
+
+    /// Take what the earlier crawl had for url, once the server said it's unchanged
+    pub fn take(&mut self, url: &str) -> Option<Carried> {
+        let page = self.pages.remove(url)?;
+        Some(Carried {
+            page,
+            article: self.articles.as_mut().and_then(|articles| articles.remove(url)),
+            quote: self.quotes.as_mut().and_then(|quotes| quotes.remove(url)),
+            feeds: self.feeds.remove(url).unwrap_or_default(),
+        })
+    }
+}
+
+//one record per line keyed by its page, later lines replacing earlier ones
+//a torn last line from a crawl that was killed is skipped
+fn load_ndjson<T: DeserializeOwned>(path: &Path, key: impl Fn(&T) -> String) -> Result<Option<BTreeMap<String, T>>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let records = data.lines()
+        .filter_map(|line| serde_json::from_str::<T>(line).ok())
+        .map(|record| (key(&record), record))
+        .collect();
+    Ok(Some(records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_forward_the_earlier_records() {
+        let dir = std::env::temp_dir().join(format!("previous-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("visited.json"), r#"{"schema_version": 1, "data": {
+            "https://news.yahoo.com/a.html": {"size": 10, "links": [], "images": [], "etag": "\"a1\""},
+            "https://news.yahoo.com/b.html": {"size": 20, "links": [], "images": []}
+        }}"#).unwrap();
+        fs::write(dir.join("articles.ndjson"), concat!(
+            r#"{"url":"https://news.yahoo.com/a.html","headline":"Old","word_count":1,"body":"old"}"#, "\n",
+            r#"{"url":"https://news.yahoo.com/a.html","headline":"New","word_count":1,"body":"new"}"#, "\n",
+            r#"{"url":"https://news.yahoo.com/b.ht"#,
+        )).unwrap();
+        fs::write(dir.join("feeds.json"), r#"{"schema_version": 1, "data": {
+            "https://news.yahoo.com/rss": {"kind": "rss", "found_on": "https://news.yahoo.com/a.html", "entries": 40}
+        }}"#).unwrap();
+
+        let mut previous = PreviousCrawl::load(&dir).unwrap();
+        assert_eq!(previous.len(), 2);
+        //only a page with a validator can be asked about
+        assert!(previous.page("https://news.yahoo.com/a.html", true, false).is_some());
+        assert!(previous.page("https://news.yahoo.com/b.html", true, false).is_none());
+        //the earlier crawl has no quotes to carry forward
+        assert!(previous.page("https://news.yahoo.com/a.html", true, true).is_none());
+
+        let carried = previous.take("https://news.yahoo.com/a.html").unwrap();
+        assert_eq!(carried.page.size, 10);
+        assert_eq!(carried.article.unwrap().headline.as_deref(), Some("New"));
+        assert!(carried.quote.is_none());
+        assert_eq!(carried.feeds.len(), 1);
+        assert_eq!(carried.feeds[0].1.entries, None);
+        assert!(previous.take("https://news.yahoo.com/a.html").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
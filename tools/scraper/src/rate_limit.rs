@@ -0,0 +1,105 @@
+//! The limits servers announce on their responses. Retry-After asks us to
+//! wait before the next request, and X-RateLimit-Limit, -Remaining and
+//! -Reset (or the RateLimit-* names of the IETF draft) say how many requests
+//! are left in the current window and when it starts over.
+//!
+//! A host that asks us to wait, or that has no requests left, gets nothing
+//! more until then: the wait is noted in the audit, which holds every request
+//! to the host back until it's over, and shows up in politeness.json.
+
+use std::time::{Duration, SystemTime};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+/// The longest a host's headers make us hold off
+pub const MAX_HOLD_OFF: Duration = Duration::from_secs(60);
+
+//reset values past this are a point in time in epoch seconds, not seconds from now
+const EPOCH_RESET_THRESHOLD: u64 = 1_000_000_000;
+
+/// What one response said about the host's limits
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub limit: Option<u64>,         //requests allowed per window
+    pub remaining: Option<u64>,     //requests left in the current window
+    pub reset: Option<Duration>,    //until the window starts over
+    pub retry_after: Option<Duration>,
+}
+
+impl RateLimit {
+    /// The limits in a response's headers, None when it has none of them
+    pub fn from_headers(headers: &HeaderMap, now: SystemTime) -> Option<Self> {
+        let header = |names: &[&str]| names.iter()
+            .find_map(|name| headers.get(*name))
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim);
+        let number = |names: &[&str]| header(names).and_then(|value| value.parse::<u64>().ok());
+        let limit = Self {
+            limit: number(&["x-ratelimit-limit", "ratelimit-limit"]),
+            remaining: number(&["x-ratelimit-remaining", "ratelimit-remaining"]),
+            reset: number(&["x-ratelimit-reset", "ratelimit-reset"]).map(|reset| match reset {
+                reset if reset >= EPOCH_RESET_THRESHOLD => SystemTime::UNIX_EPOCH + Duration::from_secs(reset),
+                reset => now + Duration::from_secs(reset),
+            }).map(|at| at.duration_since(now).unwrap_or_default()),
+            retry_after: headers.get(RETRY_AFTER).and_then(|value| value.to_str().ok()).and_then(|value| retry_after(value, now)),
+        };
+        (limit != Self::default()).then_some(limit)
+    }
+
+    /// How long to leave the host alone: what Retry-After asks for, or
+    /// until the window resets once no requests are left in it
+    pub fn hold_off(&self) -> Option<Duration> {
+        let exhausted = self.reset.filter(|_| self.remaining == Some(0));
+        self.retry_after.into_iter().chain(exhausted)
+            .max()
+            .filter(|wait| !wait.is_zero())
+            .map(|wait| wait.min(MAX_HOLD_OFF))
+    }
+}
+
+/// The wait a Retry-After value asks for, in seconds or as an HTTP date.
+/// None when it can't be read or the date has already passed.
+pub fn retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => httpdate::parse_http_date(value).ok()?.duration_since(now).ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs.iter().map(|&(name, value)| (name.parse().unwrap(), value.parse().unwrap())).collect()
+    }
+
+    #[test]
+    fn reads_the_limits_a_response_announces() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(RateLimit::from_headers(&headers(&[("content-type", "text/html")]), now), None);
+
+        let limit = RateLimit::from_headers(&headers(&[("X-RateLimit-Limit", "100"), ("X-RateLimit-Remaining", "0"), ("X-RateLimit-Reset", "30")]), now).unwrap();
+        assert_eq!(limit, RateLimit { limit: Some(100), remaining: Some(0), reset: Some(Duration::from_secs(30)), retry_after: None });
+        assert_eq!(limit.hold_off(), Some(Duration::from_secs(30)));
+
+        //a reset in epoch seconds, with requests left there's no need to wait for it
+        let limit = RateLimit::from_headers(&headers(&[("RateLimit-Remaining", "7"), ("RateLimit-Reset", "1700000045")]), now).unwrap();
+        assert_eq!(limit.reset, Some(Duration::from_secs(45)));
+        assert_eq!(limit.hold_off(), None);
+
+        let limit = RateLimit::from_headers(&headers(&[("Retry-After", "Tue, 14 Nov 2023 22:13:27 GMT")]), now).unwrap();
+        assert_eq!(limit.hold_off(), Some(Duration::from_secs(7)));
+        let limit = RateLimit::from_headers(&headers(&[("Retry-After", "600")]), now).unwrap();
+        assert_eq!(limit.hold_off(), Some(MAX_HOLD_OFF));
+    }
+
+    #[test]
+    fn reads_retry_after_in_seconds_or_as_a_date() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(retry_after(" 12 ", now), Some(Duration::from_secs(12)));
+        assert_eq!(retry_after("Tue, 14 Nov 2023 22:13:27 GMT", now), Some(Duration::from_secs(7)));
+        assert_eq!(retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now), None);
+        assert_eq!(retry_after("soon", now), None);
+    }
+}
@@ -0,0 +1,82 @@
+//! Redirector links: URLs that only exist to send the browser somewhere else,
+//! like Yahoo search results (`r.search.yahoo.com/_ylt=.../RU=<target>/RK=...`)
+//! or the out-links of other sites (`out.reddit.com/...?url=<target>`). The
+//! target is right there in the link, so it is queued instead, and the
+//! redirector is never fetched or recorded as a page. Which redirector led
+//! where is kept in redirects.json.
+
+use url::Url;
+use crate::finance::percent_decode;
+
+/// Redirectors carrying their target in a query parameter: host, path prefix, parameter
+const QUERY_REDIRECTORS: [(&str, &str, &str); 7] = [
+    ("out.reddit.com", "/", "url"),
+    ("www.google.com", "/url", "q"),
+    ("l.facebook.com", "/l.php", "u"),
+    ("lm.facebook.com", "/l.php", "u"),
+    ("www.youtube.com", "/redirect", "q"),
+    ("t.umblr.com", "/redirect", "z"),
+    ("slack-redir.net", "/link", "url"),
+];
+
+/// How many redirectors wrapped in one another are unwrapped
+const MAX_NESTING: usize = 4;
+
+/// The target of a redirector link, None when link isn't one or its target can't be read
+pub fn expand(link: &str) -> Option<String> {
+    let mut target = unwrap(link)?;
+    for _ in 1..MAX_NESTING {
+        match unwrap(&target) {
+            Some(inner) => target = inner,
+            None => break,
+        }
+    }
+    Some(target)
+}
+
+//one layer of redirection
+fn unwrap(link: &str) -> Option<String> {
+    let url = Url::parse(link).ok()?;
+    let host = url.host_str()?;
+    let target = if host == "r.search.yahoo.com" || host.ends_with(".r.search.yahoo.com") {
+        //the path is a run of KEY=value segments, RU is the target
+        url.path().split('/')
+            .find_map(|segment| segment.strip_prefix("RU="))
+            .map(percent_decode)?
+    } else {
+        let &(_, _, param) = QUERY_REDIRECTORS.iter()
+            .find(|&&(redirector, path, _)| host == redirector && url.path().starts_with(path))?;
+        url.query_pairs().find(|(name, _)| name == param)?.1.into_owned()
+    };
+    //only a real absolute link counts, anything else stays the redirector's business
+    let target = Url::parse(target.trim()).ok().filter(|target| matches!(target.scheme(), "http" | "https"))?;
+    Some(target.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_target_in_redirector_links() {
+        assert_eq!(
+            expand("https://r.search.yahoo.com/_ylt=AwrFQ;_ylu=Y29sbwNiZjEEcG9z/RV=2/RE=1714575600/RO=10/RU=https%3a%2f%2fnews.yahoo.com%2fmarkets-rally.html%3fguccounter%3d1/RK=2/RS=abc-"),
+            Some("https://news.yahoo.com/markets-rally.html?guccounter=1".to_string()),
+        );
+        assert_eq!(
+            expand("https://out.reddit.com/t3_abc?url=https%3A%2F%2Ffinance.yahoo.com%2Fquote%2FAAPL%2F&token=x"),
+            Some("https://finance.yahoo.com/quote/AAPL/".to_string()),
+        );
+        //a redirector wrapped in another
+        assert_eq!(
+            expand("https://www.google.com/url?q=https%3A%2F%2Fr.search.yahoo.com%2F_ylt%3Dx%2FRU%3Dhttps%253a%252f%252fsports.yahoo.com%252f%2FRK%3D2&sa=D"),
+            Some("https://sports.yahoo.com/".to_string()),
+        );
+
+        //not redirectors, or no target to be had
+        assert_eq!(expand("https://news.yahoo.com/world?url=https%3A%2F%2Fexample.com"), None);
+        assert_eq!(expand("https://r.search.yahoo.com/_ylt=AwrFQ/RV=2/RK=2"), None);
+        assert_eq!(expand("https://l.facebook.com/l.php?u=javascript%3Aalert(1)"), None);
+        assert_eq!(expand("/sports"), None);
+    }
+}
@@ -0,0 +1,305 @@
+//! A self-contained HTML report of a crawl for readers who would rather not
+//! dig through JSON. Charts are inline SVG, so the file needs nothing but a
+//! browser to open.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use crate::aliases::HostAliases;
+use crate::results::{self, CrawlResults};
+
+/// Width of the bar area of a chart, in pixels
+const CHART_WIDTH: usize = 480;
+/// Height of one bar and its gap
+const BAR_HEIGHT: usize = 22;
+/// Width reserved for the labels left of the bars
+const LABEL_WIDTH: usize = 320;
+/// Labels longer than this many characters are shortened in charts
+const LABEL_CHARS: usize = 48;
+
+/// Upper bounds of the image size buckets, in bytes
+const IMAGE_SIZE_BUCKETS: [(usize, &str); 4] = [
+    (1_000, "under 1 KB"),
+    (10_000, "1-10 KB"),
+    (100_000, "10-100 KB"),
+    (1_000_000, "100 KB-1 MB"),
+];
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em auto;max-width:60em;color:#222}\
+h1{border-bottom:2px solid #5f01d1}h2{margin-top:2em;color:#5f01d1}\
+table{border-collapse:collapse}td,th{padding:.2em .8em;text-align:left;border-bottom:1px solid #ddd}\
+td.n{text-align:right;font-variant-numeric:tabular-nums}\
+svg text{font-size:12px;dominant-baseline:middle}.bar{fill:#7b3fe4}.empty{color:#888}";
+
+/// Render the summary of a crawl as one HTML page, hosts grouped into sites by `aliases`
+pub fn html(results: &CrawlResults, aliases: &HostAliases) -> String {
+    let mut out = String::new();
+    let _ = write!(out, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Crawl report</title>\n<style>{}</style>\n</head>\n<body>\n<h1>Crawl report</h1>\n", STYLE);
+
+    let page_bytes: usize = results.visited.values().map(|page| page.size).sum();
+    let image_bytes: usize = results.downloaded.values().map(|image| image.size).sum();
+    let consent_walls = results.visited.values().filter(|page| page.consent_wall).count();
+    let sites = results::pages_by_site(results, aliases);
+    section(&mut out, "Overview");
+    table(&mut out, &["", ""], &[
+        vec!["Pages".to_string(), format!("{} ({} bytes)", results.visited.len(), page_bytes)],
+        vec![if aliases.is_empty() { "Hosts" } else { "Sites" }.to_string(), sites.len().to_string()],
+        vec!["Images".to_string(), format!("{} ({} bytes)", results.downloaded.len(), image_bytes)],
+        vec!["Failed URLs".to_string(), results.baddies.len().to_string()],
+        vec!["Consent walls".to_string(), consent_walls.to_string()],
+        vec!["Feeds".to_string(), results.feeds.len().to_string()],
+    ]);
+
+    section(&mut out, "Pages by section");
+    if aliases.is_empty() {
+        out.push_str("<p>Sections are the hosts pages were fetched from.</p>\n");
+    } else {
+        out.push_str("<p>Sections are the sites pages were fetched from, with the hosts grouped into each in brackets.</p>\n");
+    }
+    bar_chart(&mut out, &results::top(sites.into_iter()));
+
+    //the crawl keeps what it got rather than the status each fetch ended in,
+    //so this is as close to a status breakdown as the records allow
+    section(&mut out, "Fetch outcomes");
+    out.push_str("<p>Status codes aren't recorded per page, so fetches are grouped by what came of them.</p>\n");
+    bar_chart(&mut out, &[
+        ("Page fetched".to_string(), results.visited.len() - consent_walls),
+        ("Consent wall only".to_string(), consent_walls),
+        ("Image fetched".to_string(), results.downloaded.len()),
+        ("Failed".to_string(), results.baddies.len()),
+    ]);
+
+    section(&mut out, "Largest pages");
+    let largest = results::top(results.visited.iter().map(|(url, page)| (url.as_str(), page.size)));
+    table(&mut out, &["Page", "Bytes"], &rows(&largest));
+
+    section(&mut out, "Most linked pages");
+    let mut inbound: HashMap<&str, usize> = HashMap::new();
+    for page in results.visited.values() {
+        for link in &page.links {
+            *inbound.entry(link.as_str()).or_default() += 1;
+        }
+    }
+    let most_linked = results::top(inbound.into_iter());
+    bar_chart(&mut out, &most_linked);
+    table(&mut out, &["Page", "Links to it"], &rows(&most_linked));
+
+    section(&mut out, "Broken links");
+    let broken = broken_links(results);
+    if broken.is_empty() {
+        out.push_str("<p class=\"empty\">No page links to a URL that failed.</p>\n");
+    } else {
+        let broken: Vec<Vec<String>> = broken.iter()
+            .map(|(url, pages)| vec![url.to_string(), pages.len().to_string(), pages.iter().take(3).map(|page| page.as_str()).collect::<Vec<_>>().join(" ")])
+            .collect();
+        table(&mut out, &["Failed URL", "Pages linking", "Linked from"], &broken);
+    }
+
+    section(&mut out, "Outbound links");
+    let externals = results::externals_by_domain(results);
+    if externals.is_empty() {
+        out.push_str("<p class=\"empty\">No page links outside the crawl.</p>\n");
+    } else {
+        out.push_str("<p>Links to other sites, recorded without being followed, by the domain they point to.</p>\n");
+        let domains = results::top(externals.into_iter());
+        bar_chart(&mut out, &domains);
+        table(&mut out, &["Domain", "Links to it"], &rows(&domains));
+    }
+
+    images(&mut out, results);
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn images(out: &mut String, results: &CrawlResults) {
+    section(out, "Images");
+    let index = results::image_index(&results.visited);
+    let failed: HashSet<&str> = results.baddies.iter().map(|failure| failure.url.as_str()).collect();
+    let broken = index.keys().filter(|image| failed.contains(image.as_str())).count();
+    let sized: Vec<(usize, usize)> = results.downloaded.values()
+        .filter_map(|image| Some((image.width?, image.height?)))
+        .collect();
+    let mut stats = vec![
+        vec!["Referenced by pages".to_string(), index.len().to_string()],
+        vec!["Downloaded".to_string(), results.downloaded.len().to_string()],
+        vec!["Failed to download".to_string(), broken.to_string()],
+    ];
+    if !sized.is_empty() {
+        let width = sized.iter().map(|(width, _)| width).sum::<usize>() / sized.len();
+        let height = sized.iter().map(|(_, height)| height).sum::<usize>() / sized.len();
+        stats.push(vec!["Average dimensions".to_string(), format!("{} x {}", width, height)]);
+    }
+    table(out, &["", ""], &stats);
+
+    out.push_str("<h3>Formats</h3>\n");
+    let mut formats: BTreeMap<&str, usize> = BTreeMap::new();
+    for image in results.downloaded.values() {
+        *formats.entry(image.format.as_deref().unwrap_or("unknown")).or_default() += 1;
+    }
+    bar_chart(out, &results::top(formats.into_iter()));
+
+    out.push_str("<h3>Sizes</h3>\n");
+    let mut buckets: Vec<(&str, usize)> = IMAGE_SIZE_BUCKETS.iter().map(|&(_, label)| (label, 0)).collect();
+    buckets.push(("1 MB and over", 0));
+    for image in results.downloaded.values() {
+        let bucket = IMAGE_SIZE_BUCKETS.iter().position(|&(bound, _)| image.size < bound).unwrap_or(IMAGE_SIZE_BUCKETS.len());
+        buckets[bucket].1 += 1;
+    }
+    bar_chart(out, &buckets);
+
+    out.push_str("<h3>Largest images</h3>\n");
+    let largest = results::top(results.downloaded.iter().map(|(url, image)| (url.as_str(), image.size)));
+    table(out, &["Image", "Bytes"], &rows(&largest));
+
+    out.push_str("<h3>Images on the most pages</h3>\n");
+    let widest = results::top(index.iter().map(|(image, pages)| (image.as_str(), pages.len())));
+    table(out, &["Image", "Pages"], &rows(&widest));
+}
+
+//failed URLs that visited pages link to, with the pages linking to each
+fn broken_links(results: &CrawlResults) -> BTreeMap<&str, Vec<&String>> {
+    let failed: HashSet<&str> = results.baddies.iter().map(|failure| failure.url.as_str()).collect();
+    let mut broken: BTreeMap<&str, Vec<&String>> = BTreeMap::new();
+    for (url, page) in &results.visited {
+        for link in page.links.iter().filter(|link| failed.contains(link.as_str())) {
+            let pages = broken.entry(link.as_str()).or_default();
+            if pages.last() != Some(&url) {
+                pages.push(url);
+            }
+        }
+    }
+    broken
+}
+
+fn section(out: &mut String, title: &str) {
+    let _ = writeln!(out, "<h2>{}</h2>", escape(title));
+}
+
+fn rows<K: ToString>(entries: &[(K, usize)]) -> Vec<Vec<String>> {
+    entries.iter().map(|(key, count)| vec![key.to_string(), count.to_string()]).collect()
+}
+
+//a table with numbers right aligned, or a note when there's nothing to show
+fn table(out: &mut String, header: &[&str], rows: &[Vec<String>]) {
+    if rows.is_empty() {
+        out.push_str("<p class=\"empty\">Nothing recorded.</p>\n");
+        return;
+    }
+    out.push_str("<table>\n");
+    if header.iter().any(|title| !title.is_empty()) {
+        out.push_str("<tr>");
+        for title in header {
+            let _ = write!(out, "<th>{}</th>", escape(title));
+        }
+        out.push_str("</tr>\n");
+    }
+    for row in rows {
+        out.push_str("<tr>");
+        for cell in row {
+            match cell.parse::<usize>() {
+                Ok(_) => { let _ = write!(out, "<td class=\"n\">{}</td>", cell); },
+                Err(_) => { let _ = write!(out, "<td>{}</td>", escape(cell)); },
+            }
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+}
+
+//a horizontal bar chart as inline SVG, bars scaled to the largest count
+fn bar_chart<K: ToString>(out: &mut String, bars: &[(K, usize)]) {
+    let max = bars.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    if max == 0 {
+        out.push_str("<p class=\"empty\">Nothing recorded.</p>\n");
+        return;
+    }
+    let width = LABEL_WIDTH + CHART_WIDTH + 80;
+    let height = bars.len() * BAR_HEIGHT;
+    let _ = writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" role=\"img\">", width, height);
+    for (i, (label, count)) in bars.iter().enumerate() {
+        let label = label.to_string();
+        let y = i * BAR_HEIGHT;
+        let bar = count * CHART_WIDTH / max;
+        let short: String = match label.chars().count() > LABEL_CHARS {
+            true => label.chars().take(LABEL_CHARS - 1).chain(['…']).collect(),
+            false => label.clone(),
+        };
+        let _ = writeln!(out, "<g><title>{}: {}</title><text x=\"0\" y=\"{}\">{}</text><rect class=\"bar\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/><text x=\"{}\" y=\"{}\">{}</text></g>",
+            escape(&label), count, y + BAR_HEIGHT / 2, escape(&short),
+            LABEL_WIDTH, y + 3, bar, BAR_HEIGHT - 6,
+            LABEL_WIDTH + bar + 6, y + BAR_HEIGHT / 2, count);
+    }
+    out.push_str("</svg>\n");
+}
+
+fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Failure, Page};
+
+    fn page(size: usize, links: &[&str], images: &[&str]) -> Page {
+        Page::new(size, links.iter().map(|s| s.to_string()).collect(), images.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn reports_broken_links_and_escapes_urls() {
+        let results = CrawlResults {
+            visited: BTreeMap::from([
+                ("https://www.yahoo.com/".to_string(), page(300, &["https://news.yahoo.com/", "https://mail.yahoo.com/?a=1&b=<2>"], &["https://s.yimg.com/gone.png"])),
+                ("https://news.yahoo.com/".to_string(), page(100, &["https://mail.yahoo.com/?a=1&b=<2>"], &[])),
+            ]),
+            baddies: vec![
+                Failure { url: "https://mail.yahoo.com/?a=1&b=<2>".to_string(), request_id: None },
+                Failure { url: "https://s.yimg.com/gone.png".to_string(), request_id: None },
+            ],
+            ..Default::default()
+        };
+        let broken = broken_links(&results);
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken["https://mail.yahoo.com/?a=1&b=<2>"], ["https://news.yahoo.com/", "https://www.yahoo.com/"]);
+
+        let html = html(&results, &HostAliases::default());
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("https://mail.yahoo.com/?a=1&amp;b=&lt;2&gt;"));
+        assert!(!html.contains("b=<2>"));
+        assert!(html.contains("<td>Failed to download</td><td class=\"n\">1</td>"));
+        assert!(html.contains("<svg"));
+        assert!(html.contains("<td>Hosts</td><td class=\"n\">2</td>"));
+
+        //www and news reported as the one site
+        let aliases = HostAliases::parse(["yahoo.com=*.yahoo.com"]).unwrap();
+        let html = super::html(&results, &aliases);
+        assert!(html.contains("<td>Sites</td><td class=\"n\">1</td>"));
+        assert!(html.contains("yahoo.com [news.yahoo.com, www.yahoo.com]"));
+    }
+
+    #[test]
+    fn scales_bars_to_the_largest_count() {
+        let mut out = String::new();
+        bar_chart(&mut out, &[("a", 4), ("b", 1)]);
+        assert!(out.contains(&format!("width=\"{}\"", CHART_WIDTH)));
+        assert!(out.contains(&format!("width=\"{}\"", CHART_WIDTH / 4)));
+
+        let mut out = String::new();
+        bar_chart(&mut out, &[("a", 0)]);
+        assert!(!out.contains("<svg"));
+    }
+}
@@ -0,0 +1,567 @@
+//! Working with finished crawls: loading the JSON a crawl wrote, comparing two
+//! crawls, merging partial crawls into one dataset, checking it against the
+//! types this version reads and writes, and summarizing it.
+//!
+//! Every file is written as `{"schema_version": N, "data": ...}`. Files from
+//! older versions, including the ones from before files carried a version,
+//! are migrated to the current layout as they are read, and files from a
+//! newer version are refused instead of being misread.
+
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::aliases::HostAliases;
+use crate::feeds::Feed;
+use crate::tls::TlsDetails;
+use crate::{Failure, Image, Page};
+
+/// Version of the layout of the files a crawl writes. Bump it whenever a
+/// change to the records would stop older files from loading, and add the
+/// step bringing older files up to date to [`migrate`].
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// How many entries the "top" lists of a report show
+const REPORT_TOP: usize = 10;
+
+/// Certificates expiring within this many days are flagged in the report
+const EXPIRY_WARNING_DAYS: i64 = 30;
+
+/// Everything one crawl writes to its output directory
+#[derive(Debug, Default)]
+pub struct CrawlResults {
+    pub visited: BTreeMap<String, Page>,
+    pub downloaded: BTreeMap<String, Image>,
+    pub baddies: Vec<Failure>,
+    pub tls: BTreeMap<String, Option<TlsDetails>>,
+    pub feeds: BTreeMap<String, Feed>,
+}
+
+impl CrawlResults {
+    /// Load the output files of a crawl from `dir`. Only visited.json is
+    /// required, the other files are treated as empty when missing.
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        Ok(Self {
+            visited: load_visited(&dir.join("visited.json"))?,
+            downloaded: load_optional(&dir.join("downloaded.json"))?,
+            baddies: load_optional(&dir.join("baddies.json"))?,
+            tls: load_optional(&dir.join("tls.json"))?,
+            feeds: load_optional(&dir.join("feeds.json"))?,
+        })
+    }
+
+    /// Write the results to `dir` in the same layout a crawl produces
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+        save_json(&dir.join("visited.json"), &self.visited)?;
+        save_json(&dir.join("downloaded.json"), &self.downloaded)?;
+        save_json(&dir.join("baddies.json"), &self.baddies)?;
+        if !self.tls.is_empty() {
+            save_json(&dir.join("tls.json"), &self.tls)?;
+        }
+        if !self.feeds.is_empty() {
+            save_json(&dir.join("feeds.json"), &self.feeds)?;
+        }
+        save_json(&dir.join("image_pages.json"), &image_index(&self.visited))?;
+        Ok(())
+    }
+
+    /// Fold a later crawl into this one. Pages and images seen by both keep
+    /// the later version, and a failure is dropped once any crawl fetched the
+    /// URL successfully.
+    pub fn merge(&mut self, later: CrawlResults) {
+        self.visited.extend(later.visited);
+        self.downloaded.extend(later.downloaded);
+        self.feeds.extend(later.feeds);
+        for (host, details) in later.tls {
+            //a successful probe beats a failed one, whichever crawl made it
+            if details.is_some() || !self.tls.contains_key(&host) {
+                self.tls.insert(host, details);
+            }
+        }
+        //one record per URL, the later crawl's attempt wins
+        let mut baddies: BTreeMap<String, Failure> = self.baddies.drain(..).map(|failure| (failure.url.clone(), failure)).collect();
+        baddies.extend(later.baddies.into_iter().map(|failure| (failure.url.clone(), failure)));
+        self.baddies = baddies.into_values()
+            .filter(|failure| !self.visited.contains_key(&failure.url) && !self.downloaded.contains_key(&failure.url))
+            .collect();
+    }
+}
+
+/// Load a visited.json written by a crawl
+pub fn load_visited(path: &Path) -> Result<BTreeMap<String, Page>, String> {
+    let data = read_json(path)?;
+    serde_json::from_value(data).map_err(|e| format!("Could not parse {}: {}", path.display(), e))
+}
+
+/// Load a downloaded.json written by a crawl
+pub fn load_downloaded(path: &Path) -> Result<BTreeMap<String, Image>, String> {
+    let data = read_json(path)?;
+    serde_json::from_value(data).map_err(|e| format!("Could not parse {}: {}", path.display(), e))
+}
+
+/// Load an output file that may not be there yet, empty if it isn't
+pub fn load_optional<T: DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let data = read_json(path)?;
+    serde_json::from_value(data).map_err(|e| format!("Could not parse {}: {}", path.display(), e))
+}
+
+fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+    write_json(file, value).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
+
+/// Write records the way every output file is written, tagged with the
+/// current schema version
+pub fn write_json<W: Write, T: Serialize>(writer: W, data: &T) -> serde_json::Result<()> {
+    serde_json::ser::to_writer_pretty(writer, &json!({ "schema_version": SCHEMA_VERSION, "data": data }))
+}
+
+//read an output file and bring its records up to the current layout
+fn read_json(path: &Path) -> Result<Value, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    let value = serde_json::from_str(&contents).map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+    let file = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    migrate(&file, value).map_err(|e| format!("Could not read {}: {}", path.display(), e))
+}
+
+/// Unwrap the records of the output file named `file` and bring them up to
+/// the current layout, whichever version wrote them. Files without a
+/// version are from before versioning, version 0.
+pub fn migrate(file: &str, value: Value) -> Result<Value, String> {
+    let (version, mut data) = match value {
+        Value::Object(mut object) if object.contains_key("schema_version") => {
+            let version = object["schema_version"].as_u64().ok_or("schema_version is not a number")?;
+            (version, object.remove("data").unwrap_or_default())
+        },
+        data => (0, data),
+    };
+    if version > SCHEMA_VERSION {
+        return Err(format!("written with schema version {}, this scraper only reads up to {}", version, SCHEMA_VERSION));
+    }
+
+    //0 to 1: failures were bare URLs before they got request IDs
+    if version < 1 && file == "baddies.json" {
+        if let Value::Array(failures) = &mut data {
+            for failure in failures {
+                if let Some(url) = failure.as_str().map(str::to_string) {
+                    *failure = json!({ "url": url });
+                }
+            }
+        }
+    }
+    Ok(data)
+}
+
+/// How a page changed between two crawls
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct PageChange {
+    pub url: String,
+    pub old_size: usize,
+    pub new_size: usize,
+    pub links_added: Vec<String>,
+    pub links_removed: Vec<String>,
+}
+
+/// Differences between the pages of two crawls
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct CrawlDiff {
+    /// Pages only the new crawl visited
+    pub added: Vec<String>,
+    /// Pages only the old crawl visited
+    pub removed: Vec<String>,
+    /// Pages both crawls visited whose size or links differ
+    pub changed: Vec<PageChange>,
+}
+
+impl CrawlDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Something wrong with one record of a crawl's output files
+#[derive(Debug, PartialEq, Eq)]
+pub struct SchemaIssue {
+    pub file: &'static str,
+    /// URL or list index of the record, empty when the whole file is wrong
+    pub record: String,
+    pub problem: String,
+}
+
+/// Check the output files of the crawl in `dir` record by record. Records
+/// this version can't read are reported, and so are fields it doesn't know,
+/// which loading silently drops. Only a missing visited.json is an error.
+pub fn validate(dir: &Path) -> Result<Vec<SchemaIssue>, String> {
+    let mut issues = Vec::new();
+    validate_file::<Page>(dir, "visited.json", true, &mut issues)?;
+    validate_file::<Image>(dir, "downloaded.json", false, &mut issues)?;
+    validate_file::<Failure>(dir, "baddies.json", false, &mut issues)?;
+    validate_file::<Feed>(dir, "feeds.json", false, &mut issues)?;
+    validate_file::<Option<TlsDetails>>(dir, "tls.json", false, &mut issues)?;
+    Ok(issues)
+}
+
+fn validate_file<T: DeserializeOwned + Serialize>(dir: &Path, file: &'static str, required: bool, issues: &mut Vec<SchemaIssue>) -> Result<(), String> {
+    let path = dir.join(file);
+    if !path.exists() {
+        return match required {
+            true => Err(format!("Could not open {}: no such file", path.display())),
+            false => Ok(()),
+        };
+    }
+    let mut issue = |record: String, problem: String| issues.push(SchemaIssue { file, record, problem });
+    let records: Vec<(String, Value)> = match read_json(&path) {
+        Ok(Value::Object(records)) => records.into_iter().collect(),
+        Ok(Value::Array(records)) => records.into_iter().enumerate().map(|(i, record)| (i.to_string(), record)).collect(),
+        Ok(_) => {
+            issue(String::new(), "expected an object or a list of records".to_string());
+            return Ok(());
+        },
+        Err(e) => {
+            issue(String::new(), e);
+            return Ok(());
+        },
+    };
+    for (record, raw) in records {
+        let typed = match serde_json::from_value::<T>(raw.clone()) {
+            Ok(typed) => typed,
+            Err(e) => {
+                issue(record, e.to_string());
+                continue;
+            },
+        };
+        //whatever doesn't survive a round trip is a field we don't know about
+        let (Value::Object(raw), Ok(Value::Object(known))) = (raw, serde_json::to_value(typed)) else {
+            continue;
+        };
+        for field in raw.keys().filter(|field| !known.contains_key(*field)) {
+            issue(record.clone(), format!("unknown field `{}`", field));
+        }
+    }
+    Ok(())
+}
+
+/// For each image URL, the pages that reference it and how many times each
+pub type ImageIndex = BTreeMap<String, BTreeMap<String, usize>>;
+
+/// Index the image references of the visited pages by image, the reverse of
+/// each page's image list. Pages may be borrowed or owned, as they come
+/// back from a spill file.
+pub fn image_index<K: AsRef<str>, P: Borrow<Page>>(visited: impl IntoIterator<Item = (K, P)>) -> ImageIndex {
+    let mut index = ImageIndex::new();
+    for (url, page) in visited {
+        for image in &page.borrow().images {
+            *index.entry(image.clone()).or_default().entry(url.as_ref().to_string()).or_default() += 1;
+        }
+    }
+    index
+}
+
+/// Compare the visited pages of an old and a new crawl
+pub fn diff(old: &BTreeMap<String, Page>, new: &BTreeMap<String, Page>) -> CrawlDiff {
+    let mut result = CrawlDiff {
+        added: new.keys().filter(|url| !old.contains_key(*url)).cloned().collect(),
+        removed: old.keys().filter(|url| !new.contains_key(*url)).cloned().collect(),
+        changed: Vec::new(),
+    };
+    for (url, old_page) in old {
+        let Some(new_page) = new.get(url) else {
+            continue;
+        };
+        let old_links: BTreeSet<&String> = old_page.links.iter().collect();
+        let new_links: BTreeSet<&String> = new_page.links.iter().collect();
+        if old_page.size == new_page.size && old_links == new_links {
+            continue;
+        }
+        result.changed.push(PageChange {
+            url: url.clone(),
+            old_size: old_page.size,
+            new_size: new_page.size,
+            links_added: new_links.difference(&old_links).map(|s| s.to_string()).collect(),
+            links_removed: old_links.difference(&new_links).map(|s| s.to_string()).collect(),
+        });
+    }
+    result
+}
+
+/// A plain text summary of a crawl, hosts grouped into sites by `aliases`
+pub fn report(results: &CrawlResults, aliases: &HostAliases) -> String {
+    let mut out = String::new();
+    let page_bytes: usize = results.visited.values().map(|page| page.size).sum();
+    let _ = writeln!(out, "Pages: {} ({} bytes)", results.visited.len(), page_bytes);
+
+    //pages per site, the closest thing to site sections we have
+    let sites = pages_by_site(results, aliases);
+    let _ = writeln!(out, "{}: {}", if aliases.is_empty() { "Hosts" } else { "Sites" }, sites.len());
+    for (site, count) in top(sites.into_iter()) {
+        let _ = writeln!(out, "    {:>6}  {}", count, site);
+    }
+
+    let mut inbound: HashMap<&str, usize> = HashMap::new();
+    for page in results.visited.values() {
+        for link in &page.links {
+            *inbound.entry(link.as_str()).or_default() += 1;
+        }
+    }
+    let _ = writeln!(out, "Most linked pages:");
+    for (url, count) in top(inbound.into_iter()) {
+        let _ = writeln!(out, "    {:>6}  {}", count, url);
+    }
+
+    let _ = writeln!(out, "Largest pages:");
+    for (url, size) in top(results.visited.iter().map(|(url, page)| (url, page.size))) {
+        let _ = writeln!(out, "    {:>9}  {}", size, url);
+    }
+
+    let externals = externals_by_domain(results);
+    if !externals.is_empty() {
+        let links: usize = externals.values().sum();
+        let _ = writeln!(out, "External links: {} to {} domains", links, externals.len());
+        for (domain, count) in top(externals.into_iter()) {
+            let _ = writeln!(out, "    {:>6}  {}", count, domain);
+        }
+    }
+
+    let words: usize = results.visited.values().filter_map(|page| page.text.as_ref()).map(|text| text.word_count).sum();
+    if words > 0 {
+        let _ = writeln!(out, "Words of main text: {}", words);
+    }
+
+    let image_bytes: usize = results.downloaded.values().map(|image| image.size).sum();
+    let _ = writeln!(out, "Images: {} ({} bytes)", results.downloaded.len(), image_bytes);
+    let mut formats: BTreeMap<&str, usize> = BTreeMap::new();
+    for image in results.downloaded.values() {
+        *formats.entry(image.format.as_deref().unwrap_or("unknown")).or_default() += 1;
+    }
+    for (format, count) in top(formats.into_iter()) {
+        let _ = writeln!(out, "    {:>6}  {}", count, format);
+    }
+    let _ = writeln!(out, "Images on the most pages:");
+    for (image, pages) in top(image_index(&results.visited).into_iter().map(|(image, pages)| (image, pages.len()))) {
+        let _ = writeln!(out, "    {:>6}  {}", pages, image);
+    }
+
+    let _ = writeln!(out, "Failed URLs: {}", results.baddies.len());
+    let consent_walls = results.visited.values().filter(|page| page.consent_wall).count();
+    if consent_walls > 0 {
+        let _ = writeln!(out, "Consent walls: {}", consent_walls);
+    }
+
+    if !results.feeds.is_empty() {
+        let entries: usize = results.feeds.values().filter_map(|feed| feed.entries).sum();
+        let _ = writeln!(out, "Feeds: {} ({} entries)", results.feeds.len(), entries);
+    }
+
+    if !results.tls.is_empty() {
+        let _ = writeln!(out, "TLS:");
+        for (host, details) in &results.tls {
+            match details {
+                Some(details) => {
+                    let warning = match details.days_until_expiry {
+                        Some(days) if days < 0 => " EXPIRED",
+                        Some(days) if days < EXPIRY_WARNING_DAYS => " EXPIRING SOON",
+                        None => " EXPIRED",
+                        _ => "",
+                    };
+                    let invalid = details.validation_error.as_ref().map_or(String::new(), |e| format!(" ({})", e));
+                    let _ = writeln!(out, "    {} - {} {}, issued by {}, expires {}{}{}",
+                        host, details.protocol, details.cipher_suite, details.issuer, details.not_after, warning, invalid);
+                },
+                None => {
+                    let _ = writeln!(out, "    {} - probe failed", host);
+                },
+            }
+        }
+    }
+    out
+}
+
+//the REPORT_TOP entries with the highest counts, ties broken by name
+/// Pages per site, invalid URLs counted together. A site grouping more than
+/// one host is labelled with the hosts its pages came from.
+pub(crate) fn pages_by_site(results: &CrawlResults, aliases: &HostAliases) -> BTreeMap<String, usize> {
+    let mut sites: BTreeMap<String, (usize, BTreeSet<String>)> = BTreeMap::new();
+    for url in results.visited.keys() {
+        let host = url::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_ascii_lowercase));
+        let site = host.as_deref().map_or_else(|| "(invalid)".to_string(), |host| aliases.site(host).into_owned());
+        let (count, hosts) = sites.entry(site).or_default();
+        *count += 1;
+        hosts.extend(host);
+    }
+    sites.into_iter()
+        .map(|(site, (count, hosts))| {
+            if hosts.iter().all(|host| *host == site) {
+                (site, count)
+            } else {
+                (format!("{} [{}]", site, hosts.into_iter().collect::<Vec<_>>().join(", ")), count)
+            }
+        })
+        .collect()
+}
+
+/// Links out of scope per domain they point to, over every page
+pub(crate) fn externals_by_domain(results: &CrawlResults) -> BTreeMap<String, usize> {
+    let mut domains: BTreeMap<String, usize> = BTreeMap::new();
+    for link in results.visited.values().flat_map(|page| &page.externals) {
+        let host = url::Url::parse(link).ok().and_then(|url| url.host_str().map(str::to_ascii_lowercase));
+        *domains.entry(host.unwrap_or_else(|| "(invalid)".to_string())).or_default() += 1;
+    }
+    domains
+}
+
+pub(crate) fn top<K: Ord, I: Iterator<Item = (K, usize)>>(entries: I) -> Vec<(K, usize)> {
+    let mut entries: Vec<(K, usize)> = entries.collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    entries.truncate(REPORT_TOP);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(size: usize, links: &[&str]) -> Page {
+        Page::new(size, links.iter().map(|s| s.to_string()).collect(), Vec::new())
+    }
+
+    #[test]
+    fn validate_finds_bad_records_and_unknown_fields() {
+        let dir = std::env::temp_dir().join(format!("validate-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("visited.json"), r#"{
+            "https://www.yahoo.com/": {"size": 10, "links": [], "images": []},
+            "https://news.yahoo.com/": {"size": 10, "links": [], "images": [], "language": "en"},
+            "https://mail.yahoo.com/": {"size": "big", "links": [], "images": []}
+        }"#).unwrap();
+        fs::write(dir.join("baddies.json"), r#"["https://sports.yahoo.com/", {"url": "https://finance.yahoo.com/"}]"#).unwrap();
+        fs::write(dir.join("feeds.json"), "[").unwrap();
+
+        let issues = validate(&dir).unwrap();
+        assert_eq!(issues.len(), 3);
+        assert_eq!((issues[0].file, issues[0].record.as_str()), ("visited.json", "https://mail.yahoo.com/"));
+        assert_eq!(issues[1], SchemaIssue {
+            file: "visited.json",
+            record: "https://news.yahoo.com/".to_string(),
+            problem: "unknown field `language`".to_string(),
+        });
+        assert_eq!((issues[2].file, issues[2].record.as_str()), ("feeds.json", ""));
+
+        fs::remove_file(dir.join("visited.json")).unwrap();
+        assert!(validate(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn indexes_images_by_page() {
+        let mut home = page(100, &[]);
+        home.images = vec!["https://s.yimg.com/banner.png".to_string(), "https://s.yimg.com/banner.png".to_string()];
+        let mut news = page(100, &[]);
+        news.images = vec!["https://s.yimg.com/banner.png".to_string(), "https://s.yimg.com/story.jpg".to_string()];
+        let visited = BTreeMap::from([
+            ("https://www.yahoo.com/".to_string(), home),
+            ("https://news.yahoo.com/".to_string(), news),
+        ]);
+        assert_eq!(image_index(&visited), BTreeMap::from([
+            ("https://s.yimg.com/banner.png".to_string(), BTreeMap::from([
+                ("https://news.yahoo.com/".to_string(), 1),
+                ("https://www.yahoo.com/".to_string(), 2),
+            ])),
+            ("https://s.yimg.com/story.jpg".to_string(), BTreeMap::from([("https://news.yahoo.com/".to_string(), 1)])),
+        ]));
+    }
+
+    #[test]
+    fn reports_external_links_by_domain() {
+        let mut home = page(100, &[]);
+        home.externals = vec!["https://www.bls.gov/cpi/".to_string(), "https://www.bls.gov/".to_string(), "http://example.com/a".to_string()];
+        let mut news = page(100, &[]);
+        news.externals = vec!["https://WWW.BLS.GOV/news/".to_string()];
+        let results = CrawlResults {
+            visited: BTreeMap::from([
+                ("https://www.yahoo.com/".to_string(), home),
+                ("https://news.yahoo.com/".to_string(), news),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(externals_by_domain(&results), BTreeMap::from([("example.com".to_string(), 1), ("www.bls.gov".to_string(), 3)]));
+        assert!(report(&results, &HostAliases::default()).contains("External links: 4 to 2 domains\n         3  www.bls.gov\n         1  example.com\n"));
+    }
+
+    fn failure(url: &str, request_id: &str) -> Failure {
+        Failure { url: url.to_string(), request_id: Some(request_id.to_string()) }
+    }
+
+    #[test]
+    fn diff_finds_added_removed_and_changed_pages() {
+        let old = BTreeMap::from([
+            ("https://www.yahoo.com/".to_string(), page(100, &["https://news.yahoo.com/"])),
+            ("https://news.yahoo.com/".to_string(), page(50, &[])),
+            ("https://finance.yahoo.com/".to_string(), page(70, &[])),
+        ]);
+        let new = BTreeMap::from([
+            ("https://www.yahoo.com/".to_string(), page(100, &["https://sports.yahoo.com/"])),
+            ("https://news.yahoo.com/".to_string(), page(50, &[])),
+            ("https://sports.yahoo.com/".to_string(), page(30, &[])),
+        ]);
+        let result = diff(&old, &new);
+        assert_eq!(result.added, ["https://sports.yahoo.com/"]);
+        assert_eq!(result.removed, ["https://finance.yahoo.com/"]);
+        assert_eq!(result.changed, [PageChange {
+            url: "https://www.yahoo.com/".to_string(),
+            old_size: 100,
+            new_size: 100,
+            links_added: vec!["https://sports.yahoo.com/".to_string()],
+            links_removed: vec!["https://news.yahoo.com/".to_string()],
+        }]);
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn merge_keeps_later_pages_and_drops_recovered_failures() {
+        let mut first = CrawlResults {
+            visited: BTreeMap::from([("https://www.yahoo.com/".to_string(), page(100, &[]))]),
+            baddies: vec![failure("https://news.yahoo.com/", "a-000001"), failure("https://mail.yahoo.com/", "a-000002")],
+            ..Default::default()
+        };
+        let second = CrawlResults {
+            visited: BTreeMap::from([
+                ("https://www.yahoo.com/".to_string(), page(120, &[])),
+                ("https://news.yahoo.com/".to_string(), page(50, &[])),
+            ]),
+            baddies: vec![failure("https://mail.yahoo.com/", "b-000001")],
+            ..Default::default()
+        };
+        first.merge(second);
+        assert_eq!(first.visited.len(), 2);
+        assert_eq!(first.visited["https://www.yahoo.com/"].size, 120);
+        assert_eq!(first.baddies, [failure("https://mail.yahoo.com/", "b-000001")]);
+    }
+
+    #[test]
+    fn migrates_unversioned_files_and_refuses_newer_ones() {
+        let unversioned = json!(["https://news.yahoo.com/", {"url": "https://mail.yahoo.com/", "request_id": "a-000002"}]);
+        let baddies: Vec<Failure> = serde_json::from_value(migrate("baddies.json", unversioned).unwrap()).unwrap();
+        assert_eq!(baddies, [
+            Failure { url: "https://news.yahoo.com/".to_string(), request_id: None },
+            failure("https://mail.yahoo.com/", "a-000002"),
+        ]);
+
+        let mut written = Vec::new();
+        write_json(&mut written, &baddies).unwrap();
+        let current: Value = serde_json::from_slice(&written).unwrap();
+        assert_eq!(current["schema_version"], SCHEMA_VERSION);
+        assert_eq!(serde_json::from_value::<Vec<Failure>>(migrate("baddies.json", current).unwrap()).unwrap(), baddies);
+
+        let newer = json!({"schema_version": SCHEMA_VERSION + 1, "data": {}});
+        assert!(migrate("visited.json", newer).is_err());
+    }
+}
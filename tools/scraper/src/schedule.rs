@@ -0,0 +1,365 @@
+//! When to fetch URLs again, for the recrawl daemon. URLs fall into classes
+//! by pattern, front pages hourly and articles daily say, and the schedule
+//! store remembers for each URL when it is next due and what it looked like
+//! last time, so a restarted daemon carries on where it left off.
+//!
+//! The store is one JSON file, rewritten through a temporary file after every
+//! fetch so a crash can't leave it half written. Besides the validators for
+//! conditional requests it keeps the links and paragraph hashes of the last
+//! version of each page, enough to say what changed without the old page.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::blacklist::glob_match;
+
+/// A class of URLs and how often they are refreshed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntervalRule {
+    pub class: String,
+    pattern: String,
+    pub every: Duration,
+}
+
+impl IntervalRule {
+    /// Parse CLASS:PATTERN=INTERVAL, ie: articles:https://news.yahoo.com/*.html=1d,
+    /// where * in the pattern matches anything
+    pub fn parse(rule: &str, parse_interval: impl Fn(&str) -> Option<Duration>) -> Result<Self, String> {
+        let invalid = || format!("{} is not a recrawl interval, expected CLASS:PATTERN=INTERVAL", rule);
+        let (class, rest) = rule.split_once(':').ok_or_else(invalid)?;
+        let (pattern, every) = rest.rsplit_once('=').ok_or_else(invalid)?;
+        let every = parse_interval(every).filter(|every| !every.is_zero()).ok_or_else(invalid)?;
+        //a URL without a class would otherwise read as class https
+        if class.is_empty() || pattern.is_empty() || pattern.starts_with("//") {
+            return Err(invalid());
+        }
+        Ok(Self { class: class.to_string(), pattern: pattern.to_string(), every })
+    }
+}
+
+/// The interval rules of a daemon, the first matching rule decides a URL's class
+#[derive(Debug, Clone, Default)]
+pub struct Classes {
+    rules: Vec<IntervalRule>,
+    /// For URLs no rule matches, they aren't scheduled without it
+    default: Option<Duration>,
+}
+
+impl Classes {
+    pub fn new(rules: Vec<IntervalRule>, default: Option<Duration>) -> Self {
+        Self { rules, default }
+    }
+
+    /// The class of url and how often it is refreshed, None if it isn't scheduled
+    pub fn classify(&self, url: &str) -> Option<(&str, Duration)> {
+        match self.rules.iter().find(|rule| glob_match(&rule.pattern, url)) {
+            Some(rule) => Some((&rule.class, rule.every)),
+            None => self.default.map(|every| ("default", every)),
+        }
+    }
+}
+
+/// What the store knows about one URL
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub class: String,
+    pub every_secs: u64,
+    /// Unix seconds from when the URL is due
+    pub next_due: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_fetched: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// Hash of the body last fetched, to tell whether the page changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<u64>,
+    /// Size of the body last fetched, in bytes
+    #[serde(default)]
+    pub size: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<String>,
+    /// Hashes of the text paragraphs of the body last fetched
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paragraphs: Vec<u64>,
+    #[serde(default)]
+    pub fetches: u64,
+    #[serde(default)]
+    pub changes: u64,
+}
+
+/// How a refresh went
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Refresh {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// None when the server said the page hadn't changed
+    pub body: Option<String>,
+    /// Links and text paragraphs of the body, to tell what changed
+    pub links: Vec<String>,
+    pub paragraphs: Vec<String>,
+}
+
+/// A page whose content changed since the previous visit, one line of changes.ndjson
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub url: String,
+    pub class: String,
+    pub fetched_at: u64,
+    /// When the version it is compared with was fetched
+    pub previous_fetch: Option<u64>,
+    /// Content hashes in hex, too wide for a JSON number to carry exactly
+    pub old_hash: String,
+    pub new_hash: String,
+    pub old_size: usize,
+    pub new_size: usize,
+    pub links_added: Vec<String>,
+    pub links_removed: Vec<String>,
+    pub paragraphs_added: usize,
+    pub paragraphs_removed: usize,
+}
+
+/// The persistent schedule of every URL the daemon keeps fresh
+#[derive(Debug)]
+pub struct Schedule {
+    path: PathBuf,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Schedule {
+    /// Open the store at path, empty if it doesn't exist yet
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let entries = match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Could not parse {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+        };
+        Ok(Self { path: path.to_path_buf(), entries })
+    }
+
+    /// Schedule url for right away, unless it already is or no class wants it
+    pub fn add(&mut self, url: &str, classes: &Classes, now: u64) -> bool {
+        if self.entries.contains_key(url) {
+            return false;
+        }
+        let Some((class, every)) = classes.classify(url) else {
+            return false;
+        };
+        self.entries.insert(url.to_string(), Entry {
+            class: class.to_string(),
+            every_secs: every.as_secs(),
+            next_due: now,
+            last_fetched: None,
+            etag: None,
+            last_modified: None,
+            content_hash: None,
+            size: 0,
+            links: Vec::new(),
+            paragraphs: Vec::new(),
+            fetches: 0,
+            changes: 0,
+        });
+        true
+    }
+
+    /// Bring every URL in line with the rules, which may have changed since the
+    /// store was written. URLs no rule wants any more are dropped, the number
+    /// dropped is returned.
+    pub fn reclassify(&mut self, classes: &Classes) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|url, entry| match classes.classify(url) {
+            Some((class, every)) => {
+                //a shorter interval pulls the next fetch forward, a longer one pushes it back
+                if let Some(last) = entry.last_fetched {
+                    entry.next_due = last + every.as_secs();
+                }
+                entry.class = class.to_string();
+                entry.every_secs = every.as_secs();
+                true
+            },
+            None => false,
+        });
+        before - self.entries.len()
+    }
+
+    /// URLs due at `now`, the most overdue first
+    pub fn due(&self, now: u64) -> Vec<String> {
+        let mut due: Vec<(&String, &Entry)> = self.entries.iter().filter(|(_, entry)| entry.next_due <= now).collect();
+        due.sort_by_key(|(url, entry)| (entry.next_due, *url));
+        due.into_iter().map(|(url, _)| url.clone()).collect()
+    }
+
+    /// When the next URL is due, None with nothing scheduled
+    pub fn next_due(&self) -> Option<u64> {
+        self.entries.values().map(|entry| entry.next_due).min()
+    }
+
+    pub fn get(&self, url: &str) -> Option<&Entry> {
+        self.entries.get(url)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Note a successful refresh of url at `now` and schedule the next one,
+    /// returning what changed if the page did since the last fetch
+    pub fn refreshed(&mut self, url: &str, now: u64, refresh: Refresh) -> Option<Change> {
+        let entry = self.entries.get_mut(url)?;
+        let previous_fetch = entry.last_fetched.replace(now);
+        entry.fetches += 1;
+        entry.next_due = now + entry.every_secs;
+        let body = refresh.body?;
+        let hash = content_hash(&body);
+        let paragraphs: Vec<u64> = refresh.paragraphs.iter().map(|paragraph| content_hash(paragraph)).collect();
+        //the first fetch has nothing to compare with, it doesn't count as a change
+        let change = entry.content_hash.filter(|last| *last != hash).map(|last| {
+            let old_links: BTreeSet<&String> = entry.links.iter().collect();
+            let new_links: BTreeSet<&String> = refresh.links.iter().collect();
+            let old_paragraphs: BTreeSet<&u64> = entry.paragraphs.iter().collect();
+            let new_paragraphs: BTreeSet<&u64> = paragraphs.iter().collect();
+            Change {
+                url: url.to_string(),
+                class: entry.class.clone(),
+                fetched_at: now,
+                previous_fetch,
+                old_hash: format!("{:016x}", last),
+                new_hash: format!("{:016x}", hash),
+                old_size: entry.size,
+                new_size: body.len(),
+                links_added: new_links.difference(&old_links).map(|s| s.to_string()).collect(),
+                links_removed: old_links.difference(&new_links).map(|s| s.to_string()).collect(),
+                paragraphs_added: new_paragraphs.difference(&old_paragraphs).count(),
+                paragraphs_removed: old_paragraphs.difference(&new_paragraphs).count(),
+            }
+        });
+        entry.content_hash = Some(hash);
+        entry.size = body.len();
+        entry.links = refresh.links;
+        entry.paragraphs = paragraphs;
+        entry.etag = refresh.etag;
+        entry.last_modified = refresh.last_modified;
+        if change.is_some() {
+            entry.changes += 1;
+        }
+        change
+    }
+
+    /// Note a failed refresh, tried again after another interval
+    pub fn failed(&mut self, url: &str, now: u64) {
+        if let Some(entry) = self.entries.get_mut(url) {
+            entry.next_due = now + entry.every_secs;
+        }
+    }
+
+    /// Write the store out, replacing the old file in one step
+    pub fn save(&self) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        serde_json::to_writer_pretty(&mut tmp, &self.entries)?;
+        tmp.write_all(b"\n")?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+//the same page hashes the same from one run to the next, SipHash with fixed keys
+fn content_hash(body: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interval(value: &str) -> Option<Duration> {
+        value.strip_suffix('h').and_then(|hours| hours.parse::<u64>().ok()).map(|hours| Duration::from_secs(hours * 3600))
+    }
+
+    fn classes() -> Classes {
+        Classes::new(vec![
+            IntervalRule::parse("front:https://www.yahoo.com/=1h", interval).unwrap(),
+            IntervalRule::parse("articles:https://news.yahoo.com/*.html=24h", interval).unwrap(),
+        ], None)
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rule = IntervalRule::parse("quotes:https://finance.yahoo.com/quote/*?p=*=2h", interval).unwrap();
+        assert_eq!((rule.class.as_str(), rule.pattern.as_str(), rule.every), ("quotes", "https://finance.yahoo.com/quote/*?p=*", Duration::from_secs(7200)));
+        assert!(IntervalRule::parse("https://www.yahoo.com/=1h", interval).is_err());
+        assert!(IntervalRule::parse("front:https://www.yahoo.com/", interval).is_err());
+        assert!(IntervalRule::parse("front:https://www.yahoo.com/=0h", interval).is_err());
+        assert_eq!(classes().classify("https://news.yahoo.com/story.html"), Some(("articles", Duration::from_secs(86400))));
+        assert_eq!(classes().classify("https://sports.yahoo.com/"), None);
+        let with_default = Classes::new(Vec::new(), Some(Duration::from_secs(60)));
+        assert_eq!(with_default.classify("https://sports.yahoo.com/"), Some(("default", Duration::from_secs(60))));
+    }
+
+    #[test]
+    fn schedules_and_persists_refreshes() {
+        let path = std::env::temp_dir().join(format!("schedule-test-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let classes = classes();
+        let mut schedule = Schedule::open(&path).unwrap();
+        assert!(schedule.add("https://www.yahoo.com/", &classes, 1000));
+        assert!(schedule.add("https://news.yahoo.com/story.html", &classes, 1000));
+        assert!(!schedule.add("https://www.yahoo.com/", &classes, 1000));
+        assert!(!schedule.add("https://sports.yahoo.com/", &classes, 1000));
+        assert_eq!(schedule.due(1000), ["https://news.yahoo.com/story.html", "https://www.yahoo.com/"]);
+
+        let page = |body: &str| Refresh { body: Some(body.to_string()), ..Refresh::default() };
+        assert_eq!(schedule.refreshed("https://www.yahoo.com/", 1000, page("v1")), None);
+        assert_eq!(schedule.refreshed("https://news.yahoo.com/story.html", 1000, page("story")), None);
+        assert_eq!(schedule.due(1000), Vec::<String>::new());
+        assert_eq!(schedule.next_due(), Some(4600));
+        assert!(schedule.refreshed("https://www.yahoo.com/", 4600, page("v2")).is_some());
+        assert_eq!(schedule.refreshed("https://www.yahoo.com/", 8200, Refresh::default()), None);
+        schedule.save().unwrap();
+
+        let mut reopened = Schedule::open(&path).unwrap();
+        let front = reopened.get("https://www.yahoo.com/").unwrap();
+        assert_eq!((front.fetches, front.changes, front.next_due), (3, 1, 11800));
+        //articles aren't wanted any more, the front page goes to every two hours
+        let rules = vec![IntervalRule::parse("front:https://www.yahoo.com/=2h", interval).unwrap()];
+        assert_eq!(reopened.reclassify(&Classes::new(rules, None)), 1);
+        assert_eq!(reopened.len(), 1);
+        assert_eq!(reopened.get("https://www.yahoo.com/").unwrap().next_due, 8200 + 7200);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn summarizes_changes() {
+        let mut schedule = Schedule { path: PathBuf::new(), entries: BTreeMap::new() };
+        schedule.add("https://www.yahoo.com/", &classes(), 0);
+        let page = |body: &str, links: &[&str], paragraphs: &[&str]| Refresh {
+            etag: Some(format!("\"{}\"", body.len())),
+            last_modified: None,
+            body: Some(body.to_string()),
+            links: links.iter().map(|link| link.to_string()).collect(),
+            paragraphs: paragraphs.iter().map(|paragraph| paragraph.to_string()).collect(),
+        };
+        let first = page("<p>Markets rally</p><p>Rain today</p>", &["https://news.yahoo.com/a.html", "https://news.yahoo.com/b.html"], &["Markets rally", "Rain today"]);
+        assert_eq!(schedule.refreshed("https://www.yahoo.com/", 100, first.clone()), None);
+        assert_eq!(schedule.refreshed("https://www.yahoo.com/", 200, first), None);
+
+        let second = page("<p>Markets slide</p><p>Rain today</p><p>Goal!</p>", &["https://news.yahoo.com/b.html", "https://news.yahoo.com/c.html"], &["Markets slide", "Rain today", "Goal!"]);
+        let change = schedule.refreshed("https://www.yahoo.com/", 300, second).unwrap();
+        assert_eq!((change.class.as_str(), change.fetched_at, change.previous_fetch), ("front", 300, Some(200)));
+        assert_ne!(change.old_hash, change.new_hash);
+        assert_eq!(change.old_hash.len(), 16);
+        assert_eq!((change.old_size, change.new_size), (37, 49));
+        assert_eq!(change.links_added, ["https://news.yahoo.com/c.html"]);
+        assert_eq!(change.links_removed, ["https://news.yahoo.com/a.html"]);
+        assert_eq!((change.paragraphs_added, change.paragraphs_removed), (2, 1));
+        assert_eq!(schedule.get("https://www.yahoo.com/").unwrap().etag.as_deref(), Some("\"49\""));
+    }
+}
@@ -0,0 +1,176 @@
+//! Stores that remember which URLs the crawl has already seen, so a URL is
+//! only ever queued once.
+//!
+//! The in-memory set is exact but grows with every URL. For huge crawls the
+//! bloom filter answers membership in a fixed amount of memory at the cost of
+//! occasionally skipping a URL it never saw, and the sled store stays exact by
+//! keeping the set on disk.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+/// Membership set of URLs already queued or fetched
+pub trait SeenStore {
+    /// Record the url, returns true if it was not seen before
+    fn insert(&mut self, url: &str) -> bool;
+}
+
+/// Exact store keeping every URL in memory
+#[derive(Debug, Default)]
+pub struct MemorySeenStore {
+    urls: HashSet<String>,
+}
+
+impl SeenStore for MemorySeenStore {
+    fn insert(&mut self, url: &str) -> bool {
+        self.urls.insert(url.to_string())
+    }
+}
+
+/// Approximate store: never reports a seen URL as new, but may report a new URL
+/// as seen with probability close to the configured false positive rate
+#[derive(Debug)]
+pub struct BloomSeenStore {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+impl BloomSeenStore {
+    /// Size the filter for `capacity` URLs at the given false positive rate
+    pub fn new(capacity: usize, false_positive_rate: f64) -> Self {
+        let capacity = capacity.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-capacity * false_positive_rate.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / capacity) * ln2).round().max(1.0) as u32;
+        Self {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    //double hashing: the i-th probe is h1 + i * h2
+    fn probes(&self, url: &str) -> impl Iterator<Item = u64> {
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        let h1 = hasher.finish();
+        0x9e37_79b9_7f4a_7c15u64.hash(&mut hasher);
+        let h2 = hasher.finish() | 1;
+        let num_bits = self.num_bits;
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+
+    fn bit(&self, index: u64) -> bool {
+        self.bits[(index / 64) as usize] & (1 << (index % 64)) != 0
+    }
+}
+
+impl SeenStore for BloomSeenStore {
+    fn insert(&mut self, url: &str) -> bool {
+        let mut new = false;
+        for index in self.probes(url).collect::<Vec<_>>() {
+            if !self.bit(index) {
+                new = true;
+                self.bits[(index / 64) as usize] |= 1 << (index % 64);
+            }
+        }
+        new
+    }
+}
+
+/// Exact store backed by an on-disk sled database
+pub struct SledSeenStore {
+    db: sled::Db,
+}
+
+impl SledSeenStore {
+    /// Open the database at path. Unless resuming the crawl that left it
+    /// there, whatever it remembers is forgotten, or a new crawl would skip
+    /// every URL an earlier one saw.
+    pub fn open(path: &Path, resuming: bool) -> sled::Result<Self> {
+        let db = sled::open(path)?;
+        if !resuming {
+            db.clear()?;
+        }
+        Ok(Self { db })
+    }
+}
+
+impl SeenStore for SledSeenStore {
+    fn insert(&mut self, url: &str) -> bool {
+        //a failing disk shouldn't stop the crawl, treat the url as new
+        match self.db.insert(url, &[]) {
+            Ok(previous) => previous.is_none(),
+            Err(e) => {
+                status!("Seen store write failed! {}", e);
+                true
+            }
+        }
+    }
+}
+
+/// Pick the store named on the command line: "memory", "bloom" or "sled".
+/// Only a sled store has anything to carry over when resuming.
+pub fn open_store(kind: &str, capacity: usize, path: &Path, resuming: bool) -> Result<Box<dyn SeenStore>, String> {
+    match kind {
+        "memory" => Ok(Box::<MemorySeenStore>::default()),
+        "bloom" => Ok(Box::new(BloomSeenStore::new(capacity, 0.001))),
+        "sled" => SledSeenStore::open(path, resuming)
+            .map(|store| Box::new(store) as Box<dyn SeenStore>)
+            .map_err(|e| format!("Could not open seen store at {}: {}", path.display(), e)),
+        _ => Err(format!("Unknown seen store: {}", kind)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bloom_remembers_inserted_urls() {
+        let mut store = BloomSeenStore::new(1000, 0.01);
+        assert!(store.insert("https://www.yahoo.com/"));
+        assert!(!store.insert("https://www.yahoo.com/"));
+    }
+
+    #[test]
+    fn bloom_false_positive_rate_is_bounded() {
+        let mut store = BloomSeenStore::new(10_000, 0.01);
+        for i in 0..10_000 {
+            store.insert(&format!("https://news.yahoo.com/{}", i));
+        }
+        //a new url reported as already seen is a false positive
+        let false_positives = (0..1000)
+            .filter(|i| !store.insert(&format!("https://finance.yahoo.com/{}", i)))
+            .count();
+        assert!(false_positives < 40, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn sled_forgets_earlier_crawls_unless_resuming() {
+        let path = std::env::temp_dir().join(format!("seen-test-{}.db", std::process::id()));
+        //sled lets go of its lock from a background thread, a moment after the store is dropped
+        let open = |resuming| {
+            for _ in 0..100 {
+                if let Ok(store) = SledSeenStore::open(&path, resuming) {
+                    return store;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(20));
+            }
+            SledSeenStore::open(&path, resuming).unwrap()
+        };
+        let mut store = open(false);
+        assert!(store.insert("https://www.yahoo.com/"));
+        drop(store);
+        let mut store = open(true);
+        assert!(!store.insert("https://www.yahoo.com/"));
+        drop(store);
+        let mut store = open(false);
+        assert!(store.insert("https://www.yahoo.com/"));
+        drop(store);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
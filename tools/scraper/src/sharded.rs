@@ -0,0 +1,207 @@
+//! A map many threads can read and write at once, for the records a crawl
+//! collects. Keys are spread over shards that each have their own lock, so
+//! workers only wait on each other when they touch URLs in the same shard.
+//! One lock around the whole map serializes every worker on every page,
+//! which stops paying for more workers after a handful; the benchmark in the
+//! tests measures the two side by side.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+use serde::{Serialize, Serializer};
+
+/// Shards in a map made with [`ShardedMap::new`], plenty for the worker
+/// counts a crawl runs with while still cheap to lock all at once
+const DEFAULT_SHARDS: usize = 32;
+
+/// A URL keyed map split over independently locked shards
+#[derive(Debug)]
+pub struct ShardedMap<V> {
+    shards: Box<[Mutex<HashMap<String, V>>]>,
+}
+
+impl<V> Default for ShardedMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> ShardedMap<V> {
+    pub fn new() -> Self {
+        Self::with_shards(DEFAULT_SHARDS)
+    }
+
+    /// A map over `shards` shards, at least one
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| Mutex::new(HashMap::new())).collect(),
+        }
+    }
+
+    //the shard the key lives in, locked
+    fn shard(&self, key: &str) -> MutexGuard<'_, HashMap<String, V>> {
+        let index = shard_hash(key) as usize % self.shards.len();
+        //a worker that panicked mid-insert left the shard consistent, a HashMap insert doesn't half happen
+        self.shards[index].lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Insert or replace the value for key, returning the old one
+    pub fn insert(&self, key: String, value: V) -> Option<V> {
+        self.shard(&key).insert(key, value)
+    }
+
+    /// Insert the value only if the key is new. The check and the insert
+    /// happen under one lock, so of several workers racing on the same URL
+    /// exactly one gets true.
+    pub fn insert_new(&self, key: String, value: V) -> bool {
+        let mut shard = self.shard(&key);
+        if shard.contains_key(&key) {
+            return false;
+        }
+        shard.insert(key, value);
+        true
+    }
+
+    pub fn remove(&self, key: &str) -> Option<V> {
+        self.shard(key).remove(key)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.shard(key).contains_key(key)
+    }
+
+    /// Lock every shard for a consistent view of the whole map. Writers wait
+    /// until it is dropped, so keep it for writing out results, not during a crawl.
+    pub fn lock_all(&self) -> Snapshot<'_, V> {
+        Snapshot { shards: self.shards.iter().map(|shard| shard.lock().unwrap_or_else(|poisoned| poisoned.into_inner())).collect() }
+    }
+}
+
+//FNV-1a, picking a shard needs a cheap spread of the keys rather than the
+//flooding resistance the shard's own HashMap already has
+fn shard_hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3))
+}
+
+/// Every shard of a [`ShardedMap`], locked
+pub struct Snapshot<'a, V> {
+    shards: Vec<MutexGuard<'a, HashMap<String, V>>>,
+}
+
+impl<V> Snapshot<'_, V> {
+    /// Entries in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &V)> {
+        self.shards.iter().flat_map(|shard| shard.iter())
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.iter().map(|(key, _)| key)
+    }
+}
+
+//written out sorted by key, so the same crawl always gives the same file
+impl<V: Serialize> Serialize for ShardedMap<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let snapshot = self.lock_all();
+        snapshot.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Instant;
+
+    #[test]
+    fn only_one_racing_worker_inserts_a_key() {
+        let map = Arc::new(ShardedMap::with_shards(4));
+        let winners: usize = (0..8)
+            .map(|worker| {
+                let map = map.clone();
+                thread::spawn(move || (0..1000).filter(|i| map.insert_new(format!("https://news.yahoo.com/{}", i), worker)).count())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum();
+        assert_eq!(winners, 1000);
+        assert_eq!(map.lock_all().keys().count(), 1000);
+        assert!(map.contains_key("https://news.yahoo.com/999"));
+        assert!(!map.contains_key("https://news.yahoo.com/1000"));
+    }
+
+    #[test]
+    fn serializes_sorted() {
+        let map = ShardedMap::new();
+        map.insert("https://b.yahoo.com/".to_string(), 2);
+        map.insert("https://a.yahoo.com/".to_string(), 1);
+        assert_eq!(map.insert("https://b.yahoo.com/".to_string(), 3), Some(2));
+        assert_eq!(serde_json::to_string(&map).unwrap(), r#"{"https://a.yahoo.com/":1,"https://b.yahoo.com/":3}"#);
+    }
+
+    //the single-lock alternative, only here to be measured against
+    struct LockedMap<V>(Mutex<HashMap<String, V>>);
+
+    trait SharedMap: Send + Sync {
+        fn insert_new(&self, key: String) -> bool;
+        fn contains_key(&self, key: &str) -> bool;
+    }
+
+    impl SharedMap for LockedMap<u64> {
+        fn insert_new(&self, key: String) -> bool {
+            let mut map = self.0.lock().unwrap();
+            !map.contains_key(&key) && map.insert(key, 0).is_none()
+        }
+        fn contains_key(&self, key: &str) -> bool {
+            self.0.lock().unwrap().contains_key(key)
+        }
+    }
+
+    impl SharedMap for ShardedMap<u64> {
+        fn insert_new(&self, key: String) -> bool {
+            ShardedMap::insert_new(self, key, 0)
+        }
+        fn contains_key(&self, key: &str) -> bool {
+            ShardedMap::contains_key(self, key)
+        }
+    }
+
+    //workers each check a run of URLs the way a crawl dedups links, inserting the new ones
+    fn dedup_workload(map: Arc<dyn SharedMap>, workers: usize) -> std::time::Duration {
+        const URLS: usize = 200_000;
+        let started = Instant::now();
+        let handles: Vec<_> = (0..workers)
+            .map(|worker| {
+                let map = map.clone();
+                thread::spawn(move || {
+                    for i in 0..URLS / workers {
+                        //most links on a page were seen already, a few are new
+                        let url = format!("https://news.yahoo.com/{}/{}", worker, i);
+                        map.insert_new(url.clone());
+                        for _ in 0..8 {
+                            map.contains_key(&url);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        started.elapsed()
+    }
+
+    //cargo test --release sharded -- --ignored --nocapture
+    //on a single core there's no contention to remove and the shard lookup is pure overhead, the gap shows with several cores
+    #[test]
+    #[ignore = "benchmark, run on demand"]
+    fn benchmark_against_a_single_lock() {
+        for workers in [1, 2, 4, 8, 16] {
+            let locked = dedup_workload(Arc::new(LockedMap(Mutex::new(HashMap::new()))), workers);
+            let sharded = dedup_workload(Arc::new(ShardedMap::<u64>::new()), workers);
+            println!("{:>2} workers: single lock {:>8.1?}, sharded {:>8.1?} ({:.1}x)",
+                workers, locked, sharded, locked.as_secs_f64() / sharded.as_secs_f64());
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Sitemaps listed in robots.txt. Yahoo keeps many of its sections on their
+//! own subdomains, and the robots.txt of one often points at the sitemaps of
+//! the others, so following those is a way to reach hosts the seed never
+//! links to without seeding each of them by hand.
+
+use url::Url;
+
+/// At most this many sitemaps are fetched per host, sitemap indexes can nest
+/// far deeper than is worth following
+pub const MAX_SITEMAPS_PER_HOST: usize = 20;
+
+/// The robots.txt of the host of `url`
+pub fn robots_url(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    url.join("/robots.txt").ok().map(String::from)
+}
+
+/// The `Sitemap:` entries of a robots.txt, in order. They apply to every
+/// agent, so the groups they sit in don't matter.
+pub fn from_robots(robots_txt: &str) -> Vec<String> {
+    robots_txt.lines()
+        .filter_map(|line| {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let (field, value) = line.split_once(':')?;
+            let value = value.trim();
+            (field.trim().eq_ignore_ascii_case("sitemap") && !value.is_empty()).then(|| value.to_string())
+        })
+        .collect()
+}
+
+/// What a fetched sitemap lists
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SitemapContents {
+    /// Pages of a `<urlset>`
+    pub page_urls: Vec<String>,
+    /// Further sitemaps of a `<sitemapindex>`
+    pub sitemap_urls: Vec<String>,
+}
+
+/// Read a sitemap or a sitemap index, whichever it turns out to be
+pub fn parse(sitemap_url: &str, xml: &str) -> Result<SitemapContents, String> {
+    let document = roxmltree::Document::parse(xml).map_err(|e| format!("Could not parse sitemap {}: {}", sitemap_url, e))?;
+    let root = document.root_element();
+    //<urlset><url><loc>url</loc></url></urlset> or <sitemapindex><sitemap><loc>url</loc></sitemap></sitemapindex>
+    let entry = match root.tag_name().name() {
+        "urlset" => "url",
+        "sitemapindex" => "sitemap",
+        other => return Err(format!("{} is not a sitemap, its root element is <{}>", sitemap_url, other)),
+    };
+    let locations = root.children()
+        .filter(|node| node.has_tag_name(entry))
+        .filter_map(|node| node.children().find(|child| child.has_tag_name("loc"))?.text())
+        .filter_map(|loc| Url::parse(loc.trim()).ok().map(String::from))
+        .collect();
+    let mut contents = SitemapContents::default();
+    match entry {
+        "url" => contents.page_urls = locations,
+        _ => contents.sitemap_urls = locations,
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_sitemap_lines_from_robots() {
+        let robots = "User-agent: *\nDisallow: /search\n\
+            Sitemap: https://news.yahoo.com/sitemap.xml\n\
+            SITEMAP:https://finance.yahoo.com/sitemap_index.xml # finance\n\
+            Sitemap:\n# Sitemap: https://commented.yahoo.com/sitemap.xml\n";
+        assert_eq!(from_robots(robots), ["https://news.yahoo.com/sitemap.xml", "https://finance.yahoo.com/sitemap_index.xml"]);
+        assert_eq!(robots_url("https://www.yahoo.com/news/world?x=1").as_deref(), Some("https://www.yahoo.com/robots.txt"));
+    }
+
+    #[test]
+    fn parses_urlsets_and_indexes() {
+        let urlset = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <url><loc> https://news.yahoo.com/world/ </loc><lastmod>2024-01-01</lastmod></url>
+                <url><loc>not a url</loc></url>
+                <url><loc>https://news.yahoo.com/politics/</loc></url>
+            </urlset>"#;
+        let contents = parse("https://news.yahoo.com/sitemap.xml", urlset).unwrap();
+        assert_eq!(contents.page_urls, ["https://news.yahoo.com/world/", "https://news.yahoo.com/politics/"]);
+        assert!(contents.sitemap_urls.is_empty());
+
+        let index = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <sitemap><loc>https://news.yahoo.com/sitemap-1.xml</loc></sitemap>
+            </sitemapindex>"#;
+        let contents = parse("https://news.yahoo.com/sitemap_index.xml", index).unwrap();
+        assert_eq!(contents.sitemap_urls, ["https://news.yahoo.com/sitemap-1.xml"]);
+
+        assert!(parse("https://news.yahoo.com/rss", "<rss/>").is_err());
+        assert!(parse("https://news.yahoo.com/sitemap.xml", "User-agent: *").is_err());
+    }
+}
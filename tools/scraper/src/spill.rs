@@ -0,0 +1,207 @@
+//! Keeping a big crawl within a memory budget, with --memory-budget. Page
+//! records pile up in the visited map for the whole crawl, and on a large
+//! one they, the frontier and the pages waiting on the parser threads are
+//! what runs the process out of memory.
+//!
+//! Sizes are estimates: a record counts as its JSON size plus a fixed
+//! overhead. Once the estimate goes over the budget, the records that have
+//! been in memory the longest are written to a spill file and dropped from
+//! the map. A crawl never looks at a page record again after recording it,
+//! so the oldest are also the least recently used. Only their URLs and file
+//! offsets stay behind, and the records are read back one at a time when
+//! the results are written out.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::de::DeserializeOwned;
+use serde::ser::{Error, SerializeMap};
+use serde::{Serialize, Serializer};
+use crate::sharded::ShardedMap;
+
+/// Rough cost of a record beyond its JSON: the map entry, the Arc and the key
+const RECORD_OVERHEAD: usize = 128;
+
+/// Where a map's records go once they no longer fit in memory
+#[derive(Debug)]
+pub struct Spill {
+    budget: usize,
+    path: PathBuf,
+    file: File,
+    end: u64,                               //where the next spilled record goes
+    resident: VecDeque<(String, usize)>,    //records still in the map, oldest first, with their estimated size
+    resident_bytes: usize,
+    spilled: HashMap<String, (u64, usize)>, //offset and length of every spilled record
+}
+
+impl Spill {
+    /// Start an empty spill file at path, replacing whatever was there
+    pub fn create(path: &Path, budget: usize) -> io::Result<Self> {
+        let file = File::options().read(true).write(true).create(true).truncate(true).open(path)?;
+        Ok(Self {
+            budget,
+            path: path.to_path_buf(),
+            file,
+            end: 0,
+            resident: VecDeque::new(),
+            resident_bytes: 0,
+            spilled: HashMap::new(),
+        })
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Note a record just put in the map
+    pub fn added<V: Serialize>(&mut self, key: &str, record: &V) {
+        let mut counter = ByteCounter(0);
+        let _ = serde_json::to_writer(&mut counter, record);
+        let size = counter.0 + key.len() + RECORD_OVERHEAD;
+        self.resident.push_back((key.to_string(), size));
+        self.resident_bytes += size;
+    }
+
+    /// Whether the record for key was spilled
+    pub fn contains(&self, key: &str) -> bool {
+        self.spilled.contains_key(key)
+    }
+
+    /// Number of records spilled
+    pub fn len(&self) -> usize {
+        self.spilled.len()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.spilled.keys()
+    }
+
+    /// Move the oldest records out of map until the ones left, along with
+    /// `elsewhere` bytes held outside it, fit the budget. Returns how many moved.
+    pub fn enforce<V: Serialize>(&mut self, map: &ShardedMap<Arc<V>>, elsewhere: usize) -> io::Result<usize> {
+        let mut moved = 0;
+        while self.resident_bytes + elsewhere > self.budget {
+            let Some((key, size)) = self.resident.pop_front() else {
+                break;
+            };
+            self.resident_bytes -= size;
+            let Some(record) = map.remove(&key) else {
+                continue;
+            };
+            let mut line = serde_json::to_vec(&*record)?;
+            line.push(b'\n');
+            if let Err(e) = self.file.seek(SeekFrom::Start(self.end)).and_then(|_| self.file.write_all(&line)) {
+                //still in memory rather than lost
+                map.insert(key.clone(), record);
+                self.resident.push_front((key, size));
+                self.resident_bytes += size;
+                return Err(e);
+            }
+            self.spilled.insert(key, (self.end, line.len()));
+            self.end += line.len() as u64;
+            moved += 1;
+        }
+        Ok(moved)
+    }
+
+    /// Read a spilled record back
+    pub fn get<V: DeserializeOwned>(&self, key: &str) -> Option<io::Result<V>> {
+        let &(offset, len) = self.spilled.get(key)?;
+        Some(self.read(offset, len))
+    }
+
+    fn read<V: DeserializeOwned>(&self, offset: u64, len: usize) -> io::Result<V> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut line = vec![0; len];
+        file.read_exact(&mut line)?;
+        Ok(serde_json::from_slice(&line)?)
+    }
+
+    /// Delete the spill file, once the results are written
+    pub fn remove(self) -> io::Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+    }
+}
+
+/// A map along with the records spilled from it, written out as the one map
+/// it would have been, sorted by key like a ShardedMap
+pub struct WithSpilled<'a, V> {
+    pub map: &'a ShardedMap<Arc<V>>,
+    pub spill: Option<&'a Spill>,
+}
+
+enum Record<'a, V> {
+    Resident(&'a V),
+    Spilled(u64, usize),
+}
+
+impl<V: Serialize + DeserializeOwned> Serialize for WithSpilled<'_, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let snapshot = self.map.lock_all();
+        let mut records: BTreeMap<&str, Record<V>> = snapshot.iter().map(|(key, record)| (key.as_str(), Record::Resident(record.as_ref()))).collect();
+        if let Some(spill) = self.spill {
+            records.extend(spill.spilled.iter().map(|(key, &(offset, len))| (key.as_str(), Record::Spilled(offset, len))));
+        }
+        let mut map = serializer.serialize_map(Some(records.len()))?;
+        for (key, record) in records {
+            match record {
+                Record::Resident(record) => map.serialize_entry(key, record)?,
+                Record::Spilled(offset, len) => {
+                    //only one spilled record is back in memory at a time
+                    let record: V = self.spill.unwrap().read(offset, len).map_err(S::Error::custom)?;
+                    map.serialize_entry(key, &record)?;
+                },
+            }
+        }
+        map.end()
+    }
+}
+
+//sizes a record's JSON without keeping it
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spills_the_oldest_records_over_budget() {
+        let path = std::env::temp_dir().join(format!("spill-test-{}.ndjson", std::process::id()));
+        let map = ShardedMap::new();
+        //each record is 8 bytes of JSON, its key and the overhead
+        let mut spill = Spill::create(&path, 3 * (8 + 22 + RECORD_OVERHEAD)).unwrap();
+        for i in 0..5 {
+            let key = format!("https://yahoo.com/{:04}", i);
+            let record = Arc::new(vec![i, i * 10]);
+            spill.added(&key, &*record);
+            map.insert(key, record);
+        }
+        assert_eq!(spill.enforce(&map, 0).unwrap(), 2);
+        assert!(spill.contains("https://yahoo.com/0000") && spill.contains("https://yahoo.com/0001"));
+        assert!(!map.contains_key("https://yahoo.com/0000") && map.contains_key("https://yahoo.com/0002"));
+        assert_eq!(spill.get::<Vec<i32>>("https://yahoo.com/0001").unwrap().unwrap(), [1, 10]);
+        //memory held elsewhere pushes more records out
+        assert_eq!(spill.enforce(&map, 8 + 22 + RECORD_OVERHEAD).unwrap(), 1);
+        assert_eq!(spill.len(), 3);
+
+        let merged = serde_json::to_string(&WithSpilled { map: &map, spill: Some(&spill) }).unwrap();
+        assert_eq!(merged, r#"{"https://yahoo.com/0000":[0,0],"https://yahoo.com/0001":[1,10],"https://yahoo.com/0002":[2,20],"https://yahoo.com/0003":[3,30],"https://yahoo.com/0004":[4,40]}"#);
+        spill.remove().unwrap();
+        assert!(!path.exists());
+    }
+}
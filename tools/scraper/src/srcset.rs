@@ -0,0 +1,201 @@
+//! Responsive images: the variants an <img srcset> or the <source>s of a
+//! <picture> offer the browser to choose from, ie:
+//!
+//! ```text
+//! <picture>
+//!   <source type="image/webp" srcset="hero-640.webp 640w, hero-1280.webp 1280w">
+//!   <img src="hero-640.jpg" srcset="hero-640.jpg 640w, hero-1280.jpg 1280w">
+//! </picture>
+//! ```
+//!
+//! Every variant is recorded on the page, and --srcset picks which of them
+//! get downloaded: the largest, the smallest, or all of them.
+
+use select::node::Node;
+use select::predicate::Name;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Which variants of a responsive image to download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SrcsetPolicy {
+    /// The variant with the widest w or highest x descriptor
+    Largest,
+    /// The variant with the narrowest w or lowest x descriptor
+    Smallest,
+    /// Every variant, and the plain src too
+    All,
+}
+
+impl SrcsetPolicy {
+    pub fn from_name(name: &str) -> Option<SrcsetPolicy> {
+        match name {
+            "largest" => Some(SrcsetPolicy::Largest),
+            "smallest" => Some(SrcsetPolicy::Smallest),
+            "all" => Some(SrcsetPolicy::All),
+            _ => None,
+        }
+    }
+}
+
+/// One image of a srcset
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,     //the w descriptor, in pixels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub density: Option<f64>,   //the x descriptor, pixel density
+    //what the <source> it came from is meant for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+}
+
+impl Variant {
+    //how the variants of an image rank by size, a candidate without descriptors is 1x
+    fn rank(&self) -> f64 {
+        self.width.map(f64::from).or(self.density).unwrap_or(1.0)
+    }
+}
+
+/// An <img> with a srcset, or a <picture>, and every variant it offers
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponsiveImage {
+    /// The plain src, for browsers that don't do srcset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub src: Option<String>,
+    pub variants: Vec<Variant>,
+}
+
+impl ResponsiveImage {
+    /// The URLs to download under the policy
+    pub fn select(&self, policy: SrcsetPolicy) -> Vec<&str> {
+        let by_rank = |a: &&Variant, b: &&Variant| a.rank().total_cmp(&b.rank());
+        let chosen = match policy {
+            SrcsetPolicy::Largest => self.variants.iter().max_by(by_rank),
+            SrcsetPolicy::Smallest => self.variants.iter().min_by(by_rank),
+            SrcsetPolicy::All => {
+                return self.src.iter().map(String::as_str).chain(self.variants.iter().map(|variant| variant.url.as_str())).collect();
+            },
+        };
+        chosen.map(|variant| variant.url.as_str()).or(self.src.as_deref()).into_iter().collect()
+    }
+}
+
+/// The responsive image an <img> stands for, with the <source>s of the
+/// <picture> it's in, None for a plain <img> that only has a src
+pub fn responsive_image(img: Node, base: &Url) -> Option<ResponsiveImage> {
+    let picture = img.parent().filter(|parent| parent.is(Name("picture")));
+    let sources = picture.into_iter().flat_map(|picture| picture.children()).filter(|child| child.is(Name("source")));
+    let mut variants = Vec::new();
+    for source in sources {
+        let attr = |name| source.attr(name).map(str::trim).filter(|value| !value.is_empty()).map(str::to_string);
+        for variant in parse(source.attr("srcset").unwrap_or(""), base) {
+            variants.push(Variant { media: attr("media"), media_type: attr("type"), ..variant });
+        }
+    }
+    variants.extend(parse(img.attr("srcset").unwrap_or(""), base));
+    if variants.is_empty() {
+        return None;
+    }
+    let src = img.attr("src").and_then(|src| base.join(src.trim()).ok()).filter(|src| matches!(src.scheme(), "http" | "https"));
+    Some(ResponsiveImage { src: src.map(String::from), variants })
+}
+
+/// The candidates of a srcset attribute, resolved against base. Candidates
+/// that aren't http(s) URLs once resolved, like data: URIs, are left out.
+pub fn parse(srcset: &str, base: &Url) -> Vec<Variant> {
+    let mut variants = Vec::new();
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+        if rest.is_empty() {
+            break;
+        }
+        let end = rest.find(|c: char| c.is_ascii_whitespace()).unwrap_or(rest.len());
+        let (url, after) = rest.split_at(end);
+        //a URL followed right away by a comma has no descriptors
+        let descriptors = if url.ends_with(',') {
+            rest = after;
+            ""
+        } else {
+            let end = descriptor_end(after);
+            rest = &after[end..];
+            &after[..end]
+        };
+        let Some(url) = base.join(url.trim_end_matches(',')).ok().filter(|url| matches!(url.scheme(), "http" | "https")) else {
+            continue;
+        };
+        let mut variant = Variant { url: url.into(), width: None, density: None, media: None, media_type: None };
+        for descriptor in descriptors.split_ascii_whitespace() {
+            if let Some(width) = descriptor.strip_suffix('w') {
+                variant.width = width.parse().ok();
+            } else if let Some(density) = descriptor.strip_suffix('x') {
+                variant.density = density.parse().ok().filter(|density: &f64| density.is_finite() && *density > 0.0);
+            }
+        }
+        variants.push(variant);
+    }
+    variants
+}
+
+//descriptors run up to the next comma outside parentheses
+fn descriptor_end(text: &str) -> usize {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth <= 0 => return i,
+            _ => {},
+        }
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use select::document::Document;
+
+    #[test]
+    fn parses_srcset_candidates() {
+        let base = Url::parse("https://www.yahoo.com/news/").unwrap();
+        let variants = parse(" lead-640.jpg 640w,lead-1280.jpg   1280w , //s.yimg.com/lead.jpg?crop=(0,0,10,10) 2x, data:image/gif;base64,R0lGOD 1x, plain.jpg,", &base);
+        let urls: Vec<&str> = variants.iter().map(|variant| variant.url.as_str()).collect();
+        assert_eq!(urls, [
+            "https://www.yahoo.com/news/lead-640.jpg",
+            "https://www.yahoo.com/news/lead-1280.jpg",
+            "https://s.yimg.com/lead.jpg?crop=(0,0,10,10)",
+            "https://www.yahoo.com/news/plain.jpg",
+        ]);
+        assert_eq!(variants[1].width, Some(1280));
+        assert_eq!(variants[2].density, Some(2.0));
+        assert_eq!((variants[3].width, variants[3].density), (None, None));
+    }
+
+    #[test]
+    fn picks_variants_by_policy() {
+        let base = Url::parse("https://www.yahoo.com/").unwrap();
+        let document = Document::from(r#"<body>
+            <picture>
+                <source media="(min-width: 800px)" type="image/webp" srcset="https://s.yimg.com/hero-1600.webp 1600w, https://s.yimg.com/hero-800.webp 800w">
+                <img src="https://s.yimg.com/hero.jpg" srcset="https://s.yimg.com/hero-400.jpg 400w">
+            </picture>
+            <img src="https://s.yimg.com/logo.png">
+            </body>"#);
+        let mut imgs = document.find(Name("img"));
+        let hero = responsive_image(imgs.next().unwrap(), &base).unwrap();
+        assert_eq!(hero.src.as_deref(), Some("https://s.yimg.com/hero.jpg"));
+        assert_eq!(hero.variants.len(), 3);
+        assert_eq!(hero.variants[0].media.as_deref(), Some("(min-width: 800px)"));
+        assert_eq!(hero.variants[0].media_type.as_deref(), Some("image/webp"));
+        assert_eq!(hero.variants[2].media, None);
+        assert_eq!(hero.select(SrcsetPolicy::Largest), ["https://s.yimg.com/hero-1600.webp"]);
+        assert_eq!(hero.select(SrcsetPolicy::Smallest), ["https://s.yimg.com/hero-400.jpg"]);
+        assert_eq!(hero.select(SrcsetPolicy::All).len(), 4);
+        assert_eq!(responsive_image(imgs.next().unwrap(), &base), None);
+    }
+}
@@ -0,0 +1,167 @@
+//! Structured data embedded in pages: JSON-LD script blocks, microdata and
+//! OpenGraph/Twitter card meta tags. News pages carry schema.org NewsArticle
+//! markup with the headline, authors and publish date, which is worth keeping
+//! next to the page instead of fetching the page again to get it.
+
+use std::collections::BTreeMap;
+use select::document::Document;
+use select::node::Node;
+use select::predicate::{Attr, Name};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Everything a page says about itself in machine readable form
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Metadata {
+    /// Each JSON-LD object, arrays at the top of a block are flattened
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub json_ld: Vec<Value>,
+    /// Each top-level microdata item as {"@type": ..., property: [values]},
+    /// nested items appear as objects among the values
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub microdata: Vec<Value>,
+    /// og:* properties, the first value wins when one is repeated
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub open_graph: BTreeMap<String, String>,
+    /// twitter:* card properties, the first value wins when one is repeated
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub twitter: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.json_ld.is_empty() && self.microdata.is_empty() && self.open_graph.is_empty() && self.twitter.is_empty()
+    }
+}
+
+/// Collect the structured data of a page, None if it has none
+pub fn extract(document: &Document) -> Option<Metadata> {
+    let mut metadata = Metadata::default();
+
+    for script in document.find(Attr("type", "application/ld+json")) {
+        //one broken block shouldn't cost us the others
+        match serde_json::from_str::<Value>(&script.text()) {
+            Ok(Value::Array(items)) => metadata.json_ld.extend(items),
+            Ok(item) => metadata.json_ld.push(item),
+            Err(_) => {},
+        }
+    }
+
+    for item in document.find(Attr("itemscope", ())).filter(|node| node.attr("itemprop").is_none()) {
+        metadata.microdata.push(microdata_item(item));
+    }
+
+    for meta in document.find(Name("meta")) {
+        //sites mix up name= and property= for both kinds of tags
+        let Some(key) = meta.attr("property").or_else(|| meta.attr("name")) else {
+            continue;
+        };
+        let Some(content) = meta.attr("content") else {
+            continue;
+        };
+        let properties = if key.starts_with("og:") {
+            &mut metadata.open_graph
+        } else if key.starts_with("twitter:") {
+            &mut metadata.twitter
+        } else {
+            continue;
+        };
+        properties.entry(key.to_string()).or_insert_with(|| content.to_string());
+    }
+
+    Some(metadata).filter(|metadata| !metadata.is_empty())
+}
+
+//an itemscope element as a JSON object of its type and properties
+fn microdata_item(item: Node) -> Value {
+    let mut properties = Map::new();
+    if let Some(item_type) = item.attr("itemtype") {
+        properties.insert("@type".to_string(), Value::String(item_type.to_string()));
+    }
+    collect_properties(item, &mut properties);
+    Value::Object(properties)
+}
+
+//find the itemprops belonging to an item, without going into nested items
+fn collect_properties(node: Node, properties: &mut Map<String, Value>) {
+    for child in node.children() {
+        if let Some(names) = child.attr("itemprop") {
+            let value = if child.attr("itemscope").is_some() {
+                microdata_item(child)
+            } else {
+                Value::String(property_value(child))
+            };
+            //one element can set several properties at once
+            for name in names.split_whitespace() {
+                let values = properties.entry(name.to_string()).or_insert_with(|| Value::Array(Vec::new()));
+                if let Value::Array(values) = values {
+                    values.push(value.clone());
+                }
+            }
+        }
+        if child.attr("itemscope").is_none() {
+            collect_properties(child, properties);
+        }
+    }
+}
+
+//the value of a property element, which depends on the kind of element
+fn property_value(node: Node) -> String {
+    let attr = match node.name() {
+        Some("meta") => "content",
+        Some("a" | "link" | "area") => "href",
+        Some("img" | "audio" | "video" | "source" | "iframe" | "embed") => "src",
+        Some("time") if node.attr("datetime").is_some() => "datetime",
+        Some("data" | "meter") => "value",
+        _ => return node.text().split_whitespace().collect::<Vec<_>>().join(" "),
+    };
+    node.attr(attr).unwrap_or("").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn extracts_every_kind_of_markup() {
+        let html = r#"<html><head>
+            <meta property="og:title" content="Markets rally">
+            <meta property="og:image" content="https://s.yimg.com/a.jpg">
+            <meta property="og:image" content="https://s.yimg.com/b.jpg">
+            <meta name="twitter:card" content="summary_large_image">
+            <meta name="description" content="not structured">
+            <script type="application/ld+json">{"@type": "NewsArticle", "headline": "Markets rally"}</script>
+            <script type="application/ld+json">[{"@type": "BreadcrumbList"}, {"@type": "Organization"}]</script>
+            <script type="application/ld+json">{broken</script>
+        </head><body>
+            <div itemscope itemtype="https://schema.org/NewsArticle">
+                <h1 itemprop="headline">Markets  rally</h1>
+                <time itemprop="datePublished" datetime="2022-09-01T10:00:00Z">Sept 1</time>
+                <div itemprop="author" itemscope itemtype="https://schema.org/Person">
+                    <span itemprop="name">Jane Doe</span>
+                </div>
+                <a itemprop="url" href="https://finance.yahoo.com/news/markets.html">link</a>
+            </div>
+        </body></html>"#;
+        let metadata = extract(&Document::from(html)).unwrap();
+
+        assert_eq!(metadata.json_ld, [
+            json!({"@type": "NewsArticle", "headline": "Markets rally"}),
+            json!({"@type": "BreadcrumbList"}),
+            json!({"@type": "Organization"}),
+        ]);
+        assert_eq!(metadata.microdata, [json!({
+            "@type": "https://schema.org/NewsArticle",
+            "headline": ["Markets rally"],
+            "datePublished": ["2022-09-01T10:00:00Z"],
+            "author": [{"@type": "https://schema.org/Person", "name": ["Jane Doe"]}],
+            "url": ["https://finance.yahoo.com/news/markets.html"],
+        })]);
+        assert_eq!(metadata.open_graph["og:image"], "https://s.yimg.com/a.jpg");
+        assert_eq!(metadata.open_graph.len(), 2);
+        assert_eq!(metadata.twitter["twitter:card"], "summary_large_image");
+
+        assert_eq!(extract(&Document::from("<html><p>plain</p></html>")), None);
+    }
+}
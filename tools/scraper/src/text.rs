@@ -0,0 +1,98 @@
+//! Readability-style extraction of the main text of a page, used to record
+//! content-quality signals alongside the link topology.
+
+use select::document::Document;
+use select::node::Node;
+use select::predicate::Name;
+use serde::{Deserialize, Serialize};
+
+/// Elements that hold site chrome rather than page content
+const BOILERPLATE: [&str; 8] = ["nav", "header", "footer", "aside", "form", "script", "style", "noscript"];
+
+/// Paragraphs shorter than this are usually captions, bylines or buttons
+const MIN_PARAGRAPH_WORDS: usize = 5;
+
+/// Summary of the readable text on a page
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TextStats {
+    pub word_count: usize,
+    pub paragraph_count: usize,
+    /// The first characters of the extracted text
+    pub excerpt: String,
+}
+
+/// Extract the main text of the page and summarize it, keeping the first
+/// `excerpt_len` characters as an excerpt
+pub fn extract_text(document: &Document, excerpt_len: usize) -> TextStats {
+    let paragraphs = paragraphs(document);
+    let word_count = paragraphs.iter().map(|p| p.split_whitespace().count()).sum();
+    let excerpt = paragraphs.join(" ").chars().take(excerpt_len).collect();
+    TextStats {
+        word_count,
+        paragraph_count: paragraphs.len(),
+        excerpt,
+    }
+}
+
+/// The content paragraphs of the page, whitespace normalized, boilerplate
+/// and captions left out
+pub fn paragraphs(document: &Document) -> Vec<String> {
+    //prefer the article body when the page marks one, fall back to the whole page
+    let root = document.find(Name("article")).next()
+        .or_else(|| document.find(Name("main")).next());
+
+    match root {
+        Some(root) => paragraphs_in(root),
+        None => document.find(Name("p")).filter_map(paragraph_text).collect(),
+    }
+}
+
+/// The content paragraphs under one element
+pub fn paragraphs_in(root: Node) -> Vec<String> {
+    root.find(Name("p")).filter_map(paragraph_text).collect()
+}
+
+//normalized text of a content paragraph, None if it's boilerplate or too short
+fn paragraph_text(node: Node) -> Option<String> {
+    if in_boilerplate(node) {
+        return None;
+    }
+    let text = node.text().split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.split(' ').count() < MIN_PARAGRAPH_WORDS {
+        return None;
+    }
+    Some(text)
+}
+
+fn in_boilerplate(node: Node) -> bool {
+    let mut current = node.parent();
+    while let Some(parent) = current {
+        if parent.name().is_some_and(|name| BOILERPLATE.contains(&name)) {
+            return true;
+        }
+        current = parent.parent();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_boilerplate_and_short_paragraphs() {
+        let html = r#"<html><body>
+            <nav><p>Home News Finance Sports Entertainment Life</p></nav>
+            <article>
+                <p>Stocks rallied on Tuesday as investors weighed new data.</p>
+                <p>Read more</p>
+                <p>The   Nasdaq closed up two percent for the session.</p>
+            </article>
+            <footer><p>Terms and privacy policy for all Yahoo users</p></footer>
+        </body></html>"#;
+        let stats = extract_text(&Document::from(html), 20);
+        assert_eq!(stats.paragraph_count, 2);
+        assert_eq!(stats.word_count, 18);
+        assert_eq!(stats.excerpt, "Stocks rallied on Tu");
+    }
+}
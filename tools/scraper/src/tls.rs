@@ -0,0 +1,182 @@
+//! TLS details of the hosts a crawl contacts, for security audits, and the
+//! plain HTTP to HTTPS upgrade used by --require-https.
+//!
+//! The HTTP client doesn't tell us which TLS version it negotiated, so each
+//! HTTPS host gets one separate handshake of its own to find out. The probe
+//! records whatever certificate the host presents, expired, self-signed or
+//! issued for another name included, along with why it doesn't validate.
+
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use url::Url;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What a host presented during the TLS handshake
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TlsDetails {
+    /// Negotiated protocol version, ie: "TLSv1_3"
+    pub protocol: String,
+    pub cipher_suite: String,
+    /// Subject and issuer of the leaf certificate
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: String,
+    pub not_after: String,
+    /// Days until the certificate expires, negative once it has. None only
+    /// in files from before expired certificates were recorded
+    pub days_until_expiry: Option<i64>,
+    /// Why the certificate doesn't validate, None if it does
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation_error: Option<String>,
+}
+
+/// Validates the certificate like any client would, but lets the handshake go
+/// on whatever the outcome so the probe gets to see it
+#[derive(Debug)]
+struct RecordingVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    error: Mutex<Option<String>>,
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(&self, end_entity: &CertificateDer<'_>, intermediates: &[CertificateDer<'_>], server_name: &ServerName<'_>, ocsp_response: &[u8], now: UnixTime) -> Result<ServerCertVerified, rustls::Error> {
+        if let Err(e) = self.inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now) {
+            *self.error.lock().unwrap() = Some(e.to_string());
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Handshake with `host` at addr and record what it negotiated. The caller
+/// resolves the host, so the probe only goes where a fetch may.
+pub fn probe(host: &str, addr: SocketAddr) -> Result<TlsDetails, String> {
+    let socket = TcpStream::connect_timeout(&addr, PROBE_TIMEOUT).map_err(|e| e.to_string())?;
+    socket.set_read_timeout(Some(PROBE_TIMEOUT)).map_err(|e| e.to_string())?;
+    socket.set_write_timeout(Some(PROBE_TIMEOUT)).map_err(|e| e.to_string())?;
+    handshake(host, socket)
+}
+
+//the handshake itself, over a socket already connected to host
+fn handshake(host: &str, mut socket: TcpStream) -> Result<TlsDetails, String> {
+    let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = Arc::new(RecordingVerifier {
+        inner: WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone()).build().map_err(|e| e.to_string())?,
+        error: Mutex::new(None),
+    });
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+    let name = ServerName::try_from(host.to_string()).map_err(|e| e.to_string())?;
+    let mut connection = ClientConnection::new(Arc::new(config), name).map_err(|e| e.to_string())?;
+
+    while connection.is_handshaking() {
+        connection.complete_io(&mut socket).map_err(|e| e.to_string())?;
+    }
+
+    let leaf = connection.peer_certificates()
+        .and_then(|chain| chain.first())
+        .ok_or("no certificate presented")?;
+    let (_, certificate) = X509Certificate::from_der(leaf).map_err(|e| e.to_string())?;
+    let validity = certificate.validity();
+    let validation_error = verifier.error.lock().unwrap().take();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs() as i64);
+    Ok(TlsDetails {
+        protocol: connection.protocol_version().map_or("unknown".to_string(), |v| format!("{:?}", v)),
+        cipher_suite: connection.negotiated_cipher_suite().map_or("unknown".to_string(), |s| format!("{:?}", s.suite())),
+        subject: certificate.subject().to_string(),
+        issuer: certificate.issuer().to_string(),
+        not_before: validity.not_before.to_string(),
+        not_after: validity.not_after.to_string(),
+        //rounded down, so a certificate that expired an hour ago is a day past
+        days_until_expiry: Some((validity.not_after.timestamp() - now).div_euclid(24 * 60 * 60)),
+        validation_error,
+    })
+}
+
+/* rewrite a plain http url to https, leaving https urls alone
+    returns None for anything that can't be fetched over https
+*/
+pub fn upgrade_to_https(link: &str) -> Option<String> {
+    let mut url = Url::parse(link).ok()?;
+    match url.scheme() {
+        "https" => Some(link.to_string()),
+        "http" => {
+            url.set_scheme("https").ok()?;
+            //an explicit :80 would point https at the plain http port
+            if url.port() == Some(80) {
+                url.set_port(None).ok()?;
+            }
+            Some(url.to_string())
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrades_plain_http() {
+        assert_eq!(upgrade_to_https("http://news.yahoo.com/a?b=c").as_deref(), Some("https://news.yahoo.com/a?b=c"));
+        assert_eq!(upgrade_to_https("http://news.yahoo.com:80/").as_deref(), Some("https://news.yahoo.com/"));
+        assert_eq!(upgrade_to_https("https://www.yahoo.com/").as_deref(), Some("https://www.yahoo.com/"));
+        assert_eq!(upgrade_to_https("ftp://yahoo.com/"), None);
+    }
+
+    #[test]
+    fn records_certificates_that_do_not_validate() {
+        let fixture = |name| std::fs::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap();
+        //self-signed for expired.test, and valid only through 2020
+        let certificate = CertificateDer::from(fixture("expired_self_signed.crt.der"));
+        let key = rustls::pki_types::PrivateKeyDer::try_from(fixture("expired_self_signed.key.der")).unwrap();
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![certificate], key)
+            .unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut connection = rustls::ServerConnection::new(Arc::new(config)).unwrap();
+            while connection.is_handshaking() {
+                if connection.complete_io(&mut socket).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let details = handshake("expired.test", TcpStream::connect(addr).unwrap()).unwrap();
+        server.join().unwrap();
+        assert!(details.subject.contains("expired.test"));
+        assert!(details.not_after.contains("2021"));
+        assert!(details.days_until_expiry.unwrap() < -365);
+        assert!(details.validation_error.is_some());
+    }
+}
@@ -0,0 +1,47 @@
+//! Request IDs tying together everything a crawl records about one fetch.
+//!
+//! Every page, image and feed request gets an ID that shows up in log.txt,
+//! in its record in visited.json or downloaded.json, and in baddies.json if
+//! it failed, so grepping for the ID follows one URL through all of them. IDs
+//! start with the time the run started, so the outputs of several runs can be
+//! merged without two fetches sharing one.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Hands out the request IDs of one run
+#[derive(Debug)]
+pub struct RequestIds {
+    run: String,
+    next: u64,
+}
+
+impl RequestIds {
+    pub fn new() -> Self {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+        Self::for_run(&format!("{:x}", started))
+    }
+
+    fn for_run(run: &str) -> Self {
+        Self { run: run.to_string(), next: 1 }
+    }
+
+    /// The ID of the next request, like 6523f1a0-000042
+    pub fn next_id(&mut self) -> String {
+        let id = format!("{}-{:06}", self.run, self.next);
+        self.next += 1;
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_unique_within_and_across_runs() {
+        let mut ids = RequestIds::for_run("6523f1a0");
+        assert_eq!(ids.next_id(), "6523f1a0-000001");
+        assert_eq!(ids.next_id(), "6523f1a0-000002");
+        assert_ne!(RequestIds::for_run("6523f1a1").next_id(), "6523f1a0-000001");
+    }
+}
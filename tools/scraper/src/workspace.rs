@@ -0,0 +1,163 @@
+//! Keeping many runs side by side, with --workspace. Each crawl writes its
+//! results, log and run-manifest.json (the settings it ran with included) to
+//! a directory of its own under `runs/`, named after when it started and the
+//! --tag it was given, so no run overwrites another:
+//!
+//! ```text
+//! workspace/runs/20240501T130000Z-nightly/visited.json
+//! workspace/runs/20240501T130000Z-nightly/run-manifest.json
+//! workspace/runs/20240502T090000Z/visited.json
+//! ```
+//!
+//! `scraper runs` lists them, oldest first.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde_json::Value;
+
+/// A directory holding one subdirectory per run
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root: PathBuf,
+}
+
+/// One run in a workspace, as its manifest describes it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub name: String,
+    pub dir: PathBuf,
+    pub tag: Option<String>,
+    pub seed: Option<String>,
+    /// None while the run is going, or when it was killed before writing its manifest
+    pub outcome: Option<String>,
+    pub started_at: Option<u64>,
+    pub pages: u64,
+    pub images: u64,
+    pub failures: u64,
+}
+
+impl Workspace {
+    pub fn new(root: &Path) -> Self {
+        Self { root: root.to_path_buf() }
+    }
+
+    /// Create the directory of a run started at `started_at` (Unix seconds),
+    /// tagged with `tag`. Two runs started in the same second get a counter.
+    pub fn create_run(&self, started_at: u64, tag: Option<&str>) -> io::Result<PathBuf> {
+        let runs = self.root.join("runs");
+        fs::create_dir_all(&runs)?;
+        let mut name = utc_timestamp(started_at);
+        if let Some(tag) = tag.map(clean_tag).filter(|tag| !tag.is_empty()) {
+            name = format!("{}-{}", name, tag);
+        }
+        let mut dir = runs.join(&name);
+        let mut n = 1;
+        loop {
+            match fs::create_dir(&dir) {
+                Ok(()) => return Ok(dir),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    n += 1;
+                    dir = runs.join(format!("{}.{}", name, n));
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Every run in the workspace, oldest first
+    pub fn runs(&self) -> io::Result<Vec<Run>> {
+        let runs = self.root.join("runs");
+        if !runs.exists() {
+            return Ok(Vec::new());
+        }
+        let mut found = Vec::new();
+        for entry in fs::read_dir(&runs)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let dir = entry.path();
+            //a manifest that can't be read counts as a run that never finished
+            let manifest: Value = fs::read_to_string(dir.join("run-manifest.json")).ok()
+                .and_then(|data| serde_json::from_str(&data).ok())
+                .unwrap_or_default();
+            let text = |key: &str| manifest[key].as_str().map(str::to_string);
+            let count = |key: &str| manifest[key].as_u64().unwrap_or(0);
+            found.push(Run {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                tag: text("tag"),
+                seed: text("seed"),
+                outcome: text("outcome"),
+                started_at: manifest["started_at"].as_u64(),
+                pages: count("pages"),
+                images: count("images"),
+                failures: count("failures"),
+                dir,
+            });
+        }
+        //names start with the time the run started
+        found.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(found)
+    }
+}
+
+//tags go into a directory name, anything but letters, digits, . _ and - becomes -
+fn clean_tag(tag: &str) -> String {
+    tag.trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') { c } else { '-' })
+        .collect::<String>()
+        .trim_matches(|c| c == '-' || c == '.')
+        .to_string()
+}
+
+/// Unix seconds as a UTC timestamp that sorts by time, ie: 20240501T130000Z
+pub fn utc_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+    //days since the epoch to a civil date, after Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", year, month, day, secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_utc_timestamps() {
+        assert_eq!(utc_timestamp(0), "19700101T000000Z");
+        assert_eq!(utc_timestamp(1_700_000_000), "20231114T221320Z");
+        assert_eq!(utc_timestamp(951_782_400), "20000229T000000Z");
+    }
+
+    #[test]
+    fn gives_each_run_its_own_directory() {
+        let root = std::env::temp_dir().join(format!("workspace-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let workspace = Workspace::new(&root);
+        let first = workspace.create_run(1_700_000_000, Some("nightly run/1")).unwrap();
+        assert_eq!(first, root.join("runs/20231114T221320Z-nightly-run-1"));
+        let second = workspace.create_run(1_700_000_000, Some("nightly run/1")).unwrap();
+        assert_eq!(second, root.join("runs/20231114T221320Z-nightly-run-1.2"));
+        let untagged = workspace.create_run(1_600_000_000, None).unwrap();
+
+        fs::write(first.join("run-manifest.json"), r#"{"outcome": "success", "seed": "https://www.yahoo.com/", "tag": "nightly run/1", "started_at": 1700000000, "pages": 12, "images": 3, "failures": 0}"#).unwrap();
+        let runs = workspace.runs().unwrap();
+        assert_eq!(runs.iter().map(|run| &run.dir).collect::<Vec<_>>(), [&untagged, &first, &second]);
+        assert_eq!(runs[1].outcome.as_deref(), Some("success"));
+        assert_eq!(runs[1].tag.as_deref(), Some("nightly run/1"));
+        assert_eq!(runs[1].pages, 12);
+        assert_eq!(runs[2].outcome, None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}